@@ -0,0 +1,6 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use my_graph_lib::fuzz::FuzzCircuit;
+
+fuzz_target!(|circuit: FuzzCircuit| circuit.run());
@@ -0,0 +1,6 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use my_graph_lib::fuzz;
+
+fuzz_target!(|bytes: &[u8]| fuzz::load(bytes));
@@ -0,0 +1,95 @@
+#ifndef CIRCUIT_H
+#define CIRCUIT_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+
+
+// Returned in place of a node index when a node could not be created.
+#define CIRCUIT_INVALID ~0
+
+// An opaque handle to a graph under construction.
+typedef struct CircuitBuilder CircuitBuilder;
+
+// Creates an empty graph. Free it with `circuit_builder_free`.
+struct CircuitBuilder *circuit_builder_new(void);
+
+// Frees a graph created with `circuit_builder_new`. Passing null does nothing.
+//
+// # Safety
+// `builder` must be null or a pointer returned by `circuit_builder_new` that has not been freed.
+void circuit_builder_free(struct CircuitBuilder *builder);
+
+// Adds an input node and returns its index.
+//
+// # Safety
+// `builder` must be a live pointer returned by `circuit_builder_new`.
+size_t circuit_init(struct CircuitBuilder *builder);
+
+// Adds a node with a constant value and returns its index.
+//
+// # Safety
+// `builder` must be a live pointer returned by `circuit_builder_new`.
+size_t circuit_constant(struct CircuitBuilder *builder, uint32_t value);
+
+// Adds a node computing `a + b` and returns its index.
+//
+// # Safety
+// `builder` must be a live pointer returned by `circuit_builder_new`.
+size_t circuit_add(struct CircuitBuilder *builder, size_t a, size_t b);
+
+// Adds a node computing `a * b` and returns its index.
+//
+// # Safety
+// `builder` must be a live pointer returned by `circuit_builder_new`.
+size_t circuit_mul(struct CircuitBuilder *builder, size_t a, size_t b);
+
+// Adds a hint node computed by the registered hint function `name` (e.g. "sqrt") from the
+// output of `node`. Returns `CIRCUIT_INVALID` if the function or the node does not exist.
+//
+// # Safety
+// `builder` must be a live pointer returned by `circuit_builder_new`, and `name` a
+// NUL-terminated string.
+size_t circuit_hint(struct CircuitBuilder *builder, const char *name, size_t node);
+
+// Records that the outputs of `a` and `b` must be equal. Returns false if a node does not exist.
+//
+// # Safety
+// `builder` must be a live pointer returned by `circuit_builder_new`.
+bool circuit_enforce_equal(struct CircuitBuilder *builder, size_t a, size_t b);
+
+// Sets `len` input nodes to the given values and fills in the rest of the graph.
+// Returns false if one of the nodes is not an input or evaluation fails.
+//
+// # Safety
+// `builder` must be a live pointer returned by `circuit_builder_new`, and `inputs` and
+// `values` must each point to `len` elements.
+bool circuit_fill(struct CircuitBuilder *builder,
+                  const size_t *inputs,
+                  const uint32_t *values,
+                  size_t len);
+
+// Returns true if every constraint holds for the filled in outputs.
+//
+// # Safety
+// `builder` must be a live pointer returned by `circuit_builder_new`.
+bool circuit_is_satisfied(const struct CircuitBuilder *builder);
+
+// Writes the output of `node` to `out`. Returns false if the node does not exist or has no value.
+//
+// # Safety
+// `builder` must be a live pointer returned by `circuit_builder_new`, and `out` must be valid
+// for writes.
+bool circuit_output(const struct CircuitBuilder *builder, size_t node, uint32_t *out);
+
+// Returns the number of nodes in the graph.
+//
+// # Safety
+// `builder` must be a live pointer returned by `circuit_builder_new`.
+size_t circuit_len(const struct CircuitBuilder *builder);
+
+#endif  /* CIRCUIT_H */
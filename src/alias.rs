@@ -0,0 +1,124 @@
+//! Wire aliasing.
+//!
+//! `Builder::alias(a, b)` declares that two nodes are the same wire. Where `enforce_equal`
+//! records a constraint for every call, aliases merge the nodes into classes kept in a
+//! union-find, each represented by its first node, so that later passes and exports can treat a
+//! class as a single wire: the R1CS export gives all its nodes one variable and emits no equality
+//! for them, and Plonkish tables and trace layouts put its cells in one cycle of their
+//! permutation. `check` rejects witnesses where the nodes of a class hold different values with
+//! `ConstraintError::NotAliased`.
+//!
+//! ```
+//! use my_graph_lib::Builder;
+//!
+//! let mut b = Builder::new();
+//! let x = b.init();
+//! let y = b.init();
+//! let sum = b.add(x, y);
+//! let copy = b.init();
+//! b.alias(copy, sum);
+//! assert_eq!(b.representative(copy), sum);
+//! assert_eq!(b.alias_classes(), vec![vec![sum, copy]]);
+//! b.fill_inputs(&[(x, 1), (y, 2), (copy, 3)]);
+//! assert!(b.is_satisfied());
+//! b.fill_inputs(&[(x, 1), (y, 2), (copy, 4)]);
+//! assert!(!b.is_satisfied());
+//! ```
+//!
+//! A rollback removes the aliases of the nodes it removes, but aliases between nodes that are
+//! kept stay, like other changes to those nodes.
+
+use std::collections::BTreeMap;
+
+use crate::error::ConstraintError;
+use crate::{Builder, NodeId};
+
+impl Builder {
+    /// Declares that `a` and `b` are the same wire, merging their alias classes, see the module
+    /// documentation.
+    pub fn alias(&mut self, a: NodeId, b: NodeId) {
+        let (a, b) = (self.index(a), self.index(b));
+        self.union(a, b);
+    }
+
+    /// The first node of the alias class of `id`, which is `id` itself for nodes that were never
+    /// aliased.
+    pub fn representative(&self, id: NodeId) -> NodeId {
+        self.id(self.root(self.index(id)))
+    }
+
+    /// The alias classes with at least two nodes, each sorted, ordered by their first node.
+    pub fn alias_classes(&self) -> Vec<Vec<NodeId>> {
+        let mut classes: BTreeMap<usize, Vec<NodeId>> = BTreeMap::new();
+        for &node in self.aliases.keys() {
+            let root = self.root(node);
+            classes
+                .entry(root)
+                .or_insert_with(|| vec![self.id(root)])
+                .push(self.id(node));
+        }
+        classes
+            .into_values()
+            .map(|mut class| {
+                class.sort_unstable();
+                class
+            })
+            .collect()
+    }
+
+    /// Merges the alias classes of the nodes at `a` and `b`.
+    pub(crate) fn union(&mut self, a: usize, b: usize) {
+        let (a_root, b_root) = (self.root(a), self.root(b));
+        if a_root == b_root {
+            return;
+        }
+        // The smaller root stays, so every node comes after its parent and a class is
+        // represented by its first node.
+        let root = a_root.min(b_root);
+        for node in [a_root.max(b_root), a, b] {
+            if node != root {
+                self.aliases.insert(node, root);
+            }
+        }
+    }
+
+    /// The representative of the alias class of the node at `node`.
+    pub(crate) fn root(&self, mut node: usize) -> usize {
+        while let Some(&parent) = self.aliases.get(&node) {
+            node = parent;
+        }
+        node
+    }
+
+    /// Every aliased node other than a representative, after the representative of its class.
+    pub(crate) fn alias_pairs(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.aliases.keys().map(|&node| (self.root(node), node))
+    }
+
+    /// The pairs of nodes that must hold the same value: the equality constraints, except those
+    /// with the `Warning` severity, followed by the pairs of `alias_pairs`.
+    pub(crate) fn copies(&self) -> Vec<(usize, usize)> {
+        let constraints = self
+            .constraints
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !self.soft_constraints.contains(i))
+            .map(|(_, &copy)| copy);
+        constraints.chain(self.alias_pairs()).collect()
+    }
+
+    /// Checks that the aliased node at `node` holds the value of its representative.
+    pub(crate) fn check_alias(&self, node: usize) -> Result<(), ConstraintError> {
+        let root = self.root(node);
+        let (a_value, b_value) = (self.filled(root)?, self.filled(node)?);
+        if a_value != b_value {
+            return Err(ConstraintError::NotAliased {
+                a: root,
+                b: node,
+                a_value: self.redact(root, a_value),
+                b_value: self.redact(node, b_value),
+            });
+        }
+        Ok(())
+    }
+}
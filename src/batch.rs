@@ -0,0 +1,143 @@
+//! Recording many equality constraints at once.
+//!
+//! Gadgets comparing vectors, such as the limbs of two numbers or the words of a hash state,
+//! would otherwise call `enforce_equal` in a loop. `assert_all_equal(&pairs)` records an equality
+//! constraint for every pair, and `assert_vec_equal(&a, &b)` one for every position of two vectors
+//! of the same length. A batch is recorded whole or not at all: `try_assert_all_equal` checks
+//! every id and the constraint limit before recording any constraint. The `_with` variants label
+//! the constraints with a message numbered by position, e.g. `state[3]`, which starts their
+//! reports.
+//!
+//! Both return the `EqualityBatch` recorded, and `batch_violations` lists every constraint of a
+//! batch that doesn't hold, where `check` stops at the first violation of the graph:
+//!
+//! ```
+//! use my_graph_lib::Builder;
+//!
+//! let mut b = Builder::new();
+//! let a = [b.init(), b.init(), b.init()];
+//! let c = [b.init(), b.init(), b.init()];
+//! let batch = b.assert_vec_equal_with(&a, &c, "state");
+//! b.fill_inputs(&[(a[0], 1), (a[1], 2), (a[2], 3), (c[0], 1), (c[1], 5), (c[2], 6)]);
+//! let violations = b.batch_violations(&batch);
+//! assert_eq!(violations.len(), 2);
+//! assert!(b
+//!     .explain(&violations[0])
+//!     .to_string()
+//!     .starts_with("state[1]: constraint 1 does not hold"));
+//! ```
+
+use std::ops::Range;
+
+use crate::error::{BuildError, ConstraintError};
+use crate::observer::Constraint;
+use crate::{Builder, NodeId};
+
+/// The equality constraints recorded by one call of `assert_all_equal` or `assert_vec_equal`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EqualityBatch {
+    constraints: Range<usize>,
+}
+
+impl EqualityBatch {
+    /// The indices of the constraints, in the order constraints were recorded.
+    pub fn constraints(&self) -> Range<usize> {
+        self.constraints.clone()
+    }
+
+    /// The number of constraints in the batch, one per pair.
+    pub fn len(&self) -> usize {
+        self.constraints.len()
+    }
+
+    /// Whether the batch was recorded from no pairs at all.
+    pub fn is_empty(&self) -> bool {
+        self.constraints.is_empty()
+    }
+}
+
+impl Builder {
+    /// Records a constraint that the outputs of the nodes of each pair must be equal, like
+    /// `enforce_equal` for every pair.
+    pub fn assert_all_equal(&mut self, pairs: &[(NodeId, NodeId)]) -> EqualityBatch {
+        self.try_assert_all_equal(pairs)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `assert_all_equal`, but returns an error and records nothing if an id does not
+    /// belong to this builder or the constraints would exceed the constraint limit.
+    pub fn try_assert_all_equal(
+        &mut self,
+        pairs: &[(NodeId, NodeId)],
+    ) -> Result<EqualityBatch, BuildError> {
+        let pairs = pairs
+            .iter()
+            .map(|&(a, b)| Ok((self.resolve(a)?, self.resolve(b)?)))
+            .collect::<Result<Vec<_>, BuildError>>()?;
+        self.add_constraints(pairs.len())?;
+        let first = self.constraints.len();
+        self.constraints.extend(pairs);
+        for constraint in first..self.constraints.len() {
+            self.report_constraint(Constraint::Equality(constraint));
+        }
+        Ok(EqualityBatch {
+            constraints: first..self.constraints.len(),
+        })
+    }
+
+    /// Like `assert_all_equal`, labeling the constraint of the pair at position `i` with
+    /// `message[i]` in reports.
+    pub fn assert_all_equal_with(
+        &mut self,
+        pairs: &[(NodeId, NodeId)],
+        message: &str,
+    ) -> EqualityBatch {
+        let batch = self.assert_all_equal(pairs);
+        for (i, constraint) in batch.constraints().enumerate() {
+            self.constraint_messages
+                .insert(constraint, format!("{}[{}]", message, i));
+        }
+        batch
+    }
+
+    /// Records a constraint that the outputs of `a[i]` and `b[i]` must be equal for every
+    /// position `i`. Panics if the vectors have different lengths.
+    pub fn assert_vec_equal(&mut self, a: &[NodeId], b: &[NodeId]) -> EqualityBatch {
+        self.assert_all_equal(&zip_equal(a, b))
+    }
+
+    /// Like `assert_vec_equal`, labeling the constraint of position `i` with `message[i]` in
+    /// reports.
+    pub fn assert_vec_equal_with(
+        &mut self,
+        a: &[NodeId],
+        b: &[NodeId],
+        message: &str,
+    ) -> EqualityBatch {
+        self.assert_all_equal_with(&zip_equal(a, b), message)
+    }
+
+    /// Every constraint of `batch` that doesn't hold, in order. Constraints with a node left
+    /// without a value are reported as `ConstraintError::Unfilled`. Constraints the builder no
+    /// longer has, because a rollback removed them, are skipped; a batch is only meaningful for
+    /// the builder that recorded it.
+    pub fn batch_violations(&self, batch: &EqualityBatch) -> Vec<ConstraintError> {
+        let recorded = batch.constraints.start.min(self.constraints.len())
+            ..batch.constraints.end.min(self.constraints.len());
+        recorded
+            .filter_map(|constraint| {
+                let (a, b) = self.constraints[constraint];
+                self.check_equality(constraint, a, b).err()
+            })
+            .collect()
+    }
+}
+
+fn zip_equal(a: &[NodeId], b: &[NodeId]) -> Vec<(NodeId, NodeId)> {
+    assert_eq!(
+        a.len(),
+        b.len(),
+        "assert_vec_equal needs vectors of the same length"
+    );
+    a.iter().copied().zip(b.iter().copied()).collect()
+}
@@ -0,0 +1,221 @@
+//! Timing of graph construction, evaluation and constraint checking, without an external harness.
+//!
+//! `bench_random` times random circuits (see `Builder::random`) of growing width and
+//! `bench_graph` times a given graph. Evaluation is timed with every backend of the
+//! `differential` module, so layouts and evaluators can be compared on the same circuit. Each
+//! measurement is the fastest of `runs` repetitions. Fill times include collecting the witness.
+//!
+//! The same tables are printed from the command line by `circuit bench`.
+//!
+//! With a small field feature, `bench_field` times `Builder::evaluate_field` with Montgomery
+//! multiplication against reducing every operation with `%`.
+
+use std::fmt;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use crate::differential::Backend;
+use crate::error::EvalError;
+use crate::eval::EvalOptions;
+#[cfg(any(feature = "goldilocks", feature = "babybear", feature = "mersenne31"))]
+use crate::field::{FieldError, Fp, Naive, Representation};
+use crate::random::RandomConfig;
+use crate::{Builder, NodeId};
+
+/// The timings of one circuit.
+#[derive(Debug, Clone)]
+pub struct BenchRow {
+    pub nodes: usize,
+    pub constraints: usize,
+    /// Building a random circuit, or loading a given graph from JSON.
+    pub construction: Duration,
+    /// Filling in the graph with each backend, or None for backends that can't evaluate it.
+    pub fill: Vec<(Backend, Option<Duration>)>,
+    /// Checking every constraint of the filled in graph.
+    pub check: Duration,
+}
+
+/// Timings of circuits of growing size.
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub rows: Vec<BenchRow>,
+}
+
+/// The fastest of `runs` runs of `f`, with `setup` run untimed before each.
+fn fastest<T, U>(runs: usize, mut setup: impl FnMut() -> T, mut f: impl FnMut(T) -> U) -> Duration {
+    (0..runs.max(1))
+        .map(|_| {
+            let input = setup();
+            let start = Instant::now();
+            black_box(f(input));
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+/// Times random circuits built from `config` with every layer width in `widths`.
+pub fn bench_random(
+    seed: u64,
+    config: &RandomConfig,
+    widths: &[usize],
+    runs: usize,
+) -> Result<BenchReport, EvalError> {
+    let mut rows = Vec::with_capacity(widths.len());
+    for &width in widths {
+        let config = RandomConfig {
+            width,
+            ..config.clone()
+        };
+        let construction = fastest(runs, || (), |()| Builder::random(seed, &config));
+        let builder = Builder::random(seed, &config);
+        let inputs: Vec<(NodeId, u32)> = builder
+            .inputs()
+            .into_iter()
+            .map(|x| (x, config.input_value))
+            .collect();
+        rows.push(bench_filled(&builder, &inputs, construction, runs)?);
+    }
+    Ok(BenchReport { rows })
+}
+
+/// Times a graph evaluated with `inputs`. Construction is the time to load it from JSON.
+pub fn bench_graph(
+    builder: &Builder,
+    inputs: &[(NodeId, u32)],
+    runs: usize,
+) -> Result<BenchReport, EvalError> {
+    let json = builder.to_json();
+    let construction = fastest(runs, || (), |()| Builder::from_json(&json));
+    let row = bench_filled(builder, inputs, construction, runs)?;
+    Ok(BenchReport { rows: vec![row] })
+}
+
+fn bench_filled(
+    builder: &Builder,
+    inputs: &[(NodeId, u32)],
+    construction: Duration,
+    runs: usize,
+) -> Result<BenchRow, EvalError> {
+    let mut builder = builder.clone();
+    builder.reset();
+    // Timing metrics and observers would be called on every run, so they are not.
+    builder.metrics = None;
+    builder.observers.clear();
+    // Fails before timing anything if the graph can't be evaluated, which some backends report
+    // by panicking.
+    let mut filled = builder.clone();
+    filled.fill_inputs_with(inputs, EvalOptions::new())?;
+    let indices: Vec<(usize, u32)> = inputs
+        .iter()
+        .map(|&(id, value)| (builder.index(id), value))
+        .collect();
+    let fill = Backend::all()
+        .into_iter()
+        .map(|backend| {
+            let time = backend.supports(&builder).then(|| {
+                fastest(
+                    runs,
+                    || builder.clone(),
+                    |mut graph| backend.evaluate(&mut graph, &indices),
+                )
+            });
+            (backend, time)
+        })
+        .collect();
+    let check = fastest(runs, || (), |()| filled.check());
+    Ok(BenchRow {
+        nodes: builder.len(),
+        constraints: builder.constraints.len(),
+        construction,
+        fill,
+        check,
+    })
+}
+
+impl fmt::Display for BenchReport {
+    /// A table with a row per circuit and a column per timing.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>10} {:>11} {:>12}",
+            "nodes", "constraints", "construction"
+        )?;
+        for backend in Backend::all() {
+            write!(f, " {:>16}", backend.name())?;
+        }
+        writeln!(f, " {:>12}", "check")?;
+        for row in &self.rows {
+            write!(
+                f,
+                "{:>10} {:>11} {:>12}",
+                row.nodes,
+                row.constraints,
+                format!("{:.2?}", row.construction)
+            )?;
+            for (_, time) in &row.fill {
+                let time = time.map_or("-".to_string(), |time| format!("{:.2?}", time));
+                write!(f, " {:>16}", time)?;
+            }
+            writeln!(f, " {:>12}", format!("{:.2?}", row.check))?;
+        }
+        Ok(())
+    }
+}
+
+/// The timings of evaluating one graph modulo a prime, see `bench_field`.
+#[cfg(any(feature = "goldilocks", feature = "babybear", feature = "mersenne31"))]
+#[derive(Debug, Clone)]
+pub struct FieldBench {
+    pub modulus: u64,
+    pub nodes: usize,
+    /// `Builder::evaluate_field`, with elements in Montgomery form.
+    pub montgomery: Duration,
+    /// The same evaluation on canonical representatives, reduced with `%` after every operation.
+    pub naive: Duration,
+}
+
+/// Times evaluating a graph in `Fp<P>` with `inputs`, with Montgomery multiplication and with
+/// naive reduction. Fails before timing anything if the graph can't be evaluated.
+#[cfg(any(feature = "goldilocks", feature = "babybear", feature = "mersenne31"))]
+pub fn bench_field<const P: u64>(
+    builder: &Builder,
+    inputs: &[(NodeId, u64)],
+    runs: usize,
+) -> Result<FieldBench, FieldError> {
+    fn convert<R: Representation>(inputs: &[(NodeId, u64)]) -> Vec<(NodeId, R)> {
+        inputs
+            .iter()
+            .map(|&(id, value)| (id, R::from_u64(value)))
+            .collect()
+    }
+    let montgomery: Vec<(NodeId, Fp<P>)> = convert(inputs);
+    let naive: Vec<(NodeId, Naive<P>)> = convert(inputs);
+    builder.evaluate_in(&montgomery)?;
+    Ok(FieldBench {
+        modulus: P,
+        nodes: builder.len(),
+        montgomery: fastest(runs, || (), |()| builder.evaluate_in(&montgomery)),
+        naive: fastest(runs, || (), |()| builder.evaluate_in(&naive)),
+    })
+}
+
+#[cfg(any(feature = "goldilocks", feature = "babybear", feature = "mersenne31"))]
+impl fmt::Display for FieldBench {
+    /// A table with a column per reduction.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>20} {:>10} {:>12} {:>12}",
+            "modulus", "nodes", "montgomery", "naive"
+        )?;
+        writeln!(
+            f,
+            "{:>20} {:>10} {:>12} {:>12}",
+            self.modulus,
+            self.nodes,
+            format!("{:.2?}", self.montgomery),
+            format!("{:.2?}", self.naive)
+        )
+    }
+}
@@ -0,0 +1,457 @@
+//! Lowering of graphs to boolean circuits, for MPC and garbled circuit backends or SAT solvers.
+//!
+//! `lower` turns the 32-bit arithmetic of a graph into AND and XOR gates over bit wires:
+//! additions become ripple-carry adders, and multiplications shift-and-add multipliers. Values
+//! are lists of `WIDTH` bits, least significant first. Evaluation fails on overflow, so the
+//! circuit computes one more bit, `valid`, which is 1 when no operation overflows and every
+//! equality constraint, alias and check holds: checks are compared bitwise, and permutations sort both
+//! sides with a sorting network. Constraints with the `Warning` severity are left out.
+//!
+//! Wire 0 holds the constant 1, which negations XOR with. The other inputs are 32 wires for each
+//! input and hint computed by a function: hint functions aren't part of the graph, so their
+//! values are unconstrained advice. Custom nodes must be lowered first. Constants are folded into the gates, so multiplications by constants
+//! only add the shifted operand. The outputs are the public outputs of the graph, then `valid`.
+//!
+//! `to_bristol` renders the circuit in the Bristol fashion format of MPC frameworks, and
+//! `to_dimacs` as a CNF formula, satisfiable exactly when some inputs make `valid` 1, for SAT
+//! solvers.
+//!
+//! ```
+//! use my_graph_lib::{boolean, Builder};
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let y = builder.init();
+//! let sum = builder.add(x, y);
+//! builder.mark_public(sum);
+//! let circuit = boolean::lower(&builder);
+//! assert_eq!(circuit.evaluate(&[20, 22]), (vec![42], true));
+//! // The addition overflows.
+//! assert_eq!(circuit.evaluate(&[u32::MAX, 1]).1, false);
+//! ```
+
+use std::fmt::Write as _;
+
+use serde::Serialize;
+
+use crate::checks::Check;
+use crate::error::BuildError;
+use crate::storage::Kind;
+use crate::Builder;
+
+/// The number of bits of a value.
+pub const WIDTH: usize = 32;
+
+/// The wire holding the constant 1.
+pub const ONE: usize = 0;
+
+/// A bit of a value: a constant folded into the gates, or a wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Bit {
+    Const(bool),
+    Wire(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum GateKind {
+    And,
+    Xor,
+}
+
+/// A gate writing `a AND b` or `a XOR b` to the wire `out`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Gate {
+    pub kind: GateKind,
+    pub a: usize,
+    pub b: usize,
+    pub out: usize,
+}
+
+/// A graph lowered by `lower`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BooleanCircuit {
+    /// The number of wires: `ONE`, the inputs, then one wire per gate, in order.
+    pub wires: usize,
+    /// The inputs and hints computed by functions, with their wires, least significant first.
+    pub inputs: Vec<(usize, Vec<usize>)>,
+    pub gates: Vec<Gate>,
+    /// The public outputs, with their bits, least significant first.
+    pub outputs: Vec<(usize, Vec<Bit>)>,
+    /// 1 when no operation overflows and every constraint holds.
+    pub valid: Bit,
+}
+
+impl BooleanCircuit {
+    /// The values of the public outputs and of `valid`, from the values of the nodes of
+    /// `inputs`, in order. Panics if there are not as many values as inputs.
+    pub fn evaluate(&self, inputs: &[u32]) -> (Vec<u32>, bool) {
+        assert_eq!(
+            inputs.len(),
+            self.inputs.len(),
+            "the circuit has {} inputs",
+            self.inputs.len()
+        );
+        let mut wires = vec![false; self.wires];
+        wires[ONE] = true;
+        for ((_, bits), &value) in self.inputs.iter().zip(inputs) {
+            for (i, &wire) in bits.iter().enumerate() {
+                wires[wire] = value >> i & 1 == 1;
+            }
+        }
+        for gate in &self.gates {
+            let (a, b) = (wires[gate.a], wires[gate.b]);
+            wires[gate.out] = match gate.kind {
+                GateKind::And => a & b,
+                GateKind::Xor => a ^ b,
+            };
+        }
+        let bit = |bit: Bit| match bit {
+            Bit::Const(value) => value,
+            Bit::Wire(wire) => wires[wire],
+        };
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|(_, bits)| {
+                bits.iter()
+                    .enumerate()
+                    .map(|(i, &b)| u32::from(bit(b)) << i)
+                    .sum()
+            })
+            .collect();
+        (outputs, bit(self.valid))
+    }
+
+    /// The circuit in Bristol fashion: the input values are the 1 of `ONE`, then 32 bits per
+    /// input, and the output values 32 bits per public output, then `valid`. Outputs are copied
+    /// to the last wires with `EQW` gates, and constant outputs set with `EQ` gates, as the format
+    /// requires.
+    pub fn to_bristol(&self) -> String {
+        let outputs: Vec<Bit> = self
+            .outputs
+            .iter()
+            .flat_map(|(_, bits)| bits.iter().copied())
+            .chain([self.valid])
+            .collect();
+        let mut out = format!(
+            "{} {}\n{} 1",
+            self.gates.len() + outputs.len(),
+            self.wires + outputs.len(),
+            self.inputs.len() + 1
+        );
+        for _ in &self.inputs {
+            write!(out, " {}", WIDTH).unwrap();
+        }
+        write!(out, "\n{}", self.outputs.len() + 1).unwrap();
+        for _ in &self.outputs {
+            write!(out, " {}", WIDTH).unwrap();
+        }
+        out.push_str(" 1\n\n");
+        for gate in &self.gates {
+            let kind = match gate.kind {
+                GateKind::And => "AND",
+                GateKind::Xor => "XOR",
+            };
+            writeln!(out, "2 1 {} {} {} {}", gate.a, gate.b, gate.out, kind).unwrap();
+        }
+        for (i, &bit) in outputs.iter().enumerate() {
+            let wire = self.wires + i;
+            match bit {
+                Bit::Const(value) => writeln!(out, "1 1 {} {} EQ", u8::from(value), wire),
+                Bit::Wire(input) => writeln!(out, "1 1 {} {} EQW", input, wire),
+            }
+            .unwrap();
+        }
+        out
+    }
+
+    /// The circuit as a CNF formula in DIMACS format, by the Tseitin encoding: wire `w` is
+    /// variable `w + 1`, and the formula is satisfiable exactly when some inputs make `valid` 1.
+    /// The values of the input variables of a model are such inputs.
+    pub fn to_dimacs(&self) -> String {
+        let var = |wire: usize| wire as i64 + 1;
+        let mut clauses: Vec<Vec<i64>> = vec![vec![var(ONE)]];
+        for gate in &self.gates {
+            let (a, b, out) = (var(gate.a), var(gate.b), var(gate.out));
+            match gate.kind {
+                GateKind::And => {
+                    clauses.push(vec![-out, a]);
+                    clauses.push(vec![-out, b]);
+                    clauses.push(vec![out, -a, -b]);
+                }
+                GateKind::Xor => {
+                    clauses.push(vec![-out, a, b]);
+                    clauses.push(vec![-out, -a, -b]);
+                    clauses.push(vec![out, -a, b]);
+                    clauses.push(vec![out, a, -b]);
+                }
+            }
+        }
+        match self.valid {
+            Bit::Const(true) => {}
+            // An empty clause: nothing satisfies the formula.
+            Bit::Const(false) => clauses.push(Vec::new()),
+            Bit::Wire(wire) => clauses.push(vec![var(wire)]),
+        }
+        let mut out = format!("p cnf {} {}\n", self.wires, clauses.len());
+        for clause in clauses {
+            for literal in clause {
+                write!(out, "{} ", literal).unwrap();
+            }
+            out.push_str("0\n");
+        }
+        out
+    }
+}
+
+/// Lowers the graph to a boolean circuit, see the module documentation. Panics if the graph has
+/// custom nodes, which have no gates: lower them with `lower_custom_ops` first.
+pub fn lower(builder: &Builder) -> BooleanCircuit {
+    try_lower(builder).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Like `lower`, but returns `BuildError::Unlowered` instead of panicking if the graph has
+/// custom nodes.
+pub fn try_lower(builder: &Builder) -> Result<BooleanCircuit, BuildError> {
+    builder.check_lowered()?;
+    let graph = &builder.graph;
+    let mut lowering = Lowering {
+        wires: 1,
+        gates: Vec::new(),
+    };
+    // Inputs come first, as Bristol fashion requires.
+    let mut inputs = Vec::new();
+    for id in 0..graph.len() {
+        let advice = match graph.kind(id) {
+            Kind::Input => true,
+            Kind::Hint => builder.hint_calls.contains_key(&id),
+            _ => false,
+        };
+        if advice {
+            let wires: Vec<usize> = (lowering.wires..lowering.wires + WIDTH).collect();
+            lowering.wires += WIDTH;
+            inputs.push((id, wires));
+        }
+    }
+
+    let mut next_input = inputs.iter();
+    let mut words: Vec<Vec<Bit>> = Vec::with_capacity(graph.len());
+    let mut overflow = Bit::Const(false);
+    for id in 0..graph.len() {
+        let word = match (graph.kind(id), graph.operands(id), graph.mul_add(id)) {
+            (Kind::Add, Some((a, b)), _) => {
+                let (sum, carry) = lowering.add(&words[a], &words[b], Bit::Const(false));
+                overflow = lowering.or(overflow, carry);
+                sum
+            }
+            (Kind::Mul, Some((a, b)), _) => {
+                let (product, overflows) = lowering.mul(&words[a], &words[b]);
+                overflow = lowering.or(overflow, overflows);
+                product
+            }
+            (Kind::MulAdd, _, Some([a, b, c])) => {
+                let (product, overflows) = lowering.mul(&words[a], &words[b]);
+                let (sum, carry) = lowering.add(&product, &words[c], Bit::Const(false));
+                let overflows = lowering.or(overflows, carry);
+                overflow = lowering.or(overflow, overflows);
+                sum
+            }
+            (Kind::Input, ..) => wires(&next_input.next().unwrap().1),
+            (Kind::Hint, ..) if builder.hint_calls.contains_key(&id) => {
+                wires(&next_input.next().unwrap().1)
+            }
+            _ => constant(graph.output(id).unwrap()),
+        };
+        words.push(word);
+    }
+
+    let mut valid = lowering.not(overflow);
+    for (a, b) in builder.copies() {
+        let equal = lowering.equal(&words[a], &words[b]);
+        valid = lowering.and(valid, equal);
+    }
+    for check in &builder.checks {
+        let holds = match *check {
+            Check::Range { node, bits } => lowering.fits(&words[node], bits),
+            Check::LessThan { a, b, bits } => {
+                let less = lowering.less(&words[a], &words[b]);
+                let fits = lowering.fits(&words[b], bits);
+                lowering.and(less, fits)
+            }
+            Check::InSet { node, ref set } => set.iter().fold(Bit::Const(false), |any, &value| {
+                let equal = lowering.equal(&words[node], &constant(value));
+                lowering.or(any, equal)
+            }),
+            Check::Permutation { ref a, ref b } => {
+                let a = lowering.sort(a.iter().map(|&node| words[node].clone()).collect());
+                let b = lowering.sort(b.iter().map(|&node| words[node].clone()).collect());
+                a.iter().zip(&b).fold(Bit::Const(true), |all, (a, b)| {
+                    let equal = lowering.equal(a, b);
+                    lowering.and(all, equal)
+                })
+            }
+        };
+        valid = lowering.and(valid, holds);
+    }
+
+    Ok(BooleanCircuit {
+        wires: lowering.wires,
+        inputs,
+        gates: lowering.gates,
+        outputs: builder
+            .public
+            .iter()
+            .map(|&id| (id, words[id].clone()))
+            .collect(),
+        valid,
+    })
+}
+
+fn wires(wires: &[usize]) -> Vec<Bit> {
+    wires.iter().map(|&wire| Bit::Wire(wire)).collect()
+}
+
+fn constant(value: u32) -> Vec<Bit> {
+    (0..WIDTH)
+        .map(|i| Bit::Const(value >> i & 1 == 1))
+        .collect()
+}
+
+/// The gates emitted so far, with constants folded away.
+struct Lowering {
+    wires: usize,
+    gates: Vec<Gate>,
+}
+
+impl Lowering {
+    fn gate(&mut self, kind: GateKind, a: usize, b: usize) -> Bit {
+        self.gates.push(Gate {
+            kind,
+            a,
+            b,
+            out: self.wires,
+        });
+        self.wires += 1;
+        Bit::Wire(self.wires - 1)
+    }
+
+    fn and(&mut self, a: Bit, b: Bit) -> Bit {
+        match (a, b) {
+            (Bit::Const(false), _) | (_, Bit::Const(false)) => Bit::Const(false),
+            (Bit::Const(true), other) | (other, Bit::Const(true)) => other,
+            (Bit::Wire(a), Bit::Wire(b)) if a == b => Bit::Wire(a),
+            (Bit::Wire(a), Bit::Wire(b)) => self.gate(GateKind::And, a, b),
+        }
+    }
+
+    fn xor(&mut self, a: Bit, b: Bit) -> Bit {
+        match (a, b) {
+            (Bit::Const(a), Bit::Const(b)) => Bit::Const(a ^ b),
+            (Bit::Const(false), other) | (other, Bit::Const(false)) => other,
+            (Bit::Const(true), Bit::Wire(wire)) | (Bit::Wire(wire), Bit::Const(true)) => {
+                self.gate(GateKind::Xor, wire, ONE)
+            }
+            (Bit::Wire(a), Bit::Wire(b)) if a == b => Bit::Const(false),
+            (Bit::Wire(a), Bit::Wire(b)) => self.gate(GateKind::Xor, a, b),
+        }
+    }
+
+    fn not(&mut self, a: Bit) -> Bit {
+        self.xor(a, Bit::Const(true))
+    }
+
+    fn or(&mut self, a: Bit, b: Bit) -> Bit {
+        let both = self.and(a, b);
+        let either = self.xor(a, b);
+        self.xor(either, both)
+    }
+
+    /// `x` if `select` is 1, `y` otherwise.
+    fn mux(&mut self, select: Bit, x: Bit, y: Bit) -> Bit {
+        let difference = self.xor(x, y);
+        let picked = self.and(select, difference);
+        self.xor(y, picked)
+    }
+
+    /// The sum of two words and a carry in, and the carry out.
+    fn add(&mut self, a: &[Bit], b: &[Bit], mut carry: Bit) -> (Vec<Bit>, Bit) {
+        let mut sum = Vec::with_capacity(WIDTH);
+        for (&a, &b) in a.iter().zip(b) {
+            let half = self.xor(a, b);
+            sum.push(self.xor(half, carry));
+            // The carry is a majority: (a AND b) XOR (carry AND (a XOR b)).
+            let generated = self.and(a, b);
+            let propagated = self.and(carry, half);
+            carry = self.xor(generated, propagated);
+        }
+        (sum, carry)
+    }
+
+    /// The low `WIDTH` bits of the product, and whether it overflows: whether a partial product
+    /// has a bit beyond them, or adding them carries out.
+    fn mul(&mut self, a: &[Bit], b: &[Bit]) -> (Vec<Bit>, Bit) {
+        let mut product = constant(0);
+        let mut overflow = Bit::Const(false);
+        for (shift, &b) in b.iter().enumerate() {
+            let mut partial = vec![Bit::Const(false); WIDTH];
+            for (i, &a) in a.iter().enumerate() {
+                let bit = self.and(a, b);
+                if i + shift < WIDTH {
+                    partial[i + shift] = bit;
+                } else {
+                    overflow = self.or(overflow, bit);
+                }
+            }
+            if partial.iter().all(|&bit| bit == Bit::Const(false)) {
+                continue;
+            }
+            let (sum, carry) = self.add(&product, &partial, Bit::Const(false));
+            product = sum;
+            overflow = self.or(overflow, carry);
+        }
+        (product, overflow)
+    }
+
+    fn equal(&mut self, a: &[Bit], b: &[Bit]) -> Bit {
+        let mut differ = Bit::Const(false);
+        for (&a, &b) in a.iter().zip(b) {
+            let bit = self.xor(a, b);
+            differ = self.or(differ, bit);
+        }
+        self.not(differ)
+    }
+
+    /// Whether `a` fits in `bits` bits.
+    fn fits(&mut self, a: &[Bit], bits: u32) -> Bit {
+        let mut high = Bit::Const(false);
+        for &bit in a.iter().skip(bits as usize) {
+            high = self.or(high, bit);
+        }
+        self.not(high)
+    }
+
+    /// Whether `a < b`: `a + NOT b + 1`, which is `a - b` modulo 2^WIDTH, doesn't carry out.
+    fn less(&mut self, a: &[Bit], b: &[Bit]) -> Bit {
+        let not_b: Vec<Bit> = b.iter().map(|&bit| self.not(bit)).collect();
+        let (_, carry) = self.add(a, &not_b, Bit::Const(true));
+        self.not(carry)
+    }
+
+    /// The words in increasing order, by an odd-even transposition sort.
+    fn sort(&mut self, mut words: Vec<Vec<Bit>>) -> Vec<Vec<Bit>> {
+        for round in 0..words.len() {
+            for i in (round % 2..words.len().saturating_sub(1)).step_by(2) {
+                let swap = self.less(&words[i + 1], &words[i]);
+                let (low, high): (Vec<Bit>, Vec<Bit>) = words[i]
+                    .clone()
+                    .into_iter()
+                    .zip(words[i + 1].clone())
+                    .map(|(x, y)| (self.mux(swap, y, x), self.mux(swap, x, y)))
+                    .unzip();
+                words[i] = low;
+                words[i + 1] = high;
+            }
+        }
+        words
+    }
+}
@@ -0,0 +1,157 @@
+//! Static bounds on node values, for finding overflows before any witness is evaluated.
+//!
+//! `Builder::bounds` propagates an interval from each input through the graph: additions,
+//! multiplications and mul-adds combine the bounds of their operands, constants and fixed hints are exact, and
+//! `sqrt` and `not` hints map the bounds of their linked node. Inputs without a declared range and
+//! hints computed by other functions may be any u32. Constraints are not used to narrow the bounds.
+//!
+//! Bounds are computed on 64 bits, so a node whose maximum exceeds `u32::MAX` may overflow. Nodes
+//! computed from it use the part of its interval that fits in a u32, since evaluation stops at an
+//! overflow, so they are only reported if their own operation may overflow too.
+
+use std::ops::RangeInclusive;
+
+use crate::error::BuildError;
+use crate::savepoint::generation_of;
+use crate::storage::Kind;
+use crate::{Builder, NodeId};
+
+/// The values an input may take, both ends included.
+pub type InputRange = (NodeId, RangeInclusive<u32>);
+
+/// The values a node may take, both ends included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub min: u64,
+    pub max: u64,
+}
+
+impl Interval {
+    fn exact(value: u32) -> Self {
+        Interval {
+            min: value.into(),
+            max: value.into(),
+        }
+    }
+
+    fn of(range: &RangeInclusive<u32>) -> Self {
+        Interval {
+            min: (*range.start()).into(),
+            max: (*range.end()).into(),
+        }
+    }
+
+    pub fn contains(&self, value: u64) -> bool {
+        (self.min..=self.max).contains(&value)
+    }
+
+    /// The part of the interval that fits in a u32.
+    fn clamped(self) -> Self {
+        let limit = u64::from(u32::MAX);
+        Interval {
+            min: self.min.min(limit),
+            max: self.max.min(limit),
+        }
+    }
+}
+
+/// The result of `Builder::bounds`: an interval for every node, indexed by node index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bounds {
+    pub nodes: Vec<Interval>,
+    /// The generation of the builder the bounds were computed for, and its earlier generations.
+    generation: u32,
+    ancestors: Vec<(u32, usize)>,
+}
+
+impl Bounds {
+    /// The bounds of a node. Panics if the id belongs to another builder than the one the bounds
+    /// were computed for.
+    pub fn get(&self, id: NodeId) -> Interval {
+        if id.generation != generation_of(&self.ancestors, self.generation, id.index()) {
+            panic!("{}", BuildError::StaleNode { node: id.index() });
+        }
+        self.nodes[id.index()]
+    }
+
+    /// The nodes whose value may not fit in a u32, which makes evaluation fail, in index order.
+    pub fn overflows(&self) -> Vec<usize> {
+        self.exceeding(u64::from(u32::MAX) + 1)
+    }
+
+    /// The nodes whose value may be `modulus` or more, e.g. the modulus of the field a proof
+    /// system works in, in index order.
+    pub fn exceeding(&self, modulus: u64) -> Vec<usize> {
+        (0..self.nodes.len())
+            .filter(|&id| self.nodes[id].max >= modulus)
+            .collect()
+    }
+}
+
+impl Builder {
+    /// Computes bounds on the value of every node, given a range for some of the inputs. Inputs
+    /// without a range may be any u32. Panics if an id in `ranges` is not an input of this builder.
+    pub fn bounds(&self, ranges: &[InputRange]) -> Bounds {
+        let full = Interval::of(&(0..=u32::MAX));
+        let mut nodes = vec![full; self.graph.len()];
+        for (id, range) in ranges {
+            let node = self.index(*id);
+            if self.graph.kind(node) != Kind::Input {
+                panic!("{}", BuildError::NotAnInput { node });
+            }
+            nodes[node] = Interval::of(range);
+        }
+        for id in 0..self.graph.len() {
+            nodes[id] = match self.graph.kind(id) {
+                Kind::Input => continue,
+                Kind::Constant => Interval::exact(self.graph.output(id).unwrap()),
+                Kind::Add | Kind::Mul => {
+                    let (a, b) = self.graph.operands(id).unwrap();
+                    let (a, b) = (nodes[a].clamped(), nodes[b].clamped());
+                    if self.graph.kind(id) == Kind::Add {
+                        Interval {
+                            min: a.min + b.min,
+                            max: a.max + b.max,
+                        }
+                    } else {
+                        Interval {
+                            min: a.min * b.min,
+                            max: a.max * b.max,
+                        }
+                    }
+                }
+                // The product and the sum fit in a u64, so the maximum tells whether either
+                // may overflow.
+                Kind::MulAdd => {
+                    let [a, b, c] = self.graph.mul_add(id).unwrap();
+                    let (a, b, c) = (nodes[a].clamped(), nodes[b].clamped(), nodes[c].clamped());
+                    Interval {
+                        min: a.min * b.min + c.min,
+                        max: a.max * b.max + c.max,
+                    }
+                }
+                Kind::Hint => {
+                    let linked = nodes[self.graph.linked(id).unwrap()].clamped();
+                    match self.hint_calls.get(&id).map(String::as_str) {
+                        None => Interval::exact(self.graph.output(id).unwrap()),
+                        Some("sqrt") => Interval {
+                            min: linked.min.isqrt(),
+                            max: linked.max.isqrt(),
+                        },
+                        Some("not") => Interval {
+                            min: u64::from(linked.max == 0),
+                            max: u64::from(linked.min == 0),
+                        },
+                        Some(_) => full,
+                    }
+                }
+                Kind::Custom => full,
+            };
+        }
+        Bounds {
+            nodes,
+            generation: self.generation,
+            ancestors: self.ancestors.clone(),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Builders whose node ids can't be used with another builder, checked at compile time.
+//!
+//! A `NodeId` remembers the builder that created it, so using it with another builder panics,
+//! but only once that code runs. Inside `Builder::branded`, nodes are `Wire`s instead, which carry
+//! a lifetime unique to the call: mixing wires of two branded builders doesn't compile.
+//!
+//! ```
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let y = builder.branded(|mut b| {
+//!     let x = b.init();
+//!     let five = b.constant(5);
+//!     let y = b.mul_add(x, x, five);
+//!     b.fill_nodes(x, 3);
+//!     y.id()
+//! });
+//! assert_eq!(builder.output(y), Some(14));
+//! ```
+//!
+//! ```compile_fail
+//! use my_graph_lib::Builder;
+//!
+//! let (mut first, mut second) = (Builder::new(), Builder::new());
+//! first.branded(|mut a| {
+//!     second.branded(|mut b| {
+//!         let x = a.init();
+//!         b.add(x, x); // `x` is a wire of `a`
+//!     })
+//! });
+//! ```
+//!
+//! The brand is a lifetime and costs nothing at run time. A branded builder dereferences to the
+//! `Builder`, so the rest of the API stays available with plain `NodeId`s, checked at run time.
+
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use crate::{Builder, NodeId};
+
+/// An invariant lifetime: a brand can't be converted to another one.
+type Brand<'id> = PhantomData<fn(&'id ()) -> &'id ()>;
+
+/// A node of the branded builder with the brand `'id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Wire<'id> {
+    id: NodeId,
+    brand: Brand<'id>,
+}
+
+impl Wire<'_> {
+    /// The id of the node, for use with the `Builder` once the branded scope is over.
+    pub fn id(self) -> NodeId {
+        self.id
+    }
+}
+
+/// A builder whose nodes are `Wire`s with the brand `'id`, see `Builder::branded`.
+pub struct Branded<'id, 'b> {
+    builder: &'b mut Builder,
+    brand: Brand<'id>,
+}
+
+impl Builder {
+    /// Calls `f` with a branded view of this builder, whose wires can only be used with it.
+    pub fn branded<R>(&mut self, f: impl for<'id> FnOnce(Branded<'id, '_>) -> R) -> R {
+        f(Branded {
+            builder: self,
+            brand: PhantomData,
+        })
+    }
+}
+
+impl<'id> Branded<'id, '_> {
+    fn wrap(&self, id: NodeId) -> Wire<'id> {
+        Wire {
+            id,
+            brand: self.brand,
+        }
+    }
+
+    /// Brands a node created before the scope. Panics if it doesn't belong to this builder.
+    pub fn wire(&self, id: NodeId) -> Wire<'id> {
+        self.builder.index(id);
+        self.wrap(id)
+    }
+
+    /// Like `Builder::init`.
+    pub fn init(&mut self) -> Wire<'id> {
+        let id = self.builder.init();
+        self.wrap(id)
+    }
+
+    /// Like `Builder::private_input`.
+    pub fn private_input(&mut self) -> Wire<'id> {
+        let id = self.builder.private_input();
+        self.wrap(id)
+    }
+
+    /// Like `Builder::constant`.
+    pub fn constant(&mut self, value: u32) -> Wire<'id> {
+        let id = self.builder.constant(value);
+        self.wrap(id)
+    }
+
+    /// Like `Builder::add`.
+    pub fn add(&mut self, a: Wire<'id>, b: Wire<'id>) -> Wire<'id> {
+        let id = self.builder.add(a.id, b.id);
+        self.wrap(id)
+    }
+
+    /// Like `Builder::mul`.
+    pub fn mul(&mut self, a: Wire<'id>, b: Wire<'id>) -> Wire<'id> {
+        let id = self.builder.mul(a.id, b.id);
+        self.wrap(id)
+    }
+
+    /// Like `Builder::mul_add`.
+    pub fn mul_add(&mut self, a: Wire<'id>, b: Wire<'id>, c: Wire<'id>) -> Wire<'id> {
+        let id = self.builder.mul_add(a.id, b.id, c.id);
+        self.wrap(id)
+    }
+
+    /// Like `Builder::square`.
+    pub fn square(&mut self, a: Wire<'id>) -> Wire<'id> {
+        let id = self.builder.square(a.id);
+        self.wrap(id)
+    }
+
+    /// Like `Builder::double`.
+    pub fn double(&mut self, a: Wire<'id>) -> Wire<'id> {
+        let id = self.builder.double(a.id);
+        self.wrap(id)
+    }
+
+    /// Like `Builder::hint_fn`.
+    pub fn hint_fn(&mut self, name: &str, hint_node: Wire<'id>) -> Wire<'id> {
+        let id = self.builder.hint_fn(name, hint_node.id);
+        self.wrap(id)
+    }
+
+    /// Like `Builder::enforce_equal`.
+    pub fn enforce_equal(&mut self, a: Wire<'id>, b: Wire<'id>) {
+        self.builder.enforce_equal(a.id, b.id);
+    }
+
+    /// Like `Builder::mark_public`.
+    pub fn mark_public(&mut self, id: Wire<'id>) {
+        self.builder.mark_public(id.id);
+    }
+
+    /// Like `Builder::set_label`.
+    pub fn set_label(&mut self, id: Wire<'id>, label: &str) {
+        self.builder.set_label(id.id, label);
+    }
+
+    /// Like `Builder::fill_nodes`.
+    pub fn fill_nodes(&mut self, input_node: Wire<'id>, input_val: u32) {
+        self.builder.fill_nodes(input_node.id, input_val);
+    }
+
+    /// Like `Builder::fill_inputs`.
+    pub fn fill_inputs(&mut self, inputs: &[(Wire<'id>, u32)]) {
+        let inputs: Vec<(NodeId, u32)> = inputs.iter().map(|&(id, value)| (id.id, value)).collect();
+        self.builder.fill_inputs(&inputs);
+    }
+
+    /// Like `Builder::output`.
+    pub fn output(&self, id: Wire<'id>) -> Option<u32> {
+        self.builder.output(id.id)
+    }
+}
+
+impl Deref for Branded<'_, '_> {
+    type Target = Builder;
+
+    fn deref(&self) -> &Builder {
+        self.builder
+    }
+}
+
+impl DerefMut for Branded<'_, '_> {
+    fn deref_mut(&mut self) -> &mut Builder {
+        self.builder
+    }
+}
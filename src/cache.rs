@@ -0,0 +1,245 @@
+//! Reusing the part of an evaluation that only depends on inputs that rarely change.
+//!
+//! Some workloads evaluate the same graph over and over with mostly identical inputs, e.g. a block
+//! header where only the nonce changes. `Builder::eval_cache(&stable)` picks the inputs that are
+//! expected to stay the same, and finds the nodes computed from them alone (and from constants
+//! and fixed hints): the shared prefix of every evaluation. `fill_inputs_cached` then fills in the
+//! graph like `fill_inputs`, but keys the values of that prefix on the values of the stable inputs:
+//! when the stable inputs were seen before, the prefix is copied back and only the other nodes are
+//! computed.
+//!
+//! ```
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let header = builder.init();
+//! let nonce = builder.init();
+//! let squared = builder.square(header);
+//! let digest = builder.mul(squared, squared);
+//! let result = builder.add(digest, nonce);
+//! let mut cache = builder.eval_cache(&[header]);
+//! assert_eq!(cache.prefix_len(), 2);
+//! for nonce_value in 0..3 {
+//!     builder.fill_inputs_cached(&mut cache, &[(header, 3), (nonce, nonce_value)]);
+//!     assert_eq!(builder.output(result), Some(81 + nonce_value));
+//! }
+//! assert_eq!((cache.hits(), cache.misses()), (2, 1));
+//! ```
+//!
+//! Hint functions are assumed to be deterministic. The cache is tied to the graph it was made for:
+//! it starts over when nodes are added, but other changes to the graph, such as rewriting it or
+//! rolling it back and adding as many nodes, need a new cache.
+
+use std::collections::HashMap;
+
+use crate::error::{BuildError, EvalError};
+use crate::eval::EvalOptions;
+use crate::storage::Kind;
+use crate::{Builder, NodeId};
+
+/// The number of stable input assignments a cache keeps by default.
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// Values of the shared prefix of evaluations, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct EvalCache {
+    stable: Vec<NodeId>,
+    inputs: Vec<usize>,
+    nodes: usize,
+    /// The computed nodes that only depend on the stable inputs.
+    prefix: Vec<usize>,
+    /// Every other node.
+    rest: Vec<usize>,
+    entries: HashMap<Vec<u32>, Entry>,
+    capacity: usize,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    values: Vec<Option<u32>>,
+    used: u64,
+}
+
+impl EvalCache {
+    /// Keeps at most `entries` stable input assignments, dropping the least recently used ones
+    /// ([`DEFAULT_CAPACITY`] by default).
+    pub fn capacity(mut self, entries: usize) -> Self {
+        self.capacity = entries.max(1);
+        self
+    }
+
+    /// The number of nodes reused when the stable inputs were seen before.
+    pub fn prefix_len(&self) -> usize {
+        self.prefix.len()
+    }
+
+    /// The number of stable input assignments kept.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The number of evaluations that reused a prefix.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// The number of evaluations that computed the prefix.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Forgets every stable input assignment.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Finds the prefix and the other nodes of the builder's graph.
+    fn analyze(&mut self, builder: &Builder) -> Result<(), BuildError> {
+        let mut inputs = Vec::with_capacity(self.stable.len());
+        for &id in &self.stable {
+            let node = builder.resolve(id)?;
+            if builder.graph.kind(node) != Kind::Input {
+                return Err(BuildError::NotAnInput { node });
+            }
+            inputs.push(node);
+        }
+        let len = builder.graph.len();
+        let mut stable = vec![false; len];
+        for &node in &inputs {
+            stable[node] = true;
+        }
+        self.prefix.clear();
+        self.rest.clear();
+        for node in 0..len {
+            let computed = match builder.graph.kind(node) {
+                Kind::Input => false,
+                Kind::Constant => {
+                    stable[node] = true;
+                    false
+                }
+                Kind::Hint if !builder.hint_calls.contains_key(&node) => {
+                    stable[node] = true;
+                    false
+                }
+                _ => {
+                    // A forward reference is never stable, as its operand is not marked yet.
+                    stable[node] = builder
+                        .reads(node)
+                        .into_iter()
+                        .flatten()
+                        .all(|read| read < node && stable[read]);
+                    true
+                }
+            };
+            if computed && stable[node] {
+                self.prefix.push(node);
+            } else {
+                self.rest.push(node);
+            }
+        }
+        self.inputs = inputs;
+        self.nodes = len;
+        self.entries.clear();
+        Ok(())
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| key.clone())
+                .unwrap();
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+impl Builder {
+    /// A cache for evaluations in which the inputs `stable` rarely change, see the `cache` module.
+    /// Panics if one of them is not an input of this builder.
+    pub fn eval_cache(&self, stable: &[NodeId]) -> EvalCache {
+        self.try_eval_cache(stable)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `eval_cache`, but returns an error instead of panicking.
+    pub fn try_eval_cache(&self, stable: &[NodeId]) -> Result<EvalCache, BuildError> {
+        let mut cache = EvalCache {
+            stable: stable.to_vec(),
+            inputs: Vec::new(),
+            nodes: 0,
+            prefix: Vec::new(),
+            rest: Vec::new(),
+            entries: HashMap::new(),
+            capacity: DEFAULT_CAPACITY,
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        };
+        cache.analyze(self)?;
+        Ok(cache)
+    }
+
+    /// Fills in all the nodes of the graph like `fill_inputs`, reusing the values of the nodes
+    /// computed from the stable inputs alone when these inputs had the same values before.
+    pub fn fill_inputs_cached(&mut self, cache: &mut EvalCache, inputs: &[(NodeId, u32)]) {
+        self.try_fill_inputs_cached(cache, inputs)
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    /// Like `fill_inputs_cached`, but returns an error instead of panicking. Nothing is cached from
+    /// an evaluation that fails.
+    pub fn try_fill_inputs_cached(
+        &mut self,
+        cache: &mut EvalCache,
+        inputs: &[(NodeId, u32)],
+    ) -> Result<(), EvalError> {
+        self.try_set_inputs(inputs)?;
+        if cache.nodes != self.graph.len() {
+            cache.analyze(self)?;
+        }
+        let key: Option<Vec<u32>> = cache
+            .inputs
+            .iter()
+            .map(|&node| self.graph.output(node))
+            .collect();
+        // Without a value for every stable input there is no prefix to share.
+        let Some(key) = key else {
+            return self.fill_from(0, EvalOptions::new());
+        };
+        cache.clock += 1;
+        if let Some(entry) = cache.entries.get_mut(&key) {
+            entry.used = cache.clock;
+            cache.hits += 1;
+            let start = self.start_timer();
+            for (&node, &value) in cache.prefix.iter().zip(&entry.values) {
+                self.graph.set_output(node, value);
+            }
+            for &node in &cache.rest {
+                self.try_fill_index(node)?;
+            }
+            self.record_evaluation(start);
+            return Ok(());
+        }
+        cache.misses += 1;
+        self.fill_from(0, EvalOptions::new())?;
+        cache.evict();
+        let values = cache
+            .prefix
+            .iter()
+            .map(|&node| self.graph.output(node))
+            .collect();
+        let used = cache.clock;
+        cache.entries.insert(key, Entry { values, used });
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+//! Snapshots of a partially filled graph, so long evaluations can survive a process restart.
+//!
+//! A checkpoint only stores output values and how far the evaluation got. The graph itself has
+//! to be rebuilt (or reloaded) before resuming with `Builder::resume_with`.
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::eval::{EvalError, EvalOptions};
+use crate::Builder;
+
+/// The state of an evaluation that has filled in every node before `next`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub nodes: usize, // Number of nodes in the graph, to catch resuming against the wrong graph.
+    pub next: usize,
+    pub values: Vec<Option<u32>>,
+}
+
+impl Checkpoint {
+    /// Reads a checkpoint written during evaluation.
+    pub fn load(path: &Path) -> Result<Checkpoint, EvalError> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Writes the checkpoint next to its destination first and then renames it into place,
+    /// so a crash mid-write never leaves a truncated checkpoint behind.
+    pub fn save(&self, path: &Path) -> Result<(), EvalError> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+impl Builder {
+    /// Captures the current output values, for an evaluation that has filled in every node before `next`.
+    pub fn checkpoint(&self, next: usize) -> Checkpoint {
+        Checkpoint {
+            nodes: self.graph.len(),
+            next,
+            values: self.graph.outputs().collect(),
+        }
+    }
+
+    /// Restores the output values from a checkpoint and fills in the rest of the graph.
+    pub fn resume_with(
+        &mut self,
+        checkpoint: &Checkpoint,
+        options: EvalOptions,
+    ) -> Result<(), EvalError> {
+        if checkpoint.nodes != self.graph.len() || checkpoint.values.len() != self.graph.len() {
+            return Err(EvalError::InvalidCheckpoint(format!(
+                "checkpoint is for a graph with {} nodes, this graph has {}",
+                checkpoint.nodes,
+                self.graph.len()
+            )));
+        }
+        if checkpoint.next > self.graph.len() {
+            return Err(EvalError::InvalidCheckpoint(format!(
+                "checkpoint resumes at node {} past the end of the graph",
+                checkpoint.next
+            )));
+        }
+        for (id, &value) in checkpoint.values.iter().enumerate() {
+            self.graph.set_output(id, value);
+        }
+        self.fill_from(checkpoint.next, options)
+    }
+}
@@ -0,0 +1,287 @@
+//! Constraints other than equalities between two nodes.
+//!
+//! Graphs only compute additions, multiplications and single-input hints, with no subtraction,
+//! so conditions such as ranges, orderings, set membership and permutations can't be built out of nodes. They are
+//! recorded as checks instead, next to the equality constraints, and verified by `check` and
+//! `check_constraints` after the equalities. Checks are numbered from 0 in the order they were
+//! recorded, separately from the equalities.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BuildError, ConstraintError, SerdeError};
+use crate::observer::Constraint;
+use crate::{Builder, NodeId};
+
+/// A constraint recorded with `assert_range`, `assert_lt`, `assert_in_set` or
+/// `assert_permutation`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Check {
+    /// `a < b < 2^bits`: the difference `b - a - 1` and `b` both fit in `bits` bits.
+    LessThan { a: usize, b: usize, bits: u32 },
+    /// The output of `node` is one of `set`, which is sorted and has no duplicates.
+    InSet { node: usize, set: Vec<u32> },
+    /// The outputs of `a` are the outputs of `b` in some order.
+    Permutation { a: Vec<usize>, b: Vec<usize> },
+    /// `node < 2^bits`: the output of `node` fits in `bits` bits.
+    Range { node: usize, bits: u32 },
+}
+
+impl Check {
+    /// The nodes whose values the check reads.
+    pub fn nodes(&self) -> Vec<usize> {
+        match self {
+            Check::LessThan { a, b, .. } => vec![*a, *b],
+            Check::InSet { node, .. } | Check::Range { node, .. } => vec![*node],
+            Check::Permutation { a, b } => a.iter().chain(b).copied().collect(),
+        }
+    }
+
+    /// Checks what the `assert_` methods guarantee of a check read from a file, where it is check
+    /// number `check`: it reads some of the first `len` nodes, its bit width is between 1 and 32,
+    /// its set is not empty and its lists have the same length.
+    pub(crate) fn validate(&self, check: usize, len: usize) -> Result<(), SerdeError> {
+        if let Some(node) = self.nodes().into_iter().find(|&node| node >= len) {
+            return Err(SerdeError::IndexOutOfRange {
+                at: format!("check {}", check),
+                index: node,
+                len,
+            });
+        }
+        let (at, found, expected) = match self {
+            Check::LessThan { bits, .. } | Check::Range { bits, .. }
+                if !(1..=32).contains(bits) =>
+            {
+                ("the bit width", u64::from(*bits), "1 to 32".to_string())
+            }
+            Check::InSet { set, .. } if set.is_empty() => ("the set", 0, "at least 1".to_string()),
+            Check::Permutation { a, b } if a.len() != b.len() => (
+                "the second list",
+                b.len() as u64,
+                format!("{}, the length of the first", a.len()),
+            ),
+            _ => return Ok(()),
+        };
+        Err(SerdeError::BadLength {
+            at: format!("{} of check {}", at, check),
+            found,
+            expected,
+        })
+    }
+
+    /// The same check on other nodes: every node `n` it reads is replaced with `f(n)`.
+    pub fn map(&self, f: impl Fn(usize) -> usize) -> Check {
+        match self {
+            Check::LessThan { a, b, bits } => Check::LessThan {
+                a: f(*a),
+                b: f(*b),
+                bits: *bits,
+            },
+            Check::InSet { node, set } => Check::InSet {
+                node: f(*node),
+                set: set.clone(),
+            },
+            Check::Permutation { a, b } => Check::Permutation {
+                a: a.iter().map(|&n| f(n)).collect(),
+                b: b.iter().map(|&n| f(n)).collect(),
+            },
+            Check::Range { node, bits } => Check::Range {
+                node: f(*node),
+                bits: *bits,
+            },
+        }
+    }
+
+    /// Whether the check holds for the values `value` returns, false if one of them is missing.
+    pub fn holds(&self, value: impl Fn(usize) -> Option<u32>) -> bool {
+        let fits = |value: u32, bits: u32| bits >= 32 || value >> bits == 0;
+        let values = |nodes: &[usize]| -> Option<Vec<u32>> {
+            let mut values: Vec<u32> = nodes
+                .iter()
+                .map(|&node| value(node))
+                .collect::<Option<_>>()?;
+            values.sort_unstable();
+            Some(values)
+        };
+        match self {
+            Check::LessThan { a, b, bits } => match (value(*a), value(*b)) {
+                (Some(a), Some(b)) => a < b && fits(b, *bits),
+                _ => false,
+            },
+            Check::InSet { node, set } => {
+                value(*node).is_some_and(|value| set.binary_search(&value).is_ok())
+            }
+            Check::Permutation { a, b } => values(a).is_some_and(|a| Some(a) == values(b)),
+            Check::Range { node, bits } => value(*node).is_some_and(|value| fits(value, *bits)),
+        }
+    }
+}
+
+impl Builder {
+    /// Records a constraint that the output of `x` fits in `bits` bits, e.g. a byte or a word
+    /// that a proof system would otherwise treat as any field element. It is verified by
+    /// `check_constraints`. Panics unless `bits` is between 1 and 32.
+    pub fn assert_range(&mut self, x: NodeId, bits: u32) {
+        self.try_assert_range(x, bits)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `assert_range`, but returns an error if the id does not belong to this builder or the
+    /// graph has reached its constraint limit. Still panics unless `bits` is between 1 and 32.
+    pub fn try_assert_range(&mut self, x: NodeId, bits: u32) -> Result<(), BuildError> {
+        assert!(
+            (1..=32).contains(&bits),
+            "assert_range needs between 1 and 32 bits, got {}",
+            bits
+        );
+        let node = self.resolve(x)?;
+        self.add_check(Check::Range { node, bits })
+    }
+
+    /// Records a constraint that the output of `a` is less than the output of `b`, and that both
+    /// fit in `bits` bits, e.g. for timestamps or balances. It is verified by `check_constraints`.
+    /// Panics unless `bits` is between 1 and 32.
+    pub fn assert_lt(&mut self, a: NodeId, b: NodeId, bits: u32) {
+        self.try_assert_lt(a, b, bits)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `assert_lt`, but returns an error if an id does not belong to this builder or the
+    /// graph has reached its constraint limit. Still panics unless `bits` is between 1 and 32.
+    pub fn try_assert_lt(&mut self, a: NodeId, b: NodeId, bits: u32) -> Result<(), BuildError> {
+        assert!(
+            (1..=32).contains(&bits),
+            "assert_lt needs between 1 and 32 bits, got {}",
+            bits
+        );
+        let (a, b) = (self.resolve(a)?, self.resolve(b)?);
+        self.add_check(Check::LessThan { a, b, bits })
+    }
+
+    /// Records a constraint that the output of `x` is one of `set`, e.g. a valid opcode or an
+    /// allowed denomination. It is verified by `check_constraints`. Panics if `set` is empty.
+    pub fn assert_in_set(&mut self, x: NodeId, set: &[u32]) {
+        self.try_assert_in_set(x, set)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `assert_in_set`, but returns an error if the id does not belong to this builder or
+    /// the graph has reached its constraint limit. Still panics if `set` is empty.
+    pub fn try_assert_in_set(&mut self, x: NodeId, set: &[u32]) -> Result<(), BuildError> {
+        assert!(!set.is_empty(), "assert_in_set needs a non-empty set");
+        let mut set = set.to_vec();
+        set.sort_unstable();
+        set.dedup();
+        let node = self.resolve(x)?;
+        self.add_check(Check::InSet { node, set })
+    }
+
+    /// Records a constraint that the outputs of `a` are the outputs of `b` in some order, counting
+    /// repeated values, as used by memory checking and shuffles. It is verified by
+    /// `check_constraints`. Panics if the vectors have different lengths.
+    pub fn assert_permutation(&mut self, a: &[NodeId], b: &[NodeId]) {
+        self.try_assert_permutation(a, b)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `assert_permutation`, but returns an error if an id does not belong to this builder
+    /// or the graph has reached its constraint limit. Still panics if the vectors have different
+    /// lengths.
+    pub fn try_assert_permutation(&mut self, a: &[NodeId], b: &[NodeId]) -> Result<(), BuildError> {
+        assert_eq!(
+            a.len(),
+            b.len(),
+            "assert_permutation needs vectors of the same length"
+        );
+        let resolve = |ids: &[NodeId]| {
+            ids.iter()
+                .map(|&id| self.resolve(id))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let (a, b) = (resolve(a)?, resolve(b)?);
+        self.add_check(Check::Permutation { a, b })
+    }
+
+    /// Records `check` if the graph has not reached its constraint limit.
+    fn add_check(&mut self, check: Check) -> Result<(), BuildError> {
+        self.add_constraint()?;
+        self.checks.push(check);
+        self.report_constraint(Constraint::Check(self.checks.len() - 1));
+        Ok(())
+    }
+
+    /// Returns the checks recorded with `assert_range`, `assert_lt`, `assert_in_set` and
+    /// `assert_permutation`, in the order they were recorded.
+    pub fn checks(&self) -> &[Check] {
+        &self.checks
+    }
+
+    /// Verifies the check at index `check`.
+    pub(crate) fn verify_check(&self, check: usize) -> Result<(), ConstraintError> {
+        match self.checks[check] {
+            Check::LessThan { a, b, bits } => {
+                let (a_value, b_value) = (self.filled(a)?, self.filled(b)?);
+                let fits = u64::from(b_value) < 1u64 << bits;
+                if a_value >= b_value || !fits {
+                    return Err(ConstraintError::NotLess {
+                        check,
+                        a,
+                        b,
+                        a_value: self.redact(a, a_value),
+                        b_value: self.redact(b, b_value),
+                        bits,
+                    });
+                }
+                Ok(())
+            }
+            Check::InSet { node, ref set } => {
+                let value = self.filled(node)?;
+                if set.binary_search(&value).is_err() {
+                    return Err(ConstraintError::NotInSet {
+                        check,
+                        node,
+                        value: self.redact(node, value),
+                    });
+                }
+                Ok(())
+            }
+            Check::Range { node, bits } => {
+                let value = self.filled(node)?;
+                if u64::from(value) >= 1u64 << bits {
+                    return Err(ConstraintError::OutOfRange {
+                        check,
+                        node,
+                        value: self.redact(node, value),
+                        bits,
+                    });
+                }
+                Ok(())
+            }
+            Check::Permutation { ref a, ref b } => {
+                // How many more times each value appears in `a` than in `b`.
+                let mut counts: BTreeMap<u32, i64> = BTreeMap::new();
+                for &node in a {
+                    *counts.entry(self.filled(node)?).or_default() += 1;
+                }
+                for &node in b {
+                    *counts.entry(self.filled(node)?).or_default() -= 1;
+                }
+                let Some((&value, &count)) = counts.iter().find(|(_, &count)| count != 0) else {
+                    return Ok(());
+                };
+                let side = if count > 0 { a } else { b };
+                let node = side
+                    .iter()
+                    .copied()
+                    .find(|&node| self.graph.output(node) == Some(value))
+                    .unwrap();
+                Err(ConstraintError::NotPermutation {
+                    check,
+                    node,
+                    value: self.redact(node, value),
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Commitments to witnesses, so a party can commit to a witness before revealing it.
+//!
+//! A commitment is a hash of the canonical encoding of the witness: a domain tag, the salt, the
+//! number of values, and then every value as a `0` byte for an unfilled node or a `1` byte
+//! followed by the value in little-endian order. Witnesses with few possible values can be
+//! guessed from an unsalted commitment, so commit with a random salt and reveal it along with the
+//! witness.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+use crate::{Builder, Witness};
+
+/// Separates witness commitments from other uses of the same hash functions.
+const DOMAIN: &[u8] = b"my_graph_lib witness commitment v1";
+
+/// The hash function a commitment is computed with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashFunction {
+    #[default]
+    Blake3,
+    Sha256,
+}
+
+/// A hash committing to a witness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Commitment {
+    pub hash: HashFunction,
+    pub digest: [u8; 32],
+}
+
+/// Prints the commitment as e.g. `blake3:4f1c…`, with the digest in hex.
+impl fmt::Display for Commitment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.hash {
+            HashFunction::Blake3 => "blake3",
+            HashFunction::Sha256 => "sha256",
+        };
+        write!(f, "{}:", name)?;
+        for byte in self.digest {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+enum Hasher {
+    Blake3(Box<blake3::Hasher>),
+    Sha256(sha2::Sha256),
+}
+
+impl Hasher {
+    fn new(hash: HashFunction) -> Self {
+        match hash {
+            HashFunction::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+            HashFunction::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+            Hasher::Sha256(hasher) => hasher.update(bytes),
+        }
+    }
+
+    fn finalize(self) -> [u8; 32] {
+        match self {
+            Hasher::Blake3(hasher) => *hasher.finalize().as_bytes(),
+            Hasher::Sha256(hasher) => hasher.finalize().into(),
+        }
+    }
+}
+
+/// Hashes the canonical encoding of `values`.
+fn commit(
+    values: impl ExactSizeIterator<Item = Option<u32>>,
+    hash: HashFunction,
+    salt: &[u8],
+) -> Commitment {
+    let mut hasher = Hasher::new(hash);
+    hasher.update(DOMAIN);
+    hasher.update(&(salt.len() as u64).to_le_bytes());
+    hasher.update(salt);
+    hasher.update(&(values.len() as u64).to_le_bytes());
+    for value in values {
+        match value {
+            Some(value) => {
+                hasher.update(&[1]);
+                hasher.update(&value.to_le_bytes());
+            }
+            None => hasher.update(&[0]),
+        }
+    }
+    Commitment {
+        hash,
+        digest: hasher.finalize(),
+    }
+}
+
+impl Witness {
+    /// Commits to the witness with BLAKE3 and no salt.
+    pub fn commitment(&self) -> Commitment {
+        self.commitment_with(HashFunction::default(), &[])
+    }
+
+    /// Commits to the witness with the given hash function and salt.
+    pub fn commitment_with(&self, hash: HashFunction, salt: &[u8]) -> Commitment {
+        commit(self.values.iter().copied(), hash, salt)
+    }
+}
+
+impl Builder {
+    /// Returns true if the graph's current values are the witness `commitment` was computed from,
+    /// e.g. after a witness revealed by another party has been loaded with `load_witness`.
+    /// `salt` is the salt the commitment was computed with, empty for `Witness::commitment`.
+    pub fn verify_commitment(&self, commitment: &Commitment, salt: &[u8]) -> bool {
+        commit(self.graph.outputs(), commitment.hash, salt) == *commitment
+    }
+}
@@ -0,0 +1,72 @@
+//! zstd compression of serialized graphs, built with `--features zstd`.
+//!
+//! Graphs repeat a handful of node shapes many times over, so their serializations shrink a lot
+//! when compressed. `compress` wraps the output of any serializer in a zstd frame, and loaders
+//! recognize the frame and decompress it first: `Builder::from_json_bytes`, `from_protobuf`,
+//! `from_flatbuffer` and `Witness::from_protobuf` accept compressed and uncompressed input alike.
+//! A `flat::FlatCircuit` reads its buffer in place, so it needs the decompressed bytes.
+//!
+//! ```
+//! use my_graph_lib::compress;
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let mut x = builder.init();
+//! for _ in 0..100 {
+//!     x = builder.mul(x, x);
+//! }
+//! let bytes = builder.to_json_compressed(compress::DEFAULT_LEVEL);
+//! assert!(bytes.len() * 10 < builder.to_json().len());
+//! assert_eq!(Builder::from_json_bytes(&bytes).unwrap().len(), 101);
+//! ```
+
+use std::borrow::Cow;
+
+use crate::error::SerdeError;
+use crate::Builder;
+
+/// The level `compress` is usually called with, a balance of speed and size. Levels go from 1,
+/// the fastest, to 22, the smallest; 0 also means the default.
+pub const DEFAULT_LEVEL: i32 = zstd::DEFAULT_COMPRESSION_LEVEL;
+
+/// The first bytes of every zstd frame.
+const MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compresses `bytes` into a zstd frame. Levels outside of the supported range are clamped to it.
+pub fn compress(bytes: &[u8], level: i32) -> Vec<u8> {
+    zstd::encode_all(bytes, level).expect("compressing in memory does not fail")
+}
+
+/// Whether `bytes` starts like a zstd frame.
+pub fn is_compressed(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// Decompresses `bytes` if it is a zstd frame, and returns it unchanged otherwise.
+pub fn decompress(bytes: &[u8]) -> Result<Cow<'_, [u8]>, SerdeError> {
+    if !is_compressed(bytes) {
+        return Ok(Cow::Borrowed(bytes));
+    }
+    zstd::decode_all(bytes)
+        .map(Cow::Owned)
+        .map_err(SerdeError::Zstd)
+}
+
+impl Builder {
+    /// `to_json`, compressed at `level` (see `DEFAULT_LEVEL`).
+    pub fn to_json_compressed(&self, level: i32) -> Vec<u8> {
+        compress(self.to_json().as_bytes(), level)
+    }
+
+    /// Deserializes a graph produced by `to_json` or `to_json_compressed`.
+    pub fn from_json_bytes(bytes: &[u8]) -> Result<Builder, SerdeError> {
+        let bytes = decompress(bytes)?;
+        let json = std::str::from_utf8(&bytes).map_err(|err| {
+            SerdeError::Json(serde::de::Error::custom(format!(
+                "graph JSON is not UTF-8: {}",
+                err
+            )))
+        })?;
+        Builder::from_json(json)
+    }
+}
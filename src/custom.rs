@@ -0,0 +1,288 @@
+//! User-defined operations, for computations that additions and multiplications express poorly,
+//! such as bitwise logic or lookups in a fixed table, without forking `OPERATION`.
+//!
+//! An operation is registered by name with `Builder::register_op`, like a hint function, and
+//! `Builder::custom(name, &operands)` adds a node computing it from one to `MAX_OPERANDS`
+//! operands. A `CustomOp` bundles:
+//!
+//! - an evaluation function, giving the value of a node from the values of its operands when the
+//!   graph is filled in, or `None` where the operation is undefined;
+//! - a check function, telling whether a value is a valid result for the values of the operands,
+//!   used by `check` and `check_constraints`, e.g. on a witness computed elsewhere. It defaults to
+//!   comparing the value with the evaluation function's result;
+//! - an optional lowering, adding nodes that compute the operation with additions,
+//!   multiplications, hints and constraints, used by `Builder::lower_custom_ops`.
+//!
+//! ```
+//! use my_graph_lib::custom::CustomOp;
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let cube = CustomOp::new(1, |x| x[0].checked_mul(x[0])?.checked_mul(x[0])).lower(|builder, x| {
+//!     let square = builder.square(x[0]);
+//!     builder.mul(square, x[0])
+//! });
+//! builder.register_op("cube", cube);
+//! let x = builder.init();
+//! let y = builder.custom("cube", &[x]);
+//! builder.mark_public(y);
+//! builder.fill_nodes(x, 3);
+//! assert_eq!(builder.output(y), Some(27));
+//! assert!(builder.check_constraints());
+//!
+//! // The same graph with only additions and multiplications.
+//! let lowered = builder.lower_custom_ops();
+//! assert_eq!(lowered.len(), 3);
+//! ```
+//!
+//! Graphs record the name of the operation of every custom node, which is how JSON stores them,
+//! so a loaded graph needs its operations registered again before it is filled in or checked.
+//! The other formats and the backends that compile graphs, such as the GPU evaluator and the
+//! exports, only know the built-in operations: lower custom nodes before using them. Exports
+//! reject graphs with custom nodes left with `BuildError::Unlowered`, since a proof system would
+//! leave their values unconstrained.
+
+use crate::error::{BuildError, ConstraintError};
+use crate::storage::Kind;
+use crate::{Builder, NodeId};
+
+/// Largest number of operands of a custom node, as for mul-add nodes.
+pub const MAX_OPERANDS: usize = 3;
+
+/// Computes the value of a custom node from the values of its operands.
+pub type EvalFn = fn(&[u32]) -> Option<u32>;
+/// Tells whether a value is a valid result of a custom node for the values of its operands.
+pub type CheckFn = fn(&[u32], u32) -> bool;
+/// Adds nodes computing a custom node from its operands to a builder, returning the result.
+pub type LowerFn = fn(&mut Builder, &[NodeId]) -> NodeId;
+
+/// A user-defined operation, see the module documentation.
+#[derive(Debug, Clone, Copy)]
+pub struct CustomOp {
+    arity: usize,
+    eval: EvalFn,
+    check: Option<CheckFn>,
+    lower: Option<LowerFn>,
+}
+
+impl CustomOp {
+    /// An operation of `arity` operands computed by `eval`. Panics unless `arity` is between 1
+    /// and `MAX_OPERANDS`.
+    pub fn new(arity: usize, eval: EvalFn) -> Self {
+        assert!(
+            (1..=MAX_OPERANDS).contains(&arity),
+            "a custom operation takes between 1 and {} operands, got {}",
+            MAX_OPERANDS,
+            arity
+        );
+        CustomOp {
+            arity,
+            eval,
+            check: None,
+            lower: None,
+        }
+    }
+
+    /// Checks results with `check` instead of evaluating the operation again.
+    pub fn check(mut self, check: CheckFn) -> Self {
+        self.check = Some(check);
+        self
+    }
+
+    /// Lets `Builder::lower_custom_ops` replace the operation with the nodes `lower` adds.
+    pub fn lower(mut self, lower: LowerFn) -> Self {
+        self.lower = Some(lower);
+        self
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn can_lower(&self) -> bool {
+        self.lower.is_some()
+    }
+
+    pub(crate) fn evaluate(&self, values: &[u32]) -> Option<u32> {
+        (self.eval)(values)
+    }
+
+    fn accepts(&self, values: &[u32], value: u32) -> bool {
+        match self.check {
+            Some(check) => check(values, value),
+            None => self.evaluate(values) == Some(value),
+        }
+    }
+}
+
+impl Builder {
+    /// Registers an operation that can be referenced by `custom`, replacing any operation with
+    /// the same name.
+    pub fn register_op(&mut self, name: &str, op: CustomOp) {
+        self.custom_ops.insert(name.to_string(), op);
+    }
+
+    /// Returns true if an operation with the given name has been registered.
+    pub fn has_op(&self, name: &str) -> bool {
+        self.custom_ops.contains_key(name)
+    }
+
+    /// Adds a node computing the registered operation `name` from `operands`.
+    ///
+    /// Panics if no operation named `name` is registered or it takes another number of operands.
+    pub fn custom(&mut self, name: &str, operands: &[NodeId]) -> NodeId {
+        self.try_custom(name, operands)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `custom`, but returns an error if no operation named `name` is registered, it takes
+    /// another number of operands, an id does not belong to this builder or the new node would
+    /// exceed a limit.
+    pub fn try_custom(&mut self, name: &str, operands: &[NodeId]) -> Result<NodeId, BuildError> {
+        let op = self.custom_op(name)?;
+        if operands.len() != op.arity {
+            return Err(BuildError::WrongArity {
+                name: name.to_string(),
+                expected: op.arity,
+                found: operands.len(),
+            });
+        }
+        let operands = operands
+            .iter()
+            .map(|&operand| self.resolve(operand))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(depth) = self.check_node_limits(&operands)? {
+            self.depths.push(depth);
+        }
+        let id = self.graph.push_custom(&operands, None);
+        self.report_node(id);
+        self.custom_calls.insert(id, name.to_string());
+        Ok(self.id(id))
+    }
+
+    /// The name of the operation a custom node computes, or None for other nodes.
+    pub fn op_name(&self, id: NodeId) -> Option<&str> {
+        self.custom_calls
+            .get(&self.index(id))
+            .map(|name| name.as_str())
+    }
+
+    /// A copy of the graph where every custom node whose operation has a lowering is replaced by
+    /// the nodes the lowering adds. Other custom nodes are kept.
+    ///
+    /// Panics if a custom node's operation is not registered.
+    pub fn lower_custom_ops(&self) -> Builder {
+        self.try_lower_custom_ops()
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `lower_custom_ops`, but returns an error if a custom node's operation is not
+    /// registered or takes another number of operands.
+    pub fn try_lower_custom_ops(&self) -> Result<Builder, BuildError> {
+        for &node in self.custom_calls.keys() {
+            self.node_op(node)?;
+        }
+        let (builder, _) = self.rebuild(|graph, node| {
+            if graph.graph.kind(node) != Kind::Custom {
+                return node;
+            }
+            let op = graph.custom_ops[&graph.custom_calls[&node]];
+            match op.lower {
+                Some(lower) => {
+                    let operands: Vec<NodeId> = graph
+                        .graph
+                        .custom(node)
+                        .unwrap()
+                        .into_iter()
+                        .map(|operand| graph.id(operand))
+                        .collect();
+                    lower(graph, &operands).index()
+                }
+                None => node,
+            }
+        });
+        Ok(builder)
+    }
+
+    pub(crate) fn custom_op(&self, name: &str) -> Result<CustomOp, BuildError> {
+        self.custom_ops
+            .get(name)
+            .copied()
+            .ok_or_else(|| BuildError::UnknownOp {
+                name: name.to_string(),
+            })
+    }
+
+    /// The operation of custom node `node`. Returns an error if it is not registered or takes
+    /// another number of operands than the node has, e.g. after a loaded graph was registered
+    /// another operation with the same name.
+    pub(crate) fn node_op(&self, node: usize) -> Result<CustomOp, BuildError> {
+        let name = &self.custom_calls[&node];
+        self.registered_op(node)?
+            .ok_or_else(|| BuildError::UnknownOp { name: name.clone() })
+    }
+
+    /// Like `node_op`, but returns None if the operation is not registered.
+    pub(crate) fn registered_op(&self, node: usize) -> Result<Option<CustomOp>, BuildError> {
+        let name = &self.custom_calls[&node];
+        let Some(&op) = self.custom_ops.get(name) else {
+            return Ok(None);
+        };
+        let found = self.graph.custom(node).unwrap().len();
+        if found != op.arity {
+            return Err(BuildError::WrongArity {
+                name: name.clone(),
+                expected: op.arity,
+                found,
+            });
+        }
+        Ok(Some(op))
+    }
+
+    /// Checks that the graph has no custom nodes, which exports can't constrain.
+    pub(crate) fn check_lowered(&self) -> Result<(), BuildError> {
+        match self.custom_calls.iter().next() {
+            Some((&node, op)) => Err(BuildError::Unlowered {
+                node,
+                op: op.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that every registered operation takes as many operands as its custom nodes have.
+    pub(crate) fn check_arities(&self) -> Result<(), BuildError> {
+        for &node in self.custom_calls.keys() {
+            self.registered_op(node)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the value of custom node `node` with its operation.
+    pub(crate) fn check_custom(
+        &self,
+        node: usize,
+        operands: &[usize],
+    ) -> Result<(), ConstraintError> {
+        let name = &self.custom_calls[&node];
+        let op = self.registered_op(node)?;
+        let values = operands
+            .iter()
+            .map(|&operand| self.filled(operand))
+            .collect::<Result<Vec<_>, _>>()?;
+        let value = self.filled(node)?;
+        let accepted = match op {
+            Some(op) => op.accepts(&values, value),
+            None => false,
+        };
+        if accepted {
+            return Ok(());
+        }
+        Err(ConstraintError::CustomRejected {
+            node,
+            op: name.clone(),
+            value: self.redact(node, value),
+            registered: self.custom_ops.contains_key(name),
+        })
+    }
+}
@@ -0,0 +1,264 @@
+//! Readable reports of constraint failures.
+//!
+//! `Builder::explain` turns a `ConstraintError` into a `ConstraintReport` that shows the
+//! expressions behind the nodes involved, their values and labels, where they were defined in
+//! the source program (for graphs built by the parser) and the input assignment:
+//!
+//! ```text
+//! constraint 0 does not hold: node 2 is 17 but node 4 is 16
+//!   assert at 2:1
+//!   y = x + 7 = 17 (defined at 1:1)
+//!   n4 = hint(sqrt, y) * hint(sqrt, y) = 16 (defined at 2:1)
+//!   inputs: x = 10
+//! ```
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ConstraintError, Value};
+use crate::storage::Kind;
+use crate::{Builder, NodeId};
+
+/// How deep expressions are expanded before nodes are shown by name.
+const MAX_DEPTH: usize = 3;
+
+/// A position in the source of a textual program, counting from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    /// Converts a byte offset into `src` to a line and column.
+    pub fn from_offset(src: &str, offset: usize) -> Location {
+        let before = &src[..offset.min(src.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Location {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// How a failing constraint is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Severity {
+    /// The witness is rejected. Constraints recorded with `enforce_equal` have this severity.
+    #[default]
+    Error,
+    /// A warning is reported but the witness is accepted, for checks such as "this value should
+    /// stay small for efficiency". See `Builder::enforce_equal_warning`.
+    Warning,
+}
+
+/// A node involved in a constraint failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Side {
+    pub node: usize,
+    pub name: String,       // The node's label, or `n<index>`.
+    pub expression: String, // How the node is computed, with shallow nodes expanded.
+    pub value: Option<Value>,
+    pub location: Option<Location>,
+}
+
+/// A constraint failure with the context needed to understand it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstraintReport {
+    pub violation: ConstraintError,
+    /// The message the failing equality constraint was recorded with by `enforce_equal_with`.
+    pub message: Option<String>,
+    /// Where the failing equality constraint was asserted.
+    pub location: Option<Location>,
+    /// Both sides of a failing equality constraint, or the failing node followed by its inputs.
+    pub sides: Vec<Side>,
+    /// The value of every input node, by name.
+    pub inputs: Vec<(String, Option<Value>)>,
+}
+
+impl fmt::Display for ConstraintReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(message) = &self.message {
+            write!(f, "{}: ", message)?;
+        }
+        write!(f, "{}", self.violation)?;
+        if let Some(location) = self.location {
+            write!(f, "\n  assert at {}", location)?;
+        }
+        for side in &self.sides {
+            write!(f, "\n  {}", side.name)?;
+            if side.expression != side.name {
+                write!(f, " = {}", side.expression)?;
+            }
+            match side.value {
+                Some(value) => write!(f, " = {}", value)?,
+                None => write!(f, " = <no value>")?,
+            }
+            if let Some(location) = side.location {
+                write!(f, " (defined at {})", location)?;
+            }
+        }
+        if !self.inputs.is_empty() {
+            let inputs: Vec<String> = self
+                .inputs
+                .iter()
+                .map(|(name, value)| match value {
+                    Some(value) => format!("{} = {}", name, value),
+                    None => format!("{} = <no value>", name),
+                })
+                .collect();
+            write!(f, "\n  inputs: {}", inputs.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConstraintReport {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.violation)
+    }
+}
+
+impl Builder {
+    /// Records where a node was defined in the source of a textual program.
+    pub fn set_location(&mut self, id: NodeId, location: Location) {
+        self.locations.insert(self.index(id), location);
+    }
+
+    /// Returns where a node was defined, if known.
+    pub fn location(&self, id: NodeId) -> Option<Location> {
+        self.locations.get(&self.index(id)).copied()
+    }
+
+    /// Pretty-prints how a node is computed, e.g. `(x + 7) * hint(sqrt, y)`. Labeled nodes below
+    /// the top are shown by label, and nodes deeper than a few levels by index.
+    pub fn expression(&self, id: NodeId) -> String {
+        self.expression_at(self.index(id), 0)
+    }
+
+    /// Gathers the context of a constraint failure returned by `check` or `check_with`.
+    pub fn explain(&self, violation: &ConstraintError) -> ConstraintReport {
+        let (constraint, nodes) = self.violation_nodes(violation);
+        ConstraintReport {
+            violation: violation.clone(),
+            message: constraint
+                .and_then(|constraint| self.constraint_messages.get(&constraint).cloned()),
+            location: constraint
+                .and_then(|constraint| self.constraint_locations.get(&constraint).copied()),
+            sides: nodes.into_iter().map(|node| self.side(node)).collect(),
+            inputs: self
+                .input_nodes
+                .iter()
+                .map(|&id| (self.name(id), self.value(id)))
+                .collect(),
+        }
+    }
+
+    /// The equality constraint a violation is about, if any, and the nodes it involves: both sides
+    /// of a constraint, or the failing node followed by its inputs.
+    pub(crate) fn violation_nodes(
+        &self,
+        violation: &ConstraintError,
+    ) -> (Option<usize>, Vec<usize>) {
+        match *violation {
+            ConstraintError::NotEqual {
+                constraint, a, b, ..
+            } => (Some(constraint), vec![a, b]),
+            ConstraintError::NotLess { a, b, .. } | ConstraintError::NotAliased { a, b, .. } => {
+                (None, vec![a, b])
+            }
+            ConstraintError::WrongOutput { node, .. }
+            | ConstraintError::Overflow { node }
+            | ConstraintError::CustomRejected { node, .. } => {
+                let mut nodes = vec![node];
+                match self.graph.mul_add(node) {
+                    Some(operands) => nodes.extend(operands),
+                    None if self.graph.kind(node) == Kind::Custom => {
+                        nodes.extend(self.graph.custom(node).unwrap())
+                    }
+                    None => nodes.extend(
+                        self.graph
+                            .operands(node)
+                            .map_or(vec![], |(a, b)| vec![a, b]),
+                    ),
+                }
+                (None, nodes)
+            }
+            ConstraintError::Unfilled { node }
+            | ConstraintError::NotInSet { node, .. }
+            | ConstraintError::OutOfRange { node, .. }
+            | ConstraintError::NotPermutation { node, .. } => (None, vec![node]),
+            ConstraintError::Build(_) => (None, vec![]),
+        }
+    }
+
+    fn side(&self, node: usize) -> Side {
+        Side {
+            node,
+            name: self.name(node),
+            expression: self.expression_at(node, 0),
+            value: self.value(node),
+            location: self.locations.get(&node).copied(),
+        }
+    }
+
+    pub(crate) fn value(&self, node: usize) -> Option<Value> {
+        self.graph
+            .output(node)
+            .map(|value| self.redact(node, value))
+    }
+
+    /// The label of a node, or `n<index>` for unlabeled nodes.
+    pub(crate) fn name(&self, node: usize) -> String {
+        match self.labels.get(&node) {
+            Some(label) => label.clone(),
+            None => format!("n{}", node),
+        }
+    }
+
+    fn expression_at(&self, node: usize, depth: usize) -> String {
+        if depth > 0 && (self.labels.contains_key(&node) || depth > MAX_DEPTH) {
+            return self.name(node);
+        }
+        let operand = |operand: usize| {
+            let text = self.expression_at(operand, depth + 1);
+            // Parenthesize nested operations.
+            if text.contains(' ') && !text.starts_with("hint(") {
+                format!("({})", text)
+            } else {
+                text
+            }
+        };
+        if let (Some(op), Some((a, b))) = (self.graph.op(node), self.graph.operands(node)) {
+            return format!("{} {} {}", operand(a), op.symbol(), operand(b));
+        }
+        if let Some([a, b, c]) = self.graph.mul_add(node) {
+            return format!("{} * {} + {}", operand(a), operand(b), operand(c));
+        }
+        if let Some(operands) = self.graph.custom(node) {
+            let operands: Vec<String> = operands
+                .into_iter()
+                .map(|operand| self.expression_at(operand, depth + 1))
+                .collect();
+            return format!("{}({})", self.custom_calls[&node], operands.join(", "));
+        }
+        if let Some(linked) = self.graph.linked(node) {
+            let linked = self.expression_at(linked, depth + 1);
+            return match self.hint_calls.get(&node) {
+                Some(function) => format!("hint({}, {})", function, linked),
+                None => format!("hint({})", linked),
+            };
+        }
+        match self.graph.kind(node) {
+            Kind::Constant => self.graph.output(node).unwrap().to_string(),
+            _ => self.name(node),
+        }
+    }
+}
@@ -0,0 +1,322 @@
+//! Differential testing of the evaluators against each other.
+//!
+//! `Builder::compare_backends` evaluates a graph with every backend and compares each witness
+//! with the one computed by the interpreter (`fill_inputs_with`). A backend diverges when it
+//! computes a different value for a node, or when exactly one of the two fails, by returning an
+//! error or by panicking. Backends that can't evaluate a graph, such as the memory-mapped graph
+//! for graphs with hint functions or checks, or the GPU evaluator on machines without a GPU
+//! adapter, are skipped.
+//!
+//! The same comparison is available from the command line as `circuit compare`.
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::error::Value;
+use crate::eval::EvalOptions;
+use crate::stream::read_witness;
+use crate::{Builder, NodeId};
+
+/// An evaluator of graphs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// `fill_inputs_with`, the reference the others are compared with.
+    Interpreter,
+    /// `Stepper`, which fills in nodes level by level.
+    Stepper,
+    /// `stream_witness_to`, which writes the witness out instead of storing it in the graph.
+    Streaming,
+    /// `fill_inputs_with` with `free_dead_values`. Only the values it keeps are compared.
+    FreeDeadValues,
+    /// `mmap::MmapGraph`, built with the `mmap` feature.
+    #[cfg(feature = "mmap")]
+    Mmap,
+    /// `gpu::GpuEvaluator`, built with the `gpu` feature. Skipped on machines without a GPU
+    /// adapter.
+    #[cfg(feature = "gpu")]
+    Gpu,
+}
+
+impl Backend {
+    /// Every backend, starting with the interpreter.
+    pub fn all() -> Vec<Backend> {
+        vec![
+            Backend::Interpreter,
+            Backend::Stepper,
+            Backend::Streaming,
+            Backend::FreeDeadValues,
+            #[cfg(feature = "mmap")]
+            Backend::Mmap,
+            #[cfg(feature = "gpu")]
+            Backend::Gpu,
+        ]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Interpreter => "interpreter",
+            Backend::Stepper => "stepper",
+            Backend::Streaming => "streaming",
+            Backend::FreeDeadValues => "free-dead-values",
+            #[cfg(feature = "mmap")]
+            Backend::Mmap => "mmap",
+            #[cfg(feature = "gpu")]
+            Backend::Gpu => "gpu",
+        }
+    }
+
+    /// Returns false if the backend can't evaluate this graph.
+    pub(crate) fn supports(self, builder: &Builder) -> bool {
+        #[cfg(feature = "mmap")]
+        if self == Backend::Mmap {
+            return builder.hint_calls.is_empty() && builder.checks.is_empty();
+        }
+        #[cfg(feature = "gpu")]
+        if self == Backend::Gpu {
+            return gpu_evaluator().is_some();
+        }
+        let _ = builder;
+        true
+    }
+
+    /// Evaluates a graph, returning the value of every node, or None if the backend can't
+    /// evaluate this graph. Panics are caught and returned as errors.
+    fn run(
+        self,
+        builder: &Builder,
+        inputs: &[(usize, u32)],
+    ) -> Option<Result<Vec<Option<u32>>, String>> {
+        let mut builder = builder.clone();
+        builder.reset();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.evaluate(&mut builder, inputs)));
+        result.unwrap_or_else(|panic| Some(Err(panic_message(panic))))
+    }
+
+    /// Evaluates a graph that has not been filled in yet, as `run` does but without catching
+    /// panics.
+    pub(crate) fn evaluate(
+        self,
+        builder: &mut Builder,
+        inputs: &[(usize, u32)],
+    ) -> Option<Result<Vec<Option<u32>>, String>> {
+        let ids: Vec<(NodeId, u32)> = inputs
+            .iter()
+            .map(|&(index, value)| (builder.id(index), value))
+            .collect();
+        if !self.supports(builder) {
+            return None;
+        }
+        Some(match self {
+            Backend::Interpreter => builder
+                .fill_inputs_with(&ids, EvalOptions::new())
+                .map(|()| builder.witness().values.clone())
+                .map_err(|err| err.to_string()),
+            Backend::Stepper => {
+                let result = builder.stepper(&ids).try_run();
+                result
+                    .map(|_| builder.witness().values.clone())
+                    .map_err(|err| err.to_string())
+            }
+            Backend::Streaming => {
+                let mut file = Vec::new();
+                builder
+                    .stream_witness_to(&ids, &mut file)
+                    .map_err(|err| err.to_string())
+                    .and_then(|_| read_witness(file.as_slice()).map_err(|err| err.to_string()))
+            }
+            Backend::FreeDeadValues => builder
+                .fill_inputs_with(&ids, EvalOptions::new().free_dead_values())
+                .map(|()| builder.witness().values.clone())
+                .map_err(|err| err.to_string()),
+            #[cfg(feature = "mmap")]
+            Backend::Mmap => run_mmap(builder, inputs).map_err(|err| err.to_string()),
+            #[cfg(feature = "gpu")]
+            Backend::Gpu => gpu_evaluator()
+                .unwrap()
+                .evaluate(builder, &[ids])
+                .map(|witnesses| witnesses[0].values.clone())
+                .map_err(|err| err.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A disagreement between a backend and the interpreter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// Exactly one of the backend and the interpreter failed.
+    Outcome {
+        backend: Backend,
+        expected: Result<(), String>,
+        actual: Result<(), String>,
+    },
+    /// The backend computed a different value for a node. Only the first such node is reported.
+    Value {
+        backend: Backend,
+        node: usize,
+        expected: Option<Value>,
+        actual: Option<Value>,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outcome = |result: &Result<(), String>| match result {
+            Ok(()) => "succeeded".to_string(),
+            Err(message) => format!("failed ({})", message),
+        };
+        let value = |value: &Option<Value>| match value {
+            Some(value) => value.to_string(),
+            None => "no value".to_string(),
+        };
+        match self {
+            Divergence::Outcome {
+                backend,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} {} but the interpreter {}",
+                backend,
+                outcome(actual),
+                outcome(expected)
+            ),
+            Divergence::Value {
+                backend,
+                node,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} computed {} for node {} but the interpreter computed {}",
+                backend,
+                value(actual),
+                node,
+                value(expected)
+            ),
+        }
+    }
+}
+
+/// The result of running every backend on a graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DifferentialReport {
+    /// The backends that evaluated the graph.
+    pub compared: Vec<Backend>,
+    /// The backends that can't evaluate the graph.
+    pub skipped: Vec<Backend>,
+    pub divergences: Vec<Divergence>,
+}
+
+impl DifferentialReport {
+    /// Returns true if every backend agreed with the interpreter.
+    pub fn is_consistent(&self) -> bool {
+        self.divergences.is_empty()
+    }
+}
+
+impl Builder {
+    /// Evaluates the graph with every backend for the given inputs and reports where they
+    /// disagree with the interpreter. The builder itself is left untouched.
+    pub fn compare_backends(&self, inputs: &[(NodeId, u32)]) -> DifferentialReport {
+        let inputs: Vec<(usize, u32)> = inputs
+            .iter()
+            .map(|&(id, value)| (self.index(id), value))
+            .collect();
+        let reference = Backend::Interpreter.run(self, &inputs).unwrap();
+        let mut report = DifferentialReport {
+            compared: vec![Backend::Interpreter],
+            skipped: Vec::new(),
+            divergences: Vec::new(),
+        };
+        for backend in Backend::all().into_iter().skip(1) {
+            let Some(result) = backend.run(self, &inputs) else {
+                report.skipped.push(backend);
+                continue;
+            };
+            report.compared.push(backend);
+            let divergence = match (&reference, &result) {
+                (Ok(expected), Ok(actual)) => self.first_difference(backend, expected, actual),
+                (Err(_), Err(_)) => None,
+                _ => Some(Divergence::Outcome {
+                    backend,
+                    expected: reference.as_ref().map(|_| ()).map_err(Clone::clone),
+                    actual: result.as_ref().map(|_| ()).map_err(Clone::clone),
+                }),
+            };
+            report.divergences.extend(divergence);
+        }
+        report
+    }
+
+    fn first_difference(
+        &self,
+        backend: Backend,
+        expected: &[Option<u32>],
+        actual: &[Option<u32>],
+    ) -> Option<Divergence> {
+        let node = (0..expected.len().max(actual.len())).find(|&node| {
+            let (expected, actual) = (expected.get(node).copied(), actual.get(node).copied());
+            // Freed values are not compared.
+            let freed = backend == Backend::FreeDeadValues && actual == Some(None);
+            !freed && expected != actual
+        })?;
+        let value = |values: &[Option<u32>]| {
+            values
+                .get(node)
+                .copied()
+                .flatten()
+                .map(|value| self.redact(node, value))
+        };
+        Some(Divergence::Value {
+            backend,
+            node,
+            expected: value(expected),
+            actual: value(actual),
+        })
+    }
+}
+
+/// The GPU evaluator shared by every comparison, opened on first use, or None if no GPU adapter
+/// is available.
+#[cfg(feature = "gpu")]
+fn gpu_evaluator() -> Option<&'static crate::gpu::GpuEvaluator> {
+    static EVALUATOR: std::sync::OnceLock<Option<crate::gpu::GpuEvaluator>> =
+        std::sync::OnceLock::new();
+    EVALUATOR
+        .get_or_init(|| crate::gpu::GpuEvaluator::new().ok())
+        .as_ref()
+}
+
+#[cfg(feature = "mmap")]
+fn run_mmap(builder: &Builder, inputs: &[(usize, u32)]) -> std::io::Result<Vec<Option<u32>>> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "circuit-compare-{}-{}.map",
+        std::process::id(),
+        NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = (|| {
+        let mut graph = crate::mmap::MmapGraph::from_builder(builder, &path)?;
+        graph.fill_inputs(inputs);
+        Ok((0..builder.len()).map(|id| graph.output(id)).collect())
+    })();
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => format!("panicked: {}", message),
+        Err(panic) => match panic.downcast::<&str>() {
+            Ok(message) => format!("panicked: {}", message),
+            Err(_) => "panicked".to_string(),
+        },
+    }
+}
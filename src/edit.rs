@@ -0,0 +1,157 @@
+//! Editing built graphs, for external optimization tools and interactive editors.
+//!
+//! `replace_node(old, new)` makes everything that used `old` use `new` instead: the nodes reading
+//! it as an operand or linked to it as a hint, equality constraints, checks, aliases and its place
+//! among the public outputs. `old` stays in the graph, unreferenced, and `remove_unreferenced(old)`
+//! then removes it:
+//!
+//! ```
+//! use my_graph_lib::Builder;
+//!
+//! let mut b = Builder::new();
+//! let x = b.init();
+//! let slow = b.mul(x, x);
+//! let fast = b.square(x);
+//! let y = b.add(slow, x);
+//! b.replace_node(slow, fast);
+//! assert_eq!(b.consumers(fast).collect::<Vec<_>>(), vec![y]);
+//! let ids = b.remove_unreferenced(slow);
+//! assert_eq!(b.len(), 3);
+//! b.fill_nodes(x, 3);
+//! assert_eq!(b.output(ids[&y]), Some(12));
+//! ```
+//!
+//! Nodes only read nodes added before them, so a node can only replace `old` if it comes before
+//! every node reading `old`. Values already filled in are not recomputed: fill the graph in again
+//! after editing it.
+//!
+//! Removing a node moves the nodes after it down by one index, so their ids become stale, as after
+//! a rollback, and `remove_unreferenced` returns their new ids.
+
+use std::collections::BTreeMap;
+
+use crate::error::BuildError;
+use crate::{Builder, NodeId};
+
+impl Builder {
+    /// Makes every node, constraint, check and alias using `old` use `new` instead, and `new` a
+    /// public output in place of `old`, see the module documentation. Panics if `new` comes after
+    /// a node reading `old`.
+    pub fn replace_node(&mut self, old: NodeId, new: NodeId) {
+        self.try_replace_node(old, new)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `replace_node`, but returns an error and changes nothing if an id does not belong to
+    /// this builder or `new` comes after a node reading `old`.
+    pub fn try_replace_node(&mut self, old: NodeId, new: NodeId) -> Result<(), BuildError> {
+        let (old, new) = (self.resolve(old)?, self.resolve(new)?);
+        if old == new {
+            return Ok(());
+        }
+        let consumers: Vec<usize> = (old + 1..self.graph.len())
+            .filter(|&id| self.sources(id).any(|source| source == old))
+            .collect();
+        if let Some(&node) = consumers.iter().find(|&&node| node <= new) {
+            return Err(BuildError::ForwardReference { node, operand: new });
+        }
+        for &node in &consumers {
+            self.graph.replace_read(node, old, new);
+        }
+        // Nodes computed from a secret are secret too.
+        if let Some(&first) = consumers.first() {
+            for id in first..self.graph.len() {
+                if self.sources(id).any(|source| self.graph.is_secret(source)) {
+                    self.graph.mark_secret(id);
+                }
+            }
+        }
+
+        let replace = |node: usize| if node == old { new } else { node };
+        for (a, b) in &mut self.constraints {
+            (*a, *b) = (replace(*a), replace(*b));
+        }
+        for check in &mut self.checks {
+            *check = check.map(replace);
+        }
+        let aliases: Vec<(usize, usize)> = self.alias_pairs().collect();
+        self.aliases.clear();
+        for (a, b) in aliases {
+            self.union(replace(a), replace(b));
+        }
+        if self.public.remove(&old) {
+            self.public.insert(new);
+        }
+        if self.limits.max_depth.is_some() {
+            self.set_limits(self.limits);
+        }
+        Ok(())
+    }
+
+    /// Removes `id` from the graph, along with its label and other properties, and returns the
+    /// new id of every node after it, see the module documentation. Panics if a node,
+    /// constraint, check or alias uses `id`, or it is a public output.
+    pub fn remove_unreferenced(&mut self, id: NodeId) -> BTreeMap<NodeId, NodeId> {
+        self.try_remove_unreferenced(id)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `remove_unreferenced`, but returns an error and changes nothing if the id does not
+    /// belong to this builder or the node is still used.
+    pub fn try_remove_unreferenced(
+        &mut self,
+        id: NodeId,
+    ) -> Result<BTreeMap<NodeId, NodeId>, BuildError> {
+        let node = self.resolve(id)?;
+        if let Some(by) = self.user(node) {
+            return Err(BuildError::InUse { node, by });
+        }
+        let kept: Vec<bool> = (0..self.graph.len()).map(|id| id != node).collect();
+        let slice = self.extract(&kept);
+        let ids = slice
+            .ids
+            .into_iter()
+            .filter(|(old, _)| old.index > node)
+            .collect::<Vec<_>>();
+
+        // Ids of the nodes before `node` stay valid, as after a rollback to it.
+        let mut builder = slice.builder;
+        for (_, kept) in &mut self.ancestors {
+            *kept = (*kept).min(node);
+        }
+        builder.ancestors = std::mem::take(&mut self.ancestors);
+        builder.ancestors.push((self.generation, node));
+        builder.metrics = self.metrics.take();
+        builder.observers = std::mem::take(&mut self.observers);
+        builder.set_limits(self.limits);
+        *self = builder;
+        Ok(ids
+            .into_iter()
+            .map(|(old, new)| (old, self.id(new.index)))
+            .collect())
+    }
+
+    /// Describes something using the node at `node`, if anything does.
+    fn user(&self, node: usize) -> Option<String> {
+        if let Some(consumer) =
+            (node + 1..self.graph.len()).find(|&id| self.sources(id).any(|source| source == node))
+        {
+            return Some(format!("node {}", consumer));
+        }
+        if let Some(constraint) =
+            (self.constraints.iter()).position(|&(a, b)| a == node || b == node)
+        {
+            return Some(format!("constraint {}", constraint));
+        }
+        if let Some(check) = (self.checks.iter()).position(|check| check.nodes().contains(&node)) {
+            return Some(format!("check {}", check));
+        }
+        if self.root(node) != node || self.aliases.values().any(|&parent| parent == node) {
+            return Some("an alias".to_string());
+        }
+        if self.public.contains(&node) {
+            return Some("the public outputs".to_string());
+        }
+        None
+    }
+}
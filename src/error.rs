@@ -0,0 +1,433 @@
+//! Errors returned by the library.
+//!
+//! Each stage has its own error type: `BuildError` for node ids and hint functions that can't be
+//! used, `EvalError` for filling in a graph, `ConstraintError` for a constraint that does not hold,
+//! `SerdeError` for loading a graph and `ValidationError` for an optimization pass that changed
+//! what a graph computes. `Error` wraps all of them for applications that handle errors in one
+//! place.
+//!
+//! The panicking methods of `Builder` (e.g. `add`, `fill_inputs`, `check_constraints`) panic with
+//! the message of the corresponding error.
+//!
+//! Node values quoted by errors are `Value`s, which print as `<redacted>` when the node depends on
+//! a private input (see `Builder::private_input`).
+
+use std::fmt;
+use std::io;
+
+use thiserror::Error;
+
+use crate::limits::{EvalLimit, Limit};
+use crate::NodeId;
+
+/// Any error returned by the library.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Build(#[from] BuildError),
+    #[error(transparent)]
+    Eval(#[from] EvalError),
+    #[error(transparent)]
+    Constraint(#[from] ConstraintError),
+    #[error(transparent)]
+    Serde(#[from] SerdeError),
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+}
+
+/// A node or hint function that can't be used to build or fill in the graph.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BuildError {
+    #[error("node id {node} is stale: it was created by another builder, before this one was cleared, or rolled back or moved by a removal")]
+    StaleNode { node: usize },
+    #[error("node {node} does not exist")]
+    MissingNode { node: usize },
+    #[error("node {node} is not an input")]
+    NotAnInput { node: usize },
+    #[error("no hint function named `{name}` is registered")]
+    UnknownHint { name: String },
+    #[error("no operation named `{name}` is registered")]
+    UnknownOp { name: String },
+    #[error("operation `{name}` takes {expected} operands, got {found}")]
+    WrongArity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// An export can't constrain custom nodes, which must be lowered first.
+    #[error("node {node} computes the custom operation `{op}`, which can't be exported: lower it with `lower_custom_ops` first")]
+    Unlowered { node: usize, op: String },
+    #[error("the graph would exceed its {limit} limit of {max}")]
+    LimitExceeded { limit: Limit, max: usize },
+    #[error("the savepoint is stale: it was taken on another builder, or its nodes were already rolled back")]
+    StaleSavepoint,
+    #[error("node {node} would read node {operand}, which comes after it")]
+    ForwardReference { node: usize, operand: usize },
+    #[error("node {node} is still used by {by}")]
+    InUse { node: usize, by: String },
+}
+
+/// An error that stopped the evaluation of a graph, or a constraint check with options.
+#[derive(Debug, Error)]
+pub enum EvalError {
+    /// Writing the trace or a checkpoint failed.
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+    /// The cancellation flag was set.
+    #[error("evaluation was cancelled")]
+    Cancelled,
+    /// A checkpoint does not belong to the graph being resumed.
+    #[error("invalid checkpoint: {0}")]
+    InvalidCheckpoint(String),
+    /// An input assignment or hint function is invalid.
+    #[error(transparent)]
+    Build(#[from] BuildError),
+    /// A node was reached before the node it depends on had a value, e.g. an input without a value.
+    #[error("node {node} depends on node {operand}, which has no value")]
+    MissingValue { node: usize, operand: usize },
+    /// The result of an addition or multiplication does not fit in a u32.
+    #[error("node {node} overflows: {a} {op} {b} does not fit in a u32")]
+    Overflow {
+        node: usize,
+        op: &'static str,
+        a: Value,
+        b: Value,
+    },
+    /// The graph or its evaluation exceeded one of the `EvalLimits` it was given.
+    #[error("evaluation exceeded its {0}")]
+    LimitExceeded(EvalLimit),
+    /// The operation of a custom node is undefined on the values of its operands.
+    #[error("node {node} fails: operation `{op}` is undefined on its operands")]
+    CustomFailed { node: usize, op: String },
+    /// Some nodes could not be filled in, as listed by `Builder::try_fill_inputs`.
+    #[error(transparent)]
+    Unfillable(#[from] FillReport),
+}
+
+/// Every node `Builder::try_fill_inputs` could not fill in, in node order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FillReport {
+    pub nodes: Vec<UnfilledNode>,
+}
+
+impl std::error::Error for FillReport {}
+
+impl fmt::Display for FillReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} node(s) could not be filled in:", self.nodes.len())?;
+        for node in &self.nodes {
+            write!(f, "\n  node {}: {}", node.node, node.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// A node left without a value, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnfilledNode {
+    pub node: usize,
+    pub reason: Unfillable,
+}
+
+/// Why a node could not be filled in.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Unfillable {
+    /// An input that was given no value.
+    #[error("the input has no value")]
+    MissingInput,
+    /// A hint computed by a function that is not registered.
+    #[error("no hint function named `{name}` is registered")]
+    UnknownHint { name: String },
+    /// A custom node computed by an operation that is not registered.
+    #[error("no operation named `{name}` is registered")]
+    UnknownOp { name: String },
+    /// A custom node whose operation is undefined on the values of its operands.
+    #[error("operation `{op}` is undefined on its operands")]
+    CustomFailed { op: String },
+    /// A node reading itself or a node added after it, which graphs built by this library never
+    /// hold.
+    #[error("it reads node {operand}, which is not computed before it")]
+    ForwardReference { operand: usize },
+    /// A node reading a node that could not be filled in.
+    #[error("it depends on node {operand}, which has no value")]
+    Unfilled { operand: usize },
+    /// The result does not fit in a u32.
+    #[error("{a} {op} {b} does not fit in a u32")]
+    Overflow {
+        op: &'static str,
+        a: Value,
+        b: Value,
+    },
+}
+
+impl From<serde_json::Error> for EvalError {
+    fn from(err: serde_json::Error) -> Self {
+        EvalError::Io(err.into())
+    }
+}
+
+/// The first constraint found not to hold.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ConstraintError {
+    /// An add or mul node holds a value other than the one computed from its inputs.
+    #[error("node {node} holds {actual}, but its inputs give {expected}")]
+    WrongOutput {
+        node: usize,
+        actual: Value,
+        expected: Value,
+    },
+    /// An equality constraint recorded with `enforce_equal` does not hold.
+    #[error(
+        "constraint {constraint} does not hold: node {a} is {a_value} but node {b} is {b_value}"
+    )]
+    NotEqual {
+        constraint: usize,
+        a: usize,
+        b: usize,
+        a_value: Value,
+        b_value: Value,
+    },
+    /// Nodes declared the same wire with `alias` hold different values. `a` is the
+    /// representative of their class.
+    #[error("nodes {a} and {b} are aliased, but node {a} is {a_value} and node {b} is {b_value}")]
+    NotAliased {
+        a: usize,
+        b: usize,
+        a_value: Value,
+        b_value: Value,
+    },
+    /// An ordering recorded with `assert_lt` does not hold.
+    #[error(
+        "check {check} does not hold: node {a} is {a_value} and node {b} is {b_value}, but {a} < {b} < 2^{bits} is required"
+    )]
+    NotLess {
+        check: usize,
+        a: usize,
+        b: usize,
+        a_value: Value,
+        b_value: Value,
+        bits: u32,
+    },
+    /// A value restricted with `assert_in_set` is not in its set.
+    #[error(
+        "check {check} does not hold: node {node} is {value}, which is not in the allowed set"
+    )]
+    NotInSet {
+        check: usize,
+        node: usize,
+        value: Value,
+    },
+    /// A value restricted with `assert_range` does not fit in its bits.
+    #[error(
+        "check {check} does not hold: node {node} is {value}, which does not fit in {bits} bits"
+    )]
+    OutOfRange {
+        check: usize,
+        node: usize,
+        value: Value,
+        bits: u32,
+    },
+    /// The vectors of an `assert_permutation` hold different values. `node` holds a value that
+    /// appears more often in its vector than in the other one.
+    #[error("check {check} does not hold: node {node} is {value}, which appears more often in its vector than in the other one")]
+    NotPermutation {
+        check: usize,
+        node: usize,
+        value: Value,
+    },
+    /// The inputs of an add or mul node give a result that does not fit in a u32.
+    #[error("node {node} overflows: its inputs give a result that does not fit in a u32")]
+    Overflow { node: usize },
+    /// A node needed by a constraint has not been filled in.
+    #[error("node {node} has not been filled in")]
+    Unfilled { node: usize },
+    /// The operation of a custom node rejects its value, or is not registered so the value can't
+    /// be checked.
+    #[error("node {node} holds {value}, which {}", rejected(op, *.registered))]
+    CustomRejected {
+        node: usize,
+        op: String,
+        value: Value,
+        registered: bool,
+    },
+    /// The graph can't be checked, e.g. a custom node has another number of operands than its
+    /// registered operation takes.
+    #[error(transparent)]
+    Build(#[from] BuildError),
+}
+
+/// The value of a node, as quoted by an error or report. Values of nodes that depend on a private
+/// input print as `<redacted>` unless the builder was told to reveal them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Value {
+    value: u32,
+    redacted: bool,
+}
+
+impl Value {
+    pub(crate) fn new(value: u32, redacted: bool) -> Self {
+        Value { value, redacted }
+    }
+
+    /// The value itself, even if it is redacted when printed.
+    pub fn get(self) -> u32 {
+        self.value
+    }
+
+    /// Returns true if the value prints as `<redacted>`.
+    pub fn is_redacted(self) -> bool {
+        self.redacted
+    }
+}
+
+impl PartialEq<u32> for Value {
+    fn eq(&self, other: &u32) -> bool {
+        self.value == *other
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.redacted {
+            write!(f, "<redacted>")
+        } else {
+            write!(f, "{}", self.value)
+        }
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// A graph that could not be loaded.
+#[derive(Debug, Error)]
+pub enum SerdeError {
+    #[error("invalid graph JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    /// The graph was written by a newer version of the library, in a format this one can't read.
+    #[error("graph JSON has format version {found}, this version of the library reads versions 1 to {supported}")]
+    UnsupportedVersion { found: u64, supported: u32 },
+    /// An index past the end of the nodes or constraints `at` refers to.
+    #[error("{at} refers to index {index}, but there are only {len}")]
+    IndexOutOfRange {
+        at: String,
+        index: usize,
+        len: usize,
+    },
+    /// A length, such as a bit width, that can't be right.
+    #[error("{at} has length {found}, expected {expected}")]
+    BadLength {
+        at: String,
+        found: u64,
+        expected: String,
+    },
+    /// A node whose operands or hint link lead back to it, so that it can never be computed.
+    #[error("node {node} depends on itself")]
+    Cycle { node: usize },
+    /// A node that is neither an input nor computed, and has no value.
+    #[error("constant {node} has no value")]
+    MissingConstant { node: usize },
+    #[cfg(feature = "protobuf")]
+    #[error("invalid graph protobuf: {0}")]
+    Protobuf(#[from] prost::DecodeError),
+    /// A decoded protobuf message that doesn't describe a valid graph or witness.
+    #[cfg(feature = "protobuf")]
+    #[error("invalid graph protobuf: {0}")]
+    InvalidProtobuf(String),
+    #[cfg(feature = "flatbuffers")]
+    #[error("invalid graph FlatBuffer: {0}")]
+    Flatbuffer(#[from] flatbuffers::InvalidFlatbuffer),
+    /// A FlatBuffer that is well formed but doesn't describe a valid graph.
+    #[cfg(feature = "flatbuffers")]
+    #[error("invalid graph FlatBuffer: {0}")]
+    InvalidFlatbuffer(String),
+    #[cfg(feature = "zstd")]
+    #[error("invalid zstd frame: {0}")]
+    Zstd(io::Error),
+}
+
+impl SerdeError {
+    /// A short code naming the kind of error, which stays the same across versions, e.g. for a
+    /// service to return alongside the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SerdeError::Json(_) => "invalid_json",
+            SerdeError::UnsupportedVersion { .. } => "unsupported_version",
+            SerdeError::IndexOutOfRange { .. } => "index_out_of_range",
+            SerdeError::BadLength { .. } => "bad_length",
+            SerdeError::Cycle { .. } => "cycle",
+            SerdeError::MissingConstant { .. } => "missing_constant",
+            #[cfg(feature = "protobuf")]
+            SerdeError::Protobuf(_) | SerdeError::InvalidProtobuf(_) => "invalid_protobuf",
+            #[cfg(feature = "flatbuffers")]
+            SerdeError::Flatbuffer(_) | SerdeError::InvalidFlatbuffer(_) => "invalid_flatbuffer",
+            #[cfg(feature = "zstd")]
+            SerdeError::Zstd(_) => "invalid_zstd",
+        }
+    }
+}
+
+/// An optimization pass whose result computes something else than the original graph on some
+/// inputs, found by `PassManager::validate`. `inputs` assigns the inputs of the original graph.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ValidationError {
+    /// A public output of the original graph, `output`, has another value after the pass.
+    #[error("pass `{pass}` changed public output {output} from {expected} to {found}")]
+    OutputChanged {
+        pass: String,
+        output: usize,
+        expected: Value,
+        found: Value,
+        inputs: Vec<(NodeId, u32)>,
+    },
+    /// The constraints hold on one graph and not on the other.
+    #[error("pass `{pass}` changed whether the constraints hold: they {} before the pass and {} after it", held(*.before), held(!*.before))]
+    ConstraintsChanged {
+        pass: String,
+        before: bool,
+        inputs: Vec<(NodeId, u32)>,
+    },
+    /// The graph produced by the pass can't be evaluated on inputs the original graph accepts.
+    #[error("pass `{pass}` made evaluation fail: {message}")]
+    EvalFailed {
+        pass: String,
+        message: String,
+        inputs: Vec<(NodeId, u32)>,
+    },
+}
+
+/// A partial witness that `Builder::solve` can't complete.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SolveError {
+    #[error("witness has {found} values but the graph has {expected} nodes")]
+    WrongLength { expected: usize, found: usize },
+    /// The known values around `node` contradict each other: the values of a node and its operands,
+    /// or of the two sides of an equality constraint.
+    #[error("node {node} has no consistent value: {reason}")]
+    Conflict { node: usize, reason: String },
+    /// A custom node has another number of operands than its registered operation takes.
+    #[error(transparent)]
+    Build(#[from] BuildError),
+}
+
+fn rejected(op: &str, registered: bool) -> String {
+    if registered {
+        format!("operation `{}` rejects", op)
+    } else {
+        format!(
+            "can't be checked: no operation named `{}` is registered",
+            op
+        )
+    }
+}
+
+fn held(holds: bool) -> &'static str {
+    if holds {
+        "hold"
+    } else {
+        "fail"
+    }
+}
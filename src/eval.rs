@@ -0,0 +1,378 @@
+//! Evaluation with extra options, such as recording a trace of every step.
+
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+pub use crate::error::EvalError;
+use crate::error::{ConstraintError, Error};
+use crate::limits::{EvalLimit, EvalLimits};
+use crate::profile::{self, Profile};
+use crate::storage::Kind;
+use crate::{Builder, NodeId, OPERATION};
+
+/// Callback receiving (nodes done, total nodes).
+type ProgressCallback<'a> = Box<dyn FnMut(usize, usize) + 'a>;
+
+/// Options for `Builder::fill_inputs_with`, `Builder::check_with`, `Builder::check_constraints_with`
+/// and `Builder::is_satisfied_with`.
+/// With no options set they behave like `fill_inputs` and `check_constraints`.
+pub struct EvalOptions<'a> {
+    trace: Option<&'a mut dyn Write>,
+    progress: Option<ProgressCallback<'a>>,
+    progress_interval: usize,
+    cancel: Option<&'a AtomicBool>,
+    checkpoint: Option<(&'a Path, usize)>,
+    free_dead_values: bool,
+    profile: Option<&'a mut Profile>,
+    limits: EvalLimits,
+    deadline: Option<Instant>,
+    steps: u64,
+}
+
+impl Default for EvalOptions<'_> {
+    fn default() -> Self {
+        EvalOptions {
+            trace: None,
+            progress: None,
+            progress_interval: 4096,
+            cancel: None,
+            checkpoint: None,
+            free_dead_values: false,
+            profile: None,
+            limits: EvalLimits::default(),
+            deadline: None,
+            steps: 0,
+        }
+    }
+}
+
+impl<'a> EvalOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes a `TraceEvent` as one JSON line for every node computed during evaluation.
+    /// Only used when filling in a graph.
+    pub fn trace(mut self, sink: &'a mut dyn Write) -> Self {
+        self.trace = Some(sink);
+        self
+    }
+
+    /// Calls `callback(done, total)` every `progress_interval` nodes (4096 by default) and once at the end.
+    pub fn on_progress(mut self, callback: impl FnMut(usize, usize) + 'a) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets how many nodes are processed between progress callbacks.
+    pub fn progress_interval(mut self, nodes: usize) -> Self {
+        self.progress_interval = nodes.max(1);
+        self
+    }
+
+    /// Stops the evaluation or check with `EvalError::Cancelled` once `flag` is set,
+    /// e.g. from another thread when a request times out. The flag is polled before every node.
+    pub fn cancel_on(mut self, flag: &'a AtomicBool) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    /// Saves a `Checkpoint` to `path` every `every` nodes, and when the evaluation is cancelled.
+    /// Only used when filling in a graph.
+    pub fn checkpoint(mut self, path: &'a Path, every: usize) -> Self {
+        self.checkpoint = Some((path, every.max(1)));
+        self
+    }
+
+    /// Clears the output of every computed node once the last node reading it has been filled in.
+    /// Public outputs (see `Builder::mark_public`) and operands of equality constraints are kept,
+    /// as are inputs, constants and fixed hints. Checkpoints then only hold the values still needed.
+    /// Only used when filling in a graph, which can then no longer be checked with `check_constraints`.
+    pub fn free_dead_values(mut self) -> Self {
+        self.free_dead_values = true;
+        self
+    }
+
+    /// Adds the time spent on every node computed to `profile`, see the `profile` module.
+    /// Only used when filling in a graph.
+    pub fn profile(mut self, profile: &'a mut Profile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Stops the evaluation or check with `EvalError::LimitExceeded` when the graph or its
+    /// evaluation exceeds `limits`, see the `limits` module.
+    pub fn limits(mut self, limits: EvalLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Checks the limits on the graph and starts the clock, before the first node.
+    fn start(&mut self, builder: &Builder) -> Result<(), EvalError> {
+        self.limits.check_graph(builder)?;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        self.poll_cancel()
+    }
+
+    /// Counts a computed node against the step limit.
+    fn step(&mut self) -> Result<(), EvalError> {
+        self.steps += 1;
+        match self.limits.max_steps {
+            Some(max) if self.steps > max => Err(EvalError::LimitExceeded(EvalLimit::Steps(max))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Called after each processed node: reports progress and polls the cancellation flag and
+    /// the deadline.
+    fn report(&mut self, done: usize, total: usize) -> Result<(), EvalError> {
+        if let Some(callback) = &mut self.progress {
+            if done.is_multiple_of(self.progress_interval) || done == total {
+                callback(done, total);
+            }
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.limits.timeout) {
+            if done.is_multiple_of(1024) && Instant::now() > deadline {
+                return Err(EvalError::LimitExceeded(EvalLimit::Timeout(timeout)));
+            }
+        }
+        self.poll_cancel()
+    }
+
+    fn poll_cancel(&self) -> Result<(), EvalError> {
+        match self.cancel {
+            Some(flag) if flag.load(Ordering::Relaxed) => Err(EvalError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// One evaluation step of a trace.
+///
+/// Steps computing a secret node are `redacted`: their operands are left out and their result is 0,
+/// unless the builder reveals secrets.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceEvent {
+    pub node: usize,
+    pub op: String, // "ADD", "MUL", "MUL_ADD", "HINT" or "CUSTOM"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>, // Name of the hint function or operation for "HINT" and "CUSTOM" steps.
+    pub operands: Vec<u32>,
+    pub result: u32,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub redacted: bool,
+    pub timestamp_us: u128, // Microseconds since the Unix epoch.
+}
+
+/// Reads back a trace written during evaluation.
+pub fn read_trace(reader: impl BufRead) -> io::Result<Vec<TraceEvent>> {
+    let mut events = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            events.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(events)
+}
+
+impl Builder {
+    /// Fills in all the nodes of the graph like `fill_inputs`, applying the given options.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(nodes = self.graph.len(), inputs = inputs.len()))
+    )]
+    pub fn fill_inputs_with(
+        &mut self,
+        inputs: &[(NodeId, u32)],
+        options: EvalOptions,
+    ) -> Result<(), EvalError> {
+        self.try_set_inputs(inputs)?;
+        self.fill_from(0, options)
+    }
+
+    /// Fills in every node from `first` onwards, assuming the nodes before it are already filled in.
+    pub(crate) fn fill_from(
+        &mut self,
+        first: usize,
+        mut options: EvalOptions,
+    ) -> Result<(), EvalError> {
+        let start = self.start_timer();
+        let total = self.graph.len();
+        options.start(self)?;
+        let last_uses = options.free_dead_values.then(|| self.last_uses());
+        let context = options
+            .profile
+            .is_some()
+            .then(|| profile::Context::new(self));
+        for node in first..total {
+            if options.limits.max_steps.is_some() {
+                let computed = match self.graph.kind(node) {
+                    Kind::Input | Kind::Constant => false,
+                    Kind::Hint => self.hint_calls.contains_key(&node),
+                    Kind::Add | Kind::Mul | Kind::MulAdd | Kind::Custom => true,
+                };
+                if computed {
+                    options.step()?;
+                }
+            }
+            let started = context.as_ref().map(|_| Instant::now());
+            self.try_fill_index(node)?;
+            if let (Some(context), Some(profile), Some(started)) =
+                (&context, options.profile.as_mut(), started)
+            {
+                context.record(profile, node, started.elapsed());
+            }
+            if let Some(sink) = options.trace.as_mut() {
+                if let Some(event) = self.trace_event(node) {
+                    serde_json::to_writer(&mut *sink, &event)?;
+                    sink.write_all(b"\n")?;
+                }
+            }
+            if let Some(last_uses) = &last_uses {
+                for read in self.reads(node).into_iter().flatten() {
+                    if last_uses[read] == Some(node) {
+                        self.graph.set_output(read, None);
+                    }
+                }
+            }
+            let reported = options.report(node + 1, total);
+            if let Some((path, every)) = options.checkpoint {
+                if reported.is_err() || (node + 1).is_multiple_of(every) {
+                    self.checkpoint(node + 1).save(path)?;
+                }
+            }
+            reported?;
+        }
+        if let Some(sink) = options.trace.as_mut() {
+            sink.flush()?;
+        }
+        self.record_evaluation(start);
+        Ok(())
+    }
+
+    /// Checks the constraints like `check`, applying the given options.
+    /// Progress counts every node followed by every equality constraint.
+    pub fn check_with(&self, options: EvalOptions) -> Result<(), Error> {
+        match self.first_violation_with(options)? {
+            Some(violation) => Err(violation.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the constraints like `check_constraints`, applying the given options.
+    pub fn check_constraints_with(&self, options: EvalOptions) -> Result<bool, EvalError> {
+        if let Some(violation) = self.first_violation_with(options)? {
+            panic!("{}", self.explain(&violation));
+        }
+        self.report_warnings();
+        Ok(true)
+    }
+
+    /// Like `check_constraints_with`, but returns false instead of panicking when a constraint does not hold.
+    pub fn is_satisfied_with(&self, options: EvalOptions) -> Result<bool, EvalError> {
+        Ok(self.first_violation_with(options)?.is_none())
+    }
+
+    fn first_violation_with(
+        &self,
+        mut options: EvalOptions,
+    ) -> Result<Option<ConstraintError>, EvalError> {
+        let total = self.graph.len() + self.constraints.len() + self.checks.len();
+        options.start(self)?;
+        self.first_violation(&mut |done| options.report(done, total))
+    }
+
+    /// The nodes whose values are read to compute `id`.
+    pub(crate) fn reads(&self, id: usize) -> [Option<usize>; 3] {
+        if let Some([a, b, c]) = self.graph.mul_add(id) {
+            return [Some(a), Some(b), Some(c)];
+        }
+        if let Some(operands) = self.graph.custom(id) {
+            return std::array::from_fn(|i| operands.get(i).copied());
+        }
+        match self.graph.operands(id) {
+            Some((a, b)) => [Some(a), Some(b), None],
+            None if self.hint_calls.contains_key(&id) => [self.graph.linked(id), None, None],
+            None => [None, None, None],
+        }
+    }
+
+    /// For every computed node, the last node that reads its value. Nodes whose value has to be
+    /// kept after evaluation (public outputs, constraint operands and nodes that are not computed)
+    /// get `None`.
+    fn last_uses(&self) -> Vec<Option<usize>> {
+        let mut last_uses = vec![None; self.graph.len()];
+        for id in 0..self.graph.len() {
+            for read in self.reads(id).into_iter().flatten() {
+                last_uses[read] = Some(id);
+            }
+        }
+        for (id, last_use) in last_uses.iter_mut().enumerate() {
+            let computed = self.reads(id)[0].is_some();
+            if !computed || self.public.contains(&id) {
+                *last_use = None;
+            }
+        }
+        for &(a, b) in &self.constraints {
+            last_uses[a] = None;
+            last_uses[b] = None;
+        }
+        for node in self.checks.iter().flat_map(|check| check.nodes()) {
+            last_uses[node] = None;
+        }
+        last_uses
+    }
+
+    /// Describes how a computed node got its value. Inputs and constants are not computed.
+    fn trace_event(&self, id: usize) -> Option<TraceEvent> {
+        let (op, hint, operands) = match (self.graph.op(id), self.graph.operands(id)) {
+            _ if self.graph.kind(id) == Kind::MulAdd => {
+                let operands = self
+                    .graph
+                    .mul_add(id)?
+                    .map(|operand| self.graph.output(operand));
+                let operands: Option<Vec<u32>> = operands.into_iter().collect();
+                ("MUL_ADD", None, operands?)
+            }
+            _ if self.graph.kind(id) == Kind::Custom => {
+                let operands = self.graph.custom(id)?;
+                let operands: Option<Vec<u32>> = operands
+                    .into_iter()
+                    .map(|operand| self.graph.output(operand))
+                    .collect();
+                ("CUSTOM", self.custom_calls.get(&id).cloned(), operands?)
+            }
+            (Some(op), Some((a, b))) => {
+                let name = match op {
+                    OPERATION::ADD => "ADD",
+                    OPERATION::MUL => "MUL",
+                };
+                let operands = vec![self.graph.output(a)?, self.graph.output(b)?];
+                (name, None, operands)
+            }
+            _ => {
+                let name = self.hint_calls.get(&id)?;
+                let operand = self.graph.output(self.graph.linked(id)?)?;
+                ("HINT", Some(name.clone()), vec![operand])
+            }
+        };
+        let result = self.graph.output(id)?;
+        let redacted = !self.reveal_secrets && self.graph.is_secret(id);
+        Some(TraceEvent {
+            node: id,
+            op: op.to_string(),
+            hint,
+            operands: if redacted { Vec::new() } else { operands },
+            result: if redacted { 0 } else { result },
+            redacted,
+            timestamp_us: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_micros()),
+        })
+    }
+}
@@ -0,0 +1,477 @@
+//! Exporters for handing graphs to other tools.
+
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::checks::Check;
+use crate::error::BuildError;
+use crate::storage::Kind;
+use crate::{Builder, OPERATION};
+
+/// A rank-1 constraint system: every constraint states `<a, w> * <b, w> = <c, w>`.
+/// Variable 0 of the witness vector `w` is the constant one and variable `i + 1` is the output of node `i`.
+#[derive(Debug, Clone, Serialize)]
+pub struct R1cs {
+    pub num_variables: usize,
+    pub constraints: Vec<R1csConstraint>,
+}
+
+/// A single R1CS constraint. Each linear combination is a list of (variable, coefficient) pairs.
+#[derive(Debug, Clone, Serialize)]
+pub struct R1csConstraint {
+    pub a: Vec<(usize, u32)>,
+    pub b: Vec<(usize, u32)>,
+    pub c: Vec<(usize, u32)>,
+}
+
+const ONE: usize = 0;
+
+/// Renders the graph in Graphviz DOT format. Hint links are drawn as dashed edges, squarings and
+/// doublings are drawn as `^2` and `2*` with a single edge, and node metadata is shown as
+/// tooltips.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(nodes = builder.graph.len()))
+)]
+pub fn to_dot(builder: &Builder) -> String {
+    let mut out = String::from("digraph circuit {\n");
+    for node in builder.graph.iter() {
+        let kind = match node.op {
+            _ if builder.graph.squared(node.id).is_some() => "^2".to_string(),
+            _ if builder.graph.doubled(node.id).is_some() => "2*".to_string(),
+            Some(OPERATION::ADD) => "+".to_string(),
+            Some(OPERATION::MUL) if node.addend.is_some() => "*+".to_string(),
+            Some(OPERATION::MUL) => "*".to_string(),
+            None if node.hint.is_some() => "hint".to_string(),
+            None if builder.graph.kind(node.id) == Kind::Custom => {
+                builder.custom_calls[&node.id].clone()
+            }
+            None if builder.graph.kind(node.id) == Kind::Constant => {
+                node.output.unwrap().to_string()
+            }
+            None => "input".to_string(),
+        };
+        let text = match builder.labels.get(&node.id) {
+            Some(label) => format!("{}: {}", label, kind),
+            None => kind,
+        };
+        write!(
+            out,
+            "  n{} [label=\"{}\"",
+            node.id,
+            text.replace('"', "\\\"")
+        )
+        .unwrap();
+        if let Some(metadata) = builder.metadata.get(&node.id) {
+            let tooltip: Vec<_> = metadata
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect();
+            let tooltip = tooltip.join(", ").replace('"', "\\\"");
+            write!(out, ", tooltip=\"{}\"", tooltip).unwrap();
+        }
+        out.push_str("];\n");
+        if let Some(operands) = builder.graph.custom(node.id) {
+            for operand in operands {
+                writeln!(out, "  n{} -> n{};", operand, node.id).unwrap();
+            }
+            continue;
+        }
+        match node.inputs {
+            (Some(a), Some(b)) if a == b => writeln!(out, "  n{} -> n{};", a, node.id).unwrap(),
+            (Some(a), Some(b)) => {
+                writeln!(out, "  n{} -> n{};", a, node.id).unwrap();
+                writeln!(out, "  n{} -> n{};", b, node.id).unwrap();
+            }
+            _ => {}
+        }
+        if let Some(c) = node.addend {
+            writeln!(out, "  n{} -> n{} [label=\"+\"];", c, node.id).unwrap();
+        }
+        if let Some(linked) = node.hint {
+            writeln!(out, "  n{} -> n{} [style=dashed];", linked, node.id).unwrap();
+        }
+    }
+    for (i, &(a, b)) in builder.constraints.iter().enumerate() {
+        let label = if builder.soft_constraints.contains(&i) {
+            "== (warning)"
+        } else {
+            "=="
+        };
+        writeln!(
+            out,
+            "  n{} -> n{} [style=dotted, dir=none, label=\"{}\"];",
+            a, b, label
+        )
+        .unwrap();
+    }
+    for check in &builder.checks {
+        match *check {
+            Check::LessThan { a, b, .. } => {
+                writeln!(out, "  n{} -> n{} [style=dotted, label=\"<\"];", a, b).unwrap()
+            }
+            Check::InSet { node, ref set } => {
+                let set: Vec<String> = set.iter().map(u32::to_string).collect();
+                writeln!(
+                    out,
+                    "  n{} -> n{} [style=dotted, label=\"in {{{}}}\"];",
+                    node,
+                    node,
+                    set.join(", ")
+                )
+                .unwrap()
+            }
+            Check::Range { node, bits } => writeln!(
+                out,
+                "  n{} -> n{} [style=dotted, label=\"< 2^{}\"];",
+                node, node, bits
+            )
+            .unwrap(),
+            Check::Permutation { ref a, ref b } => {
+                for (x, y) in a.iter().zip(b) {
+                    writeln!(
+                        out,
+                        "  n{} -> n{} [style=dotted, dir=none, label=\"perm\"];",
+                        x, y
+                    )
+                    .unwrap();
+                }
+            }
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Lowers the graph to R1CS. Constants are pinned with `node * 1 = value`, additions become
+/// `(a + b) * 1 = node`, multiplications `a * b = node` and equality constraints `a * 1 = b`.
+/// Doublings have the single term `2a` and squarings `a * a = node`.
+/// Coefficients can't be negative, so `a * b = node - c` can't be stated: a mul-add takes an
+/// auxiliary variable `p` with `a * b = p` and `(p + c) * 1 = node`.
+/// Constraints with the `Warning` severity are left out, since a proof can't merely warn.
+/// Inputs and hints are unconstrained witness variables. Aliased nodes share the variable of the
+/// representative of their class, which needs no constraint, and the variables of the others are
+/// left unused. Custom nodes can't be constrained, so the graph must not have any: lower them
+/// with `lower_custom_ops` first, or this panics.
+///
+/// A check `a < b < 2^bits` adds auxiliary variables after the node variables and products: `d = b - a - 1`
+/// and the bits of `d` and of `b`. It becomes `(a + d + 1) * 1 = b`, `t * t = t` for every bit
+/// `t`, and `(sum of 2^i * t_i) * 1 = x` for `x` in `d` and `b`. A range check `x < 2^bits` adds
+/// the bits of `x` alone, constrained the same way. A check that `x` is in a set
+/// `{c_i}` adds a selector variable `s_i` per element, with `s_i * s_i = s_i`,
+/// `(sum of s_i) * 1 = 1` and `(sum of c_i * s_i) * 1 = x`. A permutation check between vectors
+/// `a` and `b` of length `n` adds a permutation matrix of `n * n` selectors `s_ij`, boolean with
+/// rows and columns summing to 1, and products `p_ij = s_ij * a_i` with `(sum of p_ij over i) * 1
+/// = b_j`.
+pub fn to_r1cs(builder: &Builder) -> R1cs {
+    try_to_r1cs(builder).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Like `to_r1cs`, but returns `BuildError::Unlowered` instead of panicking if the graph has
+/// custom nodes.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(nodes = builder.graph.len()))
+)]
+pub fn try_to_r1cs(builder: &Builder) -> Result<R1cs, BuildError> {
+    builder.check_lowered()?;
+    let var = |id: usize| builder.root(id) + 1;
+    let mut constraints = Vec::new();
+    let mut num_variables = builder.graph.len() + 1;
+    let mut aux = |count: usize| {
+        num_variables += count;
+        num_variables - count..num_variables
+    };
+    for node in builder.graph.iter() {
+        let constraint = match (node.op, node.inputs, node.addend) {
+            (Some(OPERATION::ADD), (Some(a), Some(b)), _) if a == b => R1csConstraint {
+                a: vec![(var(a), 2)],
+                b: vec![(ONE, 1)],
+                c: vec![(var(node.id), 1)],
+            },
+            (Some(OPERATION::ADD), (Some(a), Some(b)), _) => R1csConstraint {
+                a: vec![(var(a), 1), (var(b), 1)],
+                b: vec![(ONE, 1)],
+                c: vec![(var(node.id), 1)],
+            },
+            (Some(OPERATION::MUL), (Some(a), Some(b)), Some(c)) => {
+                let product = aux(1).start;
+                constraints.push(R1csConstraint {
+                    a: vec![(var(a), 1)],
+                    b: vec![(var(b), 1)],
+                    c: vec![(product, 1)],
+                });
+                R1csConstraint {
+                    a: vec![(product, 1), (var(c), 1)],
+                    b: vec![(ONE, 1)],
+                    c: vec![(var(node.id), 1)],
+                }
+            }
+            (Some(OPERATION::MUL), (Some(a), Some(b)), None) => R1csConstraint {
+                a: vec![(var(a), 1)],
+                b: vec![(var(b), 1)],
+                c: vec![(var(node.id), 1)],
+            },
+            _ if builder.graph.kind(node.id) == Kind::Constant => R1csConstraint {
+                a: vec![(var(node.id), 1)],
+                b: vec![(ONE, 1)],
+                c: vec![(ONE, node.output.unwrap())],
+            },
+            _ => continue,
+        };
+        constraints.push(constraint);
+    }
+    for (i, &(a, b)) in builder.constraints.iter().enumerate() {
+        if builder.soft_constraints.contains(&i) {
+            continue;
+        }
+        constraints.push(R1csConstraint {
+            a: vec![(var(a), 1)],
+            b: vec![(ONE, 1)],
+            c: vec![(var(b), 1)],
+        });
+    }
+    for check in &builder.checks {
+        match *check {
+            Check::LessThan { a, b, bits } => {
+                let d = aux(1).start;
+                constraints.push(R1csConstraint {
+                    a: vec![(var(a), 1), (d, 1), (ONE, 1)],
+                    b: vec![(ONE, 1)],
+                    c: vec![(var(b), 1)],
+                });
+                for value in [d, var(b)] {
+                    let bit_vars = aux(bits as usize);
+                    for t in bit_vars.clone() {
+                        constraints.push(R1csConstraint {
+                            a: vec![(t, 1)],
+                            b: vec![(t, 1)],
+                            c: vec![(t, 1)],
+                        });
+                    }
+                    constraints.push(R1csConstraint {
+                        a: bit_vars.zip(0..).map(|(t, i)| (t, 1 << i)).collect(),
+                        b: vec![(ONE, 1)],
+                        c: vec![(value, 1)],
+                    });
+                }
+            }
+            Check::Range { node, bits } => {
+                let bit_vars = aux(bits as usize);
+                for t in bit_vars.clone() {
+                    constraints.push(R1csConstraint {
+                        a: vec![(t, 1)],
+                        b: vec![(t, 1)],
+                        c: vec![(t, 1)],
+                    });
+                }
+                constraints.push(R1csConstraint {
+                    a: bit_vars.zip(0..).map(|(t, i)| (t, 1 << i)).collect(),
+                    b: vec![(ONE, 1)],
+                    c: vec![(var(node), 1)],
+                });
+            }
+            Check::InSet { node, ref set } => {
+                let selectors = aux(set.len());
+                for s in selectors.clone() {
+                    constraints.push(R1csConstraint {
+                        a: vec![(s, 1)],
+                        b: vec![(s, 1)],
+                        c: vec![(s, 1)],
+                    });
+                }
+                constraints.push(R1csConstraint {
+                    a: selectors.clone().map(|s| (s, 1)).collect(),
+                    b: vec![(ONE, 1)],
+                    c: vec![(ONE, 1)],
+                });
+                constraints.push(R1csConstraint {
+                    a: selectors.zip(set).map(|(s, &c)| (s, c)).collect(),
+                    b: vec![(ONE, 1)],
+                    c: vec![(var(node), 1)],
+                });
+            }
+            Check::Permutation { ref a, ref b } => {
+                let n = a.len();
+                let selectors = aux(n * n).start;
+                let products = aux(n * n).start;
+                let s = |i: usize, j: usize| selectors + i * n + j;
+                let p = |i: usize, j: usize| products + i * n + j;
+                for (i, &a_i) in a.iter().enumerate() {
+                    for j in 0..n {
+                        constraints.push(R1csConstraint {
+                            a: vec![(s(i, j), 1)],
+                            b: vec![(s(i, j), 1)],
+                            c: vec![(s(i, j), 1)],
+                        });
+                        constraints.push(R1csConstraint {
+                            a: vec![(s(i, j), 1)],
+                            b: vec![(var(a_i), 1)],
+                            c: vec![(p(i, j), 1)],
+                        });
+                    }
+                }
+                for (k, &b_k) in b.iter().enumerate() {
+                    for line in [
+                        (0..n).map(|j| (s(k, j), 1)).collect(),
+                        (0..n).map(|i| (s(i, k), 1)).collect(),
+                    ] {
+                        constraints.push(R1csConstraint {
+                            a: line,
+                            b: vec![(ONE, 1)],
+                            c: vec![(ONE, 1)],
+                        });
+                    }
+                    constraints.push(R1csConstraint {
+                        a: (0..n).map(|i| (p(i, k), 1)).collect(),
+                        b: vec![(ONE, 1)],
+                        c: vec![(var(b_k), 1)],
+                    });
+                }
+            }
+        }
+    }
+    #[cfg(feature = "tracing")]
+    tracing::debug!(constraints = constraints.len(), "lowered graph to r1cs");
+    Ok(R1cs {
+        num_variables,
+        constraints,
+    })
+}
+
+/// What the SMT-LIB script produced by `to_smtlib` asks the solver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtGoal {
+    /// Find a witness satisfying every constraint: `sat` comes with a model holding the value of
+    /// every node, and `unsat` means no inputs satisfy the constraints.
+    Witness,
+    /// Find inputs that make some constraint fail: `unsat` proves that every input satisfying the
+    /// graph's computation also satisfies its constraints.
+    Violation,
+}
+
+/// Renders the graph and its constraints as an SMT-LIB 2 script in the `QF_BV` logic, for solvers
+/// such as Z3 or CVC5.
+///
+/// Node `i` is the 32-bit vector `n<i>`. The computation of every node is asserted exactly as
+/// evaluation does it: additions, multiplications and mul-adds are computed on 64 bits so that results
+/// that overflow a u32 are ruled out, constants and fixed hints are pinned to their value, and
+/// hints named `sqrt` and `not` are computed like the default hint functions. Hints computed by
+/// other functions are left unconstrained, so the solver may pick any value for them. Equality
+/// constraints and checks are then asserted, or their failure is for `SmtGoal::Violation`.
+/// Constraints with the `Warning` severity are left out.
+pub fn to_smtlib(builder: &Builder, goal: SmtGoal) -> String {
+    let mut out = String::from("(set-logic QF_BV)\n");
+    let wide = |id: usize| format!("((_ zero_extend 32) n{})", id);
+    for id in 0..builder.graph.len() {
+        write!(out, "(declare-const n{} (_ BitVec 32))", id).unwrap();
+        match builder.labels.get(&id) {
+            Some(label) => writeln!(out, " ; {}", label).unwrap(),
+            None => out.push('\n'),
+        }
+    }
+    for node in builder.graph.iter() {
+        let id = node.id;
+        let definition = match (builder.graph.kind(id), node.inputs, node.hint) {
+            (Kind::Add, (Some(a), Some(b)), _) => {
+                format!("(= {} (bvadd {} {}))", wide(id), wide(a), wide(b))
+            }
+            (Kind::Mul, (Some(a), Some(b)), _) => {
+                format!("(= {} (bvmul {} {}))", wide(id), wide(a), wide(b))
+            }
+            (Kind::MulAdd, (Some(a), Some(b)), _) => format!(
+                "(= {} (bvadd (bvmul {} {}) {}))",
+                wide(id),
+                wide(a),
+                wide(b),
+                wide(node.addend.unwrap())
+            ),
+            (Kind::Constant, ..) => format!("(= n{} {})", id, bv(node.output.unwrap())),
+            (Kind::Hint, _, Some(linked)) => match builder.hint_calls.get(&id).map(String::as_str) {
+                None => format!("(= n{} {})", id, bv(node.output.unwrap())),
+                Some("sqrt") => format!(
+                    "(and (bvule (bvmul {0} {0}) {1}) (bvult {1} (bvmul (bvadd {0} {2}) (bvadd {0} {2}))))",
+                    wide(id),
+                    wide(linked),
+                    "(_ bv1 64)"
+                ),
+                Some("not") => format!(
+                    "(= n{} (ite (= n{} {}) {} {}))",
+                    id,
+                    linked,
+                    bv(0),
+                    bv(1),
+                    bv(0)
+                ),
+                Some(_) => continue,
+            },
+            _ => continue,
+        };
+        writeln!(out, "(assert {})", definition).unwrap();
+    }
+    let mut conditions = Vec::new();
+    for (a, b) in builder.copies() {
+        conditions.push(format!("(= n{} n{})", a, b));
+    }
+    for check in &builder.checks {
+        conditions.push(match *check {
+            Check::LessThan { a, b, bits } if bits < 32 => format!(
+                "(and (bvult n{} n{}) (bvult n{} {}))",
+                a,
+                b,
+                b,
+                bv(1 << bits)
+            ),
+            Check::LessThan { a, b, .. } => format!("(bvult n{} n{})", a, b),
+            Check::Range { node, bits } if bits < 32 => {
+                format!("(bvult n{} {})", node, bv(1 << bits))
+            }
+            Check::Range { .. } => "true".to_string(),
+            Check::InSet { node, ref set } => {
+                let members: Vec<String> = set
+                    .iter()
+                    .map(|&c| format!("(= n{} {})", node, bv(c)))
+                    .collect();
+                format!("(or {})", members.join(" "))
+            }
+            // Every value appears as often in `a` as in `b`.
+            Check::Permutation { ref a, ref b } => {
+                let count = |side: &[usize], value: usize| {
+                    let terms: Vec<String> = side
+                        .iter()
+                        .map(|x| format!("(ite (= n{} n{}) {} {})", x, value, bv(1), bv(0)))
+                        .collect();
+                    format!("(bvadd {} {})", bv(0), terms.join(" "))
+                };
+                let counts: Vec<String> = a
+                    .iter()
+                    .chain(b)
+                    .map(|&value| format!("(= {} {})", count(a, value), count(b, value)))
+                    .collect();
+                format!("(and true {})", counts.join(" "))
+            }
+        });
+    }
+    match goal {
+        SmtGoal::Witness => {
+            for condition in &conditions {
+                writeln!(out, "(assert {})", condition).unwrap();
+            }
+        }
+        SmtGoal::Violation => {
+            let failures: Vec<String> = conditions
+                .iter()
+                .map(|condition| format!("(not {})", condition))
+                .collect();
+            writeln!(out, "(assert (or false {}))", failures.join(" ")).unwrap();
+        }
+    }
+    out.push_str("(check-sat)\n(get-model)\n");
+    out
+}
+
+/// A 32-bit vector literal.
+fn bv(value: u32) -> String {
+    format!("(_ bv{} 32)", value)
+}
@@ -0,0 +1,224 @@
+//! A C API for embedding the evaluator, built with `--features ffi`. The matching header is
+//! `include/circuit.h`, generated with `cbindgen --config cbindgen.toml --output include/circuit.h`.
+//!
+//! Nodes are referred to by their index. Functions that create a node return `CIRCUIT_INVALID`
+//! when one of their operands does not exist, and functions returning `bool` return false on
+//! any error, e.g. an addition overflowing during evaluation.
+//!
+//! ```c
+//! CircuitBuilder *builder = circuit_builder_new();
+//! size_t x = circuit_init(builder);
+//! size_t y = circuit_mul(builder, x, x);
+//! uint32_t value = 7;
+//! circuit_fill(builder, &x, &value, 1);
+//! uint32_t out;
+//! circuit_output(builder, y, &out); // out == 49
+//! circuit_builder_free(builder);
+//! ```
+
+use std::ffi::{c_char, CStr};
+use std::slice;
+
+use crate::eval::EvalOptions;
+use crate::{Builder, NodeId};
+
+/// Returned in place of a node index when a node could not be created.
+pub const CIRCUIT_INVALID: usize = !0;
+
+/// An opaque handle to a graph under construction.
+pub struct CircuitBuilder {
+    builder: Builder,
+}
+
+impl CircuitBuilder {
+    fn node(&self, index: usize) -> Option<NodeId> {
+        self.builder.node_id(index)
+    }
+
+    /// Creates a node from existing operands, or returns `CIRCUIT_INVALID` if one is missing.
+    fn push(
+        &mut self,
+        operands: &[usize],
+        f: impl FnOnce(&mut Builder, &[NodeId]) -> NodeId,
+    ) -> usize {
+        let ids: Option<Vec<NodeId>> = operands.iter().map(|&index| self.node(index)).collect();
+        match ids {
+            Some(ids) => f(&mut self.builder, &ids).index(),
+            None => CIRCUIT_INVALID,
+        }
+    }
+}
+
+/// Creates an empty graph. Free it with `circuit_builder_free`.
+#[no_mangle]
+pub extern "C" fn circuit_builder_new() -> *mut CircuitBuilder {
+    Box::into_raw(Box::new(CircuitBuilder {
+        builder: Builder::new(),
+    }))
+}
+
+/// Frees a graph created with `circuit_builder_new`. Passing null does nothing.
+///
+/// # Safety
+/// `builder` must be null or a pointer returned by `circuit_builder_new` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn circuit_builder_free(builder: *mut CircuitBuilder) {
+    if !builder.is_null() {
+        drop(Box::from_raw(builder));
+    }
+}
+
+/// Adds an input node and returns its index.
+///
+/// # Safety
+/// `builder` must be a live pointer returned by `circuit_builder_new`.
+#[no_mangle]
+pub unsafe extern "C" fn circuit_init(builder: *mut CircuitBuilder) -> usize {
+    (*builder).builder.init().index()
+}
+
+/// Adds a node with a constant value and returns its index.
+///
+/// # Safety
+/// `builder` must be a live pointer returned by `circuit_builder_new`.
+#[no_mangle]
+pub unsafe extern "C" fn circuit_constant(builder: *mut CircuitBuilder, value: u32) -> usize {
+    (*builder).builder.constant(value).index()
+}
+
+/// Adds a node computing `a + b` and returns its index.
+///
+/// # Safety
+/// `builder` must be a live pointer returned by `circuit_builder_new`.
+#[no_mangle]
+pub unsafe extern "C" fn circuit_add(builder: *mut CircuitBuilder, a: usize, b: usize) -> usize {
+    (*builder).push(&[a, b], |builder, ids| builder.add(ids[0], ids[1]))
+}
+
+/// Adds a node computing `a * b` and returns its index.
+///
+/// # Safety
+/// `builder` must be a live pointer returned by `circuit_builder_new`.
+#[no_mangle]
+pub unsafe extern "C" fn circuit_mul(builder: *mut CircuitBuilder, a: usize, b: usize) -> usize {
+    (*builder).push(&[a, b], |builder, ids| builder.mul(ids[0], ids[1]))
+}
+
+/// Adds a hint node computed by the registered hint function `name` (e.g. "sqrt") from the
+/// output of `node`. Returns `CIRCUIT_INVALID` if the function or the node does not exist.
+///
+/// # Safety
+/// `builder` must be a live pointer returned by `circuit_builder_new`, and `name` a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn circuit_hint(
+    builder: *mut CircuitBuilder,
+    name: *const c_char,
+    node: usize,
+) -> usize {
+    let builder = &mut *builder;
+    match CStr::from_ptr(name).to_str() {
+        Ok(name) if builder.builder.has_hint(name) => {
+            builder.push(&[node], |builder, ids| builder.hint_fn(name, ids[0]))
+        }
+        _ => CIRCUIT_INVALID,
+    }
+}
+
+/// Records that the outputs of `a` and `b` must be equal. Returns false if a node does not exist.
+///
+/// # Safety
+/// `builder` must be a live pointer returned by `circuit_builder_new`.
+#[no_mangle]
+pub unsafe extern "C" fn circuit_enforce_equal(
+    builder: *mut CircuitBuilder,
+    a: usize,
+    b: usize,
+) -> bool {
+    let builder = &mut *builder;
+    match (builder.node(a), builder.node(b)) {
+        (Some(a), Some(b)) => {
+            builder.builder.enforce_equal(a, b);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Sets `len` input nodes to the given values and fills in the rest of the graph.
+/// Returns false if one of the nodes is not an input or evaluation fails.
+///
+/// # Safety
+/// `builder` must be a live pointer returned by `circuit_builder_new`, and `inputs` and
+/// `values` must each point to `len` elements.
+#[no_mangle]
+pub unsafe extern "C" fn circuit_fill(
+    builder: *mut CircuitBuilder,
+    inputs: *const usize,
+    values: *const u32,
+    len: usize,
+) -> bool {
+    let builder = &mut *builder;
+    let (inputs, values) = if len == 0 {
+        (&[][..], &[][..])
+    } else {
+        (
+            slice::from_raw_parts(inputs, len),
+            slice::from_raw_parts(values, len),
+        )
+    };
+    let input_nodes = builder.builder.inputs();
+    let mut assignment = Vec::with_capacity(len);
+    for (&index, &value) in inputs.iter().zip(values) {
+        match builder.node(index) {
+            Some(id) if input_nodes.contains(&id) => assignment.push((id, value)),
+            _ => return false,
+        }
+    }
+    builder
+        .builder
+        .fill_inputs_with(&assignment, EvalOptions::new())
+        .is_ok()
+}
+
+/// Returns true if every constraint holds for the filled in outputs.
+///
+/// # Safety
+/// `builder` must be a live pointer returned by `circuit_builder_new`.
+#[no_mangle]
+pub unsafe extern "C" fn circuit_is_satisfied(builder: *const CircuitBuilder) -> bool {
+    (*builder).builder.is_satisfied()
+}
+
+/// Writes the output of `node` to `out`. Returns false if the node does not exist or has no value.
+///
+/// # Safety
+/// `builder` must be a live pointer returned by `circuit_builder_new`, and `out` must be valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn circuit_output(
+    builder: *const CircuitBuilder,
+    node: usize,
+    out: *mut u32,
+) -> bool {
+    let builder = &*builder;
+    match builder
+        .node(node)
+        .and_then(|id| builder.builder.graph.output(id.index()))
+    {
+        Some(value) => {
+            *out = value;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns the number of nodes in the graph.
+///
+/// # Safety
+/// `builder` must be a live pointer returned by `circuit_builder_new`.
+#[no_mangle]
+pub unsafe extern "C" fn circuit_len(builder: *const CircuitBuilder) -> usize {
+    (*builder).builder.len()
+}
@@ -0,0 +1,373 @@
+//! Evaluation over the small prime fields of FRI-based provers such as Plonky3.
+//!
+//! Evaluation normally computes over u32 and fails on overflow, while a prover over a prime field
+//! wraps around its modulus. `Builder::evaluate_field` evaluates a graph in `Fp<P>`, the integers
+//! modulo `P`, so circuits meant for such a prover compute the values it will see, and
+//! `Builder::check_field` checks the constraints on them. Each field is behind its own feature:
+//!
+//! - `Goldilocks`, 2^64 - 2^32 + 1 (feature `goldilocks`),
+//! - `BabyBear`, 2^31 - 2^27 + 1 (feature `babybear`),
+//! - `Mersenne31`, 2^31 - 1 (feature `mersenne31`).
+//!
+//! Constants, fixed hints and the values of hint functions and custom operations are reduced
+//! modulo `P`. Hint functions and custom operations take u32s, so they fail on larger operands,
+//! which only Goldilocks has. Checks compare the
+//! canonical representatives of the values, in `0..P`.
+//!
+//! Elements are kept in Montgomery form, which avoids a division by `P` in every multiplication;
+//! `bench::bench_field` compares it with reducing with `%`.
+//!
+//! ```
+//! # #[cfg(feature = "babybear")] {
+//! use my_graph_lib::field::{BabyBear, Fp};
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let square = builder.mul(x, x);
+//! let values = builder
+//!     .evaluate_field(&[(x, BabyBear::new(1 << 20))])
+//!     .unwrap();
+//! // 2^40 modulo 15 * 2^27 + 1.
+//! assert_eq!(values[square.index()], Fp::new(1 << 40));
+//! assert_eq!(values[square.index()].value(), 268_434_910);
+//! assert!(builder.check_field(&values).is_ok());
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::{Add, Mul};
+
+use thiserror::Error;
+
+use crate::checks::Check;
+use crate::error::BuildError;
+use crate::storage::Kind;
+use crate::{Builder, NodeId};
+
+/// The Goldilocks field, of order 2^64 - 2^32 + 1.
+#[cfg(feature = "goldilocks")]
+pub type Goldilocks = Fp<0xffff_ffff_0000_0001>;
+
+/// The BabyBear field, of order 2^31 - 2^27 + 1.
+#[cfg(feature = "babybear")]
+pub type BabyBear = Fp<0x7800_0001>;
+
+/// The Mersenne31 field, of order 2^31 - 1.
+#[cfg(feature = "mersenne31")]
+pub type Mersenne31 = Fp<0x7fff_ffff>;
+
+/// An element of the integers modulo the odd prime `P`.
+///
+/// Elements are stored in Montgomery form, `x * 2^64 mod P`, so multiplications reduce their
+/// 128-bit products with a multiplication and a shift instead of a division. `new` and `value`
+/// convert to and from the canonical representative.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Fp<const P: u64>(u64);
+
+impl<const P: u64> Fp<P> {
+    pub const MODULUS: u64 = P;
+
+    /// -P^-1 modulo 2^64.
+    const INV: u64 = inverse(P).wrapping_neg();
+
+    /// 2^128 modulo P, which converts to Montgomery form.
+    const R2: u64 = ((u128::MAX % P as u128 + 1) % P as u128) as u64;
+
+    /// `value` modulo `P`.
+    pub fn new(value: u64) -> Self {
+        const { assert!(P % 2 == 1, "Montgomery form needs an odd modulus") };
+        Fp(Self::reduce(u128::from(value % P) * u128::from(Self::R2)))
+    }
+
+    /// The canonical representative, in `0..P`.
+    pub fn value(self) -> u64 {
+        Self::reduce(u128::from(self.0))
+    }
+
+    /// `t * 2^-64` modulo P, for `t` below `P * 2^64`.
+    fn reduce(t: u128) -> u64 {
+        let m = (t as u64).wrapping_mul(Self::INV);
+        // The low 64 bits of the sum are zero, and the sum may overflow for moduli near 2^64.
+        let (sum, carry) = t.overflowing_add(u128::from(m) * u128::from(P));
+        let reduced = (sum >> 64) | (u128::from(carry) << 64);
+        if reduced >= u128::from(P) {
+            (reduced - u128::from(P)) as u64
+        } else {
+            reduced as u64
+        }
+    }
+}
+
+/// P^-1 modulo 2^64 by Newton's iteration, each step doubling the number of correct bits.
+const fn inverse(p: u64) -> u64 {
+    let mut inverse = 1u64;
+    let mut i = 0;
+    while i < 6 {
+        inverse = inverse.wrapping_mul(2u64.wrapping_sub(p.wrapping_mul(inverse)));
+        i += 1;
+    }
+    inverse
+}
+
+impl<const P: u64> Add for Fp<P> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let (sum, carry) = self.0.overflowing_add(other.0);
+        if carry || sum >= P {
+            Fp(sum.wrapping_sub(P))
+        } else {
+            Fp(sum)
+        }
+    }
+}
+
+impl<const P: u64> Mul for Fp<P> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Fp(Self::reduce(u128::from(self.0) * u128::from(other.0)))
+    }
+}
+
+/// Elements are ordered by their canonical representatives.
+impl<const P: u64> Ord for Fp<P> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.value().cmp(&other.value())
+    }
+}
+
+impl<const P: u64> PartialOrd for Fp<P> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const P: u64> fmt::Debug for Fp<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Fp({})", self.value())
+    }
+}
+
+impl<const P: u64> fmt::Display for Fp<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value())
+    }
+}
+
+/// The integers modulo `P` as canonical representatives, reduced with `%` after every operation:
+/// the reference `Fp` is benchmarked against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Naive<const P: u64>(u64);
+
+/// A representation of the integers modulo a prime that graphs can be evaluated in.
+pub(crate) trait Representation: Copy + Eq {
+    fn from_u64(value: u64) -> Self;
+    fn to_u64(self) -> u64;
+    fn add(self, other: Self) -> Self;
+    fn mul(self, other: Self) -> Self;
+}
+
+impl<const P: u64> Representation for Fp<P> {
+    fn from_u64(value: u64) -> Self {
+        Fp::new(value)
+    }
+
+    fn to_u64(self) -> u64 {
+        self.value()
+    }
+
+    fn add(self, other: Self) -> Self {
+        self + other
+    }
+
+    fn mul(self, other: Self) -> Self {
+        self * other
+    }
+}
+
+impl<const P: u64> Representation for Naive<P> {
+    fn from_u64(value: u64) -> Self {
+        Naive(value % P)
+    }
+
+    fn to_u64(self) -> u64 {
+        self.0
+    }
+
+    fn add(self, other: Self) -> Self {
+        Naive(((u128::from(self.0) + u128::from(other.0)) % u128::from(P)) as u64)
+    }
+
+    fn mul(self, other: Self) -> Self {
+        Naive(((u128::from(self.0) * u128::from(other.0)) % u128::from(P)) as u64)
+    }
+}
+
+/// An error of `Builder::evaluate_field` or `Builder::check_field`.
+#[derive(Debug, Error)]
+pub enum FieldError {
+    #[error(transparent)]
+    Build(#[from] BuildError),
+    #[error("input node {node} has no value")]
+    MissingInput { node: usize },
+    #[error("the operand of hint node {node}, {value}, doesn't fit in the u32 its function takes")]
+    HintOperand { node: usize, value: u64 },
+    #[error(
+        "an operand of custom node {node}, {value}, doesn't fit in the u32 its operation takes"
+    )]
+    CustomOperand { node: usize, value: u64 },
+    #[error("custom node {node} fails: operation `{op}` is undefined on its operands")]
+    CustomFailed { node: usize, op: String },
+    #[error("{values} values were given for a graph of {nodes} nodes")]
+    WrongLength { values: usize, nodes: usize },
+    #[error(
+        "constraint {constraint} doesn't hold: node {a} is {a_value} and node {b} is {b_value}"
+    )]
+    NotEqual {
+        constraint: usize,
+        a: usize,
+        b: usize,
+        a_value: u64,
+        b_value: u64,
+    },
+    #[error("check {check} doesn't hold")]
+    Check { check: usize },
+}
+
+impl Builder {
+    /// The value of every node in `Fp<P>`, indexed by node index, from the values of the inputs.
+    /// Values wrap around `P` where evaluation would fail on overflow, see the module
+    /// documentation. The builder's own values are left untouched.
+    pub fn evaluate_field<const P: u64>(
+        &self,
+        inputs: &[(NodeId, Fp<P>)],
+    ) -> Result<Vec<Fp<P>>, FieldError> {
+        self.evaluate_in(inputs)
+    }
+
+    /// `evaluate_field` in any representation.
+    pub(crate) fn evaluate_in<R: Representation>(
+        &self,
+        inputs: &[(NodeId, R)],
+    ) -> Result<Vec<R>, FieldError> {
+        let mut given = HashMap::new();
+        for &(input, value) in inputs {
+            let node = self.resolve(input)?;
+            if self.graph.kind(node) != Kind::Input {
+                return Err(BuildError::NotAnInput { node }.into());
+            }
+            given.insert(node, value);
+        }
+        let mut values: Vec<R> = Vec::with_capacity(self.graph.len());
+        for id in 0..self.graph.len() {
+            let value = match self.graph.kind(id) {
+                Kind::Input => *given
+                    .get(&id)
+                    .ok_or(FieldError::MissingInput { node: id })?,
+                Kind::Add | Kind::Mul => {
+                    let (a, b) = self.graph.operands(id).unwrap();
+                    match self.graph.kind(id) {
+                        Kind::Add => values[a].add(values[b]),
+                        _ => values[a].mul(values[b]),
+                    }
+                }
+                Kind::MulAdd => {
+                    let [a, b, c] = self.graph.mul_add(id).unwrap();
+                    values[a].mul(values[b]).add(values[c])
+                }
+                Kind::Hint => match self.hint_calls.get(&id) {
+                    Some(name) => {
+                        let f = *self
+                            .hint_fns
+                            .get(name)
+                            .ok_or_else(|| BuildError::UnknownHint { name: name.clone() })?;
+                        let operand = values[self.graph.linked(id).unwrap()].to_u64();
+                        let operand =
+                            u32::try_from(operand).map_err(|_| FieldError::HintOperand {
+                                node: id,
+                                value: operand,
+                            })?;
+                        R::from_u64(u64::from(f(operand)))
+                    }
+                    None => R::from_u64(u64::from(self.graph.output(id).unwrap())),
+                },
+                Kind::Custom => {
+                    let name = &self.custom_calls[&id];
+                    let op = self.node_op(id)?;
+                    let operands = self
+                        .graph
+                        .custom(id)
+                        .unwrap()
+                        .into_iter()
+                        .map(|operand| {
+                            let value = values[operand].to_u64();
+                            u32::try_from(value)
+                                .map_err(|_| FieldError::CustomOperand { node: id, value })
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let value = op
+                        .evaluate(&operands)
+                        .ok_or_else(|| FieldError::CustomFailed {
+                            node: id,
+                            op: name.clone(),
+                        })?;
+                    R::from_u64(u64::from(value))
+                }
+                Kind::Constant => R::from_u64(u64::from(self.graph.output(id).unwrap())),
+            };
+            values.push(value);
+        }
+        Ok(values)
+    }
+
+    /// Checks the equality constraints and the checks on values from `evaluate_field`. Constraints
+    /// with the `Warning` severity are left out.
+    pub fn check_field<const P: u64>(&self, values: &[Fp<P>]) -> Result<(), FieldError> {
+        if values.len() != self.graph.len() {
+            return Err(FieldError::WrongLength {
+                values: values.len(),
+                nodes: self.graph.len(),
+            });
+        }
+        for (constraint, &(a, b)) in self.constraints.iter().enumerate() {
+            if !self.soft_constraints.contains(&constraint) && values[a] != values[b] {
+                return Err(FieldError::NotEqual {
+                    constraint,
+                    a,
+                    b,
+                    a_value: values[a].value(),
+                    b_value: values[b].value(),
+                });
+            }
+        }
+        for (check, kind) in self.checks.iter().enumerate() {
+            let value = |node: usize| values[node].value();
+            let holds = match *kind {
+                Check::LessThan { a, b, bits } => {
+                    value(a) < value(b) && u128::from(value(b)) < 1u128 << bits
+                }
+                Check::InSet { node, ref set } => {
+                    u32::try_from(value(node)).is_ok_and(|value| set.binary_search(&value).is_ok())
+                }
+                Check::Range { node, bits } => u128::from(value(node)) < 1u128 << bits,
+                Check::Permutation { ref a, ref b } => {
+                    let mut counts: BTreeMap<u64, i64> = BTreeMap::new();
+                    for &node in a {
+                        *counts.entry(value(node)).or_default() += 1;
+                    }
+                    for &node in b {
+                        *counts.entry(value(node)).or_default() -= 1;
+                    }
+                    counts.values().all(|&count| count == 0)
+                }
+            };
+            if !holds {
+                return Err(FieldError::Check { check });
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+//! A content hash identifying a circuit, so a deployment can check that it is proving against the
+//! circuit version it expects.
+//!
+//! Every node is hashed from what it computes rather than from its index: its kind, the hashes of
+//! its operands (sorted, since addition and multiplication commute), its value for constants, and
+//! its hint function's name. Inputs are told apart by their position among the inputs. The
+//! fingerprint then hashes the sorted node hashes, constraints and public outputs, so it does not
+//! change when nodes are added in a different order or constraint operands are swapped. Aliased
+//! nodes are hashed as constraints with the representative of their class.
+//! Constraints with the `Warning` severity and checks (orderings, set memberships and
+//! permutations) are hashed in sections of
+//! their own, added only when there are some. Labels, metadata, constraint messages and source locations are not part of the
+//! fingerprint.
+
+use std::fmt;
+
+use crate::checks::Check;
+use crate::storage::Kind;
+use crate::Builder;
+
+/// Separates fingerprints from other uses of BLAKE3.
+const DOMAIN: &[u8] = b"my_graph_lib circuit fingerprint v1";
+
+/// A BLAKE3 hash of the structure of a circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub [u8; 32]);
+
+/// Prints the fingerprint in hex.
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl Builder {
+    /// Computes the fingerprint of the graph's structure and constraints.
+    pub fn fingerprint(&self) -> Fingerprint {
+        let hashes = self.node_hashes();
+        let mut nodes = hashes.clone();
+        nodes.sort_unstable();
+        let mut constraints: Vec<[[u8; 32]; 2]> = Vec::new();
+        let mut soft: Vec<[[u8; 32]; 2]> = Vec::new();
+        for (i, &(a, b)) in self.constraints.iter().enumerate() {
+            let mut pair = [hashes[a], hashes[b]];
+            pair.sort_unstable();
+            if self.soft_constraints.contains(&i) {
+                soft.push(pair);
+            } else {
+                constraints.push(pair);
+            }
+        }
+        for (root, node) in self.alias_pairs() {
+            let mut pair = [hashes[root], hashes[node]];
+            pair.sort_unstable();
+            constraints.push(pair);
+        }
+        constraints.sort_unstable();
+        soft.sort_unstable();
+        let mut checks: Vec<[u8; 32]> = self
+            .checks
+            .iter()
+            .map(|check| match *check {
+                Check::LessThan { a, b, bits } => {
+                    let mut hasher = blake3::Hasher::new();
+                    hasher.update(b"lt");
+                    hasher.update(&hashes[a]);
+                    hasher.update(&hashes[b]);
+                    hasher.update(&bits.to_le_bytes());
+                    *hasher.finalize().as_bytes()
+                }
+                Check::InSet { node, ref set } => {
+                    let mut hasher = blake3::Hasher::new();
+                    hasher.update(b"in");
+                    hasher.update(&hashes[node]);
+                    for value in set {
+                        hasher.update(&value.to_le_bytes());
+                    }
+                    *hasher.finalize().as_bytes()
+                }
+                Check::Range { node, bits } => {
+                    let mut hasher = blake3::Hasher::new();
+                    hasher.update(b"range");
+                    hasher.update(&hashes[node]);
+                    hasher.update(&bits.to_le_bytes());
+                    *hasher.finalize().as_bytes()
+                }
+                Check::Permutation { ref a, ref b } => {
+                    // Neither the order within a vector nor the order of the vectors matters.
+                    let mut sides = [a, b].map(|nodes| {
+                        let mut side: Vec<[u8; 32]> = nodes.iter().map(|&id| hashes[id]).collect();
+                        side.sort_unstable();
+                        *blake3::hash(side.as_flattened()).as_bytes()
+                    });
+                    sides.sort_unstable();
+                    let mut hasher = blake3::Hasher::new();
+                    hasher.update(b"perm");
+                    hasher.update(sides.as_flattened());
+                    *hasher.finalize().as_bytes()
+                }
+            })
+            .collect();
+        checks.sort_unstable();
+        let mut public: Vec<[u8; 32]> = self.public.iter().map(|&id| hashes[id]).collect();
+        public.sort_unstable();
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(DOMAIN);
+        let mut sections = vec![
+            nodes.as_flattened(),
+            constraints.as_flattened().as_flattened(),
+            public.as_flattened(),
+        ];
+        if !soft.is_empty() {
+            sections.push(soft.as_flattened().as_flattened());
+        }
+        if !checks.is_empty() {
+            sections.push(checks.as_flattened());
+        }
+        for section in sections {
+            hasher.update(&(section.len() as u64).to_le_bytes());
+            hasher.update(section);
+        }
+        Fingerprint(*hasher.finalize().as_bytes())
+    }
+
+    /// Hashes every node from its contents, in index order.
+    fn node_hashes(&self) -> Vec<[u8; 32]> {
+        let mut hashes: Vec<[u8; 32]> = Vec::with_capacity(self.graph.len());
+        let mut next_input = 0u64;
+        for id in 0..self.graph.len() {
+            let mut hasher = blake3::Hasher::new();
+            match self.graph.kind(id) {
+                Kind::Input => {
+                    hasher.update(b"input");
+                    hasher.update(&next_input.to_le_bytes());
+                    next_input += 1;
+                }
+                Kind::Constant => {
+                    hasher.update(b"constant");
+                    hasher.update(&self.graph.output(id).unwrap().to_le_bytes());
+                }
+                kind @ (Kind::Add | Kind::Mul) => {
+                    hasher.update(if kind == Kind::Add { b"add" } else { b"mul" });
+                    let (a, b) = self.graph.operands(id).unwrap();
+                    let mut operands = [hashes[a], hashes[b]];
+                    operands.sort_unstable();
+                    hasher.update(operands.as_flattened());
+                }
+                Kind::MulAdd => {
+                    hasher.update(b"mul_add");
+                    let [a, b, c] = self.graph.mul_add(id).unwrap();
+                    let mut factors = [hashes[a], hashes[b]];
+                    factors.sort_unstable();
+                    hasher.update(factors.as_flattened());
+                    hasher.update(&hashes[c]);
+                }
+                Kind::Hint => {
+                    hasher.update(b"hint");
+                    hasher.update(&hashes[self.graph.linked(id).unwrap()]);
+                    // Fixed hint values are advice supplied with the witness, not structure.
+                    if let Some(name) = self.hint_calls.get(&id) {
+                        hasher.update(name.as_bytes());
+                    }
+                }
+                Kind::Custom => {
+                    hasher.update(b"custom");
+                    hasher.update(self.custom_calls[&id].as_bytes());
+                    for operand in self.graph.custom(id).unwrap() {
+                        hasher.update(&hashes[operand]);
+                    }
+                }
+            }
+            hashes.push(*hasher.finalize().as_bytes());
+        }
+        hashes
+    }
+}
@@ -0,0 +1,256 @@
+//! Signed fixed-point numbers, for computations such as neural network inference.
+//!
+//! Nodes hold unsigned integers and graphs have no subtraction, so a `Fixed` is a pair of nodes,
+//! `pos` and `neg`, standing for `(pos - neg) / 2^FRAC_BITS`. Additions and multiplications work
+//! on the parts directly. `fixed_normalize` finds the sign of a value with a comparison gadget
+//! and rewrites it with one part 0, which `fixed_relu` builds on:
+//!
+//! ```
+//! use my_graph_lib::fixed::Fixed;
+//! use my_graph_lib::Builder;
+//!
+//! let mut b = Builder::new();
+//! let x = b.fixed_input();
+//! let w = b.fixed_constant(-1.5);
+//! let bias = b.fixed_constant(2.0);
+//! let product = b.fixed_mul(x, w);
+//! let y = b.fixed_add(product, bias);
+//! let y = b.fixed_relu(y);
+//! b.fill_inputs(&Fixed::assign(x, 1.0));
+//! assert_eq!(b.fixed_value(y), Some(0.5));
+//! b.fill_inputs(&Fixed::assign(x, 3.0));
+//! assert_eq!(b.fixed_value(y), Some(0.0));
+//! assert!(b.is_satisfied());
+//! ```
+//!
+//! Normalized parts are range checked to `MAG_BITS` bits, so values must stay within
+//! ±2^(MAG_BITS - FRAC_BITS) = ±256 wherever they are normalized, and products of normalized
+//! values fit in a u32. A value out of range makes the constraints fail. Products are truncated
+//! to `FRAC_BITS` fractional bits, rounding each part down.
+
+use crate::wires::BoolVar;
+use crate::{Builder, HintFn, NodeId};
+
+/// Number of fractional bits: values are multiples of 2^-FRAC_BITS.
+pub const FRAC_BITS: u32 = 8;
+
+/// Number of bits of the parts of a normalized value.
+pub const MAG_BITS: u32 = 16;
+
+/// Number of bits of the parts of a value the comparison gadget accepts.
+const CMP_BITS: u32 = 30;
+
+/// Hint functions of the gadgets, registered by `Builder::new`.
+pub(crate) const HINTS: [(&str, HintFn); 5] = [
+    ("fixed_shr", |x| x >> FRAC_BITS),
+    ("fixed_frac", |x| x & ((1 << FRAC_BITS) - 1)),
+    ("fixed_complement", |x| (1u32 << CMP_BITS).saturating_sub(x)),
+    ("fixed_high", |x| x >> CMP_BITS),
+    ("fixed_low", |x| x & ((1 << CMP_BITS) - 1)),
+];
+
+/// A signed fixed-point value, `(pos - neg) / 2^FRAC_BITS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fixed {
+    pos: NodeId,
+    neg: NodeId,
+    // At most one part is nonzero, and both fit in MAG_BITS bits.
+    normalized: bool,
+}
+
+impl Fixed {
+    /// The value `(pos - neg) / 2^FRAC_BITS` of two nodes, e.g. accumulators of a custom gadget.
+    /// It is normalized before the gadgets that need it.
+    pub fn from_parts(pos: NodeId, neg: NodeId) -> Fixed {
+        Fixed {
+            pos,
+            neg,
+            normalized: false,
+        }
+    }
+
+    /// The nodes `pos` and `neg` of the value.
+    pub fn parts(self) -> (NodeId, NodeId) {
+        (self.pos, self.neg)
+    }
+
+    /// The parts standing for `x`, rounded to the nearest multiple of 2^-FRAC_BITS.
+    pub fn encode(x: f64) -> (u32, u32) {
+        let scaled = (x * f64::from(1u32 << FRAC_BITS)).round();
+        let magnitude = scaled.abs().min(f64::from(u32::MAX)) as u32;
+        if scaled < 0.0 {
+            (0, magnitude)
+        } else {
+            (magnitude, 0)
+        }
+    }
+
+    pub fn decode(pos: u32, neg: u32) -> f64 {
+        (f64::from(pos) - f64::from(neg)) / f64::from(1u32 << FRAC_BITS)
+    }
+
+    /// Input assignments setting the input `x` to `value`, for `fill_inputs`.
+    pub fn assign(x: Fixed, value: f64) -> [(NodeId, u32); 2] {
+        let (pos, neg) = Fixed::encode(value);
+        [(x.pos, pos), (x.neg, neg)]
+    }
+}
+
+impl Builder {
+    /// Adds a fixed-point input: two input nodes, see `Fixed::assign`, constrained to fit in
+    /// `MAG_BITS` bits with at least one of them 0.
+    pub fn fixed_input(&mut self) -> Fixed {
+        let (pos, neg) = (self.init(), self.init());
+        self.assert_range(pos, MAG_BITS);
+        self.assert_range(neg, MAG_BITS);
+        let product = self.mul(pos, neg);
+        let zero = self.constant(0);
+        self.enforce_equal(product, zero);
+        Fixed {
+            pos,
+            neg,
+            normalized: true,
+        }
+    }
+
+    /// Adds a fixed-point constant. Panics if it is out of range.
+    pub fn fixed_constant(&mut self, x: f64) -> Fixed {
+        let (pos, neg) = Fixed::encode(x);
+        assert!(
+            pos.max(neg) >> MAG_BITS == 0,
+            "fixed-point constant {} is out of range",
+            x
+        );
+        Fixed {
+            pos: self.constant(pos),
+            neg: self.constant(neg),
+            normalized: true,
+        }
+    }
+
+    pub fn fixed_add(&mut self, a: Fixed, b: Fixed) -> Fixed {
+        Fixed {
+            pos: self.add(a.pos, b.pos),
+            neg: self.add(a.neg, b.neg),
+            normalized: false,
+        }
+    }
+
+    /// `a * b`, normalizing both first so the products fit in a u32.
+    pub fn fixed_mul(&mut self, a: Fixed, b: Fixed) -> Fixed {
+        let (a, b) = (self.fixed_normalize(a), self.fixed_normalize(b));
+        let negatives = self.mul(a.neg, b.neg);
+        let pos = self.mul_add(a.pos, b.pos, negatives);
+        let mixed = self.mul(a.neg, b.pos);
+        let neg = self.mul_add(a.pos, b.neg, mixed);
+        Fixed {
+            pos: self.fixed_truncate(pos),
+            neg: self.fixed_truncate(neg),
+            normalized: false,
+        }
+    }
+
+    /// `a * c` for a constant `c`, which costs less than a `fixed_mul` by a `fixed_constant`.
+    /// Panics if `c` is out of range.
+    pub fn fixed_scale(&mut self, a: Fixed, c: f64) -> Fixed {
+        let (c_pos, c_neg) = Fixed::encode(c);
+        assert!(
+            c_pos.max(c_neg) >> MAG_BITS == 0,
+            "fixed-point constant {} is out of range",
+            c
+        );
+        let a = self.fixed_normalize(a);
+        let factor = self.constant(c_pos.max(c_neg));
+        let (pos, neg) = (self.mul(a.pos, factor), self.mul(a.neg, factor));
+        let (pos, neg) = if c_neg > 0 { (neg, pos) } else { (pos, neg) };
+        Fixed {
+            pos: self.fixed_truncate(pos),
+            neg: self.fixed_truncate(neg),
+            normalized: false,
+        }
+    }
+
+    /// The same value with at most one nonzero part, both range checked to `MAG_BITS` bits.
+    pub fn fixed_normalize(&mut self, a: Fixed) -> Fixed {
+        if a.normalized {
+            return a;
+        }
+        let (nonnegative, magnitude, negated) = self.fixed_sign(a);
+        let not = self.not(nonnegative);
+        let pos = self.mul(nonnegative.id(), magnitude);
+        let neg = self.mul(not.id(), negated);
+        self.assert_range(pos, MAG_BITS);
+        self.assert_range(neg, MAG_BITS);
+        Fixed {
+            pos,
+            neg,
+            normalized: true,
+        }
+    }
+
+    /// 1 if `a` is negative and 0 otherwise.
+    pub fn fixed_is_negative(&mut self, a: Fixed) -> BoolVar {
+        let (nonnegative, _, _) = self.fixed_sign(a);
+        self.not(nonnegative)
+    }
+
+    /// `max(a, 0)`, selecting the magnitude of `a` by its sign.
+    pub fn fixed_relu(&mut self, a: Fixed) -> Fixed {
+        let (nonnegative, magnitude, _) = self.fixed_sign(a);
+        let pos = self.mul(nonnegative.id(), magnitude);
+        self.assert_range(pos, MAG_BITS);
+        Fixed {
+            pos,
+            neg: self.constant(0),
+            normalized: true,
+        }
+    }
+
+    /// The value of `a`, once its parts are filled in.
+    pub fn fixed_value(&self, a: Fixed) -> Option<f64> {
+        Some(Fixed::decode(self.output(a.pos)?, self.output(a.neg)?))
+    }
+
+    /// Compares the parts of `a`, which must fit in `CMP_BITS` bits: returns whether
+    /// `pos >= neg`, and two differences, the first of which is `pos - neg` if it is and the
+    /// second `neg - pos` if it isn't.
+    ///
+    /// With `m = 2^CMP_BITS - neg`, `s = pos + m` is `pos - neg + 2^CMP_BITS`, whose bit
+    /// `CMP_BITS` is the comparison and whose low bits `low` are `pos - neg` if it is set, with
+    /// `neg - pos = 2^CMP_BITS - low` otherwise.
+    fn fixed_sign(&mut self, a: Fixed) -> (BoolVar, NodeId, NodeId) {
+        self.assert_range(a.pos, CMP_BITS);
+        self.assert_range(a.neg, CMP_BITS);
+        let power = self.constant(1 << CMP_BITS);
+        let complement = self.fixed_complement(a.neg, power);
+        let shifted = self.add(a.pos, complement);
+        let high = self.hint_fn("fixed_high", shifted);
+        let nonnegative = self.assert_bool(high);
+        let low = self.hint_fn("fixed_low", shifted);
+        self.assert_range(low, CMP_BITS);
+        let recombined = self.mul_add(high, power, low);
+        self.enforce_equal(recombined, shifted);
+        let negated = self.fixed_complement(low, power);
+        (nonnegative, low, negated)
+    }
+
+    /// `2^CMP_BITS - x`, a hint constrained by `x + complement == power`.
+    fn fixed_complement(&mut self, x: NodeId, power: NodeId) -> NodeId {
+        let complement = self.hint_fn("fixed_complement", x);
+        let sum = self.add(x, complement);
+        self.enforce_equal(sum, power);
+        complement
+    }
+
+    /// `x >> FRAC_BITS`: a hint `q` and the fraction `r`, constrained by `q * 2^FRAC_BITS + r == x`
+    /// with `r` range checked to `FRAC_BITS` bits.
+    pub(crate) fn fixed_truncate(&mut self, x: NodeId) -> NodeId {
+        let quotient = self.hint_fn("fixed_shr", x);
+        let fraction = self.hint_fn("fixed_frac", x);
+        self.assert_range(fraction, FRAC_BITS);
+        let scale = self.constant(1 << FRAC_BITS);
+        let recombined = self.mul_add(quotient, scale, fraction);
+        self.enforce_equal(recombined, x);
+        quotient
+    }
+}
@@ -0,0 +1,714 @@
+//! A FlatBuffers format for graphs, built with `--features flatbuffers`, for services that load
+//! huge circuits at startup.
+//!
+//! The schema is `schema/circuit.fbs`, also available as `SCHEMA`. Nodes are stored as columns,
+//! so a `FlatCircuit` reads them in place: opening a buffer only verifies its layout, which takes
+//! time proportional to the number of hints, labels and checks but not to the number of nodes,
+//! and `FlatCircuit::evaluate` computes a witness straight from the buffer. With `FlatFile` the
+//! buffer is a memory-mapped file, paged in by the operating system as evaluation reaches it.
+//!
+//! ```
+//! use my_graph_lib::flat::FlatCircuit;
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let y = builder.mul(x, x);
+//! builder.mark_public(y);
+//! let bytes = builder.to_flatbuffer();
+//! let circuit = FlatCircuit::from_bytes(&bytes).unwrap();
+//! let input = circuit.inputs()[0];
+//! let witness = circuit.evaluate(&[(input, 7)]).unwrap();
+//! assert_eq!(witness.values[circuit.public_outputs()[0]], Some(49));
+//! ```
+//!
+//! Constraint messages, node locations and metadata are only kept by the JSON format. Aliases are
+//! stored as equality constraints after the others, between every aliased node and the
+//! representative of its class.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use flatbuffers::{
+    FlatBufferBuilder, Follow, ForwardsUOffset, InvalidFlatbuffer, Table, Vector, Verifiable,
+    Verifier, VerifierOptions, WIPOffset,
+};
+use memmap2::Mmap;
+
+use crate::checks::Check;
+use crate::error::{BuildError, EvalError, SerdeError, Value};
+use crate::storage::Kind;
+use crate::{Builder, HintFn, Witness, OPERATION};
+
+/// The schema the buffers of this module implement.
+pub const SCHEMA: &str = include_str!("../schema/circuit.fbs");
+
+/// The `file_identifier` of the schema, at bytes 4 to 8 of every buffer.
+pub const IDENTIFIER: &str = "CIRC";
+
+const INPUT: u8 = 0;
+const CONSTANT: u8 = 1;
+const ADD: u8 = 2;
+const MUL: u8 = 3;
+const MUL_ADD: u8 = 4;
+const HINT: u8 = 5;
+
+const HAS_VALUE: u8 = 1;
+const SECRET: u8 = 2;
+const PUBLIC: u8 = 4;
+
+const LESS_THAN: u8 = 0;
+const IN_SET: u8 = 1;
+const PERMUTATION: u8 = 2;
+const RANGE: u8 = 3;
+
+/// Declares a table of the schema: a view of a verified buffer with an accessor per field, in
+/// the order of the schema, returning the field's default when it is absent.
+macro_rules! table {
+    ($name:ident { $($field:ident: $ty:ty = $slot:literal,)* }) => {
+        #[derive(Clone, Copy)]
+        struct $name<'a>(Table<'a>);
+
+        impl<'a> Follow<'a> for $name<'a> {
+            type Inner = Self;
+
+            unsafe fn follow(buf: &'a [u8], loc: usize) -> Self {
+                // Safety: the caller guarantees a table of this type starts at `loc`.
+                $name(unsafe { Table::new(buf, loc) })
+            }
+        }
+
+        impl<'a> Verifiable for $name<'a> {
+            fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+                v.visit_table(pos)?
+                    $(.visit_field::<$ty>(stringify!($field), $slot, false)?)*
+                    .finish();
+                Ok(())
+            }
+        }
+
+        impl<'a> $name<'a> {
+            $(
+                fn $field(&self) -> <$ty as Follow<'a>>::Inner {
+                    // Safety: tables are only reached through `FlatCircuit::from_bytes`, which
+                    // verified that every field holds the type it is read as.
+                    unsafe { self.0.get::<$ty>($slot, None) }.unwrap_or_default()
+                }
+            )*
+        }
+    };
+}
+
+table!(CircuitTable {
+    kinds: ForwardsUOffset<Vector<'a, u8>> = 4,
+    a: ForwardsUOffset<Vector<'a, u32>> = 6,
+    b: ForwardsUOffset<Vector<'a, u32>> = 8,
+    c: ForwardsUOffset<Vector<'a, u32>> = 10,
+    values: ForwardsUOffset<Vector<'a, u32>> = 12,
+    flags: ForwardsUOffset<Vector<'a, u8>> = 14,
+    hints: ForwardsUOffset<Vector<'a, ForwardsUOffset<HintTable<'a>>>> = 16,
+    labels: ForwardsUOffset<Vector<'a, ForwardsUOffset<LabelTable<'a>>>> = 18,
+    equal_a: ForwardsUOffset<Vector<'a, u32>> = 20,
+    equal_b: ForwardsUOffset<Vector<'a, u32>> = 22,
+    warnings: ForwardsUOffset<Vector<'a, u32>> = 24,
+    checks: ForwardsUOffset<Vector<'a, ForwardsUOffset<CheckTable<'a>>>> = 26,
+});
+
+table!(HintTable {
+    node: u32 = 4,
+    function: ForwardsUOffset<&'a str> = 6,
+});
+
+table!(LabelTable {
+    node: u32 = 4,
+    name: ForwardsUOffset<&'a str> = 6,
+});
+
+table!(CheckTable {
+    kind: u8 = 4,
+    a: ForwardsUOffset<Vector<'a, u32>> = 6,
+    b: ForwardsUOffset<Vector<'a, u32>> = 8,
+    bits: u32 = 10,
+    set: ForwardsUOffset<Vector<'a, u32>> = 12,
+});
+
+fn invalid(message: String) -> SerdeError {
+    SerdeError::InvalidFlatbuffer(message)
+}
+
+/// Converts a node index read by `at`, which must refer to one of the first `len` nodes.
+fn index(node: u32, len: usize, at: impl FnOnce() -> String) -> Result<usize, SerdeError> {
+    let index = node as usize;
+    if index < len {
+        Ok(index)
+    } else {
+        Err(SerdeError::IndexOutOfRange {
+            at: at(),
+            index,
+            len,
+        })
+    }
+}
+
+/// Position of `node` in a vector of `len` elements sorted by the node `node_at` returns.
+fn position(len: usize, node_at: impl Fn(usize) -> u32, node: usize) -> Option<usize> {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        match (node_at(middle) as usize).cmp(&node) {
+            std::cmp::Ordering::Less => low = middle + 1,
+            std::cmp::Ordering::Greater => high = middle,
+            std::cmp::Ordering::Equal => return Some(middle),
+        }
+    }
+    None
+}
+
+/// The value of `operand`, which must be a node before `node` with a value.
+fn operand(values: &[Option<u32>], node: usize, operand: u32) -> Result<u32, EvalError> {
+    let operand = operand as usize;
+    values[..node]
+        .get(operand)
+        .copied()
+        .flatten()
+        .ok_or(EvalError::MissingValue { node, operand })
+}
+
+/// A graph read in place from a FlatBuffer written by `Builder::to_flatbuffer`.
+pub struct FlatCircuit<'a> {
+    circuit: CircuitTable<'a>,
+    kinds: Vector<'a, u8>,
+    a: Vector<'a, u32>,
+    b: Vector<'a, u32>,
+    c: Vector<'a, u32>,
+    values: Vector<'a, u32>,
+    flags: Vector<'a, u8>,
+    hint_fns: HashMap<String, HintFn>,
+}
+
+impl<'a> FlatCircuit<'a> {
+    /// Verifies that `bytes` holds a circuit in the layout of `SCHEMA`. Whether nodes only refer
+    /// to nodes before them is only checked as they are read, or by `validate`.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<FlatCircuit<'a>, SerdeError> {
+        if bytes.len() < 8 || !flatbuffers::buffer_has_identifier(bytes, IDENTIFIER, false) {
+            return Err(invalid(format!("missing file identifier {}", IDENTIFIER)));
+        }
+        let options = VerifierOptions {
+            max_tables: usize::MAX,
+            max_apparent_size: usize::MAX,
+            ..VerifierOptions::default()
+        };
+        let circuit = flatbuffers::root_with_opts::<CircuitTable>(&options, bytes)?;
+        let kinds = circuit.kinds();
+        let len = kinds.len();
+        let columns = [
+            ("a", circuit.a().len()),
+            ("b", circuit.b().len()),
+            ("c", circuit.c().len()),
+            ("values", circuit.values().len()),
+            ("flags", circuit.flags().len()),
+        ];
+        if let Some((column, column_len)) = columns.into_iter().find(|&(_, l)| l != len) {
+            return Err(invalid(format!(
+                "column {} has {} elements for {} nodes",
+                column, column_len, len
+            )));
+        }
+        if circuit.equal_a().len() != circuit.equal_b().len() {
+            return Err(invalid("equal_a and equal_b differ in length".to_string()));
+        }
+        Ok(FlatCircuit {
+            circuit,
+            kinds,
+            a: circuit.a(),
+            b: circuit.b(),
+            c: circuit.c(),
+            values: circuit.values(),
+            flags: circuit.flags(),
+            hint_fns: Builder::new().hint_fns,
+        })
+    }
+
+    /// Checks every node, constraint and check like `to_builder`, without copying the graph, in
+    /// time proportional to its size. Buffers from untrusted sources should be validated before
+    /// `evaluate` or `is_satisfied`, which only reject a bad node when they reach it.
+    pub fn validate(&self) -> Result<(), SerdeError> {
+        let len = self.len();
+        for node in 0..len {
+            let operands = match self.kinds.get(node) {
+                INPUT => 0,
+                CONSTANT if self.value(node).is_none() => {
+                    return Err(invalid(format!("constant {} has no value", node)))
+                }
+                CONSTANT => 0,
+                ADD | MUL => 2,
+                MUL_ADD => 3,
+                HINT => 1,
+                kind => return Err(invalid(format!("node {} has unknown kind {}", node, kind))),
+            };
+            let columns = [self.a.get(node), self.b.get(node), self.c.get(node)];
+            for operand in columns.into_iter().take(operands) {
+                index(operand, node, || format!("node {}", node))?;
+            }
+        }
+        for label in self.circuit.labels() {
+            index(label.node(), len, || "a label".to_string())?;
+        }
+        let (equal_a, equal_b) = (self.circuit.equal_a(), self.circuit.equal_b());
+        for constraint in 0..equal_a.len() {
+            for node in [equal_a.get(constraint), equal_b.get(constraint)] {
+                index(node, len, || format!("constraint {}", constraint))?;
+            }
+        }
+        for warning in self.circuit.warnings() {
+            index(warning, equal_a.len(), || "a warning".to_string())?;
+        }
+        for check in 0..self.circuit.checks().len() {
+            self.check(check)?;
+        }
+        Ok(())
+    }
+
+    /// Number of nodes.
+    pub fn len(&self) -> usize {
+        self.kinds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty()
+    }
+
+    /// Indices of the input nodes, in order.
+    pub fn inputs(&self) -> Vec<usize> {
+        (0..self.len())
+            .filter(|&node| self.kinds.get(node) == INPUT)
+            .collect()
+    }
+
+    /// Indices of the nodes marked public, in order.
+    pub fn public_outputs(&self) -> Vec<usize> {
+        (0..self.len())
+            .filter(|&node| self.flags.get(node) & PUBLIC != 0)
+            .collect()
+    }
+
+    /// The value stored for a node: a constant, a fixed hint, or an output filled in before the
+    /// graph was written.
+    pub fn value(&self, node: usize) -> Option<u32> {
+        (self.flags.get(node) & HAS_VALUE != 0).then(|| self.values.get(node))
+    }
+
+    pub fn is_secret(&self, node: usize) -> bool {
+        self.flags.get(node) & SECRET != 0
+    }
+
+    pub fn label(&self, node: usize) -> Option<&'a str> {
+        let labels = self.circuit.labels();
+        position(labels.len(), |i| labels.get(i).node(), node).map(|i| labels.get(i).name())
+    }
+
+    /// The name of the hint function computing a hint node, if it isn't a fixed value.
+    pub fn hint_function(&self, node: usize) -> Option<&'a str> {
+        let hints = self.circuit.hints();
+        position(hints.len(), |i| hints.get(i).node(), node).map(|i| hints.get(i).function())
+    }
+
+    /// Registers a hint function for the hint nodes naming it, as `Builder::register_hint`
+    /// does. `sqrt` and `not` are registered from the start.
+    pub fn register_hint(&mut self, name: &str, f: HintFn) {
+        self.hint_fns.insert(name.to_string(), f);
+    }
+
+    /// Computes the value of every node from the values of `inputs`, by index, without
+    /// copying the graph out of the buffer. Inputs not in `inputs` keep the value stored for
+    /// them, if any.
+    pub fn evaluate(&self, inputs: &[(usize, u32)]) -> Result<Witness, EvalError> {
+        let len = self.len();
+        let mut values: Vec<Option<u32>> = (0..len).map(|node| self.value(node)).collect();
+        for &(node, value) in inputs {
+            if node >= len || self.kinds.get(node) != INPUT {
+                return Err(BuildError::NotAnInput { node }.into());
+            }
+            values[node] = Some(value);
+        }
+        for node in 0..len {
+            let (a, b) = (self.a.get(node), self.b.get(node));
+            let output = match self.kinds.get(node) {
+                kind @ (ADD | MUL) => {
+                    let op = if kind == ADD {
+                        OPERATION::ADD
+                    } else {
+                        OPERATION::MUL
+                    };
+                    let (a_val, b_val) = (operand(&values, node, a)?, operand(&values, node, b)?);
+                    op.checked_apply(a_val, b_val).ok_or(EvalError::Overflow {
+                        node,
+                        op: op.symbol(),
+                        a: self.redact(a as usize, a_val),
+                        b: self.redact(b as usize, b_val),
+                    })?
+                }
+                MUL_ADD => {
+                    let c = self.c.get(node);
+                    let (a_val, b_val) = (operand(&values, node, a)?, operand(&values, node, b)?);
+                    let c_val = operand(&values, node, c)?;
+                    let product = a_val.checked_mul(b_val).ok_or(EvalError::Overflow {
+                        node,
+                        op: "*",
+                        a: self.redact(a as usize, a_val),
+                        b: self.redact(b as usize, b_val),
+                    })?;
+                    product.checked_add(c_val).ok_or(EvalError::Overflow {
+                        node,
+                        op: "+",
+                        a: self.redact(node, product),
+                        b: self.redact(c as usize, c_val),
+                    })?
+                }
+                HINT => {
+                    let Some(name) = self.hint_function(node) else {
+                        continue;
+                    };
+                    let f = self
+                        .hint_fns
+                        .get(name)
+                        .ok_or_else(|| BuildError::UnknownHint {
+                            name: name.to_string(),
+                        })?;
+                    f(operand(&values, node, a)?)
+                }
+                _ => continue,
+            };
+            values[node] = Some(output);
+        }
+        let redacted = (0..len).filter(|&node| self.is_secret(node)).collect();
+        Ok(Witness { values, redacted })
+    }
+
+    /// Returns true if `witness` has a value for every node read by a constraint and satisfies
+    /// every equality constraint, except the ones that only warn, and every check.
+    pub fn is_satisfied(&self, witness: &Witness) -> bool {
+        let value = |node: usize| witness.values.get(node).copied().flatten();
+        if witness.values.len() != self.len() {
+            return false;
+        }
+        let (equal_a, equal_b) = (self.circuit.equal_a(), self.circuit.equal_b());
+        let warnings = self.circuit.warnings();
+        let warns =
+            |constraint: usize| position(warnings.len(), |i| warnings.get(i), constraint).is_some();
+        let equalities_hold = (0..equal_a.len()).all(|constraint| {
+            let (a, b) = (
+                value(equal_a.get(constraint) as usize),
+                value(equal_b.get(constraint) as usize),
+            );
+            warns(constraint) || (a.is_some() && a == b)
+        });
+        equalities_hold
+            && (0..self.circuit.checks().len())
+                .all(|check| self.check(check).is_ok_and(|check| check.holds(value)))
+    }
+
+    /// Copies the graph into a builder, keeping node indices, with the hint functions registered
+    /// here.
+    pub fn to_builder(&self) -> Result<Builder, SerdeError> {
+        let mut builder = Builder::new();
+        builder.hint_fns.clone_from(&self.hint_fns);
+        for node in 0..self.len() {
+            let value = self.value(node);
+            let [a, b, c] = [self.a.get(node), self.b.get(node), self.c.get(node)];
+            let operand = |operand| index(operand, node, || format!("node {}", node));
+            let graph = &mut builder.graph;
+            match self.kinds.get(node) {
+                INPUT => {
+                    graph.push(Kind::Input, [0, 0], value);
+                    builder.input_nodes.push(node);
+                }
+                CONSTANT => {
+                    let value =
+                        value.ok_or_else(|| invalid(format!("constant {} has no value", node)))?;
+                    graph.push(Kind::Constant, [0, 0], Some(value));
+                }
+                ADD => {
+                    graph.push(Kind::Add, [operand(a)?, operand(b)?], value);
+                }
+                MUL => {
+                    graph.push(Kind::Mul, [operand(a)?, operand(b)?], value);
+                }
+                MUL_ADD => {
+                    graph.push_mul_add([operand(a)?, operand(b)?, operand(c)?], value);
+                }
+                HINT => {
+                    graph.push(Kind::Hint, [operand(a)?, 0], value);
+                    if let Some(name) = self.hint_function(node) {
+                        builder.hint_calls.insert(node, name.to_string());
+                    }
+                }
+                kind => return Err(invalid(format!("node {} has unknown kind {}", node, kind))),
+            }
+            if self.is_secret(node) {
+                builder.graph.mark_secret(node);
+            }
+            if self.flags.get(node) & PUBLIC != 0 {
+                builder.public.insert(node);
+            }
+        }
+
+        let len = self.len();
+        for label in self.circuit.labels() {
+            builder.labels.insert(
+                index(label.node(), len, || "a label".to_string())?,
+                label.name().to_string(),
+            );
+        }
+        let (equal_a, equal_b) = (self.circuit.equal_a(), self.circuit.equal_b());
+        let at = |constraint| move || format!("constraint {}", constraint);
+        for constraint in 0..equal_a.len() {
+            builder.constraints.push((
+                index(equal_a.get(constraint), len, at(constraint))?,
+                index(equal_b.get(constraint), len, at(constraint))?,
+            ));
+        }
+        for warning in self.circuit.warnings() {
+            builder
+                .soft_constraints
+                .insert(index(warning, builder.constraints.len(), || {
+                    "a warning".to_string()
+                })?);
+        }
+        for check in 0..self.circuit.checks().len() {
+            let check = self.check(check)?;
+            builder.checks.push(check);
+        }
+        Ok(builder)
+    }
+
+    /// Reads the check at index `check`.
+    fn check(&self, check: usize) -> Result<Check, SerdeError> {
+        let table = self.circuit.checks().get(check);
+        let len = self.len();
+        let nodes = |nodes: Vector<'a, u32>| -> Result<Vec<usize>, SerdeError> {
+            nodes
+                .iter()
+                .map(|node| index(node, len, || format!("check {}", check)))
+                .collect()
+        };
+        let (a, b) = (nodes(table.a())?, nodes(table.b())?);
+        let single = |nodes: &[usize]| match *nodes {
+            [node] => Ok(node),
+            _ => Err(invalid(format!(
+                "check {} has {} nodes where 1 is expected",
+                check,
+                nodes.len()
+            ))),
+        };
+        let parsed = match table.kind() {
+            LESS_THAN => Check::LessThan {
+                a: single(&a)?,
+                b: single(&b)?,
+                bits: table.bits(),
+            },
+            IN_SET => Check::InSet {
+                node: single(&a)?,
+                set: table
+                    .set()
+                    .iter()
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect(),
+            },
+            PERMUTATION => Check::Permutation { a, b },
+            RANGE => Check::Range {
+                node: single(&a)?,
+                bits: table.bits(),
+            },
+            kind => {
+                return Err(invalid(format!(
+                    "check {} has unknown kind {}",
+                    check, kind
+                )))
+            }
+        };
+        parsed.validate(check, len)?;
+        Ok(parsed)
+    }
+
+    fn redact(&self, node: usize, value: u32) -> Value {
+        Value::new(value, self.is_secret(node))
+    }
+}
+
+/// A FlatBuffer file mapped into memory, read-only.
+pub struct FlatFile {
+    map: Mmap,
+}
+
+impl FlatFile {
+    /// Maps a file written by `Builder::write_flatbuffer`. Nothing is read until `circuit` is
+    /// called.
+    pub fn open(path: &Path) -> io::Result<FlatFile> {
+        let file = File::open(path)?;
+        // Safety: the mapping is only valid as long as no other process truncates or rewrites the
+        // file, which is the usual contract for memory-mapped files.
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(FlatFile { map })
+    }
+
+    /// The circuit in the file, see `FlatCircuit::from_bytes`.
+    pub fn circuit(&self) -> Result<FlatCircuit<'_>, SerdeError> {
+        FlatCircuit::from_bytes(&self.map)
+    }
+}
+
+impl Builder {
+    /// Serializes the graph (including any filled in outputs) to a FlatBuffer of `SCHEMA`.
+    /// FlatBuffers are limited to 2 GiB, about 100 million nodes. Panics if the graph has custom
+    /// nodes, which `SCHEMA` has no kind for: lower them with `lower_custom_ops` first.
+    pub fn to_flatbuffer(&self) -> Vec<u8> {
+        let len = self.graph.len();
+        let mut kinds = Vec::with_capacity(len);
+        let mut operands = [(); 3].map(|_| Vec::with_capacity(len));
+        let mut values = Vec::with_capacity(len);
+        let mut flags = Vec::with_capacity(len);
+        for id in 0..len {
+            let (kind, node_operands) = match self.graph.kind(id) {
+                Kind::Input => (INPUT, [0; 3]),
+                Kind::Constant => (CONSTANT, [0; 3]),
+                Kind::Add | Kind::Mul => {
+                    let (a, b) = self.graph.operands(id).unwrap();
+                    let kind = if self.graph.kind(id) == Kind::Add {
+                        ADD
+                    } else {
+                        MUL
+                    };
+                    (kind, [a, b, 0])
+                }
+                Kind::MulAdd => (MUL_ADD, self.graph.mul_add(id).unwrap()),
+                Kind::Hint => (HINT, [self.graph.linked(id).unwrap(), 0, 0]),
+                Kind::Custom => panic!(
+                    "custom node {} can't be stored in a FlatBuffer, lower it first",
+                    id
+                ),
+            };
+            kinds.push(kind);
+            for (column, operand) in operands.iter_mut().zip(node_operands) {
+                column.push(operand as u32);
+            }
+            let value = self.graph.output(id);
+            values.push(value.unwrap_or(0));
+            let mut node_flags = 0;
+            if value.is_some() {
+                node_flags |= HAS_VALUE;
+            }
+            if self.graph.is_secret(id) {
+                node_flags |= SECRET;
+            }
+            if self.public.contains(&id) {
+                node_flags |= PUBLIC;
+            }
+            flags.push(node_flags);
+        }
+
+        let mut fbb = FlatBufferBuilder::new();
+        let hints: Vec<WIPOffset<HintTable>> = self
+            .hint_calls
+            .iter()
+            .map(|(&node, function)| {
+                let function = fbb.create_string(function);
+                let start = fbb.start_table();
+                fbb.push_slot_always(4, node as u32);
+                fbb.push_slot_always(6, function);
+                WIPOffset::new(fbb.end_table(start).value())
+            })
+            .collect();
+        let labels: Vec<WIPOffset<LabelTable>> = self
+            .labels
+            .iter()
+            .map(|(&node, name)| {
+                let name = fbb.create_string(name);
+                let start = fbb.start_table();
+                fbb.push_slot_always(4, node as u32);
+                fbb.push_slot_always(6, name);
+                WIPOffset::new(fbb.end_table(start).value())
+            })
+            .collect();
+        let checks: Vec<WIPOffset<CheckTable>> = self
+            .checks
+            .iter()
+            .map(|check| {
+                let (kind, a, b, bits, set): (_, Vec<usize>, Vec<usize>, _, &[u32]) = match check {
+                    Check::LessThan { a, b, bits } => (LESS_THAN, vec![*a], vec![*b], *bits, &[]),
+                    Check::InSet { node, set } => (IN_SET, vec![*node], Vec::new(), 0, set),
+                    Check::Permutation { a, b } => (PERMUTATION, a.clone(), b.clone(), 0, &[]),
+                    Check::Range { node, bits } => (RANGE, vec![*node], Vec::new(), *bits, &[]),
+                };
+                let nodes_of = |nodes: Vec<usize>| -> Vec<u32> {
+                    nodes.into_iter().map(|node| node as u32).collect()
+                };
+                let a = fbb.create_vector(&nodes_of(a));
+                let b = fbb.create_vector(&nodes_of(b));
+                let set = fbb.create_vector(set);
+                let start = fbb.start_table();
+                fbb.push_slot_always(4, kind);
+                fbb.push_slot_always(6, a);
+                fbb.push_slot_always(8, b);
+                fbb.push_slot_always(10, bits);
+                fbb.push_slot_always(12, set);
+                WIPOffset::new(fbb.end_table(start).value())
+            })
+            .collect();
+        let (equal_a, equal_b): (Vec<u32>, Vec<u32>) = self
+            .constraints
+            .iter()
+            .copied()
+            .chain(self.alias_pairs())
+            .map(|(a, b)| (a as u32, b as u32))
+            .unzip();
+        let warnings: Vec<u32> = self
+            .soft_constraints
+            .iter()
+            .map(|&constraint| constraint as u32)
+            .collect();
+
+        let [a, b, c] = operands.map(|column| fbb.create_vector(&column));
+        let kinds = fbb.create_vector(&kinds);
+        let values = fbb.create_vector(&values);
+        let flags = fbb.create_vector(&flags);
+        let hints = fbb.create_vector(&hints);
+        let labels = fbb.create_vector(&labels);
+        let equal_a = fbb.create_vector(&equal_a);
+        let equal_b = fbb.create_vector(&equal_b);
+        let warnings = fbb.create_vector(&warnings);
+        let checks = fbb.create_vector(&checks);
+        let start = fbb.start_table();
+        fbb.push_slot_always(4, kinds);
+        fbb.push_slot_always(6, a);
+        fbb.push_slot_always(8, b);
+        fbb.push_slot_always(10, c);
+        fbb.push_slot_always(12, values);
+        fbb.push_slot_always(14, flags);
+        fbb.push_slot_always(16, hints);
+        fbb.push_slot_always(18, labels);
+        fbb.push_slot_always(20, equal_a);
+        fbb.push_slot_always(22, equal_b);
+        fbb.push_slot_always(24, warnings);
+        fbb.push_slot_always(26, checks);
+        let root: WIPOffset<CircuitTable> = WIPOffset::new(fbb.end_table(start).value());
+        fbb.finish(root, Some(IDENTIFIER));
+        fbb.finished_data().to_vec()
+    }
+
+    /// Writes `to_flatbuffer` to a file, to be opened with `FlatFile::open`.
+    pub fn write_flatbuffer(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, self.to_flatbuffer())
+    }
+
+    /// Deserializes a graph produced by `to_flatbuffer`, checking that every node only refers to
+    /// nodes before it. With `--features zstd`, the bytes may also be compressed.
+    pub fn from_flatbuffer(bytes: &[u8]) -> Result<Builder, SerdeError> {
+        #[cfg(feature = "zstd")]
+        let bytes = &*crate::compress::decompress(bytes)?;
+        FlatCircuit::from_bytes(bytes)?.to_builder()
+    }
+}
@@ -0,0 +1,117 @@
+//! A chainable way to build expressions, without operator overloading or macros.
+//!
+//! `Builder::var` starts an `Expr` at a node, and each method adds a node computed from the
+//! current one and makes it current:
+//!
+//! ```
+//! use my_graph_lib::Builder;
+//!
+//! let mut b = Builder::new();
+//! let x = b.init();
+//! let y = b.init();
+//! // x * x + 5 + x == y
+//! let lhs = b.var(x).mul(x).add_const(5).add(x).assert_eq(y);
+//! b.fill_inputs(&[(x, 3), (y, 17)]);
+//! assert_eq!(b.output(lhs), Some(17));
+//! assert!(b.is_satisfied());
+//! ```
+//!
+//! Operands are anything that converts into a `NodeId`, such as typed wires. Constants are added
+//! as new nodes.
+
+use crate::{Builder, NodeId};
+
+/// An expression being built, see `Builder::var`.
+pub struct Expr<'a> {
+    builder: &'a mut Builder,
+    node: NodeId,
+}
+
+impl Builder {
+    /// Starts an expression at `node`. Panics if the node doesn't belong to this builder.
+    pub fn var(&mut self, node: impl Into<NodeId>) -> Expr<'_> {
+        let node = node.into();
+        self.index(node);
+        Expr {
+            builder: self,
+            node,
+        }
+    }
+}
+
+// `add` and `mul` take a node and extend the expression, unlike the operator traits.
+#[allow(clippy::should_implement_trait)]
+impl Expr<'_> {
+    /// The current node.
+    pub fn id(&self) -> NodeId {
+        self.node
+    }
+
+    /// Adds `other` to the current node.
+    pub fn add(mut self, other: impl Into<NodeId>) -> Self {
+        self.node = self.builder.add(self.node, other.into());
+        self
+    }
+
+    /// Multiplies the current node by `other`.
+    pub fn mul(mut self, other: impl Into<NodeId>) -> Self {
+        self.node = self.builder.mul(self.node, other.into());
+        self
+    }
+
+    /// Adds a constant to the current node.
+    pub fn add_const(self, value: u32) -> Self {
+        let value = self.builder.constant(value);
+        self.add(value)
+    }
+
+    /// Multiplies the current node by a constant.
+    pub fn mul_const(self, value: u32) -> Self {
+        let value = self.builder.constant(value);
+        self.mul(value)
+    }
+
+    /// Multiplies the current node by `factor` and adds `addend`, in a single node.
+    pub fn mul_add(mut self, factor: impl Into<NodeId>, addend: impl Into<NodeId>) -> Self {
+        self.node = self
+            .builder
+            .mul_add(self.node, factor.into(), addend.into());
+        self
+    }
+
+    /// Squares the current node.
+    pub fn square(mut self) -> Self {
+        self.node = self.builder.square(self.node);
+        self
+    }
+
+    /// Doubles the current node.
+    pub fn double(mut self) -> Self {
+        self.node = self.builder.double(self.node);
+        self
+    }
+
+    /// Computes a hint from the current node with the hint function `name`.
+    pub fn hint(mut self, name: &str) -> Self {
+        self.node = self.builder.hint_fn(name, self.node);
+        self
+    }
+
+    /// Labels the current node.
+    pub fn label(self, label: &str) -> Self {
+        self.builder.set_label(self.node, label);
+        self
+    }
+
+    /// Records that the current node must equal `other`, and returns the current node.
+    pub fn assert_eq(self, other: impl Into<NodeId>) -> NodeId {
+        self.builder.enforce_equal(self.node, other.into());
+        self.node
+    }
+}
+
+impl From<Expr<'_>> for NodeId {
+    fn from(expr: Expr<'_>) -> NodeId {
+        expr.node
+    }
+}
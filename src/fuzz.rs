@@ -0,0 +1,186 @@
+//! Random circuits for fuzzing, built with `--features arbitrary`.
+//!
+//! A `FuzzCircuit` is a sequence of builder operations plus input values that `arbitrary` can
+//! generate from raw fuzzer bytes. Operands are taken modulo the number of nodes created so far,
+//! so every sequence builds a valid graph. `FuzzCircuit::run` evaluates the graph, checks its
+//! constraints and round-trips it through JSON, panicking only when the library misbehaves:
+//!
+//! ```ignore
+//! fuzz_target!(|circuit: FuzzCircuit| circuit.run());
+//! ```
+//!
+//! `load` fuzzes the deserializers instead, with raw bytes that are mostly not a valid graph:
+//!
+//! ```ignore
+//! fuzz_target!(|bytes: &[u8]| fuzz::load(bytes));
+//! ```
+
+use arbitrary::Arbitrary;
+
+use crate::eval::EvalOptions;
+use crate::{Builder, NodeId};
+
+/// One operation on a builder.
+#[derive(Debug, Clone, Arbitrary)]
+pub enum FuzzOp {
+    Init,
+    PrivateInput,
+    Constant(u32),
+    Add(u32, u32),
+    Mul(u32, u32),
+    /// A hint with a fixed value, linked to a node.
+    Hint(u32, u32),
+    /// A hint computed by `sqrt`.
+    Sqrt(u32),
+    EnforceEqual(u32, u32),
+    MarkPublic(u32),
+}
+
+/// A sequence of builder operations and the values of the inputs they create.
+#[derive(Debug, Clone, Arbitrary)]
+pub struct FuzzCircuit {
+    pub ops: Vec<FuzzOp>,
+    /// Input values, in the order the inputs are created. Missing values are 0.
+    pub inputs: Vec<u32>,
+}
+
+impl FuzzCircuit {
+    /// Builds the graph. Operations that need a node are skipped while the graph is empty.
+    pub fn build(&self) -> Builder {
+        let mut builder = Builder::new();
+        for op in &self.ops {
+            let len = builder.len() as u32;
+            let node = |index: u32| builder.node_id((index % len.max(1)) as usize);
+            match *op {
+                FuzzOp::Init => {
+                    builder.init();
+                }
+                FuzzOp::PrivateInput => {
+                    builder.private_input();
+                }
+                FuzzOp::Constant(value) => {
+                    builder.constant(value);
+                }
+                FuzzOp::Add(a, b) => {
+                    if let (Some(a), Some(b)) = (node(a), node(b)) {
+                        builder.add(a, b);
+                    }
+                }
+                FuzzOp::Mul(a, b) => {
+                    if let (Some(a), Some(b)) = (node(a), node(b)) {
+                        builder.mul(a, b);
+                    }
+                }
+                FuzzOp::Hint(value, a) => {
+                    if let Some(a) = node(a) {
+                        builder.hint(value, a);
+                    }
+                }
+                FuzzOp::Sqrt(a) => {
+                    if let Some(a) = node(a) {
+                        builder.hint_fn("sqrt", a);
+                    }
+                }
+                FuzzOp::EnforceEqual(a, b) => {
+                    if let (Some(a), Some(b)) = (node(a), node(b)) {
+                        builder.enforce_equal(a, b);
+                    }
+                }
+                FuzzOp::MarkPublic(a) => {
+                    if let Some(a) = node(a) {
+                        builder.mark_public(a);
+                    }
+                }
+            }
+        }
+        builder
+    }
+
+    /// Pairs the inputs of a graph built by `build` with their values.
+    pub fn assignment(&self, builder: &Builder) -> Vec<(NodeId, u32)> {
+        let values = self.inputs.iter().copied().chain(std::iter::repeat(0));
+        builder.inputs().into_iter().zip(values).collect()
+    }
+
+    /// Builds, evaluates and checks the graph and round-trips it through JSON, panicking if the
+    /// results disagree. Evaluation errors such as overflows are expected and ignored.
+    pub fn run(&self) {
+        let mut builder = self.build();
+        let assignment = self.assignment(&builder);
+        let filled = builder
+            .fill_inputs_with(&assignment, EvalOptions::new())
+            .is_ok();
+        let satisfied = builder.is_satisfied();
+        match builder.check() {
+            Ok(()) => assert!(satisfied),
+            Err(violation) => {
+                assert!(!satisfied);
+                let _ = builder.explain(&violation).to_string();
+            }
+        }
+
+        let json = builder.to_json();
+        let loaded = Builder::from_json(&json).expect("serialized graph does not load");
+        assert_eq!(loaded.to_json(), json);
+        assert_eq!(loaded.witness(), builder.witness());
+        assert_eq!(loaded.fingerprint(), builder.fingerprint());
+        assert_eq!(loaded.is_satisfied(), satisfied);
+
+        if filled {
+            let mut refilled = loaded;
+            refilled.reset();
+            refilled
+                .fill_inputs_with(&assignment_for(&refilled, &assignment), EvalOptions::new())
+                .expect("evaluation fails after a JSON round trip");
+            assert_eq!(refilled.witness(), builder.witness());
+        }
+    }
+}
+
+/// Moves an assignment over to a copy of the builder it was made for.
+fn assignment_for(builder: &Builder, assignment: &[(NodeId, u32)]) -> Vec<(NodeId, u32)> {
+    assignment
+        .iter()
+        .map(|&(id, value)| (builder.node_id(id.index()).unwrap(), value))
+        .collect()
+}
+
+/// Feeds `bytes` to `Builder::from_json` and, when their features are enabled, to
+/// `Builder::from_protobuf` and `FlatCircuit::from_bytes`, then evaluates, checks and writes back
+/// the graphs they accept. Malformed input must be rejected with an error: this panics if a
+/// deserializer, or a graph one accepted, panics instead.
+pub fn load(bytes: &[u8]) {
+    if let Ok(Ok(builder)) = std::str::from_utf8(bytes).map(Builder::from_json) {
+        exercise(builder);
+    }
+    #[cfg(feature = "protobuf")]
+    if let Ok(builder) = Builder::from_protobuf(bytes) {
+        exercise(builder);
+    }
+    #[cfg(feature = "flatbuffers")]
+    if let Ok(circuit) = crate::flat::FlatCircuit::from_bytes(bytes) {
+        let inputs: Vec<(usize, u32)> = circuit.inputs().into_iter().map(|id| (id, 1)).collect();
+        if let Ok(witness) = circuit.evaluate(&inputs) {
+            circuit.is_satisfied(&witness);
+        }
+        if circuit.validate().is_ok() {
+            exercise(
+                circuit
+                    .to_builder()
+                    .expect("a validated FlatBuffer does not load"),
+            );
+        }
+    }
+}
+
+/// Evaluates a loaded graph with every input set to 1, checks it and round-trips it through JSON.
+fn exercise(mut builder: Builder) {
+    let assignment: Vec<(NodeId, u32)> = builder.inputs().into_iter().map(|id| (id, 1)).collect();
+    let _ = builder.fill_inputs_with(&assignment, EvalOptions::new());
+    if let Err(violation) = builder.check() {
+        let _ = builder.explain(&violation).to_string();
+    }
+    let json = builder.to_json();
+    let loaded = Builder::from_json(&json).expect("a loaded graph does not load once written back");
+    assert_eq!(loaded.to_json(), json);
+}
@@ -0,0 +1,80 @@
+//! Gadgets: helpers that build common patterns out of several nodes and constraints.
+
+use crate::wires::BoolVar;
+use crate::{Builder, NodeId};
+
+impl Builder {
+    /// Returns the negation `1 - cond` of a boolean, a hint constrained by `cond + not == 1`.
+    pub fn not(&mut self, cond: BoolVar) -> BoolVar {
+        let not = self.hint_fn("not", cond.0);
+        let one = self.constant(1);
+        let sum = self.add(cond.0, not);
+        self.enforce_equal(sum, one);
+        BoolVar(not)
+    }
+
+    /// Returns `a` if the boolean `cond` is 1 and `b` if it is 0, computed as
+    /// `cond * a + (1 - cond) * b`.
+    pub fn select(&mut self, cond: BoolVar, a: NodeId, b: NodeId) -> NodeId {
+        let not = self.not(cond);
+        self.mux(cond, not, a, b)
+    }
+
+    /// Builds both branches of a conditional and selects each of their outputs by the boolean
+    /// `cond`: the i-th returned node is the i-th output of `then` if `cond` is 1 and of
+    /// `otherwise` if it is 0. Outputs that are the same node in both branches are returned as is.
+    ///
+    /// Both branches are part of the circuit whatever the value of `cond`, so they are both
+    /// evaluated, and constraints recorded in either one must hold in both cases. Panics if the
+    /// branches return different numbers of outputs.
+    pub fn if_else(
+        &mut self,
+        cond: BoolVar,
+        then: impl FnOnce(&mut Builder) -> Vec<NodeId>,
+        otherwise: impl FnOnce(&mut Builder) -> Vec<NodeId>,
+    ) -> Vec<NodeId> {
+        let then = then(self);
+        let otherwise = otherwise(self);
+        assert_eq!(
+            then.len(),
+            otherwise.len(),
+            "the branches of if_else return different numbers of outputs"
+        );
+        let not = self.not(cond);
+        then.into_iter()
+            .zip(otherwise)
+            .map(|(a, b)| if a == b { a } else { self.mux(cond, not, a, b) })
+            .collect()
+    }
+
+    /// Unrolls a loop: calls `body` `n` times, passing it the state returned by the previous call,
+    /// starting with `init`, and returns the final state. Used for hash rounds, recurrences and
+    /// other fixed-iteration algorithms, e.g. Fibonacci numbers with
+    /// `repeat(n, &[a, b], |builder, state| vec![state[1], builder.add(state[0], state[1])])`.
+    /// Panics if `body` returns a state of another length than `init`.
+    pub fn repeat(
+        &mut self,
+        n: usize,
+        init: &[NodeId],
+        mut body: impl FnMut(&mut Builder, Vec<NodeId>) -> Vec<NodeId>,
+    ) -> Vec<NodeId> {
+        let mut state = init.to_vec();
+        for i in 0..n {
+            state = body(self, state);
+            assert_eq!(
+                state.len(),
+                init.len(),
+                "iteration {} of repeat changed the number of state wires",
+                i
+            );
+        }
+        state
+    }
+
+    /// `cond * a + not * b`.
+    fn mux(&mut self, cond: BoolVar, not: BoolVar, a: NodeId, b: NodeId) -> NodeId {
+        let a = self.mul(cond.0, a);
+        let b = self.mul(not.0, b);
+        self.add(a, b)
+    }
+}
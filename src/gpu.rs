@@ -0,0 +1,473 @@
+//! Experimental batch evaluation on the GPU with wgpu compute shaders.
+//!
+//! `GpuEvaluator::evaluate` fills in a graph for many input assignments at once, e.g. to generate
+//! witnesses for a batch of proofs of the same giant, wide circuit. The additions,
+//! multiplications and mul-adds of each dependency level (see `Builder::levels`) are uploaded
+//! once as an op list, and every level is one dispatch that computes its nodes for every
+//! assignment in parallel.
+//! Hints are computed on the CPU: after a level with hints, the values are read back, the hints
+//! are filled in and the values are uploaded again, so graphs with many hint levels gain little.
+//!
+//! Results are the same as `Builder::fill_inputs_with`: an assignment that overflows, or that
+//! can't be evaluated on the GPU because an input has no value, is evaluated again on the CPU to
+//! report the exact error. The evaluator needs a GPU adapter, and `GpuEvaluator::new` fails on
+//! machines without one; the CPU evaluator stays the reference.
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+
+use thiserror::Error;
+
+use crate::error::EvalError;
+use crate::eval::EvalOptions;
+use crate::storage::Kind;
+use crate::{Builder, HintFn, NodeId, Witness, OPERATION};
+
+/// Nodes computed per workgroup, along x.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Largest number of workgroups of a dispatch along one dimension, which bounds the ops per
+/// dispatch and the assignments per batch.
+const MAX_WORKGROUPS: u32 = 65535;
+
+/// Words per op in the op list.
+const OP_WORDS: usize = 5;
+
+/// Computes a range of the op list for every assignment. Each op is five words: the operation
+/// (0 for add, 1 for mul, 2 for mul-add), the node, its two operands and the addend of a mul-add,
+/// or 0. `overflow` holds, for every assignment,
+/// the smallest node whose result doesn't fit in a u32.
+const SHADER: &str = r#"
+struct Dispatch {
+    offset: u32,
+    count: u32,
+}
+
+struct Shape {
+    nodes: u32,
+    batch: u32,
+}
+
+@group(0) @binding(0) var<uniform> dispatch: Dispatch;
+@group(0) @binding(1) var<uniform> shape: Shape;
+@group(0) @binding(2) var<storage, read> ops: array<u32>;
+@group(0) @binding(3) var<storage, read_write> values: array<u32>;
+@group(0) @binding(4) var<storage, read_write> overflow: array<atomic<u32>>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let item = id.y;
+    if (id.x >= dispatch.count || item >= shape.batch) {
+        return;
+    }
+    let op = (dispatch.offset + id.x) * 5u;
+    let node = ops[op + 1u];
+    let base = item * shape.nodes;
+    let a = values[base + ops[op + 2u]];
+    let b = values[base + ops[op + 3u]];
+    var result: u32;
+    var overflows: bool;
+    if (ops[op] == 0u) {
+        result = a + b;
+        overflows = result < a;
+    } else {
+        result = a * b;
+        overflows = a != 0u && result / a != b;
+        if (ops[op] == 2u) {
+            let product = result;
+            result = product + values[base + ops[op + 4u]];
+            overflows = overflows || result < product;
+        }
+    }
+    if (overflows) {
+        atomicMin(&overflow[item], node);
+    }
+    values[base + node] = result;
+}
+"#;
+
+/// An error of the GPU evaluator.
+#[derive(Debug, Error)]
+pub enum GpuError {
+    #[error("no GPU adapter is available: {0}")]
+    NoAdapter(String),
+    #[error("the GPU device could not be opened: {0}")]
+    Device(String),
+    #[error("reading values back from the GPU failed: {0}")]
+    Readback(String),
+    /// The graph needs larger buffers than the device can bind.
+    #[error("the graph has too many nodes for the GPU: a buffer of {bytes} bytes is needed, but the device binds at most {max}")]
+    TooLarge { bytes: u64, max: u64 },
+    /// Evaluating an assignment failed, as it would have on the CPU.
+    #[error("assignment {item} can't be evaluated: {error}")]
+    Eval { item: usize, error: EvalError },
+}
+
+/// The nodes of one dependency level.
+#[derive(Default)]
+struct Level {
+    /// The range of the level in the op list.
+    ops: std::ops::Range<u32>,
+    /// Hints computed at this level: the node, the node it is computed from and the function.
+    hints: Vec<(usize, usize, HintFn)>,
+}
+
+/// A GPU device with the level evaluation pipeline compiled.
+pub struct GpuEvaluator {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl std::fmt::Debug for GpuEvaluator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GpuEvaluator").finish_non_exhaustive()
+    }
+}
+
+impl GpuEvaluator {
+    /// Opens the default GPU adapter. Backends can be chosen with the `WGPU_BACKEND` environment
+    /// variable, e.g. `WGPU_BACKEND=vulkan`.
+    pub fn new() -> Result<Self, GpuError> {
+        let instance =
+            wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle_from_env());
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+                .map_err(|err| GpuError::NoAdapter(err.to_string()))?;
+        let (device, queue) =
+            pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default()))
+                .map_err(|err| GpuError::Device(err.to_string()))?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("evaluate level"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("evaluate level"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Ok(GpuEvaluator {
+            device,
+            queue,
+            pipeline,
+        })
+    }
+
+    /// Fills in `builder` for every input assignment of `batch`, returning one witness per
+    /// assignment. Inputs missing from an assignment keep the value they have in `builder`.
+    /// The builder itself is left untouched.
+    pub fn evaluate(
+        &self,
+        builder: &Builder,
+        batch: &[Vec<(NodeId, u32)>],
+    ) -> Result<Vec<Witness>, GpuError> {
+        let nodes = builder.graph.len();
+        let mut witnesses: Vec<Option<Witness>> = vec![None; batch.len()];
+        // A graph with a hint function that isn't registered fails on the CPU.
+        let plan = if nodes > 0 { plan(builder)? } else { None };
+        // Initial values of the assignments that can be evaluated on the GPU.
+        let mut gpu_items = Vec::new();
+        for (item, inputs) in batch.iter().enumerate() {
+            match initial_values(builder, inputs).filter(|_| plan.is_some()) {
+                Some(values) => gpu_items.push((item, values)),
+                None => witnesses[item] = Some(evaluate_on_cpu(builder, inputs, item)?),
+            }
+        }
+        if let Some(Plan { ops, levels }) = plan.filter(|_| !gpu_items.is_empty()) {
+            let max = self.device.limits().max_storage_buffer_binding_size;
+            let row = nodes as u64 * 4;
+            let per_chunk = (max / row).min(MAX_WORKGROUPS.into()) as usize;
+            for bytes in [row, ops.len() as u64 * 4] {
+                if bytes > max || per_chunk == 0 {
+                    return Err(GpuError::TooLarge { bytes, max });
+                }
+            }
+            let mut run = Run::new(self, nodes, &ops, &levels, per_chunk.min(gpu_items.len()));
+            for chunk in gpu_items.chunks(per_chunk) {
+                let values: Vec<u32> = chunk
+                    .iter()
+                    .flat_map(|(_, values)| values)
+                    .copied()
+                    .collect();
+                let (values, overflows) = run.evaluate(values, chunk.len())?;
+                for ((item, _), (values, overflow)) in
+                    chunk.iter().zip(values.chunks(nodes).zip(overflows))
+                {
+                    witnesses[*item] = Some(if overflow == u32::MAX {
+                        builder.witness_of(values.iter().copied().map(Some).collect())
+                    } else {
+                        evaluate_on_cpu(builder, &batch[*item], *item)?
+                    });
+                }
+            }
+        }
+        Ok(witnesses.into_iter().map(Option::unwrap).collect())
+    }
+}
+
+/// The values every node starts with, or None if an input has no value or an id is invalid.
+fn initial_values(builder: &Builder, inputs: &[(NodeId, u32)]) -> Option<Vec<u32>> {
+    let mut assigned = HashMap::new();
+    for &(id, value) in inputs {
+        let node = builder.resolve(id).ok()?;
+        if builder.graph.kind(node) != Kind::Input {
+            return None;
+        }
+        assigned.insert(node, value);
+    }
+    (0..builder.graph.len())
+        .map(|id| match builder.graph.kind(id) {
+            Kind::Input => assigned.get(&id).copied().or(builder.graph.output(id)),
+            Kind::Constant => builder.graph.output(id),
+            Kind::Hint if !builder.hint_calls.contains_key(&id) => builder.graph.output(id),
+            Kind::Add | Kind::Mul | Kind::MulAdd | Kind::Hint | Kind::Custom => Some(0),
+        })
+        .collect()
+}
+
+/// Evaluates one assignment with the CPU evaluator.
+fn evaluate_on_cpu(
+    builder: &Builder,
+    inputs: &[(NodeId, u32)],
+    item: usize,
+) -> Result<Witness, GpuError> {
+    let mut graph = builder.clone();
+    graph.metrics = None;
+    // Assignments evaluated on the GPU aren't reported, so neither are these.
+    graph.observers.clear();
+    graph
+        .fill_inputs_with(inputs, EvalOptions::new())
+        .map_err(|error| GpuError::Eval { item, error })?;
+    Ok(graph.witness())
+}
+
+/// The op list of a graph and its levels.
+struct Plan {
+    ops: Vec<u32>,
+    levels: Vec<Level>,
+}
+
+/// Splits the graph into levels, or returns None if a hint function is not registered.
+fn plan(builder: &Builder) -> Result<Option<Plan>, GpuError> {
+    let nodes = builder.graph.len();
+    if u32::try_from(nodes).is_err() {
+        return Err(GpuError::TooLarge {
+            bytes: nodes as u64 * 4,
+            max: u64::from(u32::MAX),
+        });
+    }
+    let node_levels = builder.levels();
+    let depth = node_levels.iter().max().map_or(0, |&level| level + 1);
+    let mut ops_by_level = vec![Vec::new(); depth];
+    let mut levels: Vec<Level> = (0..depth).map(|_| Level::default()).collect();
+    for (id, &level) in node_levels.iter().enumerate() {
+        if let Some([a, b, c]) = builder.graph.mul_add(id) {
+            ops_by_level[level].extend([2, id as u32, a as u32, b as u32, c as u32]);
+        } else if let (Some(op), Some((a, b))) = (builder.graph.op(id), builder.graph.operands(id))
+        {
+            let code = match op {
+                OPERATION::ADD => 0,
+                OPERATION::MUL => 1,
+            };
+            ops_by_level[level].extend([code, id as u32, a as u32, b as u32, 0]);
+        } else if let Some(name) = builder.hint_calls.get(&id) {
+            let Some(&function) = builder.hint_fns.get(name) else {
+                return Ok(None);
+            };
+            levels[level]
+                .hints
+                .push((id, builder.graph.linked(id).unwrap(), function));
+        } else if builder.graph.kind(id) == Kind::Custom {
+            // The shader only knows the built-in operations.
+            return Ok(None);
+        }
+    }
+    let mut ops = Vec::new();
+    for (level, level_ops) in levels.iter_mut().zip(ops_by_level) {
+        let start = (ops.len() / OP_WORDS) as u32;
+        ops.extend(level_ops);
+        level.ops = start..(ops.len() / OP_WORDS) as u32;
+    }
+    Ok(Some(Plan { ops, levels }))
+}
+
+/// The buffers and bind groups of one `evaluate` call, reused for every chunk of assignments.
+struct Run<'a> {
+    gpu: &'a GpuEvaluator,
+    nodes: usize,
+    levels: &'a [Level],
+    shape: wgpu::Buffer,
+    values: wgpu::Buffer,
+    overflow: wgpu::Buffer,
+    /// The bind groups of the dispatches of each level.
+    dispatches: Vec<Vec<(wgpu::BindGroup, u32)>>,
+}
+
+impl<'a> Run<'a> {
+    fn new(
+        gpu: &'a GpuEvaluator,
+        nodes: usize,
+        ops: &[u32],
+        levels: &'a [Level],
+        batch: usize,
+    ) -> Self {
+        let device = &gpu.device;
+        let buffer = |label, size: usize, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                // Bindings can't be empty.
+                size: (size as u64 * 4).max(16),
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let storage = wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::COPY_SRC;
+        let uniform = wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST;
+        let ops_buffer = buffer("ops", ops.len(), storage);
+        gpu.queue.write_buffer(&ops_buffer, 0, &to_bytes(ops));
+        let shape = buffer("shape", 4, uniform);
+        let values = buffer("values", batch * nodes, storage);
+        let overflow = buffer("overflow", batch, storage);
+        let layout = gpu.pipeline.get_bind_group_layout(0);
+        let dispatches = levels
+            .iter()
+            .map(|level| {
+                let per_dispatch = MAX_WORKGROUPS * WORKGROUP_SIZE;
+                (level.ops.start..level.ops.end)
+                    .step_by(per_dispatch as usize)
+                    .map(|offset| {
+                        let count = per_dispatch.min(level.ops.end - offset);
+                        let params = buffer("dispatch", 4, uniform);
+                        gpu.queue
+                            .write_buffer(&params, 0, &to_bytes(&[offset, count, 0, 0]));
+                        let entries: Vec<wgpu::BindGroupEntry> =
+                            [&params, &shape, &ops_buffer, &values, &overflow]
+                                .into_iter()
+                                .enumerate()
+                                .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                                    binding: binding as u32,
+                                    resource: buffer.as_entire_binding(),
+                                })
+                                .collect();
+                        let group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                            label: Some("dispatch"),
+                            layout: &layout,
+                            entries: &entries,
+                        });
+                        (group, count)
+                    })
+                    .collect()
+            })
+            .collect();
+        Run {
+            gpu,
+            nodes,
+            levels,
+            shape,
+            values,
+            overflow,
+            dispatches,
+        }
+    }
+
+    /// Evaluates `batch` assignments from their initial values, returning the values of every
+    /// node and, for every assignment, the first node that overflows or `u32::MAX`.
+    fn evaluate(
+        &mut self,
+        mut values: Vec<u32>,
+        batch: usize,
+    ) -> Result<(Vec<u32>, Vec<u32>), GpuError> {
+        let queue = &self.gpu.queue;
+        queue.write_buffer(
+            &self.shape,
+            0,
+            &to_bytes(&[self.nodes as u32, batch as u32, 0, 0]),
+        );
+        queue.write_buffer(&self.values, 0, &to_bytes(&values));
+        queue.write_buffer(&self.overflow, 0, &to_bytes(&vec![u32::MAX; batch]));
+        let mut encoder = self.encoder();
+        for (level, dispatches) in self.levels.iter().zip(&self.dispatches) {
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("evaluate level"),
+                    timestamp_writes: None,
+                });
+                pass.set_pipeline(&self.gpu.pipeline);
+                for (group, count) in dispatches {
+                    pass.set_bind_group(0, group, &[]);
+                    pass.dispatch_workgroups(count.div_ceil(WORKGROUP_SIZE), batch as u32, 1);
+                }
+            }
+            if level.hints.is_empty() {
+                continue;
+            }
+            queue.submit([encoder.finish()]);
+            values = self.read(&self.values, batch * self.nodes)?;
+            for item in values.chunks_mut(self.nodes) {
+                for &(node, linked, function) in &level.hints {
+                    item[node] = function(item[linked]);
+                }
+            }
+            queue.write_buffer(&self.values, 0, &to_bytes(&values));
+            encoder = self.encoder();
+        }
+        queue.submit([encoder.finish()]);
+        let values = self.read(&self.values, batch * self.nodes)?;
+        let overflows = self.read(&self.overflow, batch)?;
+        Ok((values, overflows))
+    }
+
+    fn encoder(&self) -> wgpu::CommandEncoder {
+        self.gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("evaluate"),
+            })
+    }
+
+    /// Copies the first `len` words of a buffer back to the CPU.
+    fn read(&self, buffer: &wgpu::Buffer, len: usize) -> Result<Vec<u32>, GpuError> {
+        let size = len as u64 * 4;
+        let staging = self.gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self.encoder();
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, size);
+        self.gpu.queue.submit([encoder.finish()]);
+        let (sender, receiver) = mpsc::channel();
+        staging.map_async(wgpu::MapMode::Read, .., move |result| {
+            let _ = sender.send(result);
+        });
+        self.gpu
+            .device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .map_err(|err| GpuError::Readback(err.to_string()))?;
+        receiver
+            .recv()
+            .map_err(|err| GpuError::Readback(err.to_string()))?
+            .map_err(|err| GpuError::Readback(err.to_string()))?;
+        let values = {
+            let bytes = staging
+                .get_mapped_range(..)
+                .map_err(|err| GpuError::Readback(err.to_string()))?;
+            bytes
+                .chunks_exact(4)
+                .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+                .collect()
+        };
+        staging.unmap();
+        Ok(values)
+    }
+}
+
+fn to_bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
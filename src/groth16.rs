@@ -0,0 +1,350 @@
+//! bellman's `Circuit` for a graph, built with `--features bellman`, for Groth16 proofs in
+//! zcash-style pipelines.
+//!
+//! `GraphCircuit` synthesizes a graph into a bellman constraint system over any prime field:
+//! inputs and hints are allocated as private variables, constants are multiples of the constant
+//! one, additions are linear combinations, and each multiplication is one constraint. Public
+//! outputs are allocated as public inputs, in the order of `Builder::public_outputs`, which is
+//! also the order of `public_inputs`. Equality constraints and checks are enforced as in
+//! `export::to_r1cs`; constraints with the `Warning` severity are left out. Aliased nodes are
+//! enforced equal to the representative of their class.
+//!
+//! Values are taken from the builder, so a filled builder gives a circuit to prove, and any
+//! builder one to generate parameters from. Values are field elements, so wrapping around the
+//! field is not ruled out as an overflow is by evaluation.
+//!
+//! ```
+//! use bellman::gadgets::test::TestConstraintSystem;
+//! use bellman::Circuit;
+//! use bls12_381::Scalar;
+//! use my_graph_lib::groth16::GraphCircuit;
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let y = builder.mul(x, x);
+//! builder.mark_public(y);
+//! builder.fill_nodes(x, 5);
+//! let mut cs = TestConstraintSystem::<Scalar>::new();
+//! GraphCircuit::new(&builder).synthesize(&mut cs).unwrap();
+//! assert!(cs.is_satisfied());
+//! assert!(cs.verify(&GraphCircuit::new(&builder).public_inputs().unwrap()));
+//! ```
+
+use bellman::{Circuit, ConstraintSystem, LinearCombination, SynthesisError, Variable};
+use ff::PrimeField;
+
+use crate::checks::Check;
+use crate::error::BuildError;
+use crate::storage::Kind;
+use crate::Builder;
+
+/// Linear combinations of additions are copied into the nodes that use them, so ones longer than
+/// this are replaced with a variable, keeping long chains of additions linear in size.
+const MAX_TERMS: usize = 32;
+
+/// A graph as a bellman circuit, see the module documentation.
+#[derive(Clone, Copy)]
+pub struct GraphCircuit<'a> {
+    builder: &'a Builder,
+}
+
+impl<'a> GraphCircuit<'a> {
+    /// Panics if the graph has custom nodes, which can't be constrained: lower them with
+    /// `Builder::lower_custom_ops` first.
+    pub fn new(builder: &'a Builder) -> GraphCircuit<'a> {
+        Self::try_new(builder).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `new`, but returns `BuildError::Unlowered` instead of panicking if the graph has
+    /// custom nodes.
+    pub fn try_new(builder: &'a Builder) -> Result<GraphCircuit<'a>, BuildError> {
+        builder.check_lowered()?;
+        Ok(GraphCircuit { builder })
+    }
+
+    /// The public inputs of a proof, the values of the public outputs. `None` if one has not been
+    /// filled in.
+    pub fn public_inputs<S: PrimeField>(&self) -> Option<Vec<S>> {
+        self.builder
+            .public
+            .iter()
+            .map(|&id| self.builder.graph.output(id).map(scalar))
+            .collect()
+    }
+
+    fn value(&self, id: usize) -> Option<u64> {
+        self.builder.graph.output(id).map(u64::from)
+    }
+}
+
+impl<S: PrimeField> Circuit<S> for GraphCircuit<'_> {
+    fn synthesize<CS: ConstraintSystem<S>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let builder = self.builder;
+        let graph = &builder.graph;
+        let mut lcs: Vec<LinearCombination<S>> = Vec::with_capacity(graph.len());
+        let constant = |value: u64| LinearCombination::zero() + (scalar(value), CS::one());
+        for id in 0..graph.len() {
+            let value = self.value(id);
+            let lc = match graph.kind(id) {
+                Kind::Constant => constant(value.unwrap()),
+                // A hint with a fixed value is a constant too.
+                Kind::Hint if !builder.hint_calls.contains_key(&id) => constant(value.unwrap()),
+                Kind::Input | Kind::Hint => {
+                    let var = alloc(cs, || format!("value {}", id), value.map(scalar))?;
+                    LinearCombination::zero() + var
+                }
+                Kind::Custom => unreachable!("`GraphCircuit::try_new` rejects custom nodes"),
+                Kind::Add => {
+                    let (a, b) = graph.operands(id).unwrap();
+                    let sum = lcs[a].clone() + &lcs[b];
+                    if sum.as_ref().len() <= MAX_TERMS {
+                        sum
+                    } else {
+                        let var = alloc(cs, || format!("value {}", id), value.map(scalar))?;
+                        cs.enforce(
+                            || format!("node {}", id),
+                            |lc| lc + &sum,
+                            |lc| lc + CS::one(),
+                            |lc| lc + var,
+                        );
+                        LinearCombination::zero() + var
+                    }
+                }
+                Kind::Mul => {
+                    let (a, b) = graph.operands(id).unwrap();
+                    let var = alloc(cs, || format!("value {}", id), value.map(scalar))?;
+                    cs.enforce(
+                        || format!("node {}", id),
+                        |lc| lc + &lcs[a],
+                        |lc| lc + &lcs[b],
+                        |lc| lc + var,
+                    );
+                    LinearCombination::zero() + var
+                }
+                Kind::MulAdd => {
+                    let [a, b, c] = graph.mul_add(id).unwrap();
+                    let product = self.value(a).zip(self.value(b));
+                    let product = product.map(|(a, b)| scalar::<S>(a) * scalar::<S>(b));
+                    let var = alloc(cs, || format!("product {}", id), product)?;
+                    cs.enforce(
+                        || format!("node {}", id),
+                        |lc| lc + &lcs[a],
+                        |lc| lc + &lcs[b],
+                        |lc| lc + var,
+                    );
+                    LinearCombination::zero() + var + &lcs[c]
+                }
+            };
+            lcs.push(lc);
+        }
+
+        for &id in &builder.public {
+            let input = cs.alloc_input(
+                || format!("public input {}", id),
+                || {
+                    self.value(id)
+                        .map(scalar)
+                        .ok_or(SynthesisError::AssignmentMissing)
+                },
+            )?;
+            cs.enforce(
+                || format!("public {}", id),
+                |lc| lc + &lcs[id],
+                |lc| lc + CS::one(),
+                |lc| lc + input,
+            );
+        }
+        for (i, &(a, b)) in builder.constraints.iter().enumerate() {
+            if builder.soft_constraints.contains(&i) {
+                continue;
+            }
+            cs.enforce(
+                || format!("constraint {}", i),
+                |lc| lc + &lcs[a] - &lcs[b],
+                |lc| lc + CS::one(),
+                |lc| lc,
+            );
+        }
+        for (a, b) in builder.alias_pairs() {
+            cs.enforce(
+                || format!("alias {}", b),
+                |lc| lc + &lcs[a] - &lcs[b],
+                |lc| lc + CS::one(),
+                |lc| lc,
+            );
+        }
+        for (i, check) in builder.checks.iter().enumerate() {
+            let mut cs = cs.namespace(|| format!("check {}", i));
+            match *check {
+                Check::Range { node, bits } => {
+                    enforce_bits(&mut cs, &lcs[node], self.value(node), bits)?;
+                }
+                Check::LessThan { a, b, bits } => {
+                    // b - a - 1 and b fit in `bits` bits.
+                    let difference = LinearCombination::zero() + &lcs[b] - &lcs[a] - CS::one();
+                    let value = self.value(a).zip(self.value(b));
+                    let value = value.map(|(a, b)| b.wrapping_sub(a).wrapping_sub(1));
+                    let mut difference_cs = cs.namespace(|| "difference");
+                    enforce_bits(&mut difference_cs, &difference, value, bits)?;
+                    drop(difference_cs);
+                    let mut bound_cs = cs.namespace(|| "bound");
+                    enforce_bits(&mut bound_cs, &lcs[b], self.value(b), bits)?;
+                }
+                Check::InSet { node, ref set } => {
+                    // The product of the differences to every member is 0.
+                    let x = self.value(node).map(scalar::<S>);
+                    let factor = |c: u32| {
+                        let lc = LinearCombination::zero() + &lcs[node];
+                        lc - (scalar::<S>(u64::from(c)), CS::one())
+                    };
+                    let mut product = factor(set[0]);
+                    let mut value = x.map(|x| x - scalar::<S>(u64::from(set[0])));
+                    for &c in &set[1..] {
+                        let next = value
+                            .zip(x)
+                            .map(|(v, x)| v * (x - scalar::<S>(u64::from(c))));
+                        let var = alloc(&mut cs, || format!("partial product {}", c), next)?;
+                        cs.enforce(
+                            || format!("product {}", c),
+                            |lc| lc + &product,
+                            |lc| lc + &factor(c),
+                            |lc| lc + var,
+                        );
+                        product = LinearCombination::zero() + var;
+                        value = next;
+                    }
+                    cs.enforce(
+                        || "member",
+                        |lc| lc + &product,
+                        |lc| lc + CS::one(),
+                        |lc| lc,
+                    );
+                }
+                Check::Permutation { ref a, ref b } => {
+                    enforce_permutation(&mut cs, &lcs, a, b, |id| self.value(id))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn scalar<S: PrimeField>(value: impl Into<u64>) -> S {
+    S::from(value.into())
+}
+
+fn alloc<S: PrimeField, CS: ConstraintSystem<S>, N: Into<String>>(
+    cs: &mut CS,
+    name: impl FnOnce() -> N,
+    value: Option<S>,
+) -> Result<Variable, SynthesisError> {
+    cs.alloc(name, || value.ok_or(SynthesisError::AssignmentMissing))
+}
+
+/// Enforces that `lc`, whose value is `value`, fits in `bits` bits: it is the sum of `bits`
+/// boolean variables weighted by powers of 2.
+fn enforce_bits<S: PrimeField, CS: ConstraintSystem<S>>(
+    cs: &mut CS,
+    lc: &LinearCombination<S>,
+    value: Option<u64>,
+    bits: u32,
+) -> Result<(), SynthesisError> {
+    let mut sum = LinearCombination::zero();
+    for i in 0..bits {
+        let bit = value.map(|v| scalar::<S>((v >> i) & 1));
+        let var = alloc(cs, || format!("bit {}", i), bit)?;
+        cs.enforce(
+            || format!("bit {} is boolean", i),
+            |lc| lc + var,
+            |lc| lc + CS::one() - var,
+            |lc| lc,
+        );
+        sum = sum + (scalar(1u64 << i), var);
+    }
+    cs.enforce(|| "bits", |x| x + &sum, |x| x + CS::one(), |x| x + lc);
+    Ok(())
+}
+
+/// Enforces that the values of `b` are those of `a` in some order: a matrix of boolean selectors
+/// `s_ij`, with every row and column summing to 1, picks for each `b_j` the `a_i` it equals, as
+/// `b_j = sum of s_ij * a_i`.
+fn enforce_permutation<S: PrimeField, CS: ConstraintSystem<S>>(
+    cs: &mut CS,
+    lcs: &[LinearCombination<S>],
+    a: &[usize],
+    b: &[usize],
+    value: impl Fn(usize) -> Option<u64>,
+) -> Result<(), SynthesisError> {
+    let n = a.len();
+    let a_values: Option<Vec<u64>> = a.iter().map(|&id| value(id)).collect();
+    let b_values: Option<Vec<u64>> = b.iter().map(|&id| value(id)).collect();
+    // Each b_j picks the first a_i of its value not picked yet. If `b` is not a permutation of
+    // `a`, some are left out and the constraints don't hold.
+    let matrix = a_values.as_ref().zip(b_values).map(|(a_values, b_values)| {
+        let mut picked = vec![false; n];
+        let mut matrix = vec![false; n * n];
+        for (j, b_value) in b_values.iter().enumerate() {
+            let i = (0..n).find(|&i| !picked[i] && a_values[i] == *b_value);
+            if let Some(i) = i {
+                picked[i] = true;
+                matrix[i * n + j] = true;
+            }
+        }
+        matrix
+    });
+    let mut selectors = Vec::with_capacity(n * n);
+    let mut products = Vec::with_capacity(n * n);
+    for (i, &a_i) in a.iter().enumerate() {
+        for j in 0..n {
+            let selected = matrix.as_ref().map(|matrix| matrix[i * n + j]);
+            let s = alloc(
+                cs,
+                || format!("selector {} {}", i, j),
+                selected.map(|selected| scalar::<S>(u64::from(selected))),
+            )?;
+            cs.enforce(
+                || format!("selector {} {} is boolean", i, j),
+                |lc| lc + s,
+                |lc| lc + CS::one() - s,
+                |lc| lc,
+            );
+            let product = selected.zip(a_values.as_ref()).map(|(selected, a_values)| {
+                if selected {
+                    scalar::<S>(a_values[i])
+                } else {
+                    S::ZERO
+                }
+            });
+            let p = alloc(cs, || format!("selected value {} {}", i, j), product)?;
+            cs.enforce(
+                || format!("product {} {}", i, j),
+                |lc| lc + s,
+                |lc| lc + &lcs[a_i],
+                |lc| lc + p,
+            );
+            selectors.push(s);
+            products.push(p);
+        }
+    }
+    for (k, &b_k) in b.iter().enumerate() {
+        let row = (0..n).fold(LinearCombination::zero(), |lc, j| lc + selectors[k * n + j]);
+        let column = (0..n).fold(LinearCombination::zero(), |lc, i| lc + selectors[i * n + k]);
+        let picked = (0..n).fold(LinearCombination::zero(), |lc, i| lc + products[i * n + k]);
+        for (name, line) in [("row", row), ("column", column)] {
+            cs.enforce(
+                || format!("{} {}", name, k),
+                |lc| lc + &line,
+                |lc| lc + CS::one(),
+                |lc| lc + CS::one(),
+            );
+        }
+        cs.enforce(
+            || format!("picked {}", k),
+            |lc| lc + &picked,
+            |lc| lc + CS::one(),
+            |lc| lc + &lcs[b_k],
+        );
+    }
+    Ok(())
+}
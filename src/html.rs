@@ -0,0 +1,116 @@
+//! A self-contained, interactive HTML viewer of a graph, to share in circuit reviews.
+//!
+//! `to_html` renders a single HTML file with the graph embedded as JSON and the viewer inline, so
+//! it opens in any browser without a server or network access. The viewer lays nodes out in
+//! columns by level (see `Builder::levels`), with pan (drag) and zoom (mouse wheel), a search box
+//! that highlights and centers on the nodes whose labels contain the query, and a panel showing
+//! the operation, value, inputs and metadata of the clicked node. Values are those the builder
+//! holds, e.g. after `fill_inputs` or `load_witness`; a graph without values shows none. Values of
+//! secret nodes are left out of the page and shown as `<redacted>`, unless the builder reveals
+//! them with `set_reveal_secrets`.
+//!
+//! Nodes are collapsed by scope: a node labelled `round/carry`, as subcircuits label their
+//! instances, is in scope `round`, and scopes nest like paths. A node with a `scope` metadata
+//! string is in that scope instead. An unlabelled node is in the scope of its first consumer, so
+//! the internal nodes of a gadget collapse with its outputs. Each scope starts as a single box,
+//! which expands when clicked.
+//!
+//! ```
+//! use my_graph_lib::{html, Builder};
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! builder.set_label(x, "x");
+//! let square = builder.mul(x, x);
+//! builder.set_label(square, "gadget/square");
+//! builder.fill_inputs(&[(x, 3)]);
+//! let page = html::to_html(&builder);
+//! assert!(page.starts_with("<!DOCTYPE html>"));
+//! assert!(page.contains(r#""scope":["gadget"]"#));
+//! assert!(page.contains(r#""value":9"#));
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::storage::Kind;
+use crate::Builder;
+
+/// The viewer, with `/*DATA*/` standing for the graph.
+const TEMPLATE: &str = include_str!("../viewer/viewer.html");
+
+/// A node as the viewer reads it.
+#[derive(Serialize)]
+struct ViewerNode<'a> {
+    id: usize,
+    kind: String,
+    label: Option<&'a str>,
+    scope: Vec<String>,
+    level: usize,
+    inputs: Vec<usize>,
+    value: Option<u32>,
+    /// Whether the node has a value that is left out because it is secret.
+    redacted: bool,
+    public: bool,
+    meta: Option<&'a BTreeMap<String, serde_json::Value>>,
+}
+
+#[derive(Serialize)]
+struct ViewerData<'a> {
+    nodes: Vec<ViewerNode<'a>>,
+    /// The nodes of each equality constraint, and whether it only warns.
+    constraints: Vec<(usize, usize, bool)>,
+}
+
+/// Renders the graph as a self-contained HTML page, see the module documentation.
+pub fn to_html(builder: &Builder) -> String {
+    let len = builder.graph.len();
+    let levels = builder.levels();
+    let scopes = builder.scopes();
+    let redacted = |id| !builder.reveal_secrets && builder.graph.is_secret(id);
+    let nodes = (0..len)
+        .map(|id| ViewerNode {
+            id,
+            kind: kind(builder, id),
+            label: builder.labels.get(&id).map(String::as_str),
+            scope: scopes[id].clone(),
+            level: levels[id],
+            inputs: builder.sources(id).collect(),
+            value: builder.graph.output(id).filter(|_| !redacted(id)),
+            redacted: builder.graph.output(id).is_some() && redacted(id),
+            public: builder.public.contains(&id),
+            meta: builder.metadata.get(&id),
+        })
+        .collect();
+    let data = ViewerData {
+        nodes,
+        constraints: builder
+            .constraints
+            .iter()
+            .enumerate()
+            .map(|(i, &(a, b))| (a, b, builder.soft_constraints.contains(&i)))
+            .collect(),
+    };
+    // `</` would end the script element the data is embedded in; `<\/` is the same JSON string.
+    let json = serde_json::to_string(&data)
+        .expect("graph data always serializes")
+        .replace("</", "<\\/");
+    TEMPLATE.replace("/*DATA*/", &json)
+}
+
+/// The operation of a node, as shown by the viewer.
+fn kind(builder: &Builder, id: usize) -> String {
+    match builder.graph.kind(id) {
+        Kind::Input => "input".to_string(),
+        Kind::Constant => format!("constant {}", builder.graph.output(id).unwrap()),
+        Kind::Add => "+".to_string(),
+        Kind::Mul => "*".to_string(),
+        Kind::MulAdd => "* +".to_string(),
+        Kind::Hint => match builder.hint_calls.get(&id) {
+            Some(name) => format!("hint {}", name),
+            None => "fixed hint".to_string(),
+        },
+        Kind::Custom => builder.custom_calls[&id].clone(),
+    }
+}
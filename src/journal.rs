@@ -0,0 +1,641 @@
+//! Building graphs larger than memory by appending nodes to a file.
+//!
+//! A `JournalWriter` writes a record per node or equality constraint as it is added, and only
+//! keeps the number of nodes written so far in memory. `JournalWriter::finalize` appends an index
+//! with the position of every node's record, which `Journal` uses to read any node, and
+//! `Journal::evaluate` computes a witness in a single pass over the records. For graphs whose
+//! witness doesn't fit in memory either, `Journal::evaluate_to` writes the witness to a file as it
+//! goes and keeps a bounded cache of recently used values, reading older ones back from the file.
+//!
+//! ```
+//! use my_graph_lib::journal::JournalWriter;
+//!
+//! let path = std::env::temp_dir().join(format!("journal-doc-{}.log", std::process::id()));
+//! let mut writer = JournalWriter::create(&path).unwrap();
+//! let x = writer.init().unwrap();
+//! let y = writer.mul(x, x).unwrap();
+//! let mut journal = writer.finalize().unwrap();
+//! assert_eq!(journal.evaluate(&[(x, 7)]).unwrap().values[y], Some(49));
+//! # std::fs::remove_file(&path).unwrap();
+//! ```
+//!
+//! Layout: an 8 byte magic, then the records, each a kind byte followed by its fields (node
+//! indices as u64, values as u32, names as a u16 length and UTF-8 bytes, all little endian).
+//! The index is one u64 position per node, followed by a footer holding the position of the
+//! index, the node count, the constraint count (u64 each) and another 8 byte magic.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{BuildError, EvalError, Value};
+use crate::storage::Kind;
+use crate::stream;
+use crate::{Builder, HintFn, Witness, OPERATION};
+
+const MAGIC: &[u8; 8] = b"NODELOG1";
+const FOOTER_MAGIC: &[u8; 8] = b"NODEIDX1";
+const HEADER_LEN: u64 = 8;
+const FOOTER_LEN: u64 = 32;
+
+const INPUT: u8 = 0;
+const CONSTANT: u8 = 1;
+const ADD: u8 = 2;
+const MUL: u8 = 3;
+const MUL_ADD: u8 = 4;
+const HINT: u8 = 5;
+const HINT_FN: u8 = 6;
+const EQUAL: u8 = 7;
+
+/// A record of a journal: a node, or an equality constraint between two nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    Input,
+    Constant(u32),
+    Add(usize, usize),
+    Mul(usize, usize),
+    /// `a * b + c`.
+    MulAdd(usize, usize, usize),
+    /// A hint with a fixed value, linked to a node.
+    Hint {
+        linked: usize,
+        value: u32,
+    },
+    /// A hint computed from the linked node by the hint function `function`.
+    HintFn {
+        linked: usize,
+        function: String,
+    },
+    Equal(usize, usize),
+}
+
+impl Record {
+    /// Whether the record adds a node, rather than a constraint.
+    pub fn is_node(&self) -> bool {
+        !matches!(self, Record::Equal(..))
+    }
+
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(25);
+        let node = |bytes: &mut Vec<u8>, node: usize| {
+            bytes.extend_from_slice(&(node as u64).to_le_bytes())
+        };
+        match self {
+            Record::Input => bytes.push(INPUT),
+            Record::Constant(value) => {
+                bytes.push(CONSTANT);
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            Record::Add(a, b) | Record::Mul(a, b) | Record::Equal(a, b) => {
+                bytes.push(match self {
+                    Record::Add(..) => ADD,
+                    Record::Mul(..) => MUL,
+                    _ => EQUAL,
+                });
+                node(&mut bytes, *a);
+                node(&mut bytes, *b);
+            }
+            Record::MulAdd(a, b, c) => {
+                bytes.push(MUL_ADD);
+                for &operand in [a, b, c] {
+                    node(&mut bytes, operand);
+                }
+            }
+            Record::Hint { linked, value } => {
+                bytes.push(HINT);
+                node(&mut bytes, *linked);
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            Record::HintFn { linked, function } => {
+                let len = u16::try_from(function.len()).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "hint function name is too long",
+                    )
+                })?;
+                bytes.push(HINT_FN);
+                node(&mut bytes, *linked);
+                bytes.extend_from_slice(&len.to_le_bytes());
+                bytes.extend_from_slice(function.as_bytes());
+            }
+        }
+        out.write_all(&bytes)
+    }
+
+    /// Reads the next record and its length, or `None` at the end of `reader`.
+    fn read(reader: &mut impl Read) -> io::Result<Option<(Record, u64)>> {
+        let mut kind = [0u8];
+        if reader.read(&mut kind)? == 0 {
+            return Ok(None);
+        }
+        let u32_field = |reader: &mut dyn Read| -> io::Result<u32> {
+            let mut bytes = [0u8; 4];
+            reader.read_exact(&mut bytes)?;
+            Ok(u32::from_le_bytes(bytes))
+        };
+        let node = |reader: &mut dyn Read| -> io::Result<usize> {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes)?;
+            usize::try_from(u64::from_le_bytes(bytes)).map_err(|_| invalid("node index overflows"))
+        };
+        let (record, len) = match kind[0] {
+            INPUT => (Record::Input, 1),
+            CONSTANT => (Record::Constant(u32_field(reader)?), 5),
+            ADD => (Record::Add(node(reader)?, node(reader)?), 17),
+            MUL => (Record::Mul(node(reader)?, node(reader)?), 17),
+            EQUAL => (Record::Equal(node(reader)?, node(reader)?), 17),
+            MUL_ADD => (
+                Record::MulAdd(node(reader)?, node(reader)?, node(reader)?),
+                25,
+            ),
+            HINT => {
+                let linked = node(reader)?;
+                let value = u32_field(reader)?;
+                (Record::Hint { linked, value }, 13)
+            }
+            HINT_FN => {
+                let linked = node(reader)?;
+                let mut len = [0u8; 2];
+                reader.read_exact(&mut len)?;
+                let mut function = vec![0u8; u16::from_le_bytes(len) as usize];
+                reader.read_exact(&mut function)?;
+                let function = String::from_utf8(function)
+                    .map_err(|_| invalid("hint function name is not UTF-8"))?;
+                let len = 11 + function.len() as u64;
+                (Record::HintFn { linked, function }, len)
+            }
+            kind => return Err(invalid(&format!("unknown record kind {}", kind))),
+        };
+        Ok(Some((record, len)))
+    }
+
+    /// The nodes the record reads.
+    fn operands(&self) -> Vec<usize> {
+        match *self {
+            Record::Input | Record::Constant(_) => Vec::new(),
+            Record::Add(a, b) | Record::Mul(a, b) | Record::Equal(a, b) => vec![a, b],
+            Record::MulAdd(a, b, c) => vec![a, b, c],
+            Record::Hint { linked, .. } | Record::HintFn { linked, .. } => vec![linked],
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Appends the nodes of a graph to a file as they are added.
+pub struct JournalWriter {
+    path: PathBuf,
+    out: BufWriter<File>,
+    len: usize,
+    constraints: usize,
+}
+
+impl JournalWriter {
+    /// Creates a new, empty journal, replacing any file at `path`.
+    pub fn create(path: &Path) -> io::Result<JournalWriter> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        Ok(JournalWriter {
+            path: path.to_path_buf(),
+            out,
+            len: 0,
+            constraints: 0,
+        })
+    }
+
+    /// Number of nodes written so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds an input node.
+    pub fn init(&mut self) -> io::Result<usize> {
+        self.push(Record::Input)
+    }
+
+    pub fn constant(&mut self, value: u32) -> io::Result<usize> {
+        self.push(Record::Constant(value))
+    }
+
+    pub fn add(&mut self, a: usize, b: usize) -> io::Result<usize> {
+        self.push(Record::Add(a, b))
+    }
+
+    pub fn mul(&mut self, a: usize, b: usize) -> io::Result<usize> {
+        self.push(Record::Mul(a, b))
+    }
+
+    /// Adds a node computing `a * b + c`.
+    pub fn mul_add(&mut self, a: usize, b: usize, c: usize) -> io::Result<usize> {
+        self.push(Record::MulAdd(a, b, c))
+    }
+
+    /// Adds a hint with a fixed value, linked to `hint_node`.
+    pub fn hint(&mut self, hint_value: u32, hint_node: usize) -> io::Result<usize> {
+        self.push(Record::Hint {
+            linked: hint_node,
+            value: hint_value,
+        })
+    }
+
+    /// Adds a hint computed from `hint_node` by the hint function `name`, which is looked up
+    /// when the journal is evaluated.
+    pub fn hint_fn(&mut self, name: &str, hint_node: usize) -> io::Result<usize> {
+        self.push(Record::HintFn {
+            linked: hint_node,
+            function: name.to_string(),
+        })
+    }
+
+    pub fn enforce_equal(&mut self, a: usize, b: usize) -> io::Result<()> {
+        self.push(Record::Equal(a, b)).map(|_| ())
+    }
+
+    /// Appends a record, whose operands must already be in the journal, and returns the index
+    /// of the node or constraint it adds.
+    fn push(&mut self, record: Record) -> io::Result<usize> {
+        if let Some(operand) = record.operands().into_iter().find(|&node| node >= self.len) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("node {} does not exist", operand),
+            ));
+        }
+        record.write(&mut self.out)?;
+        let count = if record.is_node() {
+            &mut self.len
+        } else {
+            &mut self.constraints
+        };
+        *count += 1;
+        Ok(*count - 1)
+    }
+
+    /// Writes the index after the records, in a second pass over them, and opens the journal.
+    pub fn finalize(mut self) -> io::Result<Journal> {
+        self.out.flush()?;
+        let index = self.out.get_ref().metadata()?.len();
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(HEADER_LEN))?;
+        let mut records = BufReader::new(file.take(index - HEADER_LEN));
+        let mut position = HEADER_LEN;
+        while let Some((record, len)) = Record::read(&mut records)? {
+            if record.is_node() {
+                self.out.write_all(&position.to_le_bytes())?;
+            }
+            position += len;
+        }
+        for field in [index, self.len as u64, self.constraints as u64] {
+            self.out.write_all(&field.to_le_bytes())?;
+        }
+        self.out.write_all(FOOTER_MAGIC)?;
+        self.out.flush()?;
+        Journal::open(&self.path)
+    }
+}
+
+/// A journal written by `JournalWriter::finalize`.
+pub struct Journal {
+    file: File,
+    index: u64,
+    len: usize,
+    constraints: usize,
+    hint_fns: HashMap<String, HintFn>,
+}
+
+impl Journal {
+    pub fn open(path: &Path) -> io::Result<Journal> {
+        let mut file = OpenOptions::new().read(true).open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut magic = [0u8; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC || file_len < HEADER_LEN + FOOTER_LEN {
+            return Err(invalid("not a journal"));
+        }
+        let mut footer = [0u8; FOOTER_LEN as usize];
+        file.seek(SeekFrom::Start(file_len - FOOTER_LEN))?;
+        file.read_exact(&mut footer)?;
+        if &footer[24..] != FOOTER_MAGIC {
+            return Err(invalid("journal has no index, it was not finalized"));
+        }
+        let field = |i: usize| u64::from_le_bytes(footer[i * 8..i * 8 + 8].try_into().unwrap());
+        let (index, len, constraints) = (field(0), field(1), field(2));
+        if len.checked_mul(8).and_then(|len| len.checked_add(index)) != Some(file_len - FOOTER_LEN)
+        {
+            return Err(invalid("journal index is truncated"));
+        }
+        Ok(Journal {
+            file,
+            index,
+            len: len as usize,
+            constraints: constraints as usize,
+            hint_fns: Builder::new().hint_fns,
+        })
+    }
+
+    /// Number of nodes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of equality constraints.
+    pub fn constraints(&self) -> usize {
+        self.constraints
+    }
+
+    /// Registers a hint function for the hint nodes naming it, as `Builder::register_hint`
+    /// does. `sqrt` and `not` are registered from the start.
+    pub fn register_hint(&mut self, name: &str, f: HintFn) {
+        self.hint_fns.insert(name.to_string(), f);
+    }
+
+    /// Reads the record of a single node, through the index.
+    pub fn node(&mut self, id: usize) -> io::Result<Record> {
+        if id >= self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("node {} is out of range", id),
+            ));
+        }
+        let mut position = [0u8; 8];
+        self.file
+            .seek(SeekFrom::Start(self.index + id as u64 * 8))?;
+        self.file.read_exact(&mut position)?;
+        let position = u64::from_le_bytes(position);
+        self.file.seek(SeekFrom::Start(position))?;
+        Record::read(&mut (&self.file).take(self.index.saturating_sub(position)))?
+            .map(|(record, _)| record)
+            .ok_or_else(|| invalid("journal index points past the records"))
+    }
+
+    /// Calls `f` with every record, in the order they were written.
+    pub fn for_each<E: From<io::Error>>(
+        &mut self,
+        mut f: impl FnMut(Record) -> Result<(), E>,
+    ) -> Result<(), E> {
+        self.file.seek(SeekFrom::Start(HEADER_LEN))?;
+        let mut records = BufReader::new((&self.file).take(self.index - HEADER_LEN));
+        while let Some((record, _)) = Record::read(&mut records)? {
+            f(record)?;
+        }
+        Ok(())
+    }
+
+    /// Computes the value of every node from the values of `inputs`, by index, reading the
+    /// records once. The values are held in memory, the graph is not.
+    pub fn evaluate(&mut self, inputs: &[(usize, u32)]) -> Result<Witness, EvalError> {
+        self.check_inputs(inputs)?;
+        let mut values: Vec<Option<u32>> = vec![None; self.len];
+        for &(node, value) in inputs {
+            values[node] = Some(value);
+        }
+        let hint_fns = self.hint_fns.clone();
+        let mut node = 0;
+        self.for_each(|record| -> Result<(), EvalError> {
+            if record.is_node() {
+                let value = |operand: usize| {
+                    values[..node]
+                        .get(operand)
+                        .copied()
+                        .flatten()
+                        .ok_or(EvalError::MissingValue { node, operand })
+                };
+                values[node] = compute(record, node, values[node], &hint_fns, value)?;
+                node += 1;
+            }
+            Ok(())
+        })?;
+        Ok(Witness::from(values))
+    }
+
+    /// Like `evaluate`, but writes the witness to `path` as it is computed, in the format of
+    /// `Builder::stream_witness`, and only keeps the values of the `capacity` nodes read or
+    /// computed most recently in memory. Values that were evicted are read back from the file,
+    /// so graphs far larger than memory can be evaluated; `stream::WitnessFile` reads the result.
+    pub fn evaluate_to(
+        &mut self,
+        inputs: &[(usize, u32)],
+        path: &Path,
+        capacity: usize,
+    ) -> Result<OutOfCoreStats, EvalError> {
+        self.check_inputs(inputs)?;
+        let inputs: HashMap<usize, u32> = inputs.iter().copied().collect();
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&stream::header(self.len))?;
+        let mut witness = File::open(path)?;
+        let mut cache = ValueCache::new(capacity);
+        let mut stats = OutOfCoreStats::default();
+        // Records of the nodes before this one are on disk rather than in `out`'s buffer.
+        let mut flushed = 0;
+        let hint_fns = self.hint_fns.clone();
+        let mut node = 0;
+        self.for_each(|record| -> Result<(), EvalError> {
+            if !record.is_node() {
+                return Ok(());
+            }
+            let value = |operand: usize| -> Result<u32, EvalError> {
+                let missing = EvalError::MissingValue { node, operand };
+                if operand >= node {
+                    return Err(missing);
+                }
+                if let Some(value) = cache.get(operand) {
+                    return Ok(value);
+                }
+                stats.cache_misses += 1;
+                if operand >= flushed {
+                    out.flush()?;
+                    flushed = node;
+                }
+                witness.seek(SeekFrom::Start(stream::record_position(operand)))?;
+                let mut record = [0u8; stream::RECORD_LEN as usize];
+                witness.read_exact(&mut record)?;
+                let value = stream::decode(record).ok_or(missing)?;
+                cache.insert(operand, value);
+                Ok(value)
+            };
+            let output = compute(record, node, inputs.get(&node).copied(), &hint_fns, value)?;
+            out.write_all(&stream::encode(output))?;
+            if let Some(output) = output {
+                cache.insert(node, output);
+            }
+            stats.nodes_written += 1;
+            node += 1;
+            Ok(())
+        })?;
+        out.flush()?;
+        Ok(stats)
+    }
+
+    /// Returns true if `witness` satisfies every equality constraint.
+    pub fn is_satisfied(&mut self, witness: &Witness) -> io::Result<bool> {
+        let value = |node: usize| witness.values.get(node).copied().flatten();
+        let mut satisfied = true;
+        self.for_each(|record| {
+            if let Record::Equal(a, b) = record {
+                satisfied &= value(a).is_some() && value(a) == value(b);
+            }
+            Ok::<_, io::Error>(())
+        })?;
+        Ok(satisfied)
+    }
+
+    fn check_inputs(&mut self, inputs: &[(usize, u32)]) -> Result<(), EvalError> {
+        for &(node, _) in inputs {
+            if node >= self.len || self.node(node)? != Record::Input {
+                return Err(BuildError::NotAnInput { node }.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the whole graph into a builder, keeping node indices, with the hint functions
+    /// registered here.
+    pub fn to_builder(&mut self) -> io::Result<Builder> {
+        let mut builder = Builder::new();
+        builder.hint_fns.clone_from(&self.hint_fns);
+        self.for_each(|record| -> io::Result<()> {
+            let node = builder.graph.len();
+            if let Some(&operand) = record.operands().iter().find(|&&operand| operand >= node) {
+                return Err(invalid(&format!("node {} does not exist", operand)));
+            }
+            let graph = &mut builder.graph;
+            match record {
+                Record::Input => {
+                    graph.push(Kind::Input, [0, 0], None);
+                    builder.input_nodes.push(node);
+                }
+                Record::Constant(value) => {
+                    graph.push(Kind::Constant, [0, 0], Some(value));
+                }
+                Record::Add(a, b) => {
+                    graph.push(Kind::Add, [a, b], None);
+                }
+                Record::Mul(a, b) => {
+                    graph.push(Kind::Mul, [a, b], None);
+                }
+                Record::MulAdd(a, b, c) => {
+                    graph.push_mul_add([a, b, c], None);
+                }
+                Record::Hint { linked, value } => {
+                    graph.push(Kind::Hint, [linked, 0], Some(value));
+                }
+                Record::HintFn { linked, function } => {
+                    graph.push(Kind::Hint, [linked, 0], None);
+                    builder.hint_calls.insert(node, function);
+                }
+                Record::Equal(a, b) => builder.constraints.push((a, b)),
+            }
+            Ok(())
+        })?;
+        Ok(builder)
+    }
+}
+
+/// Computes the value of the node a record adds, given the value of an input and a way to read
+/// the value of an operand.
+fn compute(
+    record: Record,
+    node: usize,
+    input: Option<u32>,
+    hint_fns: &HashMap<String, HintFn>,
+    mut value: impl FnMut(usize) -> Result<u32, EvalError>,
+) -> Result<Option<u32>, EvalError> {
+    let overflow = |op, a, b| EvalError::Overflow {
+        node,
+        op,
+        a: Value::new(a, false),
+        b: Value::new(b, false),
+    };
+    Ok(match record {
+        Record::Input => input,
+        Record::Constant(value) | Record::Hint { value, .. } => Some(value),
+        Record::Add(a, b) | Record::Mul(a, b) => {
+            let op = if matches!(record, Record::Add(..)) {
+                OPERATION::ADD
+            } else {
+                OPERATION::MUL
+            };
+            let (a, b) = (value(a)?, value(b)?);
+            Some(
+                op.checked_apply(a, b)
+                    .ok_or_else(|| overflow(op.symbol(), a, b))?,
+            )
+        }
+        Record::MulAdd(a, b, c) => {
+            let (a, b, c) = (value(a)?, value(b)?, value(c)?);
+            let product = a.checked_mul(b).ok_or_else(|| overflow("*", a, b))?;
+            Some(
+                product
+                    .checked_add(c)
+                    .ok_or_else(|| overflow("+", product, c))?,
+            )
+        }
+        Record::HintFn { linked, function } => {
+            let f = hint_fns
+                .get(&function)
+                .ok_or(BuildError::UnknownHint { name: function })?;
+            Some(f(value(linked)?))
+        }
+        Record::Equal(..) => None,
+    })
+}
+
+/// Statistics about an evaluation by `Journal::evaluate_to`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutOfCoreStats {
+    pub nodes_written: usize,
+    pub cache_misses: usize, // Values read back from the witness file.
+}
+
+/// The values of the nodes read or computed most recently, evicting the least recently used one
+/// when full.
+struct ValueCache {
+    capacity: usize,
+    values: HashMap<usize, (u32, u64)>, // Value and last use of each node.
+    by_use: BTreeMap<u64, usize>,
+    clock: u64,
+}
+
+impl ValueCache {
+    fn new(capacity: usize) -> Self {
+        ValueCache {
+            capacity,
+            values: HashMap::new(),
+            by_use: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, node: usize) -> Option<u32> {
+        let (value, used) = self.values.get_mut(&node)?;
+        self.by_use.remove(used);
+        self.clock += 1;
+        *used = self.clock;
+        self.by_use.insert(self.clock, node);
+        Some(*value)
+    }
+
+    fn insert(&mut self, node: usize, value: u32) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.values.len() >= self.capacity {
+            if let Some((_, evicted)) = self.by_use.pop_first() {
+                self.values.remove(&evicted);
+            }
+        }
+        self.clock += 1;
+        self.values.insert(node, (value, self.clock));
+        self.by_use.insert(self.clock, node);
+    }
+}
@@ -0,0 +1,350 @@
+//! JSON serialization of graphs.
+//!
+//! Hint functions are stored by name. A deserialized builder only knows the default hint functions,
+//! so custom ones have to be registered again with `register_hint` before filling the graph.
+//! Custom nodes are stored with the name of their operation and their operands as the inputs and
+//! addend of their node, and their operations have to be registered again with `register_op`.
+//!
+//! Documents record the version of the format they were written in. `from_json` reads every
+//! version up to `FORMAT_VERSION`, upgrading older documents one version at a time before
+//! loading them, so writing a loaded graph back with `to_json` upgrades the file. Documents of a
+//! newer version are rejected with `SerdeError::UnsupportedVersion` rather than misread.
+//!
+//! Documents may come from untrusted sources, so `from_json` also rejects the ones the methods of
+//! `Builder` could not have produced, which would otherwise make later calls panic or never
+//! finish: references to nodes or constraints past the end of the graph
+//! (`SerdeError::IndexOutOfRange`), checks with a bit width or list length `assert_range` and the
+//! like would refuse (`SerdeError::BadLength`), nodes whose operands or hint link lead back to
+//! them (`SerdeError::Cycle`) and constants without a value (`SerdeError::MissingConstant`).
+//! `SerdeError::code` names each kind of error.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::checks::Check;
+use crate::diagnostics::Location;
+use crate::error::SerdeError;
+use crate::{Builder, Node, OPERATION};
+
+/// The version of the format written by `to_json`.
+///
+/// 1. The format before versions were recorded. Documents without a `version` are version 1.
+/// 2. Records the `version`, so that changes to the format can be told apart.
+/// 3. Records the operation of custom nodes in `customs`.
+/// 4. Records aliased nodes in `aliases`, each with the representative of its class.
+pub const FORMAT_VERSION: u32 = 4;
+
+/// Upgrades a document from version `i + 1` to version `i + 2`.
+const MIGRATIONS: [fn(&mut Map<String, Value>); FORMAT_VERSION as usize - 1] =
+    [v1_to_v2, v2_to_v3, v3_to_v4];
+
+fn v1_to_v2(document: &mut Map<String, Value>) {
+    document.insert("version".to_string(), Value::from(2));
+}
+
+fn v2_to_v3(document: &mut Map<String, Value>) {
+    document.insert("version".to_string(), Value::from(3));
+}
+
+fn v3_to_v4(document: &mut Map<String, Value>) {
+    document.insert("version".to_string(), Value::from(4));
+}
+
+/// The part of a document read to find its version.
+#[derive(Deserialize)]
+struct Header {
+    #[serde(default = "first_version")]
+    version: u64,
+}
+
+fn first_version() -> u64 {
+    1
+}
+
+/// The on-disk representation of a builder.
+#[derive(Serialize, Deserialize)]
+struct GraphFile {
+    version: u32,
+    nodes: Vec<Node>,
+    #[serde(default)]
+    inputs: Vec<usize>,
+    #[serde(default)]
+    labels: BTreeMap<usize, String>,
+    #[serde(default)]
+    hints: BTreeMap<usize, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    customs: BTreeMap<usize, String>,
+    #[serde(default)]
+    constraints: Vec<(usize, usize)>,
+    #[serde(default)]
+    public: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    secret: Vec<usize>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    locations: BTreeMap<usize, Location>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    constraint_locations: BTreeMap<usize, Location>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    constraint_messages: BTreeMap<usize, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    soft_constraints: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<(usize, usize)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    checks: Vec<Check>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<usize, BTreeMap<String, serde_json::Value>>,
+}
+
+/// The nodes `node` reads, as `Graph::push_node` interprets it, or as `Graph::push_custom` does
+/// for a `custom` node.
+fn reads(node: &Node, custom: bool) -> Vec<usize> {
+    if custom {
+        return [node.inputs.0, node.inputs.1, node.addend]
+            .into_iter()
+            .flatten()
+            .collect();
+    }
+    match (node.op, node.inputs, node.hint) {
+        (Some(op), (Some(a), Some(b)), _) => {
+            let addend = node.addend.filter(|_| matches!(op, OPERATION::MUL));
+            [a, b].into_iter().chain(addend).collect()
+        }
+        (_, _, Some(linked)) => vec![linked],
+        _ => Vec::new(),
+    }
+}
+
+impl GraphFile {
+    /// Checks that the document describes a graph the methods of `Builder` could have built, see
+    /// the module documentation.
+    fn validate(&self) -> Result<(), SerdeError> {
+        let len = self.nodes.len();
+        let in_range = |at: &dyn Fn() -> String, index: usize, len: usize| {
+            if index < len {
+                Ok(())
+            } else {
+                Err(SerdeError::IndexOutOfRange {
+                    at: at(),
+                    index,
+                    len,
+                })
+            }
+        };
+        if let Some(&node) = self.customs.keys().next_back() {
+            in_range(&|| "`customs`".to_string(), node, len)?;
+        }
+        let reads: Vec<Vec<usize>> = (self.nodes.iter().enumerate())
+            .map(|(id, node)| reads(node, self.customs.contains_key(&id)))
+            .collect();
+        for (id, reads) in reads.iter().enumerate() {
+            for &read in reads {
+                in_range(&|| format!("node {}", id), read, len)?;
+            }
+        }
+        if let Some(&node) = self.customs.keys().find(|&&id| reads[id].is_empty()) {
+            return Err(SerdeError::BadLength {
+                at: format!("the operands of custom node {}", node),
+                found: 0,
+                expected: format!("1 to {}", crate::custom::MAX_OPERANDS),
+            });
+        }
+        for &node in &self.inputs {
+            in_range(&|| "`inputs`".to_string(), node, len)?;
+        }
+        // Nodes reading nothing that are not inputs are loaded as constants.
+        let inputs: HashSet<usize> = self.inputs.iter().copied().collect();
+        if let Some(node) = (0..len).find(|&id| {
+            reads[id].is_empty() && !inputs.contains(&id) && self.nodes[id].output.is_none()
+        }) {
+            return Err(SerdeError::MissingConstant { node });
+        }
+        let lists = [("public", &self.public), ("secret", &self.secret)];
+        for (name, list) in lists {
+            for &node in list {
+                in_range(&|| format!("`{}`", name), node, len)?;
+            }
+        }
+        let maps = [
+            ("labels", self.labels.keys().next_back()),
+            ("hints", self.hints.keys().next_back()),
+            ("locations", self.locations.keys().next_back()),
+            ("metadata", self.metadata.keys().next_back()),
+        ];
+        for (name, last) in maps {
+            if let Some(&node) = last {
+                in_range(&|| format!("`{}`", name), node, len)?;
+            }
+        }
+        for (i, &(a, b)) in self.constraints.iter().enumerate() {
+            in_range(&|| format!("constraint {}", i), a, len)?;
+            in_range(&|| format!("constraint {}", i), b, len)?;
+        }
+        for &(node, representative) in &self.aliases {
+            in_range(&|| format!("the alias of node {}", node), node, len)?;
+            in_range(
+                &|| format!("the alias of node {}", node),
+                representative,
+                len,
+            )?;
+        }
+        let constraints = self.constraints.len();
+        let constraint_maps = [
+            (
+                "constraint_locations",
+                self.constraint_locations.keys().next_back(),
+            ),
+            (
+                "constraint_messages",
+                self.constraint_messages.keys().next_back(),
+            ),
+            ("soft_constraints", self.soft_constraints.iter().max()),
+        ];
+        for (name, last) in constraint_maps {
+            if let Some(&constraint) = last {
+                in_range(&|| format!("`{}`", name), constraint, constraints)?;
+            }
+        }
+        for (i, check) in self.checks.iter().enumerate() {
+            check.validate(i, len)?;
+        }
+
+        // Only forward references can close a cycle.
+        if reads
+            .iter()
+            .enumerate()
+            .all(|(id, reads)| reads.iter().all(|&read| read < id))
+        {
+            return Ok(());
+        }
+        // A depth-first search, where `Some(false)` marks the nodes on the current path.
+        let mut done: Vec<Option<bool>> = vec![None; len];
+        for start in 0..len {
+            if done[start].is_some() {
+                continue;
+            }
+            done[start] = Some(false);
+            let mut path = vec![(start, 0)];
+            while let Some((node, next)) = path.last_mut() {
+                let Some(&read) = reads[*node].get(*next) else {
+                    done[*node] = Some(true);
+                    path.pop();
+                    continue;
+                };
+                *next += 1;
+                match done[read] {
+                    None => {
+                        done[read] = Some(false);
+                        path.push((read, 0));
+                    }
+                    Some(false) => return Err(SerdeError::Cycle { node: read }),
+                    Some(true) => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Builder {
+    /// Serializes the graph (including any filled in outputs) to a JSON string.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(nodes = self.graph.len()))
+    )]
+    pub fn to_json(&self) -> String {
+        let file = GraphFile {
+            version: FORMAT_VERSION,
+            nodes: self.graph.iter().collect(),
+            inputs: self.input_nodes.clone(),
+            labels: self.labels.clone(),
+            hints: self.hint_calls.clone(),
+            customs: self.custom_calls.clone(),
+            constraints: self.constraints.clone(),
+            public: self.public.iter().copied().collect(),
+            secret: (0..self.graph.len())
+                .filter(|&id| self.graph.is_secret(id))
+                .collect(),
+            locations: self.locations.clone(),
+            constraint_locations: self.constraint_locations.clone(),
+            constraint_messages: self.constraint_messages.clone(),
+            soft_constraints: self.soft_constraints.iter().copied().collect(),
+            aliases: self
+                .alias_pairs()
+                .map(|(root, node)| (node, root))
+                .collect(),
+            checks: self.checks.clone(),
+            metadata: self.metadata.clone(),
+        };
+        serde_json::to_string_pretty(&file).unwrap()
+    }
+
+    /// Deserializes a graph produced by `to_json`, by this or an earlier version of the library,
+    /// rejecting documents that don't describe a valid graph, see the `json` module.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(bytes = json.len()))
+    )]
+    pub fn from_json(json: &str) -> Result<Builder, SerdeError> {
+        let version = serde_json::from_str::<Header>(json)?.version;
+        let mut file: GraphFile = if version == u64::from(FORMAT_VERSION) {
+            serde_json::from_str(json)?
+        } else if (1..u64::from(FORMAT_VERSION)).contains(&version) {
+            let mut document: Map<String, Value> = serde_json::from_str(json)?;
+            for migrate in &MIGRATIONS[version as usize - 1..] {
+                migrate(&mut document);
+            }
+            serde_json::from_value(Value::Object(document))?
+        } else {
+            return Err(SerdeError::UnsupportedVersion {
+                found: version,
+                supported: FORMAT_VERSION,
+            });
+        };
+        file.validate()?;
+        for list in [&mut file.inputs, &mut file.secret] {
+            list.sort_unstable();
+            list.dedup();
+        }
+        for check in &mut file.checks {
+            if let Check::InSet { set, .. } = check {
+                set.sort_unstable();
+                set.dedup();
+            }
+        }
+        let mut builder = Builder::new();
+        for (id, node) in file.nodes.iter().enumerate() {
+            if file.customs.contains_key(&id) {
+                builder.graph.push_custom(&reads(node, true), node.output);
+            } else {
+                builder
+                    .graph
+                    .push_node(node, file.inputs.binary_search(&id).is_ok());
+            }
+            if file.secret.binary_search(&id).is_ok() {
+                builder.graph.mark_secret(id);
+            }
+        }
+        // Only nodes loaded as inputs can be assigned.
+        file.inputs
+            .retain(|&id| builder.graph.kind(id) == crate::storage::Kind::Input);
+        builder.input_nodes = file.inputs;
+        builder.labels = file.labels;
+        builder.hint_calls = file.hints;
+        builder.custom_calls = file.customs;
+        builder.constraints = file.constraints;
+        builder.public = file.public.into_iter().collect();
+        builder.locations = file.locations;
+        builder.constraint_locations = file.constraint_locations;
+        builder.constraint_messages = file.constraint_messages;
+        builder.soft_constraints = file.soft_constraints.into_iter().collect();
+        for (node, representative) in file.aliases {
+            builder.union(node, representative);
+        }
+        builder.checks = file.checks;
+        builder.metadata = file.metadata;
+        Ok(builder)
+    }
+}
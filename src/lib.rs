@@ -1,160 +1,1271 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use diagnostics::Severity;
+use error::{BuildError, ConstraintError, EvalError, FillReport, Unfillable, UnfilledNode, Value};
+use storage::Kind;
+
+pub mod alias;
+pub mod batch;
+pub mod bench;
+pub mod boolean;
+pub mod bounds;
+pub mod brand;
+pub mod cache;
+pub mod checkpoint;
+pub mod checks;
+pub mod commitment;
+#[cfg(feature = "zstd")]
+pub mod compress;
+pub mod custom;
+pub mod diagnostics;
+pub mod differential;
+pub mod edit;
+pub mod error;
+pub mod eval;
+pub mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(any(feature = "goldilocks", feature = "babybear", feature = "mersenne31"))]
+pub mod field;
+pub mod fingerprint;
+pub mod fixed;
+#[cfg(feature = "flatbuffers")]
+pub mod flat;
+pub mod fluent;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+pub mod gadgets;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+#[cfg(feature = "bellman")]
+pub mod groth16;
+pub mod html;
+pub mod journal;
+pub mod json;
+pub mod limits;
+pub mod lookup;
+pub mod memory;
+pub mod metrics;
+pub mod minimize;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod multilinear;
+pub mod mutation;
+pub mod nn;
+pub mod observer;
+#[cfg(feature = "onnx")]
+pub mod onnx;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod parser;
+pub mod passes;
+pub mod permutation;
+pub mod plonkish;
+pub mod profile;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+pub mod random;
+pub mod regions;
+pub mod report;
+pub mod rewrite;
+pub mod savepoint;
+pub mod schedule;
+pub mod signed;
+pub mod slice;
+pub mod snapshot;
+#[cfg(feature = "softfloat")]
+pub mod softfloat;
+pub mod solve;
+pub mod sp1;
+pub mod specialize;
+pub mod stepper;
+mod storage;
+pub mod stream;
+pub mod subcircuit;
+pub mod taint;
+pub mod template;
+#[cfg(feature = "proptest")]
+pub mod testing;
+pub mod trace;
+pub mod traversal;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod view;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod wires;
+
+/// Largest number of nodes a graph can hold. This is `u32::MAX` with the `narrow-indices` feature.
+pub const MAX_NODES: usize = storage::MAX_NODES;
+
+/// A function used to compute a hint value from the output of the node it links to.
+pub type HintFn = fn(u32) -> u32;
+
 /// A builder that will be used to create a computational graph and the hint graph.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Builder {
-    graph: Vec<Node>,
+    graph: storage::Graph,
+    input_nodes: Vec<usize>,
+    labels: BTreeMap<usize, String>,
+    hint_fns: HashMap<String, HintFn>,
+    hint_calls: BTreeMap<usize, String>, // Hint node index -> name of the hint function computing its output.
+    custom_ops: HashMap<String, custom::CustomOp>, // Operations custom nodes can name.
+    custom_calls: BTreeMap<usize, String>, // Custom node index -> name of its operation.
+    constraints: Vec<(usize, usize)>,    // Pairs of nodes whose outputs must be equal.
+    public: BTreeSet<usize>,             // Nodes marked as public outputs.
+    locations: BTreeMap<usize, diagnostics::Location>, // Where nodes were defined in a parsed program.
+    constraint_locations: BTreeMap<usize, diagnostics::Location>, // Where constraints were asserted.
+    constraint_messages: BTreeMap<usize, String>, // Messages reported when constraints fail.
+    soft_constraints: BTreeSet<usize>, // Constraints that only warn when they don't hold.
+    aliases: BTreeMap<usize, usize>,   // Union-find parent of each aliased node, roots left out.
+    checks: Vec<checks::Check>,        // Constraints other than equalities, e.g. orderings.
+    metadata: BTreeMap<usize, BTreeMap<String, serde_json::Value>>, // User-defined annotations of nodes.
+    metrics: Option<Arc<metrics::Metrics>>,
+    observers: Vec<Arc<dyn observer::Observer>>,
+    limits: limits::Limits,
+    depths: Vec<usize>, // Depth of each node, only tracked while a depth limit is set.
+    generation: u32, // Stamped into every `NodeId` handed out, so ids from another graph are caught.
+    ancestors: Vec<(u32, usize)>, // Generations before each rollback, and how many of their nodes are left.
+    reveal_secrets: bool,         // Print values of secret nodes instead of redacting them.
+    views: view::Views,           // Node views handed out by `Index`, dropped on reset and growth.
+}
+
+/// Source of builder generations. Every new or cleared builder takes the next one.
+static NEXT_GENERATION: AtomicU32 = AtomicU32::new(0);
+
+/// Identifies a node of a particular builder.
+///
+/// Besides the node's index, an id carries the generation of the builder that created it.
+/// Using an id with a builder that has since been cleared, or with an unrelated builder,
+/// panics instead of silently reading whichever node now sits at that index. The `Wire`s of
+/// `Builder::branded` catch this at compile time instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId {
+    index: usize,
+    generation: u32,
 }
 
-/// A node in the computational graph.
-#[derive(Debug, Copy, Clone)]
+impl NodeId {
+    /// The position of the node in the graph, as used by `Witness::values`, `levels` and the
+    /// serialized formats.
+    pub fn index(self) -> usize {
+        self.index
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.index)
+    }
+}
+
+/// A node in the computational graph. Node references are plain indices into the graph.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Node {
     pub id: usize,
     pub inputs: (Option<usize>, Option<usize>), // (Some(a), Some(b)) are indices of nodes whose outputs are used as inputs for the current node.
     pub op: Option<OPERATION>,
     pub output: Option<u32>,
     pub hint: Option<usize>,
+    /// The node added to the product of a mul node created with `mul_add`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub addend: Option<usize>,
+}
+
+/// A borrowed node of a builder, returned by `Builder::at`, for reading a node's properties
+/// without passing its id to each getter.
+#[derive(Debug, Clone, Copy)]
+pub struct NodeRef<'a> {
+    builder: &'a Builder,
+    id: NodeId,
+}
+
+impl<'a> NodeRef<'a> {
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    pub fn label(&self) -> Option<&'a str> {
+        self.builder.label(self.id)
+    }
+
+    pub fn output(&self) -> Option<u32> {
+        self.builder.output(self.id)
+    }
+
+    pub fn is_constant(&self) -> bool {
+        self.builder.is_constant(self.id)
+    }
+
+    pub fn is_secret(&self) -> bool {
+        self.builder.is_secret(self.id)
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.builder.public.contains(&self.id.index)
+    }
+
+    /// A by-value view of the node's operation, operands and output.
+    pub fn view(&self) -> Node {
+        self.builder.graph.node(self.id.index)
+    }
+
+    /// The nodes computed directly from this one.
+    pub fn consumers(&self) -> impl Iterator<Item = NodeId> + 'a {
+        self.builder.consumers(self.id)
+    }
 }
 
 /// The operations that can be performed in the computational graph.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum OPERATION {
     ADD,
     MUL,
 }
 
+impl OPERATION {
+    /// Computes the output of a node with this operation from its two input values.
+    pub fn apply(self, a: u32, b: u32) -> u32 {
+        match self {
+            OPERATION::ADD => a + b,
+            OPERATION::MUL => a * b,
+        }
+    }
+
+    /// Like `apply`, but returns None if the result does not fit in a u32.
+    pub fn checked_apply(self, a: u32, b: u32) -> Option<u32> {
+        match self {
+            OPERATION::ADD => a.checked_add(b),
+            OPERATION::MUL => a.checked_mul(b),
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            OPERATION::ADD => "+",
+            OPERATION::MUL => "*",
+        }
+    }
+}
+
+/// The output values of every node in a graph, indexed by node id.
+/// Nodes that have not been filled in are `None`.
+///
+/// `Debug` prints the values of secret nodes as `<redacted>`. The values themselves, and the
+/// serialized witness, are unaffected.
+#[derive(Clone, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Witness {
+    pub values: Vec<Option<u32>>,
+    #[serde(skip)]
+    redacted: BTreeSet<usize>, // Nodes whose values `Debug` leaves out.
+}
+
+impl PartialEq for Witness {
+    fn eq(&self, other: &Self) -> bool {
+        self.values == other.values
+    }
+}
+
+impl fmt::Debug for Witness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values: Vec<_> = self
+            .values
+            .iter()
+            .enumerate()
+            .map(|(id, value)| value.map(|value| Value::new(value, self.redacted.contains(&id))))
+            .collect();
+        f.debug_struct("Witness").field("values", &values).finish()
+    }
+}
+
+/// Overwrites the values in memory, so secrets don't linger after the witness is gone.
+#[cfg(feature = "zeroize")]
+impl Drop for Witness {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.values);
+    }
+}
+
+impl From<Vec<Option<u32>>> for Witness {
+    fn from(values: Vec<Option<u32>>) -> Self {
+        Witness {
+            values,
+            redacted: BTreeSet::new(),
+        }
+    }
+}
+
+impl fmt::Debug for Builder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outputs: Vec<_> = (0..self.graph.len())
+            .map(|id| self.graph.output(id).map(|value| self.redact(id, value)))
+            .collect();
+        f.debug_struct("Builder")
+            .field("graph", &self.graph)
+            .field("outputs", &outputs)
+            .field("input_nodes", &self.input_nodes)
+            .field("labels", &self.labels)
+            .field("hint_fns", &self.hint_fns)
+            .field("hint_calls", &self.hint_calls)
+            .field("custom_ops", &self.custom_ops)
+            .field("custom_calls", &self.custom_calls)
+            .field("constraints", &self.constraints)
+            .field("public", &self.public)
+            .field("locations", &self.locations)
+            .field("constraint_locations", &self.constraint_locations)
+            .field("constraint_messages", &self.constraint_messages)
+            .field("soft_constraints", &self.soft_constraints)
+            .field("aliases", &self.aliases)
+            .field("checks", &self.checks)
+            .field("metadata", &self.metadata)
+            .field("metrics", &self.metrics)
+            .field("observers", &self.observers.len())
+            .field("limits", &self.limits)
+            .field("depths", &self.depths)
+            .field("generation", &self.generation)
+            .field("ancestors", &self.ancestors)
+            .field("reveal_secrets", &self.reveal_secrets)
+            .finish()
+    }
+}
+
 impl Builder {
     /// Creates a new builder.
     pub fn new() -> Self {
-        Builder { graph: Vec::new() }
+        let mut builder = Builder {
+            graph: storage::Graph::default(),
+            input_nodes: Vec::new(),
+            labels: BTreeMap::new(),
+            hint_fns: HashMap::new(),
+            hint_calls: BTreeMap::new(),
+            custom_ops: HashMap::new(),
+            custom_calls: BTreeMap::new(),
+            constraints: Vec::new(),
+            public: BTreeSet::new(),
+            locations: BTreeMap::new(),
+            constraint_locations: BTreeMap::new(),
+            constraint_messages: BTreeMap::new(),
+            soft_constraints: BTreeSet::new(),
+            aliases: BTreeMap::new(),
+            checks: Vec::new(),
+            metadata: BTreeMap::new(),
+            metrics: None,
+            observers: Vec::new(),
+            limits: limits::Limits::default(),
+            depths: Vec::new(),
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+            ancestors: Vec::new(),
+            reveal_secrets: false,
+            views: view::Views::default(),
+        };
+        builder.register_hint("sqrt", u32::isqrt);
+        builder.register_hint("not", |x| u32::from(x == 0));
+        for (name, f) in fixed::HINTS {
+            builder.register_hint(name, f);
+        }
+        for (name, f) in signed::HINTS {
+            builder.register_hint(name, f);
+        }
+        builder
+    }
+
+    /// Creates a new builder with room for `nodes` nodes, for generators that know how big
+    /// their graph will get.
+    pub fn with_capacity(nodes: usize) -> Self {
+        let mut builder = Builder::new();
+        builder.reserve(nodes);
+        builder
+    }
+
+    /// Reserves room for at least `additional` more nodes, so adding them does not reallocate.
+    pub fn reserve(&mut self, additional: usize) {
+        self.graph.reserve(additional);
+    }
+
+    /// Releases unused node capacity once the graph is built.
+    pub fn shrink_to_fit(&mut self) {
+        self.graph.shrink_to_fit();
+        self.input_nodes.shrink_to_fit();
+        self.constraints.shrink_to_fit();
+        self.checks.shrink_to_fit();
+    }
+
+    /// Number of nodes the graph can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.graph.capacity()
     }
 
     /// Initializes a node in the graph.
-    pub fn init(&mut self) -> usize {
-        let node_id = self.graph.len();
+    pub fn init(&mut self) -> NodeId {
+        self.try_init().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `init`, but returns an error if the graph has reached its node limit.
+    pub fn try_init(&mut self) -> Result<NodeId, BuildError> {
         let new_node = Node {
-            id: node_id,
+            id: self.graph.len(),
             inputs: (None, None),
             op: None,
             output: None,
             hint: None,
+            addend: None,
         };
-        self.graph.push(new_node);
-        node_id
+        let id = self.try_push_node(new_node, &[])?;
+        self.input_nodes.push(id.index);
+        Ok(id)
+    }
+
+    /// Like `init`, but the input is secret: its value, and the value of every node computed
+    /// from it, is printed as `<redacted>` by `Debug`, evaluation traces and errors.
+    pub fn private_input(&mut self) -> NodeId {
+        let id = self.init();
+        self.graph.mark_secret(id.index);
+        id
+    }
+
+    /// Returns true if the node is a private input or is computed from one.
+    pub fn is_secret(&self, id: NodeId) -> bool {
+        self.graph.is_secret(self.index(id))
+    }
+
+    /// Prints the values of secret nodes in debug output, traces and errors instead of redacting
+    /// them, e.g. while debugging a circuit with test inputs.
+    pub fn set_reveal_secrets(&mut self, reveal: bool) {
+        self.reveal_secrets = reveal;
+    }
+
+    /// Wraps the value of a node for quoting in an error or report.
+    pub(crate) fn redact(&self, node: usize, value: u32) -> Value {
+        Value::new(value, !self.reveal_secrets && self.graph.is_secret(node))
     }
 
     /// Initializes a node in a graph, set to a constant value.
-    pub fn constant(&mut self, val: u32) -> usize {
-        let node_id = self.graph.len();
+    pub fn constant(&mut self, val: u32) -> NodeId {
+        self.try_constant(val)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `constant`, but returns an error if the graph has reached its node limit.
+    pub fn try_constant(&mut self, val: u32) -> Result<NodeId, BuildError> {
         let new_node = Node {
-            id: node_id,
+            id: self.graph.len(),
             inputs: (None, None),
             op: None,
             output: Some(val),
             hint: None,
+            addend: None,
         };
-        self.graph.push(new_node);
-        node_id
+        self.try_push_node(new_node, &[])
     }
+
     /// Creates a new node by adding two nodes in the graph, returning the index of the new node in the graph.
-    pub fn add(&mut self, a: usize, b: usize) -> usize {
-        let node_id = self.graph.len();
+    pub fn add(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.try_add(a, b).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `add`, but returns an error if an id does not belong to this builder or the new node
+    /// would exceed a limit.
+    pub fn try_add(&mut self, a: NodeId, b: NodeId) -> Result<NodeId, BuildError> {
+        self.try_binary(OPERATION::ADD, a, b)
+    }
+
+    /// Multiplies two nodes in the graph, returning the index of the new node in the graph.
+    pub fn mul(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.try_mul(a, b).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `mul`, but returns an error if an id does not belong to this builder or the new node
+    /// would exceed a limit.
+    pub fn try_mul(&mut self, a: NodeId, b: NodeId) -> Result<NodeId, BuildError> {
+        self.try_binary(OPERATION::MUL, a, b)
+    }
+
+    /// Squares a node. The node is the canonical squaring `a * a`, which the optimizer keeps and
+    /// exporters recognize.
+    pub fn square(&mut self, a: NodeId) -> NodeId {
+        self.try_square(a).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `square`, but returns an error if `a` does not belong to this builder or the new node
+    /// would exceed a limit.
+    pub fn try_square(&mut self, a: NodeId) -> Result<NodeId, BuildError> {
+        self.try_binary(OPERATION::MUL, a, a)
+    }
+
+    /// Doubles a node. The node is the canonical doubling `a + a`, which `passes::Simplify` also
+    /// turns `a * 2` into.
+    pub fn double(&mut self, a: NodeId) -> NodeId {
+        self.try_double(a).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `double`, but returns an error if `a` does not belong to this builder or the new node
+    /// would exceed a limit.
+    pub fn try_double(&mut self, a: NodeId) -> Result<NodeId, BuildError> {
+        self.try_binary(OPERATION::ADD, a, a)
+    }
+
+    /// Computes `a * b + c` in a single node. It is a single assertion in the SMT export and a
+    /// single op on the GPU; R1CS needs an auxiliary product variable for it.
+    pub fn mul_add(&mut self, a: NodeId, b: NodeId, c: NodeId) -> NodeId {
+        self.try_mul_add(a, b, c)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `mul_add`, but returns an error if an id does not belong to this builder or the new
+    /// node would exceed a limit.
+    pub fn try_mul_add(&mut self, a: NodeId, b: NodeId, c: NodeId) -> Result<NodeId, BuildError> {
+        let (a, b, c) = (self.resolve(a)?, self.resolve(b)?, self.resolve(c)?);
         let new_node = Node {
-            id: node_id,
+            id: self.graph.len(),
             inputs: (Some(a), Some(b)),
-            op: Some(OPERATION::ADD),
+            op: Some(OPERATION::MUL),
             output: None,
             hint: None,
+            addend: Some(c),
         };
-        self.graph.push(new_node);
-        node_id
+        self.try_push_node(new_node, &[a, b, c])
     }
 
-    /// Multiplies two nodes in the graph, returning the index of the new node in the graph.
-    pub fn mul(&mut self, a: usize, b: usize) -> usize {
-        let node_id = self.graph.len();
+    fn try_binary(&mut self, op: OPERATION, a: NodeId, b: NodeId) -> Result<NodeId, BuildError> {
+        let (a, b) = (self.resolve(a)?, self.resolve(b)?);
         let new_node = Node {
-            id: node_id,
+            id: self.graph.len(),
             inputs: (Some(a), Some(b)),
-            op: Some(OPERATION::MUL),
+            op: Some(op),
             output: None,
             hint: None,
+            addend: None,
         };
-        self.graph.push(new_node);
-        node_id
+        self.try_push_node(new_node, &[a, b])
     }
 
     /// Hint value is externally computed.  We "link" it to the node that it is dependent on.
-    pub fn hint(&mut self, hint_value: u32, hint_node: usize) -> usize {
-        let node_id = self.graph.len();
+    pub fn hint(&mut self, hint_value: u32, hint_node: NodeId) -> NodeId {
+        self.try_hint(hint_value, hint_node)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `hint`, but returns an error if `hint_node` does not belong to this builder or the
+    /// graph has reached its node limit.
+    pub fn try_hint(&mut self, hint_value: u32, hint_node: NodeId) -> Result<NodeId, BuildError> {
+        let hint_node = self.resolve(hint_node)?;
         let new_node = Node {
-            id: node_id,
+            id: self.graph.len(),
             inputs: (None, None),
             op: None,
             output: Some(hint_value),
             hint: Some(hint_node),
+            addend: None,
         };
-        self.graph.push(new_node);
-        node_id
+        self.try_push_node(new_node, &[])
+    }
+
+    /// Returns the id of the node at `index`, or None if the graph has no such node.
+    pub fn node_id(&self, index: usize) -> Option<NodeId> {
+        (index < self.graph.len()).then(|| self.id(index))
+    }
+
+    /// Returns true if `id` refers to a node of this builder.
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.resolve(id).is_ok()
+    }
+
+    /// Removes every node, label and constraint, keeping registered hint functions, custom
+    /// operations and metrics.
+    /// Ids handed out before the builder was cleared are rejected from then on.
+    pub fn clear(&mut self) {
+        #[cfg(feature = "zeroize")]
+        self.graph.clear_outputs(|_, _| false);
+        self.graph = storage::Graph::default();
+        self.input_nodes.clear();
+        self.labels.clear();
+        self.hint_calls.clear();
+        self.custom_calls.clear();
+        self.constraints.clear();
+        self.public.clear();
+        self.locations.clear();
+        self.constraint_locations.clear();
+        self.constraint_messages.clear();
+        self.soft_constraints.clear();
+        self.aliases.clear();
+        self.checks.clear();
+        self.metadata.clear();
+        self.depths.clear();
+        self.generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+        self.ancestors.clear();
+        self.views = view::Views::default();
+    }
+
+    /// Forgets every value filled in by evaluation or loaded from a witness, so the graph can be
+    /// filled in again with other inputs. Constants and hints with a fixed value are kept.
+    /// With the `zeroize` feature the forgotten values are also overwritten in memory.
+    pub fn reset(&mut self) {
+        let hint_calls = &self.hint_calls;
+        self.graph.clear_outputs(|id, kind| match kind {
+            Kind::Constant => true,
+            Kind::Hint => !hint_calls.contains_key(&id),
+            Kind::Input | Kind::Add | Kind::Mul | Kind::MulAdd | Kind::Custom => false,
+        });
+        self.views = view::Views::default();
+    }
+
+    /// Turns a node index of this builder into an id.
+    pub(crate) fn id(&self, index: usize) -> NodeId {
+        NodeId {
+            index,
+            generation: self.generation_of(index),
+        }
+    }
+
+    /// Resolves an id to the node's index, checking that it belongs to this builder.
+    pub fn resolve(&self, id: NodeId) -> Result<usize, BuildError> {
+        if id.generation != self.generation_of(id.index) {
+            Err(BuildError::StaleNode { node: id.index })
+        } else if id.index >= self.graph.len() {
+            Err(BuildError::MissingNode { node: id.index })
+        } else {
+            Ok(id.index)
+        }
+    }
+
+    /// Like `resolve`, but panics if the id does not belong to this builder.
+    pub(crate) fn index(&self, id: NodeId) -> usize {
+        self.resolve(id).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Adds a node computed from `operands` to the graph, unless it would exceed a limit. Leaf
+    /// nodes without an output are inputs.
+    fn try_push_node(&mut self, node: Node, operands: &[usize]) -> Result<NodeId, BuildError> {
+        if let Some(depth) = self.check_node_limits(operands)? {
+            self.depths.push(depth);
+        }
+        let is_input = node.output.is_none();
+        self.graph.push_node(&node, is_input);
+        self.views = view::Views::default();
+        self.report_node(node.id);
+        Ok(self.id(node.id))
+    }
+
+    /// Records node creation, evaluation and constraint checking counters into `metrics`.
+    /// Clones of this builder keep reporting to the same counters.
+    pub fn set_metrics(&mut self, metrics: Arc<metrics::Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Registers a named hint function that can be referenced by `hint_fn` (and by the parser).
+    /// `sqrt` (integer square root) and `not` (1 for 0, else 0) are registered by default.
+    pub fn register_hint(&mut self, name: &str, f: HintFn) {
+        self.hint_fns.insert(name.to_string(), f);
+    }
+
+    /// Returns true if a hint function with the given name has been registered.
+    pub fn has_hint(&self, name: &str) -> bool {
+        self.hint_fns.contains_key(name)
+    }
+
+    /// Like `hint`, but the hint value is computed by the registered hint function `name`
+    /// from the output of `hint_node` while the graph is being filled.
+    ///
+    /// Panics if no hint function named `name` is registered.
+    pub fn hint_fn(&mut self, name: &str, hint_node: NodeId) -> NodeId {
+        self.try_hint_fn(name, hint_node)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `hint_fn`, but returns an error if no hint function named `name` is registered,
+    /// `hint_node` does not belong to this builder or the new node would exceed a limit.
+    pub fn try_hint_fn(&mut self, name: &str, hint_node: NodeId) -> Result<NodeId, BuildError> {
+        if !self.has_hint(name) {
+            return Err(BuildError::UnknownHint {
+                name: name.to_string(),
+            });
+        }
+        let hint_node = self.resolve(hint_node)?;
+        let new_node = Node {
+            id: self.graph.len(),
+            inputs: (None, None),
+            op: None,
+            output: None,
+            hint: Some(hint_node),
+            addend: None,
+        };
+        let id = self.try_push_node(new_node, &[hint_node])?;
+        self.hint_calls.insert(id.index, name.to_string());
+        Ok(id)
+    }
+
+    /// Attaches a human readable label to a node.
+    pub fn set_label(&mut self, id: NodeId, label: &str) {
+        self.labels.insert(self.index(id), label.to_string());
+    }
+
+    /// Returns the label of a node, if it has one.
+    pub fn label(&self, id: NodeId) -> Option<&str> {
+        self.labels.get(&self.index(id)).map(|label| label.as_str())
+    }
+
+    /// Attaches a user-defined value to a node under `key`, replacing any previous value, e.g. the
+    /// position in a source program or the shape of a tensor. Metadata is saved by `to_json` and
+    /// shown by `export::to_dot`, but doesn't affect evaluation, checks or the fingerprint.
+    pub fn set_meta(&mut self, id: NodeId, key: &str, value: impl Into<serde_json::Value>) {
+        self.metadata
+            .entry(self.index(id))
+            .or_default()
+            .insert(key.to_string(), value.into());
+    }
+
+    /// Returns the metadata stored under `key` for a node.
+    pub fn meta(&self, id: NodeId, key: &str) -> Option<&serde_json::Value> {
+        self.metadata.get(&self.index(id))?.get(key)
+    }
+
+    /// Returns the first node with the given label.
+    pub fn find_label(&self, label: &str) -> Option<NodeId> {
+        self.labels
+            .iter()
+            .find(|(_, l)| l.as_str() == label)
+            .map(|(&id, _)| self.id(id))
+    }
+
+    /// Looks up a node by label, or else by its index written as a number.
+    pub fn find_node(&self, name: &str) -> Option<NodeId> {
+        self.find_label(name)
+            .or_else(|| self.node_id(name.parse().ok()?))
+    }
+
+    /// Returns the variable input nodes created with `init`.
+    pub fn inputs(&self) -> Vec<NodeId> {
+        self.input_nodes.iter().map(|&id| self.id(id)).collect()
+    }
+
+    /// Marks a node as a public output of the circuit: a value the circuit is meant to produce,
+    /// as opposed to an intermediate value.
+    pub fn mark_public(&mut self, id: NodeId) {
+        self.public.insert(self.index(id));
+    }
+
+    /// Returns the nodes marked with `mark_public`, in index order.
+    pub fn public_outputs(&self) -> Vec<NodeId> {
+        self.public.iter().map(|&id| self.id(id)).collect()
+    }
+
+    /// Records a constraint that the outputs of 2 nodes must be equal.
+    /// Unlike `assert_equal`, nothing is checked here: the constraint is verified by `check_constraints`.
+    pub fn enforce_equal(&mut self, a: NodeId, b: NodeId) {
+        self.try_enforce_equal(a, b)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `enforce_equal`, but returns an error if an id does not belong to this builder or the
+    /// graph has reached its constraint limit.
+    pub fn try_enforce_equal(&mut self, a: NodeId, b: NodeId) -> Result<(), BuildError> {
+        let (a, b) = (self.resolve(a)?, self.resolve(b)?);
+        self.add_constraint()?;
+        self.constraints.push((a, b));
+        self.report_constraint(observer::Constraint::Equality(self.constraints.len() - 1));
+        Ok(())
+    }
+
+    /// Like `enforce_equal`, with a message that is included in the report when the constraint
+    /// does not hold, e.g. "balance must round-trip".
+    pub fn enforce_equal_with(&mut self, a: NodeId, b: NodeId, message: &str) {
+        self.enforce_equal(a, b);
+        self.constraint_messages
+            .insert(self.constraints.len() - 1, message.to_string());
+    }
+
+    /// Records an equality constraint with the `Warning` severity: when it does not hold,
+    /// `check_constraints` doesn't fail (it emits a `tracing` warning with `message` under the
+    /// `tracing` feature), and `check` and `is_satisfied` ignore it. Use `warnings` to list the
+    /// ones that don't hold.
+    pub fn enforce_equal_warning(&mut self, a: NodeId, b: NodeId, message: &str) {
+        self.enforce_equal_with(a, b, message);
+        self.soft_constraints.insert(self.constraints.len() - 1);
+    }
+
+    /// Returns the severity of the constraint at `constraint`, counting from 0 in the order
+    /// constraints were recorded.
+    pub fn severity(&self, constraint: usize) -> Severity {
+        if self.soft_constraints.contains(&constraint) {
+            Severity::Warning
+        } else {
+            Severity::Error
+        }
     }
 
     /// Asserts that 2 nodes are equal for hinted values.
     /// This is a constraint that is checked after the graph is filled in.
     /// The first input should be the hint node, and the second input should be the node containing the output value that the hint node links to.
-    pub fn assert_equal(&self, a: usize, b: usize) -> bool {
-        let dependent_node_index = self.graph[a].hint.unwrap();
-        let dependent_output = self.graph[dependent_node_index].output.unwrap();
-        let hinted_output = self.graph[b].output.unwrap();
-        assert_eq!(dependent_output, hinted_output);
+    pub fn assert_equal(&self, a: NodeId, b: NodeId) -> bool {
+        self.assert_linked_equal(a, b, None)
+    }
+
+    /// Like `assert_equal`, with a message that prefixes the panic message.
+    pub fn assert_equal_with(&self, a: NodeId, b: NodeId, message: &str) -> bool {
+        self.assert_linked_equal(a, b, Some(message))
+    }
+
+    fn assert_linked_equal(&self, a: NodeId, b: NodeId, message: Option<&str>) -> bool {
+        let dependent_node_index = self.graph.linked(self.index(a)).unwrap();
+        let dependent_output = self.graph.output(dependent_node_index).unwrap();
+        let hinted_output = self.graph.output(self.index(b)).unwrap();
+        if dependent_output != hinted_output {
+            let prefix = message.map_or(String::new(), |message| format!("{}: ", message));
+            panic!(
+                "{}assertion failed: {} != {}",
+                prefix,
+                self.redact(dependent_node_index, dependent_output),
+                self.redact(self.index(b), hinted_output)
+            );
+        }
         true
     }
 
     /// Fills in all the nodes of the graph based on some inputs.
-    pub fn fill_nodes(&mut self, input_node: usize, input_val: u32) {
-        // We fill in the input value for the variable input node.
-        self.graph[input_node].output = Some(input_val);
+    pub fn fill_nodes(&mut self, input_node: NodeId, input_val: u32) {
+        self.fill_inputs(&[(input_node, input_val)]);
+    }
+
+    /// Like `fill_nodes`, but returns an error instead of panicking, see `try_fill_inputs`.
+    pub fn try_fill_nodes(&mut self, input_node: NodeId, input_val: u32) -> Result<(), EvalError> {
+        self.try_fill_inputs(&[(input_node, input_val)])
+    }
+
+    /// Fills in all the nodes of the graph for a graph with several input nodes.
+    /// Each pair is (input node, input value).
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(nodes = self.graph.len(), inputs = inputs.len()))
+    )]
+    pub fn fill_inputs(&mut self, inputs: &[(NodeId, u32)]) {
+        self.try_fill_inputs(inputs)
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    /// Like `fill_inputs`, but returns an error instead of panicking. An invalid assignment is
+    /// returned as `EvalError::Build` before anything is filled in. Otherwise every node that can
+    /// be computed is filled in and the others are left without a value: if there are any, they
+    /// are listed with the reason of each one in an `EvalError::Unfillable` report.
+    pub fn try_fill_inputs(&mut self, inputs: &[(NodeId, u32)]) -> Result<(), EvalError> {
+        let start = self.start_timer();
+        self.try_set_inputs(inputs)?;
 
         // We then iterate through the graph and fill in the values for the rest of the nodes.
-        // We use the `inputs` tuple to obtain the indices of the input nodes.
-        // Input and Constant nodes have a (None, None) tuple for inputs...no match block needed for that case.
+        let mut unfilled = Vec::new();
         for node in 0..self.graph.len() {
-            if let (Some(a), Some(b)) = self.graph[node].inputs {
-                let a_val = self.graph[a].output.unwrap();
-                let b_val = self.graph[b].output.unwrap();
-                match self.graph[node].op {
-                    Some(OPERATION::ADD) => {
-                        self.graph[node].output = Some(a_val + b_val);
+            let forward = self
+                .reads(node)
+                .into_iter()
+                .flatten()
+                .find(|&read| read >= node);
+            let reason = if let Some(operand) = forward {
+                Unfillable::ForwardReference { operand }
+            } else {
+                match self.try_fill_index(node) {
+                    Ok(())
+                        if self.graph.kind(node) == Kind::Input
+                            && self.graph.output(node).is_none() =>
+                    {
+                        Unfillable::MissingInput
+                    }
+                    Ok(()) => continue,
+                    Err(EvalError::MissingValue { operand, .. }) => {
+                        Unfillable::Unfilled { operand }
                     }
-                    Some(OPERATION::MUL) => {
-                        self.graph[node].output = Some(a_val * b_val);
+                    Err(EvalError::Build(BuildError::UnknownHint { name })) => {
+                        Unfillable::UnknownHint { name }
                     }
-                    None => {} // This should never be a case, but it's here to satisfy the compiler.
+                    Err(EvalError::Build(BuildError::UnknownOp { name })) => {
+                        Unfillable::UnknownOp { name }
+                    }
+                    Err(EvalError::CustomFailed { op, .. }) => Unfillable::CustomFailed { op },
+                    Err(EvalError::Overflow { op, a, b, .. }) => Unfillable::Overflow { op, a, b },
+                    Err(err) => return Err(err),
+                }
+            };
+            self.graph.set_output(node, None);
+            unfilled.push(UnfilledNode { node, reason });
+        }
+        self.record_evaluation(start);
+        if unfilled.is_empty() {
+            Ok(())
+        } else {
+            Err(FillReport { nodes: unfilled }.into())
+        }
+    }
+
+    /// Starts timing an evaluation, if anything is going to report the time. The clock is not
+    /// read otherwise, since it is unavailable on some targets such as `wasm32-unknown-unknown`.
+    pub(crate) fn start_timer(&self) -> Option<Instant> {
+        (cfg!(feature = "tracing") || self.metrics.is_some()).then(Instant::now)
+    }
+
+    /// Reports the end of an evaluation that started at `start` to tracing and metrics.
+    pub(crate) fn record_evaluation(&self, start: Option<Instant>) {
+        let Some(start) = start else {
+            return;
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            elapsed_us = start.elapsed().as_micros() as u64,
+            "filled graph"
+        );
+        if let Some(metrics) = &self.metrics {
+            metrics.evaluated(self.graph.len(), start.elapsed());
+        }
+    }
+
+    /// Sets the values of the variable input nodes without filling in the rest of the graph.
+    pub fn set_inputs(&mut self, inputs: &[(NodeId, u32)]) {
+        self.try_set_inputs(inputs)
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    /// Like `set_inputs`, but returns an error instead of panicking if a node is not an input of
+    /// this builder. No value is set in that case.
+    pub fn try_set_inputs(&mut self, inputs: &[(NodeId, u32)]) -> Result<(), BuildError> {
+        let mut resolved = Vec::with_capacity(inputs.len());
+        for &(input_node, input_val) in inputs {
+            let node = self.resolve(input_node)?;
+            if self.graph.kind(node) != Kind::Input {
+                return Err(BuildError::NotAnInput { node });
+            }
+            resolved.push((node, input_val));
+        }
+        for (node, input_val) in resolved {
+            self.graph.set_output(node, Some(input_val));
+        }
+        Ok(())
+    }
+
+    /// Computes the output of a single node from the outputs of the nodes it depends on,
+    /// which must already be filled in. Input and constant nodes are left untouched.
+    pub fn fill_node(&mut self, node: NodeId) {
+        self.fill_index(self.index(node));
+    }
+
+    pub(crate) fn fill_index(&mut self, node: usize) {
+        self.try_fill_index(node)
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    pub(crate) fn try_fill_index(&mut self, node: usize) -> Result<(), EvalError> {
+        let value = |operand: usize| {
+            self.graph
+                .output(operand)
+                .ok_or(EvalError::MissingValue { node, operand })
+        };
+        match self.graph.kind(node) {
+            // We use the input indices to obtain the outputs of the input nodes.
+            Kind::Add | Kind::Mul => {
+                let (a, b) = self.graph.operands(node).unwrap();
+                let (a_val, b_val) = (value(a)?, value(b)?);
+                let op = self.graph.op(node).unwrap();
+                let output = op.checked_apply(a_val, b_val).ok_or(EvalError::Overflow {
+                    node,
+                    op: op.symbol(),
+                    a: self.redact(a, a_val),
+                    b: self.redact(b, b_val),
+                })?;
+                self.graph.set_output(node, Some(output));
+            }
+            Kind::MulAdd => {
+                let [a, b, c] = self.graph.mul_add(node).unwrap();
+                let (a_val, b_val, c_val) = (value(a)?, value(b)?, value(c)?);
+                let product = a_val.checked_mul(b_val).ok_or(EvalError::Overflow {
+                    node,
+                    op: "*",
+                    a: self.redact(a, a_val),
+                    b: self.redact(b, b_val),
+                })?;
+                let output = product.checked_add(c_val).ok_or(EvalError::Overflow {
+                    node,
+                    op: "+",
+                    a: self.redact(node, product),
+                    b: self.redact(c, c_val),
+                })?;
+                self.graph.set_output(node, Some(output));
+            }
+            // Hint nodes backed by a hint function are computed from the node they link to.
+            Kind::Hint => {
+                if let Some(name) = self.hint_calls.get(&node) {
+                    let f = *self
+                        .hint_fns
+                        .get(name)
+                        .ok_or_else(|| BuildError::UnknownHint { name: name.clone() })?;
+                    let dependent_output = value(self.graph.linked(node).unwrap())?;
+                    self.graph.set_output(node, Some(f(dependent_output)));
                 }
             }
+            Kind::Custom => {
+                let name = &self.custom_calls[&node];
+                let op = self.node_op(node)?;
+                let operands = self.graph.custom(node).unwrap();
+                let values = operands
+                    .iter()
+                    .map(|&operand| value(operand))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let output = op
+                    .evaluate(&values)
+                    .ok_or_else(|| EvalError::CustomFailed {
+                        node,
+                        op: name.clone(),
+                    })?;
+                self.graph.set_output(node, Some(output));
+            }
+            // Input and Constant nodes already have their values.
+            Kind::Input | Kind::Constant => {}
+        }
+        self.report_evaluated(node);
+        Ok(())
+    }
+
+    /// Returns the dependency level of every node, indexed by node index: inputs, constants and fixed hints are at level 0,
+    /// and every other node is one level above the deepest node it depends on.
+    pub fn levels(&self) -> Vec<usize> {
+        let mut levels = vec![0; self.graph.len()];
+        for id in 0..self.graph.len() {
+            if let Some(level) = self
+                .reads(id)
+                .into_iter()
+                .flatten()
+                .map(|read| levels[read])
+                .max()
+            {
+                levels[id] = level + 1;
+            }
         }
+        levels
     }
 
     /// Given a graph that has `fill_nodes` already called on it
     /// checks that all the constraints hold.
+    /// Constraints with the `Warning` severity that don't hold are not failures: they are listed
+    /// by `warnings`, and emitted as `tracing` warnings with the `tracing` feature.
     pub fn check_constraints(&self) -> bool {
+        if let Err(violation) = self.check() {
+            panic!("{}", self.explain(&violation));
+        }
+        self.report_warnings();
+        true
+    }
+
+    /// Returns the constraints with the `Warning` severity that don't hold.
+    pub fn warnings(&self) -> Vec<ConstraintError> {
+        self.soft_constraints
+            .iter()
+            .filter_map(|&i| {
+                let (a, b) = self.constraints[i];
+                self.check_equality(i, a, b).err()
+            })
+            .collect()
+    }
+
+    /// Emits the constraints with the `Warning` severity that don't hold as `tracing` warnings.
+    /// Without the `tracing` feature nothing is reported: callers read them from `warnings`.
+    pub(crate) fn report_warnings(&self) {
+        #[cfg(feature = "tracing")]
+        for warning in self.warnings() {
+            tracing::warn!("{}", self.explain(&warning));
+        }
+    }
+
+    /// Checks every constraint, returning the first one that does not hold.
+    pub fn check(&self) -> Result<(), ConstraintError> {
+        match self.first_violation(&mut |_| Ok(())).unwrap() {
+            Some(violation) => Err(violation),
+            None => Ok(()),
+        }
+    }
+
+    /// Like `check_constraints`, but returns false instead of panicking when a constraint does not hold.
+    pub fn is_satisfied(&self) -> bool {
+        self.first_violation(&mut |_| Ok(())).unwrap().is_none()
+    }
+
+    /// Returns the first constraint that does not hold.
+    /// `progress` is called with the number of nodes and constraints checked so far, and can stop the check by returning an error.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "check_constraints", level = "debug", skip_all, fields(nodes = self.graph.len(), constraints = self.constraints.len()))
+    )]
+    fn first_violation(
+        &self,
+        progress: &mut dyn FnMut(usize) -> Result<(), EvalError>,
+    ) -> Result<Option<ConstraintError>, EvalError> {
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+        let violation = self.find_violation(progress)?;
+        if let Some(metrics) = &self.metrics {
+            metrics.checked(violation.is_none());
+        }
+        #[cfg(feature = "tracing")]
+        match &violation {
+            Some(violation) => tracing::warn!(%violation, "constraint does not hold"),
+            None => tracing::debug!(
+                elapsed_us = start.elapsed().as_micros() as u64,
+                "constraints hold"
+            ),
+        }
+        Ok(violation)
+    }
+
+    fn find_violation(
+        &self,
+        progress: &mut dyn FnMut(usize) -> Result<(), EvalError>,
+    ) -> Result<Option<ConstraintError>, EvalError> {
         for node in 0..self.graph.len() {
-            if let (Some(a), Some(b)) = self.graph[node].inputs {
-                let a_val = self.graph[a].output.unwrap();
-                let b_val = self.graph[b].output.unwrap();
-                match self.graph[node].op {
-                    Some(OPERATION::ADD) => {
-                        assert_eq!(self.graph[node].output.unwrap(), a_val + b_val);
-                    }
-                    Some(OPERATION::MUL) => {
-                        assert_eq!(self.graph[node].output.unwrap(), a_val * b_val);
-                    }
-                    None => {}
+            if let Err(violation) = self.check_node(node) {
+                return Ok(Some(violation));
+            }
+            progress(node + 1)?;
+        }
+        for (i, &(a, b)) in self.constraints.iter().enumerate() {
+            if !self.soft_constraints.contains(&i) {
+                if let Err(violation) = self.check_equality(i, a, b) {
+                    return Ok(Some(violation));
                 }
             }
+            progress(self.graph.len() + i + 1)?;
+        }
+        for check in 0..self.checks.len() {
+            if let Err(violation) = self.verify_check(check) {
+                return Ok(Some(violation));
+            }
+            progress(self.graph.len() + self.constraints.len() + check + 1)?;
+        }
+        for &node in self.aliases.keys() {
+            if let Err(violation) = self.check_alias(node) {
+                return Ok(Some(violation));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Checks the equality constraint at index `constraint` between nodes `a` and `b`.
+    fn check_equality(&self, constraint: usize, a: usize, b: usize) -> Result<(), ConstraintError> {
+        let (a_value, b_value) = (self.filled(a)?, self.filled(b)?);
+        if a_value != b_value {
+            return Err(ConstraintError::NotEqual {
+                constraint,
+                a,
+                b,
+                a_value: self.redact(a, a_value),
+                b_value: self.redact(b, b_value),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that an add, mul or mul-add node holds the value computed from its inputs.
+    fn check_node(&self, node: usize) -> Result<(), ConstraintError> {
+        let expected = if let Some([a, b, c]) = self.graph.mul_add(node) {
+            let (a_val, b_val, c_val) = (self.filled(a)?, self.filled(b)?, self.filled(c)?);
+            a_val
+                .checked_mul(b_val)
+                .and_then(|product| product.checked_add(c_val))
+        } else if let Some((a, b)) = self.graph.operands(node) {
+            let (a_val, b_val) = (self.filled(a)?, self.filled(b)?);
+            self.graph.op(node).unwrap().checked_apply(a_val, b_val)
+        } else if let Some(operands) = self.graph.custom(node) {
+            return self.check_custom(node, &operands);
+        } else {
+            return Ok(());
+        };
+        let actual = self.filled(node)?;
+        match expected {
+            None => Err(ConstraintError::Overflow { node }),
+            Some(expected) if actual != expected => Err(ConstraintError::WrongOutput {
+                node,
+                actual: self.redact(node, actual),
+                expected: self.redact(node, expected),
+            }),
+            Some(_) => Ok(()),
+        }
+    }
+
+    fn filled(&self, node: usize) -> Result<u32, ConstraintError> {
+        self.graph
+            .output(node)
+            .ok_or(ConstraintError::Unfilled { node })
+    }
+
+    /// Returns the current output values of every node.
+    pub fn witness(&self) -> Witness {
+        self.witness_of(self.graph.outputs().collect())
+    }
+
+    /// Wraps values of every node of this graph, computed elsewhere, in a `Witness`.
+    pub(crate) fn witness_of(&self, values: Vec<Option<u32>>) -> Witness {
+        let redacted = (0..self.graph.len())
+            .filter(|&id| !self.reveal_secrets && self.graph.is_secret(id))
+            .collect();
+        Witness { values, redacted }
+    }
+
+    /// Loads externally computed output values into the graph, e.g. before calling `check_constraints`.
+    /// Constant nodes keep the value they were built with.
+    pub fn load_witness(&mut self, witness: &Witness) {
+        assert_eq!(witness.values.len(), self.graph.len());
+        for (id, &value) in witness.values.iter().enumerate() {
+            if self.graph.kind(id) != Kind::Constant {
+                self.graph.set_output(id, value);
+            }
         }
-        true
     }
 
-    /// Getter function for obtaining a node from the graph.
-    pub fn get_node(self, id: usize) -> Node {
-        self.graph[id]
+    /// Returns true if the node was created with `constant`.
+    pub fn is_constant(&self, id: NodeId) -> bool {
+        self.graph.kind(self.index(id)) == Kind::Constant
+    }
+
+    /// Returns the number of nodes in the graph.
+    pub fn len(&self) -> usize {
+        self.graph.len()
+    }
+
+    /// Returns true if the graph has no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.graph.is_empty()
+    }
+
+    /// Returns a view of a node, or None if the id doesn't belong to this graph. Nodes are stored
+    /// column by column, so the view is built on demand and returned by value.
+    pub fn node(&self, id: NodeId) -> Option<Node> {
+        self.contains(id).then(|| self.graph.node(id.index()))
+    }
+
+    /// Returns a borrowed view of a node, e.g. `builder.at(id).label()`. Panics if the id doesn't
+    /// belong to this graph. Unlike `builder[id]` (see `view`), it keeps nothing in the builder;
+    /// nodes are changed through setters such as `set_label`.
+    pub fn at(&self, id: NodeId) -> NodeRef<'_> {
+        self.index(id);
+        NodeRef { builder: self, id }
+    }
+
+    /// Returns the output of a node, or None if it isn't filled in or the id doesn't belong to this
+    /// graph.
+    pub fn output(&self, id: NodeId) -> Option<u32> {
+        self.contains(id)
+            .then(|| self.graph.output(id.index()))
+            .flatten()
+    }
+
+    /// Returns the output of every node, in the order they were added.
+    pub fn outputs(&self) -> impl ExactSizeIterator<Item = Option<u32>> + '_ {
+        self.graph.outputs()
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
     }
 }
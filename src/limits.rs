@@ -0,0 +1,242 @@
+//! Limits on the size of a graph under construction, so that a runaway generator, such as an
+//! unroll with no bound, fails fast with a clear error instead of running out of memory.
+//!
+//! Once a limit is set with `Builder::set_limits`, the `try_` methods (`try_add`, `try_init`, …)
+//! return `BuildError::LimitExceeded` instead of adding a node or constraint past it, and the
+//! other methods panic with that error. Limits are not saved with the graph.
+//!
+//! `EvalLimits` bound the evaluation of a graph instead, for services evaluating circuits submitted
+//! by users. Passed with `EvalOptions::limits`, they reject a graph that is too large before
+//! evaluating it, and stop an evaluation that computes too many nodes or runs for too long, with
+//! `EvalError::LimitExceeded`:
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use my_graph_lib::eval::{EvalError, EvalOptions};
+//! use my_graph_lib::limits::{EvalLimit, EvalLimits};
+//! use my_graph_lib::Builder;
+//!
+//! // E.g. a graph loaded with `Builder::from_json` from a request.
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let y = builder.add(x, x);
+//! builder.mul(y, y);
+//! let limits = EvalLimits::new()
+//!     .max_nodes(1_000_000)
+//!     .max_steps(1)
+//!     .timeout(Duration::from_secs(1));
+//! let result = builder.fill_inputs_with(&[(x, 3)], EvalOptions::new().limits(limits));
+//! assert!(matches!(result, Err(EvalError::LimitExceeded(EvalLimit::Steps(1)))));
+//! ```
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::error::{BuildError, EvalError};
+use crate::Builder;
+
+/// Largest sizes a graph may grow to. `None` means unlimited, which is the default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub max_nodes: Option<usize>,
+    /// Largest number of operations on a path from an input or constant to a node. Inputs,
+    /// constants and hints with a fixed value have depth 0.
+    pub max_depth: Option<usize>,
+    /// Largest number of equality constraints and checks.
+    pub max_constraints: Option<usize>,
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_nodes(mut self, max: usize) -> Self {
+        self.max_nodes = Some(max);
+        self
+    }
+
+    pub fn max_depth(mut self, max: usize) -> Self {
+        self.max_depth = Some(max);
+        self
+    }
+
+    pub fn max_constraints(mut self, max: usize) -> Self {
+        self.max_constraints = Some(max);
+        self
+    }
+}
+
+/// The limit a graph would exceed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Nodes,
+    Depth,
+    Constraints,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Limit::Nodes => "node",
+            Limit::Depth => "depth",
+            Limit::Constraints => "constraint",
+        })
+    }
+}
+
+/// Bounds on the evaluation of a graph, see the module documentation. `None` means unlimited,
+/// which is the default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvalLimits {
+    /// Largest number of nodes in the graph, checked before evaluating it.
+    pub max_nodes: Option<usize>,
+    /// Largest number of nodes computed (operations and hint calls) in one evaluation.
+    pub max_steps: Option<u64>,
+    /// Largest number of bytes the graph takes up, as estimated by `Builder::memory_estimate`,
+    /// checked before evaluating it.
+    pub max_memory: Option<usize>,
+    /// Longest an evaluation or check may run. The clock is read every 1024 nodes, so it may run
+    /// slightly over, and a single slow hint function is not interrupted.
+    pub timeout: Option<Duration>,
+}
+
+impl EvalLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_nodes(mut self, max: usize) -> Self {
+        self.max_nodes = Some(max);
+        self
+    }
+
+    pub fn max_steps(mut self, max: u64) -> Self {
+        self.max_steps = Some(max);
+        self
+    }
+
+    pub fn max_memory(mut self, bytes: usize) -> Self {
+        self.max_memory = Some(bytes);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Checks the limits that apply to the graph as a whole.
+    pub(crate) fn check_graph(&self, builder: &Builder) -> Result<(), EvalError> {
+        if let Some(max) = self.max_nodes {
+            if builder.graph.len() > max {
+                return Err(EvalError::LimitExceeded(EvalLimit::Nodes(max)));
+            }
+        }
+        if let Some(max) = self.max_memory {
+            if builder.memory_estimate().graph_bytes > max {
+                return Err(EvalError::LimitExceeded(EvalLimit::Memory(max)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The evaluation limit a graph exceeded, with its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalLimit {
+    Nodes(usize),
+    Steps(u64),
+    Memory(usize),
+    Timeout(Duration),
+}
+
+impl fmt::Display for EvalLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalLimit::Nodes(max) => write!(f, "node limit of {}", max),
+            EvalLimit::Steps(max) => write!(f, "step limit of {}", max),
+            EvalLimit::Memory(max) => write!(f, "memory limit of {} bytes", max),
+            EvalLimit::Timeout(timeout) => write!(f, "timeout of {:?}", timeout),
+        }
+    }
+}
+
+impl Builder {
+    /// Sets the limits checked when nodes and constraints are added. Nodes and constraints already
+    /// in the graph are kept even if they exceed the new limits.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.depths.clear();
+        if limits.max_depth.is_some() {
+            for id in 0..self.graph.len() {
+                let depth = self.depth_of(id);
+                self.depths.push(depth);
+            }
+        }
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Checks that a node computed from `operands` can be added, returning its depth if depth is
+    /// tracked.
+    pub(crate) fn check_node_limits(
+        &self,
+        operands: &[usize],
+    ) -> Result<Option<usize>, BuildError> {
+        if let Some(max) = self.limits.max_nodes {
+            if self.graph.len() >= max {
+                return Err(BuildError::LimitExceeded {
+                    limit: Limit::Nodes,
+                    max,
+                });
+            }
+        }
+        let Some(max) = self.limits.max_depth else {
+            return Ok(None);
+        };
+        let depth = operands
+            .iter()
+            .map(|&operand| self.depths[operand] + 1)
+            .max()
+            .unwrap_or(0);
+        if depth > max {
+            return Err(BuildError::LimitExceeded {
+                limit: Limit::Depth,
+                max,
+            });
+        }
+        Ok(Some(depth))
+    }
+
+    /// Checks that a constraint or check can be added.
+    pub(crate) fn add_constraint(&mut self) -> Result<(), BuildError> {
+        self.add_constraints(1)
+    }
+
+    /// Checks that `count` constraints or checks can be added.
+    pub(crate) fn add_constraints(&mut self, count: usize) -> Result<(), BuildError> {
+        if let Some(max) = self.limits.max_constraints {
+            if self.constraints.len() + self.checks.len() + count > max {
+                return Err(BuildError::LimitExceeded {
+                    limit: Limit::Constraints,
+                    max,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The depth of a node whose operands already have their depth in `depths`.
+    pub(crate) fn depth_of(&self, id: usize) -> usize {
+        self.reads(id)
+            .into_iter()
+            .flatten()
+            .map(|operand| self.depths[operand] + 1)
+            .max()
+            .unwrap_or(0)
+    }
+}
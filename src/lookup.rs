@@ -0,0 +1,211 @@
+//! Export of checks as lookup arguments, for backends with plookup or LogUp.
+//!
+//! Range, ordering and set membership checks are all lookups of a value into a fixed table, which
+//! lookup-capable backends prove natively instead of through bit decompositions or products of
+//! differences. `export` describes them as tables and lookups: `Range { node, bits }` looks the
+//! node up in the table of `0..2^bits`, `LessThan { a, b, bits }` looks up `b - a - 1` and `b` in
+//! it, and `InSet { node, set }` looks the node up in the table of `set`. Equal tables are shared.
+//!
+//! Range tables are materialized, so ranges wider than `max_bits` are split into limbs of at most
+//! `max_bits` bits, each looked up in its own range table: the backend witnesses the limbs and
+//! constrains their sum, `sum_i limb_i * 2^shift_i`, to the value. Permutation checks aren't
+//! lookups and are left out.
+//!
+//! `multiplicities` counts how many times each table entry is looked up, the extra witness of
+//! LogUp.
+//!
+//! ```
+//! use my_graph_lib::{lookup, Builder};
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let y = builder.init();
+//! builder.assert_range(x, 8);
+//! builder.assert_lt(x, y, 8);
+//! builder.assert_in_set(y, &[10, 20, 30]);
+//! let export = lookup::export(&builder, lookup::DEFAULT_MAX_BITS);
+//! // The table of 0..256, shared by the three range lookups, and the set.
+//! assert_eq!(export.tables.len(), 2);
+//! assert_eq!(export.lookups.len(), 4);
+//! builder.fill_inputs(&[(x, 7), (y, 20)]);
+//! let counts = export.multiplicities(&builder).unwrap();
+//! // x = 7 and y - x - 1 = 12 in the range table, and 20 in the set.
+//! assert_eq!((counts[0][7], counts[0][12], counts[0][20]), (1, 1, 1));
+//! assert_eq!(counts[1], vec![0, 1, 0]);
+//! ```
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::checks::Check;
+use crate::Builder;
+
+/// The widest range table of the CLI export: 2^16 entries.
+pub const DEFAULT_MAX_BITS: u32 = 16;
+
+/// A fixed table of values.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub enum Table {
+    /// `0..2^bits`.
+    Range { bits: u32 },
+    /// The values of an `InSet` check, sorted and without duplicates.
+    Set(Vec<u32>),
+}
+
+impl Table {
+    /// The entries of the table, in increasing order.
+    pub fn values(&self) -> Vec<u32> {
+        match self {
+            Table::Range { bits } => (0..1u64 << bits).map(|value| value as u32).collect(),
+            Table::Set(set) => set.clone(),
+        }
+    }
+
+    /// The position of `value` in `values()`, if it is an entry.
+    pub fn position(&self, value: u32) -> Option<usize> {
+        match self {
+            Table::Range { bits } => (u64::from(value) >> bits == 0).then_some(value as usize),
+            Table::Set(set) => set.binary_search(&value).ok(),
+        }
+    }
+}
+
+/// A value looked up in a table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Input {
+    /// The output of a node.
+    Node(usize),
+    /// `b - a - 1`, for `a < b`.
+    Difference { a: usize, b: usize },
+    /// The `bits` bits of a value starting at bit `shift`, witnessed by the backend.
+    Limb {
+        value: Box<Input>,
+        shift: u32,
+        bits: u32,
+    },
+}
+
+impl Input {
+    /// The looked up value, from the values of the builder's nodes. `None` if a node has not been
+    /// filled in, or for the difference of values that aren't in order.
+    pub fn evaluate(&self, builder: &Builder) -> Option<u32> {
+        match *self {
+            Input::Node(node) => builder.graph.output(node),
+            Input::Difference { a, b } => builder
+                .graph
+                .output(b)?
+                .checked_sub(builder.graph.output(a)?)?
+                .checked_sub(1),
+            Input::Limb {
+                ref value,
+                shift,
+                bits,
+            } => {
+                let value = u64::from(value.evaluate(builder)?);
+                Some(((value >> shift) & ((1u64 << bits) - 1)) as u32)
+            }
+        }
+    }
+}
+
+/// A lookup of `input` in the table numbered `table`, enforcing part of check `check`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Lookup {
+    pub table: usize,
+    pub input: Input,
+    pub check: usize,
+}
+
+/// The tables and lookups of a graph, from `export`.
+#[derive(Debug, Clone, Serialize)]
+pub struct LookupExport {
+    pub tables: Vec<Table>,
+    /// The lookups, in the order of their checks.
+    pub lookups: Vec<Lookup>,
+}
+
+impl LookupExport {
+    /// How many times each entry of each table is looked up, by table and then by position in
+    /// `Table::values`. `None` if a node has not been filled in or a looked up value is not in its
+    /// table, in which case the check fails.
+    pub fn multiplicities(&self, builder: &Builder) -> Option<Vec<Vec<u32>>> {
+        let mut counts: Vec<Vec<u32>> = self
+            .tables
+            .iter()
+            .map(|table| match table {
+                Table::Range { bits } => vec![0; 1 << bits],
+                Table::Set(set) => vec![0; set.len()],
+            })
+            .collect();
+        for lookup in &self.lookups {
+            let value = lookup.input.evaluate(builder)?;
+            let position = self.tables[lookup.table].position(value)?;
+            counts[lookup.table][position] += 1;
+        }
+        Some(counts)
+    }
+}
+
+/// Describes the checks of the graph as lookups, with range tables of at most `max_bits` bits,
+/// see the module documentation. Panics unless `max_bits` is between 1 and 24.
+pub fn export(builder: &Builder, max_bits: u32) -> LookupExport {
+    assert!(
+        (1..=24).contains(&max_bits),
+        "range tables must have between 1 and 24 bits"
+    );
+    let mut export = LookupExport {
+        tables: Vec::new(),
+        lookups: Vec::new(),
+    };
+    let mut numbers: HashMap<Table, usize> = HashMap::new();
+    let mut table = |export: &mut LookupExport, table: Table| {
+        *numbers.entry(table.clone()).or_insert_with(|| {
+            export.tables.push(table);
+            export.tables.len() - 1
+        })
+    };
+    for (check, kind) in builder.checks.iter().enumerate() {
+        let ranges = match *kind {
+            Check::Range { node, bits } => vec![(Input::Node(node), bits)],
+            Check::LessThan { a, b, bits } => {
+                vec![(Input::Difference { a, b }, bits), (Input::Node(b), bits)]
+            }
+            Check::InSet { node, ref set } => {
+                let table = table(&mut export, Table::Set(set.clone()));
+                export.lookups.push(Lookup {
+                    table,
+                    input: Input::Node(node),
+                    check,
+                });
+                continue;
+            }
+            Check::Permutation { .. } => continue,
+        };
+        for (input, bits) in ranges {
+            if bits <= max_bits {
+                let table = table(&mut export, Table::Range { bits });
+                export.lookups.push(Lookup {
+                    table,
+                    input,
+                    check,
+                });
+                continue;
+            }
+            for shift in (0..bits).step_by(max_bits as usize) {
+                let limb = max_bits.min(bits - shift);
+                let table = table(&mut export, Table::Range { bits: limb });
+                export.lookups.push(Lookup {
+                    table,
+                    input: Input::Limb {
+                        value: Box::new(input.clone()),
+                        shift,
+                        bits: limb,
+                    },
+                    check,
+                });
+            }
+        }
+    }
+    export
+}
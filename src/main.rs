@@ -1,30 +1,704 @@
+use std::error::Error;
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand, ValueEnum};
 use my_graph_lib::*;
 
-fn main() {
-    // I used the following code to inspect and debug the implementation of the computational graph.
-    let mut builder = Builder::new();
-    let x = builder.init();
-    let seven = builder.constant(7);
-    let x_plus_seven = builder.add(x, seven);
-    let sqrt_x_plus_7 = builder.hint(4, x_plus_seven);
-    let computed_sq = builder.mul(sqrt_x_plus_7, sqrt_x_plus_7);
-    // We can inspect the whole graph.  Output values for all nodes except constants should be None.
-    println!("computational graph before filling nodes: {:?}", builder);
-    builder.fill_nodes(x, 9);
-    // Every node should now have an output value corresponding to its calculation.
-    println!("computational graph after filling nodes: {:?}", builder);
-    // Alternatively, we can inspect individual nodes.
-    // Note:  Cloning is only used for debugging purposes...quick way to avoid borrowing issues but obviously not efficient for large graphs, production code, etc.
-    let x_plus_seven_node = builder.clone().get_node(x_plus_seven);
-    println!("Node {}: {:?}", x_plus_seven_node.id, x_plus_seven_node);
-    // We can check that the constraints hold.
-    // Note:  Cloning is only used for debugging purposes.
-    let answer = builder.clone().check_constraints();
-    println!("Constraints hold: {:?}", answer);
-    // We can check that the equivalence betwee the hint and the computed square holds.
-    // Note:  Cloning is only used for debugging purposes.
-    let hint_eq = builder.clone().assert_equal(sqrt_x_plus_7, computed_sq);
-    println!("Hint equality holds: {:?}", hint_eq);
+/// Build, evaluate, check and export computational graphs.
+#[derive(Parser)]
+#[command(name = "circuit")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Builds a graph from a textual program and writes it as JSON.
+    Build {
+        program: PathBuf,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Fills in a graph from input values and writes the witness as JSON.
+    Eval {
+        graph: PathBuf,
+        /// Input assignment as `label=value` or `index=value`. Can be repeated.
+        #[arg(short, long = "input")]
+        inputs: Vec<String>,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Records every evaluation step to this file as JSON lines.
+        #[arg(long)]
+        trace: Option<PathBuf>,
+        /// Reports progress on stderr.
+        #[arg(long)]
+        progress: bool,
+        /// Periodically saves the partially filled graph to this file.
+        #[arg(long)]
+        checkpoint: Option<PathBuf>,
+        /// Number of nodes between checkpoints.
+        #[arg(long, default_value_t = 100_000)]
+        checkpoint_every: usize,
+        /// Profiles the evaluation: prints where the time goes on stderr and writes folded stacks
+        /// for flamegraph tools to this file.
+        #[arg(long)]
+        profile: Option<PathBuf>,
+        /// Continues an evaluation from a checkpoint instead of starting from the inputs.
+        #[arg(long, conflicts_with = "inputs")]
+        resume: Option<PathBuf>,
+        /// Refuses graphs with more nodes than this.
+        #[arg(long)]
+        max_nodes: Option<usize>,
+        /// Stops after computing this many nodes.
+        #[arg(long)]
+        max_steps: Option<u64>,
+        /// Refuses graphs estimated to take up more bytes than this.
+        #[arg(long)]
+        max_memory: Option<usize>,
+        /// Stops after this many seconds.
+        #[arg(long)]
+        timeout: Option<f64>,
+    },
+    /// Fixes the values of some inputs, folds away what they determine, and writes the smaller
+    /// graph over the other inputs as JSON.
+    Specialize {
+        graph: PathBuf,
+        /// Known input as `label=value` or `index=value`. Can be repeated.
+        #[arg(short, long = "input")]
+        inputs: Vec<String>,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Completes a partial witness with the values its known values and the constraints force,
+    /// and lists the nodes left without a value on stderr. Fails if any are left.
+    Solve {
+        graph: PathBuf,
+        /// Partial witness as JSON, with `null` for unknown values.
+        witness: PathBuf,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Checks that a witness satisfies every constraint of a graph.
+    Check {
+        graph: PathBuf,
+        witness: PathBuf,
+        /// Reports progress on stderr.
+        #[arg(long)]
+        progress: bool,
+        /// Also corrupts each witness value in turn and lists the ones no constraint catches.
+        #[arg(long)]
+        mutations: bool,
+        /// When a constraint fails, also prints every node it is computed from, with its value.
+        #[arg(long)]
+        slice: bool,
+    },
+    /// Shrinks the values of inputs that make a constraint fail while the same constraint keeps
+    /// failing, and prints the smallest assignment found.
+    Minimize {
+        graph: PathBuf,
+        /// Input assignment as `label=value` or `index=value`. Can be repeated.
+        #[arg(short, long = "input")]
+        inputs: Vec<String>,
+    },
+    /// Computes bounds on every node's value from ranges of the inputs, and lists the nodes that
+    /// may overflow a u32 or reach a modulus, without evaluating the graph.
+    Bounds {
+        graph: PathBuf,
+        /// Range of an input as `name=min..=max` or `name=value`. Inputs without a range may be
+        /// any u32. Can be repeated.
+        #[arg(short, long = "range")]
+        ranges: Vec<String>,
+        /// Also lists the nodes whose value may be this modulus or more.
+        #[arg(long)]
+        modulus: Option<u64>,
+    },
+    /// Lists the single-entry, single-exit regions of a graph, candidate gadget boundaries, and
+    /// the shapes that occur more than once.
+    Regions {
+        graph: PathBuf,
+        /// Leaves out regions of fewer nodes.
+        #[arg(long, default_value_t = 4)]
+        min_size: usize,
+    },
+    /// Evaluates a graph with every evaluation backend and reports where they disagree.
+    Compare {
+        graph: PathBuf,
+        /// Input assignment as `label=value` or `index=value`. Can be repeated.
+        #[arg(short, long = "input")]
+        inputs: Vec<String>,
+    },
+    /// Times construction, evaluation with every backend and constraint checking, and prints a
+    /// table. Without a graph, times random circuits of growing width.
+    Bench {
+        graph: Option<PathBuf>,
+        /// Input assignment as `label=value` or `index=value`. Can be repeated.
+        #[arg(short, long = "input", requires = "graph")]
+        inputs: Vec<String>,
+        /// Layer widths of the random circuits.
+        #[arg(long, value_delimiter = ',', default_values_t = [16, 64, 256, 1024], conflicts_with = "graph")]
+        widths: Vec<usize>,
+        /// Seed of the random circuits.
+        #[arg(long, default_value_t = 0, conflicts_with = "graph")]
+        seed: u64,
+        /// Number of runs of each measurement. The fastest one is reported.
+        #[arg(long, default_value_t = 5)]
+        runs: usize,
+    },
+    /// Opens an interactive viewer that steps through the evaluation of a graph.
+    #[cfg(feature = "tui")]
+    Debug {
+        graph: PathBuf,
+        /// Input assignment as `label=value` or `index=value`. Can be repeated.
+        #[arg(short, long = "input")]
+        inputs: Vec<String>,
+    },
+    /// Lists the public outputs computed from private inputs, with the inputs they depend on.
+    Taint { graph: PathBuf },
+    /// Optimizes a graph with constant folding, simplification, common subexpression and dead
+    /// code elimination, and prints what each pass did on stderr.
+    Optimize {
+        graph: PathBuf,
+        /// File to write the optimized graph to. Defaults to stdout.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Checks every pass on this many random input assignments, undoing passes that change
+        /// the public outputs or constraints.
+        #[arg(long, default_value_t = 0)]
+        validate: usize,
+        /// Fails instead of undoing a pass when validation finds a difference.
+        #[arg(long, requires = "validate")]
+        strict: bool,
+    },
+    /// Prints the fingerprint of a graph's structure and constraints.
+    Fingerprint {
+        graph: PathBuf,
+        /// Fails unless the fingerprint is this one.
+        #[arg(long)]
+        expect: Option<String>,
+    },
+    /// Prints statistics, constraint counts, lint findings and backend costs of a graph.
+    Report {
+        graph: PathBuf,
+        /// Prints the report as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Exports a graph in another format.
+    Export {
+        graph: PathBuf,
+        #[arg(short, long, value_enum, default_value = "json")]
+        format: Format,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// A witness whose values the HTML viewer shows.
+        #[arg(long)]
+        witness: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Dot,
+    R1cs,
+    Json,
+    Snapshot,
+    /// SMT-LIB script asking for a witness that satisfies the constraints.
+    Smt,
+    /// SMT-LIB script asking for inputs that make a constraint fail.
+    SmtViolation,
+    /// Plonkish gates of degree 3 over 4 wires, with their rows and copy constraints, as JSON.
+    Plonkish,
+    /// The copy constraints of the Plonkish table as the cycles of a permutation argument, as JSON.
+    Wiring,
+    /// Range and set checks as lookups into tables of at most 2^16 entries, as JSON.
+    Lookups,
+    /// Layered circuit of add and mul wiring predicates, for GKR and sumcheck provers, as JSON.
+    Multilinear,
+    /// Boolean circuit of AND and XOR gates in Bristol fashion, for MPC frameworks.
+    Bristol,
+    /// CNF formula in DIMACS format, satisfiable when some inputs satisfy the constraints.
+    Dimacs,
+    /// Self-contained HTML viewer with pan, zoom, search and collapsible scopes.
+    Html,
+    /// `main.rs` of an SP1 guest program re-executing the graph and asserting its constraints.
+    Sp1,
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs a subcommand. Returns false if a check failed.
+fn run(cli: Cli) -> Result<bool, Box<dyn Error>> {
+    match cli.command {
+        Command::Build { program, output } => {
+            let program = parser::parse(&fs::read_to_string(program)?)?;
+            write_output(output, &program.builder.to_json())?;
+        }
+        Command::Eval {
+            graph,
+            inputs,
+            output,
+            trace,
+            progress,
+            checkpoint,
+            checkpoint_every,
+            profile,
+            resume,
+            max_nodes,
+            max_steps,
+            max_memory,
+            timeout,
+        } => {
+            let mut builder = load_graph(&graph)?;
+            let mut sink = match trace {
+                Some(path) => Some(BufWriter::new(fs::File::create(path)?)),
+                None => None,
+            };
+            let mut report = profile::Profile::default();
+            let mut options = eval::EvalOptions::new();
+            if let Some(sink) = &mut sink {
+                options = options.trace(sink);
+            }
+            if progress {
+                options = options.on_progress(|done, total| report_progress("filled", done, total));
+            }
+            if let Some(path) = &checkpoint {
+                options = options.checkpoint(path, checkpoint_every);
+            }
+            if profile.is_some() {
+                options = options.profile(&mut report);
+            }
+            options = options.limits(limits::EvalLimits {
+                max_nodes,
+                max_steps,
+                max_memory,
+                timeout: timeout
+                    .map(std::time::Duration::try_from_secs_f64)
+                    .transpose()?,
+            });
+            match resume {
+                Some(path) => {
+                    builder.resume_with(&checkpoint::Checkpoint::load(&path)?, options)?
+                }
+                None => {
+                    let assignment = parse_inputs(&builder, &inputs)?;
+                    builder.fill_inputs_with(&assignment, options)?;
+                }
+            }
+            if let Some(path) = profile {
+                eprint!("{}", report);
+                fs::write(path, report.to_folded())?;
+            }
+            write_output(output, &serde_json::to_string_pretty(&builder.witness())?)?;
+        }
+        Command::Specialize {
+            graph,
+            inputs,
+            output,
+        } => {
+            let builder = load_graph(&graph)?;
+            let known = parse_inputs(&builder, &inputs)?;
+            let specialized = builder.try_specialize(&known)?;
+            eprintln!(
+                "{} nodes, {} folded",
+                specialized.builder.len(),
+                specialized.folded
+            );
+            write_output(output, &specialized.builder.to_json())?;
+        }
+        Command::Solve {
+            graph,
+            witness,
+            output,
+        } => {
+            let builder = load_graph(&graph)?;
+            let partial = load_witness(&witness, &builder)?;
+            let solution = builder.solve(&partial)?;
+            eprintln!("derived {} values", solution.derived.len());
+            for &node in &solution.unknown {
+                let name = builder
+                    .label(node)
+                    .map_or(format!("node {}", node.index()), |label| {
+                        format!("node {} ({})", node.index(), label)
+                    });
+                eprintln!("{} has no value", name);
+            }
+            write_output(output, &serde_json::to_string_pretty(&solution.witness)?)?;
+            return Ok(solution.is_complete());
+        }
+        Command::Check {
+            graph,
+            witness,
+            progress,
+            mutations,
+            slice,
+        } => {
+            let mut builder = load_graph(&graph)?;
+            let witness = load_witness(&witness, &builder)?;
+            builder.load_witness(&witness);
+            let mut options = eval::EvalOptions::new();
+            if progress {
+                options =
+                    options.on_progress(|done, total| report_progress("checked", done, total));
+            }
+            match builder.check_with(options) {
+                Ok(()) => {
+                    println!("constraints hold");
+                    for warning in builder.warnings() {
+                        println!("warning: {}", builder.explain(&warning));
+                    }
+                }
+                Err(error::Error::Constraint(violation)) => {
+                    println!("constraints do not hold: {}", builder.explain(&violation));
+                    if slice {
+                        println!("{}", builder.slice_violation(&violation));
+                    }
+                    return Ok(false);
+                }
+                Err(err) => return Err(err.into()),
+            }
+            if mutations {
+                let report = builder.mutation_test()?;
+                for mutation in &report.undetected {
+                    println!(
+                        "undetected: node {} changed from {} to {}",
+                        mutation.node, mutation.original, mutation.mutated
+                    );
+                }
+                println!(
+                    "{} of {} witness values are constrained",
+                    report.tested - report.undetected.len(),
+                    report.tested
+                );
+                return Ok(report.is_fully_constrained());
+            }
+        }
+        Command::Minimize { graph, inputs } => {
+            let builder = load_graph(&graph)?;
+            let assignment = parse_inputs(&builder, &inputs)?;
+            let Some(minimized) = builder.minimize_failure(&assignment)? else {
+                println!("constraints hold");
+                return Ok(true);
+            };
+            for &(node, value) in &minimized.inputs {
+                let name = builder
+                    .label(node)
+                    .map_or(node.index().to_string(), str::to_string);
+                println!("--input {}={}", name, value);
+            }
+            println!("{}", minimized.violation);
+            println!("{} evaluations", minimized.evaluations);
+        }
+        Command::Bounds {
+            graph,
+            ranges,
+            modulus,
+        } => {
+            let builder = load_graph(&graph)?;
+            let ranges = parse_ranges(&builder, &ranges)?;
+            let bounds = builder.bounds(&ranges);
+            let name = |node: usize| {
+                let id = builder.node_id(node).unwrap();
+                builder.label(id).map_or(format!("node {}", node), |label| {
+                    format!("node {} ({})", node, label)
+                })
+            };
+            let overflows = bounds.overflows();
+            for &node in &overflows {
+                let interval = bounds.nodes[node];
+                println!(
+                    "{} may overflow: its value is in {}..={}",
+                    name(node),
+                    interval.min,
+                    interval.max
+                );
+            }
+            let exceeding = modulus.map_or(vec![], |modulus| bounds.exceeding(modulus));
+            for &node in &exceeding {
+                let interval = bounds.nodes[node];
+                println!(
+                    "{} may reach the modulus: its value is in {}..={}",
+                    name(node),
+                    interval.min,
+                    interval.max
+                );
+            }
+            if !overflows.is_empty() || !exceeding.is_empty() {
+                return Ok(false);
+            }
+            println!("no node can overflow");
+        }
+        Command::Regions { graph, min_size } => {
+            let builder = load_graph(&graph)?;
+            let name = |id: NodeId| {
+                builder
+                    .label(id)
+                    .map_or(format!("node {}", id.index()), |label| {
+                        format!("node {} ({})", id.index(), label)
+                    })
+            };
+            let found = builder.regions(min_size);
+            for region in &found {
+                println!(
+                    "{} <- {}: {} nodes, {} multiplications, degree {}, depth {}, shape {:016x}",
+                    name(region.exit),
+                    name(region.entry),
+                    region.nodes.len(),
+                    region.multiplications,
+                    region.degree,
+                    region.depth,
+                    region.shape
+                );
+            }
+            for (shape, group) in regions::by_shape(&found) {
+                println!("shape {:016x} occurs {} times", shape, group.len());
+            }
+        }
+        Command::Compare { graph, inputs } => {
+            let builder = load_graph(&graph)?;
+            let assignment = parse_inputs(&builder, &inputs)?;
+            // Backends that panic are reported as failing, so the panic messages are not printed.
+            let hook = std::panic::take_hook();
+            std::panic::set_hook(Box::new(|_| {}));
+            let report = builder.compare_backends(&assignment);
+            std::panic::set_hook(hook);
+            let names = |backends: &[differential::Backend]| {
+                let names: Vec<_> = backends.iter().map(|backend| backend.name()).collect();
+                names.join(", ")
+            };
+            println!("compared: {}", names(&report.compared));
+            if !report.skipped.is_empty() {
+                println!("skipped: {}", names(&report.skipped));
+            }
+            for divergence in &report.divergences {
+                println!("{}", divergence);
+            }
+            if !report.is_consistent() {
+                return Ok(false);
+            }
+            println!("backends agree");
+        }
+        Command::Bench {
+            graph,
+            inputs,
+            widths,
+            seed,
+            runs,
+        } => {
+            let report = match graph {
+                Some(graph) => {
+                    let builder = load_graph(&graph)?;
+                    let assignment = parse_inputs(&builder, &inputs)?;
+                    bench::bench_graph(&builder, &assignment, runs)?
+                }
+                None => bench::bench_random(seed, &random::RandomConfig::default(), &widths, runs)?,
+            };
+            print!("{}", report);
+        }
+        #[cfg(feature = "tui")]
+        Command::Debug { graph, inputs } => {
+            let builder = load_graph(&graph)?;
+            let assignment = parse_inputs(&builder, &inputs)?;
+            tui::run(builder, &assignment)?;
+        }
+        Command::Taint { graph } => {
+            let builder = load_graph(&graph)?;
+            let name = |id: NodeId| {
+                builder
+                    .label(id)
+                    .map_or(id.index().to_string(), str::to_string)
+            };
+            let tainted = builder.taint();
+            for output in &tainted {
+                let sources: Vec<String> = output.sources.iter().map(|&id| name(id)).collect();
+                println!(
+                    "public output {} depends on private inputs {}",
+                    name(output.output),
+                    sources.join(", ")
+                );
+            }
+            if tainted.is_empty() {
+                println!("no public output depends on a private input");
+            }
+        }
+        Command::Optimize {
+            graph,
+            output,
+            validate,
+            strict,
+        } => {
+            let optimized = passes::PassManager::standard()
+                .validate(validate)
+                .strict(strict)
+                .try_run(&load_graph(&graph)?)?;
+            for stats in &optimized.stats {
+                eprintln!("{}", stats);
+            }
+            for rejected in &optimized.rejected {
+                eprintln!("undone: {}", rejected);
+            }
+            write_output(output, &optimized.builder.to_json())?;
+        }
+        Command::Fingerprint { graph, expect } => {
+            let fingerprint = load_graph(&graph)?.fingerprint().to_string();
+            println!("{}", fingerprint);
+            if expect.is_some_and(|expected| !expected.eq_ignore_ascii_case(&fingerprint)) {
+                eprintln!("fingerprint does not match the expected one");
+                return Ok(false);
+            }
+        }
+        Command::Report { graph, json } => {
+            let report = load_graph(&graph)?.report();
+            if json {
+                println!("{}", report.to_json());
+            } else {
+                println!("{}", report);
+            }
+        }
+        Command::Export {
+            graph,
+            format,
+            output,
+            witness,
+        } => {
+            let mut builder = load_graph(&graph)?;
+            if let Some(witness) = witness {
+                let witness = load_witness(&witness, &builder)?;
+                builder.load_witness(&witness);
+            }
+            let text = match format {
+                Format::Dot => export::to_dot(&builder),
+                Format::R1cs => serde_json::to_string_pretty(&export::try_to_r1cs(&builder)?)?,
+                Format::Json => builder.to_json(),
+                Format::Snapshot => builder.snapshot(),
+                Format::Smt => export::to_smtlib(&builder, export::SmtGoal::Witness),
+                Format::SmtViolation => export::to_smtlib(&builder, export::SmtGoal::Violation),
+                Format::Plonkish => serde_json::to_string_pretty(&plonkish::try_extract(
+                    &builder,
+                    plonkish::DEFAULT_DEGREE,
+                    plonkish::DEFAULT_WIDTH,
+                )?)?,
+                Format::Wiring => serde_json::to_string_pretty(
+                    &plonkish::try_extract(
+                        &builder,
+                        plonkish::DEFAULT_DEGREE,
+                        plonkish::DEFAULT_WIDTH,
+                    )?
+                    .permutation(),
+                )?,
+                Format::Lookups => serde_json::to_string_pretty(&lookup::export(
+                    &builder,
+                    lookup::DEFAULT_MAX_BITS,
+                ))?,
+                Format::Multilinear => {
+                    serde_json::to_string_pretty(&multilinear::try_export(&builder)?)?
+                }
+                Format::Bristol => boolean::try_lower(&builder)?.to_bristol(),
+                Format::Dimacs => boolean::try_lower(&builder)?.to_dimacs(),
+                Format::Html => html::to_html(&builder),
+                Format::Sp1 => sp1::try_guest_source(&builder)?,
+            };
+            write_output(output, &text)?;
+        }
+    }
+    Ok(true)
+}
+
+/// Reads a witness of the graph's nodes from a JSON file.
+fn load_witness(path: &Path, builder: &Builder) -> Result<Witness, Box<dyn Error>> {
+    let witness: Witness = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if witness.values.len() != builder.len() {
+        return Err(format!(
+            "witness has {} values but the graph has {} nodes",
+            witness.values.len(),
+            builder.len()
+        )
+        .into());
+    }
+    Ok(witness)
+}
+
+/// Draws a single updating progress line on stderr.
+fn report_progress(verb: &str, done: usize, total: usize) {
+    eprint!("\r{} {}/{}", verb, done, total);
+    if done == total {
+        eprintln!();
+    }
+}
+
+fn load_graph(path: &PathBuf) -> Result<Builder, Box<dyn Error>> {
+    #[cfg(feature = "zstd")]
+    return Ok(Builder::from_json_bytes(&fs::read(path)?)?);
+    #[cfg(not(feature = "zstd"))]
+    Ok(Builder::from_json(&fs::read_to_string(path)?)?)
+}
+
+/// Writes to the given file, or to stdout if no file was given.
+fn write_output(path: Option<PathBuf>, text: &str) -> Result<(), Box<dyn Error>> {
+    match path {
+        Some(path) => fs::write(path, text)?,
+        None => println!("{}", text),
+    }
+    Ok(())
+}
+
+/// Resolves `name=value` arguments into (input node, value) pairs. Every input node must be assigned.
+fn parse_inputs(builder: &Builder, args: &[String]) -> Result<Vec<(NodeId, u32)>, Box<dyn Error>> {
+    let mut assignment = Vec::new();
+    for arg in args {
+        let (name, value) = arg
+            .split_once('=')
+            .ok_or_else(|| format!("expected `name=value`, got `{}`", arg))?;
+        let node = builder
+            .find_node(name)
+            .ok_or_else(|| format!("no node named `{}`", name))?;
+        if !builder.inputs().contains(&node) {
+            return Err(format!("`{}` is not an input node", name).into());
+        }
+        assignment.push((node, value.parse()?));
+    }
+    for input in builder.inputs() {
+        if !assignment.iter().any(|&(node, _)| node == input) {
+            let name = builder
+                .label(input)
+                .map_or(input.to_string(), str::to_string);
+            return Err(format!("missing value for input `{}`", name).into());
+        }
+    }
+    Ok(assignment)
+}
+
+fn parse_ranges(
+    builder: &Builder,
+    args: &[String],
+) -> Result<Vec<bounds::InputRange>, Box<dyn Error>> {
+    let mut ranges = Vec::new();
+    for arg in args {
+        let (name, range) = arg
+            .split_once('=')
+            .ok_or_else(|| format!("expected `name=min..=max`, got `{}`", arg))?;
+        let node = builder
+            .find_node(name)
+            .ok_or_else(|| format!("no node named `{}`", name))?;
+        if !builder.inputs().contains(&node) {
+            return Err(format!("`{}` is not an input node", name).into());
+        }
+        let (min, max) = range.split_once("..=").unwrap_or((range, range));
+        ranges.push((node, min.parse()?..=max.parse()?));
+    }
+    Ok(ranges)
 }
 
 #[cfg(test)]
@@ -41,10 +715,9 @@ mod tests {
         let y = builder.add(x_squared_plus_5, x);
         builder.fill_nodes(x, 6);
         builder.check_constraints();
-        assert_eq!(builder.get_node(y).output.unwrap(), 47);
+        assert_eq!(builder.output(y).unwrap(), 47);
     }
 
-
     #[test]
     #[should_panic]
     fn test_polynomial_panic() {
@@ -56,7 +729,7 @@ mod tests {
         let y = builder.add(x_squared_plus_5, x);
         builder.fill_nodes(x, 6);
         builder.check_constraints();
-        assert_eq!(builder.get_node(y).output.unwrap(), 46);
+        assert_eq!(builder.output(y).unwrap(), 46);
     }
 
     #[test]
@@ -117,4 +790,3941 @@ mod tests {
         builder.check_constraints();
         let _ = builder.assert_equal(sqrt_x_plus_7, computed_sq);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_program() {
+        let mut program = parser::parse("y = (x + 7)^2; z = y * 2 + x").unwrap();
+        let x = program.var("x").unwrap();
+        let z = program.var("z").unwrap();
+        assert_eq!(program.inputs, vec!["x".to_string()]);
+        program.builder.fill_nodes(x, 9);
+        program.builder.check_constraints();
+        assert_eq!(program.builder.label(z), Some("z"));
+        assert_eq!(program.builder.output(z).unwrap(), 521);
+    }
+
+    #[test]
+    fn test_parse_hint_assertion() {
+        let mut program = parser::parse("y = x + 7; assert y == hint(sqrt, y)^2").unwrap();
+        let x = program.var("x").unwrap();
+        program.builder.fill_nodes(x, 9);
+        assert!(program.builder.check_constraints());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_hint_assertion_panic() {
+        let mut program = parser::parse("y = x + 7; assert y == hint(sqrt, y)^2").unwrap();
+        let x = program.var("x").unwrap();
+        program.builder.fill_nodes(x, 10);
+        program.builder.check_constraints();
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = parser::parse("y = x +; z = 1").unwrap_err();
+        assert_eq!(err.position, 7);
+        let err = parser::parse("y = hint(cbrt, x)").unwrap_err();
+        assert!(err.message.contains("cbrt"));
+        assert!(parser::parse("x = 1; x = 2").is_err());
+        let err = parser::parse("y = y + 1").unwrap_err();
+        assert_eq!(err.message, "`y` is used in its own definition");
+        assert_eq!(err.position, 0);
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        // Exponents are expanded by square-and-multiply, so huge ones stay small.
+        let program = parser::parse("y = x^4000000000").unwrap();
+        assert!(program.builder.len() < 70);
+        let mut program = parser::parse("y = x^13").unwrap();
+        assert_eq!(program.builder.len(), 6);
+        let (x, y) = (program.var("x").unwrap(), program.var("y").unwrap());
+        program.builder.fill_nodes(x, 2);
+        assert_eq!(program.builder.output(y), Some(8192));
+
+        let nested = |depth| format!("y = {}x{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parser::parse(&nested(parser::MAX_NESTING)).is_ok());
+        let err = parser::parse(&nested(1_000_000)).unwrap_err();
+        assert!(err.message.contains("nest more than"));
+        // At the start of the expression that would nest too deep.
+        assert_eq!(err.position, 5 + parser::MAX_NESTING);
+        let hints = "hint(sqrt, ".repeat(parser::MAX_NESTING + 1);
+        assert!(parser::parse(&format!("y = {}x", hints)).is_err());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let program = parser::parse("y = x + 7; assert y == hint(sqrt, y)^2").unwrap();
+        let mut builder = Builder::from_json(&program.builder.to_json()).unwrap();
+        let x = builder.find_label("x").unwrap();
+        assert_eq!(builder.inputs(), &[x]);
+        builder.fill_nodes(x, 9);
+        assert!(builder.is_satisfied());
+    }
+
+    #[test]
+    fn test_load_witness() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let five = builder.constant(5);
+        let y = builder.mul(x, five);
+        builder.fill_nodes(x, 3);
+        let mut witness = builder.witness();
+        assert_eq!(witness.values, vec![Some(3), Some(5), Some(15)]);
+        witness.values[y.index()] = Some(16);
+        builder.load_witness(&witness);
+        assert!(!builder.is_satisfied());
+    }
+
+    #[test]
+    fn test_export() {
+        let program = parser::parse("y = x * x + 5; assert y == 30").unwrap();
+        let r1cs = export::to_r1cs(&program.builder);
+        // Two constants, one mul, one add and one equality constraint.
+        assert_eq!(r1cs.constraints.len(), 5);
+        assert_eq!(r1cs.num_variables, program.builder.len() + 1);
+        let dot = export::to_dot(&program.builder);
+        assert!(dot.starts_with("digraph"));
+        assert!(dot.contains("y: +"));
+    }
+
+    #[test]
+    fn test_fill_node_by_node() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let seven = builder.constant(7);
+        let x_plus_seven = builder.add(x, seven);
+        let y = builder.mul(x_plus_seven, x);
+        builder.set_inputs(&[(x, 3)]);
+        builder.fill_node(x_plus_seven);
+        assert_eq!(
+            builder.witness().values,
+            vec![Some(3), Some(7), Some(10), None]
+        );
+        builder.fill_node(y);
+        assert_eq!(builder.witness().values[y.index()], Some(30));
+    }
+
+    #[test]
+    fn test_node_views() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let seven = builder.constant(7);
+        let x_plus_seven = builder.add(x, seven);
+        let sqrt = builder.hint_fn("sqrt", x_plus_seven);
+        builder.fill_nodes(x, 9);
+        let node = builder.node(x_plus_seven).unwrap();
+        assert_eq!(node.inputs, (Some(x.index()), Some(seven.index())));
+        assert!(matches!(node.op, Some(OPERATION::ADD)));
+        assert_eq!(node.output, Some(16));
+        let node = builder.node(sqrt).unwrap();
+        assert_eq!(
+            (node.inputs, node.hint, node.output),
+            ((None, None), Some(x_plus_seven.index()), Some(4))
+        );
+        assert!(builder.is_constant(seven) && !builder.is_constant(x));
+        builder.set_inputs(&[(x, 1)]);
+        assert_eq!(builder.output(x), Some(1));
+        builder.reset();
+        assert_eq!(builder.output(x_plus_seven), None);
+        assert_eq!(
+            builder.outputs().collect::<Vec<_>>(),
+            [None, Some(7), None, None]
+        );
+
+        let mut other = Builder::new();
+        let y = other.init();
+        other.clear();
+        assert!(other.node(y).is_none() && other.output(y).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "stale")]
+    fn test_stale_node_ids() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.mul(x, x);
+        assert!(builder.contains(y));
+        assert_eq!(builder.node_id(y.index()), Some(y));
+        assert_eq!(builder.node_id(2), None);
+        builder.clear();
+        assert!(builder.is_empty() && !builder.contains(x));
+        let z = builder.init();
+        assert_eq!(z.index(), x.index());
+        assert_ne!(z, x);
+        assert!(!Builder::new().contains(z));
+        builder.add(z, x);
+    }
+
+    #[test]
+    fn test_branded_builder() {
+        let mut builder = Builder::new();
+        let outer = builder.init();
+        let (x, y) = builder.branded(|mut b| {
+            let x = b.init();
+            let outer = b.wire(outer);
+            let sum = b.add(x, outer);
+            let y = b.square(sum);
+            b.set_label(y, "y");
+            b.fill_inputs(&[(x, 2), (outer, 1)]);
+            assert_eq!(b.output(y), Some(9));
+            assert_eq!(b.len(), 4);
+            (x.id(), y.id())
+        });
+        assert_eq!(builder.label(y), Some("y"));
+        assert_eq!(builder.inputs(), [outer, x]);
+
+        let bounds = builder.bounds(&[(x, 0..=3)]);
+        assert_eq!(bounds.get(x).max, 3);
+        let other = Builder::new().bounds(&[]);
+        let result = std::panic::catch_unwind(|| other.get(x));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        use error::{BuildError, ConstraintError, EvalError};
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let big = builder.constant(u32::MAX);
+        let sum = builder.add(x, big);
+        let result = builder.fill_inputs_with(&[(big, 1)], eval::EvalOptions::new());
+        assert!(matches!(
+            result,
+            Err(EvalError::Build(BuildError::NotAnInput { node: 1 }))
+        ));
+        let result = builder.fill_inputs_with(&[], eval::EvalOptions::new());
+        assert!(matches!(
+            result,
+            Err(EvalError::MissingValue {
+                node: 2,
+                operand: 0
+            })
+        ));
+        assert_eq!(builder.check(), Err(ConstraintError::Unfilled { node: 0 }));
+        let result = builder.fill_inputs_with(&[(x, 1)], eval::EvalOptions::new());
+        assert!(matches!(result, Err(EvalError::Overflow { node: 2, .. })));
+
+        let mut witness = builder.witness();
+        witness.values[sum.index()] = Some(3);
+        builder.load_witness(&witness);
+        let err: error::Error = builder.check().unwrap_err().into();
+        assert_eq!(
+            err.to_string(),
+            "node 2 overflows: its inputs give a result that does not fit in a u32"
+        );
+        assert_eq!(
+            Builder::new().resolve(x),
+            Err(BuildError::StaleNode { node: 0 })
+        );
+        assert!(Builder::from_json("{").is_err());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut builder = Builder::with_capacity(1000);
+        assert!(builder.capacity() >= 1000);
+        let x = builder.init();
+        let mut acc = x;
+        for _ in 0..10 {
+            acc = builder.add(acc, x);
+        }
+        builder.reserve(5000);
+        assert!(builder.capacity() >= builder.len() + 5000);
+        builder.shrink_to_fit();
+        assert!(builder.capacity() < 1000);
+        builder.fill_nodes(x, 2);
+        assert_eq!(builder.witness().values[acc.index()], Some(22));
+    }
+
+    #[test]
+    fn test_stepper_levels_and_callbacks() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let seven = builder.constant(7);
+        let x_plus_seven = builder.add(x, seven);
+        let y = builder.mul(x_plus_seven, x_plus_seven);
+        let z = builder.add(x, x);
+        assert_eq!(builder.levels(), vec![0, 0, 1, 2, 1]);
+        let mut seen = Vec::new();
+        let mut stepper = builder.stepper(&[(x, 2)]);
+        stepper.on_step(|builder, id| seen.push((id, builder.witness().values[id.index()])));
+        assert_eq!(stepper.step_level(), vec![x, seven]);
+        assert_eq!(stepper.step_level(), vec![x_plus_seven, z]);
+        assert_eq!(stepper.step(), Some(y));
+        assert_eq!(stepper.step(), None);
+        drop(stepper);
+        assert_eq!(seen[4], (y, Some(81)));
+
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.mul(x, x);
+        let mut stepper = builder.stepper(&[(x, 1 << 16)]);
+        assert_eq!(stepper.try_step().unwrap(), Some(x));
+        assert!(matches!(
+            stepper.try_run(),
+            Err(error::EvalError::Overflow { node, .. }) if node == y.index()
+        ));
+        assert_eq!(stepper.next_node(), Some(y));
+    }
+
+    #[test]
+    fn test_trace_log() {
+        let mut program = parser::parse("y = x + 7; z = hint(sqrt, y) * 3").unwrap();
+        let x = program.var("x").unwrap();
+        let mut sink = Vec::new();
+        program
+            .builder
+            .fill_inputs_with(&[(x, 9)], eval::EvalOptions::new().trace(&mut sink))
+            .unwrap();
+        let events = eval::read_trace(sink.as_slice()).unwrap();
+        let steps: Vec<_> = events
+            .iter()
+            .map(|e| (e.op.as_str(), e.operands.clone(), e.result))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                ("ADD", vec![9, 7], 16),
+                ("HINT", vec![16], 4),
+                ("MUL", vec![4, 3], 12)
+            ]
+        );
+        assert_eq!(events[1].hint.as_deref(), Some("sqrt"));
+    }
+
+    #[test]
+    fn test_metrics() {
+        let metrics = std::sync::Arc::new(metrics::Metrics::default());
+        let mut builder = Builder::new();
+        builder.set_metrics(metrics.clone());
+        let x = builder.init();
+        let y = builder.mul(x, x);
+        let nine = builder.constant(9);
+        builder.enforce_equal(y, nine);
+        let mut other = builder.clone();
+        builder.fill_nodes(x, 3);
+        assert!(builder.is_satisfied());
+        other.fill_nodes(x, 4);
+        assert!(!other.is_satisfied());
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.nodes_created, 3);
+        assert_eq!(snapshot.constraints_added, 1);
+        assert_eq!(snapshot.evaluations, 2);
+        assert_eq!(snapshot.nodes_evaluated, 6);
+        assert_eq!(snapshot.constraint_checks, 2);
+        assert_eq!(snapshot.constraint_violations, 1);
+        metrics.reset();
+        assert_eq!(metrics.snapshot(), metrics::MetricsSnapshot::default());
+    }
+
+    #[test]
+    fn test_progress_callbacks() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let mut y = x;
+        for _ in 0..9 {
+            y = builder.add(y, x);
+        }
+        let mut fill_reports = Vec::new();
+        let options = eval::EvalOptions::new()
+            .progress_interval(4)
+            .on_progress(|done, total| fill_reports.push((done, total)));
+        builder.fill_inputs_with(&[(x, 1)], options).unwrap();
+        assert_eq!(fill_reports, vec![(4, 10), (8, 10), (10, 10)]);
+        let mut check_reports = Vec::new();
+        let options = eval::EvalOptions::new()
+            .progress_interval(5)
+            .on_progress(|done, total| check_reports.push((done, total)));
+        assert!(builder.check_constraints_with(options).unwrap());
+        assert_eq!(check_reports, vec![(5, 10), (10, 10)]);
+    }
+
+    #[test]
+    fn test_cancellation() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let mut y = x;
+        for _ in 0..100 {
+            y = builder.add(y, x);
+        }
+        let cancel = AtomicBool::new(false);
+        let options = eval::EvalOptions::new()
+            .progress_interval(10)
+            .on_progress(|done, _| {
+                if done == 50 {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            })
+            .cancel_on(&cancel);
+        let result = builder.fill_inputs_with(&[(x, 1)], options);
+        assert!(matches!(result, Err(eval::EvalError::Cancelled)));
+        assert_eq!(builder.witness().values[50], None);
+        let options = eval::EvalOptions::new().cancel_on(&cancel);
+        assert!(matches!(
+            builder.is_satisfied_with(options),
+            Err(eval::EvalError::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn test_free_dead_values() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let one = builder.constant(1);
+        let x_plus_one = builder.add(x, one);
+        let squared = builder.mul(x_plus_one, x_plus_one);
+        let cubed = builder.mul(squared, x_plus_one);
+        let doubled = builder.add(cubed, cubed);
+        let sixteen = builder.constant(16);
+        let y = builder.add(doubled, x);
+        builder.enforce_equal(squared, sixteen);
+        builder.mark_public(y);
+        let builder = Builder::from_json(&builder.to_json()).unwrap();
+        assert_eq!(builder.public_outputs()[0].index(), y.index());
+
+        let mut full = builder.clone();
+        full.fill_nodes(full.inputs()[0], 3);
+        let mut live = builder.clone();
+        let options = eval::EvalOptions::new().free_dead_values();
+        live.fill_inputs_with(&[(live.inputs()[0], 3)], options)
+            .unwrap();
+        let values = live.witness().values.clone();
+        assert_eq!(
+            values,
+            vec![
+                Some(3),
+                Some(1),
+                None,
+                Some(16),
+                None,
+                None,
+                Some(16),
+                Some(131)
+            ]
+        );
+        assert_eq!(values[y.index()], full.witness().values[y.index()]);
+    }
+
+    #[test]
+    fn test_checkpoint_resume() {
+        let dir = std::env::temp_dir().join(format!("checkpoint-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("eval.checkpoint");
+        let build = || {
+            let mut builder = Builder::new();
+            let x = builder.init();
+            let mut y = x;
+            for _ in 0..20 {
+                y = builder.add(y, x);
+            }
+            (builder, x, y)
+        };
+
+        // Simulate a crash by cancelling the first evaluation part way through.
+        let (mut builder, x, y) = build();
+        let cancel = std::sync::atomic::AtomicBool::new(false);
+        let options = eval::EvalOptions::new()
+            .checkpoint(&path, 5)
+            .progress_interval(1)
+            .on_progress(|done, _| {
+                if done == 13 {
+                    cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+            })
+            .cancel_on(&cancel);
+        assert!(builder.fill_inputs_with(&[(x, 2)], options).is_err());
+        let saved = checkpoint::Checkpoint::load(&path).unwrap();
+        assert_eq!(saved.next, 13);
+
+        let (mut fresh, _, _) = build();
+        fresh.resume_with(&saved, eval::EvalOptions::new()).unwrap();
+        assert_eq!(fresh.witness().values[y.index()], Some(42));
+        assert!(fresh.is_satisfied());
+
+        let mut smaller = Builder::new();
+        smaller.init();
+        assert!(matches!(
+            smaller.resume_with(&saved, eval::EvalOptions::new()),
+            Err(eval::EvalError::InvalidCheckpoint(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stream_witness() {
+        let path = std::env::temp_dir().join(format!("stream-test-{}.wit", std::process::id()));
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let mut y = x;
+        for _ in 0..50 {
+            let y_squared = builder.mul(y, y);
+            y = builder.hint_fn("sqrt", y_squared);
+        }
+        let stats = builder.stream_witness(&[(x, 7)], &path).unwrap();
+        assert_eq!(stats.nodes_written, 101);
+        assert_eq!(stats.peak_live_values, 1);
+
+        builder.fill_nodes(x, 7);
+        let mut file = stream::WitnessFile::open(&path).unwrap();
+        assert_eq!(file.len(), builder.len());
+        for (id, &value) in builder.witness().values.iter().enumerate() {
+            assert_eq!(file.get(id).unwrap(), value);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_schedule() {
+        let mut builder = Builder::new();
+        let inputs: Vec<NodeId> = (0..100).map(|_| builder.init()).collect();
+        let mut sum = builder.constant(0);
+        for &input in &inputs {
+            let square = builder.mul(input, input);
+            sum = builder.add(sum, square);
+        }
+        let schedule = builder.schedule();
+        assert_eq!(schedule.order.len(), builder.len());
+        assert_eq!(schedule.peak_live_values, 2);
+        assert_eq!(builder.memory_estimate().peak_live_values, 101);
+
+        let assignment: Vec<(NodeId, u32)> = inputs.iter().map(|&id| (id, 3)).collect();
+        let mut sink = std::io::Cursor::new(Vec::new());
+        let stats = builder
+            .stream_witness_scheduled_to(&assignment, &mut sink, &schedule)
+            .unwrap();
+        assert_eq!(stats.peak_live_values, 2);
+        let mut file = Vec::new();
+        builder.stream_witness_to(&assignment, &mut file).unwrap();
+        assert_eq!(sink.into_inner(), file);
+    }
+
+    #[test]
+    fn test_memory_estimate() {
+        let program = parser::parse("a = x * x; b = a + x; c = b * a; y = c + c").unwrap();
+        let estimate = program.builder.memory_estimate();
+        assert_eq!(estimate.full_witness_bytes, program.builder.len() * 8);
+        assert!(estimate.graph_bytes >= program.builder.len() * 14);
+        let x = program.var("x").unwrap();
+        let mut sink = Vec::new();
+        let stats = program
+            .builder
+            .stream_witness_to(&[(x, 2)], &mut sink)
+            .unwrap();
+        assert_eq!(estimate.peak_live_values, stats.peak_live_values);
+        assert!(estimate.streaming_witness_bytes > 0);
+    }
+
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn test_wasm_circuit() {
+        let program = parser::parse("y = x * x + 3; assert y == 52").unwrap();
+        let mut circuit = wasm::Circuit::from_json(&program.builder.to_json()).unwrap();
+        assert_eq!(circuit.length(), program.builder.len());
+        circuit.evaluate(r#"{"x": 7}"#).unwrap();
+        assert!(circuit.is_satisfied());
+        assert_eq!(circuit.output("y"), Some(52));
+        assert!(circuit.witness().starts_with("[7,"));
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn test_ffi() {
+        use my_graph_lib::ffi::*;
+        unsafe {
+            let builder = circuit_builder_new();
+            let x = circuit_init(builder);
+            let seven = circuit_constant(builder, 7);
+            let x_plus_seven = circuit_add(builder, x, seven);
+            let sqrt = circuit_hint(builder, c"sqrt".as_ptr(), x_plus_seven);
+            let computed_sq = circuit_mul(builder, sqrt, sqrt);
+            assert!(circuit_enforce_equal(builder, computed_sq, x_plus_seven));
+            assert_eq!(circuit_add(builder, x, 100), CIRCUIT_INVALID);
+            assert_eq!(circuit_hint(builder, c"cbrt".as_ptr(), x), CIRCUIT_INVALID);
+            assert!(!circuit_fill(builder, &seven, &9, 1));
+            assert!(circuit_fill(builder, &x, &9, 1));
+            assert!(circuit_is_satisfied(builder));
+            let mut out = 0;
+            assert!(circuit_output(builder, sqrt, &mut out));
+            assert_eq!(out, 4);
+            assert!(!circuit_fill(builder, &x, &u32::MAX, 1));
+            assert_eq!(circuit_len(builder), 5);
+            circuit_builder_free(builder);
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mmap_graph() {
+        let path = std::env::temp_dir().join(format!("mmap-test-{}.graph", std::process::id()));
+        {
+            let mut graph = mmap::MmapGraph::create(&path, 2).unwrap();
+            let x = graph.init().unwrap();
+            let seven = graph.constant(7).unwrap();
+            let x_plus_seven = graph.add(x, seven).unwrap();
+            let sqrt = graph.hint(4, x_plus_seven).unwrap();
+            let computed_sq = graph.mul(sqrt, sqrt).unwrap();
+            graph.enforce_equal(computed_sq, x_plus_seven).unwrap();
+            graph.fill_inputs(&[(x, 9)]);
+            assert!(graph.is_satisfied());
+            graph.flush().unwrap();
+        }
+        let mut graph = mmap::MmapGraph::open(&path).unwrap();
+        assert_eq!(graph.len(), 6);
+        assert_eq!(graph.output(4), Some(16));
+        graph.fill_inputs(&[(0, 10)]);
+        assert!(!graph.is_satisfied());
+
+        let program = parser::parse("y = x * x + 3; assert y == 52").unwrap();
+        let mut graph = mmap::MmapGraph::from_builder(&program.builder, &path).unwrap();
+        graph.fill_inputs(&[(program.var("x").unwrap().index(), 7)]);
+        assert_eq!(graph.output(program.var("y").unwrap().index()), Some(52));
+        assert!(graph.is_satisfied());
+        drop(graph);
+
+        // A file of a later version of the format is rejected by name.
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[7] = b'2';
+        fs::write(&path, bytes).unwrap();
+        let err = mmap::MmapGraph::open(&path).err().unwrap();
+        assert!(err
+            .to_string()
+            .contains("format version 2 is not supported"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_explain_violation() {
+        let mut program = parser::parse("y = x + 7;\nassert y == hint(sqrt, y)^2").unwrap();
+        let x = program.var("x").unwrap();
+        program.builder.fill_nodes(x, 10);
+        let violation = program.builder.check().unwrap_err();
+        let report = program.builder.explain(&violation);
+        assert_eq!(report.sides[0].expression, "x + 7");
+        assert_eq!(report.sides[1].expression, "hint(sqrt, y) * hint(sqrt, y)");
+        assert_eq!(
+            report.to_string(),
+            "constraint 0 does not hold: node 2 is 17 but node 4 is 16\n  \
+             assert at 2:1\n  \
+             y = x + 7 = 17 (defined at 1:1)\n  \
+             n4 = hint(sqrt, y) * hint(sqrt, y) = 16 (defined at 2:1)\n  \
+             inputs: x = 10"
+        );
+    }
+
+    #[test]
+    fn test_secret_redaction() {
+        let mut builder = Builder::new();
+        let secret = builder.private_input();
+        let public = builder.init();
+        let product = builder.mul(secret, public);
+        let expected = builder.constant(100);
+        builder.enforce_equal(product, expected);
+        assert!(builder.is_secret(product) && !builder.is_secret(public));
+        let mut sink = Vec::new();
+        builder
+            .fill_inputs_with(
+                &[(secret, 1234), (public, 3)],
+                eval::EvalOptions::new().trace(&mut sink),
+            )
+            .unwrap();
+
+        let violation = builder.check().unwrap_err();
+        assert_eq!(
+            violation.to_string(),
+            "constraint 0 does not hold: node 2 is <redacted> but node 3 is 100"
+        );
+        assert!(!builder.explain(&violation).to_string().contains("1234"));
+        assert!(!format!("{:?}", builder).contains("1234"));
+        assert!(!format!("{:?}", builder.witness()).contains("1234"));
+        assert!(!String::from_utf8(sink).unwrap().contains("1234"));
+        assert_eq!(builder.witness().values[0], Some(1234));
+        let loaded = Builder::from_json(&builder.to_json()).unwrap();
+        assert!(loaded.is_secret(loaded.node_id(2).unwrap()));
+
+        builder.set_reveal_secrets(true);
+        assert!(builder.check().unwrap_err().to_string().contains("3702"));
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut program = parser::parse("y = x * 3 + 1; z = hint(sqrt, y)").unwrap();
+        let x = program.var("x").unwrap();
+        let y = program.var("y").unwrap();
+        let builder = &mut program.builder;
+        let seven = builder.hint(7, y);
+        builder.fill_nodes(x, 5);
+        builder.reset();
+        let values = builder.witness().values.clone();
+        assert_eq!(values.iter().filter(|value| value.is_some()).count(), 3);
+        assert_eq!(values[seven.index()], Some(7));
+        builder.fill_nodes(x, 1);
+        assert_eq!(builder.witness().values[y.index()], Some(4));
+    }
+
+    #[test]
+    fn test_witness_commitment() {
+        use commitment::HashFunction;
+        let mut program = parser::parse("y = x * x + 1").unwrap();
+        let x = program.var("x").unwrap();
+        program.builder.fill_nodes(x, 6);
+        let witness = program.builder.witness();
+        let commitment = witness.commitment();
+        assert!(program.builder.verify_commitment(&commitment, &[]));
+        assert_eq!(commitment.to_string().len(), "blake3:".len() + 64);
+
+        let salted = witness.commitment_with(HashFunction::Sha256, b"salt");
+        assert_ne!(salted.digest, commitment.digest);
+        assert!(program.builder.verify_commitment(&salted, b"salt"));
+        assert!(!program.builder.verify_commitment(&salted, b"pepper"));
+
+        program.builder.fill_nodes(x, 7);
+        assert!(!program.builder.verify_commitment(&commitment, &[]));
+        program.builder.load_witness(&witness);
+        assert!(program.builder.verify_commitment(&commitment, &[]));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let a = parser::parse("y = x * x + 3; z = hint(sqrt, y); assert z == x").unwrap();
+        let b = parser::parse("c = 3; sq = x * x; w = c + sq; r = hint(sqrt, w); assert x == r")
+            .unwrap();
+        let c = parser::parse("y = x * x + 4; z = hint(sqrt, y); assert z == x").unwrap();
+        assert_eq!(a.builder.fingerprint(), b.builder.fingerprint());
+        assert_ne!(a.builder.fingerprint(), c.builder.fingerprint());
+
+        let mut filled = a.builder.clone();
+        filled.fill_nodes(a.var("x").unwrap(), 2);
+        assert_eq!(filled.fingerprint(), a.builder.fingerprint());
+        let loaded = Builder::from_json(&a.builder.to_json()).unwrap();
+        assert_eq!(loaded.fingerprint(), a.builder.fingerprint());
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let program = parser::parse("y = x * x + 3; z = hint(sqrt, y); assert z == x").unwrap();
+        let mut builder = program.builder.clone();
+        builder.mark_public(program.var("y").unwrap());
+        let expected = "circuit snapshot v1\n\
+                        %0 = input  # x\n\
+                        %1 = mul %0 %0\n\
+                        %2 = const 3\n\
+                        %3 = add %1 %2  # y\n\
+                        %4 = hint sqrt %3  # z\n\
+                        assert %4 == %0\n\
+                        public %3\n";
+        assert_eq!(builder.snapshot(), expected);
+
+        builder.fill_nodes(program.var("x").unwrap(), 1);
+        assert_eq!(builder.snapshot(), expected);
+        let loaded = Builder::from_json(&builder.to_json()).unwrap();
+        assert_eq!(loaded.snapshot(), expected);
+    }
+
+    #[test]
+    fn test_bench() {
+        let config = random::RandomConfig {
+            depth: 3,
+            ..random::RandomConfig::default()
+        };
+        let report = bench::bench_random(7, &config, &[2, 8], 1).unwrap();
+        assert_eq!(report.rows.len(), 2);
+        assert!(report.rows[0].nodes < report.rows[1].nodes);
+        let widest = random::RandomConfig { width: 8, ..config };
+        assert_eq!(report.rows[1].nodes, Builder::random(7, &widest).len());
+        for row in &report.rows {
+            assert_eq!(row.fill.len(), differential::Backend::all().len());
+        }
+
+        let program = parser::parse("y = x * x + 3; z = hint(sqrt, y); assert z == x").unwrap();
+        let x = program.var("x").unwrap();
+        let report = bench::bench_graph(&program.builder, &[(x, 1)], 2).unwrap();
+        assert_eq!(report.rows[0].nodes, 5);
+        assert_eq!(report.rows[0].constraints, 1);
+        assert_eq!(report.to_string().lines().count(), 2);
+        assert!(bench::bench_graph(&program.builder, &[(x, u32::MAX)], 1).is_err());
+    }
+
+    #[test]
+    fn test_traversal() {
+        let program =
+            parser::parse("a = x * x; b = a + y; c = hint(sqrt, a); d = c + 1; assert d == b")
+                .unwrap();
+        let builder = &program.builder;
+        let [x, y, a, b, c, d] =
+            ["x", "y", "a", "b", "c", "d"].map(|name| program.var(name).unwrap());
+        assert_eq!(builder.nodes().len(), builder.len());
+        assert!(builder
+            .nodes()
+            .enumerate()
+            .all(|(index, node)| node.id == index));
+        let edges: Vec<_> = builder.edges().collect();
+        assert!(edges.contains(&(x, a)) && edges.contains(&(a, c)));
+        assert_eq!(edges.iter().filter(|&&(_, to)| to == a).count(), 1);
+        assert!(edges.iter().all(|(from, to)| from.index() < to.index()));
+        assert_eq!(builder.consumers(a).collect::<Vec<_>>(), [b, c]);
+        assert_eq!(builder.consumers(d).count(), 0);
+        let ancestors: Vec<_> = builder.ancestors(d).collect();
+        assert!(ancestors.contains(&c) && ancestors.contains(&a) && ancestors.contains(&x));
+        assert!(!ancestors.contains(&y) && !ancestors.contains(&b));
+        assert!(ancestors
+            .windows(2)
+            .all(|pair| pair[0].index() > pair[1].index()));
+        assert_eq!(builder.ancestors(x).count(), 0);
+    }
+
+    #[test]
+    fn test_node_ref() {
+        let mut program = parser::parse("y = x * x + 3; z = hint(sqrt, y); assert z == x").unwrap();
+        let (x, y) = (program.var("x").unwrap(), program.var("y").unwrap());
+        let builder = &mut program.builder;
+        builder.mark_public(y);
+        builder.fill_nodes(x, 1);
+        let node = builder.at(y);
+        assert_eq!(
+            (node.id(), node.label(), node.output()),
+            (y, Some("y"), Some(4))
+        );
+        assert!(node.is_public() && !node.is_constant() && !node.is_secret());
+        assert!(matches!(node.view().op, Some(OPERATION::ADD)));
+        assert_eq!(node.consumers().count(), 1);
+        assert!(!builder.at(x).is_public());
+
+        let view = &builder[y];
+        assert_eq!(
+            (view.id(), view.label(), view.output()),
+            (y, Some("y"), Some(4))
+        );
+        assert!(view.is_public() && !builder[x].is_public());
+        let before = builder[x].output();
+        let z = program.var("z").unwrap();
+        assert_eq!(program.builder[z].label(), Some("z"));
+        program.builder.set_label(z, "root");
+        assert_eq!(program.builder[z].label(), Some("root"));
+        program.builder.fill_nodes(x, 2);
+        assert_eq!((before, program.builder[x].output()), (Some(1), Some(2)));
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.mul(x, x);
+        builder.set_meta(y, "line", 3);
+        builder.set_meta(y, "shape", serde_json::json!([2, 2]));
+        builder.set_meta(y, "line", 4);
+        assert_eq!(builder.meta(y, "line"), Some(&serde_json::json!(4)));
+        assert_eq!(builder.meta(x, "line"), None);
+
+        let loaded = Builder::from_json(&builder.to_json()).unwrap();
+        let shape = loaded.meta(loaded.node_id(y.index()).unwrap(), "shape");
+        assert_eq!(shape, Some(&serde_json::json!([2, 2])));
+        let mut plain = Builder::new();
+        let x = plain.init();
+        plain.mul(x, x);
+        assert_eq!(plain.fingerprint(), builder.fingerprint());
+        assert!(export::to_dot(&builder).contains(r#"tooltip="line=4, shape=[2,2]""#));
+    }
+
+    #[test]
+    fn test_constraint_messages() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.init();
+        builder.enforce_equal(x, x);
+        builder.enforce_equal_with(x, y, "balance must round-trip");
+        builder.fill_inputs(&[(x, 1), (y, 2)]);
+        let report = builder.explain(&builder.check().unwrap_err());
+        assert_eq!(report.message.as_deref(), Some("balance must round-trip"));
+        assert!(report
+            .to_string()
+            .starts_with("balance must round-trip: constraint 1"));
+
+        let loaded = Builder::from_json(&builder.to_json()).unwrap();
+        let report = loaded.explain(&loaded.check().unwrap_err());
+        assert_eq!(report.message.as_deref(), Some("balance must round-trip"));
+
+        let sqrt = builder.hint_fn("sqrt", y);
+        builder.fill_inputs(&[(x, 1), (y, 2)]);
+        let result = std::panic::catch_unwind(|| builder.assert_equal_with(sqrt, x, "root"));
+        let panic = result.unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert_eq!(message, "root: assertion failed: 2 != 1");
+    }
+
+    #[test]
+    fn test_soft_constraints() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let limit = builder.constant(3);
+        builder.enforce_equal_warning(x, limit, "x should be 3 for efficiency");
+        builder.enforce_equal(x, x);
+        assert_eq!(builder.severity(0), diagnostics::Severity::Warning);
+        assert_eq!(builder.severity(1), diagnostics::Severity::Error);
+        let strict = builder.fingerprint();
+
+        builder.fill_nodes(x, 4);
+        assert!(builder.check_constraints() && builder.is_satisfied());
+        let warnings = builder.warnings();
+        assert_eq!(warnings.len(), 1);
+        let report = builder.explain(&warnings[0]);
+        assert_eq!(
+            report.message.as_deref(),
+            Some("x should be 3 for efficiency")
+        );
+        assert!(builder
+            .snapshot()
+            .contains("warn %0 == %1\nassert %0 == %0\n"));
+        assert_eq!(export::to_r1cs(&builder).constraints.len(), 2);
+
+        let loaded = Builder::from_json(&builder.to_json()).unwrap();
+        assert_eq!(loaded.warnings(), warnings);
+        assert_eq!(loaded.fingerprint(), strict);
+        let mut hard = Builder::new();
+        let x = hard.init();
+        let limit = hard.constant(3);
+        hard.enforce_equal(x, limit);
+        hard.enforce_equal(x, x);
+        assert_ne!(hard.fingerprint(), strict);
+        hard.fill_nodes(x, 4);
+        assert!(!hard.is_satisfied());
+    }
+
+    #[test]
+    fn test_assert_lt() {
+        let mut builder = Builder::new();
+        let start = builder.init();
+        let end = builder.init();
+        builder.assert_lt(start, end, 8);
+        builder.fill_inputs(&[(start, 3), (end, 200)]);
+        assert!(builder.check_constraints());
+        for (a, b) in [(5, 5), (6, 5), (3, 256)] {
+            builder.fill_inputs(&[(start, a), (end, b)]);
+            let violation = builder.check().unwrap_err();
+            assert!(matches!(
+                violation,
+                error::ConstraintError::NotLess {
+                    check: 0,
+                    bits: 8,
+                    ..
+                }
+            ));
+        }
+        assert_eq!(
+            builder.explain(&builder.check().unwrap_err()).sides.len(),
+            2
+        );
+
+        let loaded = Builder::from_json(&builder.to_json()).unwrap();
+        assert_eq!(loaded.checks(), builder.checks());
+        assert!(!loaded.is_satisfied());
+        assert!(builder.snapshot().contains("assert %0 < %1 < 2^8\n"));
+        let r1cs = export::to_r1cs(&builder);
+        assert_eq!(r1cs.num_variables, 3 + 1 + 8 + 8);
+        assert_eq!(r1cs.constraints.len(), 1 + 2 * (8 + 1));
+        let mut plain = Builder::new();
+        plain.init();
+        plain.init();
+        assert_ne!(builder.fingerprint(), plain.fingerprint());
+    }
+
+    #[test]
+    fn test_assert_in_set() {
+        let mut builder = Builder::new();
+        let coin = builder.init();
+        builder.assert_in_set(coin, &[25, 5, 10, 5]);
+        assert_eq!(
+            builder.checks(),
+            [checks::Check::InSet {
+                node: 0,
+                set: vec![5, 10, 25]
+            }]
+        );
+        builder.fill_nodes(coin, 10);
+        assert!(builder.check_constraints());
+        builder.fill_nodes(coin, 7);
+        let violation = builder.check().unwrap_err();
+        assert!(
+            matches!(violation, error::ConstraintError::NotInSet { check: 0, node: 0, value } if value == 7)
+        );
+
+        assert!(!Builder::from_json(&builder.to_json())
+            .unwrap()
+            .is_satisfied());
+        assert!(builder.snapshot().contains("assert %0 in {5, 10, 25}\n"));
+        let r1cs = export::to_r1cs(&builder);
+        assert_eq!(r1cs.num_variables, 2 + 3);
+        let last = r1cs.constraints.last().unwrap();
+        assert_eq!(last.a, [(2, 5), (3, 10), (4, 25)]);
+        assert_eq!(last.c, [(1, 1)]);
+    }
+
+    #[test]
+    fn test_assert_permutation() {
+        let mut builder = Builder::new();
+        let a: Vec<NodeId> = (0..3).map(|_| builder.init()).collect();
+        let b: Vec<NodeId> = (0..3).map(|_| builder.init()).collect();
+        builder.assert_permutation(&a, &b);
+        let fill = |builder: &mut Builder, values: [u32; 6]| {
+            let inputs: Vec<_> = a.iter().chain(&b).copied().zip(values).collect();
+            builder.fill_inputs(&inputs);
+        };
+        fill(&mut builder, [1, 2, 2, 2, 1, 2]);
+        assert!(builder.check_constraints());
+        fill(&mut builder, [1, 2, 2, 2, 1, 1]);
+        let violation = builder.check().unwrap_err();
+        assert!(
+            matches!(violation, error::ConstraintError::NotPermutation { check: 0, node: 4, value } if value == 1)
+        );
+
+        assert!(!Builder::from_json(&builder.to_json())
+            .unwrap()
+            .is_satisfied());
+        assert!(builder
+            .snapshot()
+            .contains("assert [%0, %1, %2] permutes [%3, %4, %5]\n"));
+        let r1cs = export::to_r1cs(&builder);
+        assert_eq!(r1cs.num_variables, 7 + 2 * 9);
+        assert_eq!(r1cs.constraints.len(), 2 * 9 + 3 * 3);
+
+        let mut swapped = Builder::new();
+        let a: Vec<NodeId> = (0..3).map(|_| swapped.init()).collect();
+        let b: Vec<NodeId> = (0..3).map(|_| swapped.init()).collect();
+        swapped.assert_permutation(&b, &[a[2], a[0], a[1]]);
+        assert_eq!(swapped.fingerprint(), builder.fingerprint());
+    }
+
+    #[test]
+    fn test_if_else() {
+        let mut builder = Builder::new();
+        let cond = builder.bool_input();
+        let x = builder.init();
+        let outputs = builder.if_else(
+            cond,
+            |b| {
+                let two = b.constant(2);
+                vec![b.mul(x, two), x]
+            },
+            |b| {
+                let ten = b.constant(10);
+                vec![b.add(x, ten), x]
+            },
+        );
+        assert_eq!(outputs[1], x);
+        for (cond_value, expected) in [(1, 10), (0, 15)] {
+            builder.fill_inputs(&[(cond.id(), cond_value), (x, 5)]);
+            assert!(builder.check_constraints());
+            assert_eq!(builder.output(outputs[0]), Some(expected));
+        }
+        builder.fill_inputs(&[(cond.id(), 2), (x, 5)]);
+        assert!(!builder.is_satisfied());
+
+        let mut builder = Builder::new();
+        let (cond, a, b) = (builder.bool_input(), builder.init(), builder.init());
+        let chosen = builder.select(cond, a, b);
+        builder.fill_inputs(&[(cond.id(), 0), (a, 3), (b, 4)]);
+        assert_eq!(builder.output(chosen), Some(4));
+        assert!(builder.is_satisfied());
+    }
+
+    #[test]
+    fn test_repeat() {
+        let mut builder = Builder::new();
+        let a = builder.init();
+        let b = builder.init();
+        let state = builder.repeat(10, &[a, b], |builder, state| {
+            vec![state[1], builder.add(state[0], state[1])]
+        });
+        assert_eq!(builder.len(), 2 + 10);
+        builder.fill_inputs(&[(a, 0), (b, 1)]);
+        assert_eq!(builder.output(state[0]), Some(55));
+        assert_eq!(builder.repeat(0, &[a], |_, _| unreachable!()), [a]);
+    }
+
+    #[test]
+    fn test_limits() {
+        use my_graph_lib::error::BuildError;
+        use my_graph_lib::limits::{Limit, Limits};
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let one = builder.constant(1);
+        builder.set_limits(Limits::new().max_depth(3).max_nodes(6).max_constraints(1));
+        let state = builder.repeat(3, &[x], |builder, state| vec![builder.add(state[0], one)]);
+        let err = builder.try_mul(state[0], x).unwrap_err();
+        assert!(matches!(
+            err,
+            BuildError::LimitExceeded {
+                limit: Limit::Depth,
+                max: 3
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "the graph would exceed its depth limit of 3"
+        );
+        builder.try_mul(x, x).unwrap();
+        assert!(matches!(
+            builder.try_constant(2),
+            Err(BuildError::LimitExceeded {
+                limit: Limit::Nodes,
+                ..
+            })
+        ));
+        builder.enforce_equal(x, one);
+        assert!(builder.try_enforce_equal(x, one).is_err());
+        assert!(builder.try_assert_range(x, 8).is_err());
+        assert!(builder.try_assert_lt(x, one, 8).is_err());
+        assert!(builder.try_assert_in_set(x, &[1, 2]).is_err());
+        assert!(builder.try_assert_permutation(&[x], &[one]).is_err());
+        assert!(builder.checks().is_empty());
+        assert_eq!(builder.len(), 6);
+
+        // The parser reports limits like syntax errors.
+        let mut builder = Builder::new();
+        builder.set_limits(Limits::new().max_nodes(3));
+        let err = parser::parse_with(builder, "y = x + 1 + 2 + 3").unwrap_err();
+        assert_eq!(err.message, "the graph would exceed its node limit of 3");
+    }
+
+    #[test]
+    fn test_slice_to() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.init();
+        let x_squared = builder.mul(x, x);
+        let y_doubled = builder.add(y, y);
+        let sum = builder.add(x_squared, y_doubled);
+        builder.set_label(x, "x");
+        builder.mark_public(sum);
+        let four = builder.constant(4);
+        builder.enforce_equal(x_squared, four);
+        builder.enforce_equal(y_doubled, four);
+
+        let slice = builder.slice_to(&[x_squared]);
+        assert_eq!(slice.builder.len(), 2);
+        assert_eq!(slice.get(y), None);
+        let new_x = slice.get(x).unwrap();
+        assert_eq!(slice.builder.label(new_x), Some("x"));
+        assert!(slice.builder.public_outputs().is_empty());
+        assert_eq!(slice.builder.inputs(), [new_x]);
+
+        let slice = builder.slice_to(&[y_doubled, four]);
+        assert_eq!(slice.builder.len(), 3);
+        let mut sliced = slice.builder;
+        sliced.fill_inputs(&[(slice.ids[&y], 2)]);
+        assert_eq!(sliced.output(slice.ids[&y_doubled]), Some(4));
+        assert!(sliced.is_satisfied());
+    }
+
+    #[test]
+    fn test_slice_violation() {
+        let mut program =
+            parser::parse("y = x + 7; z = w * 2; assert y == hint(sqrt, y)^2").unwrap();
+        let x = program.var("x").unwrap();
+        let w = program.var("w").unwrap();
+        let builder = &mut program.builder;
+        builder.fill_inputs(&[(x, 10), (w, 1)]);
+        let violation = builder.check().unwrap_err();
+        let slice = builder.slice_violation(&violation);
+        assert_eq!(slice.get(w), None);
+        assert_eq!(
+            slice.to_string(),
+            "x = input = 10\nn1 = 7\ny = x + n1 = 17\nn3 = hint(sqrt, y) = 4\nn4 = n3 * n3 = 16"
+        );
+        assert!(slice.to_dot().contains("n2 -> n3 [style=dashed]"));
+    }
+
+    #[test]
+    fn test_minimize_failure() {
+        let program = parser::parse("assert x * y + z == z; assert w == w").unwrap();
+        let [x, y, z, w] = ["x", "y", "z", "w"].map(|name| program.var(name).unwrap());
+        let builder = &program.builder;
+        let inputs = [(x, 1000), (y, 77), (z, 12345), (w, 9)];
+        let minimized = builder.minimize_failure(&inputs).unwrap().unwrap();
+        assert_eq!(minimized.inputs, [(x, 1), (y, 1), (z, 0), (w, 0)]);
+        assert!(matches!(
+            minimized.violation,
+            error::ConstraintError::NotEqual { constraint: 0, .. }
+        ));
+        assert!(builder.output(x).is_none());
+        let passing = [(x, 0), (y, 1), (z, 3), (w, 1)];
+        assert_eq!(builder.minimize_failure(&passing).unwrap(), None);
+    }
+
+    #[test]
+    fn test_smtlib() {
+        let mut program = parser::parse("y = x + 7; assert y == hint(sqrt, y)^2").unwrap();
+        let x = program.var("x").unwrap();
+        let builder = &mut program.builder;
+        builder.assert_in_set(x, &[2, 9]);
+        builder.assert_permutation(&[x], &[x]);
+        let witness = export::to_smtlib(builder, export::SmtGoal::Witness);
+        assert!(witness.starts_with("(set-logic QF_BV)\n(declare-const n0 (_ BitVec 32)) ; x\n"));
+        assert!(witness.contains(
+            "(assert (= ((_ zero_extend 32) n2) (bvadd ((_ zero_extend 32) n0) ((_ zero_extend 32) n1))))"
+        ));
+        assert!(witness
+            .contains("(assert (= n2 n4))\n(assert (or (= n0 (_ bv2 32)) (= n0 (_ bv9 32))))"));
+        assert!(witness.ends_with("(check-sat)\n(get-model)\n"));
+        let violation = export::to_smtlib(builder, export::SmtGoal::Violation);
+        assert!(violation.contains("(assert (or false (not (= n2 n4)) (not (or"));
+        for script in [witness, violation] {
+            let depth = script.chars().try_fold(0i32, |depth, c| match c {
+                '(' => Some(depth + 1),
+                ')' => (depth > 0).then(|| depth - 1),
+                _ => Some(depth),
+            });
+            assert_eq!(depth, Some(0));
+        }
+    }
+
+    #[test]
+    fn test_bounds() {
+        let program = parser::parse("y = x * x + 7; z = hint(sqrt, y) * w").unwrap();
+        let [x, y, z, w] = ["x", "y", "z", "w"].map(|name| program.var(name).unwrap());
+        let builder = &program.builder;
+        let bounds = builder.bounds(&[(x, 2..=10)]);
+        assert_eq!(bounds.get(y), bounds::Interval { min: 11, max: 107 });
+        assert_eq!(bounds.get(z).max, 10 * u64::from(u32::MAX));
+        assert_eq!(bounds.overflows(), [z.index()]);
+        let bounds = builder.bounds(&[(x, 2..=10), (w, 0..=1000)]);
+        assert!(bounds.overflows().is_empty());
+        assert_eq!(bounds.exceeding(1001), [z.index()]);
+        let bounds = builder.bounds(&[(x, 0..=u32::MAX), (w, 0..=1000)]);
+        assert_eq!(bounds.overflows(), [x.index() + 1, y.index()]);
+    }
+
+    #[test]
+    fn test_taint() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let secret = builder.private_input();
+        let salt = builder.private_input();
+        let sum = builder.add(x, secret);
+        let hash = builder.mul(sum, salt);
+        let doubled = builder.add(x, x);
+        builder.mark_public(hash);
+        builder.mark_public(doubled);
+        builder.mark_public(secret);
+        let tainted = builder.taint();
+        assert_eq!(tainted.len(), 2);
+        assert_eq!(tainted[0].output, secret);
+        assert_eq!(tainted[0].sources, [secret]);
+        assert_eq!(tainted[1].output, hash);
+        assert_eq!(tainted[1].sources, [secret, salt]);
+
+        // Equality constraints and aliases reveal the secret as well.
+        let mut builder = Builder::new();
+        let secret = builder.private_input();
+        let other = builder.private_input();
+        let p = builder.init();
+        let q = builder.init();
+        let r = builder.init();
+        let next = builder.add(r, r);
+        builder.enforce_equal(p, secret);
+        builder.alias(q, other);
+        builder.alias(r, p);
+        for output in [p, q, next] {
+            builder.mark_public(output);
+        }
+        let tainted = builder.taint();
+        assert_eq!(tainted.len(), 3);
+        assert_eq!(
+            (tainted[0].output, &tainted[0].sources[..]),
+            (p, &[secret][..])
+        );
+        assert_eq!(
+            (tainted[1].output, &tainted[1].sources[..]),
+            (q, &[other][..])
+        );
+        assert_eq!(
+            (tainted[2].output, &tainted[2].sources[..]),
+            (next, &[secret][..])
+        );
+    }
+
+    #[test]
+    fn test_rewrite() {
+        use rewrite::{constant, value, var, Rewriter, Rule};
+        let mut program = parser::parse("y = x * 3 * 5 + 0; assert y == x * 15").unwrap();
+        let x = program.var("x").unwrap();
+        let y = program.var("y").unwrap();
+        program.builder.mark_public(y);
+        let rewriter = Rewriter::new()
+            .rule(Rule::new(
+                "fold-mul",
+                (var("a") * constant("c1")) * constant("c2"),
+                var("a") * (constant("c1") * constant("c2")),
+            ))
+            .rule(Rule::new("add-zero", var("a") + value(0), var("a")));
+        let rewritten = rewriter.run(&program.builder);
+        assert_eq!(rewritten.applied["fold-mul"], 1);
+        assert_eq!(rewritten.applied["add-zero"], 1);
+        assert_eq!((rewritten.cost_before, rewritten.cost_after), (4, 2));
+        let mut builder = rewritten.builder;
+        assert_eq!(builder.label(rewritten.ids[&y]), Some("y"));
+        assert_eq!(builder.expression(rewritten.ids[&y]), "x * 15");
+        builder.fill_inputs(&[(rewritten.ids[&x], 2)]);
+        assert_eq!(builder.public_outputs(), [rewritten.ids[&y]]);
+        assert_eq!(builder.output(rewritten.ids[&y]), Some(30));
+        assert!(builder.check_constraints());
+    }
+
+    #[test]
+    fn test_passes() {
+        use passes::PassManager;
+        let mut program =
+            parser::parse("k = 2 * 3; y = x * k + 0; z = x * 6; w = x + x; assert y == z").unwrap();
+        let x = program.var("x").unwrap();
+        let y = program.var("y").unwrap();
+        program.builder.mark_public(y);
+        let optimized = PassManager::standard().run(&program.builder);
+        let changes: Vec<(&str, usize)> = optimized
+            .stats
+            .iter()
+            .map(|stats| (stats.name.as_str(), stats.changes))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("constant-folding", 1),
+                ("simplify", 1),
+                ("common-subexpressions", 2),
+                ("dead-code-elimination", 1),
+            ]
+        );
+        assert!(optimized
+            .stats
+            .iter()
+            .all(|stats| stats.runs == optimized.rounds));
+        assert!(!optimized.ids.contains_key(&program.var("w").unwrap()));
+        let mut builder = optimized.builder;
+        assert_eq!(builder.expression(optimized.ids[&y]), "x * k");
+        builder.fill_inputs(&[(optimized.ids[&x], 7)]);
+        assert_eq!(builder.output(optimized.ids[&y]), Some(42));
+        assert!(builder.check_constraints());
+    }
+
+    #[test]
+    fn test_pass_validation() {
+        use error::ValidationError;
+        use passes::PassManager;
+        use rewrite::{value, var, Rewriter, Rule};
+        let mut program = parser::parse("y = x * 3 + 0; assert y == x * 3").unwrap();
+        let y = program.var("y").unwrap();
+        program.builder.mark_public(y);
+        let optimized = PassManager::standard().validate(32).run(&program.builder);
+        assert!(optimized.rejected.is_empty());
+        assert!(optimized.stats.iter().any(|stats| stats.changes > 0));
+
+        let wrong = Rewriter::new().rule(Rule::new("wrong", var("a") + value(0), value(0)));
+        let manager = PassManager::new().pass(wrong).validate(32);
+        let optimized = manager.run(&program.builder);
+        assert_eq!(optimized.stats[0].changes, 0);
+        assert_eq!(optimized.builder.len(), program.builder.len());
+        let Some(ValidationError::OutputChanged {
+            pass,
+            output,
+            found,
+            inputs,
+            ..
+        }) = optimized.rejected.first()
+        else {
+            panic!("expected a changed output, got {:?}", optimized.rejected);
+        };
+        assert_eq!(
+            (pass.as_str(), *output, found.get()),
+            ("rewrite", y.index(), 0)
+        );
+        let mut builder = program.builder.clone();
+        builder.fill_inputs(inputs);
+        assert_ne!(builder.output(y), Some(0));
+
+        let err = manager.strict(true).try_run(&program.builder).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("pass `rewrite` changed public output"));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_check() {
+        let mut program =
+            parser::parse("a = x * x; b = x + 1; c = a + b; assert c == 7; assert a == 4").unwrap();
+        let x = program.var("x").unwrap();
+        let a = program.var("a").unwrap();
+        let b = program.var("b").unwrap();
+        let builder = &mut program.builder;
+        builder.assert_lt(b, a, 8);
+        builder.fill_nodes(x, 2);
+        assert_eq!(builder.par_check(), Ok(()));
+        assert!(builder.par_check_constraints());
+
+        let mut witness = builder.witness();
+        witness.values[a.index()] = Some(1);
+        builder.load_witness(&witness);
+        let violations = builder.par_violations();
+        let kinds: Vec<&str> = violations
+            .iter()
+            .map(|violation| match violation {
+                error::ConstraintError::WrongOutput { .. } => "wrong-output",
+                error::ConstraintError::NotEqual { .. } => "not-equal",
+                error::ConstraintError::NotLess { .. } => "not-less",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            ["wrong-output", "wrong-output", "not-equal", "not-less"]
+        );
+        assert_eq!(builder.par_check(), builder.check());
+        assert_eq!(builder.par_check().unwrap_err(), violations[0]);
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_gpu_evaluate() {
+        let evaluator = match gpu::GpuEvaluator::new() {
+            Ok(evaluator) => evaluator,
+            Err(err) => {
+                eprintln!("skipping the GPU test: {}", err);
+                return;
+            }
+        };
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.init();
+        let product = builder.mul(x, y);
+        let root = builder.hint_fn("sqrt", product);
+        let three = builder.constant(3);
+        let sum = builder.add(root, three);
+        builder.mul(sum, x);
+        builder.mul_add(sum, x, root);
+        let batch: Vec<Vec<(NodeId, u32)>> = (0..100).map(|i| vec![(x, i), (y, i * 4)]).collect();
+        let witnesses = evaluator.evaluate(&builder, &batch).unwrap();
+        for (inputs, witness) in batch.iter().zip(&witnesses) {
+            builder.fill_inputs(inputs);
+            assert_eq!(witness, &builder.witness());
+        }
+
+        let batch = vec![vec![(x, 2), (y, 8)], vec![(x, 1 << 20), (y, 1 << 20)]];
+        match evaluator.evaluate(&builder, &batch) {
+            Err(gpu::GpuError::Eval { item: 1, error }) => {
+                assert!(matches!(error, eval::EvalError::Overflow { .. }))
+            }
+            other => panic!("expected an overflow, got {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_fuzz_circuits() {
+        use arbitrary::{Arbitrary, Unstructured};
+        let mut state = 0x2545f491u32;
+        for _ in 0..200 {
+            let bytes: Vec<u8> = (0..256)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    (state >> 24) as u8
+                })
+                .collect();
+            let circuit = fuzz::FuzzCircuit::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+            circuit.run();
+        }
+    }
+
+    #[cfg(feature = "proptest")]
+    proptest::proptest! {
+        #[test]
+        fn test_random_circuits(circuit in testing::circuits(testing::CircuitConfig::default())) {
+            let filled = circuit.filled();
+            proptest::prop_assert!(filled.is_satisfied());
+            testing::assert_round_trips(&filled)?;
+            let loaded = Builder::from_json(&circuit.builder.to_json()).unwrap();
+            testing::assert_equivalent(&circuit.builder, &loaded, &circuit.values())?;
+        }
+    }
+
+    #[test]
+    fn test_random_circuit() {
+        let config = random::RandomConfig {
+            fan_in: 3,
+            ..Default::default()
+        };
+        let mut builder = Builder::random(42, &config);
+        assert_eq!(builder.to_json(), Builder::random(42, &config).to_json());
+        assert_ne!(
+            builder.fingerprint(),
+            Builder::random(43, &config).fingerprint()
+        );
+        assert_eq!(builder.public_outputs().len(), config.width);
+
+        let inputs: Vec<_> = builder.inputs().into_iter().map(|x| (x, 1)).collect();
+        builder
+            .fill_inputs_with(&inputs, eval::EvalOptions::new())
+            .unwrap();
+        assert!(builder.is_satisfied());
+    }
+
+    #[test]
+    fn test_compare_backends() {
+        let builder = Builder::random(7, &random::RandomConfig::default());
+        let inputs: Vec<_> = builder.inputs().into_iter().map(|x| (x, 1)).collect();
+        let report = builder.compare_backends(&inputs);
+        assert!(report.is_consistent(), "{:?}", report.divergences);
+        let backends = report.compared.len() + report.skipped.len();
+        assert_eq!(backends, differential::Backend::all().len());
+
+        let mut program = parser::parse("y = x * 3 + 1; assert y == 16").unwrap();
+        let x = program.var("x").unwrap();
+        program.builder.fill_nodes(x, 5);
+        let witness = program.builder.witness();
+        let report = program.builder.compare_backends(&[(x, 12)]);
+        assert!(report.is_consistent());
+        // The GPU backend is skipped on machines without an adapter.
+        assert!(report.skipped.iter().all(|backend| backend.name() == "gpu"));
+        assert_eq!(program.builder.witness(), witness);
+    }
+
+    #[test]
+    fn test_mutation_testing() {
+        let mut program = parser::parse("y = x * x; r = hint(sqrt, y); assert r == x").unwrap();
+        program.builder.fill_nodes(program.var("x").unwrap(), 6);
+        let report = program.builder.mutation_test().unwrap();
+        assert_eq!(report.tested, 3);
+        assert!(report.is_fully_constrained());
+
+        let mut program = parser::parse("y = x + 7; z = hint(sqrt, y)").unwrap();
+        program.builder.fill_nodes(program.var("x").unwrap(), 9);
+        let report = program.builder.mutation_test().unwrap();
+        let z = program.var("z").unwrap().index();
+        assert_eq!(report.undetected.len(), 1);
+        assert_eq!(report.undetected[0].node, z);
+        assert_eq!(report.undetected[0].original, 4);
+    }
+
+    #[test]
+    fn test_mul_add() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let five = builder.constant(5);
+        let y = builder.mul_add(x, x, five);
+        builder.set_label(y, "y");
+        assert_eq!(builder.len(), 3);
+        builder.fill_nodes(x, 3);
+        assert_eq!(builder.output(y), Some(14));
+        assert!(builder.check_constraints());
+        assert!(builder.snapshot().contains("%2 = mul_add %0 %0 %1  # y\n"));
+
+        let mut loaded = Builder::from_json(&builder.to_json()).unwrap();
+        assert_eq!(loaded.fingerprint(), builder.fingerprint());
+        loaded.fill_nodes(loaded.inputs()[0], 4);
+        assert_eq!(loaded.outputs().last(), Some(Some(21)));
+        assert_eq!(export::to_r1cs(&builder).constraints.len(), 3);
+        assert!(export::to_smtlib(&builder, export::SmtGoal::Witness).contains("(bvadd (bvmul"));
+
+        let options = eval::EvalOptions::new();
+        let err = builder
+            .fill_inputs_with(&[(x, 1 << 16)], options)
+            .unwrap_err();
+        assert!(matches!(err, eval::EvalError::Overflow { node: 2, .. }));
+        let mut mul = Builder::new();
+        let x = mul.init();
+        let square = mul.mul(x, x);
+        let five = mul.constant(5);
+        mul.add(square, five);
+        assert_ne!(mul.fingerprint(), builder.fingerprint());
+    }
+
+    #[test]
+    fn test_square_double() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let square = builder.square(x);
+        let double = builder.double(square);
+        builder.mark_public(double);
+        builder.fill_nodes(x, 5);
+        assert_eq!(builder.output(double), Some(50));
+        assert_eq!(builder.expression(double), "(n0 * n0) + (n0 * n0)");
+        let r1cs = export::to_r1cs(&builder);
+        assert_eq!(r1cs.constraints[0].b, [(1, 1)]);
+        assert_eq!(r1cs.constraints[1].a, [(2, 2)]);
+        let dot = export::to_dot(&builder);
+        assert!(dot.contains("n1 [label=\"^2\"]") && dot.contains("n2 [label=\"2*\"]"));
+        assert_eq!(dot.matches("-> n2;").count(), 1);
+
+        let mut program = parser::parse("y = x * 2").unwrap();
+        let y = program.var("y").unwrap();
+        program.builder.mark_public(y);
+        let optimized = passes::PassManager::standard().run(&program.builder);
+        let mut expected = Builder::new();
+        let x = expected.init();
+        let y = expected.double(x);
+        expected.set_label(x, "x");
+        expected.set_label(y, "y");
+        expected.mark_public(y);
+        assert_eq!(optimized.builder.snapshot(), expected.snapshot());
+    }
+
+    #[test]
+    fn test_fill_report() {
+        use error::{Unfillable, UnfilledNode};
+        let mut builder = Builder::new();
+        builder.register_hint("half", |x| x / 2);
+        let x = builder.init();
+        let y = builder.init();
+        let half = builder.hint_fn("half", x);
+        let big = builder.constant(1 << 20);
+        let product = builder.mul(x, big);
+        let sum = builder.add(y, half);
+        builder.mul(x, x);
+        let mut loaded = Builder::from_json(&builder.to_json()).unwrap();
+        let x = loaded.inputs()[0];
+        let err = loaded.try_fill_nodes(x, 1 << 12).unwrap_err();
+        let eval::EvalError::Unfillable(report) = err else {
+            panic!("expected a fill report, got {:?}", err);
+        };
+        let reasons: Vec<(usize, Unfillable)> = report
+            .nodes
+            .iter()
+            .map(|UnfilledNode { node, reason }| (*node, reason.clone()))
+            .collect();
+        assert_eq!(reasons.len(), 4);
+        assert_eq!(reasons[0], (y.index(), Unfillable::MissingInput));
+        assert_eq!(
+            reasons[1],
+            (
+                half.index(),
+                Unfillable::UnknownHint {
+                    name: "half".into()
+                }
+            )
+        );
+        assert!(
+            matches!(reasons[2], (node, Unfillable::Overflow { op: "*", .. }) if node == product.index())
+        );
+        assert_eq!(
+            reasons[3],
+            (sum.index(), Unfillable::Unfilled { operand: y.index() })
+        );
+        assert_eq!(loaded.outputs().last(), Some(Some(1 << 24)));
+        assert!(report
+            .to_string()
+            .starts_with("4 node(s) could not be filled in:\n  node 1: "));
+    }
+
+    #[test]
+    fn test_typed_wires() {
+        use wires::{BoolVar, FieldVar, U32Var};
+        let mut builder = Builder::new();
+        let a = builder.bool_input();
+        let b = builder.bool_input();
+        let both = builder.and(a, b);
+        let word = builder.u32_input();
+        let byte = builder.init();
+        builder.assert_range(byte, 8);
+        let chosen = builder.select(both, word.id(), byte);
+        let field: FieldVar = U32Var::from(both).into();
+        assert_eq!(NodeId::from(field), both.id());
+        assert_eq!(builder.checks().len(), 2);
+
+        let fill = |builder: &mut Builder, a_value, b_value, byte_value| {
+            let inputs = [
+                (a.id(), a_value),
+                (b.id(), b_value),
+                (word.into(), 70000),
+                (byte, byte_value),
+            ];
+            builder.fill_inputs(&inputs);
+        };
+        fill(&mut builder, 1, 1, 200);
+        assert!(builder.check_constraints());
+        assert_eq!(builder.output(chosen), Some(70000));
+        fill(&mut builder, 1, 0, 200);
+        assert_eq!(builder.output(chosen), Some(200));
+        fill(&mut builder, 1, 2, 7);
+        assert!(matches!(
+            builder.check(),
+            Err(error::ConstraintError::NotEqual { constraint: 1, .. })
+        ));
+        fill(&mut builder, 0, 0, 256);
+        assert!(matches!(
+            builder.check(),
+            Err(error::ConstraintError::OutOfRange { check: 1, value, bits: 8, .. }) if value == 256
+        ));
+
+        let range = format!("assert %{} < 2^8\n", byte.index());
+        assert!(builder.snapshot().contains(&range));
+        let loaded = Builder::from_json(&builder.to_json()).unwrap();
+        assert_eq!(loaded.checks(), builder.checks());
+        let r1cs = export::to_r1cs(&builder);
+        assert_eq!(r1cs.num_variables, builder.len() + 1 + 32 + 8);
+        let one: BoolVar = builder.bool_constant(true);
+        assert_eq!(builder.output(one.id()), Some(1));
+    }
+
+    #[test]
+    fn test_fluent_expressions() {
+        let mut fluent = Builder::new();
+        let x = fluent.init();
+        let y = fluent.init();
+        let lhs = fluent
+            .var(x)
+            .mul(x)
+            .add_const(5)
+            .add(x)
+            .label("lhs")
+            .assert_eq(y);
+        let root = fluent.var(lhs).hint("sqrt").double().id();
+
+        let mut manual = Builder::new();
+        let (a, b) = (manual.init(), manual.init());
+        let square = manual.mul(a, a);
+        let five = manual.constant(5);
+        let sum = manual.add(square, five);
+        let sum = manual.add(sum, a);
+        manual.set_label(sum, "lhs");
+        manual.enforce_equal(sum, b);
+        let sqrt = manual.hint_fn("sqrt", sum);
+        manual.double(sqrt);
+        assert_eq!(fluent.snapshot(), manual.snapshot());
+
+        fluent.fill_inputs(&[(x, 3), (y, 17)]);
+        assert!(fluent.check_constraints());
+        assert_eq!(fluent.output(root), Some(8));
+        let cond = fluent.bool_input();
+        let scaled: NodeId = fluent.var(cond).mul_const(3).mul_add(cond, y).into();
+        fluent.fill_inputs(&[(x, 3), (y, 17), (cond.id(), 1)]);
+        assert_eq!(fluent.output(scaled), Some(20));
+    }
+
+    #[test]
+    fn test_format_versions() {
+        use my_graph_lib::error::SerdeError;
+        use my_graph_lib::json::FORMAT_VERSION;
+
+        let program = parser::parse("y = x * x + 3; assert y == 52").unwrap();
+        let json = program.builder.to_json();
+        let mut document: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(document["version"], FORMAT_VERSION);
+
+        // Documents written before versions were recorded are read as version 1 and upgraded.
+        document.as_object_mut().unwrap().remove("version");
+        let legacy = Builder::from_json(&document.to_string()).unwrap();
+        assert_eq!(legacy.snapshot(), program.builder.snapshot());
+        assert_eq!(legacy.to_json(), json);
+
+        for version in [0, u64::from(FORMAT_VERSION) + 1] {
+            document["version"] = version.into();
+            let err = Builder::from_json(&document.to_string()).unwrap_err();
+            assert!(
+                matches!(err, SerdeError::UnsupportedVersion { found, .. } if found == version)
+            );
+        }
+        assert_eq!(
+            Builder::from_json(r#"{"version": 5, "nodes": []}"#)
+                .unwrap_err()
+                .to_string(),
+            "graph JSON has format version 5, this version of the library reads versions 1 to 4"
+        );
+        assert!(Builder::from_json(r#"{"version": "2", "nodes": []}"#).is_err());
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[test]
+    fn test_protobuf() {
+        use my_graph_lib::error::SerdeError;
+        use my_graph_lib::protobuf;
+
+        let mut builder = Builder::new();
+        let x = builder.private_input();
+        let y = builder.init();
+        let five = builder.constant(5);
+        let z = builder.mul_add(x, y, five);
+        let root = builder.hint_fn("sqrt", z);
+        let fixed = builder.hint(3, root);
+        builder.set_label(z, "z");
+        builder.mark_public(root);
+        builder.enforce_equal_with(root, fixed, "root is 3");
+        builder.enforce_equal_warning(x, y, "x is y");
+        builder.assert_lt(x, y, 8);
+        builder.assert_in_set(fixed, &[3, 4]);
+        builder.assert_permutation(&[x, y], &[y, x]);
+        builder.assert_range(z, 16);
+        builder.fill_inputs(&[(x, 1), (y, 4)]);
+
+        let loaded = Builder::from_protobuf(&builder.to_protobuf()).unwrap();
+        assert_eq!(loaded.snapshot(), builder.snapshot());
+        assert_eq!(loaded.to_json(), builder.to_json());
+        assert_eq!(
+            loaded.public_outputs(),
+            vec![loaded.node_id(root.index()).unwrap()]
+        );
+        assert!(loaded.is_satisfied());
+        assert_eq!(loaded.warnings().len(), 1);
+
+        let witness = builder.witness();
+        assert_eq!(
+            Witness::from_protobuf(&witness.to_protobuf()).unwrap(),
+            witness
+        );
+        let mut partial = Builder::new();
+        partial.init();
+        let witness = partial.witness();
+        assert_eq!(
+            Witness::from_protobuf(&witness.to_protobuf()).unwrap(),
+            witness
+        );
+
+        // Nodes may only refer to nodes before them.
+        let mut circuit = builder.to_protobuf_message();
+        circuit.nodes[3].operands[2] = 3;
+        assert!(matches!(
+            Builder::from_protobuf_message(&circuit),
+            Err(SerdeError::InvalidProtobuf(_))
+        ));
+        assert!(matches!(
+            Builder::from_protobuf(b"\xff"),
+            Err(SerdeError::Protobuf(_))
+        ));
+        assert!(protobuf::SCHEMA.contains("package circuit.v1;"));
+    }
+
+    #[test]
+    fn test_subcircuits() {
+        use my_graph_lib::subcircuit::{Composer, Subcircuit};
+
+        // A gadget computing a * b + 1, and checking it with a hint of its square root.
+        let step = Subcircuit::new("step", 2, |b, args| {
+            let one = b.constant(1);
+            let out = b.mul_add(args[0], args[1], one);
+            b.set_label(out, "out");
+            let root = b.hint_fn("sqrt", out);
+            let square = b.square(root);
+            b.assert_range(square, 16);
+            vec![out, root]
+        });
+        // Two steps chained, calling the gadget from another one.
+        let twice = Subcircuit::new("twice", 1, |b, args| {
+            let first = b.call(&step, &[args[0], args[0]]);
+            b.call(&step, &[first[1], first[1]])
+        });
+        assert_eq!(step.instance_len(), 4);
+        assert_eq!(twice.instance_len(), 8);
+
+        let mut circuit = Composer::new();
+        let x = circuit.init();
+        let mut state = x;
+        for _ in 0..100 {
+            state = circuit.call(&twice, &[state])[1];
+        }
+        let y = circuit.init();
+        circuit.enforce_equal(state, y);
+        // Each call only adds its outputs to the composer.
+        assert_eq!(circuit.len(), 202);
+        assert_eq!(circuit.expanded_len(), 802);
+
+        let mut builder = circuit.finalize();
+        assert_eq!(builder.len(), 802);
+        assert_eq!(builder.inputs().len(), 2);
+        let out = builder.find_label("twice/step/out").unwrap();
+        assert_eq!(out.index(), 2);
+        let (x, y) = (builder.inputs()[0], builder.inputs()[1]);
+        // 3 * 3 + 1 = 10, whose square root is 3 again, and so on.
+        builder.fill_inputs(&[(x, 3), (y, 3)]);
+        assert_eq!(builder.output(out), Some(10));
+        assert!(builder.is_satisfied());
+        builder.fill_inputs(&[(x, 3), (y, 4)]);
+        assert!(!builder.is_satisfied());
+    }
+
+    #[test]
+    fn test_templates() {
+        use my_graph_lib::error::BuildError;
+        use my_graph_lib::limits::Limits;
+        use my_graph_lib::subcircuit::Composer;
+        use my_graph_lib::template::Template;
+
+        /// The sum of `terms` inputs, each multiplied by `scale`, built from a smaller sum.
+        static SCALED_SUM: Template<(usize, u32)> = Template::new(
+            "scaled_sum",
+            |&(terms, _)| terms,
+            |&(terms, scale), b, args| {
+                let factor = b.constant(scale);
+                let term = b.mul(args[terms - 1], factor);
+                b.set_label(term, "term");
+                if terms == 1 {
+                    return vec![term];
+                }
+                let rest = SCALED_SUM.call(b, &(terms - 1, scale), &args[..terms - 1]);
+                vec![b.add(rest[0], term)]
+            },
+        );
+
+        let mut builder = Builder::new();
+        let xs: Vec<NodeId> = (0..3).map(|_| builder.init()).collect();
+        let sum = SCALED_SUM.instantiate(&mut builder, &(3, 2), &xs)[0];
+        let again = SCALED_SUM.instantiate(&mut builder, &(3, 2), &xs)[0];
+        // One specialization for each number of terms.
+        assert_eq!(SCALED_SUM.specializations(), 3);
+        assert_eq!(SCALED_SUM.specialize(&(3, 2)).name(), "scaled_sum((3, 2))");
+        let innermost = "scaled_sum((3, 2))/scaled_sum((2, 2))/scaled_sum((1, 2))/term";
+        let term = builder.find_label(innermost).unwrap();
+        builder.fill_inputs(&[(xs[0], 1), (xs[1], 2), (xs[2], 3)]);
+        assert_eq!(builder.output(term), Some(2));
+        assert_eq!(builder.output(sum), Some(12));
+        assert_eq!(builder.output(again), Some(12));
+
+        // A copy exceeding a limit is not added.
+        let len = builder.len();
+        builder.set_limits(Limits::new().max_nodes(len + 3));
+        let err = SCALED_SUM.try_instantiate(&mut builder, &(3, 2), &xs);
+        assert!(matches!(err, Err(BuildError::LimitExceeded { .. })));
+        assert_eq!(builder.len(), len);
+        assert!(builder.contains(sum));
+        let one = SCALED_SUM
+            .try_instantiate(&mut builder, &(1, 2), &xs[..1])
+            .unwrap();
+        assert_eq!(builder.len(), len + 2);
+        builder.fill_inputs(&[(xs[0], 1), (xs[1], 2), (xs[2], 3)]);
+        assert_eq!(builder.output(one[0]), Some(2));
+
+        // Calls by reference expand to the same graph.
+        let mut composer = Composer::new();
+        let xs: Vec<NodeId> = (0..3).map(|_| composer.init()).collect();
+        SCALED_SUM.call(&mut composer, &(3, 2), &xs);
+        SCALED_SUM.call(&mut composer, &(3, 2), &xs);
+        assert_eq!(composer.len(), 5);
+        let mut expected = Builder::new();
+        let xs: Vec<NodeId> = (0..3).map(|_| expected.init()).collect();
+        SCALED_SUM.instantiate(&mut expected, &(3, 2), &xs);
+        SCALED_SUM.instantiate(&mut expected, &(3, 2), &xs);
+        assert_eq!(composer.finalize().snapshot(), expected.snapshot());
+    }
+
+    #[test]
+    fn test_savepoint_rollback() {
+        use my_graph_lib::error::BuildError;
+
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let one = builder.constant(1);
+        let before = builder.savepoint();
+
+        // Speculatively build x + 1 == y with a labelled input and a range check.
+        let y = builder.init();
+        let sum = builder.add(x, one);
+        builder.set_label(sum, "sum");
+        builder.enforce_equal(sum, y);
+        builder.assert_range(y, 8);
+        builder.rollback(before);
+
+        assert_eq!(builder.len(), 2);
+        assert_eq!(builder.inputs(), vec![x]);
+        assert!(!builder.contains(y) && !builder.contains(sum));
+        assert_eq!(
+            builder.resolve(sum),
+            Err(BuildError::StaleNode { node: sum.index() })
+        );
+
+        // New nodes reuse the indices, but not the ids, of the removed ones.
+        let doubled = builder.double(x);
+        assert_eq!(doubled.index(), y.index());
+        assert!(!builder.contains(y));
+        builder.fill_inputs(&[(x, 4)]);
+        assert!(builder.check_constraints());
+        assert_eq!(builder.output(doubled), Some(8));
+
+        // The savepoint can be rolled back to again, but a later one is gone with its nodes.
+        let after = builder.savepoint();
+        builder.rollback(before);
+        assert_eq!(builder.len(), 2);
+        assert_eq!(builder.try_rollback(after), Err(BuildError::StaleSavepoint));
+        assert_eq!(
+            Builder::new().try_rollback(before),
+            Err(BuildError::StaleSavepoint)
+        );
+    }
+
+    #[cfg(feature = "flatbuffers")]
+    #[test]
+    fn test_flatbuffers() {
+        use my_graph_lib::error::{BuildError, EvalError, SerdeError};
+        use my_graph_lib::flat::{FlatCircuit, FlatFile};
+
+        let mut builder = Builder::new();
+        let x = builder.private_input();
+        let y = builder.init();
+        let five = builder.constant(5);
+        let z = builder.mul_add(x, y, five);
+        let root = builder.hint_fn("sqrt", z);
+        let fixed = builder.hint(3, root);
+        builder.set_label(z, "z");
+        builder.mark_public(root);
+        builder.enforce_equal_with(root, fixed, "root is 3");
+        builder.enforce_equal_warning(x, y, "x is y");
+        builder.assert_lt(x, y, 8);
+        builder.assert_in_set(fixed, &[3, 4]);
+        builder.assert_permutation(&[x, y], &[y, x]);
+        builder.assert_range(z, 16);
+
+        let path = std::env::temp_dir().join(format!("flat-test-{}.circ", std::process::id()));
+        builder.write_flatbuffer(&path).unwrap();
+        let file = FlatFile::open(&path).unwrap();
+        let circuit = file.circuit().unwrap();
+        assert_eq!(circuit.len(), builder.len());
+        assert_eq!(circuit.inputs(), vec![x.index(), y.index()]);
+        assert_eq!(circuit.public_outputs(), vec![root.index()]);
+        assert_eq!(circuit.label(z.index()), Some("z"));
+        assert_eq!(circuit.hint_function(root.index()), Some("sqrt"));
+        assert!(circuit.is_secret(z.index()));
+
+        // Evaluating over the mapped buffer agrees with the builder.
+        let witness = circuit.evaluate(&[(x.index(), 1), (y.index(), 4)]).unwrap();
+        builder.fill_inputs(&[(x, 1), (y, 4)]);
+        assert_eq!(witness, builder.witness());
+        assert!(circuit.is_satisfied(&witness));
+        let witness = circuit.evaluate(&[(x.index(), 5), (y.index(), 4)]).unwrap();
+        assert!(!circuit.is_satisfied(&witness));
+        assert!(matches!(
+            circuit.evaluate(&[(x.index(), 1)]),
+            Err(EvalError::MissingValue { .. })
+        ));
+        assert!(matches!(
+            circuit.evaluate(&[(z.index(), 1)]),
+            Err(EvalError::Build(BuildError::NotAnInput { .. }))
+        ));
+        std::fs::remove_file(&path).unwrap();
+
+        let loaded = Builder::from_flatbuffer(&builder.to_flatbuffer()).unwrap();
+        assert_eq!(loaded.snapshot(), builder.snapshot());
+        assert!(loaded.is_satisfied());
+        assert_eq!(loaded.warnings().len(), 1);
+        assert_eq!(loaded.checks(), builder.checks());
+
+        let mut bytes = builder.to_flatbuffer();
+        bytes[4] = b'X';
+        assert!(matches!(
+            FlatCircuit::from_bytes(&bytes),
+            Err(SerdeError::InvalidFlatbuffer(_))
+        ));
+        let bytes = builder.to_flatbuffer();
+        assert!(matches!(
+            FlatCircuit::from_bytes(&bytes[..bytes.len() / 2]),
+            Err(SerdeError::Flatbuffer(_))
+        ));
+    }
+
+    #[test]
+    fn test_journal() {
+        use my_graph_lib::error::{BuildError, EvalError};
+        use my_graph_lib::journal::{Journal, JournalWriter, Record};
+
+        let path = std::env::temp_dir().join(format!("journal-test-{}.log", std::process::id()));
+        let mut writer = JournalWriter::create(&path).unwrap();
+        let x = writer.init().unwrap();
+        let y = writer.init().unwrap();
+        let five = writer.constant(5).unwrap();
+        let z = writer.mul_add(x, y, five).unwrap();
+        let root = writer.hint_fn("sqrt", z).unwrap();
+        let fixed = writer.hint(3, root).unwrap();
+        let sum = writer.add(root, fixed).unwrap();
+        writer.enforce_equal(root, fixed).unwrap();
+        assert!(writer.mul(sum, 100).is_err());
+        assert_eq!(writer.len(), 7);
+        writer.finalize().unwrap();
+
+        let mut journal = Journal::open(&path).unwrap();
+        assert_eq!((journal.len(), journal.constraints()), (7, 1));
+        assert_eq!(journal.node(z).unwrap(), Record::MulAdd(x, y, five));
+        assert_eq!(
+            journal.node(root).unwrap(),
+            Record::HintFn {
+                linked: z,
+                function: "sqrt".to_string()
+            }
+        );
+
+        // The same graph built in memory computes the same witness.
+        let mut builder = journal.to_builder().unwrap();
+        let witness = journal.evaluate(&[(x, 1), (y, 4)]).unwrap();
+        assert!(journal.is_satisfied(&witness).unwrap());
+        builder.fill_inputs(&[
+            (builder.node_id(x).unwrap(), 1),
+            (builder.node_id(y).unwrap(), 4),
+        ]);
+        assert_eq!(witness, builder.witness());
+        assert!(builder.is_satisfied());
+        let witness = journal.evaluate(&[(x, 5), (y, 4)]).unwrap();
+        assert!(!journal.is_satisfied(&witness).unwrap());
+        assert!(matches!(
+            journal.evaluate(&[(z, 1)]),
+            Err(EvalError::Build(BuildError::NotAnInput { .. }))
+        ));
+        assert!(matches!(
+            journal.evaluate(&[(x, 1)]),
+            Err(EvalError::MissingValue {
+                node: 3,
+                operand: 1
+            })
+        ));
+
+        // A journal is only readable once finalized.
+        let writer = JournalWriter::create(&path).unwrap();
+        drop(writer);
+        assert!(Journal::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_out_of_core_evaluation() {
+        use my_graph_lib::journal::JournalWriter;
+        use my_graph_lib::stream::WitnessFile;
+
+        let dir = std::env::temp_dir();
+        let journal_path = dir.join(format!("out-of-core-{}.log", std::process::id()));
+        let witness_path = dir.join(format!("out-of-core-{}.wit", std::process::id()));
+        let mut writer = JournalWriter::create(&journal_path).unwrap();
+        let x = writer.init().unwrap();
+        let one = writer.constant(1).unwrap();
+        // Every node reads the one before it and one far behind it.
+        let mut nodes = vec![x, one];
+        for i in 2..1000 {
+            let far = nodes[i / 3];
+            let node = if i % 2 == 0 {
+                writer.add(nodes[i - 1], far).unwrap()
+            } else {
+                let sum = writer.add(nodes[i - 1], one).unwrap();
+                writer.mul_add(far, one, sum).unwrap()
+            };
+            nodes.push(node);
+        }
+        let mut journal = writer.finalize().unwrap();
+        let expected = journal.evaluate(&[(x, 0)]).unwrap();
+
+        for capacity in [0, 4, 2000] {
+            let stats = journal
+                .evaluate_to(&[(x, 0)], &witness_path, capacity)
+                .unwrap();
+            assert_eq!(stats.nodes_written, journal.len());
+            assert_eq!(stats.cache_misses == 0, capacity == 2000);
+            let mut witness = WitnessFile::open(&witness_path).unwrap();
+            assert_eq!(witness.len(), journal.len());
+            for (id, &value) in expected.values.iter().enumerate() {
+                assert_eq!(witness.get(id).unwrap(), value);
+            }
+        }
+        std::fs::remove_file(&journal_path).unwrap();
+        std::fs::remove_file(&witness_path).unwrap();
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_compression() {
+        use my_graph_lib::compress;
+        use my_graph_lib::error::SerdeError;
+
+        let program = parser::parse("y = x + 7; assert y == hint(sqrt, y)^2").unwrap();
+        let mut builder = program.builder;
+        for i in 0..200 {
+            let x = builder.constant(i);
+            let y = builder.mul(x, x);
+            builder.set_label(y, &format!("square {}", i));
+        }
+        let json = builder.to_json();
+        let fast = builder.to_json_compressed(1);
+        let small = builder.to_json_compressed(19);
+        assert!(compress::is_compressed(&fast));
+        assert!(small.len() <= fast.len());
+        assert!(fast.len() * 5 < json.len());
+        for bytes in [&fast, &small, json.as_bytes()] {
+            let loaded = Builder::from_json_bytes(bytes).unwrap();
+            assert_eq!(loaded.to_json(), json);
+        }
+
+        #[cfg(feature = "flatbuffers")]
+        {
+            let bytes = compress::compress(&builder.to_flatbuffer(), compress::DEFAULT_LEVEL);
+            let loaded = Builder::from_flatbuffer(&bytes).unwrap();
+            assert_eq!(loaded.snapshot(), builder.snapshot());
+        }
+        #[cfg(feature = "protobuf")]
+        {
+            let bytes = compress::compress(&builder.to_protobuf(), compress::DEFAULT_LEVEL);
+            let loaded = Builder::from_protobuf(&bytes).unwrap();
+            assert_eq!(loaded.snapshot(), builder.snapshot());
+        }
+
+        // A zstd frame that was cut short.
+        assert!(matches!(
+            Builder::from_json_bytes(&fast[..fast.len() / 2]),
+            Err(SerdeError::Zstd(_))
+        ));
+    }
+
+    #[test]
+    fn test_fixed_point() {
+        use my_graph_lib::fixed::Fixed;
+
+        let mut builder = Builder::new();
+        let x = builder.fixed_input();
+        let y = builder.fixed_input();
+        let product = builder.fixed_mul(x, y);
+        let scaled = builder.fixed_scale(x, -0.5);
+        let sum = builder.fixed_add(product, scaled);
+        let normalized = builder.fixed_normalize(sum);
+        let relu = builder.fixed_relu(sum);
+        let negative = builder.fixed_is_negative(sum);
+        for (a, b) in [
+            (1.5, 2.0),
+            (-3.25, 4.0),
+            (2.0, -0.25),
+            (0.0, 0.0),
+            (-10.0, -10.0),
+        ] {
+            let mut inputs = Fixed::assign(x, a).to_vec();
+            inputs.extend(Fixed::assign(y, b));
+            builder.fill_inputs(&inputs);
+            let expected = a * b - a / 2.0;
+            assert_eq!(builder.fixed_value(sum), Some(expected));
+            assert_eq!(builder.fixed_value(normalized), Some(expected));
+            assert_eq!(builder.fixed_value(relu), Some(expected.max(0.0)));
+            assert_eq!(
+                builder.output(negative.id()),
+                Some(u32::from(expected < 0.0))
+            );
+            let (pos, neg) = normalized.parts();
+            assert!(builder.output(pos) == Some(0) || builder.output(neg) == Some(0));
+            assert!(builder.is_satisfied());
+        }
+
+        // Out of range: 300 doesn't fit in MAG_BITS bits once normalized.
+        let mut inputs = Fixed::assign(x, 200.0).to_vec();
+        inputs.extend(Fixed::assign(y, 2.0));
+        builder.fill_inputs(&inputs);
+        assert!(!builder.is_satisfied());
+    }
+
+    #[cfg(feature = "onnx")]
+    #[test]
+    fn test_onnx_import() {
+        use my_graph_lib::onnx::{
+            Dimension, GraphProto, ModelProto, NodeProto, OnnxError, TensorProto, TensorShapeProto,
+            TensorTypeProto, TypeProto, ValueInfoProto, FLOAT,
+        };
+        use prost::Message;
+
+        fn tensor(name: &str, dims: &[i64], data: &[f32], raw: bool) -> TensorProto {
+            TensorProto {
+                dims: dims.to_vec(),
+                data_type: FLOAT,
+                float_data: if raw { Vec::new() } else { data.to_vec() },
+                name: name.to_string(),
+                raw_data: if raw {
+                    data.iter().flat_map(|x| x.to_le_bytes()).collect()
+                } else {
+                    Vec::new()
+                },
+            }
+        }
+        fn value(name: &str, dims: &[i64]) -> ValueInfoProto {
+            ValueInfoProto {
+                name: name.to_string(),
+                r#type: Some(TypeProto {
+                    tensor_type: Some(TensorTypeProto {
+                        elem_type: FLOAT,
+                        shape: Some(TensorShapeProto {
+                            dim: dims
+                                .iter()
+                                .map(|&size| Dimension {
+                                    dim_value: Some(size),
+                                    dim_param: None,
+                                })
+                                .collect(),
+                        }),
+                    }),
+                }),
+            }
+        }
+        fn node(op: &str, inputs: &[&str], output: &str) -> NodeProto {
+            NodeProto {
+                input: inputs.iter().map(|name| name.to_string()).collect(),
+                output: vec![output.to_string()],
+                name: output.to_string(),
+                op_type: op.to_string(),
+                domain: String::new(),
+            }
+        }
+
+        // y = relu(x @ w1 + b1) @ w2 * s
+        let w1 = [
+            0.5, -1.0, 0.25, 2.0, 1.5, 0.75, -0.5, 1.0, -2.0, 0.125, 1.0, -0.25,
+        ];
+        let b1 = [0.5, -1.0, 0.0, 2.0];
+        let w2 = [1.0, -0.5, 0.25, 2.0, -1.5, 0.5, 0.75, -1.0];
+        let s = 1.5;
+        let mut graph = GraphProto {
+            node: vec![
+                node("MatMul", &["x", "w1"], "h"),
+                node("Add", &["h", "b1"], "h_bias"),
+                node("Relu", &["h_bias"], "a"),
+                node("MatMul", &["a", "w2"], "z"),
+                node("Mul", &["z", "s"], "y"),
+            ],
+            name: "mlp".to_string(),
+            initializer: vec![
+                tensor("w1", &[3, 4], &w1, false),
+                tensor("b1", &[4], &b1, true),
+                tensor("w2", &[4, 2], &w2, true),
+                tensor("s", &[], &[s], false),
+            ],
+            input: vec![value("x", &[1, 3]), value("w1", &[3, 4])],
+            output: vec![value("y", &[1, 2])],
+        };
+        let model = ModelProto {
+            ir_version: 8,
+            producer_name: "test".to_string(),
+            graph: Some(graph.clone()),
+        };
+
+        let mut builder = Builder::new();
+        let imported = builder.import_onnx(&model.encode_to_vec()).unwrap();
+        assert_eq!(imported.inputs.len(), 1);
+        assert_eq!(imported.inputs[0].shape, vec![1, 3]);
+        assert_eq!(imported.outputs[0].shape, vec![1, 2]);
+        assert_eq!(builder.public_outputs().len(), 4);
+        for x in [[1.0, 2.0, -1.0], [-0.5, 0.25, 3.0], [0.0, 0.0, 0.0]] {
+            builder.fill_inputs(&imported.assign(&[&x]));
+            let hidden: Vec<f64> = (0..4)
+                .map(|j| {
+                    let sum: f64 = (0..3).map(|k| x[k] * w1[k * 4 + j] as f64).sum();
+                    (sum + b1[j] as f64).max(0.0)
+                })
+                .collect();
+            let read = imported.outputs[0].read(&builder).unwrap();
+            for (j, &y) in read.iter().enumerate() {
+                let expected: f64 = (0..4).map(|k| hidden[k] * w2[k * 2 + j] as f64).sum();
+                assert!(
+                    (y - expected * s as f64).abs() < 0.05,
+                    "{} != {}",
+                    y,
+                    expected
+                );
+            }
+            assert!(builder.is_satisfied());
+        }
+
+        graph.node.push(node("Softmax", &["y"], "p"));
+        assert!(matches!(
+            Builder::new().import_onnx_graph(&graph),
+            Err(OnnxError::Unsupported(_))
+        ));
+        graph.node[4].input[1] = "missing".to_string();
+        assert!(matches!(
+            Builder::new().import_onnx_graph(&graph),
+            Err(OnnxError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_nn_layers() {
+        use my_graph_lib::fixed::Fixed;
+
+        fn dense(x: &[f64], weights: &[f64], bias: &[f64]) -> Vec<f64> {
+            (0..bias.len())
+                .map(|j| {
+                    let products = x
+                        .iter()
+                        .enumerate()
+                        .map(|(k, value)| value * weights[j * x.len() + k]);
+                    products.sum::<f64>() + bias[j]
+                })
+                .collect()
+        }
+        fn avg_pool(x: &[f64], width: usize, window: usize) -> Vec<f64> {
+            let mut out = Vec::new();
+            for row in (0..x.len() / width).step_by(window) {
+                for column in (0..width).step_by(window) {
+                    let values = (0..window * window)
+                        .map(|i| x[(row + i / window) * width + column + i % window]);
+                    out.push(values.sum::<f64>() / (window * window) as f64);
+                }
+            }
+            out
+        }
+
+        // A 4x4 image, pooled to 2x2, then two dense layers with a ReLU in between.
+        let w1 = [
+            0.5, -1.25, 2.0, 0.75, -0.5, 1.0, 0.3, -2.0, 1.5, 0.1, -0.7, 0.25,
+        ];
+        let b1 = [0.1, -0.5, 1.0];
+        let w2 = [1.0, -0.4, 0.9, -1.5, 2.0, 0.6];
+        let b2 = [-0.25, 0.75];
+        let mut builder = Builder::new();
+        let image: Vec<Fixed> = (0..16).map(|_| builder.fixed_input()).collect();
+        let pooled = builder.avg_pool(&image, 4, 2);
+        let hidden = builder.dense(&pooled, &w1, &b1);
+        let activated = builder.relu(&hidden);
+        let output = builder.dense(&activated, &w2, &b2);
+        let coarse = builder.avg_pool(&image[..9], 3, 3);
+
+        for seed in 0..5u32 {
+            let pixels: Vec<f64> = (0..16u32)
+                .map(|i| f64::from((i * 37 + seed * 101) % 29) / 4.0 - 3.5)
+                .collect();
+            let inputs: Vec<_> = image
+                .iter()
+                .zip(&pixels)
+                .flat_map(|(&x, &value)| Fixed::assign(x, value))
+                .collect();
+            builder.fill_inputs(&inputs);
+
+            let expected_pooled = avg_pool(&pixels, 4, 2);
+            let expected_hidden: Vec<f64> = dense(&expected_pooled, &w1, &b1);
+            let expected_activated: Vec<f64> = expected_hidden.iter().map(|x| x.max(0.0)).collect();
+            let expected = dense(&expected_activated, &w2, &b2);
+            let close = |actual: &[Fixed], expected: &[f64], tolerance: f64| {
+                for (&x, &expected) in actual.iter().zip(expected) {
+                    let x = builder.fixed_value(x).unwrap();
+                    assert!((x - expected).abs() <= tolerance, "{} != {}", x, expected);
+                }
+            };
+            // Power-of-two windows are exact.
+            close(&pooled, &expected_pooled, 0.0);
+            close(&activated, &expected_activated, 0.02);
+            close(&output, &expected, 0.05);
+            close(&coarse, &avg_pool(&pixels[..9], 3, 3), 0.01);
+            assert!(builder.is_satisfied());
+        }
+    }
+
+    #[test]
+    fn test_sp1_guest() {
+        let program = parser::parse("y = x + 7; assert y == hint(sqrt, y)^2").unwrap();
+        let mut builder = program.builder;
+        let x = program.variables["x"];
+        let y = program.variables["y"];
+        let z = builder.mul_add(x, y, y);
+        let bound = builder.constant(1000);
+        builder.assert_lt(x, bound, 10);
+        builder.assert_range(z, 16);
+        builder.assert_in_set(x, &[2, 9]);
+        builder.assert_permutation(&[x, y], &[y, x]);
+        builder.enforce_equal_with(z, y, "z is \"{y}\"");
+        builder.enforce_equal_warning(x, y, "x differs from y");
+        builder.mark_public(y);
+        builder.mark_public(z);
+
+        let source = sp1::guest_source(&builder);
+        for line in [
+            "sp1_zkvm::entrypoint!(main);",
+            "assert_eq!(advice.len(), 2,",
+            "let n0: u32 = advice[0]; // x",
+            "let n3: u32 = advice[1]; // sqrt(n2)",
+            "let n5: u32 = mul_add(5, n0, n2, n2);",
+            "assert_eq!(n2, n4, \"{}\", \"constraint 0\");",
+            "assert_eq!(n5, n2, \"{}\", \"z is \\\"{y}\\\"\");",
+            "assert!(n0 < n6 && n6 >> 10 == 0, \"check 0\");",
+            "assert!(n5 >> 16 == 0, \"check 1\");",
+            "assert!([2, 9].contains(&n0), \"check 2\");",
+            "assert!(sorted([n0, n2]) == sorted([n2, n0]), \"check 3\");",
+            "sp1_zkvm::io::commit(&n2);\n    sp1_zkvm::io::commit(&n5);",
+        ] {
+            assert!(source.contains(line), "missing `{}` in\n{}", line, source);
+        }
+        // Warnings are left out.
+        assert!(!source.contains("x differs from y"));
+
+        assert_eq!(sp1::stdin_values(&builder), None);
+        builder.fill_nodes(x, 9);
+        assert_eq!(sp1::stdin_values(&builder), Some(vec![9, 4]));
+
+        let dir = std::env::temp_dir().join(format!("sp1-test-{}", std::process::id()));
+        sp1::write_guest(&builder, &dir, "circuit-guest").unwrap();
+        let manifest = std::fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("name = \"circuit-guest\""));
+        assert!(manifest.contains("sp1-zkvm = "));
+        assert_eq!(
+            std::fs::read_to_string(dir.join("src/main.rs")).unwrap(),
+            source
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_plonkish_extraction() {
+        use my_graph_lib::fixed::Fixed;
+
+        let mut builder = Builder::new();
+        let x: Vec<Fixed> = (0..3).map(|_| builder.fixed_input()).collect();
+        let hidden = builder.dense(&x, &[1.0, -0.5, 2.0, 0.25, 1.5, -1.0], &[0.5, -0.25]);
+        let y = builder.relu(&hidden);
+        for y in &y {
+            let (pos, _) = y.parts();
+            builder.mark_public(pos);
+        }
+        let mut inputs = Vec::new();
+        for (&x, value) in x.iter().zip([1.5, -2.0, 0.75]) {
+            inputs.extend(Fixed::assign(x, value));
+        }
+        builder.fill_inputs(&inputs);
+        assert!(builder.is_satisfied());
+
+        for (degree, width) in [(2, 3), (3, 4), (4, 6)] {
+            let table = plonkish::extract(&builder, degree, width);
+            // Fewer rows than nodes, with one row per node at most.
+            assert!(table.rows.len() < builder.len());
+            assert!(table.gates.iter().all(|gate| gate.degree() <= degree));
+            let assignment = table.assignment(&builder).unwrap();
+            for (row, cells) in table.rows.iter().zip(&assignment) {
+                assert!(row.wires.len() <= width);
+                if let Some(gate) = row.gate {
+                    let expected = table.gates[gate].evaluate(&cells[..width], &row.fixed);
+                    assert_eq!(expected, Some(u64::from(cells[width])), "{:?}", row);
+                }
+            }
+            for (a, b) in &table.copies {
+                assert_eq!(assignment[a.row][a.column], assignment[b.row][b.column]);
+            }
+            for output in builder.public_outputs() {
+                assert!(table.row(output.index()).is_some());
+            }
+            assert!(!table.checks.is_empty());
+            for node in table.checks.iter().flat_map(|check| check.nodes()) {
+                assert!(table.row(node).is_some());
+            }
+        }
+
+        // Wider gates absorb more of a sum of products.
+        let mut builder = Builder::new();
+        let x: Vec<NodeId> = (0..8).map(|_| builder.init()).collect();
+        let mut sum = builder.mul(x[0], x[1]);
+        for pair in x[2..].chunks(2) {
+            sum = builder.mul_add(pair[0], pair[1], sum);
+        }
+        builder.mark_public(sum);
+        let narrow = plonkish::extract(&builder, 2, 3);
+        let wide = plonkish::extract(&builder, 2, 6);
+        assert_eq!(narrow.rows.len(), 8 + 4);
+        assert_eq!(wide.rows.len(), 8 + 2);
+
+        // A shared node gets its own row, and constants go to fixed cells.
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let two = builder.constant(2);
+        let doubled = builder.mul(x, two);
+        let a = builder.add(doubled, x);
+        let b = builder.mul(doubled, a);
+        builder.enforce_equal(a, b);
+        let table = plonkish::extract(&builder, 3, 4);
+        let gates: Vec<String> = table.gates.iter().map(|gate| gate.to_string()).collect();
+        assert_eq!(gates, ["w0 * c0", "w0 + w1", "w0 * w1"]);
+        assert_eq!(table.rows.len(), 4);
+        assert_eq!(table.rows[1].fixed, vec![2]);
+        let row = |node: NodeId| table.row(node.index()).unwrap();
+        let output = |node| plonkish::Cell {
+            row: row(node),
+            column: 4,
+        };
+        assert!(table.copies.contains(&(output(a), output(b))));
+        assert!(table.copies.contains(&(
+            output(doubled),
+            plonkish::Cell {
+                row: row(b),
+                column: 0
+            }
+        )));
+    }
+
+    #[cfg(feature = "bellman")]
+    #[test]
+    fn test_bellman_circuit() {
+        use bellman::gadgets::test::TestConstraintSystem;
+        use bellman::groth16;
+        use bellman::Circuit;
+        use bls12_381::{Bls12, Scalar};
+        use my_graph_lib::groth16::GraphCircuit;
+        use rand_core::OsRng;
+
+        let program = parser::parse("y = x + 7; assert y == hint(sqrt, y)^2").unwrap();
+        let mut builder = program.builder;
+        let x = program.variables["x"];
+        let y = program.variables["y"];
+        let z = builder.mul_add(x, y, y);
+        let mut sum = x;
+        for _ in 0..40 {
+            sum = builder.add(sum, y);
+        }
+        let bound = builder.constant(1000);
+        builder.assert_lt(x, bound, 10);
+        builder.assert_range(z, 16);
+        builder.assert_in_set(x, &[9, 282]);
+        builder.assert_permutation(&[x, y, z], &[z, x, y]);
+        builder.enforce_equal_warning(x, y, "x differs from y");
+        builder.mark_public(z);
+        builder.mark_public(sum);
+
+        let satisfied = |builder: &Builder| {
+            let mut cs = TestConstraintSystem::<Scalar>::new();
+            GraphCircuit::new(builder).synthesize(&mut cs).unwrap();
+            cs.is_satisfied()
+        };
+        builder.fill_nodes(x, 9);
+        assert!(builder.is_satisfied());
+        assert!(satisfied(&builder));
+        let inputs: Vec<Scalar> = GraphCircuit::new(&builder).public_inputs().unwrap();
+        assert_eq!(
+            inputs,
+            [Scalar::from(9 * 16 + 16), Scalar::from(9 + 40 * 16)]
+        );
+        // Each breaks one check: 57 is not in the set, and z overflows 16 bits for 282.
+        for x_value in [57, 282] {
+            builder.fill_nodes(x, x_value);
+            assert!(!builder.is_satisfied());
+            assert!(!satisfied(&builder));
+        }
+
+        // A Groth16 proof over BLS12-381: y = x^2 + 7 is public and fits in 8 bits.
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let seven = builder.constant(7);
+        let y = builder.mul_add(x, x, seven);
+        builder.assert_range(y, 8);
+        builder.mark_public(y);
+        let params = groth16::generate_random_parameters::<Bls12, _, _>(
+            GraphCircuit::new(&builder),
+            &mut OsRng,
+        )
+        .unwrap();
+        let key = groth16::prepare_verifying_key(&params.vk);
+        builder.fill_nodes(x, 11);
+        let proof =
+            groth16::create_random_proof(GraphCircuit::new(&builder), &params, &mut OsRng).unwrap();
+        assert!(groth16::verify_proof(&key, &proof, &[Scalar::from(128)]).is_ok());
+        assert!(groth16::verify_proof(&key, &proof, &[Scalar::from(127)]).is_err());
+    }
+
+    #[test]
+    fn test_trace_layout() {
+        use my_graph_lib::trace::{self, Operand, Packing};
+
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.init();
+        let three = builder.constant(3);
+        let square = builder.mul(x, x);
+        let scaled = builder.mul(y, three);
+        let sum = builder.add(square, scaled);
+        let z = builder.mul_add(sum, x, y);
+        let hundred = builder.constant(100);
+        let two = builder.hint(2, x);
+        let w = builder.add(z, two);
+        builder.enforce_equal(w, hundred);
+        builder.assert_range(sum, 8);
+        builder.mark_public(z);
+        // 4 * 4 + 6 * 3 = 34, 34 * 4 + 6 = 142: w = 144 != 100.
+        builder.fill_inputs(&[(x, 4), (y, 6)]);
+
+        for (columns, packing) in [
+            (4, Packing::Sequential),
+            (8, Packing::Dense),
+            (10, Packing::Dense),
+            (12, Packing::Levels),
+        ] {
+            let layout = trace::layout(&builder, columns, packing);
+            // Inputs, computed nodes and the constrained constant, but not the other constants.
+            assert_eq!(layout.slots.len(), 8);
+            assert!(layout.rows.is_power_of_two());
+            assert!(layout.slot(three.index()).is_none());
+            assert!(layout.slot(hundred.index()).is_some());
+            assert!(layout.boundaries.iter().any(|&(_, value)| value == 3));
+            assert_eq!(
+                layout.public,
+                [(z.index(), layout.cell(z.index()).unwrap())]
+            );
+            assert_eq!(layout.checks.len(), 1);
+            let table = layout.trace(&builder).unwrap();
+            // The equality constraint fails.
+            assert!(!layout.is_satisfied(&table));
+            let copies = layout.copies.len();
+            let mut relaxed = layout.clone();
+            relaxed.copies.truncate(copies - 1);
+            assert!(relaxed.is_satisfied(&table));
+            let public = layout.public[0].1;
+            assert_eq!(table[public.row][public.column], 142);
+            match packing {
+                Packing::Sequential => assert_eq!(layout.rows, 8),
+                Packing::Dense => assert_eq!(layout.rows, 4),
+                Packing::Levels => {
+                    // Slots of a row don't read each other.
+                    for slot in &layout.slots {
+                        for operand in slot.operands {
+                            if let Operand::Node(node) = operand {
+                                assert!(layout.slot(node).unwrap().row < slot.row);
+                            }
+                        }
+                    }
+                }
+            }
+            // Tampering with an operand breaks a transition or a copy.
+            let mut tampered = table.clone();
+            let cell = layout.slot(sum.index()).unwrap().cell(0);
+            tampered[cell.row][cell.column] += 1;
+            assert!(!relaxed.is_satisfied(&tampered));
+        }
+
+        // A satisfied graph: 3 * 3 + 3 = 12.
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.mul_add(x, x, x);
+        let expected = builder.constant(12);
+        builder.enforce_equal(y, expected);
+        builder.fill_inputs(&[(x, 3)]);
+        let layout = trace::layout(&builder, 4, Packing::Levels);
+        assert_eq!(layout.rows, 4);
+        assert!(layout.is_satisfied(&layout.trace(&builder).unwrap()));
+    }
+
+    #[cfg(all(feature = "goldilocks", feature = "babybear", feature = "mersenne31"))]
+    #[test]
+    fn test_small_fields() {
+        use my_graph_lib::field::{BabyBear, FieldError, Fp, Goldilocks, Mersenne31};
+
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.init();
+        let square = builder.mul(x, x);
+        let z = builder.mul_add(square, y, x);
+        let bound = builder.constant(1000);
+        builder.assert_lt(x, bound, 16);
+
+        // 2^31 - 2: 2^31 - 1 is 0 in Mersenne31, so z = (-1)^2 * 3 - 1 = 2.
+        let values = builder
+            .evaluate_field(&[(x, Mersenne31::new((1 << 31) - 2)), (y, Mersenne31::new(3))])
+            .unwrap();
+        assert_eq!(values[square.index()], Fp::new(1));
+        assert_eq!(values[z.index()].value(), 2);
+        // x is not below 1000.
+        assert!(matches!(
+            builder.check_field(&values),
+            Err(FieldError::Check { check: 0 })
+        ));
+
+        // The same inputs overflow a u32 but not Goldilocks.
+        let big = (1u64 << 31) - 2;
+        let values = builder
+            .evaluate_field(&[(x, Goldilocks::new(big)), (y, Goldilocks::new(3))])
+            .unwrap();
+        let expected = (u128::from(big) * u128::from(big) * 3 + u128::from(big))
+            % u128::from(Goldilocks::MODULUS);
+        assert_eq!(u128::from(values[z.index()].value()), expected);
+
+        // Where u32 evaluation succeeds, BabyBear agrees up to reduction.
+        let target = builder.constant(5 * 5 * 7 + 5);
+        builder.enforce_equal(z, target);
+        let values = builder
+            .evaluate_field(&[(x, BabyBear::new(5)), (y, BabyBear::new(7))])
+            .unwrap();
+        assert!(builder.check_field(&values).is_ok());
+        builder.fill_inputs(&[(x, 5), (y, 7)]);
+        for (value, output) in values.iter().zip(builder.outputs()) {
+            assert_eq!(*value, BabyBear::new(u64::from(output.unwrap())));
+        }
+        let values = builder
+            .evaluate_field(&[(x, BabyBear::new(6)), (y, BabyBear::new(7))])
+            .unwrap();
+        assert!(matches!(
+            builder.check_field(&values),
+            Err(FieldError::NotEqual { constraint: 0, .. })
+        ));
+        assert!(matches!(
+            builder.evaluate_field::<{ BabyBear::MODULUS }>(&[(x, Fp::new(1))]),
+            Err(FieldError::MissingInput { .. })
+        ));
+    }
+
+    #[cfg(all(feature = "goldilocks", feature = "babybear", feature = "mersenne31"))]
+    #[test]
+    fn test_montgomery_field_arithmetic() {
+        use my_graph_lib::bench;
+        use my_graph_lib::field::{BabyBear, Fp, Goldilocks, Mersenne31};
+
+        fn agrees<const P: u64>() {
+            let samples = [
+                0,
+                1,
+                2,
+                P - 1,
+                P - 2,
+                P / 2,
+                P + 5,
+                u64::MAX,
+                0x1234_5678_9abc_def0,
+            ];
+            for &a in &samples {
+                assert_eq!(Fp::<P>::new(a).value(), a % P);
+                for &b in &samples {
+                    let (x, y) = (Fp::<P>::new(a), Fp::<P>::new(b));
+                    let (a, b) = (u128::from(a % P), u128::from(b % P));
+                    let p = u128::from(P);
+                    assert_eq!(u128::from((x + y).value()), (a + b) % p);
+                    assert_eq!(u128::from((x * y).value()), a * b % p);
+                }
+            }
+        }
+        agrees::<{ Goldilocks::MODULUS }>();
+        agrees::<{ BabyBear::MODULUS }>();
+        agrees::<{ Mersenne31::MODULUS }>();
+        assert!(Mersenne31::new(3) < Mersenne31::new(5));
+        assert_eq!(format!("{:?}", Goldilocks::new(7)), "Fp(7)");
+
+        // A chain of squarings, timed with both reductions.
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let mut y = x;
+        for _ in 0..1000 {
+            y = builder.mul_add(y, y, x);
+        }
+        let report = bench::bench_field::<{ Goldilocks::MODULUS }>(&builder, &[(x, 3)], 3).unwrap();
+        assert_eq!(report.nodes, 1001);
+        assert!(report.to_string().contains("montgomery"));
+        assert!(bench::bench_field::<{ BabyBear::MODULUS }>(&builder, &[], 1).is_err());
+    }
+
+    #[test]
+    fn test_lookup_export() {
+        use my_graph_lib::lookup::{self, Input, Table};
+
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.init();
+        let z = builder.add(x, y);
+        builder.assert_range(x, 8);
+        builder.assert_range(z, 20);
+        builder.assert_lt(x, y, 8);
+        builder.assert_in_set(y, &[3, 200]);
+        builder.assert_in_set(x, &[3, 200]);
+        builder.assert_permutation(&[x], &[x]);
+
+        let export = lookup::export(&builder, 8);
+        // 20 bits are split into limbs of 8, 8 and 4 bits.
+        assert_eq!(
+            export.tables,
+            [
+                Table::Range { bits: 8 },
+                Table::Range { bits: 4 },
+                Table::Set(vec![3, 200]),
+            ]
+        );
+        assert_eq!(export.lookups.len(), 1 + 3 + 2 + 2);
+        assert!(export.lookups.iter().all(|lookup| lookup.check < 5));
+        let limbs: Vec<(u32, u32)> = export
+            .lookups
+            .iter()
+            .filter_map(|lookup| match lookup.input {
+                Input::Limb { shift, bits, .. } => Some((shift, bits)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(limbs, [(0, 8), (8, 8), (16, 4)]);
+
+        builder.fill_inputs(&[(x, 3), (y, 200)]);
+        assert!(builder.is_satisfied());
+        let counts = export.multiplicities(&builder).unwrap();
+        // x, the low and middle limbs of z = 203, y - x - 1 = 196 and y = 200.
+        assert_eq!(counts[0].iter().sum::<u32>(), 5);
+        assert_eq!((counts[0][3], counts[0][203], counts[0][0]), (1, 1, 1));
+        assert_eq!((counts[0][196], counts[0][200]), (1, 1));
+        assert_eq!(counts[1][0], 1);
+        assert_eq!(counts[2], vec![1, 1]);
+        // Every lookup of a satisfied graph finds its value, and a failing check doesn't.
+        for lookup in &export.lookups {
+            let value = lookup.input.evaluate(&builder).unwrap();
+            assert!(export.tables[lookup.table].position(value).is_some());
+        }
+        builder.fill_inputs(&[(x, 200), (y, 3)]);
+        assert!(export.multiplicities(&builder).is_none());
+
+        let wide = lookup::export(&builder, 24);
+        assert_eq!(wide.tables.len(), 3);
+        assert_eq!(wide.tables[1], Table::Range { bits: 20 });
+    }
+
+    #[test]
+    fn test_permutation_export() {
+        use my_graph_lib::permutation;
+        use my_graph_lib::trace::{self, Packing};
+
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.init();
+        let square = builder.mul(x, x);
+        let sum = builder.add(square, y);
+        let twice = builder.add(sum, sum);
+        let z = builder.init();
+        let w = builder.init();
+        builder.enforce_equal(twice, z);
+        builder.enforce_equal(w, z);
+        builder.enforce_equal_warning(x, y, "x and y differ");
+        // Two classes at most: x and y are only equal with a warning.
+        assert_eq!(builder.copy_classes(), vec![vec![twice, z, w]]);
+
+        builder.fill_inputs(&[(x, 3), (y, 1), (z, 20), (w, 20)]);
+        assert!(builder.is_satisfied());
+        let table = plonkish::extract(&builder, 3, 4);
+        let assignment = table.assignment(&builder).unwrap();
+        let wiring = table.permutation();
+        assert!(wiring.is_satisfied(|cell: plonkish::Cell| assignment[cell.row][cell.column]));
+        // Every copy is in a cycle, and sigma walks each cycle back to its start.
+        let sigma = wiring.sigma();
+        for (a, b) in &table.copies {
+            let cycle = wiring
+                .cycles
+                .iter()
+                .find(|cycle| cycle.contains(a))
+                .unwrap();
+            assert!(cycle.contains(b));
+        }
+        for cycle in &wiring.cycles {
+            let mut cell = cycle[0];
+            for _ in 0..cycle.len() {
+                cell = sigma[&cell];
+            }
+            assert_eq!(cell, cycle[0]);
+        }
+
+        let layout = trace::layout(&builder, 8, Packing::Dense);
+        let values = layout.trace(&builder).unwrap();
+        let wiring = layout.permutation();
+        assert!(wiring.is_satisfied(|cell: trace::Cell| values[cell.row][cell.column]));
+        builder.fill_inputs(&[(x, 3), (y, 1), (z, 20), (w, 21)]);
+        let values = layout.trace(&builder).unwrap();
+        assert!(!wiring.is_satisfied(|cell: trace::Cell| values[cell.row][cell.column]));
+
+        assert_eq!(
+            permutation::from_copies(&[(2, 2), (1, 0)]).cycles,
+            vec![vec![0, 1]]
+        );
+    }
+
+    #[test]
+    fn test_boolean_lowering() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.init();
+        let three = builder.constant(3);
+        let product = builder.mul(x, y);
+        let z = builder.mul_add(product, three, x);
+        let w = builder.add(z, y);
+        let limit = builder.constant(1000);
+        builder.assert_lt(x, limit, 16);
+        builder.assert_range(w, 20);
+        builder.assert_in_set(y, &[2, 5, 70000]);
+        let sorted = [builder.constant(2), x];
+        builder.assert_permutation(&[x, y], &sorted);
+        builder.mark_public(w);
+        builder.mark_public(z);
+
+        let circuit = boolean::lower(&builder);
+        assert_eq!(circuit.inputs.len(), 2);
+        assert!(circuit
+            .gates
+            .iter()
+            .all(|gate| gate.a < gate.out && gate.b < gate.out));
+        for (a, b) in [
+            (7, 2),
+            (2, 2),
+            (2, 5),
+            (999, 2),
+            (1000, 2),
+            (70000, 70000),
+            (u32::MAX, 2),
+            (7, 70000),
+            (1 << 20, 1 << 12),
+        ] {
+            let mut reference = builder.clone();
+            let filled = reference.try_fill_inputs(&[(x, a), (y, b)]).is_ok();
+            let expected = filled && reference.is_satisfied();
+            let (outputs, valid) = circuit.evaluate(&[a, b]);
+            assert_eq!(valid, expected, "x = {}, y = {}", a, b);
+            if filled {
+                let public: Vec<u32> = reference
+                    .public_outputs()
+                    .into_iter()
+                    .map(|id| reference.output(id).unwrap())
+                    .collect();
+                assert_eq!(outputs, public);
+            }
+        }
+
+        // Bristol fashion: the inputs, then one output per public output and `valid`.
+        let bristol = circuit.to_bristol();
+        let lines: Vec<&str> = bristol.lines().collect();
+        let outputs = 2 * 32 + 1;
+        assert_eq!(
+            lines[0],
+            format!(
+                "{} {}",
+                circuit.gates.len() + outputs,
+                circuit.wires + outputs
+            )
+        );
+        assert_eq!(lines[1], "3 1 32 32");
+        assert_eq!(lines[2], "3 32 32 1");
+        assert_eq!(lines.len(), 4 + circuit.gates.len() + outputs);
+
+        let dimacs = circuit.to_dimacs();
+        let header = format!("p cnf {} ", circuit.wires);
+        assert!(dimacs.starts_with(&header));
+        assert!(dimacs
+            .lines()
+            .skip(1)
+            .all(|line| line.ends_with(" 0") || line == "0"));
+
+        // A constraint that never holds folds `valid` to a constant, and the formula to an
+        // unsatisfiable one.
+        let mut builder = Builder::new();
+        let one = builder.constant(1);
+        let two = builder.constant(2);
+        builder.enforce_equal(one, two);
+        let circuit = boolean::lower(&builder);
+        assert_eq!(circuit.valid, boolean::Bit::Const(false));
+        assert!(circuit.gates.is_empty());
+        assert!(circuit.to_dimacs().ends_with("\n0\n"));
+    }
+
+    #[test]
+    fn test_signed_comparisons() {
+        let mut builder = Builder::new();
+        let a = builder.init();
+        let b = builder.init();
+        let lt = builder.slt(a, b);
+        let le = builder.sle(a, b);
+        let gt = builder.sgt(a, b);
+        let ge = builder.sge(a, b);
+        let bits = builder.to_bits(a);
+        let values = [i32::MIN, i32::MIN + 1, -300, -1, 0, 1, 7, 300, i32::MAX];
+        for &x in &values {
+            for &y in &values {
+                builder.fill_inputs(&[(a, x as u32), (b, y as u32)]);
+                assert!(builder.is_satisfied());
+                let output = |wire: my_graph_lib::wires::BoolVar| builder.output(wire.id());
+                assert_eq!(output(lt), Some(u32::from(x < y)), "{} < {}", x, y);
+                assert_eq!(output(le), Some(u32::from(x <= y)), "{} <= {}", x, y);
+                assert_eq!(output(gt), Some(u32::from(x > y)), "{} > {}", x, y);
+                assert_eq!(output(ge), Some(u32::from(x >= y)), "{} >= {}", x, y);
+            }
+            let decomposed: u32 = bits
+                .iter()
+                .enumerate()
+                .map(|(i, bit)| builder.output(bit.id()).unwrap() << i)
+                .sum();
+            assert_eq!(decomposed as i32, x);
+            assert_eq!(builder.signed_value(a), Some(x));
+        }
+        // An unsigned comparison gets it wrong: -1 is u32::MAX.
+        builder.fill_inputs(&[(a, -1i32 as u32), (b, 0)]);
+        assert!(builder.output(a).unwrap() > builder.output(b).unwrap());
+        assert_eq!(builder.output(lt.id()), Some(1));
+
+        // A dishonest bit breaks the decomposition.
+        let mut builder = Builder::new();
+        let x = builder.signed_constant(-2);
+        let bits = builder.to_bits(x);
+        builder.fill_inputs(&[]);
+        assert!(builder.is_satisfied());
+        assert_eq!(builder.output(bits[0].id()), Some(0));
+        let mut witness = builder.witness();
+        witness.values[bits[0].id().index()] = Some(1);
+        builder.load_witness(&witness);
+        assert!(!builder.is_satisfied());
+    }
+
+    #[cfg(feature = "softfloat")]
+    #[test]
+    fn test_softfloat() {
+        let mut builder = Builder::new();
+        let a = builder.init();
+        let b = builder.init();
+        let sum = builder.f32_add(a, b);
+        let product = builder.f32_mul(a, b);
+        let mut values: Vec<u32> = [
+            0.0,
+            -0.0,
+            1.0,
+            -1.5,
+            0.1,
+            3.0e38,
+            -3.4e38,
+            1.0e-20,
+            1.0e-40,
+            -1.0e-45,
+            f32::MIN_POSITIVE,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NAN,
+        ]
+        .iter()
+        .map(|x: &f32| x.to_bits())
+        .collect();
+        let mut seed = 0x2545_f491u32;
+        for _ in 0..8 {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            values.push(seed);
+            // Close to 1, so that sums cancel and products round.
+            values.push(0x3f80_0000 ^ (seed >> 9));
+        }
+        for &x in &values {
+            for &y in &values {
+                builder.fill_inputs(&[(a, x), (b, y)]);
+                assert!(builder.is_satisfied());
+                for (node, expected) in [
+                    (sum, f32::from_bits(x) + f32::from_bits(y)),
+                    (product, f32::from_bits(x) * f32::from_bits(y)),
+                ] {
+                    let expected = if expected.is_nan() {
+                        my_graph_lib::softfloat::NAN
+                    } else {
+                        expected.to_bits()
+                    };
+                    assert_eq!(builder.output(node), Some(expected), "{x:#x} and {y:#x}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_multilinear_export() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.init();
+        let three = builder.constant(3);
+        let square = builder.mul(x, x);
+        let z = builder.mul_add(square, y, three);
+        let w = builder.add(z, x);
+        let expected = builder.init();
+        builder.enforce_equal(w, expected);
+        builder.mark_public(z);
+        let circuit = multilinear::export(&builder);
+        // x * x, then its product with y, then the sum with 3, then the sum with x.
+        assert_eq!(circuit.layers.len(), 5);
+        assert_eq!(circuit.constraints.len(), 1);
+        builder.fill_inputs(&[(x, 2), (y, 5), (expected, 25)]);
+        let mut values = circuit.witness(&builder).unwrap();
+        assert!(circuit.is_satisfied(&values));
+
+        // The sumcheck claim of every layer, at a point off the hypercube.
+        let modulus = (1 << 31) - 1;
+        for (i, pair) in circuit.layers.windows(2).enumerate() {
+            let (layer, next) = (&pair[0], &pair[1]);
+            let z: Vec<u64> = (0..layer.vars).map(|k| 1_000 + u64::from(k) * 7).collect();
+            let mut claim = 0;
+            for a in 0..1usize << next.vars {
+                for b in 0..1usize << next.vars {
+                    let bits = |index: usize| -> Vec<u64> {
+                        (0..next.vars).map(|k| (index >> k & 1) as u64).collect()
+                    };
+                    let (va, vb) = (u64::from(values[i + 1][a]), u64::from(values[i + 1][b]));
+                    let add =
+                        multilinear::evaluate_wiring(&layer.add, &z, &bits(a), &bits(b), modulus);
+                    let mul =
+                        multilinear::evaluate_wiring(&layer.mul, &z, &bits(a), &bits(b), modulus);
+                    claim =
+                        (claim + add * (va + vb) % modulus + mul * (va * vb % modulus)) % modulus;
+                }
+            }
+            assert_eq!(multilinear::evaluate(&values[i], &z, modulus), claim);
+        }
+
+        values[1][1] += 1;
+        assert!(!circuit.is_satisfied(&values));
+    }
+
+    #[test]
+    fn test_report() {
+        use my_graph_lib::report::Lint;
+
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let unused = builder.init();
+        let root = builder.hint_fn("sqrt", x);
+        let y = builder.add(root, x);
+        builder.mark_public(y);
+        builder.enforce_equal(x, x);
+        builder.assert_range(x, 8);
+        let report = builder.report();
+        assert_eq!(report.stats.inputs, 2);
+        assert_eq!(report.stats.hints, 1);
+        assert_eq!(report.constraints.equalities, 1);
+        assert_eq!(report.constraints.range, 1);
+        let lints: Vec<(Lint, usize)> = report
+            .lints
+            .iter()
+            .map(|finding| (finding.lint, finding.node))
+            .collect();
+        assert_eq!(
+            lints,
+            vec![
+                (Lint::UnusedInput, unused.index()),
+                (Lint::UnconstrainedHint, root.index()),
+                (Lint::TrivialConstraint, x.index()),
+            ]
+        );
+        assert_eq!(report.costs.as_ref().unwrap().lookups, 1);
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["lints"][1]["lint"], "unconstrained_hint");
+        assert_eq!(json["degrees"]["1"], 4);
+        assert!(report.to_string().contains("lints: 3"));
+    }
+
+    #[test]
+    fn test_html_viewer() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        builder.set_label(x, "x");
+        let doubled = builder.double(x);
+        let sum = builder.add(doubled, x);
+        builder.set_label(sum, "triple/sum");
+        let square = builder.square(sum);
+        builder.set_label(square, "</script>");
+        builder.set_meta(square, "scope", "outer/inner");
+        builder.fill_inputs(&[(x, 4)]);
+        let page = html::to_html(&builder);
+        assert!(!page.contains("/*DATA*/"));
+        assert_eq!(page.matches("</script>").count(), 2);
+        let start = page.find(r#"type="application/json">"#).unwrap() + 24;
+        let end = start + page[start..].find("</script>").unwrap();
+        let data: serde_json::Value = serde_json::from_str(&page[start..end]).unwrap();
+        let nodes = data["nodes"].as_array().unwrap();
+        let scope = |id: NodeId| nodes[id.index()]["scope"].clone();
+        assert_eq!(scope(x), serde_json::json!([]));
+        // The unlabelled doubling joins the scope of the sum reading it.
+        assert_eq!(scope(doubled), serde_json::json!(["triple"]));
+        assert_eq!(scope(square), serde_json::json!(["outer", "inner"]));
+        assert_eq!(nodes[square.index()]["label"], "</script>");
+        assert_eq!(nodes[square.index()]["value"], 144);
+        assert_eq!(nodes[sum.index()]["inputs"], serde_json::json!([1, 0]));
+
+        // Pages are shared, so values of secret nodes are left out unless revealed.
+        let mut builder = Builder::new();
+        let secret = builder.private_input();
+        let public = builder.init();
+        builder.add(secret, public);
+        builder.fill_inputs(&[(secret, 54321), (public, 7)]);
+        let page = html::to_html(&builder);
+        assert!(!page.contains("54321") && !page.contains("54328"));
+        assert!(page.contains(r#""value":7,"redacted":false"#));
+        assert!(page.contains(r#""value":null,"redacted":true"#));
+        builder.set_reveal_secrets(true);
+        assert!(html::to_html(&builder).contains(r#""value":54328"#));
+    }
+
+    #[test]
+    fn test_profile() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let one = builder.constant(1);
+        let mut acc = x;
+        for i in 0..3 {
+            let square = builder.mul(acc, acc);
+            acc = builder.add(square, one);
+            builder.set_label(acc, &format!("round{}/next", i));
+        }
+        let root = builder.hint_fn("sqrt", acc);
+        let mut profile = profile::Profile::default();
+        builder
+            .fill_inputs_with(&[(x, 2)], eval::EvalOptions::new().profile(&mut profile))
+            .unwrap();
+        builder
+            .fill_inputs_with(&[(x, 3)], eval::EvalOptions::new().profile(&mut profile))
+            .unwrap();
+        assert_eq!(profile.total.count, 2 * builder.len() as u64);
+        assert_eq!(profile.operations["mul"].count, 6);
+        assert_eq!(profile.operations["hint sqrt"].count, 2);
+        // Each square is charged to the round of the sum reading it, and the input and constant to
+        // the first round.
+        assert_eq!(profile.scopes["round1"].count, 4);
+        assert_eq!(profile.scopes["round0"].count, 8);
+        assert_eq!(profile.scopes[""].count, 2);
+        assert_eq!(profile.levels[&0].count, 4);
+        assert_eq!(builder.levels()[root.index()], 7);
+        let folded = profile.to_folded();
+        assert!(folded
+            .lines()
+            .any(|line| line.starts_with("fill;round2;mul ")));
+        assert!(folded
+            .lines()
+            .any(|line| line.starts_with("fill;hint sqrt ")));
+        let nanos: u128 = folded
+            .lines()
+            .map(|line| line.rsplit(' ').next().unwrap().parse::<u128>().unwrap())
+            .sum();
+        assert_eq!(nanos, profile.total.time.as_nanos());
+        assert!(profile.to_string().contains("(top level)"));
+    }
+
+    #[test]
+    fn test_eval_cache() {
+        let mut builder = Builder::new();
+        let key = builder.init();
+        let nonce = builder.init();
+        let one = builder.constant(1);
+        let mut state = key;
+        for _ in 0..4 {
+            let squared = builder.square(state);
+            state = builder.add(squared, one);
+        }
+        let mixed = builder.mul_add(state, nonce, key);
+        let root = builder.hint_fn("sqrt", state);
+        let out = builder.add(mixed, root);
+        builder.mark_public(out);
+        let cache = builder.eval_cache(&[key]);
+        assert_eq!(cache.prefix_len(), 9);
+        let mut cache = cache.capacity(2);
+        let mut reference = builder.clone();
+        for (k, n) in [(1, 5), (1, 6), (2, 7), (1, 8), (0, 9), (2, 1)] {
+            builder.fill_inputs_cached(&mut cache, &[(key, k), (nonce, n)]);
+            reference.fill_inputs(&[(key, k), (nonce, n)]);
+            assert_eq!(builder.witness(), reference.witness());
+        }
+        // key 2 was evicted by key 0, as key 1 was used more recently.
+        assert_eq!((cache.hits(), cache.misses()), (2, 4));
+        assert_eq!(cache.len(), 2);
+
+        // An evaluation that fails caches nothing, and the inputs must belong to the graph.
+        cache.clear();
+        let big = 1 << 20;
+        assert!(builder
+            .try_fill_inputs_cached(&mut cache, &[(key, big), (nonce, 0)])
+            .is_err());
+        assert!(cache.is_empty());
+        assert!(builder.try_eval_cache(&[one]).is_err());
+
+        // Adding nodes starts the cache over.
+        let doubled = builder.double(state);
+        builder.fill_inputs_cached(&mut cache, &[(key, 1), (nonce, 1)]);
+        assert_eq!(cache.prefix_len(), 10);
+        assert_eq!(builder.output(doubled), Some(2 * 677));
+    }
+
+    #[test]
+    fn test_specialize() {
+        let mut builder = Builder::new();
+        let modulus = builder.init();
+        builder.set_label(modulus, "modulus");
+        let x = builder.init();
+        let two = builder.constant(2);
+        let doubled = builder.mul(modulus, two);
+        let root = builder.hint_fn("sqrt", doubled);
+        let scaled = builder.mul_add(x, root, doubled);
+        let big = builder.constant(u32::MAX);
+        let overflow = builder.add(modulus, big);
+        builder.mark_public(scaled);
+        builder.enforce_equal(root, two);
+        builder.assert_range(x, 8);
+
+        let specialized = builder.specialize(&[(modulus, 2)]);
+        let residual = &specialized.builder;
+        // modulus, doubled and root are folded; the overflowing sum is kept.
+        assert_eq!(specialized.folded, 3);
+        assert_eq!(residual.inputs().len(), 1);
+        let modulus_id = specialized.ids[&modulus];
+        assert_eq!(residual.output(modulus_id), Some(2));
+        assert_eq!(residual.label(modulus_id), Some("modulus"));
+        let overflow = specialized.ids[&overflow];
+        assert_eq!(residual.output(overflow), None);
+
+        let mut residual = specialized.builder.clone();
+        // Both fill in every node but the sum that overflows.
+        assert!(residual.try_fill_nodes(specialized.ids[&x], 5).is_err());
+        assert!(builder.try_fill_inputs(&[(modulus, 2), (x, 5)]).is_err());
+        assert_eq!(residual.output(specialized.ids[&scaled]), Some(14));
+        assert_eq!(builder.output(scaled), Some(14));
+        assert_eq!(residual.output(specialized.ids[&root]), Some(2));
+        assert!(builder.try_specialize(&[(two, 1)]).is_err());
+    }
+
+    #[test]
+    fn test_regions() {
+        let build = |constrained: bool| {
+            let mut builder = Builder::new();
+            let three = builder.constant(3);
+            let mut gadgets = Vec::new();
+            for _ in 0..2 {
+                // A gadget reading one input, with a hint and a fan-out inside.
+                let x = builder.init();
+                let root = builder.hint_fn("sqrt", x);
+                let a = builder.mul(root, three);
+                let b = builder.add(root, x);
+                let gadget = builder.mul(a, b);
+                gadgets.push((x, [root, a, b, gadget]));
+            }
+            // Constraining a node inside the second gadget gives it two exits.
+            if constrained {
+                builder.enforce_equal(gadgets[1].1[1], three);
+            }
+            let out = builder.add(gadgets[0].1[3], gadgets[1].1[3]);
+            builder.mark_public(out);
+            (builder, gadgets)
+        };
+
+        let (builder, gadgets) = build(true);
+        let found = builder.regions(3);
+        assert_eq!(found.len(), 1);
+        let region = &found[0];
+        let (x, nodes) = gadgets[0];
+        assert_eq!((region.entry, region.exit), (x, nodes[3]));
+        assert_eq!(region.nodes, nodes.to_vec());
+        assert_eq!(region.multiplications, 2);
+        assert_eq!((region.degree, region.depth), (2, 3));
+        assert!(regions::by_shape(&found).is_empty());
+
+        let (builder, gadgets) = build(false);
+        let found = builder.regions(3);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1].exit, gadgets[1].1[3]);
+        assert_eq!(found[0].shape, found[1].shape);
+        assert_eq!(regions::by_shape(&found).len(), 1);
+        assert!(builder.regions(5).is_empty());
+    }
+
+    #[test]
+    fn test_solve() {
+        use my_graph_lib::solve::{Rule, SolveError};
+
+        let mut builder = Builder::new();
+        let a = builder.init();
+        let b = builder.init();
+        let sum = builder.add(a, b);
+        let square = builder.square(sum);
+        let root = builder.hint_fn("sqrt", square);
+        let scaled = builder.mul_add(root, a, b);
+        let target = builder.constant(49);
+        builder.enforce_equal(square, target);
+        let free = builder.init();
+        let unused = builder.mul(free, a);
+        let other = builder.init();
+        builder.enforce_equal_warning(other, a, "other should match a");
+
+        // sum^2 = 49 gives sum = 7, then a = 7 - 4 and the rest forward.
+        let mut partial = builder.witness();
+        partial.values[b.index()] = Some(4);
+        let solution = builder.solve(&partial).unwrap();
+        let values = &solution.witness.values;
+        assert_eq!(values[sum.index()], Some(7));
+        assert_eq!(values[a.index()], Some(3));
+        assert_eq!(values[root.index()], Some(7));
+        assert_eq!(values[scaled.index()], Some(25));
+        // Nothing forces the free input, nor the side of the soft constraint.
+        assert_eq!(solution.unknown, vec![free, unused, other]);
+        let rule = |node: NodeId| {
+            let derived = solution.derived.iter().find(|d| d.node == node).unwrap();
+            derived.rule
+        };
+        assert_eq!(rule(square), Rule::Equality);
+        assert_eq!(rule(sum), Rule::Inverse);
+        assert_eq!(rule(root), Rule::Forward);
+
+        // From the result of the mul-add and its addend, the product 21 is divided by root.
+        let mut partial = builder.witness();
+        partial.values[scaled.index()] = Some(25);
+        partial.values[b.index()] = Some(4);
+        partial.values[root.index()] = Some(7);
+        let solution = builder.solve(&partial).unwrap();
+        assert_eq!(solution.witness.values[a.index()], Some(3));
+        assert_eq!(solution.witness.values[sum.index()], Some(7));
+
+        // a = 10 makes sum^2 at least 100, which the constraint contradicts.
+        partial.values[a.index()] = Some(10);
+        partial.values[scaled.index()] = None;
+        let err = builder.solve(&partial).unwrap_err();
+        assert!(matches!(err, SolveError::Conflict { .. }), "{}", err);
+        partial.values.pop();
+        assert!(matches!(
+            builder.solve(&partial),
+            Err(SolveError::WrongLength { .. })
+        ));
+    }
+
+    #[test]
+    fn test_eval_limits() {
+        use my_graph_lib::eval::{EvalError, EvalOptions};
+        use my_graph_lib::limits::{EvalLimit, EvalLimits};
+        use std::time::Duration;
+
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let mut y = x;
+        for _ in 0..3000 {
+            y = builder.add(y, x);
+        }
+        builder.register_hint("half", |v| v / 2);
+        let hint = builder.hint_fn("half", y);
+        builder.mark_public(hint);
+
+        // Graph-wide limits are checked before any node is filled in.
+        let result = builder.fill_inputs_with(
+            &[(x, 1)],
+            EvalOptions::new().limits(EvalLimits::new().max_nodes(100)),
+        );
+        assert!(matches!(
+            result,
+            Err(EvalError::LimitExceeded(EvalLimit::Nodes(100)))
+        ));
+        assert_eq!(builder.output(x), Some(1));
+        assert_eq!(builder.output(y), None);
+        let result = builder.fill_inputs_with(
+            &[(x, 1)],
+            EvalOptions::new().limits(EvalLimits::new().max_memory(1024)),
+        );
+        let error = result.unwrap_err();
+        assert!(matches!(
+            error,
+            EvalError::LimitExceeded(EvalLimit::Memory(1024))
+        ));
+        assert_eq!(
+            error.to_string(),
+            "evaluation exceeded its memory limit of 1024 bytes"
+        );
+
+        // Steps count the additions and the hint call, not the input.
+        let limits = EvalLimits::new().max_steps(3001);
+        builder
+            .fill_inputs_with(&[(x, 1)], EvalOptions::new().limits(limits))
+            .unwrap();
+        assert_eq!(builder.output(hint), Some(1500));
+        let limits = EvalLimits::new().max_steps(3000);
+        let result = builder.fill_inputs_with(&[(x, 1)], EvalOptions::new().limits(limits));
+        assert!(matches!(
+            result,
+            Err(EvalError::LimitExceeded(EvalLimit::Steps(3000)))
+        ));
+
+        let limits = EvalLimits::new().timeout(Duration::ZERO);
+        let result = builder.fill_inputs_with(&[(x, 1)], EvalOptions::new().limits(limits));
+        assert!(matches!(
+            result,
+            Err(EvalError::LimitExceeded(EvalLimit::Timeout(_)))
+        ));
+        assert!(builder
+            .check_with(EvalOptions::new().limits(limits))
+            .is_err());
+    }
+
+    #[test]
+    fn test_json_hardening() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.mul(x, x);
+        let h = builder.hint_fn("sqrt", y);
+        builder.enforce_equal(h, x);
+        builder.assert_range(y, 8);
+        let document: serde_json::Value = serde_json::from_str(&builder.to_json()).unwrap();
+        let load = |edit: &dyn Fn(&mut serde_json::Value)| {
+            let mut document = document.clone();
+            edit(&mut document);
+            Builder::from_json(&document.to_string()).map(|_| ())
+        };
+        assert!(load(&|_| {}).is_ok());
+
+        let error = load(&|d| d["nodes"][1]["inputs"][1] = 7.into()).unwrap_err();
+        assert_eq!(error.code(), "index_out_of_range");
+        assert_eq!(
+            error.to_string(),
+            "node 1 refers to index 7, but there are only 3"
+        );
+        let error = load(&|d| d["constraints"][0][0] = 3.into()).unwrap_err();
+        assert!(matches!(
+            error,
+            error::SerdeError::IndexOutOfRange {
+                index: 3,
+                len: 3,
+                ..
+            }
+        ));
+        let error = load(&|d| d["soft_constraints"] = serde_json::json!([1])).unwrap_err();
+        assert_eq!(error.code(), "index_out_of_range");
+
+        // A hint linked to itself, and a multiplication and a hint reading each other.
+        let error = load(&|d| d["nodes"][2]["hint"] = 2.into()).unwrap_err();
+        assert!(matches!(error, error::SerdeError::Cycle { node: 2 }));
+        let error = load(&|d| d["nodes"][1]["inputs"][1] = 2.into()).unwrap_err();
+        assert_eq!(error.code(), "cycle");
+
+        let error = load(&|d| d["checks"][0]["Range"]["bits"] = 100.into()).unwrap_err();
+        assert_eq!(error.code(), "bad_length");
+        assert_eq!(
+            error.to_string(),
+            "the bit width of check 0 has length 100, expected 1 to 32"
+        );
+        assert_eq!(
+            Builder::from_json("{\"nodes\": 5}").unwrap_err().code(),
+            "invalid_json"
+        );
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_fuzz_deserializers() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.mul_add(x, x, x);
+        let h = builder.hint_fn("sqrt", y);
+        builder.enforce_equal(h, x);
+        builder.assert_lt(x, y, 16);
+        builder.assert_permutation(&[x, y], &[y, x]);
+        builder.set_label(y, "y");
+        let seeds = vec![
+            builder.to_json().into_bytes(),
+            #[cfg(feature = "flatbuffers")]
+            builder.to_flatbuffer(),
+            #[cfg(feature = "protobuf")]
+            builder.to_protobuf(),
+        ];
+
+        let mut state = 0x9e3779b9u32;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+        for seed in &seeds {
+            fuzz::load(seed);
+            for _ in 0..500 {
+                let mut bytes = seed.clone();
+                for _ in 0..1 + next() % 4 {
+                    let at = next() as usize % bytes.len();
+                    match next() % 3 {
+                        0 => bytes[at] = next() as u8,
+                        1 => bytes[at] = b"0123456789"[next() as usize % 10],
+                        _ => bytes.truncate(at),
+                    }
+                    if bytes.is_empty() {
+                        break;
+                    }
+                }
+                fuzz::load(&bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_ops() {
+        use my_graph_lib::custom::CustomOp;
+
+        let mut builder = Builder::new();
+        builder.register_op("xor", CustomOp::new(2, |x| Some(x[0] ^ x[1])));
+        let half = CustomOp::new(1, |x| Some(x[0] / 2)).check(|x, out| out * 2 == x[0]);
+        builder.register_op("half", half);
+        let x = builder.init();
+        let y = builder.init();
+        let z = builder.custom("xor", &[x, y]);
+        let h = builder.custom("half", &[x]);
+        builder.mark_public(z);
+        builder.mark_public(h);
+        assert_eq!(builder.op_name(z), Some("xor"));
+        assert!(matches!(
+            builder.try_custom("and", &[x, y]),
+            Err(error::BuildError::UnknownOp { .. })
+        ));
+        assert!(matches!(
+            builder.try_custom("xor", &[x]),
+            Err(error::BuildError::WrongArity {
+                expected: 2,
+                found: 1,
+                ..
+            })
+        ));
+
+        builder.fill_inputs(&[(x, 6), (y, 3)]);
+        assert_eq!(builder.output(z), Some(5));
+        assert_eq!(builder.output(h), Some(3));
+        assert!(builder.check().is_ok());
+        builder.fill_inputs(&[(x, 7), (y, 3)]);
+        let violation = builder.check().unwrap_err();
+        assert!(
+            matches!(violation, error::ConstraintError::CustomRejected { node, .. } if node == h.index())
+        );
+        assert_eq!(
+            violation.to_string(),
+            "node 3 holds 3, which operation `half` rejects"
+        );
+
+        // Graphs store operations by name, to be registered again after loading.
+        let mut loaded = Builder::from_json(&builder.to_json()).unwrap();
+        let (inputs, outputs) = (loaded.inputs(), loaded.public_outputs());
+        assert_eq!(loaded.op_name(outputs[0]), Some("xor"));
+        let assignment = [(inputs[0], 6), (inputs[1], 3)];
+        assert!(loaded.try_fill_inputs(&assignment).is_err());
+        loaded.register_op("xor", CustomOp::new(2, |x| Some(x[0] ^ x[1])));
+        loaded.register_op("half", half);
+        loaded.fill_inputs(&assignment);
+        assert_eq!(loaded.output(outputs[0]), Some(5));
+
+        // An operation registered with another arity than the graph's nodes is rejected.
+        let witness = loaded.witness();
+        loaded.register_op("half", CustomOp::new(2, |x| Some(x[0] + x[1])));
+        let wrong_arity = error::BuildError::WrongArity {
+            name: "half".to_string(),
+            expected: 2,
+            found: 1,
+        };
+        assert!(matches!(
+            loaded.try_fill_inputs(&assignment),
+            Err(error::EvalError::Build(err)) if err == wrong_arity
+        ));
+        assert_eq!(loaded.check(), Err(wrong_arity.clone().into()));
+        assert_eq!(
+            loaded.solve(&witness).unwrap_err(),
+            wrong_arity.clone().into()
+        );
+        assert_eq!(loaded.try_specialize(&[]).unwrap_err(), wrong_arity);
+        #[cfg(feature = "protobuf")]
+        {
+            let loaded = Builder::from_protobuf(&builder.to_protobuf()).unwrap();
+            assert_eq!(loaded.op_name(loaded.public_outputs()[1]), Some("half"));
+        }
+
+        let mut builder = Builder::new();
+        let square = CustomOp::new(1, |x| x[0].checked_mul(x[0])).lower(|b, x| b.mul(x[0], x[0]));
+        builder.register_op("square", square);
+        let x = builder.init();
+        let y = builder.custom("square", &[x]);
+        let sum = builder.add(y, x);
+        builder.mark_public(sum);
+        // Exports can't constrain custom nodes, which must be lowered first.
+        assert!(matches!(
+            export::try_to_r1cs(&builder),
+            Err(error::BuildError::Unlowered { node: 1, .. })
+        ));
+        assert!(boolean::try_lower(&builder).is_err());
+        assert!(builder.report().costs.is_none());
+        let mut lowered = builder.lower_custom_ops();
+        assert_eq!(lowered.len(), 3);
+        assert_eq!(lowered.report().stats.customs, 0);
+        assert!(export::try_to_r1cs(&lowered).is_ok());
+        lowered.fill_nodes(lowered.inputs()[0], 4);
+        assert_eq!(lowered.output(lowered.public_outputs()[0]), Some(20));
+    }
+
+    #[test]
+    fn test_observers() {
+        use my_graph_lib::observer::{Constraint, Observer};
+        use my_graph_lib::subcircuit::Subcircuit;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<String>>);
+
+        impl Observer for Recorder {
+            fn on_node_created(&self, _builder: &Builder, node: NodeId) {
+                let index = node.index();
+                self.0.lock().unwrap().push(format!("node {}", index));
+            }
+            fn on_constraint_added(&self, _builder: &Builder, constraint: Constraint) {
+                self.0.lock().unwrap().push(format!("{:?}", constraint));
+            }
+            fn on_node_evaluated(&self, _builder: &Builder, node: NodeId, value: u32) {
+                let index = node.index();
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("n{} = {}", index, value));
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let mut builder = Builder::new();
+        builder.add_observer(recorder.clone());
+        let x = builder.init();
+        let y = builder.mul(x, x);
+        let nine = builder.constant(9);
+        builder.enforce_equal(y, nine);
+        builder.assert_range(x, 4);
+        assert_eq!(
+            recorder.0.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec!["node 0", "node 1", "node 2", "Equality(0)", "Check(0)"]
+        );
+        // Clones keep reporting to the observer.
+        let mut other = builder.clone();
+        other.fill_nodes(x, 3);
+        assert!(other.is_satisfied());
+        assert_eq!(
+            recorder.0.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec!["n0 = 3", "n1 = 9", "n2 = 9"]
+        );
+        // Inlined nodes and constraints are reported once the subcircuit fits the limits.
+        let double = Subcircuit::new("double", 1, |b, args| {
+            let out = b.add(args[0], args[0]);
+            b.assert_range(out, 8);
+            vec![out]
+        });
+        builder.inline(&double, &[x]);
+        assert_eq!(
+            recorder.0.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec!["node 3", "Check(1)"]
+        );
+    }
+
+    #[test]
+    fn test_aliases() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let y = builder.init();
+        let sum = builder.add(x, y);
+        let copy = builder.init();
+        let other = builder.init();
+        builder.alias(copy, sum);
+        builder.alias(other, copy);
+        assert_eq!(builder.representative(other), sum);
+        assert_eq!(builder.representative(x), x);
+        assert_eq!(builder.alias_classes(), vec![vec![sum, copy, other]]);
+        assert_eq!(builder.copy_classes(), vec![vec![sum, copy, other]]);
+        // The class is one variable, so only the addition is constrained.
+        assert_eq!(export::to_r1cs(&builder).constraints.len(), 1);
+        assert!(builder.snapshot().contains("alias %4 = %2\n"));
+
+        builder.fill_inputs(&[(x, 1), (y, 2), (copy, 3), (other, 3)]);
+        assert!(builder.is_satisfied());
+        builder.fill_inputs(&[(x, 1), (y, 2), (copy, 3), (other, 4)]);
+        let violation = builder.check().unwrap_err();
+        assert!(matches!(
+            violation,
+            error::ConstraintError::NotAliased { a: 2, b: 4, .. }
+        ));
+        assert_eq!(builder.explain(&violation).sides.len(), 2);
+
+        let loaded = Builder::from_json(&builder.to_json()).unwrap();
+        assert_eq!(loaded.snapshot(), builder.snapshot());
+        assert!(!loaded.is_satisfied());
+        #[cfg(feature = "protobuf")]
+        assert_eq!(
+            Builder::from_protobuf(&builder.to_protobuf())
+                .unwrap()
+                .snapshot(),
+            builder.snapshot()
+        );
+        let optimized = passes::PassManager::standard().run(&builder);
+        assert_eq!(optimized.builder.alias_classes().len(), 1);
+
+        // A rollback removes the aliases of the removed nodes.
+        let savepoint = builder.savepoint();
+        let z = builder.init();
+        builder.alias(z, x);
+        builder.rollback(savepoint);
+        assert_eq!(builder.representative(x), x);
+        assert_eq!(builder.alias_classes(), vec![vec![sum, copy, other]]);
+    }
+
+    #[test]
+    fn test_batch_assertions() {
+        let mut builder = Builder::new();
+        let a: Vec<NodeId> = (0..3).map(|_| builder.init()).collect();
+        let b: Vec<NodeId> = (0..3).map(|_| builder.init()).collect();
+        let first = builder.assert_all_equal(&[(a[0], b[0])]);
+        assert_eq!(first.constraints(), 0..1);
+        let batch = builder.assert_vec_equal_with(&a, &b, "limbs");
+        assert_eq!(batch.constraints(), 1..4);
+        assert_eq!(builder.savepoint().constraints(), 4);
+
+        let values: Vec<(NodeId, u32)> = a.iter().chain(&b).map(|&id| (id, 7)).collect();
+        builder.fill_inputs(&values);
+        assert!(builder.batch_violations(&batch).is_empty());
+        builder.fill_inputs(&[
+            (a[0], 1),
+            (a[1], 2),
+            (a[2], 3),
+            (b[0], 1),
+            (b[1], 0),
+            (b[2], 0),
+        ]);
+        let violations = builder.batch_violations(&batch);
+        assert!(matches!(
+            violations[..],
+            [
+                error::ConstraintError::NotEqual { constraint: 2, .. },
+                error::ConstraintError::NotEqual { constraint: 3, .. }
+            ]
+        ));
+        assert!(builder
+            .explain(&violations[1])
+            .to_string()
+            .starts_with("limbs[2]: "));
+        assert_eq!(builder.check(), Err(violations[0].clone()));
+
+        // A batch is recorded whole or not at all.
+        builder.set_limits(limits::Limits::new().max_constraints(6));
+        let pairs: Vec<(NodeId, NodeId)> = a.iter().copied().zip(b.iter().copied()).collect();
+        assert!(matches!(
+            builder.try_assert_all_equal(&pairs),
+            Err(error::BuildError::LimitExceeded { .. })
+        ));
+        let stale = Builder::new().init();
+        assert!(builder
+            .try_assert_all_equal(&[(a[0], b[0]), (a[1], stale)])
+            .is_err());
+        assert_eq!(builder.savepoint().constraints(), 4);
+        assert_eq!(builder.try_assert_all_equal(&pairs[..2]).unwrap().len(), 2);
+
+        // Constraints removed by a rollback are no longer reported.
+        builder.set_limits(limits::Limits::new());
+        let savepoint = builder.savepoint();
+        let rolled_back = builder.assert_vec_equal(&a, &b);
+        assert_eq!(builder.batch_violations(&rolled_back).len(), 2);
+        builder.rollback(savepoint);
+        assert!(builder.batch_violations(&rolled_back).is_empty());
+    }
+
+    #[test]
+    fn test_graph_editing() {
+        let mut builder = Builder::new();
+        let x = builder.init();
+        let three = builder.constant(3);
+        let slow = builder.mul(x, three);
+        let zero = builder.constant(0);
+        let fast = builder.mul_add(x, three, zero);
+        let root = builder.hint_fn("sqrt", slow);
+        let y = builder.add(slow, x);
+        builder.set_label(y, "y");
+        builder.enforce_equal(slow, y);
+        builder.assert_range(slow, 8);
+        builder.mark_public(slow);
+
+        // Consumers must come after the replacement.
+        assert!(matches!(
+            builder.try_replace_node(slow, y),
+            Err(error::BuildError::ForwardReference {
+                node: 5,
+                operand: 6
+            })
+        ));
+        builder.replace_node(slow, fast);
+        assert_eq!(builder.consumers(slow).count(), 0);
+        assert_eq!(builder.consumers(fast).collect::<Vec<_>>(), vec![root, y]);
+        assert_eq!(builder.public_outputs(), vec![fast]);
+        assert!(builder
+            .snapshot()
+            .contains("assert %4 == %6\nassert %4 < 2^8\n"));
+
+        // The replaced node is unreferenced now, and removing it renumbers the nodes after it.
+        let err = builder.try_remove_unreferenced(x).unwrap_err();
+        assert_eq!(err.to_string(), "node 0 is still used by node 2");
+        let ids = builder.remove_unreferenced(slow);
+        assert_eq!(builder.len(), 6);
+        assert!(!builder.contains(y));
+        assert!(builder.contains(x));
+        let (root, fast, y) = (ids[&root], ids[&fast], ids[&y]);
+        assert_eq!(builder.find_label("y"), Some(y));
+        builder.fill_nodes(x, 4);
+        assert_eq!(builder.output(fast), Some(12));
+        assert_eq!(builder.output(root), Some(3));
+        assert_eq!(builder.output(y), Some(16));
+        assert!(!builder.is_satisfied());
+    }
+}
@@ -0,0 +1,61 @@
+//! Up-front estimates of how much memory a graph needs, to decide whether to evaluate it in
+//! memory or to stream its witness to disk (see `Builder::stream_witness`).
+//!
+//! Estimates are computed from the shape of the graph alone, without evaluating it, and cover
+//! heap allocations only. Allocator overhead and spare vector capacity are not included.
+
+use std::mem::size_of;
+
+use crate::storage::Graph;
+use crate::Builder;
+
+/// Bytes per value held by the streaming evaluator: a hash map entry plus its control byte,
+/// at the map's maximum load factor of 7/8.
+const LIVE_VALUE_BYTES: usize = (size_of::<(usize, u32)>() + 1) * 8 / 7;
+
+/// Expected memory use of a graph, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryEstimate {
+    /// The nodes, labels, hint calls, constraints, constraint messages and metadata of the graph. Metadata values are
+    /// counted by the size of their JSON text.
+    pub graph_bytes: usize,
+    /// A `Witness` holding every output, as returned by `Builder::witness`.
+    /// Filling in the graph itself needs no memory beyond `graph_bytes`.
+    pub full_witness_bytes: usize,
+    /// The values `stream_witness` keeps in memory at its peak, plus its use counts.
+    pub streaming_witness_bytes: usize,
+    /// Largest number of values `stream_witness` holds at once.
+    pub peak_live_values: usize,
+}
+
+impl Builder {
+    /// Estimates the memory needed to hold this graph and to evaluate it in memory or by streaming.
+    pub fn memory_estimate(&self) -> MemoryEstimate {
+        let len = self.graph.len();
+        let entry = |text: &String| size_of::<(usize, String)>() + text.len();
+        let graph_bytes = len * Graph::BYTES_PER_NODE
+            + self.labels.values().map(entry).sum::<usize>()
+            + self.hint_calls.values().map(entry).sum::<usize>()
+            + self.custom_calls.values().map(entry).sum::<usize>()
+            + self.constraint_messages.values().map(entry).sum::<usize>()
+            + (self.input_nodes.len() + self.public.len()) * size_of::<usize>()
+            + self.constraints.len() * size_of::<(usize, usize)>()
+            + self.soft_constraints.len() * size_of::<usize>()
+            + self.checks.len() * size_of::<crate::checks::Check>()
+            + self
+                .metadata
+                .values()
+                .flatten()
+                .map(|(key, value)| {
+                    size_of::<(String, serde_json::Value)>() + key.len() + value.to_string().len()
+                })
+                .sum::<usize>();
+        let peak_live_values = self.peak_live_values(0..len);
+        MemoryEstimate {
+            graph_bytes,
+            full_witness_bytes: len * size_of::<Option<u32>>(),
+            streaming_witness_bytes: peak_live_values * LIVE_VALUE_BYTES + len * size_of::<u32>(),
+            peak_live_values,
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Counters for monitoring services that build and evaluate many graphs.
+//!
+//! A `Metrics` instance is shared through an `Arc`, so one set of counters can aggregate over
+//! every builder a service creates:
+//!
+//! ```
+//! use std::sync::Arc;
+//! use my_graph_lib::{metrics::Metrics, Builder};
+//!
+//! let metrics = Arc::new(Metrics::default());
+//! let mut builder = Builder::new();
+//! builder.set_metrics(metrics.clone());
+//! let x = builder.init();
+//! builder.add(x, x);
+//! builder.fill_nodes(x, 1);
+//! assert_eq!(metrics.snapshot().nodes_created, 2);
+//! assert_eq!(metrics.snapshot().evaluations, 1);
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Live counters. All updates use relaxed atomics, so they can be shared across threads.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    nodes_created: AtomicU64,
+    constraints_added: AtomicU64,
+    evaluations: AtomicU64,
+    nodes_evaluated: AtomicU64,
+    evaluation_time_us: AtomicU64,
+    last_evaluation_time_us: AtomicU64,
+    constraint_checks: AtomicU64,
+    constraint_violations: AtomicU64,
+}
+
+/// A point-in-time copy of the counters in `Metrics`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub nodes_created: u64,
+    pub constraints_added: u64,
+    pub evaluations: u64,
+    pub nodes_evaluated: u64,
+    pub evaluation_time_us: u64, // Total time spent filling graphs.
+    pub last_evaluation_time_us: u64,
+    pub constraint_checks: u64,
+    pub constraint_violations: u64,
+}
+
+impl Metrics {
+    /// Reads every counter.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        MetricsSnapshot {
+            nodes_created: get(&self.nodes_created),
+            constraints_added: get(&self.constraints_added),
+            evaluations: get(&self.evaluations),
+            nodes_evaluated: get(&self.nodes_evaluated),
+            evaluation_time_us: get(&self.evaluation_time_us),
+            last_evaluation_time_us: get(&self.last_evaluation_time_us),
+            constraint_checks: get(&self.constraint_checks),
+            constraint_violations: get(&self.constraint_violations),
+        }
+    }
+
+    /// Sets every counter back to zero.
+    pub fn reset(&self) {
+        for counter in [
+            &self.nodes_created,
+            &self.constraints_added,
+            &self.evaluations,
+            &self.nodes_evaluated,
+            &self.evaluation_time_us,
+            &self.last_evaluation_time_us,
+            &self.constraint_checks,
+            &self.constraint_violations,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn node_created(&self) {
+        self.nodes_created.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn constraint_added(&self) {
+        self.constraints_added.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn evaluated(&self, nodes: usize, elapsed: Duration) {
+        let elapsed_us = elapsed.as_micros() as u64;
+        self.evaluations.fetch_add(1, Ordering::Relaxed);
+        self.nodes_evaluated
+            .fetch_add(nodes as u64, Ordering::Relaxed);
+        self.evaluation_time_us
+            .fetch_add(elapsed_us, Ordering::Relaxed);
+        self.last_evaluation_time_us
+            .store(elapsed_us, Ordering::Relaxed);
+    }
+
+    pub(crate) fn checked(&self, satisfied: bool) {
+        self.constraint_checks.fetch_add(1, Ordering::Relaxed);
+        if !satisfied {
+            self.constraint_violations.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Minimization of failing input assignments, for readable bug reports and regression tests.
+//!
+//! `Builder::minimize_failure` starts from inputs that make a constraint fail and shrinks their
+//! values while the same constraint keeps failing. It first tries zeroing groups of inputs at once,
+//! halving the groups like delta debugging, then binary searches each input for a smaller failing
+//! value, and repeats until neither step shrinks a value. Every try evaluates the whole graph.
+//! The result is not guaranteed to be minimal: the binary search assumes that values below a
+//! passing one pass too, so when failures are not monotonic in an input it can settle on a value
+//! while a smaller one still fails, and lowering several inputs together might fail as well.
+
+use crate::error::{ConstraintError, EvalError};
+use crate::eval::EvalOptions;
+use crate::{Builder, NodeId};
+
+/// The result of `Builder::minimize_failure`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Minimized {
+    /// The smallest failing inputs found, in the order they were given.
+    pub inputs: Vec<(NodeId, u32)>,
+    /// The violation the minimized inputs cause, which is the same constraint, check or node as
+    /// the original one.
+    pub violation: ConstraintError,
+    /// Number of input assignments that were evaluated.
+    pub evaluations: usize,
+}
+
+/// Returns true if two violations are about the same constraint, check or node, whatever the
+/// values involved.
+fn same_failure(a: &ConstraintError, b: &ConstraintError) -> bool {
+    use ConstraintError::*;
+    match (a, b) {
+        (NotEqual { constraint: a, .. }, NotEqual { constraint: b, .. }) => a == b,
+        (NotLess { check: a, .. }, NotLess { check: b, .. })
+        | (NotInSet { check: a, .. }, NotInSet { check: b, .. })
+        | (OutOfRange { check: a, .. }, OutOfRange { check: b, .. })
+        | (NotPermutation { check: a, .. }, NotPermutation { check: b, .. }) => a == b,
+        (WrongOutput { node: a, .. }, WrongOutput { node: b, .. })
+        | (Overflow { node: a }, Overflow { node: b })
+        | (Unfilled { node: a }, Unfilled { node: b }) => a == b,
+        _ => false,
+    }
+}
+
+/// Evaluates input assignments on a copy of a graph.
+struct Search {
+    graph: Builder,
+    target: ConstraintError,
+    evaluations: usize,
+}
+
+impl Search {
+    /// Returns the violation `inputs` cause if it is the one being minimized. Assignments whose
+    /// evaluation fails, e.g. with an overflow, don't reproduce it.
+    fn fails(&mut self, inputs: &[(NodeId, u32)]) -> Option<ConstraintError> {
+        self.evaluations += 1;
+        self.graph.reset();
+        self.graph
+            .fill_inputs_with(inputs, EvalOptions::new())
+            .ok()?;
+        let violation = self.graph.check().err()?;
+        same_failure(&violation, &self.target).then_some(violation)
+    }
+}
+
+impl Builder {
+    /// Searches for an input assignment with smaller values than `inputs` that makes the same
+    /// constraint fail, e.g. to turn a failure found by a fuzzer into a small regression test.
+    ///
+    /// Returns None if `inputs` satisfy every constraint, and an error if they can't be evaluated.
+    /// The graph itself is left untouched.
+    pub fn minimize_failure(
+        &self,
+        inputs: &[(NodeId, u32)],
+    ) -> Result<Option<Minimized>, EvalError> {
+        let mut graph = self.clone();
+        // The tries are not evaluations of the circuit, so keep them out of the metrics and away
+        // from observers.
+        graph.metrics = None;
+        graph.observers.clear();
+        graph.reset();
+        graph.fill_inputs_with(inputs, EvalOptions::new())?;
+        let Err(target) = graph.check() else {
+            return Ok(None);
+        };
+        let mut search = Search {
+            graph,
+            target: target.clone(),
+            evaluations: 1,
+        };
+        let mut current = inputs.to_vec();
+        let mut violation = target;
+        loop {
+            let mut shrunk = false;
+            // Zero groups of inputs at once, starting with all of them.
+            let mut group = current.len();
+            while group > 0 {
+                let nonzero: Vec<usize> =
+                    (0..current.len()).filter(|&i| current[i].1 != 0).collect();
+                for chunk in nonzero.chunks(group) {
+                    let mut candidate = current.clone();
+                    for &i in chunk {
+                        candidate[i].1 = 0;
+                    }
+                    if let Some(found) = search.fails(&candidate) {
+                        current = candidate;
+                        violation = found;
+                        shrunk = true;
+                    }
+                }
+                group /= 2;
+            }
+            // Search each input for a smaller failing value. Failures need not be monotonic in
+            // the value, so this finds a failing value, not necessarily the smallest one.
+            for i in 0..current.len() {
+                let (mut low, mut high) = (0, current[i].1);
+                while low < high {
+                    let mid = low + (high - low) / 2;
+                    let mut candidate = current.clone();
+                    candidate[i].1 = mid;
+                    match search.fails(&candidate) {
+                        Some(found) => {
+                            high = mid;
+                            violation = found;
+                        }
+                        None => low = mid + 1,
+                    }
+                }
+                if high < current[i].1 {
+                    current[i].1 = high;
+                    shrunk = true;
+                }
+            }
+            if !shrunk {
+                break;
+            }
+        }
+        Ok(Some(Minimized {
+            inputs: current,
+            violation,
+            evaluations: search.evaluations,
+        }))
+    }
+}
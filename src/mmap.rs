@@ -0,0 +1,305 @@
+//! A graph stored in a memory-mapped file instead of a `Vec<Node>`.
+//!
+//! Every node is a fixed size 16 byte record, and output values are written into the records
+//! themselves, so graphs with hundreds of millions of nodes can be built and filled in while the
+//! operating system pages records in and out as needed.
+//!
+//! Layout: an 8 byte magic and the node count (u64, little endian), then one record per node:
+//!
+//! | bytes  | field                                                    |
+//! |--------|----------------------------------------------------------|
+//! | 0      | kind (input, constant, add, mul, hint, equality)         |
+//! | 1      | flags (bit 0: the value field holds an output)           |
+//! | 2..4   | unused                                                   |
+//! | 4..8   | first input, or the linked node for hints (u32)          |
+//! | 8..12  | second input (u32)                                       |
+//! | 12..16 | output value (u32)                                       |
+//!
+//! Equality constraints are stored as records of their own, so they take up a node index.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+use memmap2::MmapMut;
+
+use crate::storage::Kind;
+use crate::{Builder, OPERATION};
+
+const MAGIC: &[u8; 8] = b"CIRCMAP1";
+const HEADER_LEN: usize = 16;
+const RECORD_LEN: usize = 16;
+const HAS_VALUE: u8 = 1;
+
+const INPUT: u8 = 0;
+const CONSTANT: u8 = 1;
+const ADD: u8 = 2;
+const MUL: u8 = 3;
+const HINT: u8 = 4;
+const EQUAL: u8 = 5;
+
+/// A graph backed by a memory-mapped file.
+pub struct MmapGraph {
+    file: File,
+    map: MmapMut,
+    len: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Record {
+    kind: u8,
+    flags: u8,
+    a: u32,
+    b: u32,
+    value: u32,
+}
+
+impl MmapGraph {
+    /// Creates a new, empty graph file with room for `capacity` nodes. The file grows as needed.
+    pub fn create(path: &Path, capacity: usize) -> io::Result<MmapGraph> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len((HEADER_LEN + capacity.max(1) * RECORD_LEN) as u64)?;
+        // Safety: the mapping is only valid as long as no other process truncates or rewrites the
+        // file, which is the usual contract for memory-mapped files.
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        map[..8].copy_from_slice(MAGIC);
+        let mut graph = MmapGraph { file, map, len: 0 };
+        graph.write_len();
+        Ok(graph)
+    }
+
+    /// Opens a graph file written by `create`, including any outputs filled in so far.
+    pub fn open(path: &Path) -> io::Result<MmapGraph> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        // Safety: see `create`.
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        if map.len() < HEADER_LEN || &map[..8] != MAGIC {
+            // The last byte of the magic is the version of the format.
+            let message = if map.len() >= 8 && map[..7] == MAGIC[..7] {
+                format!(
+                    "memory-mapped graph format version {} is not supported, this version of the library reads version {}",
+                    char::from(map[7]),
+                    char::from(MAGIC[7])
+                )
+            } else {
+                "not a memory-mapped graph".to_string()
+            };
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        let len = u64::from_le_bytes(map[8..16].try_into().unwrap()) as usize;
+        if map.len() < HEADER_LEN + len * RECORD_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "graph file is truncated",
+            ));
+        }
+        Ok(MmapGraph { file, map, len })
+    }
+
+    /// Copies a graph into a memory-mapped file, keeping node indices. Equality constraints are
+    /// appended after the nodes, except the ones with the `Warning` severity, followed by an
+    /// equality between every aliased node and the representative of its class. Hints computed by
+    /// hint functions, mul-adds, custom operations and checks such as orderings can not be stored.
+    pub fn from_builder(builder: &Builder, path: &Path) -> io::Result<MmapGraph> {
+        if !builder.hint_calls.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "hint functions can not be stored in a memory-mapped graph",
+            ));
+        }
+        if !builder.checks.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "checks can not be stored in a memory-mapped graph",
+            ));
+        }
+        if !builder.custom_calls.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "custom operations can not be stored in a memory-mapped graph",
+            ));
+        }
+        if builder.graph.iter().any(|node| node.addend.is_some()) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "mul-adds can not be stored in a memory-mapped graph",
+            ));
+        }
+        let constraints = builder.copies();
+        let mut graph = MmapGraph::create(path, builder.len() + constraints.len())?;
+        for node in builder.graph.iter() {
+            let (kind, a, b) = match (node.op, node.inputs, node.hint) {
+                (Some(OPERATION::ADD), (Some(a), Some(b)), _) => (ADD, a, b),
+                (Some(OPERATION::MUL), (Some(a), Some(b)), _) => (MUL, a, b),
+                (_, _, Some(linked)) => (HINT, linked, 0),
+                _ if builder.graph.kind(node.id) == Kind::Constant => (CONSTANT, 0, 0),
+                _ => (INPUT, 0, 0),
+            };
+            graph.push(kind, a, b, node.output)?;
+        }
+        for (a, b) in constraints {
+            graph.push(EQUAL, a, b, None)?;
+        }
+        Ok(graph)
+    }
+
+    /// Number of records, including equality constraint records.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds a variable input node.
+    pub fn init(&mut self) -> io::Result<usize> {
+        self.push(INPUT, 0, 0, None)
+    }
+
+    /// Adds a node with a constant value.
+    pub fn constant(&mut self, val: u32) -> io::Result<usize> {
+        self.push(CONSTANT, 0, 0, Some(val))
+    }
+
+    /// Adds a node computing `a + b`.
+    pub fn add(&mut self, a: usize, b: usize) -> io::Result<usize> {
+        self.push(ADD, a, b, None)
+    }
+
+    /// Adds a node computing `a * b`.
+    pub fn mul(&mut self, a: usize, b: usize) -> io::Result<usize> {
+        self.push(MUL, a, b, None)
+    }
+
+    /// Adds a hint node with an externally computed value, linked to `hint_node`.
+    pub fn hint(&mut self, hint_value: u32, hint_node: usize) -> io::Result<usize> {
+        self.push(HINT, hint_node, 0, Some(hint_value))
+    }
+
+    /// Records that the outputs of `a` and `b` must be equal.
+    pub fn enforce_equal(&mut self, a: usize, b: usize) -> io::Result<()> {
+        self.push(EQUAL, a, b, None)?;
+        Ok(())
+    }
+
+    /// Returns the output of a node, if it has been filled in.
+    pub fn output(&self, id: usize) -> Option<u32> {
+        let record = self.read(id);
+        (record.flags & HAS_VALUE != 0).then_some(record.value)
+    }
+
+    /// Sets the input values and fills in every add and mul node.
+    pub fn fill_inputs(&mut self, inputs: &[(usize, u32)]) {
+        for &(id, value) in inputs {
+            assert_eq!(self.read(id).kind, INPUT, "node {} is not an input", id);
+            self.set_output(id, value);
+        }
+        for id in 0..self.len {
+            let record = self.read(id);
+            let op = match record.kind {
+                ADD => OPERATION::ADD,
+                MUL => OPERATION::MUL,
+                _ => continue,
+            };
+            let a_val = self.output(record.a as usize).unwrap();
+            let b_val = self.output(record.b as usize).unwrap();
+            self.set_output(id, op.apply(a_val, b_val));
+        }
+    }
+
+    /// Returns true if every add, mul and equality record holds for the filled in outputs.
+    pub fn is_satisfied(&self) -> bool {
+        (0..self.len).all(|id| {
+            let record = self.read(id);
+            let a_val = || self.output(record.a as usize).unwrap();
+            let b_val = || self.output(record.b as usize).unwrap();
+            match record.kind {
+                ADD => self.output(id) == Some(OPERATION::ADD.apply(a_val(), b_val())),
+                MUL => self.output(id) == Some(OPERATION::MUL.apply(a_val(), b_val())),
+                EQUAL => a_val() == b_val(),
+                _ => true,
+            }
+        })
+    }
+
+    /// Flushes outstanding writes to disk.
+    pub fn flush(&self) -> io::Result<()> {
+        self.map.flush()
+    }
+
+    fn push(&mut self, kind: u8, a: usize, b: usize, value: Option<u32>) -> io::Result<usize> {
+        let id = self.len;
+        for input in [a, b] {
+            assert!(
+                input < id || (kind == INPUT || kind == CONSTANT),
+                "node {} does not exist yet",
+                input
+            );
+        }
+        if HEADER_LEN + (id + 1) * RECORD_LEN > self.map.len() {
+            self.grow()?;
+        }
+        let record = Record {
+            kind,
+            flags: if value.is_some() { HAS_VALUE } else { 0 },
+            a: u32::try_from(a).expect("node index does not fit in a u32"),
+            b: u32::try_from(b).expect("node index does not fit in a u32"),
+            value: value.unwrap_or(0),
+        };
+        self.write(id, record);
+        self.len += 1;
+        self.write_len();
+        Ok(id)
+    }
+
+    /// Doubles the size of the file and remaps it.
+    fn grow(&mut self) -> io::Result<()> {
+        self.map.flush()?;
+        let records = (self.map.len() - HEADER_LEN) / RECORD_LEN;
+        self.file
+            .set_len((HEADER_LEN + records.max(1) * 2 * RECORD_LEN) as u64)?;
+        // Safety: see `create`.
+        self.map = unsafe { MmapMut::map_mut(&self.file)? };
+        Ok(())
+    }
+
+    fn write_len(&mut self) {
+        self.map[8..16].copy_from_slice(&(self.len as u64).to_le_bytes());
+    }
+
+    fn set_output(&mut self, id: usize, value: u32) {
+        let mut record = self.read(id);
+        record.flags |= HAS_VALUE;
+        record.value = value;
+        self.write(id, record);
+    }
+
+    fn read(&self, id: usize) -> Record {
+        assert!(id < self.len, "node {} is out of range", id);
+        let bytes = &self.map[HEADER_LEN + id * RECORD_LEN..][..RECORD_LEN];
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        Record {
+            kind: bytes[0],
+            flags: bytes[1],
+            a: u32_at(4),
+            b: u32_at(8),
+            value: u32_at(12),
+        }
+    }
+
+    fn write(&mut self, id: usize, record: Record) {
+        let bytes = &mut self.map[HEADER_LEN + id * RECORD_LEN..][..RECORD_LEN];
+        bytes[0] = record.kind;
+        bytes[1] = record.flags;
+        bytes[2..4].fill(0);
+        bytes[4..8].copy_from_slice(&record.a.to_le_bytes());
+        bytes[8..12].copy_from_slice(&record.b.to_le_bytes());
+        bytes[12..16].copy_from_slice(&record.value.to_le_bytes());
+    }
+}
@@ -0,0 +1,322 @@
+//! Export as a layered circuit of multilinear polynomials, for GKR and sumcheck-based provers.
+//!
+//! GKR proves a circuit layer by layer: the values of layer `i` are a table over the boolean
+//! hypercube, whose multilinear extension `V_i` is tied to the next layer by
+//!
+//! ```text
+//! V_i(z) = sum over x, y of add_i(z, x, y) * (V_{i+1}(x) + V_{i+1}(y))
+//!                          + mul_i(z, x, y) * V_{i+1}(x) * V_{i+1}(y)
+//! ```
+//!
+//! where the wiring predicates `add_i` and `mul_i` are 1 on the triples `(z, x, y)` of gates `z`
+//! of layer `i` reading gates `x` and `y` of layer `i + 1`, and 0 elsewhere. `export` lays the
+//! graph out that way: layer 0 holds the outputs and the last layer the inputs, constants and
+//! hints, which the prover supplies. A `mul_add` node takes two layers, its product and then its
+//! sum. Gates read only the next layer, so a value used further up is relayed, added to the zero
+//! that gate 0 of every layer holds.
+//!
+//! The outputs are the public nodes, the operands of constraints and checks, and the nodes read by
+//! no gate. Equality constraints and aliased nodes with the representative of their class become
+//! pairs of output gates; checks are left to the backend.
+//! Index `z` of a layer of `2^vars` gates is the point whose `k`-th coordinate is bit `k` of `z`,
+//! and `evaluate` and `evaluate_wiring` evaluate the extensions at other points, modulo a prime
+//! below 2^63.
+//!
+//! ```
+//! use my_graph_lib::{multilinear, Builder};
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let y = builder.init();
+//! let sum = builder.add(x, y);
+//! let product = builder.mul(sum, x);
+//! builder.mark_public(product);
+//! let circuit = multilinear::export(&builder);
+//! // The product, the sum, and the inputs, each layer after the zero.
+//! assert_eq!(circuit.layers.len(), 3);
+//! assert_eq!(circuit.layers[0].mul, vec![[1, 2, 1]]);
+//! // x is relayed past the sum.
+//! assert_eq!(circuit.layers[1].add, vec![[1, 1, 0], [2, 1, 2]]);
+//! builder.fill_inputs(&[(x, 3), (y, 4)]);
+//! let values = circuit.witness(&builder).unwrap();
+//! assert_eq!(values[0], vec![0, 21]);
+//! assert!(circuit.is_satisfied(&values));
+//! ```
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::error::BuildError;
+use crate::storage::Kind;
+use crate::Builder;
+
+/// The value a gate carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Wire {
+    /// 0, at gate 0 of every layer.
+    Zero,
+    /// The output of a node.
+    Node(usize),
+    /// The product `a * b` of the `mul_add` node `a * b + c`.
+    Product(usize),
+}
+
+/// A layer of the circuit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Layer {
+    /// The number of variables of the layer's polynomials: the layer is padded with zeros to
+    /// `2^vars` gates.
+    pub vars: u32,
+    /// The value of each gate.
+    pub wires: Vec<Wire>,
+    /// The triples `(z, x, y)` where `add_i` is 1, ordered by `z`. Empty for the input layer.
+    pub add: Vec<[usize; 3]>,
+    /// The triples `(z, x, y)` where `mul_i` is 1, ordered by `z`.
+    pub mul: Vec<[usize; 3]>,
+}
+
+/// A graph as a layered circuit, from `export`.
+#[derive(Debug, Clone, Serialize)]
+pub struct LayeredCircuit {
+    /// The layers, from the outputs to the inputs.
+    pub layers: Vec<Layer>,
+    /// Pairs of gates of the output layer that must be equal.
+    pub constraints: Vec<(usize, usize)>,
+}
+
+impl LayeredCircuit {
+    /// The values of every layer on the hypercube, from the outputs of the builder's nodes. `None`
+    /// if a node has not been filled in.
+    pub fn witness(&self, builder: &Builder) -> Option<Vec<Vec<u32>>> {
+        let output = |node: usize| builder.graph.output(node);
+        self.layers
+            .iter()
+            .map(|layer| {
+                let mut values = vec![0; 1 << layer.vars];
+                for (value, &wire) in values.iter_mut().zip(&layer.wires) {
+                    *value = match wire {
+                        Wire::Zero => 0,
+                        Wire::Node(node) => output(node)?,
+                        Wire::Product(node) => {
+                            let [a, b, _] = builder.graph.mul_add(node)?;
+                            output(a)?.checked_mul(output(b)?)?
+                        }
+                    };
+                }
+                Some(values)
+            })
+            .collect()
+    }
+
+    /// Whether `values` has the shape of the layers, every layer is computed from the next one by
+    /// its wiring predicates, and the constraints hold.
+    pub fn is_satisfied(&self, values: &[Vec<u32>]) -> bool {
+        if values.len() != self.layers.len()
+            || self
+                .layers
+                .iter()
+                .zip(values)
+                .any(|(layer, values)| values.len() != 1 << layer.vars)
+        {
+            return false;
+        }
+        let layers_hold = (0..self.layers.len() - 1).all(|i| {
+            let next = |gate: usize| u64::from(values[i + 1][gate]);
+            let mut expected = vec![0u64; values[i].len()];
+            for &[z, x, y] in &self.layers[i].add {
+                expected[z] += next(x) + next(y);
+            }
+            for &[z, x, y] in &self.layers[i].mul {
+                expected[z] += next(x) * next(y);
+            }
+            values[i]
+                .iter()
+                .zip(&expected)
+                .all(|(&value, &expected)| u64::from(value) == expected)
+        });
+        layers_hold
+            && self
+                .constraints
+                .iter()
+                .all(|&(a, b)| values[0][a] == values[0][b])
+    }
+}
+
+/// The multilinear extension of `table`, a layer of `2^point.len()` values, at `point`, modulo
+/// the prime `modulus`. Coordinate `k` of the point stands for bit `k` of the index.
+pub fn evaluate(table: &[u32], point: &[u64], modulus: u64) -> u64 {
+    assert_eq!(
+        table.len(),
+        1 << point.len(),
+        "the table doesn't match the point"
+    );
+    let mut values: Vec<u64> = table
+        .iter()
+        .map(|&value| u64::from(value) % modulus)
+        .collect();
+    for &r in point {
+        values = values
+            .chunks(2)
+            .map(|pair| {
+                let (low, high) = (pair[0], pair[1]);
+                // low + r * (high - low)
+                let difference = (high + modulus - low) % modulus;
+                (low + mul_mod(r, difference, modulus)) % modulus
+            })
+            .collect();
+    }
+    values[0]
+}
+
+/// The multilinear extension of a wiring predicate, the triples `entries` of `Layer::add` or
+/// `Layer::mul`, at `(z, x, y)`, modulo the prime `modulus`.
+pub fn evaluate_wiring(
+    entries: &[[usize; 3]],
+    z: &[u64],
+    x: &[u64],
+    y: &[u64],
+    modulus: u64,
+) -> u64 {
+    entries.iter().fold(0, |sum, &[gate, left, right]| {
+        let term = mul_mod(eq(gate, z, modulus), eq(left, x, modulus), modulus);
+        let term = mul_mod(term, eq(right, y, modulus), modulus);
+        (sum + term) % modulus
+    })
+}
+
+/// The extension of the indicator of `index` at `point`: the product of `r` for the bits of
+/// `index` that are 1 and `1 - r` for the others.
+fn eq(index: usize, point: &[u64], modulus: u64) -> u64 {
+    point.iter().enumerate().fold(1, |product, (k, &r)| {
+        let factor = if index >> k & 1 == 1 {
+            r % modulus
+        } else {
+            (1 + modulus - r % modulus) % modulus
+        };
+        mul_mod(product, factor, modulus)
+    })
+}
+
+fn mul_mod(a: u64, b: u64, modulus: u64) -> u64 {
+    (u128::from(a) * u128::from(b) % u128::from(modulus)) as u64
+}
+
+/// Lays the graph out as a layered circuit, see the module documentation. Panics if the graph has
+/// custom nodes, which have no gates: lower them with `lower_custom_ops` first.
+pub fn export(builder: &Builder) -> LayeredCircuit {
+    try_export(builder).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Like `export`, but returns `BuildError::Unlowered` instead of panicking if the graph has
+/// custom nodes.
+pub fn try_export(builder: &Builder) -> Result<LayeredCircuit, BuildError> {
+    builder.check_lowered()?;
+    let graph = &builder.graph;
+    // The gate computing each wire, as whether it multiplies and its two operands.
+    let gate = |wire: Wire| -> Option<(bool, Wire, Wire)> {
+        match wire {
+            Wire::Zero => None,
+            Wire::Product(node) => {
+                let [a, b, _] = graph.mul_add(node)?;
+                Some((true, Wire::Node(a), Wire::Node(b)))
+            }
+            Wire::Node(node) => match graph.kind(node) {
+                Kind::Add | Kind::Mul => {
+                    let (a, b) = graph.operands(node)?;
+                    Some((graph.kind(node) == Kind::Mul, Wire::Node(a), Wire::Node(b)))
+                }
+                Kind::MulAdd => {
+                    let [_, _, c] = graph.mul_add(node)?;
+                    Some((false, Wire::Product(node), Wire::Node(c)))
+                }
+                _ => None,
+            },
+        }
+    };
+
+    // Every wire, each product just before its sum, with the depth of its gate above the inputs.
+    let mut wires = Vec::new();
+    let mut depth: HashMap<Wire, usize> = HashMap::new();
+    for node in 0..graph.len() {
+        if graph.kind(node) == Kind::MulAdd {
+            wires.push(Wire::Product(node));
+        }
+        wires.push(Wire::Node(node));
+    }
+    for &wire in &wires {
+        let level = gate(wire).map_or(0, |(_, a, b)| 1 + depth[&a].max(depth[&b]));
+        depth.insert(wire, level);
+    }
+    let top = depth.values().copied().max().unwrap_or(0).max(1);
+
+    // The highest depth each wire is needed at: the outputs at the top, and operands just below
+    // the gates reading them.
+    let mut last = depth.clone();
+    let mut read = vec![false; graph.len()];
+    for &wire in &wires {
+        if let Some((_, a, b)) = gate(wire) {
+            for operand in [a, b] {
+                let needed = last.get_mut(&operand).unwrap();
+                *needed = (*needed).max(depth[&wire] - 1);
+                if let Wire::Node(node) = operand {
+                    read[node] = true;
+                }
+            }
+        }
+    }
+    let mut outputs: Vec<usize> = builder.public.iter().copied().collect();
+    outputs.extend(builder.constraints.iter().flat_map(|&(a, b)| [a, b]));
+    outputs.extend(builder.alias_pairs().flat_map(|(a, b)| [a, b]));
+    outputs.extend(builder.checks.iter().flat_map(|check| check.nodes()));
+    outputs.extend((0..graph.len()).filter(|&node| !read[node]));
+    for node in outputs {
+        last.insert(Wire::Node(node), top);
+    }
+
+    let mut layers: Vec<Layer> = Vec::with_capacity(top + 1);
+    let mut below: HashMap<Wire, usize> = HashMap::new();
+    for level in 0..=top {
+        let present: Vec<Wire> = std::iter::once(Wire::Zero)
+            .chain(
+                wires
+                    .iter()
+                    .copied()
+                    .filter(|wire| depth[wire] <= level && level <= last[wire]),
+            )
+            .collect();
+        let mut layer = Layer {
+            vars: present.len().next_power_of_two().trailing_zeros(),
+            wires: present,
+            add: Vec::new(),
+            mul: Vec::new(),
+        };
+        if level > 0 {
+            for (z, &wire) in layer.wires.iter().enumerate().skip(1) {
+                match gate(wire).filter(|_| depth[&wire] == level) {
+                    Some((true, a, b)) => layer.mul.push([z, below[&a], below[&b]]),
+                    Some((false, a, b)) => layer.add.push([z, below[&a], below[&b]]),
+                    None => layer.add.push([z, below[&wire], 0]),
+                }
+            }
+        }
+        below = layer
+            .wires
+            .iter()
+            .enumerate()
+            .map(|(z, &wire)| (wire, z))
+            .collect();
+        layers.push(layer);
+    }
+    layers.reverse();
+    Ok(LayeredCircuit {
+        layers,
+        constraints: builder
+            .constraints
+            .iter()
+            .copied()
+            .chain(builder.alias_pairs())
+            .map(|(a, b)| (below[&Wire::Node(a)], below[&Wire::Node(b)]))
+            .collect(),
+    })
+}
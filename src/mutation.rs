@@ -0,0 +1,81 @@
+//! Mutation testing of witnesses: how well do the constraints pin down the witness?
+//!
+//! `Builder::mutation_test` corrupts one witness value at a time and checks that the constraints
+//! catch it. A value whose corruption goes undetected is unconstrained, e.g. a hint that nothing
+//! checks, or an input that no node reads. Every value is checked against the whole graph, so a
+//! run takes time quadratic in the size of the graph.
+
+use crate::error::{ConstraintError, Value};
+use crate::storage::Kind;
+use crate::Builder;
+
+/// A corrupted witness value that still satisfies every constraint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UndetectedMutation {
+    pub node: usize,
+    pub original: Value,
+    pub mutated: Value,
+}
+
+/// The result of `Builder::mutation_test`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MutationReport {
+    /// Number of witness values that were corrupted.
+    pub tested: usize,
+    pub undetected: Vec<UndetectedMutation>,
+}
+
+impl MutationReport {
+    /// Returns true if corrupting any single witness value breaks a constraint.
+    pub fn is_fully_constrained(&self) -> bool {
+        self.undetected.is_empty()
+    }
+}
+
+/// The corrupted values tried for a value: off by one, a flipped top bit and zero.
+fn mutations(value: u32) -> impl Iterator<Item = u32> {
+    [value.wrapping_add(1), value ^ 0x8000_0000, 0]
+        .into_iter()
+        .filter(move |&mutated| mutated != value)
+}
+
+impl Builder {
+    /// Corrupts every filled in value of the graph in turn, except constants, and reports the ones
+    /// whose corruption no constraint catches. The graph must satisfy its constraints to begin
+    /// with; otherwise the first violation is returned.
+    pub fn mutation_test(&self) -> Result<MutationReport, ConstraintError> {
+        self.check()?;
+        let mut graph = self.clone();
+        // The checks below are not evaluations of the circuit, so keep them out of the metrics and
+        // away from observers.
+        graph.metrics = None;
+        graph.observers.clear();
+        let mut report = MutationReport {
+            tested: 0,
+            undetected: Vec::new(),
+        };
+        for node in 0..self.graph.len() {
+            let Some(value) = self.graph.output(node) else {
+                continue;
+            };
+            if self.graph.kind(node) == Kind::Constant {
+                continue;
+            }
+            report.tested += 1;
+            for mutated in mutations(value) {
+                graph.graph.set_output(node, Some(mutated));
+                let detected = !graph.is_satisfied();
+                graph.graph.set_output(node, Some(value));
+                if !detected {
+                    report.undetected.push(UndetectedMutation {
+                        node,
+                        original: self.redact(node, value),
+                        mutated: self.redact(node, mutated),
+                    });
+                    break;
+                }
+            }
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,131 @@
+//! Neural network layers over `fixed::Fixed` values, for proving inference (zkML).
+//!
+//! Weights and biases are known when the circuit is built, so they are quantized into constants:
+//! weights to multiples of 2^-FRAC_BITS, and biases to multiples of 2^-(2 * FRAC_BITS), the scale of
+//! the products. A dense layer accumulates its products and bias at that scale and truncates each
+//! output once, rather than once per product as `fixed_mul` would. `relu` compares each value to
+//! 0 and selects it or 0, and `avg_pool` sums each window and scales it down.
+//!
+//! ```
+//! use my_graph_lib::fixed::Fixed;
+//! use my_graph_lib::Builder;
+//!
+//! let mut b = Builder::new();
+//! let x: Vec<Fixed> = (0..2).map(|_| b.fixed_input()).collect();
+//! // Two outputs: x0 - x1 + 1 and 0.5 * x0 + 2 * x1.
+//! let hidden = b.dense(&x, &[1.0, -1.0, 0.5, 2.0], &[1.0, 0.0]);
+//! let y = b.relu(&hidden);
+//! let mut inputs = Fixed::assign(x[0], 1.5).to_vec();
+//! inputs.extend(Fixed::assign(x[1], 3.0));
+//! b.fill_inputs(&inputs);
+//! assert_eq!(b.fixed_value(y[0]), Some(0.0));
+//! assert_eq!(b.fixed_value(y[1]), Some(6.75));
+//! assert!(b.is_satisfied());
+//! ```
+
+use crate::fixed::{Fixed, FRAC_BITS, MAG_BITS};
+use crate::Builder;
+
+impl Builder {
+    /// A dense (fully connected) layer: output `j` is `sum_k weights[j * x.len() + k] * x[k] +
+    /// bias[j]`, with `weights` in row-major order, one row per output.
+    ///
+    /// Inputs are normalized, so their magnitude is below 2^(MAG_BITS - FRAC_BITS), and the
+    /// accumulators must fit in a u32: panics unless the absolute values of each row of weights
+    /// sum to less than 2^(32 - MAG_BITS - FRAC_BITS) = 256, with the bias counting for
+    /// 2^-FRAC_BITS of its absolute value, or if `weights` doesn't have `x.len() * bias.len()`
+    /// elements.
+    pub fn dense(&mut self, x: &[Fixed], weights: &[f64], bias: &[f64]) -> Vec<Fixed> {
+        assert_eq!(
+            weights.len(),
+            x.len() * bias.len(),
+            "a dense layer from {} inputs to {} outputs needs {} weights",
+            x.len(),
+            bias.len(),
+            x.len() * bias.len()
+        );
+        let x: Vec<Fixed> = x.iter().map(|&x| self.fixed_normalize(x)).collect();
+        let mut outputs = Vec::with_capacity(bias.len());
+        for (j, &b) in bias.iter().enumerate() {
+            let row: Vec<(u32, u32)> = weights[j * x.len()..(j + 1) * x.len()]
+                .iter()
+                .map(|&w| Fixed::encode(w))
+                .collect();
+            let (b_pos, b_neg) = Fixed::encode(b * f64::from(1u32 << FRAC_BITS));
+            let bound = row
+                .iter()
+                .map(|&(pos, neg)| u64::from(pos.max(neg)) << MAG_BITS)
+                .sum::<u64>()
+                + u64::from(b_pos.max(b_neg));
+            assert!(
+                bound >> 32 == 0,
+                "row {} of the dense layer is out of range: its weights are too large",
+                j
+            );
+            let (mut pos, mut neg) = (self.constant(b_pos), self.constant(b_neg));
+            for (&x, &(w_pos, w_neg)) in x.iter().zip(&row) {
+                if (w_pos, w_neg) == (0, 0) {
+                    continue;
+                }
+                let w = self.constant(w_pos.max(w_neg));
+                let (x_pos, x_neg) = x.parts();
+                let (x_pos, x_neg) = if w_neg > 0 {
+                    (x_neg, x_pos)
+                } else {
+                    (x_pos, x_neg)
+                };
+                pos = self.mul_add(x_pos, w, pos);
+                neg = self.mul_add(x_neg, w, neg);
+            }
+            let (pos, neg) = (self.fixed_truncate(pos), self.fixed_truncate(neg));
+            outputs.push(Fixed::from_parts(pos, neg));
+        }
+        outputs
+    }
+
+    /// `max(x, 0)` of each value, see `fixed_relu`.
+    pub fn relu(&mut self, x: &[Fixed]) -> Vec<Fixed> {
+        x.iter().map(|&x| self.fixed_relu(x)).collect()
+    }
+
+    /// Average pooling of an image of `x.len() / width` rows of `width` values, in row-major order,
+    /// over non-overlapping `window` by `window` squares. Returns the averages in row-major order.
+    ///
+    /// Each sum is normalized, so it must be in range, then multiplied by 2^(2 * FRAC_BITS) / n
+    /// for windows of n values, rounded, and truncated: averages over windows of a power of two
+    /// values are exact up to the rounding down of fixed-point values. Panics unless the image
+    /// divides into windows.
+    pub fn avg_pool(&mut self, x: &[Fixed], width: usize, window: usize) -> Vec<Fixed> {
+        assert!(
+            window > 0 && width.is_multiple_of(window) && x.len().is_multiple_of(width * window),
+            "an image of {} values, {} wide, doesn't divide into {}x{} windows",
+            x.len(),
+            width,
+            window,
+            window
+        );
+        let height = x.len() / width;
+        let n = window * window;
+        let scale = f64::from(1u32 << (2 * FRAC_BITS)) / n as f64;
+        let factor = self.constant(scale.round() as u32);
+        let mut outputs = Vec::with_capacity(x.len() / n);
+        for row in (0..height).step_by(window) {
+            for column in (0..width).step_by(window) {
+                let mut sum = x[row * width + column];
+                for i in 1..n {
+                    let value = x[(row + i / window) * width + column + i % window];
+                    sum = self.fixed_add(sum, value);
+                }
+                let (pos, neg) = self.fixed_normalize(sum).parts();
+                let mut parts = [pos, neg];
+                for part in &mut parts {
+                    let scaled = self.mul(*part, factor);
+                    let truncated = self.fixed_truncate(scaled);
+                    *part = self.fixed_truncate(truncated);
+                }
+                outputs.push(Fixed::from_parts(parts[0], parts[1]));
+            }
+        }
+        outputs
+    }
+}
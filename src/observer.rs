@@ -0,0 +1,106 @@
+//! Hooks for tools that watch graphs being built and evaluated, such as loggers, live
+//! visualizers or coverage trackers, without changes to the crate.
+//!
+//! An `Observer` is registered with `Builder::add_observer` and called synchronously, with the
+//! builder, after every node is added, every equality constraint or check is recorded and every
+//! node gets its value during an evaluation. Like `Metrics`, observers are shared through an
+//! `Arc`, so clones of a builder keep reporting to them, and they take `&self`, so they keep their
+//! state behind atomics or a lock.
+//!
+//! ```
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use std::sync::Arc;
+//! use my_graph_lib::observer::Observer;
+//! use my_graph_lib::{Builder, NodeId};
+//!
+//! #[derive(Default)]
+//! struct Coverage(AtomicUsize);
+//!
+//! impl Observer for Coverage {
+//!     fn on_node_evaluated(&self, _builder: &Builder, _node: NodeId, _value: u32) {
+//!         self.0.fetch_add(1, Ordering::Relaxed);
+//!     }
+//! }
+//!
+//! let coverage = Arc::new(Coverage::default());
+//! let mut builder = Builder::new();
+//! builder.add_observer(coverage.clone());
+//! let x = builder.init();
+//! let y = builder.mul(x, x);
+//! builder.fill_nodes(x, 3);
+//! assert_eq!(builder.output(y), Some(9));
+//! assert_eq!(coverage.0.load(Ordering::Relaxed), 2);
+//! ```
+//!
+//! Observers see the values of secret nodes, like any code holding the builder. Nodes whose value
+//! comes from an `EvalCache` are not evaluated again, so they are not reported, and neither are
+//! evaluations that don't fill in the builder, such as streaming, the GPU evaluator or the copies
+//! that passes and mutation testing evaluate.
+
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+
+use crate::{Builder, NodeId};
+
+/// A constraint reported by `Observer::on_constraint_added`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Constraint {
+    /// An equality constraint, with its index in the order constraints were recorded.
+    Equality(usize),
+    /// A check, with its index in `Builder::checks`.
+    Check(usize),
+}
+
+/// Callbacks on the events of a builder, see the module documentation. Every method does nothing
+/// by default. Observers are `RefUnwindSafe` so that builders stay so, which locks and atomics are.
+pub trait Observer: Send + Sync + RefUnwindSafe {
+    /// Called after `node` is added to the graph.
+    fn on_node_created(&self, _builder: &Builder, _node: NodeId) {}
+
+    /// Called after an equality constraint or a check is recorded.
+    fn on_constraint_added(&self, _builder: &Builder, _constraint: Constraint) {}
+
+    /// Called when an evaluation reaches `node` and it has a value, inputs and constants included,
+    /// in the order nodes are evaluated.
+    fn on_node_evaluated(&self, _builder: &Builder, _node: NodeId, _value: u32) {}
+}
+
+impl Builder {
+    /// Calls `observer` on the events of this builder and of its clones, after the observers
+    /// added before it.
+    pub fn add_observer(&mut self, observer: Arc<dyn Observer>) {
+        self.observers.push(observer);
+    }
+
+    /// Reports that `node` was added, to the metrics and the observers.
+    pub(crate) fn report_node(&self, node: usize) {
+        if let Some(metrics) = &self.metrics {
+            metrics.node_created();
+        }
+        for observer in &self.observers {
+            observer.on_node_created(self, self.id(node));
+        }
+    }
+
+    /// Reports that `constraint` was recorded, to the metrics and the observers.
+    pub(crate) fn report_constraint(&self, constraint: Constraint) {
+        if let Some(metrics) = &self.metrics {
+            metrics.constraint_added();
+        }
+        for observer in &self.observers {
+            observer.on_constraint_added(self, constraint);
+        }
+    }
+
+    /// Reports that `node` got its value during an evaluation.
+    pub(crate) fn report_evaluated(&self, node: usize) {
+        if self.observers.is_empty() {
+            return;
+        }
+        if let Some(value) = self.graph.output(node) {
+            for observer in &self.observers {
+                observer.on_node_evaluated(self, self.id(node), value);
+            }
+        }
+    }
+}
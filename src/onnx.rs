@@ -0,0 +1,546 @@
+//! Import of ONNX models, built with `--features onnx`, to express inference as constraints.
+//!
+//! Only a restricted subset of ONNX is read: `MatMul` of vectors and matrices, `Add` and `Mul`
+//! with broadcasting, and `Relu`, on `FLOAT` tensors with static shapes. That covers dense layers
+//! with ReLU activations. Graph inputs become `fixed::Fixed` inputs, initializers (the weights)
+//! are folded into the graph as constants, and each operator is lowered to the gadgets of
+//! `fixed`: a `Relu` becomes a comparison and a select. Graph outputs are normalized and their
+//! parts marked public.
+//!
+//! Values are fixed-point, so weights are rounded to multiples of 2^-FRAC_BITS, products are
+//! truncated, and every value must stay in the range of `fixed`. The results approximate those of
+//! floating-point inference.
+//!
+//! The message types below are the parts of `onnx.proto` the importer reads, as prost messages;
+//! other fields are skipped when decoding.
+
+use prost::Message;
+use thiserror::Error;
+
+use crate::fixed::{Fixed, MAG_BITS};
+use crate::{Builder, NodeId};
+
+/// `onnx.TensorProto.DataType.FLOAT`, the only element type the importer reads.
+pub const FLOAT: i32 = 1;
+
+/// `onnx.ModelProto`.
+#[derive(Clone, PartialEq, Message)]
+pub struct ModelProto {
+    #[prost(int64, tag = "1")]
+    pub ir_version: i64,
+    #[prost(string, tag = "2")]
+    pub producer_name: String,
+    #[prost(message, optional, tag = "7")]
+    pub graph: Option<GraphProto>,
+}
+
+/// `onnx.GraphProto`: nodes in topological order, over the graph inputs and initializers.
+#[derive(Clone, PartialEq, Message)]
+pub struct GraphProto {
+    #[prost(message, repeated, tag = "1")]
+    pub node: Vec<NodeProto>,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(message, repeated, tag = "5")]
+    pub initializer: Vec<TensorProto>,
+    #[prost(message, repeated, tag = "11")]
+    pub input: Vec<ValueInfoProto>,
+    #[prost(message, repeated, tag = "12")]
+    pub output: Vec<ValueInfoProto>,
+}
+
+/// `onnx.NodeProto`: an operator, reading and writing tensors by name.
+#[derive(Clone, PartialEq, Message)]
+pub struct NodeProto {
+    #[prost(string, repeated, tag = "1")]
+    pub input: Vec<String>,
+    #[prost(string, repeated, tag = "2")]
+    pub output: Vec<String>,
+    #[prost(string, tag = "3")]
+    pub name: String,
+    #[prost(string, tag = "4")]
+    pub op_type: String,
+    #[prost(string, tag = "7")]
+    pub domain: String,
+}
+
+/// `onnx.TensorProto`, with its data in `float_data` or little-endian in `raw_data`.
+#[derive(Clone, PartialEq, Message)]
+pub struct TensorProto {
+    #[prost(int64, repeated, packed = "false", tag = "1")]
+    pub dims: Vec<i64>,
+    #[prost(int32, tag = "2")]
+    pub data_type: i32,
+    #[prost(float, repeated, tag = "4")]
+    pub float_data: Vec<f32>,
+    #[prost(string, tag = "8")]
+    pub name: String,
+    #[prost(bytes = "vec", tag = "9")]
+    pub raw_data: Vec<u8>,
+}
+
+/// `onnx.ValueInfoProto`: the name and type of a graph input or output.
+#[derive(Clone, PartialEq, Message)]
+pub struct ValueInfoProto {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, optional, tag = "2")]
+    pub r#type: Option<TypeProto>,
+}
+
+/// `onnx.TypeProto`. Its `value` oneof is read as its only variant the importer supports.
+#[derive(Clone, PartialEq, Message)]
+pub struct TypeProto {
+    #[prost(message, optional, tag = "1")]
+    pub tensor_type: Option<TensorTypeProto>,
+}
+
+/// `onnx.TypeProto.Tensor`.
+#[derive(Clone, PartialEq, Message)]
+pub struct TensorTypeProto {
+    #[prost(int32, tag = "1")]
+    pub elem_type: i32,
+    #[prost(message, optional, tag = "2")]
+    pub shape: Option<TensorShapeProto>,
+}
+
+/// `onnx.TensorShapeProto`.
+#[derive(Clone, PartialEq, Message)]
+pub struct TensorShapeProto {
+    #[prost(message, repeated, tag = "1")]
+    pub dim: Vec<Dimension>,
+}
+
+/// `onnx.TensorShapeProto.Dimension`: a size, or the name of a symbolic size, which the importer
+/// rejects.
+#[derive(Clone, PartialEq, Message)]
+pub struct Dimension {
+    #[prost(int64, optional, tag = "1")]
+    pub dim_value: Option<i64>,
+    #[prost(string, optional, tag = "2")]
+    pub dim_param: Option<String>,
+}
+
+/// A model that could not be imported.
+#[derive(Debug, Error)]
+pub enum OnnxError {
+    #[error("invalid ONNX model: {0}")]
+    Decode(#[from] prost::DecodeError),
+    /// The model uses an operator, type or shape outside of the supported subset.
+    #[error("unsupported ONNX model: {0}")]
+    Unsupported(String),
+    /// The model is malformed, e.g. a node reads a tensor that doesn't exist.
+    #[error("invalid ONNX model: {0}")]
+    Invalid(String),
+}
+
+/// A graph input or output of an imported model, with one value per element, in row-major
+/// order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnnxTensor {
+    pub name: String,
+    pub shape: Vec<usize>,
+    pub values: Vec<Fixed>,
+}
+
+impl OnnxTensor {
+    /// Input assignments setting the elements of this input to `values`, for `fill_inputs`.
+    /// Panics if `values` doesn't have one value per element.
+    pub fn assign(&self, values: &[f64]) -> Vec<(NodeId, u32)> {
+        assert_eq!(
+            values.len(),
+            self.values.len(),
+            "input `{}` has {} elements",
+            self.name,
+            self.values.len()
+        );
+        self.values
+            .iter()
+            .zip(values)
+            .flat_map(|(&x, &value)| Fixed::assign(x, value))
+            .collect()
+    }
+
+    /// The elements of this tensor, once they are filled in.
+    pub fn read(&self, builder: &Builder) -> Option<Vec<f64>> {
+        self.values
+            .iter()
+            .map(|&x| builder.fixed_value(x))
+            .collect()
+    }
+}
+
+/// The graph inputs and outputs of a model imported by `Builder::import_onnx`, in the order of the
+/// model. Inputs that are also initializers are constants, and aren't listed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnnxModel {
+    pub inputs: Vec<OnnxTensor>,
+    pub outputs: Vec<OnnxTensor>,
+}
+
+impl OnnxModel {
+    /// Input assignments for all inputs, one slice of values per input.
+    pub fn assign(&self, inputs: &[&[f64]]) -> Vec<(NodeId, u32)> {
+        assert_eq!(inputs.len(), self.inputs.len(), "wrong number of inputs");
+        self.inputs
+            .iter()
+            .zip(inputs)
+            .flat_map(|(input, values)| input.assign(values))
+            .collect()
+    }
+}
+
+/// An element of a tensor during lowering: constants are folded, and only become nodes when they
+/// meet a variable.
+#[derive(Debug, Clone, Copy)]
+enum Element {
+    Constant(f64),
+    Variable(Fixed),
+}
+
+#[derive(Debug, Clone)]
+struct Tensor {
+    shape: Vec<usize>,
+    elements: Vec<Element>,
+}
+
+impl Builder {
+    /// Decodes an ONNX model and lowers it into this builder.
+    /// If the model can't be imported, the nodes added before the error was found are kept.
+    pub fn import_onnx(&mut self, bytes: &[u8]) -> Result<OnnxModel, OnnxError> {
+        let model = ModelProto::decode(bytes)?;
+        let graph = model
+            .graph
+            .ok_or_else(|| OnnxError::Invalid("the model has no graph".to_string()))?;
+        self.import_onnx_graph(&graph)
+    }
+
+    /// Lowers an ONNX graph into this builder.
+    pub fn import_onnx_graph(&mut self, graph: &GraphProto) -> Result<OnnxModel, OnnxError> {
+        let mut tensors = std::collections::HashMap::new();
+        for initializer in &graph.initializer {
+            tensors.insert(initializer.name.clone(), initializer_tensor(initializer)?);
+        }
+        let mut inputs = Vec::new();
+        for input in &graph.input {
+            if tensors.contains_key(&input.name) {
+                continue;
+            }
+            let shape = value_shape(input)?;
+            let values: Vec<Fixed> = (0..shape.iter().product())
+                .map(|_| self.fixed_input())
+                .collect();
+            let elements = values.iter().map(|&x| Element::Variable(x)).collect();
+            tensors.insert(
+                input.name.clone(),
+                Tensor {
+                    shape: shape.clone(),
+                    elements,
+                },
+            );
+            inputs.push(OnnxTensor {
+                name: input.name.clone(),
+                shape,
+                values,
+            });
+        }
+        for node in &graph.node {
+            if !node.domain.is_empty() && node.domain != "ai.onnx" {
+                return Err(OnnxError::Unsupported(format!(
+                    "operator `{}` of domain `{}`",
+                    node.op_type, node.domain
+                )));
+            }
+            let operands = node
+                .input
+                .iter()
+                .map(|name| {
+                    tensors.get(name).ok_or_else(|| {
+                        OnnxError::Invalid(format!(
+                            "node `{}` reads tensor `{}`, which is not computed before it",
+                            node.name, name
+                        ))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let result = match (node.op_type.as_str(), operands.as_slice()) {
+                ("MatMul", [a, b]) => self.onnx_matmul(a, b)?,
+                ("Add", [a, b]) => self.onnx_elementwise(a, b, Builder::onnx_add)?,
+                ("Mul", [a, b]) => self.onnx_elementwise(a, b, Builder::onnx_mul)?,
+                ("Relu", [a]) => Tensor {
+                    shape: a.shape.clone(),
+                    elements: a.elements.iter().map(|&x| self.onnx_relu(x)).collect(),
+                },
+                ("MatMul" | "Add" | "Mul" | "Relu", _) => {
+                    return Err(OnnxError::Invalid(format!(
+                        "node `{}` has {} inputs",
+                        node.name,
+                        operands.len()
+                    )))
+                }
+                (op, _) => return Err(OnnxError::Unsupported(format!("operator `{}`", op))),
+            };
+            let [output] = node.output.as_slice() else {
+                return Err(OnnxError::Invalid(format!(
+                    "node `{}` has {} outputs",
+                    node.name,
+                    node.output.len()
+                )));
+            };
+            tensors.insert(output.clone(), result);
+        }
+        let mut outputs = Vec::new();
+        for output in &graph.output {
+            let tensor = tensors.get(&output.name).ok_or_else(|| {
+                OnnxError::Invalid(format!("output `{}` is never computed", output.name))
+            })?;
+            let mut values = Vec::new();
+            for &element in &tensor.elements {
+                let x = match element {
+                    Element::Constant(c) => self.onnx_constant(c)?,
+                    Element::Variable(x) => self.fixed_normalize(x),
+                };
+                let (pos, neg) = x.parts();
+                self.mark_public(pos);
+                self.mark_public(neg);
+                values.push(x);
+            }
+            outputs.push(OnnxTensor {
+                name: output.name.clone(),
+                shape: tensor.shape.clone(),
+                values,
+            });
+        }
+        Ok(OnnxModel { inputs, outputs })
+    }
+
+    /// `MatMul` of matrices, or of vectors treated as a row (left) or a column (right).
+    fn onnx_matmul(&mut self, a: &Tensor, b: &Tensor) -> Result<Tensor, OnnxError> {
+        let (m, k) = match *a.shape.as_slice() {
+            [k] => (None, k),
+            [m, k] => (Some(m), k),
+            _ => return Err(unsupported_rank("MatMul", &a.shape)),
+        };
+        let (inner, n) = match *b.shape.as_slice() {
+            [k] => (k, None),
+            [k, n] => (k, Some(n)),
+            _ => return Err(unsupported_rank("MatMul", &b.shape)),
+        };
+        if k != inner {
+            return Err(OnnxError::Invalid(format!(
+                "MatMul of shapes {:?} and {:?}",
+                a.shape, b.shape
+            )));
+        }
+        let (rows, columns) = (m.unwrap_or(1), n.unwrap_or(1));
+        let mut elements = Vec::with_capacity(rows * columns);
+        for i in 0..rows {
+            for j in 0..columns {
+                let mut sum = Element::Constant(0.0);
+                for l in 0..k {
+                    let product =
+                        self.onnx_mul(a.elements[i * k + l], b.elements[l * columns + j])?;
+                    sum = self.onnx_add(sum, product)?;
+                }
+                elements.push(sum);
+            }
+        }
+        Ok(Tensor {
+            shape: m.into_iter().chain(n).collect(),
+            elements,
+        })
+    }
+
+    /// Applies `op` to the elements of `a` and `b`, broadcast to a common shape.
+    fn onnx_elementwise(
+        &mut self,
+        a: &Tensor,
+        b: &Tensor,
+        op: fn(&mut Builder, Element, Element) -> Result<Element, OnnxError>,
+    ) -> Result<Tensor, OnnxError> {
+        let shape = broadcast(&a.shape, &b.shape).ok_or_else(|| {
+            OnnxError::Invalid(format!(
+                "shapes {:?} and {:?} can't be broadcast together",
+                a.shape, b.shape
+            ))
+        })?;
+        let elements = (0..shape.iter().product())
+            .map(|i| {
+                let x = a.elements[source(&a.shape, &shape, i)];
+                let y = b.elements[source(&b.shape, &shape, i)];
+                op(self, x, y)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Tensor { shape, elements })
+    }
+
+    fn onnx_add(&mut self, a: Element, b: Element) -> Result<Element, OnnxError> {
+        Ok(match (a, b) {
+            (Element::Constant(a), Element::Constant(b)) => Element::Constant(a + b),
+            (Element::Variable(x), Element::Constant(c))
+            | (Element::Constant(c), Element::Variable(x)) => {
+                if c == 0.0 {
+                    Element::Variable(x)
+                } else {
+                    let c = self.onnx_constant(c)?;
+                    Element::Variable(self.fixed_add(x, c))
+                }
+            }
+            (Element::Variable(x), Element::Variable(y)) => Element::Variable(self.fixed_add(x, y)),
+        })
+    }
+
+    fn onnx_mul(&mut self, a: Element, b: Element) -> Result<Element, OnnxError> {
+        Ok(match (a, b) {
+            (Element::Constant(a), Element::Constant(b)) => Element::Constant(a * b),
+            (Element::Variable(x), Element::Constant(c))
+            | (Element::Constant(c), Element::Variable(x)) => {
+                if Fixed::encode(c) == (0, 0) {
+                    Element::Constant(0.0)
+                } else {
+                    check_range(c)?;
+                    Element::Variable(self.fixed_scale(x, c))
+                }
+            }
+            (Element::Variable(x), Element::Variable(y)) => Element::Variable(self.fixed_mul(x, y)),
+        })
+    }
+
+    fn onnx_relu(&mut self, a: Element) -> Element {
+        match a {
+            Element::Constant(c) => Element::Constant(c.max(0.0)),
+            Element::Variable(x) => Element::Variable(self.fixed_relu(x)),
+        }
+    }
+
+    fn onnx_constant(&mut self, c: f64) -> Result<Fixed, OnnxError> {
+        check_range(c)?;
+        Ok(self.fixed_constant(c))
+    }
+}
+
+fn check_range(c: f64) -> Result<(), OnnxError> {
+    let (pos, neg) = Fixed::encode(c);
+    if pos.max(neg) >> MAG_BITS != 0 {
+        return Err(OnnxError::Unsupported(format!(
+            "constant {} is out of the fixed-point range",
+            c
+        )));
+    }
+    Ok(())
+}
+
+fn unsupported_rank(op: &str, shape: &[usize]) -> OnnxError {
+    OnnxError::Unsupported(format!("{} of a tensor of shape {:?}", op, shape))
+}
+
+/// The shape of a graph input, which must be a `FLOAT` tensor of known size.
+fn value_shape(value: &ValueInfoProto) -> Result<Vec<usize>, OnnxError> {
+    let tensor = value
+        .r#type
+        .as_ref()
+        .and_then(|t| t.tensor_type.as_ref())
+        .ok_or_else(|| OnnxError::Unsupported(format!("input `{}` is not a tensor", value.name)))?;
+    if tensor.elem_type != FLOAT {
+        return Err(OnnxError::Unsupported(format!(
+            "input `{}` has element type {}",
+            value.name, tensor.elem_type
+        )));
+    }
+    let dims = tensor
+        .shape
+        .as_ref()
+        .map_or(&[][..], |shape| &shape.dim[..]);
+    dims.iter()
+        .map(|dim| match dim.dim_value {
+            Some(size) if size > 0 => Ok(size as usize),
+            _ => Err(OnnxError::Unsupported(format!(
+                "input `{}` has a dimension of unknown size",
+                value.name
+            ))),
+        })
+        .collect()
+}
+
+fn initializer_tensor(tensor: &TensorProto) -> Result<Tensor, OnnxError> {
+    if tensor.data_type != FLOAT {
+        return Err(OnnxError::Unsupported(format!(
+            "initializer `{}` has element type {}",
+            tensor.name, tensor.data_type
+        )));
+    }
+    let shape = tensor
+        .dims
+        .iter()
+        .map(|&size| usize::try_from(size))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| {
+            OnnxError::Invalid(format!(
+                "initializer `{}` has a negative dimension",
+                tensor.name
+            ))
+        })?;
+    let data: Vec<f32> = if tensor.raw_data.is_empty() {
+        tensor.float_data.clone()
+    } else {
+        tensor
+            .raw_data
+            .chunks(4)
+            .map(|chunk| chunk.try_into().map(f32::from_le_bytes))
+            .collect::<Result<_, _>>()
+            .map_err(|_| {
+                OnnxError::Invalid(format!(
+                    "initializer `{}` has a partial element",
+                    tensor.name
+                ))
+            })?
+    };
+    if data.len() != shape.iter().product::<usize>() {
+        return Err(OnnxError::Invalid(format!(
+            "initializer `{}` of shape {:?} has {} elements",
+            tensor.name,
+            shape,
+            data.len()
+        )));
+    }
+    Ok(Tensor {
+        shape,
+        elements: data
+            .into_iter()
+            .map(|x| Element::Constant(f64::from(x)))
+            .collect(),
+    })
+}
+
+/// The shape `a` and `b` broadcast to, numpy style: aligned on the right, with dimensions of
+/// size 1 stretched.
+fn broadcast(a: &[usize], b: &[usize]) -> Option<Vec<usize>> {
+    let rank = a.len().max(b.len());
+    (0..rank)
+        .map(|i| match (dim(a, rank, i), dim(b, rank, i)) {
+            (x, y) if x == y || y == 1 => Some(x),
+            (1, y) => Some(y),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Dimension `i` of `shape`, aligned on the right to `rank` dimensions.
+fn dim(shape: &[usize], rank: usize, i: usize) -> usize {
+    (i + shape.len()).checked_sub(rank).map_or(1, |j| shape[j])
+}
+
+/// The index in a tensor of `shape` of the element broadcast to index `flat` of `out`.
+fn source(shape: &[usize], out: &[usize], flat: usize) -> usize {
+    let (mut rest, mut index, mut stride) = (flat, 0, 1);
+    for i in (0..out.len()).rev() {
+        let coordinate = rest % out[i];
+        rest /= out[i];
+        let size = dim(shape, out.len(), i);
+        if size != 1 {
+            index += coordinate * stride;
+        }
+        stride *= size;
+    }
+    index
+}
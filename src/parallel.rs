@@ -0,0 +1,65 @@
+//! Constraint checking spread over every core with rayon.
+//!
+//! Each node, equality constraint and check is verified on its own, so they are split between
+//! rayon's worker threads. Results are reported in the order `Builder::check` uses, nodes first,
+//! then equality constraints, then checks, so the report doesn't depend on how the work was
+//! scheduled.
+
+use rayon::prelude::*;
+
+use crate::error::ConstraintError;
+use crate::Builder;
+
+impl Builder {
+    /// Returns every constraint that does not hold, in the order `check` looks for them.
+    /// Constraints with the `Warning` severity are left out, as in `check`.
+    pub fn par_violations(&self) -> Vec<ConstraintError> {
+        let violations: Vec<ConstraintError> = self.par_results().filter_map(Result::err).collect();
+        if let Some(metrics) = &self.metrics {
+            metrics.checked(violations.is_empty());
+        }
+        violations
+    }
+
+    /// Like `check`, but verifies the constraints in parallel. The violation returned is the same
+    /// one `check` returns.
+    pub fn par_check(&self) -> Result<(), ConstraintError> {
+        let violation = self.par_results().find_map_first(Result::err);
+        if let Some(metrics) = &self.metrics {
+            metrics.checked(violation.is_none());
+        }
+        match violation {
+            Some(violation) => Err(violation),
+            None => Ok(()),
+        }
+    }
+
+    /// Like `check_constraints`, but verifies the constraints in parallel.
+    pub fn par_check_constraints(&self) -> bool {
+        if let Err(violation) = self.par_check() {
+            panic!("{}", self.explain(&violation));
+        }
+        self.report_warnings();
+        true
+    }
+
+    fn par_results(&self) -> impl ParallelIterator<Item = Result<(), ConstraintError>> + '_ {
+        let nodes = (0..self.graph.len())
+            .into_par_iter()
+            .map(|node| self.check_node(node));
+        let constraints = self
+            .constraints
+            .par_iter()
+            .enumerate()
+            .filter(|(i, _)| !self.soft_constraints.contains(i))
+            .map(|(i, &(a, b))| self.check_equality(i, a, b));
+        let checks = (0..self.checks.len())
+            .into_par_iter()
+            .map(|check| self.verify_check(check));
+        let aliases = self
+            .aliases
+            .par_iter()
+            .map(|(&node, _)| self.check_alias(node));
+        nodes.chain(constraints).chain(checks).chain(aliases)
+    }
+}
@@ -0,0 +1,377 @@
+//! A small textual language for building computational graphs.
+//!
+//! A program is a list of statements separated by `;`:
+//!
+//! ```text
+//! y = (x + 7)^2;
+//! assert y == hint(sqrt, x + 7)^2
+//! ```
+//!
+//! - `name = expr` evaluates `expr` into the graph and names the resulting node.
+//! - `assert expr == expr` records an equality constraint (checked by `check_constraints`).
+//! - Names that are used before being assigned become input nodes.
+//! - `hint(f, expr)` creates a hint node whose value is computed by the registered hint function `f`.
+//! - `^` takes a constant exponent and is expanded by square-and-multiply, into at most two
+//!   multiplications per bit of the exponent.
+//! - Parentheses and hints nest at most `MAX_NESTING` deep, so that hostile programs fail to
+//!   parse instead of overflowing the stack.
+//!
+//! Builder errors, such as a limit set with `Builder::set_limits` being exceeded, are returned as
+//! a `ParseError` at the token being parsed.
+//!
+//! Every node and constraint records the location of the statement that created it, which
+//! `Builder::explain` shows when a constraint does not hold.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::diagnostics::Location;
+use crate::error::BuildError;
+use crate::{Builder, NodeId};
+
+/// How deep parentheses and hints may nest.
+pub const MAX_NESTING: usize = 256;
+
+/// The result of parsing a program: the graph plus the node of every named variable.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub builder: Builder,
+    pub variables: HashMap<String, NodeId>,
+    pub inputs: Vec<String>, // Names of the input nodes, in the order they first appear.
+}
+
+impl Program {
+    /// Looks up the node of a named variable.
+    pub fn var(&self, name: &str) -> Option<NodeId> {
+        self.variables.get(name).copied()
+    }
+}
+
+/// An error produced while parsing a program. `position` is a byte offset into the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses a program into a new builder.
+pub fn parse(src: &str) -> Result<Program, ParseError> {
+    parse_with(Builder::new(), src)
+}
+
+/// Parses a program into an existing builder, e.g. one with extra hint functions registered.
+pub fn parse_with(builder: Builder, src: &str) -> Result<Program, ParseError> {
+    let tokens = tokenize(src)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        src,
+        nesting: 0,
+        program: Program {
+            builder,
+            variables: HashMap::new(),
+            inputs: Vec::new(),
+        },
+    };
+    parser.program()?;
+    Ok(parser.program)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(u32),
+    Plus,
+    Star,
+    Caret,
+    LParen,
+    RParen,
+    Comma,
+    Assign,
+    EqEq,
+    Semi,
+}
+
+fn tokenize(src: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+        let token = match c {
+            b' ' | b'\t' | b'\n' | b'\r' => {
+                i += 1;
+                continue;
+            }
+            b'+' => Token::Plus,
+            b'*' => Token::Star,
+            b'^' => Token::Caret,
+            b'(' => Token::LParen,
+            b')' => Token::RParen,
+            b',' => Token::Comma,
+            b';' => Token::Semi,
+            b'=' if bytes.get(i + 1) == Some(&b'=') => {
+                i += 1;
+                Token::EqEq
+            }
+            b'=' => Token::Assign,
+            b'0'..=b'9' => {
+                while i + 1 < bytes.len() && bytes[i + 1].is_ascii_digit() {
+                    i += 1;
+                }
+                let value = src[start..=i].parse().map_err(|_| ParseError {
+                    message: format!("number `{}` does not fit in a u32", &src[start..=i]),
+                    position: start,
+                })?;
+                Token::Number(value)
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                while i + 1 < bytes.len()
+                    && (bytes[i + 1].is_ascii_alphanumeric() || bytes[i + 1] == b'_')
+                {
+                    i += 1;
+                }
+                Token::Ident(src[start..=i].to_string())
+            }
+            _ => {
+                return Err(ParseError {
+                    message: format!(
+                        "unexpected character `{}`",
+                        src[start..].chars().next().unwrap()
+                    ),
+                    position: start,
+                })
+            }
+        };
+        tokens.push((token, start));
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    src: &'a str,
+    nesting: usize,
+    program: Program,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.src.len(), |&(_, position)| position)
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError {
+            message: message.into(),
+            position: self.position(),
+        }
+    }
+
+    /// Adds to the graph with `add`, turning a builder error, such as an exceeded limit, into a
+    /// parse error at the current token.
+    fn build<T>(
+        &mut self,
+        add: impl FnOnce(&mut Builder) -> Result<T, BuildError>,
+    ) -> Result<T, ParseError> {
+        let result = add(&mut self.program.builder);
+        result.map_err(|err| self.error(err.to_string()))
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        if self.peek() == Some(&expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {:?}", expected)))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.error("expected an identifier")),
+        }
+    }
+
+    fn program(&mut self) -> Result<(), ParseError> {
+        while self.peek().is_some() {
+            if self.peek() != Some(&Token::Semi) {
+                self.statement()?;
+            }
+            match self.peek() {
+                Some(Token::Semi) => self.pos += 1,
+                None => {}
+                Some(_) => return Err(self.error("expected `;`")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses a statement, recording its location on every node it creates.
+    fn statement(&mut self) -> Result<(), ParseError> {
+        let location = Location::from_offset(self.src, self.position());
+        let first_node = self.program.builder.len();
+        let first_constraint = self.program.builder.constraints.len();
+        self.statement_body()?;
+        let builder = &mut self.program.builder;
+        for index in first_node..builder.len() {
+            builder.locations.insert(index, location);
+        }
+        for constraint in first_constraint..builder.constraints.len() {
+            builder.constraint_locations.insert(constraint, location);
+        }
+        Ok(())
+    }
+
+    fn statement_body(&mut self) -> Result<(), ParseError> {
+        if self.peek() == Some(&Token::Ident("assert".to_string())) {
+            self.pos += 1;
+            let a = self.expr()?;
+            self.expect(Token::EqEq)?;
+            let b = self.expr()?;
+            self.build(|builder| builder.try_enforce_equal(a, b))?;
+            return Ok(());
+        }
+        let position = self.position();
+        let name = self.ident()?;
+        self.expect(Token::Assign)?;
+        if self.program.variables.contains_key(&name) {
+            return Err(ParseError {
+                message: format!("`{}` is already defined", name),
+                position,
+            });
+        }
+        let node = self.expr()?;
+        // A name first used in its own definition would be declared an input, then rebound.
+        if self.program.variables.contains_key(&name) {
+            return Err(ParseError {
+                message: format!("`{}` is used in its own definition", name),
+                position,
+            });
+        }
+        self.program.builder.set_label(node, &name);
+        self.program.variables.insert(name, node);
+        Ok(())
+    }
+
+    fn expr(&mut self) -> Result<NodeId, ParseError> {
+        let mut node = self.term()?;
+        while self.peek() == Some(&Token::Plus) {
+            self.pos += 1;
+            let rhs = self.term()?;
+            node = self.build(|builder| builder.try_add(node, rhs))?;
+        }
+        Ok(node)
+    }
+
+    fn term(&mut self) -> Result<NodeId, ParseError> {
+        let mut node = self.power()?;
+        while self.peek() == Some(&Token::Star) {
+            self.pos += 1;
+            let rhs = self.power()?;
+            node = self.build(|builder| builder.try_mul(node, rhs))?;
+        }
+        Ok(node)
+    }
+
+    fn power(&mut self) -> Result<NodeId, ParseError> {
+        let base = self.atom()?;
+        if self.peek() != Some(&Token::Caret) {
+            return Ok(base);
+        }
+        self.pos += 1;
+        let exponent = match self.peek() {
+            Some(&Token::Number(exponent)) => exponent,
+            _ => return Err(self.error("expected a constant exponent")),
+        };
+        self.pos += 1;
+        if exponent == 0 {
+            return self.build(|builder| builder.try_constant(1));
+        }
+        // Square-and-multiply over the bits of the exponent, from the highest one down.
+        let mut node = base;
+        for bit in (0..exponent.ilog2()).rev() {
+            node = self.build(|builder| builder.try_square(node))?;
+            if exponent >> bit & 1 == 1 {
+                node = self.build(|builder| builder.try_mul(node, base))?;
+            }
+        }
+        Ok(node)
+    }
+
+    /// Parses an expression nested in parentheses or a hint.
+    fn nested_expr(&mut self) -> Result<NodeId, ParseError> {
+        if self.nesting == MAX_NESTING {
+            return Err(self.error(format!("expressions nest more than {} deep", MAX_NESTING)));
+        }
+        self.nesting += 1;
+        let node = self.expr();
+        self.nesting -= 1;
+        node
+    }
+
+    fn atom(&mut self) -> Result<NodeId, ParseError> {
+        match self.peek().cloned() {
+            Some(Token::Number(value)) => {
+                self.pos += 1;
+                self.build(|builder| builder.try_constant(value))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let node = self.nested_expr()?;
+                self.expect(Token::RParen)?;
+                Ok(node)
+            }
+            Some(Token::Ident(name)) if name == "hint" => {
+                self.pos += 1;
+                self.expect(Token::LParen)?;
+                let position = self.position();
+                let function = self.ident()?;
+                if !self.program.builder.has_hint(&function) {
+                    return Err(ParseError {
+                        message: format!("unknown hint function `{}`", function),
+                        position,
+                    });
+                }
+                self.expect(Token::Comma)?;
+                let linked = self.nested_expr()?;
+                self.expect(Token::RParen)?;
+                self.build(|builder| builder.try_hint_fn(&function, linked))
+            }
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                if let Some(&node) = self.program.variables.get(&name) {
+                    return Ok(node);
+                }
+                // First use of an unassigned name declares a new input.
+                let node = self.build(|builder| builder.try_init())?;
+                self.program.builder.set_label(node, &name);
+                self.program.variables.insert(name.clone(), node);
+                self.program.inputs.push(name);
+                Ok(node)
+            }
+            _ => Err(self.error("expected an expression")),
+        }
+    }
+}
@@ -0,0 +1,606 @@
+//! Optimization passes and a pass manager to run them in a configurable pipeline.
+//!
+//! A `Pass` turns a graph into an equivalent one. The library provides `ConstantFolding`,
+//! `Simplify`, `CommonSubexpressions` and `DeadCodeElimination`, and a `rewrite::Rewriter` is a
+//! pass too, so custom rewrite rules can be part of a pipeline:
+//!
+//! ```
+//! use my_graph_lib::passes::{CommonSubexpressions, ConstantFolding, PassManager};
+//! # let builder = my_graph_lib::Builder::new();
+//!
+//! let optimized = PassManager::new()
+//!     .pass(ConstantFolding)
+//!     .pass(CommonSubexpressions)
+//!     .run(&builder);
+//! for stats in &optimized.stats {
+//!     println!("{}", stats);
+//! }
+//! ```
+//!
+//! The manager runs its passes in order, in rounds, until a round changes nothing, and records how
+//! many changes each pass made and how long it took. Passes may remove computations whose
+//! evaluation would overflow, e.g. `x * 0`, so an optimized graph can evaluate where the
+//! original one fails.
+//!
+//! With `validate(samples)`, the result of every pass is checked against the original graph on
+//! random inputs: the public outputs must have the same values and the constraints must hold on
+//! both graphs or on neither. A pass whose result differs is undone and reported in
+//! `Optimized::rejected`, or, with `strict(true)`, fails the whole run with a `ValidationError`.
+//! Inputs are drawn with random bit widths, so small values that don't overflow are common, and
+//! inputs on which the original graph can't be evaluated are skipped. Random testing can miss a
+//! difference, so a validated pipeline is very likely, not proven, to be correct.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::error::ValidationError;
+use crate::eval::EvalOptions;
+use crate::random::Rng;
+use crate::rewrite::{value, var, Rewriter, Rule};
+use crate::storage::Kind;
+use crate::{Builder, NodeId};
+
+/// The result of running a pass on a graph.
+#[derive(Debug, Clone)]
+pub struct PassOutput {
+    pub builder: Builder,
+    /// The id in `builder` of the node standing for each node of the input graph that was kept.
+    pub ids: BTreeMap<NodeId, NodeId>,
+    /// Number of changes the pass made, such as nodes folded or removed. Zero if the graph is
+    /// unchanged.
+    pub changes: usize,
+}
+
+/// A transformation of a graph into one computing the same values and constraints.
+pub trait Pass {
+    fn name(&self) -> &str;
+
+    fn run(&self, builder: &Builder) -> PassOutput;
+}
+
+/// Builds a pass output from a rebuilt graph.
+fn output(
+    before: &Builder,
+    (builder, map): (Builder, Vec<Option<usize>>),
+    changes: usize,
+) -> PassOutput {
+    let ids = map
+        .iter()
+        .enumerate()
+        .filter_map(|(old, &new)| Some((before.id(old), builder.id(new?))))
+        .collect();
+    PassOutput {
+        builder,
+        ids,
+        changes,
+    }
+}
+
+/// Replaces additions, multiplications and mul-adds of constants with a constant, unless the
+/// result overflows.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConstantFolding;
+
+impl Pass for ConstantFolding {
+    fn name(&self) -> &str {
+        "constant-folding"
+    }
+
+    fn run(&self, builder: &Builder) -> PassOutput {
+        let mut changes = 0;
+        let rebuilt = builder.rebuild(|graph, node| {
+            let constant = |id: usize| {
+                (graph.graph.kind(id) == Kind::Constant).then(|| graph.graph.output(id).unwrap())
+            };
+            let folded = if let Some([a, b, c]) = graph.graph.mul_add(node) {
+                let [Some(a), Some(b), Some(c)] = [a, b, c].map(constant) else {
+                    return node;
+                };
+                a.checked_mul(b).and_then(|product| product.checked_add(c))
+            } else if let (Some(op), Some((a, b))) =
+                (graph.graph.op(node), graph.graph.operands(node))
+            {
+                let (Some(a), Some(b)) = (constant(a), constant(b)) else {
+                    return node;
+                };
+                op.checked_apply(a, b)
+            } else {
+                return node;
+            };
+            match folded {
+                Some(value) => {
+                    changes += 1;
+                    graph.graph.push(Kind::Constant, [0, 0], Some(value))
+                }
+                None => node,
+            }
+        });
+        output(builder, rebuilt, changes)
+    }
+}
+
+/// Removes algebraic identities: `x + 0` and `x * 1` become `x`, and `x * 0` becomes 0. Doublings
+/// `x * 2` become the canonical doubling `x + x`, as built by `Builder::double`.
+#[derive(Debug, Clone)]
+pub struct Simplify {
+    rewriter: Rewriter,
+}
+
+impl Default for Simplify {
+    fn default() -> Self {
+        Simplify {
+            rewriter: Rewriter::new()
+                .rule(Rule::new("add-zero", var("x") + value(0), var("x")))
+                .rule(Rule::new("mul-one", var("x") * value(1), var("x")))
+                .rule(Rule::new("mul-zero", var("x") * value(0), value(0)))
+                .rule(Rule::new(
+                    "mul-two",
+                    var("x") * value(2),
+                    var("x") + var("x"),
+                )),
+        }
+    }
+}
+
+impl Pass for Simplify {
+    fn name(&self) -> &str {
+        "simplify"
+    }
+
+    fn run(&self, builder: &Builder) -> PassOutput {
+        self.rewriter.run(builder).into()
+    }
+}
+
+/// What identifies the value of a node for `CommonSubexpressions`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Constant(u32),
+    // Operands are sorted, since addition and multiplication are commutative.
+    Add(usize, usize),
+    Mul(usize, usize),
+    MulAdd(usize, usize, usize),
+    Hint(String, usize),
+    Custom(String, Vec<usize>),
+}
+
+/// Merges nodes computing the same value: constants with the same value, additions and
+/// multiplications of the same operands in any order, and hints and custom operations computed by
+/// the same function from the same nodes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommonSubexpressions;
+
+impl Pass for CommonSubexpressions {
+    fn name(&self) -> &str {
+        "common-subexpressions"
+    }
+
+    fn run(&self, builder: &Builder) -> PassOutput {
+        let mut changes = 0;
+        let mut seen = HashMap::new();
+        let rebuilt = builder.rebuild(|graph, node| {
+            let key = match graph.graph.kind(node) {
+                Kind::Constant => Key::Constant(graph.graph.output(node).unwrap()),
+                Kind::Add | Kind::Mul => {
+                    let (a, b) = graph.graph.operands(node).unwrap();
+                    let (a, b) = (a.min(b), a.max(b));
+                    if graph.graph.kind(node) == Kind::Add {
+                        Key::Add(a, b)
+                    } else {
+                        Key::Mul(a, b)
+                    }
+                }
+                Kind::MulAdd => {
+                    let [a, b, c] = graph.graph.mul_add(node).unwrap();
+                    Key::MulAdd(a.min(b), a.max(b), c)
+                }
+                Kind::Hint => match graph.hint_calls.get(&node) {
+                    Some(function) => {
+                        Key::Hint(function.clone(), graph.graph.linked(node).unwrap())
+                    }
+                    None => return node,
+                },
+                Kind::Custom => Key::Custom(
+                    graph.custom_calls[&node].clone(),
+                    graph.graph.custom(node).unwrap(),
+                ),
+                Kind::Input => return node,
+            };
+            match seen.get(&key) {
+                Some(&existing) => {
+                    changes += 1;
+                    existing
+                }
+                None => {
+                    seen.insert(key, node);
+                    node
+                }
+            }
+        });
+        output(builder, rebuilt, changes)
+    }
+}
+
+/// Removes the nodes that no input, public output, constraint or check needs. Labels, locations
+/// and metadata of removed nodes are removed with them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeadCodeElimination;
+
+impl Pass for DeadCodeElimination {
+    fn name(&self) -> &str {
+        "dead-code-elimination"
+    }
+
+    fn run(&self, builder: &Builder) -> PassOutput {
+        let mut live = vec![false; builder.graph.len()];
+        for &id in builder.input_nodes.iter().chain(&builder.public) {
+            live[id] = true;
+        }
+        for &(a, b) in &builder.constraints {
+            live[a] = true;
+            live[b] = true;
+        }
+        for (&node, &parent) in &builder.aliases {
+            live[node] = true;
+            live[parent] = true;
+        }
+        for check in &builder.checks {
+            for id in check.nodes() {
+                live[id] = true;
+            }
+        }
+        for id in (0..builder.graph.len()).rev() {
+            if live[id] {
+                for source in builder.sources(id).collect::<Vec<_>>() {
+                    live[source] = true;
+                }
+            }
+        }
+        let slice = builder.extract(&live);
+        PassOutput {
+            changes: builder.graph.len() - slice.builder.graph.len(),
+            builder: slice.builder,
+            ids: slice.ids,
+        }
+    }
+}
+
+impl Pass for Rewriter {
+    fn name(&self) -> &str {
+        "rewrite"
+    }
+
+    fn run(&self, builder: &Builder) -> PassOutput {
+        Rewriter::run(self, builder).into()
+    }
+}
+
+impl From<crate::rewrite::Rewritten> for PassOutput {
+    fn from(rewritten: crate::rewrite::Rewritten) -> Self {
+        PassOutput {
+            builder: rewritten.builder,
+            ids: rewritten.ids,
+            changes: rewritten.applied.values().sum(),
+        }
+    }
+}
+
+/// What a pass did over a `PassManager::run`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassStats {
+    pub name: String,
+    /// Number of times the pass ran, once per round.
+    pub runs: usize,
+    pub changes: usize,
+    /// Nodes added, negative if the pass removed nodes.
+    pub node_delta: isize,
+    pub time: Duration,
+}
+
+impl fmt::Display for PassStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} changes, {:+} nodes in {} runs ({:.2?})",
+            self.name, self.changes, self.node_delta, self.runs, self.time
+        )
+    }
+}
+
+/// The result of `PassManager::run`.
+#[derive(Debug, Clone)]
+pub struct Optimized {
+    pub builder: Builder,
+    /// The id in `builder` of the node standing for each node of the original graph that was
+    /// kept.
+    pub ids: BTreeMap<NodeId, NodeId>,
+    /// Statistics of every pass, in pipeline order.
+    pub stats: Vec<PassStats>,
+    /// Number of rounds run, including the last one, in which no pass changed the graph.
+    pub rounds: usize,
+    /// The differences found by validation, one for each pass result that was undone.
+    pub rejected: Vec<ValidationError>,
+}
+
+/// Random input assignments and what the original graph computes on them.
+struct Reference {
+    samples: Vec<Sample>,
+}
+
+struct Sample {
+    inputs: Vec<(NodeId, u32)>,
+    /// The values of the public outputs, in index order.
+    outputs: Vec<(usize, u32)>,
+    satisfied: bool,
+}
+
+/// Fills in a copy of `builder`, or returns why the inputs can't be evaluated.
+fn evaluate(builder: &Builder, inputs: &[(NodeId, u32)]) -> Result<Builder, String> {
+    let mut graph = builder.clone();
+    // Validation runs are not evaluations of the circuit, so keep them out of the metrics and away
+    // from observers.
+    graph.metrics = None;
+    graph.observers.clear();
+    graph.reset();
+    graph
+        .fill_inputs_with(inputs, EvalOptions::new())
+        .map_err(|err| err.to_string())?;
+    Ok(graph)
+}
+
+impl Reference {
+    fn new(builder: &Builder, samples: usize) -> Self {
+        let mut rng = Rng(0x5eed);
+        // A graph without inputs always computes the same values.
+        let samples = if builder.input_nodes.is_empty() {
+            samples.min(1)
+        } else {
+            samples
+        };
+        let samples = (0..samples)
+            .filter_map(|_| {
+                let inputs: Vec<(NodeId, u32)> = builder
+                    .input_nodes
+                    .iter()
+                    .map(|&id| {
+                        let bits = rng.below(33) as u32;
+                        let value = (rng.next() as u32).checked_shr(32 - bits).unwrap_or(0);
+                        (builder.id(id), value)
+                    })
+                    .collect();
+                let graph = evaluate(builder, &inputs).ok()?;
+                let outputs = builder
+                    .public
+                    .iter()
+                    .map(|&id| (id, graph.graph.output(id).unwrap()))
+                    .collect();
+                Some(Sample {
+                    inputs,
+                    outputs,
+                    satisfied: graph.is_satisfied(),
+                })
+            })
+            .collect();
+        Reference { samples }
+    }
+
+    /// Checks that `candidate` computes the same as the original graph, given the id in
+    /// `candidate` of each original node.
+    fn validate(
+        &self,
+        pass: &str,
+        original: &Builder,
+        candidate: &Builder,
+        ids: &BTreeMap<NodeId, NodeId>,
+    ) -> Result<(), ValidationError> {
+        for sample in &self.samples {
+            let inputs: Vec<(NodeId, u32)> = sample
+                .inputs
+                .iter()
+                .filter_map(|&(id, value)| Some((*ids.get(&id)?, value)))
+                .collect();
+            let graph =
+                evaluate(candidate, &inputs).map_err(|message| ValidationError::EvalFailed {
+                    pass: pass.to_string(),
+                    message,
+                    inputs: sample.inputs.clone(),
+                })?;
+            for &(output, expected) in &sample.outputs {
+                let found = ids
+                    .get(&original.id(output))
+                    .and_then(|id| graph.graph.output(id.index));
+                if found != Some(expected) {
+                    return Err(ValidationError::OutputChanged {
+                        pass: pass.to_string(),
+                        output,
+                        expected: original.redact(output, expected),
+                        found: original.redact(output, found.unwrap_or_default()),
+                        inputs: sample.inputs.clone(),
+                    });
+                }
+            }
+            if graph.is_satisfied() != sample.satisfied {
+                return Err(ValidationError::ConstraintsChanged {
+                    pass: pass.to_string(),
+                    before: sample.satisfied,
+                    inputs: sample.inputs.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs a pipeline of passes until they no longer change the graph.
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    max_rounds: usize,
+    samples: usize,
+    strict: bool,
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        PassManager {
+            passes: Vec::new(),
+            max_rounds: 16,
+            samples: 0,
+            strict: false,
+        }
+    }
+}
+
+impl fmt::Debug for PassManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.passes.iter().map(|pass| pass.name()).collect();
+        f.debug_struct("PassManager")
+            .field("passes", &names)
+            .field("max_rounds", &self.max_rounds)
+            .field("samples", &self.samples)
+            .field("strict", &self.strict)
+            .finish()
+    }
+}
+
+impl PassManager {
+    /// Creates a pass manager with no passes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The default pipeline: constant folding, simplification, common subexpression
+    /// elimination, then dead code elimination.
+    pub fn standard() -> Self {
+        PassManager::new()
+            .pass(ConstantFolding)
+            .pass(Simplify::default())
+            .pass(CommonSubexpressions)
+            .pass(DeadCodeElimination)
+    }
+
+    /// Appends a pass to the pipeline.
+    pub fn pass(mut self, pass: impl Pass + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Sets the largest number of rounds through the pipeline (16 by default).
+    pub fn max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    /// Checks the result of every pass against the original graph on `samples` random input
+    /// assignments (none by default).
+    pub fn validate(mut self, samples: usize) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Fails the run when validation finds a difference, instead of undoing the pass.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Optimizes a copy of `builder`. Panics in strict mode if validation finds a difference.
+    pub fn run(&self, builder: &Builder) -> Optimized {
+        self.try_run(builder)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Optimizes a copy of `builder`, returning an error in strict mode if validation finds a
+    /// difference.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "optimize", level = "debug", skip_all, fields(nodes = builder.graph.len(), passes = self.passes.len()))
+    )]
+    pub fn try_run(&self, builder: &Builder) -> Result<Optimized, ValidationError> {
+        #[cfg(feature = "tracing")]
+        let run_start = Instant::now();
+        let reference = (self.samples > 0).then(|| Reference::new(builder, self.samples));
+        let mut rejected = Vec::new();
+        let mut stats: Vec<PassStats> = self
+            .passes
+            .iter()
+            .map(|pass| PassStats {
+                name: pass.name().to_string(),
+                runs: 0,
+                changes: 0,
+                node_delta: 0,
+                time: Duration::ZERO,
+            })
+            .collect();
+        let mut current = builder.clone();
+        let mut ids: BTreeMap<NodeId, NodeId> = (0..builder.graph.len())
+            .map(|id| (builder.id(id), builder.id(id)))
+            .collect();
+        let mut rounds = 0;
+        while rounds < self.max_rounds {
+            rounds += 1;
+            let mut changed = false;
+            for (pass, stats) in self.passes.iter().zip(&mut stats) {
+                #[cfg(feature = "tracing")]
+                let _span =
+                    tracing::debug_span!("pass", name = pass.name(), round = rounds).entered();
+                let start = Instant::now();
+                let output = pass.run(&current);
+                stats.time += start.elapsed();
+                stats.runs += 1;
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    nodes_before = current.graph.len(),
+                    nodes_after = output.builder.graph.len(),
+                    changes = output.changes,
+                    elapsed_us = start.elapsed().as_micros() as u64,
+                    "ran pass"
+                );
+                if output.changes == 0 {
+                    continue;
+                }
+                let new_ids: BTreeMap<NodeId, NodeId> = ids
+                    .iter()
+                    .filter_map(|(&original, id)| Some((original, *output.ids.get(id)?)))
+                    .collect();
+                if let Some(reference) = &reference {
+                    let validated =
+                        reference.validate(pass.name(), builder, &output.builder, &new_ids);
+                    if let Err(err) = validated {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(%err, "pass changed the graph's behavior, undoing it");
+                        if self.strict {
+                            return Err(err);
+                        }
+                        rejected.push(err);
+                        continue;
+                    }
+                }
+                changed = true;
+                stats.changes += output.changes;
+                stats.node_delta +=
+                    output.builder.graph.len() as isize - current.graph.len() as isize;
+                ids = new_ids;
+                current = output.builder;
+            }
+            if !changed {
+                break;
+            }
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            nodes_after = current.graph.len(),
+            rounds,
+            rejected = rejected.len(),
+            elapsed_us = run_start.elapsed().as_micros() as u64,
+            "optimized graph"
+        );
+        Ok(Optimized {
+            builder: current,
+            ids,
+            stats,
+            rounds,
+            rejected,
+        })
+    }
+}
@@ -0,0 +1,104 @@
+//! Copy constraints as a permutation argument, for Plonkish backends.
+//!
+//! Plonkish backends prove gate constraints row by row, and copy constraints, which tie cells
+//! anywhere in the table together, separately with a permutation argument: the cells that must be
+//! equal form cycles, and the permutation `sigma` maps every cell to the next cell of its cycle.
+//! The argument proves that the table is unchanged by `sigma`, so that cells of a cycle hold the
+//! same value.
+//!
+//! `from_copies` groups pairs of equal cells into cycles, merging pairs that share a cell.
+//! `Plonkish::permutation` and `trace::Layout::permutation` build the permutation of their
+//! tables, from the wiring of operands to the cells of their nodes and from the equality
+//! constraints and aliases of the graph. `Builder::copy_classes` gives the nodes those make
+//! equal. Only recorded constraints count: `assert_equal` compares values without recording one.
+//!
+//! ```
+//! use my_graph_lib::permutation;
+//!
+//! let copies = [(0, 3), (5, 1), (3, 7)];
+//! let permutation = permutation::from_copies(&copies);
+//! assert_eq!(permutation.cycles, vec![vec![0, 3, 7], vec![1, 5]]);
+//! let sigma = permutation.sigma();
+//! assert_eq!((sigma[&0], sigma[&3], sigma[&7]), (3, 7, 0));
+//! assert!(permutation.is_satisfied(|cell| [4, 9, 0, 4, 0, 9, 0, 4][cell]));
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{Builder, NodeId};
+
+/// Cells that must hold the same values, in cycles of at least two cells.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Permutation<C> {
+    /// The cycles, each sorted, ordered by their first cell.
+    pub cycles: Vec<Vec<C>>,
+}
+
+impl<C: Copy + Ord> Permutation<C> {
+    /// The next cell of the cycle of every cell in a cycle. Cells in no cycle, which map to
+    /// themselves, are left out.
+    pub fn sigma(&self) -> BTreeMap<C, C> {
+        let mut sigma = BTreeMap::new();
+        for cycle in &self.cycles {
+            for (i, &cell) in cycle.iter().enumerate() {
+                sigma.insert(cell, cycle[(i + 1) % cycle.len()]);
+            }
+        }
+        sigma
+    }
+
+    /// Whether every cycle holds a single value, reading the value of a cell with `value`.
+    pub fn is_satisfied<V: PartialEq>(&self, value: impl Fn(C) -> V) -> bool {
+        self.cycles
+            .iter()
+            .all(|cycle| cycle.iter().all(|&cell| value(cell) == value(cycle[0])))
+    }
+}
+
+/// Groups pairs of cells that must be equal into cycles, see the module documentation.
+pub fn from_copies<C: Copy + Ord>(copies: &[(C, C)]) -> Permutation<C> {
+    let mut cells: Vec<C> = copies.iter().flat_map(|&(a, b)| [a, b]).collect();
+    cells.sort_unstable();
+    cells.dedup();
+    let number = |cell: C| cells.binary_search(&cell).unwrap();
+    let mut parent: Vec<usize> = (0..cells.len()).collect();
+    for &(a, b) in copies {
+        let (a, b) = (root(&mut parent, number(a)), root(&mut parent, number(b)));
+        // The smaller root stays, so every class is represented by its first cell.
+        parent[a.max(b)] = a.min(b);
+    }
+    let mut cycles: BTreeMap<usize, Vec<C>> = BTreeMap::new();
+    for (i, &cell) in cells.iter().enumerate() {
+        let root = root(&mut parent, i);
+        cycles.entry(root).or_default().push(cell);
+    }
+    Permutation {
+        cycles: cycles
+            .into_values()
+            .filter(|cycle| cycle.len() > 1)
+            .collect(),
+    }
+}
+
+/// The representative of the class of `i`, compressing the path to it.
+fn root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+impl Builder {
+    /// The classes of nodes that the equality constraints and aliases of the graph make equal,
+    /// with at least two nodes each, sorted. Constraints with the `Warning` severity are left out.
+    pub fn copy_classes(&self) -> Vec<Vec<NodeId>> {
+        from_copies(&self.copies())
+            .cycles
+            .into_iter()
+            .map(|cycle| cycle.into_iter().map(|node| self.id(node)).collect())
+            .collect()
+    }
+}
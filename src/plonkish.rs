@@ -0,0 +1,388 @@
+//! Extraction of wide custom gates, for Plonkish backends such as Halo2.
+//!
+//! Lowering one node per row wastes rows: in a Plonkish table a row can hold any polynomial
+//! identity of its cells, not just one operation. `extract` groups the graph into gates, each
+//! computing one node, its row's output cell, as an expression of degree at most `max_degree`
+//! over at most `width` wire cells. Nodes used once, only by the gate, are inlined into it; nodes
+//! that are used more than once, public, or read by a constraint or a check get their own row, as
+//! do nodes that don't fit in their consumer's gate. Constants are folded into the gates as fixed
+//! cells, and inputs and hints computed by functions get rows without a gate, whose cells hold
+//! advice. Custom nodes must be lowered with `Builder::lower_custom_ops` first.
+//!
+//! Gates whose expressions have the same shape, such as `w0 * w1 + c0`, are one custom gate: a
+//! backend declares each with a selector and enables it on its rows. Copy constraints tie every
+//! wire cell to the output cell of the row of its node, and the output cells of the nodes of
+//! every equality constraint and alias class. Checks are left to the backend's lookup arguments, over the output
+//! cells of their nodes. Values are field elements in the backend, so wrapping around the field
+//! is not ruled out as an overflow is by evaluation.
+//!
+//! ```
+//! use my_graph_lib::{plonkish, Builder};
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let seven = builder.constant(7);
+//! let square = builder.mul(x, x);
+//! let y = builder.mul_add(square, x, seven);
+//! builder.mark_public(y);
+//! let table = plonkish::extract(&builder, 3, 4);
+//! // The input, and `x * x * x + 7` in one row.
+//! assert_eq!(table.rows.len(), 2);
+//! assert_eq!(table.gates[0].to_string(), "w0 * w0 * w0 + c0");
+//! ```
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+use crate::checks::Check;
+use crate::error::BuildError;
+use crate::permutation::{self, Permutation};
+use crate::storage::Kind;
+use crate::Builder;
+
+/// The gate degree used by the CLI, the highest most Plonkish backends handle comfortably.
+pub const DEFAULT_DEGREE: u32 = 3;
+
+/// The number of wire columns used by the CLI.
+pub const DEFAULT_WIDTH: usize = 4;
+
+/// A gate expression: `Wire(i)` is the cell of wire column `i` of the row, and `Fixed(i)` its
+/// fixed cell `i`, holding a constant.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Expr {
+    Wire(usize),
+    Fixed(usize),
+    Add(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn degree(&self) -> u32 {
+        match self {
+            Expr::Wire(_) => 1,
+            Expr::Fixed(_) => 0,
+            Expr::Add(a, b) => a.degree().max(b.degree()),
+            Expr::Mul(a, b) => a.degree() + b.degree(),
+        }
+    }
+
+    /// The value of the expression over the integers, or `None` if it doesn't fit in a u64. The
+    /// cells are indexed by column.
+    pub fn evaluate(&self, wires: &[u32], fixed: &[u32]) -> Option<u64> {
+        match self {
+            Expr::Wire(i) => Some(u64::from(wires[*i])),
+            Expr::Fixed(i) => Some(u64::from(fixed[*i])),
+            Expr::Add(a, b) => a
+                .evaluate(wires, fixed)?
+                .checked_add(b.evaluate(wires, fixed)?),
+            Expr::Mul(a, b) => a
+                .evaluate(wires, fixed)?
+                .checked_mul(b.evaluate(wires, fixed)?),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Wire(i) => write!(f, "w{}", i),
+            Expr::Fixed(i) => write!(f, "c{}", i),
+            Expr::Add(a, b) => write!(f, "{} + {}", a, b),
+            Expr::Mul(a, b) => {
+                for (i, factor) in [a, b].into_iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" * ")?;
+                    }
+                    match **factor {
+                        Expr::Add(..) => write!(f, "({})", factor)?,
+                        _ => write!(f, "{}", factor)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Expressions are serialized as their `Display` form.
+impl Serialize for Expr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A row of the table. Its output cell holds the value of `node`, which must equal the
+/// expression of `gate` over the row's wire and fixed cells. Rows without a gate hold advice.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Row {
+    pub node: usize,
+    pub gate: Option<usize>,
+    /// The nodes whose values the wire cells hold, by column.
+    pub wires: Vec<usize>,
+    /// The constants the fixed cells hold, by column.
+    pub fixed: Vec<u32>,
+}
+
+/// A cell of the table: column `width` is the output column, and the others are wire columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct Cell {
+    pub row: usize,
+    pub column: usize,
+}
+
+/// A graph grouped into custom gates by `extract`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Plonkish {
+    pub width: usize,
+    /// The distinct gate expressions, numbered by `Row::gate`.
+    pub gates: Vec<Expr>,
+    /// The rows, ordered by node.
+    pub rows: Vec<Row>,
+    /// Pairs of cells that must hold the same value.
+    pub copies: Vec<(Cell, Cell)>,
+    /// The checks of the graph, to be enforced on the output cells of their nodes.
+    pub checks: Vec<Check>,
+}
+
+impl Plonkish {
+    /// The row whose output cell holds the value of `node`, if it has one.
+    pub fn row(&self, node: usize) -> Option<usize> {
+        self.rows.binary_search_by_key(&node, |row| row.node).ok()
+    }
+
+    /// The copy constraints as the cycles of a permutation argument.
+    pub fn permutation(&self) -> Permutation<Cell> {
+        permutation::from_copies(&self.copies)
+    }
+
+    /// The advice values of the table, from the values of the builder's nodes: for each row, its
+    /// wire cells, padded with zeros to `width`, and its output cell. `None` if a node has not
+    /// been filled in.
+    pub fn assignment(&self, builder: &Builder) -> Option<Vec<Vec<u32>>> {
+        self.rows
+            .iter()
+            .map(|row| {
+                let mut cells = row
+                    .wires
+                    .iter()
+                    .map(|&node| builder.graph.output(node))
+                    .collect::<Option<Vec<u32>>>()?;
+                cells.resize(self.width, 0);
+                cells.push(builder.graph.output(row.node)?);
+                Some(cells)
+            })
+            .collect()
+    }
+}
+
+/// Groups the graph into gates of degree at most `max_degree` over at most `width` wires, see the
+/// module documentation. Panics if `max_degree` is below 2 or `width` below 3, which single
+/// nodes may need, or if the graph has custom nodes, which can't be constrained: lower them with
+/// `lower_custom_ops` first.
+pub fn extract(builder: &Builder, max_degree: u32, width: usize) -> Plonkish {
+    try_extract(builder, max_degree, width).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Like `extract`, but returns `BuildError::Unlowered` instead of panicking if the graph has
+/// custom nodes.
+pub fn try_extract(
+    builder: &Builder,
+    max_degree: u32,
+    width: usize,
+) -> Result<Plonkish, BuildError> {
+    builder.check_lowered()?;
+    assert!(
+        max_degree >= 2 && width >= 3,
+        "gates need a degree of at least 2 and at least 3 wires"
+    );
+    let graph = &builder.graph;
+    let mut uses = vec![0usize; graph.len()];
+    for id in 0..graph.len() {
+        for operand in operands(builder, id) {
+            uses[operand] += 1;
+        }
+    }
+    let mut required = vec![false; graph.len()];
+    for (a, b) in builder.copies() {
+        required[a] = true;
+        required[b] = true;
+    }
+    for &id in &builder.public {
+        required[id] = true;
+    }
+    for id in builder.checks.iter().flat_map(Check::nodes) {
+        required[id] = true;
+    }
+
+    // Consumers come after their operands, so rows are built from the last node back, and the
+    // nodes a gate doesn't inline are queued for rows of their own before they are reached.
+    let mut pending: BTreeSet<usize> = (0..graph.len())
+        .filter(|&id| match graph.kind(id) {
+            Kind::Add | Kind::Mul | Kind::MulAdd => required[id] || uses[id] != 1,
+            _ => required[id] && is_constant(builder, id),
+        })
+        .collect();
+    let mut gates: HashMap<usize, Gate> = HashMap::new();
+    while let Some(root) = pending.pop_last() {
+        let mut inlined = BTreeSet::new();
+        let mut candidates: VecDeque<usize> = operands(builder, root).into();
+        while let Some(candidate) = candidates.pop_front() {
+            let computed = matches!(graph.kind(candidate), Kind::Add | Kind::Mul | Kind::MulAdd);
+            if !computed || required[candidate] || uses[candidate] != 1 {
+                continue;
+            }
+            inlined.insert(candidate);
+            let gate = Gate::build(builder, root, &inlined);
+            if gate.expr.degree() <= max_degree && gate.wires.len() <= width {
+                candidates.extend(operands(builder, candidate));
+            } else {
+                inlined.remove(&candidate);
+            }
+        }
+        let gate = Gate::build(builder, root, &inlined);
+        for &wire in &gate.wires {
+            if matches!(graph.kind(wire), Kind::Add | Kind::Mul | Kind::MulAdd)
+                && !gates.contains_key(&wire)
+            {
+                pending.insert(wire);
+            }
+        }
+        gates.insert(root, gate);
+    }
+
+    let mut expressions = Vec::new();
+    let mut numbers: HashMap<Expr, usize> = HashMap::new();
+    let mut rows = Vec::new();
+    for id in 0..graph.len() {
+        let row = match gates.remove(&id) {
+            Some(gate) => {
+                let number = *numbers.entry(gate.expr.clone()).or_insert_with(|| {
+                    expressions.push(gate.expr);
+                    expressions.len() - 1
+                });
+                Row {
+                    node: id,
+                    gate: Some(number),
+                    wires: gate.wires,
+                    fixed: gate.fixed,
+                }
+            }
+            None if is_advice(builder, id) => Row {
+                node: id,
+                gate: None,
+                wires: Vec::new(),
+                fixed: Vec::new(),
+            },
+            None => continue,
+        };
+        rows.push(row);
+    }
+
+    let mut table = Plonkish {
+        width,
+        gates: expressions,
+        rows,
+        copies: Vec::new(),
+        checks: builder.checks.clone(),
+    };
+    let output = |table: &Plonkish, node: usize| Cell {
+        row: table
+            .row(node)
+            .expect("wires and constrained nodes have rows"),
+        column: width,
+    };
+    let mut copies = Vec::new();
+    for (row, cells) in table.rows.iter().enumerate() {
+        for (column, &node) in cells.wires.iter().enumerate() {
+            copies.push((output(&table, node), Cell { row, column }));
+        }
+    }
+    for (a, b) in builder.copies() {
+        copies.push((output(&table, a), output(&table, b)));
+    }
+    table.copies = copies;
+    Ok(table)
+}
+
+/// The gate of a row while it is being built: its expression, and the nodes and constants of its
+/// cells.
+struct Gate {
+    expr: Expr,
+    wires: Vec<usize>,
+    fixed: Vec<u32>,
+}
+
+impl Gate {
+    /// The gate computing `root` with the nodes of `inlined` folded into it. Wires are numbered
+    /// in the order their nodes are first reached, so gates of the same shape get the same
+    /// expression.
+    fn build(builder: &Builder, root: usize, inlined: &BTreeSet<usize>) -> Gate {
+        let mut gate = Gate {
+            expr: Expr::Fixed(0),
+            wires: Vec::new(),
+            fixed: Vec::new(),
+        };
+        gate.expr = gate.expand(builder, root, root, inlined);
+        gate
+    }
+
+    fn expand(
+        &mut self,
+        builder: &Builder,
+        root: usize,
+        id: usize,
+        inlined: &BTreeSet<usize>,
+    ) -> Expr {
+        let graph = &builder.graph;
+        if is_constant(builder, id) {
+            self.fixed.push(graph.output(id).unwrap());
+            return Expr::Fixed(self.fixed.len() - 1);
+        }
+        if id != root && !inlined.contains(&id) {
+            let position = self.wires.iter().position(|&wire| wire == id);
+            return Expr::Wire(position.unwrap_or_else(|| {
+                self.wires.push(id);
+                self.wires.len() - 1
+            }));
+        }
+        let mut expand = |operand| Box::new(self.expand(builder, root, operand, inlined));
+        match (graph.kind(id), graph.operands(id), graph.mul_add(id)) {
+            (Kind::Add, Some((a, b)), _) => Expr::Add(expand(a), expand(b)),
+            (Kind::Mul, Some((a, b)), _) => Expr::Mul(expand(a), expand(b)),
+            (Kind::MulAdd, _, Some([a, b, c])) => {
+                let product = Box::new(Expr::Mul(expand(a), expand(b)));
+                Expr::Add(product, expand(c))
+            }
+            _ => unreachable!("only computed nodes are expanded"),
+        }
+    }
+}
+
+/// The nodes a node is computed from, in the circuit: hint links aren't part of it.
+fn operands(builder: &Builder, id: usize) -> Vec<usize> {
+    match (builder.graph.operands(id), builder.graph.mul_add(id)) {
+        (Some((a, b)), _) => vec![a, b],
+        (_, Some(operands)) => operands.to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+/// Constants and hints with a fixed value, which are folded into gates.
+fn is_constant(builder: &Builder, id: usize) -> bool {
+    match builder.graph.kind(id) {
+        Kind::Constant => true,
+        Kind::Hint => !builder.hint_calls.contains_key(&id),
+        _ => false,
+    }
+}
+
+/// Inputs and hints computed by functions, whose values are advice.
+fn is_advice(builder: &Builder, id: usize) -> bool {
+    match builder.graph.kind(id) {
+        Kind::Input => true,
+        Kind::Hint => builder.hint_calls.contains_key(&id),
+        _ => false,
+    }
+}
@@ -0,0 +1,189 @@
+//! Profiling of evaluation, to see where witness generation time goes.
+//!
+//! `EvalOptions::profile` times every node computed by `Builder::fill_inputs_with` and adds it to a
+//! `Profile`, aggregated by operation, by scope and by dependency level. Operations are `input`,
+//! `constant`, `add`, `mul`, `mul_add`, and `hint <name>` for each hint function (`hint` for fixed
+//! hints). Scopes are the prefixes of labels, as subcircuits label their instances (`round/carry`
+//! is in scope `round`), or a `scope` metadata string; unlabelled nodes count towards the scope of
+//! their first consumer, so gadget internals are charged to the gadget. `to_folded()` writes the
+//! samples as folded stacks for flamegraph tools such as `inferno-flamegraph` or `flamegraph.pl`.
+//!
+//! Timing a node costs about as much as computing it, so absolute times are inflated; the shares
+//! are what matter. A profile can be reused to aggregate over several evaluations.
+//!
+//! ```
+//! use my_graph_lib::{eval::EvalOptions, profile::Profile, Builder};
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let square = builder.mul(x, x);
+//! builder.set_label(square, "gadget/square");
+//! let mut profile = Profile::default();
+//! builder
+//!     .fill_inputs_with(&[(x, 3)], EvalOptions::new().profile(&mut profile))
+//!     .unwrap();
+//! assert_eq!(profile.total.count, 2);
+//! assert_eq!(profile.operations["mul"].count, 1);
+//! // x is first read by the square, so it counts towards the gadget too.
+//! assert_eq!(profile.scopes["gadget"].count, 2);
+//! assert_eq!(profile.levels[&1].count, 1);
+//! assert!(profile.to_folded().contains("fill;gadget;mul "));
+//! ```
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::storage::Kind;
+use crate::Builder;
+
+/// The number of nodes computed and the time spent on them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpStats {
+    pub count: u64,
+    pub time: Duration,
+}
+
+impl OpStats {
+    fn add(&mut self, time: Duration) {
+        self.count += 1;
+        self.time += time;
+    }
+}
+
+/// Evaluation time aggregated over nodes, see the module documentation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// Every node computed.
+    pub total: OpStats,
+    /// By operation.
+    pub operations: BTreeMap<String, OpStats>,
+    /// By scope, as a `/`-separated path. Nodes outside any scope are under `""`.
+    pub scopes: BTreeMap<String, OpStats>,
+    /// By dependency level, see `Builder::levels`.
+    pub levels: BTreeMap<usize, OpStats>,
+    /// By folded stack: the scope path and the operation, separated by `;`.
+    stacks: BTreeMap<String, OpStats>,
+}
+
+impl Profile {
+    /// The samples as folded stacks: one `fill;scope;...;operation nanoseconds` line per stack.
+    pub fn to_folded(&self) -> String {
+        self.stacks
+            .iter()
+            .map(|(stack, stats)| format!("fill;{} {}\n", stack, stats.time.as_nanos()))
+            .collect()
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} nodes in {:.3?}", self.total.count, self.total.time)?;
+        let operations = self
+            .operations
+            .iter()
+            .map(|(name, &stats)| (name.clone(), stats));
+        section(f, "operations", self.total, operations.collect())?;
+        let scopes = self.scopes.iter().map(|(name, &stats)| {
+            let name = if name.is_empty() { "(top level)" } else { name };
+            (name.to_string(), stats)
+        });
+        section(f, "scopes", self.total, scopes.collect())?;
+        let levels = self
+            .levels
+            .iter()
+            .map(|(level, &stats)| (level.to_string(), stats));
+        section(f, "levels", self.total, levels.collect())
+    }
+}
+
+/// Writes a table of `rows` by decreasing time, with their share of `total`.
+fn section(
+    f: &mut fmt::Formatter<'_>,
+    title: &str,
+    total: OpStats,
+    mut rows: Vec<(String, OpStats)>,
+) -> fmt::Result {
+    writeln!(f, "{}:", title)?;
+    rows.sort_by_key(|row| Reverse(row.1.time));
+    for (name, stats) in rows {
+        let share = if total.time.is_zero() {
+            0.0
+        } else {
+            100.0 * stats.time.as_secs_f64() / total.time.as_secs_f64()
+        };
+        writeln!(
+            f,
+            "  {:<24} {:>10} nodes {:>12.3?} {:>5.1}%",
+            name, stats.count, stats.time, share
+        )?;
+    }
+    Ok(())
+}
+
+/// The operation, scope and level of every node, computed once before profiling an evaluation.
+pub(crate) struct Context {
+    operations: Vec<String>,
+    scopes: Vec<String>,
+    levels: Vec<usize>,
+    stacks: Vec<String>,
+}
+
+impl Context {
+    pub(crate) fn new(builder: &Builder) -> Self {
+        let operations = (0..builder.graph.len())
+            .map(|id| match builder.graph.kind(id) {
+                Kind::Input => "input".to_string(),
+                Kind::Constant => "constant".to_string(),
+                Kind::Add => "add".to_string(),
+                Kind::Mul => "mul".to_string(),
+                Kind::MulAdd => "mul_add".to_string(),
+                Kind::Hint => match builder.hint_calls.get(&id) {
+                    Some(name) => format!("hint {}", name),
+                    None => "hint".to_string(),
+                },
+                Kind::Custom => format!("custom {}", builder.custom_calls[&id]),
+            })
+            .collect();
+        let scopes: Vec<Vec<String>> = builder.scopes();
+        let stacks = scopes
+            .iter()
+            .zip(&operations)
+            .map(|(path, operation)| {
+                path.iter()
+                    .chain(std::iter::once(operation))
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(";")
+            })
+            .collect();
+        Context {
+            operations,
+            scopes: scopes.iter().map(|path| path.join("/")).collect(),
+            levels: builder.levels(),
+            stacks,
+        }
+    }
+
+    /// Adds the time spent computing `node` to the profile.
+    pub(crate) fn record(&self, profile: &mut Profile, node: usize, time: Duration) {
+        profile.total.add(time);
+        entry(&mut profile.operations, &self.operations[node]).add(time);
+        entry(&mut profile.scopes, &self.scopes[node]).add(time);
+        profile
+            .levels
+            .entry(self.levels[node])
+            .or_default()
+            .add(time);
+        entry(&mut profile.stacks, &self.stacks[node]).add(time);
+    }
+}
+
+/// The stats under `key`, inserted without allocating when already present.
+fn entry<'m>(map: &'m mut BTreeMap<String, OpStats>, key: &str) -> &'m mut OpStats {
+    if !map.contains_key(key) {
+        map.insert(key.to_string(), OpStats::default());
+    }
+    map.get_mut(key).unwrap()
+}
@@ -0,0 +1,431 @@
+//! Protobuf encoding of graphs and witnesses, built with `--features protobuf`, for services
+//! that already speak protobuf.
+//!
+//! The schema is `proto/circuit.proto`, also available as `SCHEMA`, and the message types below
+//! are its prost counterparts, so other languages can generate theirs from the same file. Node
+//! locations and metadata are only kept by the JSON format.
+//!
+//! ```
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let y = builder.mul(x, x);
+//! builder.mark_public(y);
+//! let bytes = builder.to_protobuf();
+//! let mut loaded = Builder::from_protobuf(&bytes).unwrap();
+//! loaded.fill_nodes(loaded.inputs()[0], 7);
+//! assert_eq!(loaded.output(loaded.public_outputs()[0]), Some(49));
+//! ```
+
+use std::collections::BTreeSet;
+
+use prost::Message;
+
+use crate::checks;
+use crate::error::SerdeError;
+use crate::storage::Kind;
+use crate::Builder;
+
+/// The schema the messages of this module implement.
+pub const SCHEMA: &str = include_str!("../proto/circuit.proto");
+
+/// `circuit.v1.Circuit`: a graph of nodes, with the constraints its values must satisfy.
+#[derive(Clone, PartialEq, Message)]
+pub struct Circuit {
+    #[prost(message, repeated, tag = "1")]
+    pub nodes: Vec<Node>,
+    #[prost(message, repeated, tag = "2")]
+    pub constraints: Vec<Constraint>,
+    #[prost(message, repeated, tag = "3")]
+    pub checks: Vec<Check>,
+}
+
+/// `circuit.v1.Node`.
+#[derive(Clone, PartialEq, Message)]
+pub struct Node {
+    #[prost(enumeration = "NodeKind", tag = "1")]
+    pub kind: i32,
+    #[prost(uint64, repeated, tag = "2")]
+    pub operands: Vec<u64>,
+    #[prost(uint32, optional, tag = "3")]
+    pub value: Option<u32>,
+    #[prost(string, tag = "4")]
+    pub hint_function: String,
+    #[prost(string, tag = "5")]
+    pub label: String,
+    #[prost(bool, tag = "6")]
+    pub secret: bool,
+    #[prost(bool, tag = "7")]
+    pub public: bool,
+    #[prost(string, tag = "8")]
+    pub operation: String,
+    #[prost(uint64, optional, tag = "9")]
+    pub alias: Option<u64>,
+}
+
+/// `circuit.v1.Node.Kind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum NodeKind {
+    Input = 0,
+    Constant = 1,
+    Add = 2,
+    Mul = 3,
+    MulAdd = 4,
+    Hint = 5,
+    Custom = 6,
+}
+
+/// `circuit.v1.Constraint`: the values of nodes `a` and `b` must be equal.
+#[derive(Clone, PartialEq, Message)]
+pub struct Constraint {
+    #[prost(uint64, tag = "1")]
+    pub a: u64,
+    #[prost(uint64, tag = "2")]
+    pub b: u64,
+    #[prost(string, tag = "3")]
+    pub message: String,
+    #[prost(bool, tag = "4")]
+    pub warning: bool,
+}
+
+/// `circuit.v1.Check`: a constraint other than an equality.
+#[derive(Clone, PartialEq, Message)]
+pub struct Check {
+    #[prost(oneof = "CheckKind", tags = "1, 2, 3, 4")]
+    pub kind: Option<CheckKind>,
+}
+
+/// The `kind` of a `Check`.
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum CheckKind {
+    #[prost(message, tag = "1")]
+    LessThan(LessThan),
+    #[prost(message, tag = "2")]
+    InSet(InSet),
+    #[prost(message, tag = "3")]
+    Permutation(Permutation),
+    #[prost(message, tag = "4")]
+    Range(Range),
+}
+
+/// `circuit.v1.LessThan`: `a < b < 2^bits`.
+#[derive(Clone, PartialEq, Message)]
+pub struct LessThan {
+    #[prost(uint64, tag = "1")]
+    pub a: u64,
+    #[prost(uint64, tag = "2")]
+    pub b: u64,
+    #[prost(uint32, tag = "3")]
+    pub bits: u32,
+}
+
+/// `circuit.v1.InSet`: the value of `node` is one of `set`.
+#[derive(Clone, PartialEq, Message)]
+pub struct InSet {
+    #[prost(uint64, tag = "1")]
+    pub node: u64,
+    #[prost(uint32, repeated, tag = "2")]
+    pub set: Vec<u32>,
+}
+
+/// `circuit.v1.Permutation`: the values of `a` are the values of `b` in some order.
+#[derive(Clone, PartialEq, Message)]
+pub struct Permutation {
+    #[prost(uint64, repeated, tag = "1")]
+    pub a: Vec<u64>,
+    #[prost(uint64, repeated, tag = "2")]
+    pub b: Vec<u64>,
+}
+
+/// `circuit.v1.Range`: `node < 2^bits`.
+#[derive(Clone, PartialEq, Message)]
+pub struct Range {
+    #[prost(uint64, tag = "1")]
+    pub node: u64,
+    #[prost(uint32, tag = "2")]
+    pub bits: u32,
+}
+
+/// `circuit.v1.Witness`: the value of every node, 0 for the nodes listed in `missing`.
+#[derive(Clone, PartialEq, Message)]
+pub struct Witness {
+    #[prost(uint32, repeated, tag = "1")]
+    pub values: Vec<u32>,
+    #[prost(uint64, repeated, tag = "2")]
+    pub missing: Vec<u64>,
+}
+
+fn invalid(message: String) -> SerdeError {
+    SerdeError::InvalidProtobuf(message)
+}
+
+/// Converts a node index of a message, which must refer to one of the first `len` nodes.
+fn index(node: u64, len: usize) -> Result<usize, SerdeError> {
+    usize::try_from(node)
+        .ok()
+        .filter(|&node| node < len)
+        .ok_or_else(|| invalid(format!("node {} does not exist", node)))
+}
+
+impl Builder {
+    /// The graph as a `Circuit` message.
+    pub fn to_protobuf_message(&self) -> Circuit {
+        let nodes = (0..self.graph.len())
+            .map(|id| {
+                let kind = self.graph.kind(id);
+                let operands: Vec<usize> = match kind {
+                    Kind::Input | Kind::Constant => Vec::new(),
+                    Kind::Add | Kind::Mul => {
+                        let (a, b) = self.graph.operands(id).unwrap();
+                        vec![a, b]
+                    }
+                    Kind::MulAdd => self.graph.mul_add(id).unwrap().to_vec(),
+                    Kind::Hint => vec![self.graph.linked(id).unwrap()],
+                    Kind::Custom => self.graph.custom(id).unwrap(),
+                };
+                Node {
+                    kind: match kind {
+                        Kind::Input => NodeKind::Input,
+                        Kind::Constant => NodeKind::Constant,
+                        Kind::Add => NodeKind::Add,
+                        Kind::Mul => NodeKind::Mul,
+                        Kind::MulAdd => NodeKind::MulAdd,
+                        Kind::Hint => NodeKind::Hint,
+                        Kind::Custom => NodeKind::Custom,
+                    } as i32,
+                    operands: operands.into_iter().map(|id| id as u64).collect(),
+                    value: self.graph.output(id),
+                    hint_function: self.hint_calls.get(&id).cloned().unwrap_or_default(),
+                    label: self.labels.get(&id).cloned().unwrap_or_default(),
+                    secret: self.graph.is_secret(id),
+                    public: self.public.contains(&id),
+                    operation: self.custom_calls.get(&id).cloned().unwrap_or_default(),
+                    alias: Some(self.root(id) as u64).filter(|&root| root != id as u64),
+                }
+            })
+            .collect();
+        let constraints = self
+            .constraints
+            .iter()
+            .enumerate()
+            .map(|(constraint, &(a, b))| Constraint {
+                a: a as u64,
+                b: b as u64,
+                message: self
+                    .constraint_messages
+                    .get(&constraint)
+                    .cloned()
+                    .unwrap_or_default(),
+                warning: self.soft_constraints.contains(&constraint),
+            })
+            .collect();
+        let nodes_of = |nodes: &[usize]| nodes.iter().map(|&id| id as u64).collect();
+        let checks = self
+            .checks
+            .iter()
+            .map(|check| Check {
+                kind: Some(match check {
+                    checks::Check::LessThan { a, b, bits } => CheckKind::LessThan(LessThan {
+                        a: *a as u64,
+                        b: *b as u64,
+                        bits: *bits,
+                    }),
+                    checks::Check::InSet { node, set } => CheckKind::InSet(InSet {
+                        node: *node as u64,
+                        set: set.clone(),
+                    }),
+                    checks::Check::Permutation { a, b } => CheckKind::Permutation(Permutation {
+                        a: nodes_of(a),
+                        b: nodes_of(b),
+                    }),
+                    checks::Check::Range { node, bits } => CheckKind::Range(Range {
+                        node: *node as u64,
+                        bits: *bits,
+                    }),
+                }),
+            })
+            .collect();
+        Circuit {
+            nodes,
+            constraints,
+            checks,
+        }
+    }
+
+    /// Serializes the graph (including any filled in outputs) to a protobuf `Circuit`.
+    pub fn to_protobuf(&self) -> Vec<u8> {
+        self.to_protobuf_message().encode_to_vec()
+    }
+
+    /// Builds a graph from a `Circuit` message, checking that every node only refers to nodes
+    /// before it.
+    pub fn from_protobuf_message(circuit: &Circuit) -> Result<Builder, SerdeError> {
+        let mut builder = Builder::new();
+        for (id, node) in circuit.nodes.iter().enumerate() {
+            let kind = NodeKind::try_from(node.kind)
+                .map_err(|_| invalid(format!("node {} has unknown kind {}", id, node.kind)))?;
+            let operands = node
+                .operands
+                .iter()
+                .map(|&operand| index(operand, id))
+                .collect::<Result<Vec<_>, _>>()?;
+            let expected = match kind {
+                NodeKind::Input | NodeKind::Constant => 0,
+                NodeKind::Add | NodeKind::Mul => 2,
+                NodeKind::MulAdd => 3,
+                NodeKind::Hint => 1,
+                NodeKind::Custom => operands.len().clamp(1, crate::custom::MAX_OPERANDS),
+            };
+            if operands.len() != expected {
+                return Err(invalid(format!(
+                    "node {} has {} operands, {:?} nodes have {}",
+                    id,
+                    operands.len(),
+                    kind,
+                    expected
+                )));
+            }
+            let graph = &mut builder.graph;
+            match kind {
+                NodeKind::Input => {
+                    graph.push(Kind::Input, [0, 0], node.value);
+                    builder.input_nodes.push(id);
+                }
+                NodeKind::Constant => {
+                    let value = node
+                        .value
+                        .ok_or_else(|| invalid(format!("constant {} has no value", id)))?;
+                    graph.push(Kind::Constant, [0, 0], Some(value));
+                }
+                NodeKind::Add => {
+                    graph.push(Kind::Add, [operands[0], operands[1]], node.value);
+                }
+                NodeKind::Mul => {
+                    graph.push(Kind::Mul, [operands[0], operands[1]], node.value);
+                }
+                NodeKind::MulAdd => {
+                    graph.push_mul_add([operands[0], operands[1], operands[2]], node.value);
+                }
+                NodeKind::Hint => {
+                    graph.push(Kind::Hint, [operands[0], 0], node.value);
+                    if !node.hint_function.is_empty() {
+                        builder.hint_calls.insert(id, node.hint_function.clone());
+                    }
+                }
+                NodeKind::Custom => {
+                    if node.operation.is_empty() {
+                        return Err(invalid(format!("custom node {} has no operation", id)));
+                    }
+                    graph.push_custom(&operands, node.value);
+                    builder.custom_calls.insert(id, node.operation.clone());
+                }
+            }
+            if node.secret {
+                builder.graph.mark_secret(id);
+            }
+            if !node.label.is_empty() {
+                builder.labels.insert(id, node.label.clone());
+            }
+            if node.public {
+                builder.public.insert(id);
+            }
+            if let Some(alias) = node.alias {
+                let alias = index(alias, id)?;
+                builder.union(alias, id);
+            }
+        }
+
+        let len = circuit.nodes.len();
+        for (id, constraint) in circuit.constraints.iter().enumerate() {
+            builder
+                .constraints
+                .push((index(constraint.a, len)?, index(constraint.b, len)?));
+            if !constraint.message.is_empty() {
+                builder
+                    .constraint_messages
+                    .insert(id, constraint.message.clone());
+            }
+            if constraint.warning {
+                builder.soft_constraints.insert(id);
+            }
+        }
+        let nodes_of = |nodes: &[u64]| -> Result<Vec<usize>, SerdeError> {
+            nodes.iter().map(|&node| index(node, len)).collect()
+        };
+        for (id, check) in circuit.checks.iter().enumerate() {
+            let check = match &check.kind {
+                Some(CheckKind::LessThan(check)) => checks::Check::LessThan {
+                    a: index(check.a, len)?,
+                    b: index(check.b, len)?,
+                    bits: check.bits,
+                },
+                Some(CheckKind::InSet(check)) => checks::Check::InSet {
+                    node: index(check.node, len)?,
+                    set: check
+                        .set
+                        .iter()
+                        .copied()
+                        .collect::<BTreeSet<_>>()
+                        .into_iter()
+                        .collect(),
+                },
+                Some(CheckKind::Permutation(check)) => checks::Check::Permutation {
+                    a: nodes_of(&check.a)?,
+                    b: nodes_of(&check.b)?,
+                },
+                Some(CheckKind::Range(check)) => checks::Check::Range {
+                    node: index(check.node, len)?,
+                    bits: check.bits,
+                },
+                None => return Err(invalid(format!("check {} has no kind", id))),
+            };
+            check.validate(id, len)?;
+            builder.checks.push(check);
+        }
+        Ok(builder)
+    }
+
+    /// Deserializes a graph produced by `to_protobuf`, or by another implementation of `SCHEMA`.
+    /// With `--features zstd`, the bytes may also be compressed.
+    pub fn from_protobuf(bytes: &[u8]) -> Result<Builder, SerdeError> {
+        #[cfg(feature = "zstd")]
+        let bytes = &*crate::compress::decompress(bytes)?;
+        Builder::from_protobuf_message(&Circuit::decode(bytes)?)
+    }
+}
+
+impl crate::Witness {
+    /// The witness as a `Witness` message.
+    pub fn to_protobuf_message(&self) -> Witness {
+        Witness {
+            values: self.values.iter().map(|value| value.unwrap_or(0)).collect(),
+            missing: (0..self.values.len())
+                .filter(|&id| self.values[id].is_none())
+                .map(|id| id as u64)
+                .collect(),
+        }
+    }
+
+    /// Serializes the witness to a protobuf `Witness`.
+    pub fn to_protobuf(&self) -> Vec<u8> {
+        self.to_protobuf_message().encode_to_vec()
+    }
+
+    /// Deserializes a witness produced by `to_protobuf`, compressed or not like a graph.
+    pub fn from_protobuf(bytes: &[u8]) -> Result<crate::Witness, SerdeError> {
+        #[cfg(feature = "zstd")]
+        let bytes = &*crate::compress::decompress(bytes)?;
+        let message = Witness::decode(bytes)?;
+        let mut values: Vec<Option<u32>> = message.values.into_iter().map(Some).collect();
+        for node in message.missing {
+            let node = index(node, values.len())?;
+            values[node] = None;
+        }
+        Ok(crate::Witness {
+            values,
+            redacted: BTreeSet::new(),
+        })
+    }
+}
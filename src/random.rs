@@ -0,0 +1,174 @@
+//! Reproducible random circuits for benchmarking and stress-testing evaluators and exporters.
+//!
+//! `Builder::random(seed, config)` always builds the same circuit for the same seed and config,
+//! on every platform and version of the library's dependencies, since it uses its own
+//! SplitMix64 generator. Circuits are built in layers of gates. A gate reads `fan_in` operands,
+//! at least one from the layer before, and combines them with a tree of add or mul nodes, or
+//! takes the `sqrt` hint of one of them. Inputs are labeled `x0`, `x1`, … and the gates of the
+//! last layer are marked as public outputs.
+//!
+//! The circuit evaluates without overflowing when every input is set to `config.input_value`:
+//! gates that would overflow with those inputs are turned into hints.
+
+use crate::{Builder, NodeId};
+
+/// The shape of a random circuit.
+#[derive(Debug, Clone)]
+pub struct RandomConfig {
+    pub inputs: usize,
+    /// Number of layers of gates.
+    pub depth: usize,
+    /// Number of gates in a layer.
+    pub width: usize,
+    /// Number of operands of an add or mul gate, at least 2.
+    pub fan_in: usize,
+    /// Relative frequencies of add, mul and hint gates.
+    pub add_weight: u32,
+    pub mul_weight: u32,
+    pub hint_weight: u32,
+    /// Chance out of 100 that an operand is a new constant instead of an earlier node.
+    pub constant_percent: u32,
+    /// Number of constraints `hint(sqrt, n * n) == n` on random nodes `n`.
+    pub constraints: usize,
+    /// Input value for which the circuit is guaranteed to evaluate.
+    pub input_value: u32,
+}
+
+impl Default for RandomConfig {
+    fn default() -> Self {
+        RandomConfig {
+            inputs: 4,
+            depth: 8,
+            width: 16,
+            fan_in: 2,
+            add_weight: 4,
+            mul_weight: 4,
+            hint_weight: 1,
+            constant_percent: 10,
+            constraints: 4,
+            input_value: 1,
+        }
+    }
+}
+
+/// SplitMix64, a small generator whose output is fixed by its seed.
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`, for `n > 0`.
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len() as u64) as usize]
+    }
+}
+
+/// A node and its value when every input is `input_value`.
+type Valued = (NodeId, u32);
+
+impl Builder {
+    /// Generates a random circuit. The same seed and config always give the same circuit.
+    pub fn random(seed: u64, config: &RandomConfig) -> Builder {
+        let mut rng = Rng(seed);
+        let mut builder = Builder::new();
+        let mut nodes: Vec<Valued> = Vec::new();
+        for i in 0..config.inputs.max(1) {
+            let x = builder.init();
+            builder.set_label(x, &format!("x{}", i));
+            nodes.push((x, config.input_value));
+        }
+        let mut previous = nodes.clone();
+        let total_weight = config.add_weight + config.mul_weight + config.hint_weight;
+        for _ in 0..config.depth {
+            let mut layer = Vec::with_capacity(config.width);
+            for _ in 0..config.width.max(1) {
+                let roll = rng.below(u64::from(total_weight.max(1))) as u32;
+                let first = rng.pick(&previous);
+                let gate = if roll < config.add_weight + config.mul_weight {
+                    let mul = roll >= config.add_weight;
+                    let mut operands = vec![first];
+                    for _ in 1..config.fan_in.max(2) {
+                        let operand = if rng.below(100) < u64::from(config.constant_percent) {
+                            let value = rng.below(16) as u32;
+                            (builder.constant(value), value)
+                        } else {
+                            rng.pick(&nodes)
+                        };
+                        operands.push(operand);
+                    }
+                    builder.gate(&operands, mul)
+                } else {
+                    None
+                };
+                // Hint gates, and gates that would overflow, take the square root of an operand.
+                let gate =
+                    gate.unwrap_or_else(|| (builder.hint_fn("sqrt", first.0), first.1.isqrt()));
+                layer.push(gate);
+            }
+            nodes.extend(&layer);
+            previous = layer;
+        }
+        for (i, &(node, _)) in previous.iter().enumerate() {
+            builder.mark_public(node);
+            builder.set_label(node, &format!("out{}", i));
+        }
+        for _ in 0..config.constraints {
+            let (node, value) = rng.pick(&nodes);
+            if value.checked_mul(value).is_some() {
+                let square = builder.mul(node, node);
+                let root = builder.hint_fn("sqrt", square);
+                builder.enforce_equal(root, node);
+            }
+        }
+        builder
+    }
+
+    /// Adds or multiplies `operands` pairwise in a balanced tree. Returns None, without adding any
+    /// node, if a value would overflow.
+    fn gate(&mut self, operands: &[Valued], mul: bool) -> Option<Valued> {
+        let apply = |a: u32, b: u32| {
+            if mul {
+                a.checked_mul(b)
+            } else {
+                a.checked_add(b)
+            }
+        };
+        let mut values: Vec<u32> = operands.iter().map(|&(_, value)| value).collect();
+        while values.len() > 1 {
+            let mut next = Vec::with_capacity(values.len().div_ceil(2));
+            for pair in values.chunks(2) {
+                next.push(match *pair {
+                    [a, b] => apply(a, b)?,
+                    [a] => a,
+                    _ => unreachable!(),
+                });
+            }
+            values = next;
+        }
+        let mut level = operands.to_vec();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| match *pair {
+                    [(a, a_value), (b, b_value)] => {
+                        let node = if mul { self.mul(a, b) } else { self.add(a, b) };
+                        (node, apply(a_value, b_value).unwrap())
+                    }
+                    [single] => single,
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        Some(level[0])
+    }
+}
@@ -0,0 +1,290 @@
+//! Finding candidate gadget boundaries in monolithic circuits.
+//!
+//! Generated circuits often inline the same computation many times. `Builder::regions(min_size)`
+//! finds the single-entry, single-exit regions of a graph: subgraphs that read one node from the
+//! rest of the graph (besides constants), the entry, and whose values only leave through one node,
+//! the exit. In terms of dominators, every path from the inputs to a node of the region goes
+//! through the entry, and every path from it to an output, a constraint or a check goes through
+//! the exit. Such a region computes a function of its entry and could be replaced by a call to a
+//! template with one input and one output.
+//!
+//! Regions nest, so for every node the smallest region of at least `min_size` nodes it exits is
+//! considered, and the largest of these regions for each entry is reported, with its size, number
+//! of multiplications, degree and depth. Regions computing the same thing in the same way have the
+//! same `shape`, so `by_shape` finds the computations worth turning into a reusable template:
+//!
+//! ```
+//! use my_graph_lib::{regions, Builder};
+//!
+//! let mut builder = Builder::new();
+//! let one = builder.constant(1);
+//! let mut x = builder.init();
+//! for _ in 0..3 {
+//!     // x^3 + 1, three times in a row.
+//!     let square = builder.square(x);
+//!     let cube = builder.mul(square, x);
+//!     x = builder.add(cube, one);
+//! }
+//! builder.mark_public(x);
+//! let found = builder.regions(2);
+//! assert_eq!(found.len(), 3);
+//! assert_eq!(found[2].exit, x);
+//! // The square, the cube and the sum.
+//! assert_eq!((found[0].nodes.len(), found[0].degree, found[0].depth), (3, 3, 3));
+//! let groups = regions::by_shape(&found);
+//! assert_eq!(groups.len(), 1);
+//! ```
+//!
+//! Constants are left out of regions, as any template can build them. Nodes read before they
+//! are computed (forward references) are not analyzed correctly.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use crate::storage::Kind;
+use crate::{Builder, NodeId};
+
+/// A single-entry, single-exit region of a graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    /// The only node outside the region it reads, constants aside.
+    pub entry: NodeId,
+    /// The only node of the region read outside it, or observed by a public output, a constraint
+    /// or a check.
+    pub exit: NodeId,
+    /// The nodes of the region, without its entry, in index order. The exit is the last one.
+    pub nodes: Vec<NodeId>,
+    /// The number of multiplications and multiply-adds.
+    pub multiplications: usize,
+    /// The degree of the exit as a polynomial in the entry, with hints as fresh variables of
+    /// degree 1.
+    pub degree: u32,
+    /// The number of nodes on the longest path from the entry to the exit.
+    pub depth: usize,
+    /// A hash of the region's computation relative to its entry: regions with the same shape
+    /// compute the same function in the same way.
+    pub shape: u64,
+}
+
+/// The indices in `regions` of the regions of each shape that occurs more than once, by shape.
+pub fn by_shape(regions: &[Region]) -> BTreeMap<u64, Vec<usize>> {
+    let mut groups: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for (i, region) in regions.iter().enumerate() {
+        groups.entry(region.shape).or_default().push(i);
+    }
+    groups.retain(|_, group| group.len() > 1);
+    groups
+}
+
+/// A dominator tree over the nodes of a graph and a virtual root, with the interval of every
+/// node in a depth-first walk so that ancestors can be told apart in constant time.
+struct Tree {
+    parent: Vec<usize>,
+    enter: Vec<usize>,
+    leave: Vec<usize>,
+}
+
+impl Tree {
+    /// Builds the tree of a DAG from the predecessors of each node, given in an order where
+    /// predecessors come first. The root is the index after the last node: nodes without
+    /// predecessors hang from it, and it may be a predecessor too.
+    fn new(order: impl Iterator<Item = usize>, predecessors: &[Vec<usize>]) -> Self {
+        let root = predecessors.len();
+        let mut parent = vec![root; root + 1];
+        let mut depth = vec![0; root + 1];
+        for node in order {
+            let mut preds = predecessors[node].iter().copied();
+            let Some(first) = preds.next() else {
+                depth[node] = 1;
+                continue;
+            };
+            let idom = preds.fold(first, |mut a, mut b| {
+                while a != b {
+                    if depth[a] >= depth[b] {
+                        a = parent[a];
+                    } else {
+                        b = parent[b];
+                    }
+                }
+                a
+            });
+            parent[node] = idom;
+            depth[node] = depth[idom] + 1;
+        }
+        let mut children = vec![Vec::new(); root + 1];
+        for node in 0..root {
+            children[parent[node]].push(node);
+        }
+        let (mut enter, mut leave) = (vec![0; root + 1], vec![0; root + 1]);
+        let mut clock = 0;
+        let mut stack = vec![(root, false)];
+        while let Some((node, done)) = stack.pop() {
+            if done {
+                leave[node] = clock;
+                continue;
+            }
+            enter[node] = clock;
+            clock += 1;
+            stack.push((node, true));
+            stack.extend(children[node].iter().map(|&child| (child, false)));
+        }
+        Tree {
+            parent,
+            enter,
+            leave,
+        }
+    }
+
+    /// Whether `a` is `b` or one of its ancestors.
+    fn dominates(&self, a: usize, b: usize) -> bool {
+        self.enter[a] <= self.enter[b] && self.enter[b] < self.leave[a]
+    }
+}
+
+impl Builder {
+    /// The smallest single-entry, single-exit region ending at each node, for the regions of at
+    /// least `min_size` nodes, ordered by exit. See the `regions` module.
+    pub fn regions(&self, min_size: usize) -> Vec<Region> {
+        let len = self.graph.len();
+        let is_constant = |id: usize| self.graph.kind(id) == Kind::Constant;
+        let mut sources = vec![Vec::new(); len];
+        let mut consumers = vec![Vec::new(); len];
+        for id in (0..len).filter(|&id| !is_constant(id)) {
+            for source in self.sources(id).filter(|&source| !is_constant(source)) {
+                sources[id].push(source);
+                consumers[source].push(id);
+            }
+        }
+        let mut observed = vec![false; len];
+        for &id in &self.public {
+            observed[id] = true;
+        }
+        for &(a, b) in &self.constraints {
+            observed[a] = true;
+            observed[b] = true;
+        }
+        for id in self.checks.iter().flat_map(|check| check.nodes()) {
+            observed[id] = true;
+        }
+        // The successors of a node on its way to the outputs, with a virtual sink for the nodes
+        // that are observed or not read.
+        let sink = len;
+        let successors: Vec<Vec<usize>> = (0..len)
+            .map(|id| {
+                let mut successors = consumers[id].clone();
+                if (observed[id] || successors.is_empty()) && !is_constant(id) {
+                    successors.push(sink);
+                }
+                successors
+            })
+            .collect();
+        let dominators = Tree::new((0..len).filter(|&id| !is_constant(id)), &sources);
+        // Post-dominators are dominators of the reversed graph, rooted at the sink.
+        let post_dominators = Tree::new((0..len).rev().filter(|&id| !is_constant(id)), &successors);
+
+        // The smallest large enough region exiting at each node, and the largest of those
+        // starting at each entry: regions with the same entry are nested.
+        let mut largest: BTreeMap<usize, Region> = BTreeMap::new();
+        for exit in (0..len).filter(|&id| !is_constant(id) && !sources[id].is_empty()) {
+            let mut entry = dominators.parent[exit];
+            // A path from `entry` to the outputs avoiding `exit` extends to the nodes dominating
+            // `entry`, so the search stops at the first entry `exit` doesn't post-dominate.
+            while entry != len && post_dominators.dominates(exit, entry) {
+                if let Some(region) = self.region(entry, exit, &sources, min_size) {
+                    let kept = largest.get(&entry);
+                    if kept.is_none_or(|kept| kept.nodes.len() < region.nodes.len()) {
+                        largest.insert(entry, region);
+                    }
+                    break;
+                }
+                entry = dominators.parent[entry];
+            }
+        }
+        let mut regions: Vec<Region> = largest.into_values().collect();
+        regions.sort_by_key(|region| region.exit.index);
+        regions
+    }
+
+    /// The region between `entry` and `exit`: the nodes reaching `exit` without going through
+    /// `entry`, which `entry` dominates and `exit` post-dominates.
+    fn region(
+        &self,
+        entry: usize,
+        exit: usize,
+        sources: &[Vec<usize>],
+        min_size: usize,
+    ) -> Option<Region> {
+        let mut inside = vec![exit];
+        let mut seen = HashSet::from([entry, exit]);
+        let mut i = 0;
+        while i < inside.len() {
+            for &source in &sources[inside[i]] {
+                if seen.insert(source) {
+                    inside.push(source);
+                }
+            }
+            i += 1;
+        }
+        if inside.len() < min_size {
+            return None;
+        }
+        inside.sort_unstable();
+
+        let mut local = BTreeMap::from([(entry, (0usize, 1u32, 0usize))]);
+        let mut shape = DefaultHasher::new();
+        let mut multiplications = 0;
+        for (position, &id) in inside.iter().enumerate() {
+            let kind = self.graph.kind(id);
+            format!("{:?}", kind).hash(&mut shape);
+            self.hint_calls.get(&id).hash(&mut shape);
+            self.custom_calls.get(&id).hash(&mut shape);
+            let operands: Vec<usize> = match kind {
+                Kind::Add | Kind::Mul => {
+                    let (a, b) = self.graph.operands(id).unwrap();
+                    vec![a, b]
+                }
+                Kind::MulAdd => self.graph.mul_add(id).unwrap().to_vec(),
+                Kind::Hint => self.graph.linked(id).into_iter().collect(),
+                Kind::Custom => self.graph.custom(id).unwrap(),
+                Kind::Input | Kind::Constant => Vec::new(),
+            };
+            // Each operand as its position in the region (the entry is 0) or a constant value.
+            let mut read = Vec::with_capacity(operands.len());
+            for &operand in &operands {
+                match local.get(&operand) {
+                    Some(&(index, degree, depth)) => {
+                        (0u8, index).hash(&mut shape);
+                        read.push((degree, depth));
+                    }
+                    None => {
+                        (1u8, self.graph.output(operand)).hash(&mut shape);
+                        read.push((0, 0));
+                    }
+                }
+            }
+            let degree = match kind {
+                Kind::Add => read[0].0.max(read[1].0),
+                Kind::Mul => read[0].0.saturating_add(read[1].0),
+                Kind::MulAdd => read[0].0.saturating_add(read[1].0).max(read[2].0),
+                Kind::Hint | Kind::Custom | Kind::Input => 1,
+                Kind::Constant => 0,
+            };
+            if matches!(kind, Kind::Mul | Kind::MulAdd) {
+                multiplications += 1;
+            }
+            let depth = 1 + read.iter().map(|&(_, depth)| depth).max().unwrap_or(0);
+            local.insert(id, (position + 1, degree, depth));
+        }
+        let (_, degree, depth) = local[&exit];
+        Some(Region {
+            entry: self.id(entry),
+            exit: self.id(exit),
+            nodes: inside.iter().map(|&id| self.id(id)).collect(),
+            multiplications,
+            degree,
+            depth,
+            shape: shape.finish(),
+        })
+    }
+}
@@ -0,0 +1,394 @@
+//! A machine-readable report on a constraint system, for dashboards and CI gates.
+//!
+//! `Builder::report` gathers in one `Report`:
+//!
+//! - node counts by kind, the number of public outputs and the depth of the graph,
+//! - a histogram of the degree of every node as a polynomial in the inputs and hints,
+//! - constraint and check counts by kind,
+//! - lint findings: nodes nothing needs, unused inputs, hints no constraint or check pins down,
+//!   constraints of a node with itself, nodes that may overflow, and public outputs computed from
+//!   private inputs,
+//! - the cost of the graph in the backends of the crate: R1CS constraints and variables, Plonkish
+//!   rows and gates, lookups and boolean gates.
+//!
+//! It serializes to JSON with `to_json`, the output of `circuit report --json`, and displays as a
+//! short text summary. Overflows are found with `Builder::bounds`, taking the range checks on
+//! inputs as their ranges.
+//!
+//! ```
+//! use my_graph_lib::report::Lint;
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let root = builder.hint_fn("sqrt", x);
+//! let square = builder.mul(x, x);
+//! builder.mark_public(square);
+//! let report = builder.report();
+//! assert_eq!(report.stats.nodes, 3);
+//! assert_eq!(report.degrees[&2], 1);
+//! assert_eq!(report.lints[0].lint, Lint::DeadNode);
+//! assert_eq!(report.lints[0].node, root.index());
+//! assert!(report.to_json().contains("\"r1cs_constraints\""));
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::checks::Check;
+use crate::storage::Kind;
+use crate::{boolean, export, lookup, plonkish, Builder};
+
+/// Everything `Builder::report` finds, see the module documentation.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub stats: Stats,
+    /// The number of nodes of each degree. Inputs, hints and custom nodes have degree 1 and
+    /// constants degree 0.
+    pub degrees: BTreeMap<u32, usize>,
+    pub constraints: ConstraintCounts,
+    /// The findings, by lint and then by node.
+    pub lints: Vec<Finding>,
+    /// None if the graph has custom nodes, which the backends can't express until they are
+    /// lowered.
+    pub costs: Option<Costs>,
+}
+
+/// Node counts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    pub nodes: usize,
+    pub inputs: usize,
+    pub constants: usize,
+    pub adds: usize,
+    pub muls: usize,
+    pub mul_adds: usize,
+    /// Hints computed by a hint function.
+    pub hints: usize,
+    /// Hints with a fixed value.
+    pub fixed_hints: usize,
+    /// Nodes computing a user-defined operation.
+    pub customs: usize,
+    pub public: usize,
+    /// The highest level of `Builder::levels`.
+    pub depth: usize,
+}
+
+/// Constraint and check counts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConstraintCounts {
+    pub equalities: usize,
+    /// Equality constraints with the `Warning` severity.
+    pub warnings: usize,
+    pub less_than: usize,
+    pub in_set: usize,
+    pub permutation: usize,
+    pub range: usize,
+}
+
+/// A kind of finding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lint {
+    /// A node that no public output, constraint or check needs.
+    DeadNode,
+    /// An input that no public output, constraint or check needs.
+    UnusedInput,
+    /// A hint computed by a function whose value no constraint or check depends on, so a prover
+    /// may replace it with anything.
+    UnconstrainedHint,
+    /// A constraint of a node with itself, which always holds.
+    TrivialConstraint,
+    /// A node whose evaluation may overflow.
+    PossibleOverflow,
+    /// A public output computed from private inputs.
+    SecretOutput,
+}
+
+impl Lint {
+    /// The name of the lint, as serialized.
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::DeadNode => "dead_node",
+            Lint::UnusedInput => "unused_input",
+            Lint::UnconstrainedHint => "unconstrained_hint",
+            Lint::TrivialConstraint => "trivial_constraint",
+            Lint::PossibleOverflow => "possible_overflow",
+            Lint::SecretOutput => "secret_output",
+        }
+    }
+}
+
+/// A lint finding on a node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub lint: Lint,
+    pub node: usize,
+    pub message: String,
+}
+
+/// The cost of the graph in each backend.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Costs {
+    pub r1cs_constraints: usize,
+    pub r1cs_variables: usize,
+    /// Rows and custom gates of `plonkish::extract` with the CLI's degree and width.
+    pub plonkish_rows: usize,
+    pub plonkish_gates: usize,
+    /// Lookups of `lookup::export` with the CLI's widest table.
+    pub lookups: usize,
+    /// AND and XOR gates of `boolean::lower`.
+    pub boolean_gates: usize,
+}
+
+impl Report {
+    /// The report as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a report always serializes")
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stats = &self.stats;
+        writeln!(
+            f,
+            "nodes: {} ({} inputs, {} constants, {} adds, {} muls, {} mul-adds, {} hints, {} fixed \
+             hints, {} custom), {} public, depth {}",
+            stats.nodes,
+            stats.inputs,
+            stats.constants,
+            stats.adds,
+            stats.muls,
+            stats.mul_adds,
+            stats.hints,
+            stats.fixed_hints,
+            stats.customs,
+            stats.public,
+            stats.depth
+        )?;
+        let degrees: Vec<String> = self
+            .degrees
+            .iter()
+            .map(|(degree, count)| format!("{}: {}", degree, count))
+            .collect();
+        writeln!(f, "degrees: {}", degrees.join(", "))?;
+        let counts = &self.constraints;
+        writeln!(
+            f,
+            "constraints: {} equalities, {} warnings; checks: {} less-than, {} in-set, {} \
+             permutation, {} range",
+            counts.equalities,
+            counts.warnings,
+            counts.less_than,
+            counts.in_set,
+            counts.permutation,
+            counts.range
+        )?;
+        match &self.costs {
+            Some(costs) => writeln!(
+                f,
+                "costs: {} R1CS constraints over {} variables, {} Plonkish rows with {} gates, {} \
+                 lookups, {} boolean gates",
+                costs.r1cs_constraints,
+                costs.r1cs_variables,
+                costs.plonkish_rows,
+                costs.plonkish_gates,
+                costs.lookups,
+                costs.boolean_gates
+            )?,
+            None => writeln!(f, "costs: unknown until the custom nodes are lowered")?,
+        }
+        write!(f, "lints: {}", self.lints.len())?;
+        for finding in &self.lints {
+            write!(f, "\n  {}: {}", finding.lint.name(), finding.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl Builder {
+    /// Reports on the graph, see the module documentation.
+    pub fn report(&self) -> Report {
+        let len = self.graph.len();
+        let count = |kind: Kind| (0..len).filter(|&id| self.graph.kind(id) == kind).count();
+        let hints = self.hint_calls.len();
+        let stats = Stats {
+            nodes: len,
+            inputs: count(Kind::Input),
+            constants: count(Kind::Constant),
+            adds: count(Kind::Add),
+            muls: count(Kind::Mul),
+            mul_adds: count(Kind::MulAdd),
+            hints,
+            fixed_hints: count(Kind::Hint) - hints,
+            customs: count(Kind::Custom),
+            public: self.public.len(),
+            depth: self.levels().into_iter().max().unwrap_or(0),
+        };
+
+        let mut degree = vec![0u32; len];
+        for id in 0..len {
+            degree[id] = match self.graph.kind(id) {
+                Kind::Constant => 0,
+                Kind::Input | Kind::Hint | Kind::Custom => 1,
+                Kind::Add => {
+                    let (a, b) = self.graph.operands(id).unwrap();
+                    degree[a].max(degree[b])
+                }
+                Kind::Mul => {
+                    let (a, b) = self.graph.operands(id).unwrap();
+                    degree[a].saturating_add(degree[b])
+                }
+                Kind::MulAdd => {
+                    let [a, b, c] = self.graph.mul_add(id).unwrap();
+                    degree[a].saturating_add(degree[b]).max(degree[c])
+                }
+            };
+        }
+        let mut degrees = BTreeMap::new();
+        for &degree in &degree {
+            *degrees.entry(degree).or_insert(0) += 1;
+        }
+
+        let soft = self.soft_constraints.len();
+        let checks =
+            |matches: fn(&Check) -> bool| self.checks.iter().filter(|c| matches(c)).count();
+        let constraints = ConstraintCounts {
+            equalities: self.constraints.len() - soft,
+            warnings: soft,
+            less_than: checks(|check| matches!(check, Check::LessThan { .. })),
+            in_set: checks(|check| matches!(check, Check::InSet { .. })),
+            permutation: checks(|check| matches!(check, Check::Permutation { .. })),
+            range: checks(|check| matches!(check, Check::Range { .. })),
+        };
+
+        let costs = self.check_lowered().is_ok().then(|| {
+            let r1cs = export::to_r1cs(self);
+            let plonkish =
+                plonkish::extract(self, plonkish::DEFAULT_DEGREE, plonkish::DEFAULT_WIDTH);
+            Costs {
+                r1cs_constraints: r1cs.constraints.len(),
+                r1cs_variables: r1cs.num_variables,
+                plonkish_rows: plonkish.rows.len(),
+                plonkish_gates: plonkish.gates.len(),
+                lookups: lookup::export(self, lookup::DEFAULT_MAX_BITS).lookups.len(),
+                boolean_gates: boolean::lower(self).gates.len(),
+            }
+        });
+
+        Report {
+            stats,
+            degrees,
+            constraints,
+            lints: self.lints(),
+            costs,
+        }
+    }
+
+    /// The lint findings of `report`, by lint and then by node.
+    fn lints(&self) -> Vec<Finding> {
+        let len = self.graph.len();
+        let mut findings = Vec::new();
+        let mut finding = |lint: Lint, node: usize, message: String| {
+            findings.push(Finding {
+                lint,
+                node,
+                message,
+            })
+        };
+
+        // What public outputs, constraints and checks need, through operands and hint links.
+        let mut live = vec![false; len];
+        // What constraints and checks depend on, through operands only: a hint's link doesn't
+        // constrain its value.
+        let mut pinned = vec![false; len];
+        for &id in &self.public {
+            live[id] = true;
+        }
+        for id in self
+            .constraints
+            .iter()
+            .flat_map(|&(a, b)| [a, b])
+            .chain(self.checks.iter().flat_map(Check::nodes))
+        {
+            live[id] = true;
+            pinned[id] = true;
+        }
+        for id in (0..len).rev() {
+            if live[id] {
+                for source in self.sources(id).collect::<Vec<_>>() {
+                    live[source] = true;
+                }
+            }
+            if pinned[id] && !self.hint_calls.contains_key(&id) {
+                for read in self.reads(id).into_iter().flatten() {
+                    pinned[read] = true;
+                }
+            }
+        }
+
+        for id in (0..len).filter(|&id| !live[id]) {
+            if self.graph.kind(id) == Kind::Input {
+                let message = format!(
+                    "input {} is not needed by any output, constraint or check",
+                    id
+                );
+                finding(Lint::UnusedInput, id, message);
+            } else {
+                let message = format!(
+                    "node {} is not needed by any output, constraint or check",
+                    id
+                );
+                finding(Lint::DeadNode, id, message);
+            }
+        }
+        for (&id, name) in &self.hint_calls {
+            if live[id] && !pinned[id] {
+                let message = format!(
+                    "hint {} ({}) is not checked by any constraint or check, so any value passes",
+                    id, name
+                );
+                finding(Lint::UnconstrainedHint, id, message);
+            }
+        }
+        for (constraint, &(a, b)) in self.constraints.iter().enumerate() {
+            if a == b {
+                let message = format!("constraint {} compares node {} with itself", constraint, a);
+                finding(Lint::TrivialConstraint, a, message);
+            }
+        }
+        let ranges: Vec<_> = self
+            .checks
+            .iter()
+            .filter_map(|check| match *check {
+                Check::Range { node, bits } if self.graph.kind(node) == Kind::Input => {
+                    let max = if bits >= 32 {
+                        u32::MAX
+                    } else {
+                        (1 << bits) - 1
+                    };
+                    Some((self.id(node), 0..=max))
+                }
+                _ => None,
+            })
+            .collect();
+        for id in self.bounds(&ranges).overflows() {
+            let message = format!("node {} may overflow a u32 within the input ranges", id);
+            finding(Lint::PossibleOverflow, id, message);
+        }
+        for output in self.taint() {
+            let id = output.output.index();
+            let message = format!(
+                "public output {} is computed from {} private inputs",
+                id,
+                output.sources.len()
+            );
+            finding(Lint::SecretOutput, id, message);
+        }
+        findings.sort_by_key(|finding| (finding.lint, finding.node));
+        findings
+    }
+}
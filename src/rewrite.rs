@@ -0,0 +1,571 @@
+//! A rewrite engine for peephole optimizations defined outside the library.
+//!
+//! A `Rule` replaces every subgraph matching a pattern with a template computing the same value,
+//! e.g. `(a * c1) * c2 → a * (c1 * c2)`, which folds two multiplications by constants into one:
+//!
+//! ```
+//! use my_graph_lib::rewrite::{constant, var, Rewriter, Rule};
+//!
+//! let rewriter = Rewriter::new().rule(Rule::new(
+//!     "fold-mul",
+//!     (var("a") * constant("c1")) * constant("c2"),
+//!     var("a") * (constant("c1") * constant("c2")),
+//! ));
+//! ```
+//!
+//! Patterns and templates are `Term`s. In a pattern, `var` matches any node, `constant` a constant
+//! node and `value` a constant node with the given value, and a name used twice must match the same
+//! node or value. Additions and multiplications match their operands in either order. In a
+//! template, operations whose operands are both constants are computed right away, so the rule
+//! above creates a single constant; a rule whose constants overflow is not applied.
+//!
+//! `Rewriter::run` copies the graph node by node, rewriting each addition and multiplication with
+//! the first rule that matches until none does, and drops the nodes that were only used by the
+//! replaced ones. Passes are repeated until no rule applies, for at most `max_passes` passes, and a
+//! pass that increases the cost of the graph (by default its number of additions and
+//! multiplications) is undone. The rewritten graph keeps the constraints, checks, labels and public
+//! outputs of the original one, moved to the nodes that replaced theirs, but no values other than
+//! constants and fixed hints.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops;
+
+use crate::storage::Kind;
+use crate::{Builder, NodeId};
+
+/// A pattern or template of a rewrite rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+    /// Any node.
+    Var(String),
+    /// Any constant node, standing for its value.
+    Constant(String),
+    /// A constant node with this value.
+    Value(u32),
+    Add(Box<Term>, Box<Term>),
+    Mul(Box<Term>, Box<Term>),
+}
+
+/// A term matching any node, bound to `name`.
+pub fn var(name: &str) -> Term {
+    Term::Var(name.to_string())
+}
+
+/// A term matching any constant node, whose value is bound to `name`.
+pub fn constant(name: &str) -> Term {
+    Term::Constant(name.to_string())
+}
+
+/// A term matching a constant node with the given value.
+pub fn value(value: u32) -> Term {
+    Term::Value(value)
+}
+
+impl ops::Add for Term {
+    type Output = Term;
+
+    fn add(self, rhs: Term) -> Term {
+        Term::Add(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl ops::Mul for Term {
+    type Output = Term;
+
+    fn mul(self, rhs: Term) -> Term {
+        Term::Mul(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Term::Var(name) | Term::Constant(name) => write!(f, "{}", name),
+            Term::Value(value) => write!(f, "{}", value),
+            Term::Add(a, b) => write!(f, "({} + {})", a, b),
+            Term::Mul(a, b) => write!(f, "({} * {})", a, b),
+        }
+    }
+}
+
+impl Term {
+    /// Calls `f` with every name bound by the term and whether it stands for a constant.
+    fn names(&self, f: &mut impl FnMut(&str, bool)) {
+        match self {
+            Term::Var(name) => f(name, false),
+            Term::Constant(name) => f(name, true),
+            Term::Value(_) => {}
+            Term::Add(a, b) | Term::Mul(a, b) => {
+                a.names(f);
+                b.names(f);
+            }
+        }
+    }
+}
+
+/// A rewrite rule: nodes matching `pattern` are replaced with `template`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub name: String,
+    pub pattern: Term,
+    pub template: Term,
+}
+
+impl Rule {
+    /// Creates a rule. Panics if the template uses a name the pattern does not bind, or uses it as
+    /// a node where the pattern binds a constant or the other way around.
+    pub fn new(name: &str, pattern: Term, template: Term) -> Rule {
+        let mut bound = BTreeMap::new();
+        pattern.names(&mut |name, is_constant| {
+            bound.insert(name.to_string(), is_constant);
+        });
+        template.names(&mut |used, is_constant| {
+            assert!(
+                bound.get(used) == Some(&is_constant),
+                "rule `{}` uses `{}` in its template, but its pattern does not bind it as a {}",
+                name,
+                used,
+                if is_constant { "constant" } else { "variable" }
+            );
+        });
+        Rule {
+            name: name.to_string(),
+            pattern,
+            template,
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.name, self.pattern, self.template)
+    }
+}
+
+/// What a pattern bound while matching a node.
+#[derive(Debug, Clone, Default)]
+struct Bindings {
+    nodes: BTreeMap<String, usize>,
+    constants: BTreeMap<String, u32>,
+}
+
+/// The result of instantiating a template: a node, or a constant not yet added to the graph.
+enum Instance {
+    Node(usize),
+    Value(u32),
+}
+
+/// A cost function for graphs. Lower is better.
+pub type Cost = fn(&Builder) -> usize;
+
+/// The default cost: the number of additions, multiplications and mul-adds.
+pub fn operation_count(builder: &Builder) -> usize {
+    (0..builder.graph.len())
+        .filter(|&id| matches!(builder.graph.kind(id), Kind::Add | Kind::Mul | Kind::MulAdd))
+        .count()
+}
+
+/// Applies rewrite rules to graphs.
+#[derive(Debug, Clone)]
+pub struct Rewriter {
+    rules: Vec<Rule>,
+    max_passes: usize,
+    cost: Cost,
+}
+
+impl Default for Rewriter {
+    fn default() -> Self {
+        Rewriter {
+            rules: Vec::new(),
+            max_passes: 16,
+            cost: operation_count,
+        }
+    }
+}
+
+/// The result of `Rewriter::run`.
+#[derive(Debug, Clone)]
+pub struct Rewritten {
+    pub builder: Builder,
+    /// The id in `builder` of the node standing for each node of the original graph that was
+    /// kept. Nodes only used to compute replaced nodes are dropped.
+    pub ids: BTreeMap<NodeId, NodeId>,
+    /// Number of times each rule was applied, by name, in the passes that were kept.
+    pub applied: BTreeMap<String, usize>,
+    /// Number of passes run, including the last one, in which no rule applied or the cost grew.
+    pub passes: usize,
+    pub cost_before: usize,
+    pub cost_after: usize,
+}
+
+impl Rewriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a rule. Rules are tried in the order they were added.
+    pub fn rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Sets the largest number of passes over the graph (16 by default).
+    pub fn max_passes(mut self, max_passes: usize) -> Self {
+        self.max_passes = max_passes;
+        self
+    }
+
+    /// Sets the cost function that rewriting must not increase.
+    pub fn cost(mut self, cost: Cost) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Rewrites a copy of `builder` until no rule applies.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "rewrite", level = "debug", skip_all, fields(nodes = builder.graph.len(), rules = self.rules.len()))
+    )]
+    pub fn run(&self, builder: &Builder) -> Rewritten {
+        #[cfg(feature = "tracing")]
+        let start = std::time::Instant::now();
+        let cost_before = (self.cost)(builder);
+        let mut current = builder.clone();
+        let mut map: Vec<Option<usize>> = (0..builder.graph.len()).map(Some).collect();
+        let mut cost = cost_before;
+        let mut applied = BTreeMap::new();
+        let mut passes = 0;
+        while passes < self.max_passes {
+            passes += 1;
+            let mut counts = BTreeMap::new();
+            let (next, next_map) =
+                current.rebuild(|graph, node| self.rewrite(graph, node, &mut counts));
+            if counts.is_empty() {
+                break;
+            }
+            let next_cost = (self.cost)(&next);
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                pass = passes,
+                nodes_before = current.graph.len(),
+                nodes_after = next.graph.len(),
+                rewrites = counts.values().sum::<usize>(),
+                cost = next_cost,
+                "rewrite pass"
+            );
+            if next_cost > cost {
+                break;
+            }
+            for (name, count) in counts {
+                *applied.entry(name).or_insert(0) += count;
+            }
+            map = map.into_iter().map(|node| next_map[node?]).collect();
+            current = next;
+            cost = next_cost;
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            nodes_after = current.graph.len(),
+            passes,
+            cost_before,
+            cost_after = cost,
+            elapsed_us = start.elapsed().as_micros() as u64,
+            "rewrote graph"
+        );
+        let ids = map
+            .iter()
+            .enumerate()
+            .filter_map(|(old, &new)| Some((builder.id(old), current.id(new?))))
+            .collect();
+        Rewritten {
+            builder: current,
+            ids,
+            applied,
+            passes,
+            cost_before,
+            cost_after: cost,
+        }
+    }
+
+    /// Rewrites `node` with the first matching rule until none matches, or until it was rewritten
+    /// `max_passes` times, and returns the node standing for it. Only additions and
+    /// multiplications are rewritten.
+    fn rewrite(
+        &self,
+        graph: &mut Builder,
+        mut node: usize,
+        counts: &mut BTreeMap<String, usize>,
+    ) -> usize {
+        for _ in 0..self.max_passes {
+            if !matches!(graph.graph.kind(node), Kind::Add | Kind::Mul) {
+                break;
+            }
+            let rewritten = self.rules.iter().find_map(|rule| {
+                let mut bindings = Bindings::default();
+                if !graph.matches(&rule.pattern, node, &mut bindings) {
+                    return None;
+                }
+                let replacement = match graph.instantiate(&rule.template, &bindings)? {
+                    Instance::Node(replacement) => replacement,
+                    Instance::Value(value) => {
+                        graph.push_rebuilt(Kind::Constant, [0, 0], Some(value))
+                    }
+                };
+                Some((rule, replacement))
+            });
+            let Some((rule, replacement)) = rewritten else {
+                break;
+            };
+            *counts.entry(rule.name.clone()).or_insert(0) += 1;
+            node = replacement;
+        }
+        node
+    }
+}
+
+impl Builder {
+    /// Returns true if `node` matches `pattern`, extending `bindings` with what the pattern binds.
+    fn matches(&self, pattern: &Term, node: usize, bindings: &mut Bindings) -> bool {
+        match pattern {
+            Term::Var(name) => match bindings.nodes.get(name) {
+                Some(&bound) => bound == node,
+                None => {
+                    bindings.nodes.insert(name.clone(), node);
+                    true
+                }
+            },
+            Term::Constant(name) => {
+                if self.graph.kind(node) != Kind::Constant {
+                    return false;
+                }
+                let value = self.graph.output(node).unwrap();
+                match bindings.constants.get(name) {
+                    Some(&bound) => bound == value,
+                    None => {
+                        bindings.constants.insert(name.clone(), value);
+                        true
+                    }
+                }
+            }
+            Term::Value(value) => {
+                self.graph.kind(node) == Kind::Constant && self.graph.output(node) == Some(*value)
+            }
+            Term::Add(p, q) | Term::Mul(p, q) => {
+                let kind = if matches!(pattern, Term::Add(..)) {
+                    Kind::Add
+                } else {
+                    Kind::Mul
+                };
+                if self.graph.kind(node) != kind {
+                    return false;
+                }
+                let (a, b) = self.graph.operands(node).unwrap();
+                for (x, y) in [(a, b), (b, a)] {
+                    let mut attempt = bindings.clone();
+                    if self.matches(p, x, &mut attempt) && self.matches(q, y, &mut attempt) {
+                        *bindings = attempt;
+                        return true;
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    /// Adds the nodes of a template to the graph. Returns None if a constant overflows.
+    fn instantiate(&mut self, template: &Term, bindings: &Bindings) -> Option<Instance> {
+        Some(match template {
+            Term::Var(name) => Instance::Node(bindings.nodes[name]),
+            Term::Constant(name) => Instance::Value(bindings.constants[name]),
+            Term::Value(value) => Instance::Value(*value),
+            Term::Add(p, q) | Term::Mul(p, q) => {
+                let is_add = matches!(template, Term::Add(..));
+                let (a, b) = (
+                    self.instantiate(p, bindings)?,
+                    self.instantiate(q, bindings)?,
+                );
+                if let (Instance::Value(a), Instance::Value(b)) = (&a, &b) {
+                    let value = if is_add {
+                        a.checked_add(*b)
+                    } else {
+                        a.checked_mul(*b)
+                    };
+                    return value.map(Instance::Value);
+                }
+                let mut materialize = |instance| match instance {
+                    Instance::Node(node) => node,
+                    Instance::Value(value) => {
+                        self.push_rebuilt(Kind::Constant, [0, 0], Some(value))
+                    }
+                };
+                let (a, b) = (materialize(a), materialize(b));
+                let kind = if is_add { Kind::Add } else { Kind::Mul };
+                Instance::Node(self.push_rebuilt(kind, [a, b], None))
+            }
+        })
+    }
+
+    /// Adds a node to a graph being rebuilt.
+    fn push_rebuilt(&mut self, kind: Kind, inputs: [usize; 2], output: Option<u32>) -> usize {
+        self.graph.push(kind, inputs, output)
+    }
+
+    /// Copies the graph node by node into a new builder, without values other than constants and
+    /// fixed hints. `on_node` is called with each copied node, once its operands have been
+    /// copied, and returns the node of the new graph standing for it, which may be another node
+    /// computing the same value. Constraints, checks, aliases, labels and the other properties of
+    /// a node are moved to the node standing for it.
+    ///
+    /// Nodes that are not needed to compute the nodes standing for an input, a public output, a
+    /// constrained, aliased, labeled or annotated node, or a node that no node used, are dropped, such as
+    /// nodes that were replaced or nodes only used by them.
+    ///
+    /// Returns the new graph and the index of the node standing for each node of this one, or
+    /// None for the nodes that were dropped.
+    pub(crate) fn rebuild(
+        &self,
+        mut on_node: impl FnMut(&mut Builder, usize) -> usize,
+    ) -> (Builder, Vec<Option<usize>>) {
+        let mut graph = Builder::new();
+        graph.hint_fns = self.hint_fns.clone();
+        graph.custom_ops = self.custom_ops.clone();
+        graph.reveal_secrets = self.reveal_secrets;
+        let mut map = Vec::with_capacity(self.graph.len());
+        for id in 0..self.graph.len() {
+            let kind = self.graph.kind(id);
+            let new_id = match kind {
+                Kind::Input => {
+                    let new_id = graph.graph.push(Kind::Input, [0, 0], None);
+                    if self.graph.is_secret(id) {
+                        graph.graph.mark_secret(new_id);
+                    }
+                    graph.input_nodes.push(new_id);
+                    new_id
+                }
+                Kind::Constant => graph
+                    .graph
+                    .push(Kind::Constant, [0, 0], self.graph.output(id)),
+                Kind::Add | Kind::Mul => {
+                    let (a, b) = self.graph.operands(id).unwrap();
+                    graph.graph.push(kind, [map[a], map[b]], None)
+                }
+                Kind::MulAdd => {
+                    let operands = self.graph.mul_add(id).unwrap().map(|operand| map[operand]);
+                    graph.graph.push_mul_add(operands, None)
+                }
+                Kind::Hint => {
+                    let linked = map[self.graph.linked(id).unwrap()];
+                    match self.hint_calls.get(&id) {
+                        Some(function) => {
+                            let new_id = graph.graph.push(Kind::Hint, [linked, 0], None);
+                            graph.hint_calls.insert(new_id, function.clone());
+                            new_id
+                        }
+                        None => graph
+                            .graph
+                            .push(Kind::Hint, [linked, 0], self.graph.output(id)),
+                    }
+                }
+                Kind::Custom => {
+                    let operands: Vec<usize> = self
+                        .graph
+                        .custom(id)
+                        .unwrap()
+                        .into_iter()
+                        .map(|operand| map[operand])
+                        .collect();
+                    let new_id = graph.graph.push_custom(&operands, None);
+                    graph
+                        .custom_calls
+                        .insert(new_id, self.custom_calls[&id].clone());
+                    new_id
+                }
+            };
+            map.push(on_node(&mut graph, new_id));
+        }
+        for (&id, label) in &self.labels {
+            graph.labels.entry(map[id]).or_insert_with(|| label.clone());
+        }
+        for (&id, location) in &self.locations {
+            graph.locations.entry(map[id]).or_insert(*location);
+        }
+        for (&id, metadata) in &self.metadata {
+            let merged = graph.metadata.entry(map[id]).or_default();
+            for (key, value) in metadata {
+                merged.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        graph.public = self.public.iter().map(|&id| map[id]).collect();
+        graph.constraints = self
+            .constraints
+            .iter()
+            .map(|&(a, b)| (map[a], map[b]))
+            .collect();
+        graph.constraint_locations = self.constraint_locations.clone();
+        graph.constraint_messages = self.constraint_messages.clone();
+        graph.soft_constraints = self.soft_constraints.clone();
+        graph.checks = self
+            .checks
+            .iter()
+            .map(|check| check.map(|id| map[id]))
+            .collect();
+        for (a, b) in self.alias_pairs() {
+            graph.union(map[a], map[b]);
+        }
+
+        // Keep the nodes standing for the nodes of the old graph that are observable, or that no
+        // node used, and the nodes they are computed from.
+        let mut used = vec![false; self.graph.len()];
+        for id in 0..self.graph.len() {
+            for source in self.sources(id) {
+                used[source] = true;
+            }
+        }
+        for check in &self.checks {
+            for id in check.nodes() {
+                used[id] = false;
+            }
+        }
+        for &(a, b) in &self.constraints {
+            used[a] = false;
+            used[b] = false;
+        }
+        for (a, b) in self.alias_pairs() {
+            used[a] = false;
+            used[b] = false;
+        }
+        for id in self
+            .input_nodes
+            .iter()
+            .chain(&self.public)
+            .chain(self.labels.keys())
+            .chain(self.metadata.keys())
+        {
+            used[*id] = false;
+        }
+        let mut kept = vec![false; graph.graph.len()];
+        for (id, &new_id) in map.iter().enumerate() {
+            if !used[id] {
+                kept[new_id] = true;
+            }
+        }
+        for id in (0..graph.graph.len()).rev() {
+            if kept[id] {
+                for source in graph.sources(id).collect::<Vec<_>>() {
+                    kept[source] = true;
+                }
+            }
+        }
+        let slice = graph.extract(&kept);
+        let compacted = map
+            .iter()
+            .map(|&id| slice.get(graph.id(id)).map(|id| id.index))
+            .collect();
+        (slice.builder, compacted)
+    }
+}
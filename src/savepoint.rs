@@ -0,0 +1,126 @@
+//! Undoing speculative construction.
+//!
+//! `Builder::savepoint` records how big the graph is, and `Builder::rollback` removes every node,
+//! constraint and check added since, along with their labels, locations and metadata. A gadget
+//! can try one encoding and fall back to another without leaving the abandoned nodes behind:
+//!
+//! ```
+//! use my_graph_lib::Builder;
+//!
+//! let mut b = Builder::new();
+//! let x = b.init();
+//! let savepoint = b.savepoint();
+//! let y = b.square(x);
+//! b.enforce_equal(y, x);
+//! b.rollback(savepoint);
+//! assert_eq!(b.len(), 1);
+//! assert!(!b.contains(y));
+//! assert!(b.contains(x));
+//! ```
+//!
+//! Ids of the removed nodes are stale from then on, even once new nodes take their indices, and
+//! ids of the nodes that were kept stay valid. Changes to nodes that were kept, such as a label,
+//! a filled in value or an alias between two of them, are not undone.
+
+use crate::error::BuildError;
+use crate::{Builder, NEXT_GENERATION};
+use std::sync::atomic::Ordering;
+
+/// The size of a builder at some point, to roll back to with `Builder::rollback`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Savepoint {
+    generation: u32,
+    nodes: usize,
+    constraints: usize,
+    checks: usize,
+}
+
+impl Savepoint {
+    /// Number of nodes the builder had.
+    pub fn nodes(&self) -> usize {
+        self.nodes
+    }
+
+    /// Number of equality constraints and checks the builder had.
+    pub fn constraints(&self) -> usize {
+        self.constraints + self.checks
+    }
+}
+
+impl Builder {
+    /// Records the current size of the builder, to undo what is added after it with `rollback`.
+    pub fn savepoint(&self) -> Savepoint {
+        Savepoint {
+            generation: self.generation,
+            nodes: self.graph.len(),
+            constraints: self.constraints.len(),
+            checks: self.checks.len(),
+        }
+    }
+
+    /// Removes every node, constraint and check added since `savepoint` was taken. A savepoint
+    /// can be rolled back to several times, but not once an earlier savepoint has been rolled
+    /// back to, or with another builder.
+    pub fn try_rollback(&mut self, savepoint: Savepoint) -> Result<(), BuildError> {
+        let kept = if savepoint.generation == self.generation {
+            self.graph.len()
+        } else {
+            self.ancestors
+                .iter()
+                .find(|&&(generation, _)| generation == savepoint.generation)
+                .map_or(0, |&(_, kept)| kept)
+        };
+        if savepoint.nodes > kept {
+            return Err(BuildError::StaleSavepoint);
+        }
+
+        let nodes = savepoint.nodes;
+        self.graph.truncate(nodes);
+        self.input_nodes.retain(|&id| id < nodes);
+        self.labels.split_off(&nodes);
+        self.hint_calls.split_off(&nodes);
+        self.custom_calls.split_off(&nodes);
+        self.public.split_off(&nodes);
+        self.locations.split_off(&nodes);
+        self.metadata.split_off(&nodes);
+        // Nodes come after their parent in the alias union-find, so the nodes left stay rooted.
+        self.aliases.split_off(&nodes);
+        self.depths.truncate(nodes);
+
+        let constraints = savepoint.constraints;
+        self.constraints.truncate(constraints);
+        self.constraint_locations.split_off(&constraints);
+        self.constraint_messages.split_off(&constraints);
+        self.soft_constraints.split_off(&constraints);
+        self.checks.truncate(savepoint.checks);
+
+        // New nodes will reuse the removed indices, so they get a new generation, and ids of
+        // older generations are only accepted for the nodes that are left.
+        for (_, kept) in &mut self.ancestors {
+            *kept = (*kept).min(nodes);
+        }
+        self.ancestors.push((self.generation, nodes));
+        self.generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Like `try_rollback`, but panics if the savepoint is stale.
+    pub fn rollback(&mut self, savepoint: Savepoint) {
+        self.try_rollback(savepoint)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// The generation stamped into the id of the node at `index`: the one it was created in.
+    pub(crate) fn generation_of(&self, index: usize) -> u32 {
+        generation_of(&self.ancestors, self.generation, index)
+    }
+}
+
+/// The generation the node at `index` was created in, given the generations before each
+/// rollback and how many of their nodes are left, which grows from one to the next.
+pub(crate) fn generation_of(ancestors: &[(u32, usize)], current: u32, index: usize) -> u32 {
+    ancestors
+        .iter()
+        .find(|&&(_, kept)| index < kept)
+        .map_or(current, |&(generation, _)| generation)
+}
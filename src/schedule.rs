@@ -0,0 +1,107 @@
+//! Evaluation orders that keep few values alive at once, for streaming evaluation.
+//!
+//! `Builder::stream_witness` evaluates nodes in the order they were added and holds each value
+//! until its last consumer is evaluated. That order can be far from the best one: a graph that
+//! creates all its inputs up front and then folds them one by one holds every input at once.
+//! `Builder::schedule` computes another order that respects dependencies, and
+//! `Builder::stream_witness_scheduled` evaluates in it.
+//!
+//! The schedule is built depth first from the nodes nobody reads, evaluating first the operand
+//! that needs more live values (the Sethi-Ullman order for trees), so inputs and constants are
+//! only evaluated when they are needed. It is a heuristic: finding the best order of a DAG is
+//! NP-hard. If the order the nodes were added in keeps fewer values alive, that order is returned.
+
+use crate::{Builder, NodeId};
+
+/// An order in which to evaluate every node of a graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    /// Every node of the graph, each after the nodes it reads.
+    pub order: Vec<NodeId>,
+    /// Largest number of values held at once when streaming in this order.
+    pub peak_live_values: usize,
+}
+
+impl Builder {
+    /// Computes an evaluation order that keeps few intermediate values alive at once.
+    pub fn schedule(&self) -> Schedule {
+        let len = self.graph.len();
+        // An estimate of the live values needed to compute each node.
+        let mut need = vec![1usize; len];
+        let mut read = vec![false; len];
+        for id in 0..len {
+            let mut operands: Vec<usize> = self.reads(id).into_iter().flatten().collect();
+            operands.sort_unstable();
+            operands.dedup();
+            // Computing the operands by decreasing need holds one more value for each one done.
+            let mut needs: Vec<usize> = operands.iter().map(|&operand| need[operand]).collect();
+            needs.sort_unstable_by(|a, b| b.cmp(a));
+            if let Some(most) = needs.iter().enumerate().map(|(held, n)| n + held).max() {
+                need[id] = most;
+            }
+            for operand in operands {
+                read[operand] = true;
+            }
+        }
+
+        let mut order = Vec::with_capacity(len);
+        let mut scheduled = vec![false; len];
+        // Nodes to visit, and whether their operands have been visited already.
+        let mut stack = Vec::new();
+        for root in (0..len).filter(|&id| !read[id]) {
+            stack.push((root, false));
+            while let Some((id, expanded)) = stack.pop() {
+                if scheduled[id] {
+                    continue;
+                }
+                if expanded {
+                    scheduled[id] = true;
+                    order.push(id);
+                    continue;
+                }
+                stack.push((id, true));
+                let mut operands: Vec<usize> = self.reads(id).into_iter().flatten().collect();
+                // The operand popped first, i.e. pushed last, is the one needing more values.
+                operands.sort_by_key(|&operand| need[operand]);
+                stack.extend(operands.into_iter().map(|operand| (operand, false)));
+            }
+        }
+
+        let peak = self.peak_live_values(order.iter().copied());
+        let added = self.peak_live_values(0..len);
+        let (order, peak_live_values) = if added <= peak {
+            ((0..len).collect(), added)
+        } else {
+            (order, peak)
+        };
+        Schedule {
+            order: order.into_iter().map(|id| self.id(id)).collect(),
+            peak_live_values,
+        }
+    }
+
+    /// Replays the use counting of `stream_witness` for nodes evaluated in `order`, without
+    /// computing any values.
+    pub(crate) fn peak_live_values(&self, order: impl IntoIterator<Item = usize>) -> usize {
+        let mut uses = vec![0u32; self.graph.len()];
+        for id in 0..self.graph.len() {
+            for read in self.reads(id).into_iter().flatten() {
+                uses[read] += 1;
+            }
+        }
+        let (mut live, mut peak) = (0, 0);
+        for id in order {
+            for read in self.reads(id).into_iter().flatten() {
+                uses[read] -= 1;
+                if uses[read] == 0 {
+                    live -= 1;
+                }
+            }
+            if uses[id] > 0 {
+                live += 1;
+                peak = peak.max(live);
+            }
+        }
+        peak
+    }
+}
@@ -0,0 +1,107 @@
+//! Signed comparisons of 32-bit values in two's complement.
+//!
+//! Nodes hold u32 values, which stand for i32 values in two's complement: `-1` is `u32::MAX`.
+//! Comparing them as unsigned values gets every comparison between a negative and a nonnegative
+//! value wrong, so `slt`, `sle`, `sgt` and `sge` decompose both values into bits with `to_bits`
+//! and compare them from the most significant differing bit: at every bit but the sign bit the
+//! value with the 1 is greater, and at the sign bit the value with the 1 is negative, so smaller.
+//!
+//! ```
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let a = builder.init();
+//! let b = builder.init();
+//! let less = builder.slt(a, b);
+//! builder.fill_inputs(&[(a, -5i32 as u32), (b, 3)]);
+//! assert_eq!(builder.output(less.id()), Some(1));
+//! assert!(builder.is_satisfied());
+//! ```
+
+use crate::wires::BoolVar;
+use crate::{Builder, HintFn, NodeId};
+
+/// The number of bits of a value, the last one being the sign bit.
+pub const BITS: usize = 32;
+
+/// Hint functions of the gadgets, registered by `Builder::new`.
+pub(crate) const HINTS: [(&str, HintFn); 2] =
+    [("signed_lsb", |x| x & 1), ("signed_shr", |x| x >> 1)];
+
+impl Builder {
+    /// The constant `value` in two's complement.
+    pub fn signed_constant(&mut self, value: i32) -> NodeId {
+        self.constant(value as u32)
+    }
+
+    /// The value of `x` read in two's complement, once it is filled in.
+    pub fn signed_value(&self, x: NodeId) -> Option<i32> {
+        self.output(x).map(|value| value as i32)
+    }
+
+    /// The `BITS` bits of `x`, least significant first. Each bit is a hint constrained to be 0 or
+    /// 1, split off with `rest == 2 * half + bit`, and the last half must be 0.
+    pub fn to_bits(&mut self, x: NodeId) -> Vec<BoolVar> {
+        self.decompose(x, BITS)
+    }
+
+    /// The `width` low bits of `x`, as in `to_bits`, constraining the higher bits to be 0.
+    pub(crate) fn decompose(&mut self, x: NodeId, width: usize) -> Vec<BoolVar> {
+        let two = self.constant(2);
+        let mut rest = x;
+        let mut bits = Vec::with_capacity(width);
+        for _ in 0..width {
+            let bit = self.hint_fn("signed_lsb", rest);
+            let bit = self.assert_bool(bit);
+            let half = self.hint_fn("signed_shr", rest);
+            let recombined = self.mul_add(half, two, bit.id());
+            self.enforce_equal(recombined, rest);
+            bits.push(bit);
+            rest = half;
+        }
+        let zero = self.constant(0);
+        self.enforce_equal(rest, zero);
+        bits
+    }
+
+    /// 1 if `a < b` as signed values, and 0 otherwise.
+    pub fn slt(&mut self, a: NodeId, b: NodeId) -> BoolVar {
+        let a_bits = self.to_bits(a);
+        let b_bits = self.to_bits(b);
+        let mut less = self.bool_constant(false);
+        for (i, (&a_bit, &b_bit)) in a_bits.iter().zip(&b_bits).enumerate() {
+            let differ = self.xor(a_bit, b_bit);
+            let smaller = if i == BITS - 1 { a_bit } else { b_bit };
+            less = BoolVar(self.select(differ, smaller.id(), less.id()));
+        }
+        less
+    }
+
+    /// 1 if `a <= b` as signed values, and 0 otherwise.
+    pub fn sle(&mut self, a: NodeId, b: NodeId) -> BoolVar {
+        let greater = self.slt(b, a);
+        self.not(greater)
+    }
+
+    /// 1 if `a > b` as signed values, and 0 otherwise.
+    pub fn sgt(&mut self, a: NodeId, b: NodeId) -> BoolVar {
+        self.slt(b, a)
+    }
+
+    /// 1 if `a >= b` as signed values, and 0 otherwise.
+    pub fn sge(&mut self, a: NodeId, b: NodeId) -> BoolVar {
+        let less = self.slt(a, b);
+        self.not(less)
+    }
+
+    /// `a XOR b`: the low bit of `a + b`, a hint constrained by `xor + 2 * a * b == a + b`.
+    fn xor(&mut self, a: BoolVar, b: BoolVar) -> BoolVar {
+        let sum = self.add(a.id(), b.id());
+        let xor = self.hint_fn("signed_lsb", sum);
+        let product = self.mul(a.id(), b.id());
+        let two = self.constant(2);
+        let recombined = self.mul_add(product, two, xor);
+        self.enforce_equal(recombined, sum);
+        BoolVar(xor)
+    }
+}
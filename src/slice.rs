@@ -0,0 +1,232 @@
+//! Extracting the part of a graph that computes some of its nodes, e.g. to verify a few outputs
+//! of a big graph with a lightweight subcircuit, or to see every computation that led to a failing
+//! constraint:
+//!
+//! ```text
+//! x = input = 3
+//! n1 = 7
+//! y = x + n1 = 10
+//! n3 = hint(sqrt, y) = 3
+//! n4 = n3 * n3 = 9
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::checks::Check;
+use crate::error::ConstraintError;
+use crate::storage::Kind;
+use crate::{export, Builder, Node, NodeId};
+
+/// A graph extracted with `Builder::slice_to`, with the ids its nodes had in the original graph.
+#[derive(Debug, Clone)]
+pub struct Slice {
+    pub builder: Builder,
+    /// Id in `builder` of each node of the original graph that was kept.
+    pub ids: BTreeMap<NodeId, NodeId>,
+}
+
+impl Slice {
+    /// The id in the slice of a node of the original graph, or None if it was left out.
+    pub fn get(&self, original: NodeId) -> Option<NodeId> {
+        self.ids.get(&original).copied()
+    }
+
+    /// Renders the slice in Graphviz DOT format, like `export::to_dot`.
+    pub fn to_dot(&self) -> String {
+        export::to_dot(&self.builder)
+    }
+}
+
+/// Lists the nodes of the slice, one per line, with the operation computing each one and its
+/// value. Nodes are named by label, or `n<index>` with their index in the slice.
+impl fmt::Display for Slice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let builder = &self.builder;
+        for id in 0..builder.graph.len() {
+            if id > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{} = ", builder.name(id))?;
+            match builder.graph.kind(id) {
+                Kind::Input => write!(f, "input")?,
+                Kind::Constant => write!(f, "{}", builder.graph.output(id).unwrap())?,
+                Kind::Add | Kind::Mul => {
+                    let (a, b) = builder.graph.operands(id).unwrap();
+                    let op = builder.graph.op(id).unwrap();
+                    write!(f, "{} {} {}", builder.name(a), op.symbol(), builder.name(b))?;
+                }
+                Kind::MulAdd => {
+                    let [a, b, c] = builder
+                        .graph
+                        .mul_add(id)
+                        .unwrap()
+                        .map(|id| builder.name(id));
+                    write!(f, "{} * {} + {}", a, b, c)?;
+                }
+                Kind::Hint => {
+                    let linked = builder.name(builder.graph.linked(id).unwrap());
+                    match builder.hint_calls.get(&id) {
+                        Some(function) => write!(f, "hint({}, {})", function, linked)?,
+                        None => write!(f, "hint({})", linked)?,
+                    }
+                }
+                Kind::Custom => {
+                    let operands: Vec<String> = builder
+                        .graph
+                        .custom(id)
+                        .unwrap()
+                        .into_iter()
+                        .map(|id| builder.name(id))
+                        .collect();
+                    write!(f, "{}({})", builder.custom_calls[&id], operands.join(", "))?;
+                }
+            }
+            if builder.graph.kind(id) != Kind::Constant {
+                match builder.value(id) {
+                    Some(value) => write!(f, " = {}", value)?,
+                    None => write!(f, " = <no value>")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Builder {
+    /// Builds a new graph with only the nodes needed to compute `outputs`, in the same order.
+    ///
+    /// Labels, locations, metadata, filled in values and registered hint functions of the kept
+    /// nodes are copied, and so are the constraints and checks that only read kept nodes. Public
+    /// outputs stay public if they are kept.
+    pub fn slice_to(&self, outputs: &[NodeId]) -> Slice {
+        let mut kept = vec![false; self.graph.len()];
+        for &id in outputs {
+            kept[self.index(id)] = true;
+            for ancestor in self.ancestors(id) {
+                kept[ancestor.index] = true;
+            }
+        }
+        self.extract(&kept)
+    }
+
+    /// Slices the graph to the nodes that feed a constraint failure returned by `check`: both sides
+    /// of an equality, the nodes of a failing check, or the failing node, with everything they
+    /// are computed from and their values.
+    pub fn slice_violation(&self, violation: &ConstraintError) -> Slice {
+        let nodes = match *violation {
+            ConstraintError::NotLess { check, .. }
+            | ConstraintError::NotInSet { check, .. }
+            | ConstraintError::OutOfRange { check, .. }
+            | ConstraintError::NotPermutation { check, .. } => self.checks[check].nodes(),
+            _ => self.violation_nodes(violation).1,
+        };
+        let outputs: Vec<NodeId> = nodes.into_iter().map(|node| self.id(node)).collect();
+        self.slice_to(&outputs)
+    }
+
+    /// Copies the nodes marked in `kept`, and everything attached to them only, into a new graph.
+    /// The nodes a kept node is computed from must be kept too.
+    pub(crate) fn extract(&self, kept: &[bool]) -> Slice {
+        let mut map = vec![None; self.graph.len()];
+        let mut builder = Builder::new();
+        builder.hint_fns = self.hint_fns.clone();
+        builder.custom_ops = self.custom_ops.clone();
+        builder.reveal_secrets = self.reveal_secrets;
+        for id in (0..self.graph.len()).filter(|&id| kept[id]) {
+            if let Some(operands) = self.graph.custom(id) {
+                let operands: Vec<usize> = operands.iter().map(|&id| map[id].unwrap()).collect();
+                map[id] = Some(builder.graph.push_custom(&operands, self.graph.output(id)));
+                continue;
+            }
+            let node = self.graph.node(id);
+            let remap = |input: Option<usize>| input.map(|input| map[input].unwrap());
+            let new_node = Node {
+                id: builder.graph.len(),
+                inputs: (remap(node.inputs.0), remap(node.inputs.1)),
+                op: node.op,
+                output: node.output,
+                hint: remap(node.hint),
+                addend: remap(node.addend),
+            };
+            let is_input = self.input_nodes.binary_search(&id).is_ok();
+            let new_id = builder.graph.push_node(&new_node, is_input);
+            if self.graph.is_secret(id) {
+                builder.graph.mark_secret(new_id);
+            }
+            if is_input {
+                builder.input_nodes.push(new_id);
+            }
+            map[id] = Some(new_id);
+        }
+        builder.labels = remap_keys(&self.labels, &map);
+        builder.hint_calls = remap_keys(&self.hint_calls, &map);
+        builder.custom_calls = remap_keys(&self.custom_calls, &map);
+        builder.locations = remap_keys(&self.locations, &map);
+        builder.metadata = remap_keys(&self.metadata, &map);
+        builder.public = self.public.iter().filter_map(|&id| map[id]).collect();
+        for (constraint, &(a, b)) in self.constraints.iter().enumerate() {
+            let (Some(a), Some(b)) = (map[a], map[b]) else {
+                continue;
+            };
+            let new_constraint = builder.constraints.len();
+            builder.constraints.push((a, b));
+            if let Some(location) = self.constraint_locations.get(&constraint) {
+                builder
+                    .constraint_locations
+                    .insert(new_constraint, *location);
+            }
+            if let Some(message) = self.constraint_messages.get(&constraint) {
+                builder
+                    .constraint_messages
+                    .insert(new_constraint, message.clone());
+            }
+            if self.soft_constraints.contains(&constraint) {
+                builder.soft_constraints.insert(new_constraint);
+            }
+        }
+        for check in &self.checks {
+            let remap =
+                |nodes: &[usize]| nodes.iter().map(|&id| map[id]).collect::<Option<Vec<_>>>();
+            let check = match check {
+                Check::LessThan { a, b, bits } => remap(&[*a, *b]).map(|nodes| Check::LessThan {
+                    a: nodes[0],
+                    b: nodes[1],
+                    bits: *bits,
+                }),
+                Check::InSet { node, set } => map[*node].map(|node| Check::InSet {
+                    node,
+                    set: set.clone(),
+                }),
+                Check::Range { node, bits } => {
+                    map[*node].map(|node| Check::Range { node, bits: *bits })
+                }
+                Check::Permutation { a, b } => remap(a)
+                    .zip(remap(b))
+                    .map(|(a, b)| Check::Permutation { a, b }),
+            };
+            builder.checks.extend(check);
+        }
+        // Kept nodes stay aliased through the first kept node of their class.
+        let mut firsts: BTreeMap<usize, usize> = BTreeMap::new();
+        for (root, node) in self.alias_pairs() {
+            for new_id in [map[root], map[node]].into_iter().flatten() {
+                let first = *firsts.entry(root).or_insert(new_id);
+                builder.union(first, new_id);
+            }
+        }
+        let ids = map
+            .iter()
+            .enumerate()
+            .filter_map(|(id, new_id)| Some((self.id(id), builder.id((*new_id)?))))
+            .collect();
+        Slice { builder, ids }
+    }
+}
+
+/// Copies the entries of `from` whose node was kept, keyed by the node's new index.
+fn remap_keys<T: Clone>(from: &BTreeMap<usize, T>, map: &[Option<usize>]) -> BTreeMap<usize, T> {
+    from.iter()
+        .filter_map(|(&id, value)| Some((map[id]?, value.clone())))
+        .collect()
+}
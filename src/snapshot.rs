@@ -0,0 +1,118 @@
+//! A textual dump of a circuit's structure for snapshot tests.
+//!
+//! ```text
+//! circuit snapshot v1
+//! %0 = input  # x
+//! %1 = const 7
+//! %2 = add %0 %1  # y
+//! %3 = hint sqrt %2
+//! %4 = mul %3 %3
+//! assert %2 == %4
+//! warn %3 == %1
+//! assert %0 < %2 < 2^8
+//! assert %1 in {3, 7}
+//! assert [%0, %1] permutes [%4, %2]
+//! alias %4 = %2
+//! public %2
+//! ```
+//!
+//! Nodes are listed in the order they were added, one per line, followed by the equality
+//! constraints in the order they were recorded, with `warn` for constraints with the `Warning`
+//! severity, then the ranges, orderings, set memberships and permutations in the order they were
+//! recorded, every aliased node with the representative of its class, and the public outputs.
+//! Labels are trailing `#` comments. Witness values, including the values of fixed hints, and
+//! source locations are left out, so a snapshot only changes when the structure of the circuit
+//! does.
+//!
+//! The format is stable: any change to it comes with a new version in the first line.
+
+use std::fmt::Write;
+
+use crate::checks::Check;
+use crate::storage::Kind;
+use crate::Builder;
+
+impl Builder {
+    /// Renders the structure of the circuit in the snapshot format.
+    pub fn snapshot(&self) -> String {
+        let mut out = String::from("circuit snapshot v1\n");
+        for id in 0..self.graph.len() {
+            let node = match self.graph.kind(id) {
+                Kind::Input if self.graph.is_secret(id) => "private input".to_string(),
+                Kind::Input => "input".to_string(),
+                Kind::Constant => format!("const {}", self.graph.output(id).unwrap()),
+                Kind::Add => {
+                    let (a, b) = self.graph.operands(id).unwrap();
+                    format!("add %{} %{}", a, b)
+                }
+                Kind::Mul => {
+                    let (a, b) = self.graph.operands(id).unwrap();
+                    format!("mul %{} %{}", a, b)
+                }
+                Kind::MulAdd => {
+                    let [a, b, c] = self.graph.mul_add(id).unwrap();
+                    format!("mul_add %{} %{} %{}", a, b, c)
+                }
+                Kind::Hint => {
+                    let linked = self.graph.linked(id).unwrap();
+                    match self.hint_calls.get(&id) {
+                        Some(name) => format!("hint {} %{}", name, linked),
+                        None => format!("hint %{}", linked),
+                    }
+                }
+                Kind::Custom => {
+                    let operands: Vec<String> = self
+                        .graph
+                        .custom(id)
+                        .unwrap()
+                        .into_iter()
+                        .map(|operand| format!("%{}", operand))
+                        .collect();
+                    format!("custom {} {}", self.custom_calls[&id], operands.join(" "))
+                }
+            };
+            write!(out, "%{} = {}", id, node).unwrap();
+            if let Some(label) = self.labels.get(&id) {
+                write!(out, "  # {}", label).unwrap();
+            }
+            out.push('\n');
+        }
+        for (i, &(a, b)) in self.constraints.iter().enumerate() {
+            let keyword = if self.soft_constraints.contains(&i) {
+                "warn"
+            } else {
+                "assert"
+            };
+            writeln!(out, "{} %{} == %{}", keyword, a, b).unwrap();
+        }
+        for check in &self.checks {
+            match *check {
+                Check::LessThan { a, b, bits } => {
+                    writeln!(out, "assert %{} < %{} < 2^{}", a, b, bits).unwrap()
+                }
+                Check::InSet { node, ref set } => {
+                    let set: Vec<String> = set.iter().map(u32::to_string).collect();
+                    writeln!(out, "assert %{} in {{{}}}", node, set.join(", ")).unwrap()
+                }
+                Check::Range { node, bits } => {
+                    writeln!(out, "assert %{} < 2^{}", node, bits).unwrap()
+                }
+                Check::Permutation { ref a, ref b } => {
+                    let list = |nodes: &[usize]| {
+                        let nodes: Vec<String> =
+                            nodes.iter().map(|id| format!("%{}", id)).collect();
+                        nodes.join(", ")
+                    };
+                    writeln!(out, "assert [{}] permutes [{}]", list(a), list(b)).unwrap()
+                }
+            }
+        }
+        for (root, node) in self.alias_pairs() {
+            writeln!(out, "alias %{} = %{}", node, root).unwrap();
+        }
+        for id in &self.public {
+            writeln!(out, "public %{}", id).unwrap();
+        }
+        out
+    }
+}
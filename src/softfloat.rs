@@ -0,0 +1,525 @@
+//! IEEE-754 single precision addition and multiplication, for computations specified in floating
+//! point.
+//!
+//! A node holds the bits of an `f32`, as `f32::to_bits` gives them. `f32_add` and `f32_mul` round
+//! to nearest, ties to even, like the `+` and `*` of Rust, including subnormals, signed zeros,
+//! infinities and overflow to infinity. Every NaN result is the quiet NaN `NAN`, whatever the NaN
+//! operands were.
+//!
+//! Both gadgets are bit-level: operands are decomposed with the hints of `to_bits`, exponents are
+//! compared and subtracted with ripple adders, mantissas are aligned and normalized with barrel
+//! shifters, and only the 24-bit mantissa product is computed arithmetically, in 12-bit limbs so
+//! that no value overflows a u32. Bits known at build time are folded away. This costs thousands of
+//! nodes per operation, so the gadgets are behind the `softfloat` feature.
+//!
+//! ```
+//! # #[cfg(feature = "softfloat")] {
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let a = builder.init();
+//! let b = builder.init();
+//! let product = builder.f32_mul(a, b);
+//! let sum = builder.f32_add(product, b);
+//! builder.fill_inputs(&[(a, 1.5f32.to_bits()), (b, 0.1f32.to_bits())]);
+//! assert_eq!(builder.f32_value(sum), Some(1.5 * 0.1 + 0.1));
+//! assert!(builder.is_satisfied());
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use crate::{Builder, NodeId};
+
+/// The quiet NaN every NaN result is, the bits of `f32::NAN`.
+pub const NAN: u32 = 0x7fc0_0000;
+
+/// The bits of `f32::INFINITY`, and the smallest magnitude that isn't finite.
+const INFINITY: u32 = 0x7f80_0000;
+
+/// The sign bit.
+const SIGN: u32 = 0x8000_0000;
+
+impl Builder {
+    /// The constant `value`.
+    pub fn f32_constant(&mut self, value: f32) -> NodeId {
+        self.constant(value.to_bits())
+    }
+
+    /// The value of `x` read as an `f32`, once it is filled in.
+    pub fn f32_value(&self, x: NodeId) -> Option<f32> {
+        self.output(x).map(f32::from_bits)
+    }
+
+    /// `a + b`, rounded to nearest, ties to even.
+    pub fn f32_add(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        let mut bits = Bits::new(self);
+        let a = bits.unpack(a);
+        let b = bits.unpack(b);
+        // The operand of larger magnitude is `big`, so the difference of magnitudes is nonnegative.
+        let (_, swap) = bits.sub(&a.magnitude, &b.magnitude);
+        let big_sign = bits.mux(swap, b.sign, a.sign);
+        let big_exponent = bits.mux_word(swap, &b.exponent, &a.exponent);
+        let small_exponent = bits.mux_word(swap, &a.exponent, &b.exponent);
+        let big_mantissa = bits.mux_word(swap, &b.mantissa, &a.mantissa);
+        let small_mantissa = bits.mux_word(swap, &a.mantissa, &b.mantissa);
+
+        // Three extra low bits, the guard, round and sticky bits, are enough to round correctly.
+        let big = widen(&[&word(0, 3)[..], &big_mantissa].concat(), 28);
+        let small = [&word(0, 3)[..], &small_mantissa].concat();
+        let (distance, _) = bits.sub(&big_exponent, &small_exponent);
+        let (mut small, lost) = bits.shr(&small, &distance);
+        small[0] = bits.or(small[0], lost);
+        let small = widen(&small, 28);
+
+        // Subtraction adds the complement of `small` and 1.
+        let subtract = bits.xor(a.sign, b.sign);
+        let small: Word = small.iter().map(|&bit| bits.xor(bit, subtract)).collect();
+        let (sum, _) = bits.add(&big, &small, subtract);
+
+        // A carry out of the mantissa shifts the sum right by one, keeping the lost bit sticky.
+        let carried = sum[27];
+        let mut high = sum[1..].to_vec();
+        high[0] = bits.or(sum[0], sum[1]);
+        let (high_exponent, _) = bits.add(&widen(&big_exponent, 9), &word(1, 9), Bit::Const(false));
+        // Otherwise the sum is shifted left to its leading 1, but not below the smallest exponent.
+        let low = &sum[..27];
+        let (_, zeros) = bits.normalize(low);
+        let (limit, _) = bits.sub(&big_exponent, &word(1, 8));
+        let zeros = widen(&zeros, 8);
+        let (_, limited) = bits.sub(&limit, &zeros);
+        let shift = bits.mux_word(limited, &limit, &zeros);
+        let low = bits.shl(low, &shift);
+        let (low_exponent, _) = bits.sub(&big_exponent, &shift);
+        let normal = bits.mux_word(carried, &high, &low);
+        let exponent = bits.mux_word(carried, &high_exponent, &widen(&low_exponent, 9));
+        let (exponent, _) = bits.sub(&exponent, &word(1, 9));
+        let sticky = bits.or(normal[1], normal[0]);
+        let magnitude = bits.round(&exponent, &normal[3..], normal[2], sticky);
+
+        // An exact zero is positive, unless both operands are negative zeros.
+        let any = bits.any(&sum);
+        let zero = bits.not(any);
+        let add = bits.not(subtract);
+        let zero_sign = bits.and(add, a.sign);
+        let opposite_infinities = bits.and(a.infinite, b.infinite);
+        let opposite_infinities = bits.and(opposite_infinities, subtract);
+        let nan = bits.or(a.nan, b.nan);
+        let nan = bits.or(nan, opposite_infinities);
+        let infinite = bits.or(a.infinite, b.infinite);
+        let infinite_sign = bits.mux(a.infinite, a.sign, b.sign);
+        let sign = bits.mux(zero, zero_sign, big_sign);
+        let sign = bits.mux(infinite, infinite_sign, sign);
+        bits.finish(nan, infinite, zero, sign, magnitude)
+    }
+
+    /// `a * b`, rounded to nearest, ties to even.
+    pub fn f32_mul(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        let mut bits = Bits::new(self);
+        let a = bits.unpack(a);
+        let b = bits.unpack(b);
+        let sign = bits.xor(a.sign, b.sign);
+
+        // The 48-bit product of the mantissas, from the products of their 12-bit halves.
+        let a_low = bits.value(&a.mantissa[..12]);
+        let a_high = bits.value(&a.mantissa[12..]);
+        let b_low = bits.value(&b.mantissa[..12]);
+        let b_high = bits.value(&b.mantissa[12..]);
+        let builder = &mut *bits.builder;
+        let low_product = builder.mul(a_low, b_low);
+        let cross = builder.mul(a_low, b_high);
+        let middle_product = builder.mul_add(a_high, b_low, cross);
+        let high_product = builder.mul(a_high, b_high);
+        let middle = bits.bits(middle_product, 25);
+        let middle_low = bits.value(&middle[..12]);
+        let middle_high = bits.value(&middle[12..]);
+        let scale = bits.constant(1 << 12);
+        let low = bits.builder.mul_add(middle_low, scale, low_product);
+        let low = bits.bits(low, 25);
+        let high = bits.builder.add(high_product, middle_high);
+        let high = bits.add_bit(high, low[24]);
+        let high = bits.bits(high, 24);
+        let product = [&low[..24], &high[..]].concat();
+
+        // With its leading 1 at bit 47, the product has the biased exponent
+        // `exponent_a + exponent_b - 126 - zeros`, kept 256 higher to stay nonnegative.
+        let (product, zeros) = bits.normalize(&product);
+        let (sum, _) = bits.add(
+            &widen(&a.exponent, 10),
+            &widen(&b.exponent, 10),
+            Bit::Const(false),
+        );
+        let (biased, _) = bits.add(&sum, &word(130, 10), Bit::Const(false));
+        let (biased, _) = bits.sub(&biased, &widen(&zeros, 10));
+        // Below the smallest exponent, 1, the product is shifted right into a subnormal.
+        let (exponent, subnormal) = bits.sub(&biased, &word(257, 10));
+        let (distance, _) = bits.sub(&word(0, 10), &exponent);
+        let distance = bits.mux_word(subnormal, &distance, &word(0, 10));
+        let exponent = bits.mux_word(subnormal, &word(0, 10), &exponent);
+        let (product, lost) = bits.shr(&product, &distance);
+        let sticky = bits.any(&product[..23]);
+        let sticky = bits.or(sticky, lost);
+        let magnitude = bits.round(&exponent, &product[24..], product[23], sticky);
+
+        let zero = bits.or(a.zero, b.zero);
+        let nan = bits.or(a.nan, b.nan);
+        let zero_infinity = bits.and(a.zero, b.infinite);
+        let nan = bits.or(nan, zero_infinity);
+        let infinity_zero = bits.and(a.infinite, b.zero);
+        let nan = bits.or(nan, infinity_zero);
+        let infinite = bits.or(a.infinite, b.infinite);
+        bits.finish(nan, infinite, zero, sign, magnitude)
+    }
+}
+
+/// A bit, either known at build time or the output of a node that is 0 or 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bit {
+    Const(bool),
+    Node(NodeId),
+}
+
+/// Bits of a value, least significant first.
+type Word = Vec<Bit>;
+
+/// The `width` low bits of `value`.
+fn word(value: u32, width: usize) -> Word {
+    (0..width)
+        .map(|i| Bit::Const(i < 32 && value >> i & 1 == 1))
+        .collect()
+}
+
+/// `word` padded with zeros to `width` bits.
+fn widen(word: &[Bit], width: usize) -> Word {
+    let mut word = word.to_vec();
+    word.resize(width, Bit::Const(false));
+    word
+}
+
+/// The fields of an operand.
+struct Float {
+    sign: Bit,
+    /// The 31 bits below the sign, which order magnitudes like their values.
+    magnitude: Word,
+    /// The 8-bit biased exponent, 1 for subnormals.
+    exponent: Word,
+    /// The 24-bit mantissa, with its leading 1 unless the operand is subnormal.
+    mantissa: Word,
+    zero: Bit,
+    infinite: Bit,
+    nan: Bit,
+}
+
+/// Bit-level operations on a builder, sharing constants and negations between them.
+struct Bits<'a> {
+    builder: &'a mut Builder,
+    constants: HashMap<u32, NodeId>,
+    nots: HashMap<NodeId, NodeId>,
+}
+
+impl<'a> Bits<'a> {
+    fn new(builder: &'a mut Builder) -> Self {
+        Bits {
+            builder,
+            constants: HashMap::new(),
+            nots: HashMap::new(),
+        }
+    }
+
+    fn constant(&mut self, value: u32) -> NodeId {
+        if let Some(&node) = self.constants.get(&value) {
+            return node;
+        }
+        let node = self.builder.constant(value);
+        self.constants.insert(value, node);
+        node
+    }
+
+    /// The `width` bits of `x`, constraining the higher bits to be 0.
+    fn bits(&mut self, x: NodeId, width: usize) -> Word {
+        self.builder
+            .decompose(x, width)
+            .into_iter()
+            .map(|bit| Bit::Node(bit.id()))
+            .collect()
+    }
+
+    /// The value of the at most 32 bits of `word`.
+    fn value(&mut self, word: &[Bit]) -> NodeId {
+        let known = word
+            .iter()
+            .enumerate()
+            .filter(|&(_, &bit)| bit == Bit::Const(true))
+            .map(|(i, _)| 1 << i)
+            .sum();
+        let mut value = self.constant(known);
+        for (i, &bit) in word.iter().enumerate() {
+            if let Bit::Node(node) = bit {
+                let weight = self.constant(1 << i);
+                value = self.builder.mul_add(node, weight, value);
+            }
+        }
+        value
+    }
+
+    /// `x + bit`.
+    fn add_bit(&mut self, x: NodeId, bit: Bit) -> NodeId {
+        match bit {
+            Bit::Const(false) => x,
+            Bit::Const(true) => {
+                let one = self.constant(1);
+                self.builder.add(x, one)
+            }
+            Bit::Node(node) => self.builder.add(x, node),
+        }
+    }
+
+    fn not(&mut self, a: Bit) -> Bit {
+        let x = match a {
+            Bit::Const(a) => return Bit::Const(!a),
+            Bit::Node(x) => x,
+        };
+        if let Some(&not) = self.nots.get(&x) {
+            return Bit::Node(not);
+        }
+        let not = self.builder.hint_fn("not", x);
+        let one = self.constant(1);
+        let sum = self.builder.add(x, not);
+        self.builder.enforce_equal(sum, one);
+        self.nots.insert(x, not);
+        self.nots.insert(not, x);
+        Bit::Node(not)
+    }
+
+    fn and(&mut self, a: Bit, b: Bit) -> Bit {
+        match (a, b) {
+            (Bit::Const(false), _) | (_, Bit::Const(false)) => Bit::Const(false),
+            (Bit::Const(true), other) | (other, Bit::Const(true)) => other,
+            (Bit::Node(x), Bit::Node(y)) if x == y => a,
+            (Bit::Node(x), Bit::Node(y)) => Bit::Node(self.builder.mul(x, y)),
+        }
+    }
+
+    /// `a XOR b`: the low bit of `a + b`, a hint constrained by `xor + 2 * a * b == a + b`.
+    fn xor(&mut self, a: Bit, b: Bit) -> Bit {
+        match (a, b) {
+            (Bit::Const(false), other) | (other, Bit::Const(false)) => other,
+            (Bit::Const(true), other) | (other, Bit::Const(true)) => self.not(other),
+            (Bit::Node(x), Bit::Node(y)) if x == y => Bit::Const(false),
+            (Bit::Node(x), Bit::Node(y)) => {
+                let sum = self.builder.add(x, y);
+                let xor = self.builder.hint_fn("signed_lsb", sum);
+                let product = self.builder.mul(x, y);
+                let two = self.constant(2);
+                let recombined = self.builder.mul_add(product, two, xor);
+                self.builder.enforce_equal(recombined, sum);
+                Bit::Node(xor)
+            }
+        }
+    }
+
+    /// `a OR b` for bits that aren't both 1, which is their sum.
+    fn disjoint_or(&mut self, a: Bit, b: Bit) -> Bit {
+        match (a, b) {
+            (Bit::Const(false), other) | (other, Bit::Const(false)) => other,
+            (Bit::Const(true), _) | (_, Bit::Const(true)) => Bit::Const(true),
+            (Bit::Node(x), Bit::Node(y)) => Bit::Node(self.builder.add(x, y)),
+        }
+    }
+
+    fn or(&mut self, a: Bit, b: Bit) -> Bit {
+        match (a, b) {
+            (Bit::Const(true), _) | (_, Bit::Const(true)) => Bit::Const(true),
+            (Bit::Const(false), other) | (other, Bit::Const(false)) => other,
+            _ => {
+                let xor = self.xor(a, b);
+                let and = self.and(a, b);
+                self.disjoint_or(xor, and)
+            }
+        }
+    }
+
+    /// `a` if `cond` is 1 and `b` if it is 0.
+    fn mux(&mut self, cond: Bit, a: Bit, b: Bit) -> Bit {
+        if a == b {
+            return a;
+        }
+        let a = self.and(cond, a);
+        let not = self.not(cond);
+        let b = self.and(not, b);
+        self.disjoint_or(a, b)
+    }
+
+    fn mux_word(&mut self, cond: Bit, a: &[Bit], b: &[Bit]) -> Word {
+        a.iter()
+            .zip(b)
+            .map(|(&a, &b)| self.mux(cond, a, b))
+            .collect()
+    }
+
+    /// The node `a` if `cond` is 1 and `b` if it is 0.
+    fn select(&mut self, cond: Bit, a: NodeId, b: NodeId) -> NodeId {
+        match cond {
+            Bit::Const(true) => a,
+            Bit::Const(false) => b,
+            Bit::Node(cond) => {
+                let Bit::Node(not) = self.not(Bit::Node(cond)) else {
+                    unreachable!("the negation of a node is a node")
+                };
+                let a = self.builder.mul(cond, a);
+                self.builder.mul_add(not, b, a)
+            }
+        }
+    }
+
+    /// Whether any bit of `word` is 1.
+    fn any(&mut self, word: &[Bit]) -> Bit {
+        let mut none = Bit::Const(true);
+        for &bit in word {
+            let not = self.not(bit);
+            none = self.and(none, not);
+        }
+        self.not(none)
+    }
+
+    /// Whether every bit of `word` is 1.
+    fn all(&mut self, word: &[Bit]) -> Bit {
+        word.iter()
+            .fold(Bit::Const(true), |all, &bit| self.and(all, bit))
+    }
+
+    /// `a + b + carry` modulo 2^n for words of n bits, and the carry out.
+    fn add(&mut self, a: &[Bit], b: &[Bit], mut carry: Bit) -> (Word, Bit) {
+        let mut sum = Vec::with_capacity(a.len());
+        for (&a, &b) in a.iter().zip(b) {
+            let half = self.xor(a, b);
+            sum.push(self.xor(half, carry));
+            let both = self.and(a, b);
+            let carried = self.and(half, carry);
+            carry = self.disjoint_or(both, carried);
+        }
+        (sum, carry)
+    }
+
+    /// `a - b` modulo 2^n for words of n bits, and whether it borrows, which is whether `a < b`.
+    fn sub(&mut self, a: &[Bit], b: &[Bit]) -> (Word, Bit) {
+        let not_b: Word = b.iter().map(|&bit| self.not(bit)).collect();
+        let (difference, carry) = self.add(a, &not_b, Bit::Const(true));
+        (difference, self.not(carry))
+    }
+
+    /// `word << amount`, dropping the bits shifted out.
+    fn shl(&mut self, word: &[Bit], amount: &[Bit]) -> Word {
+        let mut word = word.to_vec();
+        for (k, &bit) in amount.iter().enumerate() {
+            let shift = (1usize << k).min(word.len());
+            let shifted = [&vec![Bit::Const(false); shift], &word[..word.len() - shift]].concat();
+            word = self.mux_word(bit, &shifted, &word);
+        }
+        word
+    }
+
+    /// `word >> amount`, and whether any 1 was shifted out.
+    fn shr(&mut self, word: &[Bit], amount: &[Bit]) -> (Word, Bit) {
+        let mut word = word.to_vec();
+        let mut lost = Bit::Const(false);
+        for (k, &bit) in amount.iter().enumerate() {
+            let shift = (1usize << k).min(word.len());
+            let dropped = self.any(&word[..shift]);
+            let dropped = self.and(bit, dropped);
+            lost = self.or(lost, dropped);
+            let shifted = widen(&word[shift..], word.len());
+            word = self.mux_word(bit, &shifted, &word);
+        }
+        (word, lost)
+    }
+
+    /// `word` shifted left until its top bit is 1, and the number of leading zeros that took. A
+    /// zero word stays zero.
+    fn normalize(&mut self, word: &[Bit]) -> (Word, Word) {
+        let len = word.len();
+        let stages = (usize::BITS - (len - 1).leading_zeros()) as usize;
+        let mut word = word.to_vec();
+        let mut zeros = vec![Bit::Const(false); stages];
+        for k in (0..stages).rev() {
+            let shift = 1 << k;
+            let top = self.any(&word[len - shift..]);
+            let empty = self.not(top);
+            let shifted = [&vec![Bit::Const(false); shift], &word[..len - shift]].concat();
+            word = self.mux_word(empty, &shifted, &word);
+            zeros[k] = empty;
+        }
+        (word, zeros)
+    }
+
+    fn unpack(&mut self, x: NodeId) -> Float {
+        let bits = self.bits(x, 32);
+        let (fraction, exponent) = (&bits[..23], &bits[23..31]);
+        let any_exponent = self.any(exponent);
+        let no_exponent = self.not(any_exponent);
+        let all_exponent = self.all(exponent);
+        let any_fraction = self.any(fraction);
+        let no_fraction = self.not(any_fraction);
+        let nan = self.and(all_exponent, any_fraction);
+        let infinite = self.and(all_exponent, no_fraction);
+        let zero = self.and(no_exponent, no_fraction);
+        let mantissa = [fraction, &[any_exponent]].concat();
+        let mut exponent = exponent.to_vec();
+        exponent[0] = self.or(exponent[0], no_exponent);
+        Float {
+            sign: bits[31],
+            magnitude: bits[..31].to_vec(),
+            exponent,
+            mantissa,
+            zero,
+            infinite,
+            nan,
+        }
+    }
+
+    /// The magnitude `exponent * 2^23 + mantissa`, rounded up if `guard` and either `sticky` or
+    /// the low bit of the mantissa are 1, and infinite if that overflows. The leading 1 of the
+    /// mantissa carries into the exponent, so `exponent` is the biased exponent minus 1, and
+    /// subnormals, without a leading 1, get an exponent field of 0.
+    fn round(&mut self, exponent: &[Bit], mantissa: &[Bit], guard: Bit, sticky: Bit) -> NodeId {
+        let odd = self.or(sticky, mantissa[0]);
+        let up = self.and(guard, odd);
+        let exponent = self.value(exponent);
+        let mantissa = self.value(mantissa);
+        let scale = self.constant(1 << 23);
+        let magnitude = self.builder.mul_add(exponent, scale, mantissa);
+        let magnitude = self.add_bit(magnitude, up);
+        let bits = self.bits(magnitude, 32);
+        let (_, finite) = self.sub(&bits, &word(INFINITY, 32));
+        let infinity = self.constant(INFINITY);
+        self.select(finite, magnitude, infinity)
+    }
+
+    /// The result with sign `sign` and magnitude `magnitude`, unless it is NaN, infinite or zero.
+    fn finish(
+        &mut self,
+        nan: Bit,
+        infinite: Bit,
+        zero: Bit,
+        sign: Bit,
+        magnitude: NodeId,
+    ) -> NodeId {
+        let zero_magnitude = self.constant(0);
+        let magnitude = self.select(zero, zero_magnitude, magnitude);
+        let infinity = self.constant(INFINITY);
+        let magnitude = self.select(infinite, infinity, magnitude);
+        let signed = match sign {
+            Bit::Const(false) => magnitude,
+            Bit::Const(true) => {
+                let sign = self.constant(SIGN);
+                self.builder.add(sign, magnitude)
+            }
+            Bit::Node(sign) => {
+                let weight = self.constant(SIGN);
+                self.builder.mul_add(sign, weight, magnitude)
+            }
+        };
+        let nan_value = self.constant(NAN);
+        self.select(nan, nan_value, signed)
+    }
+}
@@ -0,0 +1,399 @@
+//! Deriving the witness values that constraints force, from a partially assigned witness.
+//!
+//! `Builder::solve(&partial)` fills in the values of a witness that follow from the ones it has,
+//! so fewer values, such as hints, have to be provided by hand. Every node is a relation between
+//! its value and its operands, and every equality constraint a relation between two nodes: when
+//! all but one of their values are known and the relation has a single solution for the last one,
+//! that value is derived. Besides computing nodes from their operands, this subtracts a known
+//! operand of an addition from its result, divides the result of a multiplication by a known
+//! operand, takes the root of a square and copies values across equality constraints and
+//! aliases. The solver iterates until nothing new can be derived and reports the nodes left
+//! without a value:
+//!
+//! ```
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let a = builder.init();
+//! let b = builder.init();
+//! let c = builder.add(a, b);
+//! let product = builder.mul(c, a);
+//! let expected = builder.constant(35);
+//! builder.enforce_equal(product, expected);
+//! // Knowing a = 5, the constraint gives the product 35, then c = 35 / 5 and b = c - 5.
+//! let mut partial = builder.witness();
+//! partial.values[a.index()] = Some(5);
+//! let solution = builder.solve(&partial).unwrap();
+//! assert_eq!(solution.witness.values[b.index()], Some(2));
+//! assert!(solution.unknown.is_empty());
+//! ```
+//!
+//! Relations whose known values contradict each other, e.g. a constraint between two different
+//! values or a result that is not a multiple of a known factor, make `solve` fail with
+//! `SolveError::Conflict`. Range, ordering, set and permutation checks are left out; the
+//! completed witness should still be checked.
+
+use std::collections::VecDeque;
+
+pub use crate::error::SolveError;
+use crate::storage::Kind;
+use crate::{Builder, NodeId, Witness, OPERATION};
+
+/// A witness completed by `Builder::solve`.
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub witness: Witness,
+    /// The values derived, in the order they were found.
+    pub derived: Vec<Derived>,
+    /// The nodes still without a value, in index order.
+    pub unknown: Vec<NodeId>,
+}
+
+impl Solution {
+    /// Whether every node has a value.
+    pub fn is_complete(&self) -> bool {
+        self.unknown.is_empty()
+    }
+}
+
+/// A value derived by the solver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Derived {
+    pub node: NodeId,
+    pub value: u32,
+    pub rule: Rule,
+}
+
+/// How a value was derived.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// Computed from the node's operands, like evaluation does.
+    Forward,
+    /// Solved from the result of a node reading it and the other operands.
+    Inverse,
+    /// Copied from the other side of an equality constraint.
+    Equality,
+}
+
+/// What the solver knows about one node or constraint.
+#[derive(Debug, Clone, Copy)]
+enum Relation {
+    Node(usize),
+    Equal(usize, usize),
+}
+
+type Assignments = Vec<(usize, u32, Rule)>;
+
+impl Builder {
+    /// Completes `partial` with the values its known values force, see the `solve` module. The
+    /// values of constants are taken from the graph.
+    pub fn solve(&self, partial: &Witness) -> Result<Solution, SolveError> {
+        let len = self.graph.len();
+        if partial.values.len() != len {
+            return Err(SolveError::WrongLength {
+                expected: len,
+                found: partial.values.len(),
+            });
+        }
+        self.check_arities()?;
+        let mut values = partial.values.clone();
+        for (id, value) in values.iter_mut().enumerate() {
+            let fixed = match self.graph.kind(id) {
+                Kind::Constant => self.graph.output(id),
+                Kind::Hint if !self.hint_calls.contains_key(&id) => self.graph.output(id),
+                _ => None,
+            };
+            if let (Some(fixed), Some(given)) = (fixed, *value) {
+                if fixed != given {
+                    return Err(conflict(
+                        id,
+                        format!("it is fixed to {} but the witness gives {}", fixed, given),
+                    ));
+                }
+            }
+            *value = value.or(fixed);
+        }
+
+        let mut relations: Vec<Relation> = (0..len).map(Relation::Node).collect();
+        relations.extend(
+            self.copies()
+                .into_iter()
+                .map(|(a, b)| Relation::Equal(a, b)),
+        );
+        let mut touching = vec![Vec::new(); len];
+        for (i, &relation) in relations.iter().enumerate() {
+            let nodes: Vec<usize> = match relation {
+                Relation::Node(id) => std::iter::once(id)
+                    .chain(self.reads(id).into_iter().flatten())
+                    .collect(),
+                Relation::Equal(a, b) => vec![a, b],
+            };
+            for node in nodes {
+                if touching[node].last() != Some(&i) {
+                    touching[node].push(i);
+                }
+            }
+        }
+
+        let mut queue: VecDeque<usize> = (0..relations.len()).collect();
+        let mut queued = vec![true; relations.len()];
+        let mut derived = Vec::new();
+        while let Some(i) = queue.pop_front() {
+            queued[i] = false;
+            let assignments = match relations[i] {
+                Relation::Node(id) => self.derive(id, &values)?,
+                Relation::Equal(a, b) => self.equate(a, b, &values)?,
+            };
+            for (node, value, rule) in assignments {
+                values[node] = Some(value);
+                derived.push(Derived {
+                    node: self.id(node),
+                    value,
+                    rule,
+                });
+                for &relation in &touching[node] {
+                    if !queued[relation] {
+                        queued[relation] = true;
+                        queue.push_back(relation);
+                    }
+                }
+            }
+        }
+
+        let unknown = (0..len)
+            .filter(|&id| values[id].is_none())
+            .map(|id| self.id(id))
+            .collect();
+        Ok(Solution {
+            witness: self.witness_of(values),
+            derived,
+            unknown,
+        })
+    }
+
+    /// The values a node forces on itself or its operands, or a conflict if its known values
+    /// don't fit together.
+    fn derive(&self, id: usize, values: &[Option<u32>]) -> Result<Assignments, SolveError> {
+        let out = values[id];
+        match self.graph.kind(id) {
+            Kind::Add | Kind::Mul => {
+                let (a, b) = self.graph.operands(id).unwrap();
+                let op = self.graph.op(id).unwrap();
+                self.solve_op(id, op, a, b, out, values)
+            }
+            Kind::MulAdd => {
+                let [a, b, c] = self.graph.mul_add(id).unwrap();
+                let product = match (values[a], values[b]) {
+                    (Some(x), Some(y)) => Some(x.checked_mul(y).ok_or_else(|| {
+                        conflict(
+                            id,
+                            format!(
+                                "{} * {} does not fit in a u32",
+                                self.redact(a, x),
+                                self.redact(b, y)
+                            ),
+                        )
+                    })?),
+                    _ => None,
+                };
+                match (product, values[c], out) {
+                    (Some(p), Some(z), _) => {
+                        let sum = p.checked_add(z).ok_or_else(|| {
+                            conflict(
+                                id,
+                                format!("{} + {} does not fit in a u32", p, self.redact(c, z)),
+                            )
+                        })?;
+                        self.forward(id, sum, out)
+                    }
+                    (Some(p), None, Some(o)) => {
+                        let z = o.checked_sub(p).ok_or_else(|| {
+                            conflict(
+                                id,
+                                format!("it is {} but its product is {}", self.redact(id, o), p),
+                            )
+                        })?;
+                        Ok(vec![(c, z, Rule::Inverse)])
+                    }
+                    (None, Some(z), Some(o)) => {
+                        let p = o.checked_sub(z).ok_or_else(|| {
+                            conflict(
+                                id,
+                                format!(
+                                    "it is {} but its addend is {}",
+                                    self.redact(id, o),
+                                    self.redact(c, z)
+                                ),
+                            )
+                        })?;
+                        // The product is not a node: solve a * b = p directly.
+                        self.solve_op(id, OPERATION::MUL, a, b, Some(p), values)
+                            .map(|assignments| {
+                                assignments
+                                    .into_iter()
+                                    .filter(|&(node, _, _)| node != id)
+                                    .collect()
+                            })
+                    }
+                    _ => Ok(Vec::new()),
+                }
+            }
+            Kind::Hint => {
+                let (Some(name), Some(linked)) = (self.hint_calls.get(&id), self.graph.linked(id))
+                else {
+                    return Ok(Vec::new());
+                };
+                match (self.hint_fns.get(name), values[linked]) {
+                    (Some(f), Some(x)) => self.forward(id, f(x), out),
+                    _ => Ok(Vec::new()),
+                }
+            }
+            Kind::Custom => {
+                let Some(op) = self.custom_ops.get(&self.custom_calls[&id]) else {
+                    return Ok(Vec::new());
+                };
+                let operands = self.graph.custom(id).unwrap();
+                let Some(known) = operands
+                    .iter()
+                    .map(|&operand| values[operand])
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    return Ok(Vec::new());
+                };
+                match op.evaluate(&known) {
+                    Some(result) => self.forward(id, result, out),
+                    None => Err(conflict(
+                        id,
+                        format!("`{}` is undefined on its operands", self.custom_calls[&id]),
+                    )),
+                }
+            }
+            Kind::Input | Kind::Constant => Ok(Vec::new()),
+        }
+    }
+
+    /// Solves `out = a op b` for whichever of its values is unknown. `id` is the node it stands
+    /// for, and is only assigned when `out` is its value.
+    fn solve_op(
+        &self,
+        id: usize,
+        op: OPERATION,
+        a: usize,
+        b: usize,
+        out: Option<u32>,
+        values: &[Option<u32>],
+    ) -> Result<Assignments, SolveError> {
+        match (values[a], values[b], out) {
+            (Some(x), Some(y), out) => {
+                let result = op.checked_apply(x, y).ok_or_else(|| {
+                    conflict(
+                        id,
+                        format!(
+                            "{} {} {} does not fit in a u32",
+                            self.redact(a, x),
+                            op.symbol(),
+                            self.redact(b, y)
+                        ),
+                    )
+                })?;
+                self.forward(id, result, out)
+            }
+            (Some(x), None, Some(o)) => self.inverse(id, op, o, (a, x), b),
+            (None, Some(y), Some(o)) => self.inverse(id, op, o, (b, y), a),
+            (None, None, Some(o)) if a == b => {
+                let root = match op {
+                    OPERATION::ADD => o.is_multiple_of(2).then_some(o / 2),
+                    OPERATION::MUL => {
+                        let root = (o as f64).sqrt() as u64;
+                        (root.saturating_sub(1)..=root + 1)
+                            .find(|&r| r * r == u64::from(o))
+                            .map(|r| r as u32)
+                    }
+                };
+                match root {
+                    Some(root) => Ok(vec![(a, root, Rule::Inverse)]),
+                    None => Err(conflict(
+                        id,
+                        format!(
+                            "it is {}, which is not n {} n for any n",
+                            self.redact(id, o),
+                            op.symbol()
+                        ),
+                    )),
+                }
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Solves `o = x op ?` for the operand `unknown`, where `known` holds `x`.
+    fn inverse(
+        &self,
+        id: usize,
+        op: OPERATION,
+        o: u32,
+        (known, x): (usize, u32),
+        unknown: usize,
+    ) -> Result<Assignments, SolveError> {
+        let solved = match op {
+            OPERATION::ADD => o.checked_sub(x),
+            // 0 * y = 0 holds for any y, so nothing is forced.
+            OPERATION::MUL if x == 0 && o == 0 => return Ok(Vec::new()),
+            OPERATION::MUL => (x != 0 && o.is_multiple_of(x)).then(|| o / x),
+        };
+        match solved {
+            Some(y) => Ok(vec![(unknown, y, Rule::Inverse)]),
+            None => Err(conflict(
+                id,
+                format!(
+                    "it is {}, which is not {} {} n for any n",
+                    self.redact(id, o),
+                    self.redact(known, x),
+                    op.symbol()
+                ),
+            )),
+        }
+    }
+
+    /// Assigns `result` to `id`, or checks it against the value `id` already has.
+    fn forward(&self, id: usize, result: u32, out: Option<u32>) -> Result<Assignments, SolveError> {
+        match out {
+            None => Ok(vec![(id, result, Rule::Forward)]),
+            Some(o) if o == result => Ok(Vec::new()),
+            Some(o) => Err(conflict(
+                id,
+                format!(
+                    "it is {} but its inputs give {}",
+                    self.redact(id, o),
+                    self.redact(id, result)
+                ),
+            )),
+        }
+    }
+
+    fn equate(
+        &self,
+        a: usize,
+        b: usize,
+        values: &[Option<u32>],
+    ) -> Result<Assignments, SolveError> {
+        match (values[a], values[b]) {
+            (Some(x), None) => Ok(vec![(b, x, Rule::Equality)]),
+            (None, Some(y)) => Ok(vec![(a, y, Rule::Equality)]),
+            (Some(x), Some(y)) if x != y => Err(conflict(
+                a,
+                format!(
+                    "it is {} but is constrained to equal node {}, which is {}",
+                    self.redact(a, x),
+                    b,
+                    self.redact(b, y)
+                ),
+            )),
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+fn conflict(node: usize, reason: String) -> SolveError {
+    SolveError::Conflict { node, reason }
+}
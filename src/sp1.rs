@@ -0,0 +1,204 @@
+//! Emission of SP1 guest programs, to prove graphs with the SP1 zkVM.
+//!
+//! `guest_source` renders a graph as the `main.rs` of a guest program that re-executes it and
+//! asserts its constraints, so a proof of the program's execution proves that the graph is
+//! satisfied. The guest reads one `Vec<u32>` from its stdin, holding the values of the graph's
+//! inputs and of its hints computed by functions, in node order: hint functions are not part of
+//! the graph, so their values are advice from the prover, only trusted as far as the constraints
+//! check them. Custom nodes must be lowered with `Builder::lower_custom_ops` first. `stdin_values` collects them from a filled builder for the host to write. The
+//! guest commits the values of the public outputs, in the order of `Builder::public_outputs`, as
+//! its public values.
+//!
+//! `write_guest` writes the source and a manifest to a directory, ready for `cargo prove build`:
+//!
+//! ```
+//! use my_graph_lib::{sp1, Builder};
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let y = builder.mul(x, x);
+//! builder.mark_public(y);
+//! let source = sp1::guest_source(&builder);
+//! assert!(source.contains("sp1_zkvm::io::commit(&n1);"));
+//! builder.fill_nodes(x, 6);
+//! // The host writes these with `SP1Stdin::write`.
+//! assert_eq!(sp1::stdin_values(&builder), Some(vec![6]));
+//! ```
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::checks::Check;
+use crate::error::BuildError;
+use crate::storage::Kind;
+use crate::Builder;
+
+/// The version requirement of `sp1-zkvm` in the manifests written by `write_guest`.
+pub const SP1_ZKVM_VERSION: &str = "5";
+
+/// Helpers called by the generated `main`, with the same overflow semantics as evaluation.
+const HELPERS: &str = r#"
+#[allow(dead_code)]
+fn add(node: usize, a: u32, b: u32) -> u32 {
+    a.checked_add(b)
+        .unwrap_or_else(|| panic!("node {} overflows", node))
+}
+
+#[allow(dead_code)]
+fn mul(node: usize, a: u32, b: u32) -> u32 {
+    a.checked_mul(b)
+        .unwrap_or_else(|| panic!("node {} overflows", node))
+}
+
+#[allow(dead_code)]
+fn mul_add(node: usize, a: u32, b: u32, c: u32) -> u32 {
+    u32::try_from(u64::from(a) * u64::from(b) + u64::from(c))
+        .unwrap_or_else(|_| panic!("node {} overflows", node))
+}
+
+#[allow(dead_code)]
+fn sorted<const N: usize>(mut values: [u32; N]) -> [u32; N] {
+    values.sort_unstable();
+    values
+}
+"#;
+
+/// Renders the graph as the `main.rs` of an SP1 guest program, see the module documentation.
+/// Constraints with the `Warning` severity are left out. Panics if the graph has custom nodes,
+/// which the guest can't compute: lower them with `lower_custom_ops` first.
+pub fn guest_source(builder: &Builder) -> String {
+    try_guest_source(builder).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Like `guest_source`, but returns `BuildError::Unlowered` instead of panicking if the graph has
+/// custom nodes.
+pub fn try_guest_source(builder: &Builder) -> Result<String, BuildError> {
+    builder.check_lowered()?;
+    let advice = builder
+        .graph
+        .iter()
+        .filter(|node| is_advice(builder, node.id))
+        .count();
+    let mut out = format!(
+        "//! SP1 guest program generated from a graph of {} nodes: re-executes the graph and\n\
+         //! asserts its constraints.\n\
+         #![no_main]\n\
+         sp1_zkvm::entrypoint!(main);\n\n\
+         pub fn main() {{\n    \
+         let advice: Vec<u32> = sp1_zkvm::io::read();\n    \
+         assert_eq!(advice.len(), {}, \"wrong number of input and hint values\");\n",
+        builder.graph.len(),
+        advice
+    );
+    let mut next = 0;
+    for node in builder.graph.iter() {
+        let id = node.id;
+        let value = match (builder.graph.kind(id), node.inputs) {
+            _ if is_advice(builder, id) => {
+                next += 1;
+                format!("advice[{}]", next - 1)
+            }
+            (Kind::Add, (Some(a), Some(b))) => format!("add({}, n{}, n{})", id, a, b),
+            (Kind::Mul, (Some(a), Some(b))) => format!("mul({}, n{}, n{})", id, a, b),
+            (Kind::MulAdd, (Some(a), Some(b))) => {
+                format!("mul_add({}, n{}, n{}, n{})", id, a, b, node.addend.unwrap())
+            }
+            _ => node.output.unwrap().to_string(),
+        };
+        write!(out, "    let n{}: u32 = {};", id, value).unwrap();
+        let comment = match (builder.hint_calls.get(&id), builder.labels.get(&id)) {
+            (Some(function), _) => Some(format!("{}(n{})", function, node.hint.unwrap())),
+            (None, Some(label)) => Some(label.replace(['\n', '\r'], " ")),
+            (None, None) => None,
+        };
+        match comment {
+            Some(comment) => writeln!(out, " // {}", comment).unwrap(),
+            None => out.push('\n'),
+        }
+    }
+    for (i, &(a, b)) in builder.constraints.iter().enumerate() {
+        if builder.soft_constraints.contains(&i) {
+            continue;
+        }
+        let message = match builder.constraint_messages.get(&i) {
+            Some(message) => format!("{:?}", message),
+            None => format!("\"constraint {}\"", i),
+        };
+        writeln!(
+            out,
+            "    assert_eq!(n{}, n{}, \"{{}}\", {});",
+            a, b, message
+        )
+        .unwrap();
+    }
+    for (a, b) in builder.alias_pairs() {
+        writeln!(out, "    assert_eq!(n{}, n{}, \"alias {}\");", a, b, b).unwrap();
+    }
+    for (i, check) in builder.checks.iter().enumerate() {
+        let condition = match *check {
+            Check::LessThan { a, b, bits } if bits < 32 => {
+                format!("n{} < n{} && n{} >> {} == 0", a, b, b, bits)
+            }
+            Check::LessThan { a, b, .. } => format!("n{} < n{}", a, b),
+            Check::Range { node, bits } if bits < 32 => format!("n{} >> {} == 0", node, bits),
+            Check::Range { .. } => continue,
+            Check::InSet { node, ref set } => format!("{:?}.contains(&n{})", set, node),
+            Check::Permutation { ref a, ref b } => {
+                format!("sorted([{}]) == sorted([{}])", variables(a), variables(b))
+            }
+        };
+        writeln!(out, "    assert!({}, \"check {}\");", condition, i).unwrap();
+    }
+    for &id in &builder.public {
+        writeln!(out, "    sp1_zkvm::io::commit(&n{});", id).unwrap();
+    }
+    out.push_str("}\n");
+    out.push_str(HELPERS);
+    Ok(out)
+}
+
+/// The values the guest program of `guest_source` reads: those of the graph's inputs and of its
+/// hints computed by functions, in node order. `None` if one of them has not been filled in.
+pub fn stdin_values(builder: &Builder) -> Option<Vec<u32>> {
+    builder
+        .graph
+        .iter()
+        .filter(|node| is_advice(builder, node.id))
+        .map(|node| node.output)
+        .collect()
+}
+
+/// A manifest for the guest program, a package named `name`.
+pub fn guest_manifest(name: &str) -> String {
+    format!(
+        "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+         [dependencies]\nsp1-zkvm = \"{}\"\n",
+        name, SP1_ZKVM_VERSION
+    )
+}
+
+/// Writes the guest program of the graph to `dir`, as a package named `name`: `Cargo.toml` and
+/// `src/main.rs`. Creates the directories that don't exist. Panics if the graph has custom
+/// nodes, like `guest_source`.
+pub fn write_guest(builder: &Builder, dir: impl AsRef<Path>, name: &str) -> io::Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir.join("src"))?;
+    fs::write(dir.join("Cargo.toml"), guest_manifest(name))?;
+    fs::write(dir.join("src").join("main.rs"), guest_source(builder))
+}
+
+/// Whether the value of a node is read from the guest's stdin.
+fn is_advice(builder: &Builder, id: usize) -> bool {
+    match builder.graph.kind(id) {
+        Kind::Input => true,
+        Kind::Hint => builder.hint_calls.contains_key(&id),
+        _ => false,
+    }
+}
+
+fn variables(nodes: &[usize]) -> String {
+    let names: Vec<String> = nodes.iter().map(|id| format!("n{}", id)).collect();
+    names.join(", ")
+}
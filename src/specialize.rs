@@ -0,0 +1,137 @@
+//! Partial evaluation: fixing some inputs of a graph and folding away what they determine.
+//!
+//! `Builder::specialize(&known)` substitutes constants for the known inputs, constant-folds every
+//! node computed from constants alone, including hints whose function is registered, and drops
+//! the nodes no longer needed. The result is a smaller graph over the remaining inputs, e.g. a
+//! circuit with its protocol parameters fixed at deployment time:
+//!
+//! ```
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let rounds = builder.init();
+//! let x = builder.init();
+//! let one = builder.constant(1);
+//! let squared = builder.mul(rounds, rounds);
+//! let factor = builder.add(squared, one);
+//! let y = builder.mul(factor, x);
+//! builder.mark_public(y);
+//! let specialized = builder.specialize(&[(rounds, 3)]);
+//! // The constant 3 standing for rounds, x, the folded factor 10, and y.
+//! assert_eq!(specialized.builder.len(), 4);
+//! let (x, y) = (specialized.ids[&x], specialized.ids[&y]);
+//! let mut residual = specialized.builder;
+//! residual.fill_nodes(x, 4);
+//! assert_eq!(residual.output(y), Some(40));
+//! ```
+//!
+//! Constraints, checks, public outputs, labels and metadata move to the nodes standing for their
+//! nodes, which may be constants. A computation that overflows with the known values is not
+//! folded, so the residual graph fails to evaluate like the original one.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::error::BuildError;
+use crate::storage::Kind;
+use crate::{Builder, NodeId};
+
+/// A graph specialized with `Builder::specialize`.
+#[derive(Debug, Clone)]
+pub struct Specialized {
+    pub builder: Builder,
+    /// The id in `builder` of the node standing for each node of the original graph that was kept.
+    pub ids: BTreeMap<NodeId, NodeId>,
+    /// The number of nodes that became constants, including the known inputs.
+    pub folded: usize,
+}
+
+impl Builder {
+    /// Fixes the values of some inputs and folds away everything they determine, see the
+    /// `specialize` module. Panics if a node is not an input of this builder.
+    pub fn specialize(&self, known: &[(NodeId, u32)]) -> Specialized {
+        self.try_specialize(known)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `specialize`, but returns an error instead of panicking.
+    pub fn try_specialize(&self, known: &[(NodeId, u32)]) -> Result<Specialized, BuildError> {
+        let mut values = HashMap::with_capacity(known.len());
+        for &(id, value) in known {
+            let node = self.resolve(id)?;
+            if self.graph.kind(node) != Kind::Input {
+                return Err(BuildError::NotAnInput { node });
+            }
+            values.insert(node, value);
+        }
+        self.check_arities()?;
+
+        let mut folded = 0;
+        // `rebuild` copies the nodes in order, so the copy of node `old` is passed on call `old`.
+        let mut old = 0;
+        let (builder, map) = self.rebuild(|graph, node| {
+            let constant = |id: usize| {
+                (graph.graph.kind(id) == Kind::Constant).then(|| graph.graph.output(id).unwrap())
+            };
+            let value = match graph.graph.kind(node) {
+                Kind::Input => values.get(&old).copied(),
+                Kind::Add | Kind::Mul => {
+                    let (a, b) = graph.graph.operands(node).unwrap();
+                    let op = graph.graph.op(node).unwrap();
+                    match (constant(a), constant(b)) {
+                        (Some(a), Some(b)) => op.checked_apply(a, b),
+                        _ => None,
+                    }
+                }
+                Kind::MulAdd => match graph.graph.mul_add(node).unwrap().map(constant) {
+                    [Some(a), Some(b), Some(c)] => {
+                        a.checked_mul(b).and_then(|product| product.checked_add(c))
+                    }
+                    _ => None,
+                },
+                Kind::Hint => {
+                    let function = graph
+                        .hint_calls
+                        .get(&node)
+                        .and_then(|name| graph.hint_fns.get(name));
+                    match (function, constant(graph.graph.linked(node).unwrap())) {
+                        (Some(function), Some(linked)) => Some(function(linked)),
+                        _ => None,
+                    }
+                }
+                Kind::Custom => {
+                    let op = graph.custom_ops.get(&graph.custom_calls[&node]);
+                    let operands = graph.graph.custom(node).unwrap();
+                    match (
+                        op,
+                        operands
+                            .into_iter()
+                            .map(constant)
+                            .collect::<Option<Vec<_>>>(),
+                    ) {
+                        (Some(op), Some(values)) => op.evaluate(&values),
+                        _ => None,
+                    }
+                }
+                Kind::Constant => None,
+            };
+            old += 1;
+            match value {
+                Some(value) => {
+                    folded += 1;
+                    graph.graph.push(Kind::Constant, [0, 0], Some(value))
+                }
+                None => node,
+            }
+        });
+        let ids = map
+            .iter()
+            .enumerate()
+            .filter_map(|(old, &new)| Some((self.id(old), builder.id(new?))))
+            .collect();
+        Ok(Specialized {
+            builder,
+            ids,
+            folded,
+        })
+    }
+}
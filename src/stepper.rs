@@ -0,0 +1,171 @@
+//! Step-through evaluation of a graph, for finding where an unexpected value first appears.
+//!
+//! ```
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let x_squared = builder.mul(x, x);
+//! let y = builder.add(x_squared, x);
+//! builder.set_label(x_squared, "x_squared");
+//!
+//! let mut stepper = builder.stepper(&[(x, 6)]);
+//! stepper.break_on_label("x_squared");
+//! assert_eq!(stepper.run(), Some(x_squared));
+//! assert_eq!(stepper.builder().witness().values[y.index()], None);
+//! stepper.run();
+//! assert!(stepper.is_done());
+//! ```
+//!
+//! `try_step`, `try_step_level` and `try_run` return the error of a node that can't be filled in,
+//! such as an overflow, and leave it as the next node, so the graph around it can be inspected.
+
+use std::collections::BTreeSet;
+
+use crate::error::EvalError;
+use crate::{Builder, NodeId};
+
+/// Callback invoked with the partially filled graph and the node just filled in.
+type StepCallback<'a> = Box<dyn FnMut(&Builder, NodeId) + 'a>;
+
+/// Fills in a graph one node (or one dependency level) at a time.
+///
+/// Nodes are visited level by level (see `Builder::levels`), and by index within a level,
+/// so stepping by node and stepping by level can be mixed freely.
+pub struct Stepper<'a> {
+    builder: &'a mut Builder,
+    schedule: Vec<usize>,
+    levels: Vec<usize>,
+    next: usize, // Position in `schedule` of the next node to fill.
+    breakpoints: BTreeSet<usize>,
+    on_step: Option<StepCallback<'a>>,
+}
+
+impl Builder {
+    /// Sets the input values and returns a stepper that fills in the rest of the graph on demand.
+    pub fn stepper(&mut self, inputs: &[(NodeId, u32)]) -> Stepper<'_> {
+        self.set_inputs(inputs);
+        let levels = self.levels();
+        let mut schedule: Vec<usize> = (0..self.graph.len()).collect();
+        schedule.sort_by_key(|&id| (levels[id], id));
+        Stepper {
+            builder: self,
+            schedule,
+            levels,
+            next: 0,
+            breakpoints: BTreeSet::new(),
+            on_step: None,
+        }
+    }
+}
+
+impl<'a> Stepper<'a> {
+    /// The partially filled graph.
+    pub fn builder(&self) -> &Builder {
+        self.builder
+    }
+
+    /// The node that will be filled in by the next call to `step`.
+    pub fn next_node(&self) -> Option<NodeId> {
+        let index = *self.schedule.get(self.next)?;
+        Some(self.builder.id(index))
+    }
+
+    /// Returns true once every node has been filled in.
+    pub fn is_done(&self) -> bool {
+        self.next >= self.schedule.len()
+    }
+
+    /// Makes `run` stop after filling in the given node.
+    pub fn break_on(&mut self, id: NodeId) {
+        let index = self.builder.index(id);
+        self.breakpoints.insert(index);
+    }
+
+    /// Makes `run` stop after filling in every node with the given label.
+    /// Returns false if no node has that label.
+    pub fn break_on_label(&mut self, label: &str) -> bool {
+        let ids: Vec<usize> = self
+            .builder
+            .labels
+            .iter()
+            .filter(|(_, l)| l.as_str() == label)
+            .map(|(&id, _)| id)
+            .collect();
+        self.breakpoints.extend(&ids);
+        !ids.is_empty()
+    }
+
+    /// Removes a breakpoint set with `break_on` or `break_on_label`.
+    pub fn clear_breakpoint(&mut self, id: NodeId) {
+        let index = self.builder.index(id);
+        self.breakpoints.remove(&index);
+    }
+
+    /// Registers a callback invoked with the partially filled graph after every node is filled in.
+    pub fn on_step(&mut self, callback: impl FnMut(&Builder, NodeId) + 'a) {
+        self.on_step = Some(Box::new(callback));
+    }
+
+    /// Fills in the next node and returns it, or `None` if every node is filled in.
+    /// Panics if the node can't be filled in, see `try_step`.
+    pub fn step(&mut self) -> Option<NodeId> {
+        self.try_step().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `step`, but returns an error if the node can't be filled in, e.g. because it
+    /// overflows or its hint function is not registered. The node is not filled in then, and
+    /// stays the next one.
+    pub fn try_step(&mut self) -> Result<Option<NodeId>, EvalError> {
+        let Some(id) = self.next_node() else {
+            return Ok(None);
+        };
+        self.builder.try_fill_index(id.index())?;
+        self.next += 1;
+        if let Some(callback) = &mut self.on_step {
+            callback(self.builder, id);
+        }
+        Ok(Some(id))
+    }
+
+    /// Fills in every remaining node of the current dependency level and returns them.
+    /// Panics if a node can't be filled in, see `try_step_level`.
+    pub fn step_level(&mut self) -> Vec<NodeId> {
+        self.try_step_level()
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `step_level`, but stops at the first node that can't be filled in and returns its
+    /// error, see `try_step`.
+    pub fn try_step_level(&mut self) -> Result<Vec<NodeId>, EvalError> {
+        let mut filled = Vec::new();
+        if let Some(first) = self.next_node() {
+            let level = self.levels[first.index()];
+            while self
+                .next_node()
+                .is_some_and(|id| self.levels[id.index()] == level)
+            {
+                filled.extend(self.try_step()?);
+            }
+        }
+        Ok(filled)
+    }
+
+    /// Fills in nodes until a breakpoint is filled in, returning the breakpoint,
+    /// or until every node is filled in, returning `None`. Panics if a node can't be filled in,
+    /// see `try_run`.
+    pub fn run(&mut self) -> Option<NodeId> {
+        self.try_run().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `run`, but stops at the first node that can't be filled in and returns its error,
+    /// see `try_step`.
+    pub fn try_run(&mut self) -> Result<Option<NodeId>, EvalError> {
+        while let Some(id) = self.try_step()? {
+            if self.breakpoints.contains(&id.index()) {
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,267 @@
+//! Evaluation that streams the witness to disk instead of keeping it in the graph.
+//!
+//! Only values that still have an unevaluated consumer are kept in memory, so the memory used
+//! for values is bounded by the widest "frontier" of the graph rather than its size.
+//!
+//! The witness file starts with an 8 byte magic and the node count (u64, little endian),
+//! followed by one fixed size record per node: a presence byte and the value (u32, little endian).
+//! Because records are fixed size the file is its own index: node `i` lives at
+//! `HEADER_LEN + i * RECORD_LEN`, which `WitnessFile` uses for random access.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::eval::EvalError;
+use crate::schedule::Schedule;
+use crate::{Builder, NodeId};
+
+const MAGIC: &[u8; 8] = b"WITNESS1";
+const HEADER_LEN: u64 = 16;
+pub(crate) const RECORD_LEN: u64 = 5;
+
+/// Statistics about a streamed evaluation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamStats {
+    pub nodes_written: usize,
+    pub peak_live_values: usize, // Largest number of values held in memory at once.
+}
+
+impl Builder {
+    /// Evaluates the graph for the given inputs, writing every output to `path` as it is computed.
+    /// The builder's own outputs are left untouched.
+    pub fn stream_witness(
+        &self,
+        inputs: &[(NodeId, u32)],
+        path: &Path,
+    ) -> Result<StreamStats, EvalError> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let stats = self.stream_witness_to(inputs, &mut out)?;
+        out.flush()?;
+        Ok(stats)
+    }
+
+    /// Like `stream_witness`, but writes the witness file format to any writer.
+    pub fn stream_witness_to(
+        &self,
+        inputs: &[(NodeId, u32)],
+        out: &mut impl Write,
+    ) -> Result<StreamStats, EvalError> {
+        out.write_all(&header(self.graph.len()))?;
+        self.stream(inputs, 0..self.graph.len(), |_, record| {
+            out.write_all(record)
+        })
+    }
+
+    /// Like `stream_witness`, but evaluates the nodes in the order of `schedule` (see
+    /// `Builder::schedule`), which can hold far fewer values at once.
+    pub fn stream_witness_scheduled(
+        &self,
+        inputs: &[(NodeId, u32)],
+        path: &Path,
+        schedule: &Schedule,
+    ) -> Result<StreamStats, EvalError> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let stats = self.stream_witness_scheduled_to(inputs, &mut out, schedule)?;
+        out.flush()?;
+        Ok(stats)
+    }
+
+    /// Like `stream_witness_scheduled`, but writes the witness file format to any seekable writer.
+    /// Records are written at the position of their node, in the order of the schedule.
+    pub fn stream_witness_scheduled_to(
+        &self,
+        inputs: &[(NodeId, u32)],
+        out: &mut (impl Write + Seek),
+        schedule: &Schedule,
+    ) -> Result<StreamStats, EvalError> {
+        let start = out.stream_position()?;
+        out.write_all(&header(self.graph.len()))?;
+        let order: Vec<usize> = schedule.order.iter().map(|&id| self.index(id)).collect();
+        let stats = self.stream(inputs, order, |id, record| {
+            out.seek(SeekFrom::Start(start + record_position(id)))?;
+            out.write_all(record)
+        })?;
+        out.seek(SeekFrom::Start(start + record_position(self.graph.len())))?;
+        Ok(stats)
+    }
+
+    /// Evaluates the nodes in `order`, passing the witness record of each one to `write`.
+    fn stream(
+        &self,
+        inputs: &[(NodeId, u32)],
+        order: impl IntoIterator<Item = usize>,
+        mut write: impl FnMut(usize, &[u8]) -> io::Result<()>,
+    ) -> Result<StreamStats, EvalError> {
+        let inputs: HashMap<usize, u32> = inputs
+            .iter()
+            .map(|&(id, value)| (self.index(id), value))
+            .collect();
+
+        // Remaining number of reads of each node's value by nodes that have not been evaluated yet.
+        let mut uses = vec![0u32; self.graph.len()];
+        for id in 0..self.graph.len() {
+            for read in self.reads(id).into_iter().flatten() {
+                uses[read] += 1;
+            }
+        }
+
+        let mut live: HashMap<usize, u32> = HashMap::new();
+        let mut peak_live_values = 0;
+        let mut nodes_written = 0;
+        for id in order {
+            let node = self.graph.node(id);
+            let mut read = |operand: usize| -> Result<u32, EvalError> {
+                let value = *live
+                    .get(&operand)
+                    .ok_or(EvalError::MissingValue { node: id, operand })?;
+                uses[operand] -= 1;
+                if uses[operand] == 0 {
+                    live.remove(&operand);
+                }
+                Ok(value)
+            };
+            let value = match (node.op, node.inputs) {
+                (Some(op), (Some(a), Some(b))) if node.addend.is_some() => {
+                    let (a_val, b_val) = (read(a)?, read(b)?);
+                    let c_val = read(node.addend.unwrap())?;
+                    Some(op.apply(a_val, b_val) + c_val)
+                }
+                (Some(op), (Some(a), Some(b))) => {
+                    let (a_val, b_val) = (read(a)?, read(b)?);
+                    Some(op.apply(a_val, b_val))
+                }
+                _ if self.custom_calls.contains_key(&id) => {
+                    let name = &self.custom_calls[&id];
+                    let op = self.node_op(id)?;
+                    let values = self
+                        .graph
+                        .custom(id)
+                        .unwrap()
+                        .into_iter()
+                        .map(&mut read)
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let value = op
+                        .evaluate(&values)
+                        .ok_or_else(|| EvalError::CustomFailed {
+                            node: id,
+                            op: name.clone(),
+                        })?;
+                    Some(value)
+                }
+                _ => match self.hint_calls.get(&id) {
+                    Some(name) => {
+                        let f = self.hint_fns[name];
+                        Some(f(read(node.hint.unwrap())?))
+                    }
+                    None => inputs.get(&id).copied().or(node.output),
+                },
+            };
+            let record = encode(value);
+            if let Some(value) = value {
+                if uses[id] > 0 {
+                    live.insert(id, value);
+                    peak_live_values = peak_live_values.max(live.len());
+                }
+            }
+            write(id, &record)?;
+            nodes_written += 1;
+        }
+        Ok(StreamStats {
+            nodes_written,
+            peak_live_values,
+        })
+    }
+}
+
+/// Reads every value of a witness file written by `Builder::stream_witness`.
+pub(crate) fn read_witness(mut reader: impl Read) -> io::Result<Vec<Option<u32>>> {
+    let mut header = [0u8; HEADER_LEN as usize];
+    reader.read_exact(&mut header)?;
+    if &header[..8] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a witness file",
+        ));
+    }
+    let len = u64::from_le_bytes(header[8..].try_into().unwrap()) as usize;
+    let mut values = Vec::with_capacity(len);
+    for _ in 0..len {
+        let mut record = [0u8; RECORD_LEN as usize];
+        reader.read_exact(&mut record)?;
+        values.push(decode(record));
+    }
+    Ok(values)
+}
+
+/// The header of a witness file of `len` nodes.
+pub(crate) fn header(len: usize) -> [u8; HEADER_LEN as usize] {
+    let mut header = [0u8; HEADER_LEN as usize];
+    header[..8].copy_from_slice(MAGIC);
+    header[8..].copy_from_slice(&(len as u64).to_le_bytes());
+    header
+}
+
+/// Where the record of node `id` starts in a witness file.
+pub(crate) fn record_position(id: usize) -> u64 {
+    HEADER_LEN + id as u64 * RECORD_LEN
+}
+
+pub(crate) fn encode(value: Option<u32>) -> [u8; RECORD_LEN as usize] {
+    let mut record = [0u8; RECORD_LEN as usize];
+    if let Some(value) = value {
+        record[0] = 1;
+        record[1..].copy_from_slice(&value.to_le_bytes());
+    }
+    record
+}
+
+pub(crate) fn decode(record: [u8; RECORD_LEN as usize]) -> Option<u32> {
+    (record[0] == 1).then(|| u32::from_le_bytes(record[1..].try_into().unwrap()))
+}
+
+/// Random access to a witness file written by `Builder::stream_witness`.
+pub struct WitnessFile {
+    file: File,
+    len: usize,
+}
+
+impl WitnessFile {
+    pub fn open(path: &Path) -> io::Result<WitnessFile> {
+        let mut file = File::open(path)?;
+        let mut header = [0u8; HEADER_LEN as usize];
+        file.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a witness file",
+            ));
+        }
+        let len = u64::from_le_bytes(header[8..].try_into().unwrap()) as usize;
+        Ok(WitnessFile { file, len })
+    }
+
+    /// Number of nodes in the witness.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Reads the value of a single node. Nodes without a value (e.g. unassigned inputs) are `None`.
+    pub fn get(&mut self, id: usize) -> io::Result<Option<u32>> {
+        if id >= self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("node {} is out of range", id),
+            ));
+        }
+        let mut record = [0u8; RECORD_LEN as usize];
+        self.file.seek(SeekFrom::Start(record_position(id)))?;
+        self.file.read_exact(&mut record)?;
+        Ok(decode(record))
+    }
+}
@@ -0,0 +1,406 @@
+//! Subcircuits defined once and instantiated by reference.
+//!
+//! A circuit that instantiates the same gadget hundreds of times holds hundreds of copies of its
+//! nodes. A `Subcircuit` is built once and shared behind an `Arc`, and `Composer::call` only
+//! records the call and adds a node standing for each of its outputs, so a `Composer` takes
+//! memory proportional to the distinct structure of the circuit. `Composer::finalize` expands
+//! every call into a plain `Builder`, which is what evaluation and the exporters work on:
+//!
+//! ```
+//! use my_graph_lib::subcircuit::{Composer, Subcircuit};
+//!
+//! // state * state + x
+//! let round = Subcircuit::new("round", 2, |b, args| vec![b.mul_add(args[0], args[0], args[1])]);
+//! let mut circuit = Composer::new();
+//! let x = circuit.init();
+//! let one = circuit.constant(1);
+//! let mut state = one;
+//! for _ in 0..3 {
+//!     state = circuit.call(&round, &[state, x])[0];
+//! }
+//! circuit.mark_public(state);
+//!
+//! let mut builder = circuit.finalize();
+//! assert_eq!(builder.len(), circuit.expanded_len());
+//! builder.fill_inputs(&[(builder.inputs()[0], 1)]);
+//! assert_eq!(builder.output(builder.public_outputs()[0]), Some(26));
+//! ```
+//!
+//! `Builder::inline` adds a copy of a subcircuit to a builder right away instead.
+//!
+//! The nodes of a composer are in the `Builder` it dereferences to, where the output of a call is
+//! a node without a value until the composer is finalized. Subcircuits can call other
+//! subcircuits. Inputs a subcircuit creates besides its parameters become new inputs of every
+//! instance. Constraints and checks are copied into every instance, labels are prefixed with the
+//! name of the subcircuit, such as `round/carry`, and public outputs of a subcircuit are ignored.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use crate::error::BuildError;
+use crate::limits::Limit;
+use crate::observer::Constraint;
+use crate::storage::Kind;
+use crate::{Builder, Node, NodeId};
+
+/// A circuit with parameters and outputs, to be instantiated with `Composer::call`.
+#[derive(Debug)]
+pub struct Subcircuit {
+    name: String,
+    body: Composer,
+    params: usize,
+    outputs: Vec<usize>,
+    instance_len: usize, // Nodes added by expanding one call, parameters excluded.
+}
+
+impl Subcircuit {
+    /// Builds a subcircuit with `params` parameters: `f` receives a composer and the nodes of the
+    /// parameters, and returns the outputs.
+    pub fn new(
+        name: &str,
+        params: usize,
+        f: impl FnOnce(&mut Composer, &[NodeId]) -> Vec<NodeId>,
+    ) -> Arc<Subcircuit> {
+        let mut body = Composer::new();
+        let args: Vec<NodeId> = (0..params).map(|_| body.init()).collect();
+        let outputs = f(&mut body, &args)
+            .into_iter()
+            .map(|id| body.index(id))
+            .collect();
+        let instance_len = body.expanded_len() - params;
+        Arc::new(Subcircuit {
+            name: name.to_string(),
+            body,
+            params,
+            outputs,
+            instance_len,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Number of parameters.
+    pub fn params(&self) -> usize {
+        self.params
+    }
+
+    /// Number of outputs.
+    pub fn outputs(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Number of nodes every call adds to a finalized builder.
+    pub fn instance_len(&self) -> usize {
+        self.instance_len
+    }
+}
+
+/// A call of a subcircuit, whose outputs stand at `first` and the nodes after it.
+#[derive(Debug)]
+struct Call {
+    subcircuit: Arc<Subcircuit>,
+    args: Vec<usize>,
+    first: usize,
+}
+
+/// A builder whose nodes can be computed by calls to subcircuits, see the module docs.
+#[derive(Debug, Default)]
+pub struct Composer {
+    builder: Builder,
+    calls: Vec<Call>,
+}
+
+impl Composer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `subcircuit` with `args` and returns the nodes standing for its outputs. Panics if
+    /// the number of arguments doesn't match, or an argument doesn't belong to this composer.
+    pub fn call(&mut self, subcircuit: &Arc<Subcircuit>, args: &[NodeId]) -> Vec<NodeId> {
+        assert_eq!(
+            args.len(),
+            subcircuit.params,
+            "subcircuit `{}` takes {} arguments",
+            subcircuit.name,
+            subcircuit.params
+        );
+        let args: Vec<usize> = args.iter().map(|&id| self.builder.index(id)).collect();
+        let secret = args.iter().any(|&arg| self.builder.graph.is_secret(arg));
+        let first = self.builder.graph.len();
+        let outputs = (0..subcircuit.outputs.len())
+            .map(|_| {
+                let node = Node {
+                    id: self.builder.graph.len(),
+                    inputs: (None, None),
+                    op: None,
+                    output: None,
+                    hint: None,
+                    addend: None,
+                };
+                let id = self
+                    .builder
+                    .try_push_node(node, &args)
+                    .unwrap_or_else(|err| panic!("{}", err));
+                if secret {
+                    self.builder.graph.mark_secret(id.index);
+                }
+                id
+            })
+            .collect();
+        self.calls.push(Call {
+            subcircuit: Arc::clone(subcircuit),
+            args,
+            first,
+        });
+        outputs
+    }
+
+    /// Number of nodes of the finalized builder.
+    pub fn expanded_len(&self) -> usize {
+        self.calls.iter().fold(self.builder.len(), |len, call| {
+            len - call.subcircuit.outputs.len() + call.subcircuit.instance_len
+        })
+    }
+
+    /// Expands every call into a new builder.
+    pub fn finalize(&self) -> Builder {
+        let mut builder = Builder::new();
+        builder.reveal_secrets = self.builder.reveal_secrets;
+        let map = self.expand_into(&mut builder, &[], "");
+        builder.public = self.builder.public.iter().map(|&id| map[id]).collect();
+        builder
+    }
+
+    /// Copies the nodes, constraints and checks of this composer into `target`, with the nodes
+    /// at `args` standing for its first inputs and calls expanded in place, and returns the node
+    /// of `target` standing for each of its nodes.
+    fn expand_into(&self, target: &mut Builder, args: &[usize], prefix: &str) -> Vec<usize> {
+        let source = &self.builder;
+        for (name, &f) in &source.hint_fns {
+            target.hint_fns.entry(name.clone()).or_insert(f);
+        }
+        for (name, &op) in &source.custom_ops {
+            target.custom_ops.entry(name.clone()).or_insert(op);
+        }
+        let mut map = Vec::with_capacity(source.graph.len());
+        let mut args = args.iter();
+        let mut calls = self.calls.iter().peekable();
+        let mut id = 0;
+        loop {
+            while let Some(call) = calls.next_if(|call| call.first == id) {
+                let subcircuit = &call.subcircuit;
+                let call_args: Vec<usize> = call.args.iter().map(|&arg| map[arg]).collect();
+                let prefix = format!("{}{}/", prefix, subcircuit.name);
+                let instance = subcircuit.body.expand_into(target, &call_args, &prefix);
+                map.extend(subcircuit.outputs.iter().map(|&output| instance[output]));
+                id += subcircuit.outputs.len();
+            }
+            if id == source.graph.len() {
+                break;
+            }
+            let kind = source.graph.kind(id);
+            let new_id = match kind {
+                Kind::Input => match args.next() {
+                    Some(&arg) => arg,
+                    None => {
+                        let new_id = target.graph.push(Kind::Input, [0, 0], None);
+                        if source.graph.is_secret(id) {
+                            target.graph.mark_secret(new_id);
+                        }
+                        target.input_nodes.push(new_id);
+                        new_id
+                    }
+                },
+                Kind::Constant => {
+                    target
+                        .graph
+                        .push(Kind::Constant, [0, 0], source.graph.output(id))
+                }
+                Kind::Add | Kind::Mul => {
+                    let (a, b) = source.graph.operands(id).unwrap();
+                    target.graph.push(kind, [map[a], map[b]], None)
+                }
+                Kind::MulAdd => {
+                    let operands = source
+                        .graph
+                        .mul_add(id)
+                        .unwrap()
+                        .map(|operand| map[operand]);
+                    target.graph.push_mul_add(operands, None)
+                }
+                Kind::Hint => {
+                    let linked = map[source.graph.linked(id).unwrap()];
+                    match source.hint_calls.get(&id) {
+                        Some(function) => {
+                            let new_id = target.graph.push(Kind::Hint, [linked, 0], None);
+                            target.hint_calls.insert(new_id, function.clone());
+                            new_id
+                        }
+                        None => target
+                            .graph
+                            .push(Kind::Hint, [linked, 0], source.graph.output(id)),
+                    }
+                }
+                Kind::Custom => {
+                    let operands: Vec<usize> = source
+                        .graph
+                        .custom(id)
+                        .unwrap()
+                        .into_iter()
+                        .map(|operand| map[operand])
+                        .collect();
+                    let new_id = target.graph.push_custom(&operands, None);
+                    target
+                        .custom_calls
+                        .insert(new_id, source.custom_calls[&id].clone());
+                    new_id
+                }
+            };
+            map.push(new_id);
+            id += 1;
+        }
+
+        for (&id, label) in &source.labels {
+            let label = format!("{}{}", prefix, label);
+            target.labels.entry(map[id]).or_insert(label);
+        }
+        for (&id, location) in &source.locations {
+            target.locations.entry(map[id]).or_insert(*location);
+        }
+        for (&id, metadata) in &source.metadata {
+            let merged = target.metadata.entry(map[id]).or_default();
+            for (key, value) in metadata {
+                merged.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        let offset = target.constraints.len();
+        target
+            .constraints
+            .extend(source.constraints.iter().map(|&(a, b)| (map[a], map[b])));
+        for (&constraint, location) in &source.constraint_locations {
+            target
+                .constraint_locations
+                .insert(offset + constraint, *location);
+        }
+        for (&constraint, message) in &source.constraint_messages {
+            target
+                .constraint_messages
+                .insert(offset + constraint, message.clone());
+        }
+        target.soft_constraints.extend(
+            source
+                .soft_constraints
+                .iter()
+                .map(|&constraint| offset + constraint),
+        );
+        target
+            .checks
+            .extend(source.checks.iter().map(|check| check.map(|id| map[id])));
+        for (a, b) in source.alias_pairs() {
+            target.union(map[a], map[b]);
+        }
+        map
+    }
+}
+
+impl Builder {
+    /// Adds a copy of `subcircuit` computed from `args` to this builder, and returns its outputs.
+    /// Panics if the number of arguments doesn't match, an argument doesn't belong to this
+    /// builder, or the copy would exceed a limit.
+    pub fn inline(&mut self, subcircuit: &Subcircuit, args: &[NodeId]) -> Vec<NodeId> {
+        self.try_inline(subcircuit, args)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `inline`, but returns an error instead of adding the copy if it would exceed a limit.
+    pub fn try_inline(
+        &mut self,
+        subcircuit: &Subcircuit,
+        args: &[NodeId],
+    ) -> Result<Vec<NodeId>, BuildError> {
+        assert_eq!(
+            args.len(),
+            subcircuit.params,
+            "subcircuit `{}` takes {} arguments",
+            subcircuit.name,
+            subcircuit.params
+        );
+        let args = args
+            .iter()
+            .map(|&id| self.resolve(id))
+            .collect::<Result<Vec<_>, _>>()?;
+        let savepoint = self.savepoint();
+        let (constraints, checks) = (self.constraints.len(), self.checks.len());
+        let prefix = format!("{}/", subcircuit.name);
+        let map = subcircuit.body.expand_into(self, &args, &prefix);
+        if let Err(err) = self.check_inlined(savepoint.nodes()) {
+            self.rollback(savepoint);
+            return Err(err);
+        }
+        for node in savepoint.nodes()..self.graph.len() {
+            self.report_node(node);
+        }
+        for constraint in constraints..self.constraints.len() {
+            self.report_constraint(Constraint::Equality(constraint));
+        }
+        for check in checks..self.checks.len() {
+            self.report_constraint(Constraint::Check(check));
+        }
+        Ok(subcircuit
+            .outputs
+            .iter()
+            .map(|&output| self.id(map[output]))
+            .collect())
+    }
+
+    /// Checks the graph against the limits after inlining, tracking the depth of the nodes from
+    /// `nodes` on.
+    fn check_inlined(&mut self, nodes: usize) -> Result<(), BuildError> {
+        if let Some(max) = self.limits.max_nodes {
+            if self.graph.len() > max {
+                return Err(BuildError::LimitExceeded {
+                    limit: Limit::Nodes,
+                    max,
+                });
+            }
+        }
+        if let Some(max) = self.limits.max_constraints {
+            if self.constraints.len() + self.checks.len() > max {
+                return Err(BuildError::LimitExceeded {
+                    limit: Limit::Constraints,
+                    max,
+                });
+            }
+        }
+        if let Some(max) = self.limits.max_depth {
+            for id in nodes..self.graph.len() {
+                let depth = self.depth_of(id);
+                if depth > max {
+                    return Err(BuildError::LimitExceeded {
+                        limit: Limit::Depth,
+                        max,
+                    });
+                }
+                self.depths.push(depth);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Deref for Composer {
+    type Target = Builder;
+
+    fn deref(&self) -> &Builder {
+        &self.builder
+    }
+}
+
+impl DerefMut for Composer {
+    fn deref_mut(&mut self) -> &mut Builder {
+        &mut self.builder
+    }
+}
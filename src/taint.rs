@@ -0,0 +1,66 @@
+//! Auditing what a circuit reveals about its private inputs.
+//!
+//! Every node computed from a private input is secret (see `Builder::private_input`). A public
+//! output that is secret reveals something about the private inputs it is computed from, which is
+//! sometimes the point (a hash of a secret) and sometimes a leak. `Builder::taint` lists them.
+//!
+//! Equality constraints and aliases reveal values too: a public output constrained equal to a
+//! secret node holds the secret, even though it is not computed from it. `taint` follows them, and
+//! the nodes computed from a node tainted that way, until nothing changes.
+
+use std::collections::BTreeSet;
+
+use crate::storage::Kind;
+use crate::{Builder, NodeId};
+
+/// A public output computed from private inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaintedOutput {
+    pub output: NodeId,
+    /// The private inputs the output is computed from, in index order.
+    pub sources: Vec<NodeId>,
+}
+
+impl Builder {
+    /// Lists the public outputs that depend on private inputs, through their operands, equality
+    /// constraints or aliases, in index order, with the private inputs each one depends on.
+    pub fn taint(&self) -> Vec<TaintedOutput> {
+        let len = self.graph.len();
+        let mut sources: Vec<BTreeSet<usize>> = (0..len)
+            .map(|id| {
+                let private = self.graph.kind(id) == Kind::Input && self.graph.is_secret(id);
+                private.then_some(id).into_iter().collect()
+            })
+            .collect();
+        let copies = self.copies();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in 0..len {
+                let operands: BTreeSet<usize> = self
+                    .sources(id)
+                    .flat_map(|operand| sources[operand].iter().copied())
+                    .collect();
+                let before = sources[id].len();
+                sources[id].extend(operands);
+                changed |= sources[id].len() != before;
+            }
+            for &(a, b) in &copies {
+                if sources[a] != sources[b] {
+                    let union: BTreeSet<usize> = sources[a].union(&sources[b]).copied().collect();
+                    sources[a] = union.clone();
+                    sources[b] = union;
+                    changed = true;
+                }
+            }
+        }
+        self.public
+            .iter()
+            .filter(|&&output| !sources[output].is_empty())
+            .map(|&output| TaintedOutput {
+                output: self.id(output),
+                sources: sources[output].iter().map(|&id| self.id(id)).collect(),
+            })
+            .collect()
+    }
+}
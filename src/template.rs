@@ -0,0 +1,130 @@
+//! Parameterized gadgets, specialized once per set of parameters.
+//!
+//! A `Template` describes a family of subcircuits indexed by constants such as a bit width, a
+//! number of rounds or an arity. `Template::specialize` builds the subcircuit for some parameters
+//! and caches it, so instantiating a template many times with the same parameters builds it once.
+//! Templates are plain data built with a `const fn`, so a gadget library can ship them as
+//! statics:
+//!
+//! ```
+//! use my_graph_lib::template::Template;
+//! use my_graph_lib::Builder;
+//!
+//! /// x^(2^rounds), by repeated squaring.
+//! static POW2: Template<usize> = Template::new("pow2", |_| 1, |&rounds, b, args| {
+//!     let mut x = args[0];
+//!     for _ in 0..rounds {
+//!         x = b.square(x);
+//!     }
+//!     vec![x]
+//! });
+//!
+//! let mut b = Builder::new();
+//! let x = b.init();
+//! let y = POW2.instantiate(&mut b, &2, &[x])[0];
+//! let z = POW2.instantiate(&mut b, &2, &[y])[0];
+//! assert_eq!(POW2.specializations(), 1);
+//! b.fill_nodes(x, 2);
+//! assert_eq!(b.output(y), Some(16));
+//! assert_eq!(b.output(z), Some(65536));
+//! ```
+//!
+//! The subcircuit of a specialization is named after the template and its parameters, such as
+//! `pow2(2)`, which prefixes the labels of its nodes. A template may instantiate other templates,
+//! or itself with other parameters.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::error::BuildError;
+use crate::subcircuit::{Composer, Subcircuit};
+use crate::{Builder, NodeId};
+
+/// The number of arguments of a specialization.
+pub type ArityFn<P> = fn(&P) -> usize;
+
+/// Builds a specialization, given its parameters, a composer and the nodes of its arguments,
+/// and returns its outputs.
+pub type BuildFn<P> = fn(&P, &mut Composer, &[NodeId]) -> Vec<NodeId>;
+
+/// A family of subcircuits indexed by parameters of type `P`.
+pub struct Template<P> {
+    name: &'static str,
+    arity: ArityFn<P>,
+    build: BuildFn<P>,
+    cache: Mutex<BTreeMap<P, Arc<Subcircuit>>>,
+}
+
+impl<P: Ord + Clone + fmt::Debug> Template<P> {
+    pub const fn new(name: &'static str, arity: ArityFn<P>, build: BuildFn<P>) -> Self {
+        Template {
+            name,
+            arity,
+            build,
+            cache: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The subcircuit for `params`, built the first time it is asked for.
+    pub fn specialize(&self, params: &P) -> Arc<Subcircuit> {
+        if let Some(subcircuit) = self.cache().get(params) {
+            return Arc::clone(subcircuit);
+        }
+        // The lock isn't held while building, so a template can specialize itself.
+        let name = format!("{}({:?})", self.name, params);
+        let subcircuit = Subcircuit::new(&name, (self.arity)(params), |b, args| {
+            (self.build)(params, b, args)
+        });
+        Arc::clone(self.cache().entry(params.clone()).or_insert(subcircuit))
+    }
+
+    /// Adds a copy of the specialization for `params` computed from `args` to `builder`, see
+    /// `Builder::inline`.
+    pub fn instantiate(&self, builder: &mut Builder, params: &P, args: &[NodeId]) -> Vec<NodeId> {
+        builder.inline(&self.specialize(params), args)
+    }
+
+    /// Like `instantiate`, but returns an error instead if the copy would exceed a limit.
+    pub fn try_instantiate(
+        &self,
+        builder: &mut Builder,
+        params: &P,
+        args: &[NodeId],
+    ) -> Result<Vec<NodeId>, BuildError> {
+        builder.try_inline(&self.specialize(params), args)
+    }
+
+    /// Calls the specialization for `params` by reference, see `Composer::call`.
+    pub fn call(&self, composer: &mut Composer, params: &P, args: &[NodeId]) -> Vec<NodeId> {
+        composer.call(&self.specialize(params), args)
+    }
+
+    /// Number of specializations built so far.
+    pub fn specializations(&self) -> usize {
+        self.cache().len()
+    }
+
+    /// Forgets every specialization built so far.
+    pub fn clear_cache(&self) {
+        self.cache().clear();
+    }
+
+    fn cache(&self) -> MutexGuard<'_, BTreeMap<P, Arc<Subcircuit>>> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<P: fmt::Debug> fmt::Debug for Template<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("Template")
+            .field("name", &self.name)
+            .field("specializations", &cache.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
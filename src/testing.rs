@@ -0,0 +1,188 @@
+//! Proptest strategies for random circuits, built with `--features proptest`, for property testing
+//! gadgets and graph transformations:
+//!
+//! ```ignore
+//! proptest! {
+//!     #[test]
+//!     fn my_pass_keeps_outputs(circuit in testing::circuits(CircuitConfig::default())) {
+//!         let transformed = my_pass(&circuit.builder);
+//!         testing::assert_equivalent(&circuit.builder, &transformed, &circuit.values())?;
+//!     }
+//! }
+//! ```
+//!
+//! Circuits are built in layers. Every node of a layer reads at least one node of the layer
+//! before, so the depth of a circuit is its number of layers. The nodes of the last layer are
+//! marked as public outputs, and inputs are labeled `x0`, `x1`, ….
+
+use proptest::prelude::*;
+use proptest::sample::Index;
+use proptest::test_runner::TestCaseError;
+
+use crate::eval::EvalOptions;
+use crate::{Builder, NodeId};
+
+/// Bounds on the circuits generated by `circuits`.
+#[derive(Debug, Clone)]
+pub struct CircuitConfig {
+    pub max_inputs: usize,
+    /// Largest number of layers of operations above the inputs.
+    pub max_depth: usize,
+    /// Largest number of nodes in a layer.
+    pub max_width: usize,
+    /// Largest input and constant value. Circuits whose evaluation overflows are not generated,
+    /// so large values with deep circuits mostly produce shallow ones.
+    pub max_value: u32,
+    /// Largest number of `sqrt` hint checks: `hint(sqrt, n * n) == n` for a random node `n`.
+    pub max_checks: usize,
+}
+
+impl Default for CircuitConfig {
+    fn default() -> Self {
+        CircuitConfig {
+            max_inputs: 3,
+            max_depth: 4,
+            max_width: 4,
+            max_value: 3,
+            max_checks: 2,
+        }
+    }
+}
+
+/// A generated circuit and the input values it is evaluated with.
+#[derive(Debug, Clone)]
+pub struct TestCircuit {
+    pub builder: Builder,
+    pub inputs: Vec<(NodeId, u32)>,
+}
+
+impl TestCircuit {
+    /// The input values, in the order of `Builder::inputs`.
+    pub fn values(&self) -> Vec<u32> {
+        self.inputs.iter().map(|&(_, value)| value).collect()
+    }
+
+    /// A copy of the circuit with every node filled in. Every constraint holds.
+    pub fn filled(&self) -> Builder {
+        let mut builder = self.builder.clone();
+        builder.fill_inputs(&self.inputs);
+        builder
+    }
+}
+
+/// One generated node: an operation, its operands and a constant for operations taking one.
+type NodeSpec = (u8, Index, Index, u32);
+
+/// Random circuits within `config`, with input values for which they evaluate without overflowing.
+pub fn circuits(config: CircuitConfig) -> impl Strategy<Value = TestCircuit> {
+    let layer = prop::collection::vec(
+        (0u8..4, any::<Index>(), any::<Index>(), 0..=config.max_value),
+        1..=config.max_width.max(1),
+    );
+    (
+        prop::collection::vec(0..=config.max_value, 1..=config.max_inputs.max(1)),
+        prop::collection::vec(layer, 1..=config.max_depth.max(1)),
+        prop::collection::vec(any::<Index>(), 0..=config.max_checks),
+    )
+        .prop_map(|(values, layers, checks)| build(&values, &layers, &checks))
+        .prop_filter("evaluation overflows", |circuit| {
+            let mut builder = circuit.builder.clone();
+            builder
+                .fill_inputs_with(&circuit.inputs, EvalOptions::new())
+                .is_ok()
+        })
+}
+
+fn build(values: &[u32], layers: &[Vec<NodeSpec>], checks: &[Index]) -> TestCircuit {
+    let mut builder = Builder::new();
+    let mut inputs = Vec::new();
+    for (i, &value) in values.iter().enumerate() {
+        let x = builder.init();
+        builder.set_label(x, &format!("x{}", i));
+        inputs.push((x, value));
+    }
+    let mut nodes: Vec<NodeId> = inputs.iter().map(|&(x, _)| x).collect();
+    let mut previous = nodes.clone();
+    for layer in layers {
+        let mut current = Vec::new();
+        for &(op, a, b, constant) in layer {
+            let a = *a.get(&previous);
+            let node = match op {
+                0 => builder.add(a, *b.get(&nodes)),
+                1 => builder.mul(a, *b.get(&nodes)),
+                2 => {
+                    let constant = builder.constant(constant);
+                    builder.add(a, constant)
+                }
+                _ => {
+                    let constant = builder.constant(constant);
+                    builder.mul(a, constant)
+                }
+            };
+            current.push(node);
+        }
+        nodes.extend(&current);
+        previous = current;
+    }
+    for (i, &node) in previous.iter().enumerate() {
+        builder.mark_public(node);
+        builder.set_label(node, &format!("out{}", i));
+    }
+    for check in checks {
+        let node = *check.get(&nodes);
+        let square = builder.mul(node, node);
+        let root = builder.hint_fn("sqrt", square);
+        builder.enforce_equal(root, node);
+    }
+    TestCircuit { builder, inputs }
+}
+
+/// Checks that two circuits with the same inputs compute the same public outputs and agree on
+/// whether their constraints hold, e.g. a circuit before and after an optimization. The circuits
+/// are evaluated on copies, with `values` assigned to their inputs in order.
+pub fn assert_equivalent(
+    original: &Builder,
+    other: &Builder,
+    values: &[u32],
+) -> Result<(), TestCaseError> {
+    let evaluate = |builder: &Builder| {
+        let mut builder = builder.clone();
+        let inputs: Vec<_> = builder
+            .inputs()
+            .into_iter()
+            .zip(values.iter().copied())
+            .collect();
+        let result = builder.fill_inputs_with(&inputs, EvalOptions::new());
+        let witness = builder.witness();
+        let outputs: Vec<_> = builder
+            .public_outputs()
+            .into_iter()
+            .map(|id| witness.values[id.index()])
+            .collect();
+        (result.is_ok(), outputs, builder.is_satisfied())
+    };
+    prop_assert_eq!(
+        original.inputs().len(),
+        other.inputs().len(),
+        "input counts differ"
+    );
+    let (original, other) = (evaluate(original), evaluate(other));
+    prop_assert_eq!(original.0, other.0, "only one circuit evaluates");
+    prop_assert_eq!(original.1, other.1, "public outputs differ");
+    prop_assert_eq!(
+        original.2,
+        other.2,
+        "only one circuit satisfies its constraints"
+    );
+    Ok(())
+}
+
+/// Checks that a circuit, with whatever values it holds, survives a JSON round trip.
+pub fn assert_round_trips(builder: &Builder) -> Result<(), TestCaseError> {
+    let json = builder.to_json();
+    let loaded = Builder::from_json(&json).map_err(|err| TestCaseError::fail(err.to_string()))?;
+    prop_assert_eq!(loaded.to_json(), json);
+    prop_assert_eq!(loaded.fingerprint(), builder.fingerprint());
+    prop_assert_eq!(loaded.is_satisfied(), builder.is_satisfied());
+    Ok(())
+}
@@ -0,0 +1,339 @@
+//! Execution traces, for STARK-style backends such as Winterfell or Plonky3.
+//!
+//! `layout` lays the evaluation of a graph out in a table of `columns` columns, split into slots
+//! of four columns `a, b, c, out`. Every slot satisfies the same transition constraint,
+//! `out = a * b + c`, so one polynomial identity per slot covers the whole table: an addition
+//! `x + y` is the slot `(x, 1, y)`, a multiplication `x * y` the slot `(x, y, 0)`, and a
+//! multiply-add the slot of its three operands. Inputs and hints computed by functions are
+//! loaded by the slot `(x, 1, 0)`, `x` being advice. Unused slots and padding rows are all zeros,
+//! which satisfy the transition.
+//!
+//! Boundary constraints pin the cells holding constants, and copy constraints tie every operand
+//! cell to the output cell of the slot of its node, and the output cells of the nodes of every
+//! equality constraint and alias class, for a permutation argument. Constants only get a slot, loading them like
+//! inputs with `a` pinned, when they are public or constrained. Public outputs are boundary
+//! constraints whose values the verifier supplies, and checks are left to the backend's lookup
+//! arguments, over the output cells of their nodes.
+//!
+//! `Packing` decides how slots fill the rows: one per row, as many as fit, or one dependency
+//! level per row. The number of rows is padded to a power of two.
+//!
+//! ```
+//! use my_graph_lib::trace::{self, Packing};
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let y = builder.init();
+//! let sum = builder.add(x, y);
+//! let product = builder.mul(sum, x);
+//! builder.mark_public(product);
+//! let layout = trace::layout(&builder, 8, Packing::Dense);
+//! assert_eq!(layout.transitions[1].to_string(), "c7 = c4 * c5 + c6");
+//! builder.fill_inputs(&[(x, 2), (y, 3)]);
+//! let table = layout.trace(&builder).unwrap();
+//! assert_eq!(table.len(), 2);
+//! assert_eq!(table[1], vec![2, 1, 3, 5, 5, 2, 0, 10]);
+//! assert!(layout.is_satisfied(&table));
+//! ```
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::checks::Check;
+use crate::error::BuildError;
+use crate::permutation::{self, Permutation};
+use crate::storage::Kind;
+use crate::Builder;
+
+/// The number of columns of a slot.
+pub const SLOT_WIDTH: usize = 4;
+
+/// How `layout` assigns slots to rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Packing {
+    /// One slot per row, in node order.
+    Sequential,
+    /// As many slots per row as fit, in node order.
+    Dense,
+    /// The slots of one dependency level per row, spilling over into more rows when they don't
+    /// fit, so the slots of a row don't depend on each other.
+    Levels,
+}
+
+/// The transition constraint of a slot: `out = a * b + c` over the cells of the same row, by
+/// column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Transition {
+    pub a: usize,
+    pub b: usize,
+    pub c: usize,
+    pub out: usize,
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "c{} = c{} * c{} + c{}", self.out, self.a, self.b, self.c)
+    }
+}
+
+/// A cell of the table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct Cell {
+    pub row: usize,
+    pub column: usize,
+}
+
+/// Where the value of an operand cell comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Operand {
+    /// The value of a node, copied from the output cell of its slot.
+    Node(usize),
+    /// A constant, pinned by a boundary constraint.
+    Constant(u32),
+    /// The value of the slot's own node, loaded as advice.
+    Advice,
+}
+
+/// A slot of the table, whose output cell holds the value of `node`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Slot {
+    pub node: usize,
+    pub row: usize,
+    /// The index of the slot in its row: its cells are columns `SLOT_WIDTH * index` onwards.
+    pub index: usize,
+    /// The sources of the `a`, `b` and `c` cells.
+    pub operands: [Operand; 3],
+}
+
+impl Slot {
+    /// The cell of column `offset` of the slot: 0 to 2 for the operands, 3 for the output.
+    pub fn cell(&self, offset: usize) -> Cell {
+        Cell {
+            row: self.row,
+            column: SLOT_WIDTH * self.index + offset,
+        }
+    }
+}
+
+/// A graph laid out in a table by `layout`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Layout {
+    pub columns: usize,
+    /// The number of rows, a power of two.
+    pub rows: usize,
+    pub packing: Packing,
+    /// The slots, ordered by node.
+    pub slots: Vec<Slot>,
+    /// The transition constraints, one per slot of a row, which hold on every row.
+    pub transitions: Vec<Transition>,
+    /// Cells that must hold the given values.
+    pub boundaries: Vec<(Cell, u32)>,
+    /// Pairs of cells that must hold the same value.
+    pub copies: Vec<(Cell, Cell)>,
+    /// The public outputs and the output cells of their slots, in the order of
+    /// `Builder::public_outputs`: boundary constraints whose values the verifier supplies.
+    pub public: Vec<(usize, Cell)>,
+    /// The checks of the graph, to be enforced on the output cells of their nodes.
+    pub checks: Vec<Check>,
+}
+
+impl Layout {
+    /// The slot holding the value of `node`, if it has one.
+    pub fn slot(&self, node: usize) -> Option<&Slot> {
+        self.slots
+            .binary_search_by_key(&node, |slot| slot.node)
+            .ok()
+            .map(|i| &self.slots[i])
+    }
+
+    /// The output cell of the slot of `node`, if it has one.
+    pub fn cell(&self, node: usize) -> Option<Cell> {
+        self.slot(node).map(|slot| slot.cell(SLOT_WIDTH - 1))
+    }
+
+    /// The copy constraints as the cycles of a permutation argument.
+    pub fn permutation(&self) -> Permutation<Cell> {
+        permutation::from_copies(&self.copies)
+    }
+
+    /// The trace, from the values of the builder's nodes: `rows` rows of `columns` values. `None`
+    /// if a node has not been filled in.
+    pub fn trace(&self, builder: &Builder) -> Option<Vec<Vec<u32>>> {
+        let mut table = vec![vec![0; self.columns]; self.rows];
+        for slot in &self.slots {
+            let value = builder.graph.output(slot.node)?;
+            for (offset, operand) in slot.operands.iter().enumerate() {
+                let cell = slot.cell(offset);
+                table[cell.row][cell.column] = match *operand {
+                    Operand::Node(node) => builder.graph.output(node)?,
+                    Operand::Constant(constant) => constant,
+                    Operand::Advice => value,
+                };
+            }
+            let out = slot.cell(SLOT_WIDTH - 1);
+            table[out.row][out.column] = value;
+        }
+        Some(table)
+    }
+
+    /// Whether a trace has the table's shape and satisfies its transition, boundary and copy
+    /// constraints, over the integers. Public outputs and checks are not verified.
+    pub fn is_satisfied(&self, trace: &[Vec<u32>]) -> bool {
+        let at = |cell: Cell| trace[cell.row][cell.column];
+        trace.len() == self.rows
+            && trace.iter().all(|row| row.len() == self.columns)
+            && trace.iter().all(|row| {
+                self.transitions.iter().all(|t| {
+                    u64::from(row[t.out])
+                        == u64::from(row[t.a]) * u64::from(row[t.b]) + u64::from(row[t.c])
+                })
+            })
+            && self
+                .boundaries
+                .iter()
+                .all(|&(cell, value)| at(cell) == value)
+            && self.copies.iter().all(|&(a, b)| at(a) == at(b))
+    }
+}
+
+/// Lays the graph out in a table of `columns` columns, packed by `packing`, see the module
+/// documentation. Constraints with the `Warning` severity are left out. Panics if `columns` is
+/// below `SLOT_WIDTH`, or if the graph has custom nodes, which can't be constrained: lower them
+/// with `lower_custom_ops` first.
+pub fn layout(builder: &Builder, columns: usize, packing: Packing) -> Layout {
+    try_layout(builder, columns, packing).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Like `layout`, but returns `BuildError::Unlowered` instead of panicking if the graph has
+/// custom nodes.
+pub fn try_layout(
+    builder: &Builder,
+    columns: usize,
+    packing: Packing,
+) -> Result<Layout, BuildError> {
+    builder.check_lowered()?;
+    assert!(
+        columns >= SLOT_WIDTH,
+        "a trace needs at least {} columns",
+        SLOT_WIDTH
+    );
+    let graph = &builder.graph;
+    let mut required = vec![false; graph.len()];
+    for (a, b) in builder.copies() {
+        required[a] = true;
+        required[b] = true;
+    }
+    for &id in &builder.public {
+        required[id] = true;
+    }
+    for id in builder.checks.iter().flat_map(Check::nodes) {
+        required[id] = true;
+    }
+
+    let width = columns / SLOT_WIDTH;
+    let levels = builder.levels();
+    let source = |id: usize| match constant(builder, id) {
+        Some(value) => Operand::Constant(value),
+        None => Operand::Node(id),
+    };
+    let mut slots: Vec<Slot> = Vec::new();
+    for id in 0..graph.len() {
+        let operands = match (graph.kind(id), graph.operands(id), graph.mul_add(id)) {
+            (Kind::Add, Some((a, b)), _) => [source(a), Operand::Constant(1), source(b)],
+            (Kind::Mul, Some((a, b)), _) => [source(a), source(b), Operand::Constant(0)],
+            (Kind::MulAdd, _, Some([a, b, c])) => [source(a), source(b), source(c)],
+            _ => match constant(builder, id) {
+                Some(value) if required[id] => [
+                    Operand::Constant(value),
+                    Operand::Constant(1),
+                    Operand::Constant(0),
+                ],
+                Some(_) => continue,
+                None => [Operand::Advice, Operand::Constant(1), Operand::Constant(0)],
+            },
+        };
+        let (row, index) = match (slots.last(), packing) {
+            (None, _) => (0, 0),
+            (Some(last), Packing::Sequential) => (last.row + 1, 0),
+            (Some(last), Packing::Dense) if last.index + 1 < width => (last.row, last.index + 1),
+            (Some(last), Packing::Levels)
+                if last.index + 1 < width && levels[last.node] == levels[id] =>
+            {
+                (last.row, last.index + 1)
+            }
+            (Some(last), _) => (last.row + 1, 0),
+        };
+        slots.push(Slot {
+            node: id,
+            row,
+            index,
+            operands,
+        });
+    }
+
+    let rows = slots
+        .last()
+        .map_or(1, |slot| slot.row + 1)
+        .next_power_of_two();
+    let slots_per_row = match packing {
+        Packing::Sequential => 1,
+        Packing::Dense | Packing::Levels => width,
+    };
+    let transitions = (0..slots_per_row)
+        .map(|i| Transition {
+            a: SLOT_WIDTH * i,
+            b: SLOT_WIDTH * i + 1,
+            c: SLOT_WIDTH * i + 2,
+            out: SLOT_WIDTH * i + 3,
+        })
+        .collect();
+    let mut layout = Layout {
+        columns,
+        rows,
+        packing,
+        slots,
+        transitions,
+        boundaries: Vec::new(),
+        copies: Vec::new(),
+        public: Vec::new(),
+        checks: builder.checks.clone(),
+    };
+    let output = |layout: &Layout, node: usize| {
+        layout
+            .cell(node)
+            .expect("operands and constrained nodes have slots")
+    };
+    let mut boundaries = Vec::new();
+    let mut copies = Vec::new();
+    for slot in &layout.slots {
+        for (offset, operand) in slot.operands.iter().enumerate() {
+            match *operand {
+                Operand::Node(node) => copies.push((output(&layout, node), slot.cell(offset))),
+                Operand::Constant(value) => boundaries.push((slot.cell(offset), value)),
+                Operand::Advice => {}
+            }
+        }
+    }
+    for (a, b) in builder.copies() {
+        copies.push((output(&layout, a), output(&layout, b)));
+    }
+    layout.public = builder
+        .public
+        .iter()
+        .map(|&id| (id, output(&layout, id)))
+        .collect();
+    layout.boundaries = boundaries;
+    layout.copies = copies;
+    Ok(layout)
+}
+
+/// The value of a constant or a hint with a fixed value, which are pinned rather than loaded.
+fn constant(builder: &Builder, id: usize) -> Option<u32> {
+    match builder.graph.kind(id) {
+        Kind::Constant => builder.graph.output(id),
+        Kind::Hint if !builder.hint_calls.contains_key(&id) => builder.graph.output(id),
+        _ => None,
+    }
+}
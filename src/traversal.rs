@@ -0,0 +1,104 @@
+//! Iterators over the nodes and edges of a graph, for analyses and custom exporters.
+//!
+//! An edge `(from, to)` means that `to` is computed from `from`: `from` is an operand of `to`, or
+//! the node a hint `to` is linked to. A node reading the same operand twice has a single edge to
+//! it. Edges always go from a node to a node added after it, so nodes in index order are in
+//! topological order.
+
+use crate::{Builder, Node, NodeId};
+
+impl Builder {
+    /// The nodes whose outputs `id` is computed from, without duplicates.
+    pub(crate) fn sources(&self, id: usize) -> impl Iterator<Item = usize> {
+        let operands = self.graph.mul_add(id).or_else(|| {
+            let operands = self.graph.custom(id)?;
+            Some(std::array::from_fn(|i| {
+                operands.get(i).copied().unwrap_or(operands[0])
+            }))
+        });
+        let [a, b, c] = match (operands, self.graph.operands(id)) {
+            (Some([a, b, c]), _) => [
+                Some(a),
+                (b != a).then_some(b),
+                (c != a && c != b).then_some(c),
+            ],
+            (None, Some((a, b))) => [Some(a), (b != a).then_some(b), None],
+            (None, None) => [self.graph.linked(id), None, None],
+        };
+        a.into_iter().chain(b).chain(c)
+    }
+
+    /// The scope of every node, as the path of its nested scopes: the prefix of its label before
+    /// the last `/` (subcircuits label their instances `name/...`), or a `scope` metadata string.
+    /// An unlabelled node is in the scope of its first consumer.
+    pub(crate) fn scopes(&self) -> Vec<Vec<String>> {
+        let len = self.graph.len();
+        let split = |path: &str| -> Vec<String> {
+            path.split('/')
+                .filter(|part| !part.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        let mut first_consumer = vec![None; len];
+        for id in (0..len).rev() {
+            for source in self.sources(id) {
+                first_consumer[source] = Some(id);
+            }
+        }
+        let mut scopes: Vec<Vec<String>> = vec![Vec::new(); len];
+        // Consumers come after the nodes they read, so they have their scope already.
+        for id in (0..len).rev() {
+            let meta = self.metadata.get(&id).and_then(|meta| meta.get("scope"));
+            scopes[id] = if let Some(scope) = meta.and_then(|scope| scope.as_str()) {
+                split(scope)
+            } else if let Some(label) = self.labels.get(&id) {
+                let mut path = split(label);
+                path.pop();
+                path
+            } else {
+                first_consumer[id].map_or_else(Vec::new, |consumer| scopes[consumer].clone())
+            };
+        }
+        scopes
+    }
+
+    /// Views of every node, in the order they were added.
+    pub fn nodes(&self) -> impl ExactSizeIterator<Item = Node> + '_ {
+        (0..self.graph.len()).map(|id| self.graph.node(id))
+    }
+
+    /// Every edge `(from, to)` of the graph, ordered by `to`.
+    pub fn edges(&self) -> impl Iterator<Item = (NodeId, NodeId)> + '_ {
+        (0..self.graph.len()).flat_map(move |to| {
+            self.sources(to)
+                .map(move |from| (self.id(from), self.id(to)))
+        })
+    }
+
+    /// The nodes computed directly from `id`, in index order.
+    pub fn consumers(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        let index = self.index(id);
+        (index + 1..self.graph.len())
+            .filter(move |&to| self.sources(to).any(|from| from == index))
+            .map(|to| self.id(to))
+    }
+
+    /// The nodes `id` is computed from, directly or not, in decreasing index order. The node itself
+    /// is not included.
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        let index = self.index(id);
+        let mut needed = vec![false; index];
+        for from in self.sources(index) {
+            needed[from] = true;
+        }
+        (0..index).rev().filter_map(move |node| {
+            if !needed[node] {
+                return None;
+            }
+            for from in self.sources(node) {
+                needed[from] = true;
+            }
+            Some(self.id(node))
+        })
+    }
+}
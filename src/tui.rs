@@ -0,0 +1,314 @@
+//! An interactive terminal viewer for stepping through the evaluation of a graph.
+//!
+//! Keys: `↑`/`↓` (or `k`/`j`), `PgUp`/`PgDn`, `g`/`G` move the selection, `/` searches labels,
+//! `n` jumps to the next match, `a`/`b` jump to the selected node's inputs, `h` to its hint link,
+//! `c` to its first consumer, `s` fills in the next node, `r` fills in the rest and `q` quits.
+
+use std::io;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::storage::Kind;
+use crate::{Builder, Node, NodeId, OPERATION};
+
+/// Opens the viewer on a graph. The input values are set up front and the rest of the graph
+/// is filled in as the user steps through it.
+pub fn run(mut builder: Builder, inputs: &[(NodeId, u32)]) -> io::Result<()> {
+    builder.set_inputs(inputs);
+    let mut app = App::new(builder);
+    ratatui::run(|terminal| app.event_loop(terminal))
+}
+
+struct App {
+    builder: Builder,
+    consumers: Vec<Vec<usize>>,
+    selected: usize,
+    offset: usize,
+    next: usize, // Index of the next node to be filled in.
+    search: Option<String>,
+    query: String,
+    status: String,
+}
+
+impl App {
+    fn new(builder: Builder) -> Self {
+        let mut consumers = vec![Vec::new(); builder.len()];
+        for (from, to) in builder.edges() {
+            consumers[from.index()].push(to.index());
+        }
+        App {
+            builder,
+            consumers,
+            selected: 0,
+            offset: 0,
+            next: 0,
+            search: None,
+            query: String::new(),
+            status: String::new(),
+        }
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if let Some(search) = &mut self.search {
+                match key.code {
+                    KeyCode::Esc => self.search = None,
+                    KeyCode::Enter => {
+                        self.query = self.search.take().unwrap();
+                        self.find_next(self.selected);
+                    }
+                    KeyCode::Backspace => {
+                        search.pop();
+                    }
+                    KeyCode::Char(c) => search.push(c),
+                    _ => {}
+                }
+                continue;
+            }
+            let last = self.builder.len().saturating_sub(1);
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Up | KeyCode::Char('k') => self.select(self.selected.saturating_sub(1)),
+                KeyCode::Down | KeyCode::Char('j') => self.select(self.selected + 1),
+                KeyCode::PageUp => self.select(self.selected.saturating_sub(20)),
+                KeyCode::PageDown => self.select(self.selected + 20),
+                KeyCode::Char('g') => self.select(0),
+                KeyCode::Char('G') => self.select(last),
+                KeyCode::Char('/') => self.search = Some(String::new()),
+                KeyCode::Char('n') => self.find_next(self.selected + 1),
+                KeyCode::Char('a') => self.jump(self.node_inputs().0),
+                KeyCode::Char('b') => self.jump(self.node_inputs().1),
+                KeyCode::Char('h') => self.jump(self.selected_node().and_then(|n| n.hint)),
+                KeyCode::Char('c') => self.jump(
+                    self.consumers
+                        .get(self.selected)
+                        .and_then(|c| c.first().copied()),
+                ),
+                KeyCode::Char('s') => self.step(),
+                KeyCode::Char('r') => {
+                    while self.next < self.builder.len() {
+                        let before = self.next;
+                        self.step();
+                        if self.next == before {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn node_inputs(&self) -> (Option<usize>, Option<usize>) {
+        self.selected_node()
+            .map_or((None, None), |node| node.inputs)
+    }
+
+    fn selected_node(&self) -> Option<Node> {
+        (self.selected < self.builder.len()).then(|| self.builder.graph.node(self.selected))
+    }
+
+    fn select(&mut self, id: usize) {
+        self.selected = id.min(self.builder.len().saturating_sub(1));
+    }
+
+    fn jump(&mut self, id: Option<usize>) {
+        match id {
+            Some(id) => self.select(id),
+            None => self.status = "nothing to jump to".to_string(),
+        }
+    }
+
+    /// Selects the first node at or after `start` (wrapping around) whose label contains the query.
+    fn find_next(&mut self, start: usize) {
+        let len = self.builder.len();
+        let found = (0..len).map(|i| (start + i) % len).find(|&id| {
+            self.label(id)
+                .is_some_and(|label| label.contains(self.query.as_str()))
+        });
+        match found {
+            Some(id) => {
+                self.select(id);
+                self.status = format!("found `{}`", self.query);
+            }
+            None => self.status = format!("no label matches `{}`", self.query),
+        }
+    }
+
+    /// Fills in the next node and selects it.
+    fn step(&mut self) {
+        if self.next >= self.builder.len() {
+            self.status = "every node is filled in".to_string();
+            return;
+        }
+        let ready = self
+            .builder
+            .reads(self.next)
+            .into_iter()
+            .flatten()
+            .all(|read| self.is_filled(read));
+        if !ready {
+            self.status = format!("node {} depends on a node without a value", self.next);
+            return;
+        }
+        // Failing to fill a node must not panic while the terminal is in raw mode.
+        if let Err(err) = self.builder.try_fill_index(self.next) {
+            self.select(self.next);
+            self.status = format!("node {} can't be filled in: {}", self.next, err);
+            return;
+        }
+        self.select(self.next);
+        self.status = format!("filled node {}", self.next);
+        self.next += 1;
+    }
+
+    fn is_filled(&self, id: usize) -> bool {
+        self.builder.graph.output(id).is_some()
+    }
+
+    fn describe(&self, id: usize) -> String {
+        let node = self.builder.graph.node(id);
+        match node.op {
+            Some(OPERATION::ADD) => "add".to_string(),
+            Some(OPERATION::MUL) if node.addend.is_some() => "mul_add".to_string(),
+            Some(OPERATION::MUL) => "mul".to_string(),
+            None if node.hint.is_some() => match self.builder.hint_calls.get(&id) {
+                Some(name) => format!("hint({})", name),
+                None => "hint".to_string(),
+            },
+            None if self.builder.graph.kind(id) == Kind::Custom => {
+                self.builder.custom_calls[&id].clone()
+            }
+            None if self.builder.graph.kind(id) == Kind::Constant => "const".to_string(),
+            None => "input".to_string(),
+        }
+    }
+
+    fn value(&self, id: usize) -> String {
+        self.builder
+            .graph
+            .output(id)
+            .map_or("-".to_string(), |v| v.to_string())
+    }
+
+    fn label(&self, id: usize) -> Option<&str> {
+        self.builder.labels.get(&id).map(|label| label.as_str())
+    }
+
+    fn name(&self, id: usize) -> String {
+        match self.label(id) {
+            Some(label) => format!("{} ({})", id, label),
+            None => id.to_string(),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list_area, detail_area] =
+            Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)])
+                .areas(main);
+
+        // Only the visible window of the node list is rendered, so huge graphs stay responsive.
+        let height = list_area.height.saturating_sub(2) as usize;
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if height > 0 && self.selected >= self.offset + height {
+            self.offset = self.selected + 1 - height;
+        }
+        let end = (self.offset + height).min(self.builder.len());
+        let items: Vec<ListItem> = (self.offset..end)
+            .map(|id| {
+                let marker = if id == self.next { ">" } else { " " };
+                let line = format!(
+                    "{}{:>7} {:<16} {:<10} {}",
+                    marker,
+                    id,
+                    self.label(id).unwrap_or(""),
+                    self.describe(id),
+                    self.value(id)
+                );
+                let style = if id == self.selected {
+                    Style::default().add_modifier(Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                ListItem::new(line).style(style)
+            })
+            .collect();
+        let title = format!(" nodes ({}/{} filled) ", self.next, self.builder.len());
+        frame.render_widget(
+            List::new(items).block(Block::default().borders(Borders::ALL).title(title)),
+            list_area,
+        );
+
+        let mut lines = Vec::new();
+        if self.selected < self.builder.len() {
+            let id = self.selected;
+            let node = self.builder.graph.node(id);
+            lines.push(Line::from(format!("node   {}", self.name(id))));
+            lines.push(Line::from(format!("kind   {}", self.describe(id))));
+            lines.push(Line::from(format!("value  {}", self.value(id))));
+            let inputs = match self.builder.graph.custom(id) {
+                Some(operands) => operands,
+                None => match node.inputs {
+                    (Some(a), Some(b)) => [a, b].into_iter().chain(node.addend).collect(),
+                    _ => Vec::new(),
+                },
+            };
+            if !inputs.is_empty() {
+                lines.push(Line::from(""));
+                lines.push(Line::from("inputs"));
+                for input in inputs {
+                    lines.push(Line::from(format!(
+                        "  {} = {}",
+                        self.name(input),
+                        self.value(input)
+                    )));
+                }
+            }
+            if let Some(linked) = node.hint {
+                lines.push(Line::from(""));
+                lines.push(Line::from(format!(
+                    "hint of {} = {}",
+                    self.name(linked),
+                    self.value(linked)
+                )));
+            }
+            lines.push(Line::from(""));
+            lines.push(Line::from(format!(
+                "consumers ({})",
+                self.consumers[id].len()
+            )));
+            for &consumer in self.consumers[id].iter().take(50) {
+                lines.push(Line::from(format!("  {}", self.name(consumer))));
+            }
+        }
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(" node ")),
+            detail_area,
+        );
+
+        let status_line = match &self.search {
+            Some(search) => format!("/{}", search),
+            None if self.status.is_empty() => {
+                "j/k move  / search  n next  a/b inputs  h hint  c consumer  s step  r run  q quit"
+                    .to_string()
+            }
+            None => self.status.clone(),
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+    }
+}
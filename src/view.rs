@@ -0,0 +1,155 @@
+//! `Index<NodeId>` for `Builder`: `builder[id]` is a `NodeView` of the node, e.g.
+//! `builder[id].label()`.
+//!
+//! Nodes are stored column by column, so there is no node in memory for an index to borrow. A view
+//! is built from the columns the first time a node is indexed and kept in the builder, so the
+//! reference can be handed out. A node changed since it was last indexed gets a new view next to
+//! the earlier ones, which are only dropped when the graph is reset or grows. Nodes are changed
+//! through setters such as `set_label`: there is no `IndexMut`, since a view can't write back to
+//! the columns. `Builder::at` reads the columns directly and keeps nothing, which is cheaper in
+//! loops over many nodes.
+//!
+//! ```
+//! use my_graph_lib::Builder;
+//!
+//! let mut builder = Builder::new();
+//! let x = builder.init();
+//! let y = builder.mul(x, x);
+//! builder.set_label(y, "y");
+//! builder.fill_nodes(x, 3);
+//! assert_eq!((builder[y].label(), builder[y].output()), (Some("y"), Some(9)));
+//! ```
+
+use std::ops::Index;
+use std::sync::OnceLock;
+
+use crate::{Builder, Node, NodeId, NodeRef};
+
+/// A snapshot of a node of a builder, returned by indexing the builder with the node's id.
+#[derive(Debug, Clone)]
+pub struct NodeView {
+    id: NodeId,
+    node: Node,
+    label: Option<String>,
+    constant: bool,
+    secret: bool,
+    public: bool,
+}
+
+impl NodeView {
+    fn new(node: NodeRef<'_>) -> Self {
+        NodeView {
+            id: node.id(),
+            node: node.view(),
+            label: node.label().map(str::to_string),
+            constant: node.is_constant(),
+            secret: node.is_secret(),
+            public: node.is_public(),
+        }
+    }
+
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    pub fn output(&self) -> Option<u32> {
+        self.node.output
+    }
+
+    pub fn is_constant(&self) -> bool {
+        self.constant
+    }
+
+    pub fn is_secret(&self) -> bool {
+        self.secret
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.public
+    }
+
+    /// The node's operation, operands and output.
+    pub fn view(&self) -> Node {
+        self.node
+    }
+
+    /// Whether both views show the node in the same state.
+    fn same_as(&self, other: &NodeView) -> bool {
+        let (a, b) = (&self.node, &other.node);
+        self.id == other.id
+            && (
+                a.inputs,
+                a.op.map(|op| op as u8),
+                a.output,
+                a.hint,
+                a.addend,
+            ) == (
+                b.inputs,
+                b.op.map(|op| op as u8),
+                b.output,
+                b.hint,
+                b.addend,
+            )
+            && (&self.label, self.constant, self.secret, self.public)
+                == (&other.label, other.constant, other.secret, other.public)
+    }
+}
+
+/// The views handed out by `Index`, one chain of states per node.
+#[derive(Default)]
+pub(crate) struct Views {
+    /// A chain per node of the graph when the first view was built.
+    nodes: OnceLock<Box<[Chain]>>,
+    /// A chain for the nodes added since.
+    added: Chain,
+}
+
+impl Views {
+    /// Returns the kept view equal to `view`, keeping it first if there is none.
+    fn get(&self, nodes: usize, view: NodeView) -> &NodeView {
+        let chains = self
+            .nodes
+            .get_or_init(|| (0..nodes).map(|_| Chain::default()).collect());
+        chains.get(view.id.index()).unwrap_or(&self.added).get(view)
+    }
+}
+
+/// Copies of a builder start without views.
+impl Clone for Views {
+    fn clone(&self) -> Self {
+        Views::default()
+    }
+}
+
+/// Views of a node, oldest first. Views are only appended, so references to them stay valid for
+/// as long as the builder is borrowed.
+#[derive(Default)]
+struct Chain(OnceLock<Box<(NodeView, Chain)>>);
+
+impl Chain {
+    fn get(&self, view: NodeView) -> &NodeView {
+        let mut chain = self;
+        loop {
+            let (kept, next) = &**chain
+                .0
+                .get_or_init(|| Box::new((view.clone(), Chain::default())));
+            if kept.same_as(&view) {
+                return kept;
+            }
+            chain = next;
+        }
+    }
+}
+
+impl Index<NodeId> for Builder {
+    type Output = NodeView;
+
+    /// Panics if the id doesn't belong to this graph, like `at`.
+    fn index(&self, id: NodeId) -> &NodeView {
+        self.views.get(self.graph.len(), NodeView::new(self.at(id)))
+    }
+}
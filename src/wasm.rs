@@ -0,0 +1,95 @@
+//! JavaScript bindings, built with `--features wasm` and packaged with e.g. `wasm-pack`.
+//!
+//! ```js
+//! const circuit = Circuit.fromJson(graphJson);
+//! circuit.evaluate(JSON.stringify({ x: 9 }));
+//! circuit.isSatisfied(); // true
+//! JSON.parse(circuit.witness());
+//! ```
+
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+
+use crate::eval::EvalOptions;
+use crate::{Builder, NodeId};
+
+/// A graph loaded from the JSON format of `Builder::to_json`.
+#[wasm_bindgen]
+pub struct Circuit {
+    builder: Builder,
+}
+
+#[wasm_bindgen]
+impl Circuit {
+    /// Loads a graph serialized with `Builder::to_json` (or `circuit build`).
+    #[wasm_bindgen(js_name = fromJson)]
+    pub fn from_json(json: &str) -> Result<Circuit, JsError> {
+        Ok(Circuit {
+            builder: Builder::from_json(json)?,
+        })
+    }
+
+    /// Serializes the graph, including any filled in outputs.
+    #[wasm_bindgen(js_name = toJson)]
+    pub fn to_json(&self) -> String {
+        self.builder.to_json()
+    }
+
+    /// Number of nodes in the graph.
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.builder.len()
+    }
+
+    /// Fills in the graph. `inputs` is a JSON object mapping input labels (or node indices)
+    /// to values, and every input must be given a value.
+    pub fn evaluate(&mut self, inputs: &str) -> Result<(), JsError> {
+        let inputs: HashMap<String, u32> = serde_json::from_str(inputs)?;
+        let assignment = self.assignment(&inputs)?;
+        self.builder
+            .fill_inputs_with(&assignment, EvalOptions::new())?;
+        Ok(())
+    }
+
+    /// Returns true if every constraint holds for the filled in outputs.
+    #[wasm_bindgen(js_name = isSatisfied)]
+    pub fn is_satisfied(&self) -> bool {
+        self.builder.is_satisfied()
+    }
+
+    /// The output of every node as a JSON array, with `null` for nodes that are not filled in.
+    pub fn witness(&self) -> String {
+        serde_json::to_string(&self.builder.witness()).unwrap()
+    }
+
+    /// The output of the node with the given label (or index), if it is filled in.
+    pub fn output(&self, name: &str) -> Option<u32> {
+        let id = self.builder.find_node(name)?;
+        self.builder.witness().values[id.index()]
+    }
+}
+
+impl Circuit {
+    fn assignment(&self, inputs: &HashMap<String, u32>) -> Result<Vec<(NodeId, u32)>, JsError> {
+        let mut assignment = Vec::new();
+        for (name, &value) in inputs {
+            let node = self
+                .builder
+                .find_node(name)
+                .filter(|node| self.builder.inputs().contains(node))
+                .ok_or_else(|| JsError::new(&format!("`{}` is not an input node", name)))?;
+            assignment.push((node, value));
+        }
+        for input in self.builder.inputs() {
+            if !assignment.iter().any(|&(node, _)| node == input) {
+                let name = self
+                    .builder
+                    .label(input)
+                    .map_or(input.to_string(), str::to_string);
+                return Err(JsError::new(&format!("missing value for input `{}`", name)));
+            }
+        }
+        Ok(assignment)
+    }
+}
@@ -0,0 +1,111 @@
+//! Typed wires: node ids that carry the domain of their value.
+//!
+//! A `NodeId` may hold any value, and a proof system sees it as any field element. Gadgets often
+//! need more: `select` is only sound if its condition is 0 or 1. A `BoolVar` is a node constrained
+//! to be 0 or 1 and a `U32Var` a node range checked to 32 bits, and the only way to get one is
+//! through the method that records the constraint, so gadgets taking them can't be handed an
+//! unconstrained node. A `FieldVar` is a node with no such guarantee.
+//!
+//! Every typed wire converts into a `NodeId` (`id()` or `into()`) for the rest of the API.
+
+use crate::{Builder, NodeId};
+
+/// A node with no constraint on its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FieldVar(NodeId);
+
+/// A node constrained to be 0 or 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BoolVar(pub(crate) NodeId);
+
+/// A node range checked to fit in 32 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct U32Var(NodeId);
+
+macro_rules! wire {
+    ($wire:ident) => {
+        impl $wire {
+            /// The node of the wire.
+            pub fn id(self) -> NodeId {
+                self.0
+            }
+        }
+
+        impl From<$wire> for NodeId {
+            fn from(wire: $wire) -> NodeId {
+                wire.0
+            }
+        }
+    };
+}
+
+wire!(FieldVar);
+wire!(BoolVar);
+wire!(U32Var);
+
+impl From<NodeId> for FieldVar {
+    fn from(id: NodeId) -> FieldVar {
+        FieldVar(id)
+    }
+}
+
+impl From<BoolVar> for FieldVar {
+    fn from(wire: BoolVar) -> FieldVar {
+        FieldVar(wire.0)
+    }
+}
+
+impl From<U32Var> for FieldVar {
+    fn from(wire: U32Var) -> FieldVar {
+        FieldVar(wire.0)
+    }
+}
+
+/// 0 and 1 fit in 32 bits.
+impl From<BoolVar> for U32Var {
+    fn from(wire: BoolVar) -> U32Var {
+        U32Var(wire.0)
+    }
+}
+
+impl Builder {
+    /// Adds an input with no constraint on its value.
+    pub fn field_input(&mut self) -> FieldVar {
+        FieldVar(self.init())
+    }
+
+    /// Adds an input constrained to be 0 or 1.
+    pub fn bool_input(&mut self) -> BoolVar {
+        let x = self.init();
+        self.assert_bool(x)
+    }
+
+    /// Adds an input range checked to fit in 32 bits.
+    pub fn u32_input(&mut self) -> U32Var {
+        let x = self.init();
+        self.assert_u32(x)
+    }
+
+    /// Adds the constant 0 or 1, which needs no constraint.
+    pub fn bool_constant(&mut self, value: bool) -> BoolVar {
+        BoolVar(self.constant(u32::from(value)))
+    }
+
+    /// Constrains `x` to be 0 or 1, with `x * x == x`, and returns it as a boolean.
+    pub fn assert_bool(&mut self, x: NodeId) -> BoolVar {
+        let square = self.square(x);
+        self.enforce_equal(square, x);
+        BoolVar(x)
+    }
+
+    /// Range checks `x` to fit in 32 bits, with `assert_range`, and returns it as a u32.
+    pub fn assert_u32(&mut self, x: NodeId) -> U32Var {
+        self.assert_range(x, 32);
+        U32Var(x)
+    }
+
+    /// `a * b`, which is 1 if both booleans are.
+    pub fn and(&mut self, a: BoolVar, b: BoolVar) -> BoolVar {
+        BoolVar(self.mul(a.0, b.0))
+    }
+}